@@ -0,0 +1,269 @@
+use crate::error::MutinyError;
+use crate::keymanager::{generate_seed, xprivkey_from_mnemonic};
+use crate::logging::MutinyLogger;
+use crate::storage::{MutinyStorage, NamespacedStorage};
+use crate::utils;
+use crate::{MutinyWallet, MutinyWalletBuilder, MutinyWalletConfig};
+use bip39::Mnemonic;
+use futures_util::lock::Mutex;
+use lightning::util::logger::Logger;
+use lightning::{log_info, log_trace};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const WALLET_ACCOUNTS_KEY: &str = "wallet_accounts";
+const MAIN_ACCOUNT_NAME: &str = "Main";
+
+/// One of the wallets in a [MutinyWalletManager]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WalletAccount {
+    /// Namespace of the account in storage, `None` for the main wallet
+    pub id: Option<String>,
+    pub name: String,
+    /// Unix timestamp of when the account was created, 0 for the main wallet
+    pub created_at: u64,
+}
+
+impl WalletAccount {
+    fn main() -> Self {
+        Self {
+            id: None,
+            name: MAIN_ACCOUNT_NAME.to_string(),
+            created_at: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+struct AccountStorage {
+    /// The accounts besides the main wallet
+    accounts: Vec<WalletAccount>,
+    /// Id of the account that was last opened, `None` for the main wallet
+    active: Option<String>,
+}
+
+fn get_account_storage<S: MutinyStorage>(storage: &S) -> Result<AccountStorage, MutinyError> {
+    Ok(storage.get_data(WALLET_ACCOUNTS_KEY)?.unwrap_or_default())
+}
+
+fn set_account_storage<S: MutinyStorage>(
+    storage: &S,
+    accounts: &AccountStorage,
+) -> Result<(), MutinyError> {
+    storage.set_data(WALLET_ACCOUNTS_KEY.to_string(), accounts, None)
+}
+
+/// Manages several independent wallets, each with their own seed, in a single storage.
+///
+/// The main wallet is the one that was already in the storage, the other accounts are
+/// namespaced with a [NamespacedStorage]. Only one account runs at a time, switching
+/// stops the running wallet before starting the other one.
+///
+/// Only the main wallet uses the auth client, so subscriptions, blind auth and hermes
+/// are not available to the other accounts.
+pub struct MutinyWalletManager<S: MutinyStorage> {
+    storage: S,
+    config: MutinyWalletConfig,
+    wallet: Mutex<Option<MutinyWallet<NamespacedStorage<S>>>>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> MutinyWalletManager<S> {
+    /// The xprivkey of the config is ignored, each account uses the one of its own seed
+    pub fn new(storage: S, config: MutinyWalletConfig, logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            storage,
+            config,
+            wallet: Mutex::new(None),
+            logger,
+        }
+    }
+
+    /// Lists all the accounts, starting with the main wallet
+    pub fn list_accounts(&self) -> Result<Vec<WalletAccount>, MutinyError> {
+        let mut accounts = vec![WalletAccount::main()];
+        accounts.extend(get_account_storage(&self.storage)?.accounts);
+        Ok(accounts)
+    }
+
+    /// Gets the account that was last opened
+    pub fn active_account(&self) -> Result<WalletAccount, MutinyError> {
+        let storage = get_account_storage(&self.storage)?;
+        Ok(storage
+            .active
+            .and_then(|id| {
+                storage
+                    .accounts
+                    .into_iter()
+                    .find(|a| a.id.as_ref() == Some(&id))
+            })
+            .unwrap_or_else(WalletAccount::main))
+    }
+
+    /// Creates a new account with the given seed, or a new one if none is given.
+    /// The account isn't opened, see [MutinyWalletManager::switch_account].
+    pub async fn create_account(
+        &self,
+        name: String,
+        mnemonic: Option<Mnemonic>,
+    ) -> Result<WalletAccount, MutinyError> {
+        log_trace!(self.logger, "calling create_account");
+
+        let mnemonic = match mnemonic {
+            Some(m) => m,
+            None => generate_seed(12)?,
+        };
+
+        let account = WalletAccount {
+            id: Some(Uuid::new_v4().to_string()),
+            name,
+            created_at: utils::now().as_secs(),
+        };
+        let storage = NamespacedStorage::new(self.storage.clone(), account.id.clone());
+        storage.insert_mnemonic(mnemonic)?;
+
+        let mut accounts = get_account_storage(&self.storage)?;
+        accounts.accounts.push(account.clone());
+        set_account_storage(&self.storage, &accounts)?;
+
+        log_info!(self.logger, "Created wallet account {}", account.name);
+        log_trace!(self.logger, "finished calling create_account");
+        Ok(account)
+    }
+
+    /// Deletes an account and all of its data.
+    /// The main wallet and the running account can't be removed.
+    pub async fn remove_account(&self, id: &str) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling remove_account");
+
+        let mut accounts = get_account_storage(&self.storage)?;
+        if accounts.active.as_deref() == Some(id) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let Some(index) = accounts
+            .accounts
+            .iter()
+            .position(|a| a.id.as_deref() == Some(id))
+        else {
+            return Err(MutinyError::NotFound);
+        };
+
+        let storage = NamespacedStorage::new(self.storage.clone(), Some(id.to_string()));
+        storage.delete_all().await?;
+
+        accounts.accounts.remove(index);
+        set_account_storage(&self.storage, &accounts)?;
+
+        log_trace!(self.logger, "finished calling remove_account");
+        Ok(())
+    }
+
+    /// Gets the running wallet, if an account has been opened
+    pub async fn wallet(&self) -> Option<MutinyWallet<NamespacedStorage<S>>> {
+        self.wallet.lock().await.clone()
+    }
+
+    /// Opens the account that was last opened
+    pub async fn start(&self) -> Result<MutinyWallet<NamespacedStorage<S>>, MutinyError> {
+        let active = get_account_storage(&self.storage)?.active;
+        self.switch_account(active).await
+    }
+
+    /// Stops the running wallet and opens the given account, `None` for the main wallet
+    pub async fn switch_account(
+        &self,
+        id: Option<String>,
+    ) -> Result<MutinyWallet<NamespacedStorage<S>>, MutinyError> {
+        log_trace!(self.logger, "calling switch_account");
+
+        let mut accounts = get_account_storage(&self.storage)?;
+        if let Some(id) = id.as_ref() {
+            if !accounts.accounts.iter().any(|a| a.id.as_ref() == Some(id)) {
+                return Err(MutinyError::NotFound);
+            }
+        }
+
+        let mut wallet = self.wallet.lock().await;
+        if let Some(running) = wallet.take() {
+            running.stop().await?;
+        }
+
+        let storage = NamespacedStorage::new(self.storage.clone(), id.clone());
+        let mnemonic = storage.get_mnemonic()?.ok_or(MutinyError::NotFound)?;
+        let xprivkey = xprivkey_from_mnemonic(&mnemonic, None, self.config.network)?;
+
+        let mut config = self.config.clone();
+        config.xprivkey = xprivkey;
+        if id.is_some() {
+            config.auth_client = None;
+        }
+        let new_wallet = MutinyWalletBuilder::new(xprivkey, storage)
+            .with_config(config)
+            .build()
+            .await?;
+        *wallet = Some(new_wallet.clone());
+
+        accounts.active = id;
+        set_account_storage(&self.storage, &accounts)?;
+
+        log_trace!(self.logger, "finished calling switch_account");
+        Ok(new_wallet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::MutinyWalletConfigBuilder;
+    use bitcoin::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn manager(storage: MemoryStorage) -> MutinyWalletManager<MemoryStorage> {
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[0; 32]).unwrap();
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(Network::Regtest)
+            .build();
+        MutinyWalletManager::new(storage, config, Arc::new(MutinyLogger::default()))
+    }
+
+    #[test]
+    async fn test_create_and_remove_account() {
+        let storage = MemoryStorage::default();
+        let main_mnemonic = generate_seed(12).unwrap();
+        storage.insert_mnemonic(main_mnemonic.clone()).unwrap();
+        let manager = manager(storage.clone());
+
+        let main = vec![WalletAccount::main()];
+        assert_eq!(manager.list_accounts().unwrap(), main);
+
+        let account = manager
+            .create_account("Savings".to_string(), None)
+            .await
+            .unwrap();
+        let id = account.id.clone().unwrap();
+        assert_eq!(
+            manager.list_accounts().unwrap(),
+            vec![WalletAccount::main(), account]
+        );
+        assert_eq!(manager.active_account().unwrap(), WalletAccount::main());
+
+        // the account has its own seed and doesn't touch the main wallet
+        let account_storage = NamespacedStorage::new(storage.clone(), Some(id.clone()));
+        let mnemonic = account_storage.get_mnemonic().unwrap().unwrap();
+        assert_ne!(mnemonic, main_mnemonic);
+        assert_eq!(storage.get_mnemonic().unwrap(), Some(main_mnemonic));
+
+        manager.remove_account(&id).await.unwrap();
+        assert_eq!(manager.list_accounts().unwrap(), main);
+        assert!(account_storage.get_mnemonic().unwrap().is_none());
+        assert_eq!(
+            manager.remove_account(&id).await,
+            Err(MutinyError::NotFound)
+        );
+    }
+}
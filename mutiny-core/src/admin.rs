@@ -0,0 +1,323 @@
+//! A small REST admin API to run mutiny-core headless as a node daemon,
+//! enabled with the `admin_api` feature on native targets.
+//!
+//! It only listens on localhost and every request needs the token it was started with
+//! as an `Authorization: Bearer <token>` header. Requests and responses are JSON:
+//!
+//! - `GET /balance`
+//! - `GET /channels`
+//! - `GET /peers`
+//! - `POST /invoice` with `{"amount_sats": 1000, "labels": []}`
+//! - `POST /pay` with `{"invoice": "lnbc...", "amount_sats": null, "labels": []}`,
+//!   paying an invoice without an amount also needs `"confirm_amountless": true`
+//!
+//! Errors are returned as `{"error": "..."}`.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils::{self, sleep};
+use crate::{InvoiceParams, MutinyWallet, PayInvoiceOptions};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_info, log_warn};
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request body we accept
+const MAX_BODY_LEN: usize = 64 * 1024;
+/// Most headers we read of a request
+const MAX_HEADERS: usize = 100;
+/// Longest request or header line we accept
+const MAX_LINE_LEN: usize = 8 * 1024;
+/// How long a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AdminApiConfig {
+    /// Port on localhost to listen on
+    pub port: u16,
+    /// Token the requests need to be authorized with
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct InvoiceRequest {
+    amount_sats: u64,
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PayRequest {
+    invoice: String,
+    amount_sats: Option<u64>,
+    #[serde(default)]
+    labels: Vec<String>,
+    /// Must be set to pay an invoice without an amount
+    #[serde(default)]
+    confirm_amountless: bool,
+}
+
+/// Starts serving the admin API of the wallet until it is stopped.
+///
+/// Returns once listening, fails if the port can't be bound.
+pub async fn start_admin_api<S: MutinyStorage>(
+    wallet: &MutinyWallet<S>,
+    config: AdminApiConfig,
+) -> Result<(), MutinyError> {
+    // without a token anyone on the machine could spend our funds
+    if config.token.is_empty() {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+    let listener = TcpListener::bind(addr).await.map_err(|e| {
+        log_warn!(wallet.logger, "Could not bind admin API to {addr}: {e}");
+        MutinyError::ConnectionFailed
+    })?;
+    log_info!(wallet.logger, "Admin API listening on {addr}");
+
+    let wallet = wallet.clone();
+    utils::spawn(async move {
+        loop {
+            if wallet.stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let accepted = tokio::select! {
+                res = listener.accept() => res,
+                // check if we stopped once in a while
+                _ = tokio::time::sleep(Duration::from_secs(1)) => continue,
+            };
+            let stream = match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log_warn!(wallet.logger, "Admin API failed to accept connection: {e}");
+                    sleep(1_000).await;
+                    continue;
+                }
+            };
+
+            let wallet = wallet.clone();
+            let token = config.token.clone();
+            utils::spawn(async move {
+                if let Err(e) = handle_connection(&wallet, &token, stream).await {
+                    log_debug!(wallet.logger, "Admin API connection failed: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection<S: MutinyStorage>(
+    wallet: &MutinyWallet<S>,
+    token: &str,
+    stream: TcpStream,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut reader))
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+    let expected = format!("Bearer {token}");
+    let (status, response) = match request {
+        Some(request)
+            if !request
+                .authorization
+                .as_ref()
+                .is_some_and(|a| utils::constant_time_eq(a.as_bytes(), expected.as_bytes())) =>
+        {
+            (401, json!({ "error": "Unauthorized" }))
+        }
+        Some(request) => route(wallet, request).await,
+        None => (400, json!({ "error": "Invalid request" })),
+    };
+
+    let body = response.to_string();
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        _ => "Bad Request",
+    };
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+
+    let stream = reader.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reads a line of at most [MAX_LINE_LEN], false if it is longer
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+) -> std::io::Result<bool> {
+    line.clear();
+    let len = reader.take(MAX_LINE_LEN as u64 + 1).read_line(line).await?;
+    Ok(len <= MAX_LINE_LEN)
+}
+
+/// Reads an HTTP/1.1 request, none if it is malformed or too large
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Request>> {
+    let mut line = String::new();
+    if !read_line(reader, &mut line).await? {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let method = method.to_string();
+    let path = path.to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for _ in 0..MAX_HEADERS {
+        if !read_line(reader, &mut line).await? {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            return Ok(None);
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => match value.trim().parse() {
+                Ok(len) => content_length = len,
+                Err(_) => return Ok(None),
+            },
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    if content_length > MAX_BODY_LEN {
+        return Ok(None);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(Request {
+        method,
+        path,
+        authorization,
+        body,
+    }))
+}
+
+async fn route<S: MutinyStorage>(wallet: &MutinyWallet<S>, request: Request) -> (u16, Value) {
+    let res = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/balance") => to_json(wallet.get_balance().await),
+        ("GET", "/channels") => to_json(wallet.node_manager.list_channels().await),
+        ("GET", "/peers") => to_json(wallet.node_manager.list_peers().await),
+        ("POST", "/invoice") => {
+            let Ok(req) = serde_json::from_slice::<InvoiceRequest>(&request.body) else {
+                return (400, json!({ "error": "Invalid request" }));
+            };
+            let params = InvoiceParams::default();
+            to_json(
+                wallet
+                    .create_lightning_invoice(req.amount_sats, req.labels, &params)
+                    .await,
+            )
+        }
+        ("POST", "/pay") => {
+            let Ok(req) = serde_json::from_slice::<PayRequest>(&request.body) else {
+                return (400, json!({ "error": "Invalid request" }));
+            };
+            let Ok(invoice) = Bolt11Invoice::from_str(&req.invoice) else {
+                return (400, json!({ "error": "Invalid invoice" }));
+            };
+            let options = PayInvoiceOptions {
+                confirm_amountless: req.confirm_amountless,
+                ..Default::default()
+            };
+            to_json(
+                wallet
+                    .pay_invoice_with_options(&invoice, req.amount_sats, req.labels, options)
+                    .await,
+            )
+        }
+        _ => return (404, json!({ "error": "Not found" })),
+    };
+
+    match res {
+        Ok(value) => (200, value),
+        Err(e) => {
+            let status = match e {
+                MutinyError::Other(_)
+                | MutinyError::PersistenceFailed { .. }
+                | MutinyError::BroadcastFailed
+                | MutinyError::VssError => 500,
+                _ => 400,
+            };
+            (status, json!({ "error": e.to_string() }))
+        }
+    }
+}
+
+fn to_json<T: Serialize>(res: Result<T, MutinyError>) -> Result<Value, MutinyError> {
+    Ok(serde_json::to_value(res?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let raw = b"POST /pay HTTP/1.1\r\nHost: localhost\r\n\
+            Authorization: Bearer token\r\nContent-Length: 2\r\n\r\n{}";
+        let request = read_request(&mut &raw[..]).await.unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/pay");
+        assert_eq!(request.authorization, Some("Bearer token".to_string()));
+        assert_eq!(request.body, b"{}".to_vec());
+
+        // without a body or authorization
+        let raw = b"GET /balance HTTP/1.1\r\n\r\n";
+        let request = read_request(&mut &raw[..]).await.unwrap().unwrap();
+        assert_eq!(request.path, "/balance");
+        assert_eq!(request.authorization, None);
+        assert!(request.body.is_empty());
+
+        // malformed or too large requests are rejected
+        let raw = b"GET\r\n\r\n";
+        assert_eq!(read_request(&mut &raw[..]).await.unwrap(), None);
+        let raw = format!(
+            "POST /pay HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        );
+        assert_eq!(read_request(&mut raw.as_bytes()).await.unwrap(), None);
+        let raw = format!(
+            "GET /balance HTTP/1.1\r\nAuthorization: {}\r\n\r\n",
+            "a".repeat(MAX_LINE_LEN)
+        );
+        assert_eq!(read_request(&mut raw.as_bytes()).await.unwrap(), None);
+    }
+}
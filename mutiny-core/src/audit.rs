@@ -0,0 +1,499 @@
+use crate::error::MutinyError;
+use crate::event::PaymentInfo;
+use crate::federation::FederationStorage;
+use crate::labels::{
+    vss_version, Contact, LabelItem, ADDRESS_LABELS_MAP_KEY, CONTACT_PREFIX,
+    INVOICE_LABELS_MAP_KEY, LABEL_PREFIX,
+};
+use crate::ldkstorage::{CHANNEL_CLOSURE_PREFIX, CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+use crate::nodemanager::{ChannelClosure, NodeStorage};
+use crate::nostr::nwc::{PendingNwcInvoice, Profile, PENDING_NWC_EVENTS_KEY};
+use crate::nostr::NWC_STORAGE_KEY;
+use crate::storage::{
+    build_activity_index, rewrite_activity_index, IndexItem, MutinyStorage,
+    ACTIVITY_INDEX_BUILT_KEY, ACTIVITY_INDEX_PREFIX, FEDERATIONS_KEY, NODES_KEY, ONCHAIN_PREFIX,
+    PAYMENT_INBOUND_PREFIX_KEY, PAYMENT_OUTBOUND_PREFIX_KEY, TRANSACTION_DETAILS_PREFIX_KEY,
+};
+use crate::TransactionDetails;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditIssueKind {
+    /// The value can't be read
+    Corrupt,
+    /// The value belongs to, or points at, something that doesn't exist anymore
+    Orphaned,
+}
+
+/// A problem with a stored value, found by [audit_storage]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditIssue {
+    pub key: String,
+    pub kind: AuditIssueKind,
+    pub detail: String,
+    /// If [repair_storage] can fix it, the rest needs to be looked at by hand
+    pub repairable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StorageAudit {
+    pub keys_scanned: usize,
+    pub issues: Vec<AuditIssue>,
+}
+
+impl StorageAudit {
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn corrupt(&mut self, key: impl Into<String>, detail: impl Into<String>) {
+        self.issues.push(AuditIssue {
+            key: key.into(),
+            kind: AuditIssueKind::Corrupt,
+            detail: detail.into(),
+            repairable: false,
+        });
+    }
+
+    fn orphaned(&mut self, key: impl Into<String>, detail: impl Into<String>, repairable: bool) {
+        self.issues.push(AuditIssue {
+            key: key.into(),
+            kind: AuditIssueKind::Orphaned,
+            detail: detail.into(),
+            repairable,
+        });
+    }
+
+    /// Adds the channel managers and monitors that couldn't be read back,
+    /// see [crate::nodemanager::NodeManager::check_channel_data]
+    pub(crate) fn add_unreadable_channel_data(&mut self, errors: Vec<(String, String)>) {
+        for (key, error) in errors {
+            self.corrupt(key, error);
+        }
+    }
+}
+
+/// What [repair_storage] is allowed to change
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepairOptions {
+    /// Deletes labels that aren't used by any address or invoice, contacts are kept.
+    /// Labels also stop listing addresses and invoices that don't have them anymore.
+    pub drop_orphaned_labels: bool,
+    /// Rebuilds the activity index from the stored activity
+    pub rebuild_activity_index: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            drop_orphaned_labels: true,
+            rebuild_activity_index: true,
+        }
+    }
+}
+
+/// What [repair_storage] did
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub labels_removed: usize,
+    pub labels_updated: usize,
+    /// Number of items in the rebuilt activity index, `None` if it wasn't rebuilt
+    pub activity_index_items: Option<usize>,
+}
+
+/// Checks that the stored values deserialize and look for entries
+/// that belong to, or point at, something that doesn't exist anymore.
+///
+/// Channel managers and monitors are only checked for orphans here, they need the
+/// node's keys to be read, [crate::MutinyWallet::audit_storage] reads them back.
+pub fn audit_storage<S: MutinyStorage>(storage: &S) -> Result<StorageAudit, MutinyError> {
+    let keys = storage.scan_keys("", None)?;
+    let mut audit = StorageAudit {
+        keys_scanned: keys.len(),
+        issues: vec![],
+    };
+
+    // without the nodes we can't tell which channel data is orphaned
+    let node_uuids: Option<HashSet<String>> = match storage.get_data::<NodeStorage>(NODES_KEY) {
+        Ok(nodes) => Some(nodes.unwrap_or_default().nodes.into_keys().collect()),
+        Err(e) => {
+            audit.corrupt(NODES_KEY, e.to_string());
+            None
+        }
+    };
+
+    let mut labels: HashMap<String, LabelItem> = HashMap::new();
+    let mut contacts: HashSet<String> = HashSet::new();
+    let mut address_labels: Option<HashMap<String, Vec<String>>> = None;
+    let mut invoice_labels: Option<HashMap<Bolt11Invoice, Vec<String>>> = None;
+    // stored activity index items by the key of their activity
+    let mut stored_index: HashMap<String, String> = HashMap::new();
+
+    for key in keys.iter() {
+        if let (Some(uuid), Some(node_uuids)) = (channel_data_node(key), node_uuids.as_ref()) {
+            if !node_uuids.contains(uuid) {
+                // this could still be needed to recover funds, never delete it
+                audit.orphaned(key, format!("Node {uuid} does not exist"), false);
+            }
+            continue;
+        }
+
+        match key.as_str() {
+            FEDERATIONS_KEY => {
+                check::<S, FederationStorage>(storage, key, &mut audit);
+            }
+            NWC_STORAGE_KEY => {
+                check::<S, Vec<Profile>>(storage, key, &mut audit);
+            }
+            PENDING_NWC_EVENTS_KEY => {
+                check::<S, Vec<PendingNwcInvoice>>(storage, key, &mut audit);
+            }
+            ADDRESS_LABELS_MAP_KEY => address_labels = check(storage, key, &mut audit),
+            INVOICE_LABELS_MAP_KEY => invoice_labels = check(storage, key, &mut audit),
+            k if k.starts_with(ACTIVITY_INDEX_PREFIX) => {
+                if let Some(item) = check::<S, IndexItem>(storage, key, &mut audit) {
+                    stored_index.insert(item.key, key.clone());
+                }
+            }
+            k if k.starts_with(PAYMENT_INBOUND_PREFIX_KEY)
+                || k.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY) =>
+            {
+                check::<S, PaymentInfo>(storage, key, &mut audit);
+            }
+            k if k.starts_with(TRANSACTION_DETAILS_PREFIX_KEY) => {
+                check::<S, TransactionDetails>(storage, key, &mut audit);
+            }
+            k if k.starts_with(CHANNEL_CLOSURE_PREFIX) => {
+                check::<S, ChannelClosure>(storage, key, &mut audit);
+            }
+            k if k.starts_with(LABEL_PREFIX) => {
+                if let Some(item) = check(storage, key, &mut audit) {
+                    labels.insert(k[LABEL_PREFIX.len()..].to_string(), item);
+                }
+            }
+            k if k.starts_with(CONTACT_PREFIX) => {
+                if check::<S, Contact>(storage, key, &mut audit).is_some() {
+                    contacts.insert(k[CONTACT_PREFIX.len()..].to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the label maps are what is shown to the user, the label items just mirror them
+    let address_labels = address_labels.unwrap_or_default();
+    let invoice_labels = invoice_labels.unwrap_or_default();
+    for (label, item) in labels.iter() {
+        let stale = stale_label_references(label, item, &address_labels, &invoice_labels);
+        if stale == item.addresses.len() + item.invoices.len() && !contacts.contains(label) {
+            audit.orphaned(
+                format!("{LABEL_PREFIX}{label}"),
+                "Label is not used by any address or invoice",
+                true,
+            );
+        } else if stale > 0 {
+            audit.orphaned(
+                format!("{LABEL_PREFIX}{label}"),
+                format!("Label lists {stale} addresses or invoices that don't have it"),
+                true,
+            );
+        }
+    }
+    let exists = |l: &String| labels.contains_key(l) || contacts.contains(l);
+    if address_labels.values().flatten().any(|l| !exists(l)) {
+        audit.orphaned(
+            ADDRESS_LABELS_MAP_KEY,
+            "Addresses have labels that don't exist",
+            true,
+        );
+    }
+    if invoice_labels.values().flatten().any(|l| !exists(l)) {
+        audit.orphaned(
+            INVOICE_LABELS_MAP_KEY,
+            "Invoices have labels that don't exist",
+            true,
+        );
+    }
+
+    // before the index is stored it gets built on startup, and if any activity is
+    // corrupt it was reported already and the index can't be rebuilt
+    let index_built = keys.iter().any(|k| k == ACTIVITY_INDEX_BUILT_KEY);
+    if let (true, Ok(rebuilt)) = (index_built, build_activity_index(storage)) {
+        let rebuilt: HashSet<String> = rebuilt.into_iter().map(|i| i.key).collect();
+        for (activity, key) in stored_index.iter() {
+            if !rebuilt.contains(activity) {
+                audit.orphaned(key, format!("{activity} does not exist"), true);
+            }
+        }
+        let missing = rebuilt
+            .iter()
+            .filter(|k| !stored_index.contains_key(*k))
+            .count();
+        if missing > 0 {
+            audit.orphaned(
+                ACTIVITY_INDEX_PREFIX,
+                format!("Activity index is missing {missing} items"),
+                true,
+            );
+        }
+    }
+
+    Ok(audit)
+}
+
+/// Fixes the issues [audit_storage] reports as repairable, as allowed by the [RepairOptions]
+pub fn repair_storage<S: MutinyStorage>(
+    storage: &S,
+    options: RepairOptions,
+) -> Result<RepairReport, MutinyError> {
+    storage.check_writable()?;
+    let mut report = RepairReport::default();
+
+    if options.drop_orphaned_labels {
+        let (removed, updated) = drop_orphaned_labels(storage)?;
+        report.labels_removed = removed;
+        report.labels_updated = updated;
+    }
+
+    if options.rebuild_activity_index {
+        let rebuilt = build_activity_index(storage)?;
+        let mut items = 0;
+        // on-chain activity isn't stored, it comes from the wallet
+        storage.update_activity_index(|index| {
+            index.retain(|i| i.key.starts_with(ONCHAIN_PREFIX));
+            index.extend(rebuilt);
+            items = index.len();
+        })?;
+        // the stored items can differ from the ones in memory, that's what needs repairing
+        rewrite_activity_index(storage)?;
+        report.activity_index_items = Some(items);
+    }
+
+    Ok(report)
+}
+
+fn drop_orphaned_labels<S: MutinyStorage>(storage: &S) -> Result<(usize, usize), MutinyError> {
+    let mut address_labels: HashMap<String, Vec<String>> = storage
+        .get_data(ADDRESS_LABELS_MAP_KEY)?
+        .unwrap_or_default();
+    let mut invoice_labels: HashMap<Bolt11Invoice, Vec<String>> = storage
+        .get_data(INVOICE_LABELS_MAP_KEY)?
+        .unwrap_or_default();
+    let labels: HashMap<String, LabelItem> = storage.scan(LABEL_PREFIX, None)?;
+    let contacts: HashSet<String> = storage
+        .scan_keys(CONTACT_PREFIX, None)?
+        .into_iter()
+        .map(|k| k[CONTACT_PREFIX.len()..].to_string())
+        .collect();
+
+    let mut to_set: Vec<(String, Value)> = vec![];
+    let mut to_delete: Vec<String> = vec![];
+    let mut remaining: HashSet<String> = contacts;
+    for (key, mut item) in labels {
+        let label = key[LABEL_PREFIX.len()..].to_string();
+        let stale = stale_label_references(&label, &item, &address_labels, &invoice_labels);
+        if stale == 0 && (!item.addresses.is_empty() || !item.invoices.is_empty()) {
+            remaining.insert(label);
+            continue;
+        }
+
+        item.addresses
+            .retain(|a| address_labels.get(a).is_some_and(|l| l.contains(&label)));
+        item.invoices
+            .retain(|i| invoice_labels.get(i).is_some_and(|l| l.contains(&label)));
+        if item.addresses.is_empty() && item.invoices.is_empty() && !remaining.contains(&label) {
+            to_delete.push(key);
+        } else {
+            if stale > 0 {
+                to_set.push((key, serde_json::to_value(item)?));
+            }
+            remaining.insert(label);
+        }
+    }
+    let removed = to_delete.len();
+    let updated = to_set.len();
+
+    // addresses and invoices shouldn't keep labels that don't exist
+    let mut maps_changed = false;
+    for labels in address_labels
+        .values_mut()
+        .chain(invoice_labels.values_mut())
+    {
+        let len = labels.len();
+        labels.retain(|l| remaining.contains(l));
+        maps_changed |= labels.len() != len;
+    }
+    if maps_changed {
+        to_set.push((
+            ADDRESS_LABELS_MAP_KEY.to_string(),
+            serde_json::to_value(address_labels)?,
+        ));
+        to_set.push((
+            INVOICE_LABELS_MAP_KEY.to_string(),
+            serde_json::to_value(invoice_labels)?,
+        ));
+    }
+
+    for (key, value) in to_set {
+        storage.set_data(key, value, vss_version())?;
+    }
+    storage.delete(&to_delete)?;
+    Ok((removed, updated))
+}
+
+/// Number of addresses and invoices the label item lists that don't have the label anymore
+fn stale_label_references(
+    label: &str,
+    item: &LabelItem,
+    address_labels: &HashMap<String, Vec<String>>,
+    invoice_labels: &HashMap<Bolt11Invoice, Vec<String>>,
+) -> usize {
+    let stale_addresses = item
+        .addresses
+        .iter()
+        .filter(|a| {
+            !address_labels
+                .get(*a)
+                .is_some_and(|l| l.iter().any(|l| l == label))
+        })
+        .count();
+    let stale_invoices = item
+        .invoices
+        .iter()
+        .filter(|i| {
+            !invoice_labels
+                .get(*i)
+                .is_some_and(|l| l.iter().any(|l| l == label))
+        })
+        .count();
+    stale_addresses + stale_invoices
+}
+
+/// The node a channel manager or monitor key belongs to
+fn channel_data_node(key: &str) -> Option<&str> {
+    if let Some(uuid) = key.strip_prefix(&format!("{CHANNEL_MANAGER_KEY}_")) {
+        return Some(uuid);
+    }
+    // monitors are stored as monitors/{txid}_{index}_{uuid}
+    key.strip_prefix(MONITORS_PREFIX_KEY)
+        .and_then(|k| k.rsplit_once('_'))
+        .map(|(_, uuid)| uuid)
+}
+
+/// Reads the value as `T`, reporting it as corrupt if that fails
+fn check<S, T>(storage: &S, key: &str, audit: &mut StorageAudit) -> Option<T>
+where
+    S: MutinyStorage,
+    T: for<'de> Deserialize<'de>,
+{
+    match storage.get_data(key) {
+        Ok(value) => value,
+        Err(e) => {
+            audit.corrupt(key, e.to_string());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn label_item(addresses: &[&str]) -> Value {
+        let item = LabelItem {
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        };
+        serde_json::to_value(item).unwrap()
+    }
+
+    fn issue_keys(audit: &StorageAudit) -> HashSet<String> {
+        audit.issues.iter().map(|i| i.key.clone()).collect()
+    }
+
+    #[test]
+    fn test_audit_and_repair_storage() {
+        let storage = MemoryStorage::default();
+        let monitor_key = format!("{MONITORS_PREFIX_KEY}txid_0_uuid");
+        let stale_item = IndexItem {
+            timestamp: Some(1),
+            key: format!("{PAYMENT_INBOUND_PREFIX_KEY}gone"),
+        };
+        let stale_key = format!("{ACTIVITY_INDEX_PREFIX}{}", stale_item.key);
+        storage
+            .set(vec![
+                (FEDERATIONS_KEY.to_string(), json!("garbage")),
+                (monitor_key.clone(), json!([1, 2, 3])),
+                (
+                    ADDRESS_LABELS_MAP_KEY.to_string(),
+                    json!({ "addr1": ["friends"], "addr3": ["missing"], "addr4": ["c1"] }),
+                ),
+                ("label/friends".to_string(), label_item(&["addr1", "addr2"])),
+                ("label/old".to_string(), label_item(&["addr2"])),
+                ("label/c1".to_string(), label_item(&[])),
+                (
+                    "contact/c1".to_string(),
+                    serde_json::to_value(Contact::default()).unwrap(),
+                ),
+                (stale_key.clone(), serde_json::to_value(stale_item).unwrap()),
+                (ACTIVITY_INDEX_BUILT_KEY.to_string(), json!(true)),
+            ])
+            .unwrap();
+
+        let audit = audit_storage(&storage).unwrap();
+        assert_eq!(audit.keys_scanned, 9);
+        let expected: HashSet<String> = [
+            FEDERATIONS_KEY,
+            monitor_key.as_str(),
+            ADDRESS_LABELS_MAP_KEY,
+            "label/friends",
+            "label/old",
+            stale_key.as_str(),
+        ]
+        .iter()
+        .map(|k| k.to_string())
+        .collect();
+        assert_eq!(issue_keys(&audit), expected);
+        let monitor = audit.issues.iter().find(|i| i.key == monitor_key).unwrap();
+        assert_eq!(monitor.kind, AuditIssueKind::Orphaned);
+        assert!(!monitor.repairable);
+
+        let report = repair_storage(&storage, RepairOptions::default()).unwrap();
+        assert_eq!(
+            report,
+            RepairReport {
+                labels_removed: 1,
+                labels_updated: 1,
+                activity_index_items: Some(0),
+            }
+        );
+
+        let item: LabelItem = storage.get_data("label/friends").unwrap().unwrap();
+        assert_eq!(item.addresses, HashSet::from(["addr1".to_string()]));
+        assert!(storage
+            .get_data::<LabelItem>("label/old")
+            .unwrap()
+            .is_none());
+        assert!(storage.get_data::<LabelItem>("label/c1").unwrap().is_some());
+        let address_labels: HashMap<String, Vec<String>> =
+            storage.get_data(ADDRESS_LABELS_MAP_KEY).unwrap().unwrap();
+        assert!(address_labels["addr3"].is_empty());
+        assert_eq!(address_labels["addr4"], vec!["c1".to_string()]);
+        assert!(storage.get_data::<IndexItem>(&stale_key).unwrap().is_none());
+
+        // only what needs to be looked at by hand is left
+        let audit = audit_storage(&storage).unwrap();
+        assert!(audit.issues.iter().all(|i| !i.repairable));
+        let expected: HashSet<String> = [FEDERATIONS_KEY.to_string(), monitor_key].into();
+        assert_eq!(issue_keys(&audit), expected);
+    }
+}
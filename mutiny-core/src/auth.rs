@@ -63,6 +63,14 @@ impl MutinyAuthClient {
         None
     }
 
+    /// Gets a JWT for services that take it directly, like a self-hosted VSS server
+    pub async fn get_jwt(&self, refresh: bool) -> Result<String, MutinyError> {
+        match self.is_authenticated().await {
+            Some(jwt) if !refresh => Ok(jwt),
+            _ => self.retrieve_new_jwt().await,
+        }
+    }
+
     pub async fn request(
         &self,
         method: Method,
@@ -0,0 +1,279 @@
+use crate::error::MutinyError;
+use crate::nostr::nwc::BudgetPeriod;
+use crate::storage::MutinyStorage;
+use chrono::{DateTime, Days, Months, Utc};
+use serde::{Deserialize, Serialize};
+
+const SUBSCRIPTIONS_KEY: &str = "subscriptions";
+/// How often we check if a subscription renews soon
+pub(crate) const SUBSCRIPTION_CHECK_INTERVAL_SECS: u64 = 10 * 60;
+/// How long before a renewal we remind the user
+pub(crate) const RENEWAL_REMINDER_SECS: u64 = 24 * 60 * 60;
+
+/// How the service of a [Subscription] gets paid
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SubscriptionPayment {
+    /// The service pulls the payments over its own NWC profile, within a budget
+    Nwc { profile_index: u32 },
+    /// The service sends an invoice every period that the user pays
+    Invoice,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Active,
+    /// The NWC profile is disabled and there are no reminders until it is resumed
+    Paused,
+    Canceled,
+}
+
+/// A recurring payment to a service, like Mutiny+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Subscription {
+    pub id: String,
+    /// Name of the service
+    pub name: String,
+    /// Amount in sats for every period
+    pub amount_sats: u64,
+    pub period: BudgetPeriod,
+    pub payment: SubscriptionPayment,
+    pub status: SubscriptionStatus,
+    /// Unix timestamp of when the subscription was registered
+    pub created_at: u64,
+    /// Unix timestamp of the next payment
+    pub next_renewal: u64,
+    /// Unix timestamp of the first renewal, the ones after it are counted from it
+    /// so they stay on the same day of the month. Not set for subscriptions from before
+    /// it was kept, they start counting at their next renewal.
+    #[serde(default)]
+    pub first_renewal: Option<u64>,
+    /// If we already reminded the user of the next renewal
+    #[serde(default)]
+    pub reminded: bool,
+}
+
+/// Something that happened to a [Subscription], see
+/// [crate::MutinyWallet::take_subscription_events]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SubscriptionEvent {
+    /// The subscription renews within a day, invoice based ones need to be paid
+    RenewalDue { subscription: Subscription },
+    /// The renewal date passed and the subscription is in its next period
+    Renewed { subscription: Subscription },
+}
+
+pub(crate) fn get_subscriptions<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<Subscription>, MutinyError> {
+    Ok(storage.get_data(SUBSCRIPTIONS_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn set_subscriptions<S: MutinyStorage>(
+    storage: &S,
+    subscriptions: &[Subscription],
+) -> Result<(), MutinyError> {
+    storage.set_data(SUBSCRIPTIONS_KEY.to_string(), subscriptions, None)
+}
+
+/// The renewal a number of periods after `anchor`, months past the end
+/// of a shorter month are clamped to its last day
+fn renewal_at(anchor: u64, period: &BudgetPeriod, periods: u32) -> u64 {
+    let Some(time) = DateTime::<Utc>::from_timestamp(anchor as i64, 0) else {
+        return u64::MAX;
+    };
+    let next = match period {
+        BudgetPeriod::Day => time.checked_add_days(Days::new(periods.into())),
+        BudgetPeriod::Week => time.checked_add_days(Days::new(7 * u64::from(periods))),
+        BudgetPeriod::Month => time.checked_add_months(Months::new(periods)),
+        BudgetPeriod::Year => periods
+            .checked_mul(12)
+            .and_then(|months| time.checked_add_months(Months::new(months))),
+        BudgetPeriod::Seconds(secs) => {
+            return anchor.saturating_add((*secs).max(1).saturating_mul(periods.into()))
+        }
+    };
+
+    next.map_or(u64::MAX, |t| t.timestamp() as u64)
+}
+
+/// The renewal one period after `from`
+pub(crate) fn next_renewal(from: u64, period: &BudgetPeriod) -> u64 {
+    renewal_at(from, period, 1)
+}
+
+/// The first renewal after `now`, skipping the periods that passed.
+///
+/// Renewals are counted in whole periods from the `anchor` renewal, so a short month
+/// doesn't move the ones after it to an earlier day.
+pub(crate) fn next_renewal_after(anchor: u64, period: &BudgetPeriod, now: u64) -> u64 {
+    if anchor > now {
+        return anchor;
+    }
+    if let BudgetPeriod::Seconds(secs) = period {
+        let secs = (*secs).max(1);
+        let periods = (now - anchor) / secs + 1;
+        return anchor.saturating_add(periods.saturating_mul(secs));
+    }
+
+    let mut periods = 1;
+    loop {
+        let renewal = renewal_at(anchor, period, periods);
+        if renewal > now {
+            return renewal;
+        }
+        periods += 1;
+    }
+}
+
+impl Subscription {
+    /// Moves the subscription to its first renewal after `now`
+    pub(crate) fn renew_after(&mut self, now: u64) {
+        let anchor = *self.first_renewal.get_or_insert(self.next_renewal);
+        self.next_renewal = next_renewal_after(anchor, &self.period, now);
+        self.reminded = false;
+    }
+}
+
+/// Moves the active subscriptions to their next period and reminds of upcoming renewals
+pub(crate) fn check_renewals(
+    subscriptions: &mut [Subscription],
+    now: u64,
+) -> Vec<SubscriptionEvent> {
+    let mut events = vec![];
+    for subscription in subscriptions
+        .iter_mut()
+        .filter(|s| s.status == SubscriptionStatus::Active)
+    {
+        if subscription.next_renewal <= now {
+            subscription.renew_after(now);
+            events.push(SubscriptionEvent::Renewed {
+                subscription: subscription.clone(),
+            });
+        }
+
+        if !subscription.reminded && subscription.next_renewal <= now + RENEWAL_REMINDER_SECS {
+            subscription.reminded = true;
+            events.push(SubscriptionEvent::RenewalDue {
+                subscription: subscription.clone(),
+            });
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // 2024-01-31 00:00:00 UTC
+    const JAN_31: u64 = 1_706_659_200;
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn subscription(period: BudgetPeriod, next_renewal: u64) -> Subscription {
+        Subscription {
+            id: "id".to_string(),
+            name: "Service".to_string(),
+            amount_sats: 1_000,
+            period,
+            payment: SubscriptionPayment::Invoice,
+            status: SubscriptionStatus::Active,
+            created_at: 0,
+            next_renewal,
+            first_renewal: Some(next_renewal),
+            reminded: false,
+        }
+    }
+
+    #[test]
+    fn test_next_renewal() {
+        assert_eq!(next_renewal(JAN_31, &BudgetPeriod::Day), JAN_31 + DAY);
+        assert_eq!(next_renewal(JAN_31, &BudgetPeriod::Week), JAN_31 + 7 * DAY);
+        // months are clamped to their last day, 2024-02-29
+        assert_eq!(
+            next_renewal(JAN_31, &BudgetPeriod::Month),
+            JAN_31 + 29 * DAY
+        );
+        assert_eq!(
+            next_renewal(JAN_31, &BudgetPeriod::Year),
+            JAN_31 + 366 * DAY
+        );
+        assert_eq!(
+            next_renewal(JAN_31, &BudgetPeriod::Seconds(60)),
+            JAN_31 + 60
+        );
+
+        let period = BudgetPeriod::Seconds(60);
+        assert_eq!(next_renewal_after(JAN_31, &period, JAN_31 - 1), JAN_31);
+        assert_eq!(next_renewal_after(JAN_31, &period, JAN_31), JAN_31 + 60);
+        assert_eq!(
+            next_renewal_after(JAN_31, &period, JAN_31 + 130),
+            JAN_31 + 180
+        );
+        assert_eq!(
+            next_renewal_after(JAN_31, &BudgetPeriod::Day, JAN_31 + DAY + 1),
+            JAN_31 + 2 * DAY
+        );
+    }
+
+    #[test]
+    fn test_check_renewals() {
+        let mut subscriptions = vec![
+            subscription(BudgetPeriod::Month, JAN_31),
+            subscription(BudgetPeriod::Week, JAN_31 + 3 * DAY),
+        ];
+        let mut paused = subscription(BudgetPeriod::Day, JAN_31);
+        paused.status = SubscriptionStatus::Paused;
+        subscriptions.push(paused.clone());
+
+        // nothing is due yet
+        assert!(check_renewals(&mut subscriptions, JAN_31 - 2 * DAY).is_empty());
+
+        // the first one renews within a day, only reminded once
+        let events = check_renewals(&mut subscriptions, JAN_31 - DAY / 2);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            SubscriptionEvent::RenewalDue { subscription } if subscription.next_renewal == JAN_31
+        ));
+        assert!(check_renewals(&mut subscriptions, JAN_31 - DAY / 4).is_empty());
+
+        // it moves on to the next month
+        let events = check_renewals(&mut subscriptions, JAN_31 + 1);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], SubscriptionEvent::Renewed { .. }));
+        assert_eq!(subscriptions[0].next_renewal, JAN_31 + 29 * DAY);
+        assert!(!subscriptions[0].reminded);
+
+        // and back to the 31st after February, 2024-03-31
+        check_renewals(&mut subscriptions, JAN_31 + 29 * DAY);
+        assert_eq!(subscriptions[0].next_renewal, JAN_31 + 60 * DAY);
+
+        // subscriptions from before the first renewal was kept start counting at the next one
+        let mut old = subscription(BudgetPeriod::Month, JAN_31);
+        old.first_renewal = None;
+        old.renew_after(JAN_31);
+        assert_eq!(old.first_renewal, Some(JAN_31));
+        assert_eq!(old.next_renewal, JAN_31 + 29 * DAY);
+
+        // paused subscriptions are left alone
+        assert_eq!(subscriptions[2], paused);
+    }
+
+    #[test]
+    fn test_subscription_storage() {
+        let storage = MemoryStorage::default();
+        assert!(get_subscriptions(&storage).unwrap().is_empty());
+
+        let subscriptions = vec![subscription(BudgetPeriod::Month, JAN_31)];
+        set_subscriptions(&storage, &subscriptions).unwrap();
+        assert_eq!(get_subscriptions(&storage).unwrap(), subscriptions);
+    }
+}
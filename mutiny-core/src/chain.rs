@@ -1,16 +1,97 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use bitcoin::{Script, Transaction, Txid};
+use async_trait::async_trait;
+use bdk_chain::ConfirmationTime;
+use bitcoin::{BlockHash, OutPoint, Script, Transaction, Txid};
+use esplora_client::{AsyncClient, TxStatus};
 use lightning::chain::chaininterface::BroadcasterInterface;
 use lightning::chain::{Filter, WatchedOutput};
-use lightning::log_warn;
 use lightning::util::logger::Logger;
+use lightning::{log_info, log_warn};
 use lightning_transaction_sync::EsploraSyncClient;
+use serde::{Deserialize, Serialize};
 
+use crate::error::MutinyError;
 use crate::logging::MutinyLogger;
 use crate::onchain::OnChainWallet;
-use crate::storage::MutinyStorage;
+use crate::storage::{persist_transaction_details, transaction_details_key, MutinyStorage};
 use crate::utils;
+use crate::TransactionDetails;
+
+/// Confirmations we keep checking for reorgs, see [check_confirmations]
+pub(crate) const TRACKED_CONFIRMATIONS_KEY: &str = "tracked_confirmations";
+
+/// Number of confirmations after which we don't expect a reorg and stop tracking
+pub(crate) const REORG_SAFETY_DEPTH: u32 = 6;
+
+/// How long we keep tracking a transaction that doesn't confirm, it was likely replaced
+pub(crate) const TRACKED_CONFIRMATION_EXPIRY_SECS: u64 = 14 * 24 * 60 * 60;
+
+/// The chain lookups we make ourselves, syncing is done by BDK and LDK's esplora clients.
+///
+/// [AsyncClient] is used outside of tests, [crate::test_utils::MockChainSource] lets
+/// tests script blocks, confirmations and reorgs without network access.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait ChainSource {
+    async fn get_height(&self) -> Result<u32, esplora_client::Error>;
+
+    async fn get_tip_hash(&self) -> Result<BlockHash, esplora_client::Error>;
+
+    /// Gets the hash of the block at the height in the best chain
+    async fn get_block_hash(&self, height: u32) -> Result<BlockHash, esplora_client::Error>;
+
+    /// Gets the transaction if it is in the mempool or confirmed
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, esplora_client::Error>;
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus, esplora_client::Error>;
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), esplora_client::Error>;
+
+    /// Gets the transaction spending the output if it is in the mempool or confirmed
+    async fn get_spending_txid(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, esplora_client::Error>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl ChainSource for AsyncClient {
+    async fn get_height(&self) -> Result<u32, esplora_client::Error> {
+        AsyncClient::get_height(self).await
+    }
+
+    async fn get_tip_hash(&self) -> Result<BlockHash, esplora_client::Error> {
+        AsyncClient::get_tip_hash(self).await
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<BlockHash, esplora_client::Error> {
+        AsyncClient::get_block_hash(self, height).await
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, esplora_client::Error> {
+        AsyncClient::get_tx(self, txid).await
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus, esplora_client::Error> {
+        AsyncClient::get_tx_status(self, txid).await
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), esplora_client::Error> {
+        AsyncClient::broadcast(self, tx).await
+    }
+
+    async fn get_spending_txid(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, esplora_client::Error> {
+        let status =
+            AsyncClient::get_output_status(self, &outpoint.txid, outpoint.vout as u64).await?;
+        Ok(status.and_then(|s| s.txid))
+    }
+}
 
 pub struct MutinyChain<S: MutinyStorage> {
     pub tx_sync: Arc<EsploraSyncClient<Arc<MutinyLogger>>>,
@@ -52,6 +133,11 @@ impl<S: MutinyStorage> BroadcasterInterface for MutinyChain<S> {
         let logger = self.logger.clone();
         utils::spawn(async move {
             for tx in txs_clone {
+                // channel closes may not be in our wallet, watch them so
+                // they are rebroadcast if they get evicted
+                if let Err(e) = wallet.watch_broadcast(&tx) {
+                    log_warn!(logger, "Error watching transaction: {e}")
+                }
                 if let Err(e) = wallet.broadcast_transaction(tx).await {
                     log_warn!(logger, "Error broadcasting transaction: {e}")
                 }
@@ -59,3 +145,463 @@ impl<S: MutinyStorage> BroadcasterInterface for MutinyChain<S> {
         });
     }
 }
+
+/// What a tracked transaction is for, this decides how a reorg is handled
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TrackedTx {
+    /// Our [TransactionDetails] with the given internal id, like a federation peg-out
+    TransactionDetails(Txid),
+    /// The funding transaction of one of our channels
+    ChannelFunding,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TrackedConfirmation {
+    pub kind: TrackedTx,
+    /// Height and hash of the block it confirmed in, `None` until
+    /// we have seen it confirm or after it was reorged out
+    pub block: Option<(u32, BlockHash)>,
+    /// Unix timestamp of when we started tracking it
+    #[serde(default = "tracked_now")]
+    pub tracked_at: u64,
+}
+
+fn tracked_now() -> u64 {
+    utils::now().as_secs()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ConfirmationChange {
+    /// Confirmed for the first time or in a different block after a reorg
+    Confirmed {
+        txid: Txid,
+        kind: TrackedTx,
+        height: u32,
+        time: u64,
+    },
+    /// The block it confirmed in isn't in the best chain anymore
+    Reorged { txid: Txid, kind: TrackedTx },
+}
+
+fn get_tracked_confirmations<S: MutinyStorage>(
+    storage: &S,
+) -> Result<HashMap<Txid, TrackedConfirmation>, MutinyError> {
+    Ok(storage
+        .get_data(TRACKED_CONFIRMATIONS_KEY)?
+        .unwrap_or_default())
+}
+
+/// Starts tracking the confirmation of a transaction so we notice if it is reorged out
+pub(crate) fn track_confirmation<S: MutinyStorage>(
+    storage: &S,
+    txid: Txid,
+    kind: TrackedTx,
+    block: Option<(u32, BlockHash)>,
+) -> Result<(), MutinyError> {
+    let mut tracked = get_tracked_confirmations(storage)?;
+    let confirmation = TrackedConfirmation {
+        kind,
+        block,
+        tracked_at: tracked_now(),
+    };
+    tracked.insert(txid, confirmation);
+    storage.set_data(TRACKED_CONFIRMATIONS_KEY.to_string(), tracked, None)
+}
+
+/// Checks that the blocks our tracked transactions confirmed in are still in the best chain.
+///
+/// Only the block hash at each confirmation height is fetched, the transaction status
+/// is only looked up if the block changed or it hasn't confirmed yet. Transactions that
+/// are [REORG_SAFETY_DEPTH] blocks deep aren't tracked anymore, neither are ones that
+/// didn't confirm within [TRACKED_CONFIRMATION_EXPIRY_SECS] or whose details were deleted.
+///
+/// A transaction that fails to be checked is logged and checked again next time.
+pub(crate) async fn check_confirmations<S: MutinyStorage>(
+    storage: &S,
+    chain: &(dyn ChainSource + Send + Sync),
+    logger: &MutinyLogger,
+) -> Result<Vec<ConfirmationChange>, MutinyError> {
+    let tracked = get_tracked_confirmations(storage)?;
+    if tracked.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let tip = chain.get_height().await?;
+    let now = utils::now().as_secs();
+    let mut block_hashes: HashMap<u32, BlockHash> = HashMap::new();
+    let mut changes = vec![];
+    // the new block of the transactions that changed and the ones that are finished
+    let mut updates: HashMap<Txid, Option<(u32, BlockHash)>> = HashMap::new();
+    let mut finished = vec![];
+    for (txid, t) in tracked.iter() {
+        if let TrackedTx::TransactionDetails(internal_id) = t.kind {
+            if storage
+                .get_data::<TransactionDetails>(transaction_details_key(internal_id))?
+                .is_none()
+            {
+                finished.push(*txid);
+                continue;
+            }
+        }
+
+        if let Some((height, hash)) = t.block {
+            let current = match block_hashes.get(&height) {
+                Some(hash) => Some(*hash),
+                None if height <= tip => match chain.get_block_hash(height).await {
+                    Ok(hash) => {
+                        block_hashes.insert(height, hash);
+                        Some(hash)
+                    }
+                    Err(e) => {
+                        log_warn!(logger, "Failed to get block {height} for {txid}: {e}");
+                        continue;
+                    }
+                },
+                // the chain got shorter than our confirmation
+                None => None,
+            };
+            if current == Some(hash) {
+                if tip - height + 1 >= REORG_SAFETY_DEPTH {
+                    finished.push(*txid);
+                }
+                continue;
+            }
+        }
+
+        // see where the transaction is now
+        let status = match chain.get_tx_status(txid).await {
+            Ok(status) => status,
+            Err(e) => {
+                log_warn!(logger, "Failed to check confirmation of {txid}: {e}");
+                continue;
+            }
+        };
+        match (status.confirmed, status.block_height, status.block_hash) {
+            (true, Some(height), Some(hash)) => {
+                updates.insert(*txid, Some((height, hash)));
+                changes.push(ConfirmationChange::Confirmed {
+                    txid: *txid,
+                    kind: t.kind,
+                    height,
+                    time: status.block_time.unwrap_or(now),
+                });
+            }
+            _ if t.block.is_some() => {
+                updates.insert(*txid, None);
+                changes.push(ConfirmationChange::Reorged {
+                    txid: *txid,
+                    kind: t.kind,
+                });
+            }
+            _ if now.saturating_sub(t.tracked_at) >= TRACKED_CONFIRMATION_EXPIRY_SECS => {
+                log_info!(logger, "Stopped tracking {txid}, it never confirmed");
+                finished.push(*txid);
+            }
+            _ => {}
+        }
+    }
+
+    if updates.is_empty() && finished.is_empty() {
+        return Ok(changes);
+    }
+
+    // transactions could have been tracked while we were checking, so only the entries
+    // we checked are changed, and only if they weren't tracked again in the meantime
+    let mut current = get_tracked_confirmations(storage)?;
+    for (txid, t) in tracked {
+        let Some(entry) = current.get_mut(&txid) else {
+            continue;
+        };
+        if *entry != t {
+            continue;
+        }
+        if finished.contains(&txid) {
+            current.remove(&txid);
+        } else if let Some(block) = updates.remove(&txid) {
+            entry.block = block;
+        }
+    }
+    storage.set_data(TRACKED_CONFIRMATIONS_KEY.to_string(), current, None)?;
+
+    Ok(changes)
+}
+
+/// Checks the tracked confirmations and updates our state for any that changed.
+///
+/// Reorged [TransactionDetails] go back to unconfirmed, and reorged channel funding
+/// transactions are watched so they are rebroadcast if they drop out of the mempool.
+/// LDK rewinds the channel itself through its own chain sync.
+pub(crate) async fn handle_reorgs<S: MutinyStorage>(
+    storage: &S,
+    chain: &(dyn ChainSource + Send + Sync),
+    wallet: &OnChainWallet<S>,
+    logger: &MutinyLogger,
+) -> Result<(), MutinyError> {
+    for change in check_confirmations(storage, chain, logger).await? {
+        if let Err(e) = handle_confirmation_change(storage, chain, wallet, logger, change).await {
+            log_warn!(logger, "Failed to handle confirmation change: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_confirmation_change<S: MutinyStorage>(
+    storage: &S,
+    chain: &(dyn ChainSource + Send + Sync),
+    wallet: &OnChainWallet<S>,
+    logger: &MutinyLogger,
+    change: ConfirmationChange,
+) -> Result<(), MutinyError> {
+    match change {
+        ConfirmationChange::Confirmed {
+            txid,
+            kind,
+            height,
+            time,
+        } => {
+            log_info!(logger, "Transaction {txid} confirmed at height {height}");
+            if let TrackedTx::TransactionDetails(internal_id) = kind {
+                let position = ConfirmationTime::Confirmed { height, time };
+                update_transaction_details(storage, internal_id, position)?;
+            }
+        }
+        ConfirmationChange::Reorged { txid, kind } => {
+            log_warn!(logger, "Transaction {txid} was reorged out, rolling back");
+            match kind {
+                TrackedTx::TransactionDetails(internal_id) => {
+                    let position = ConfirmationTime::Unconfirmed {
+                        last_seen: utils::now().as_secs(),
+                    };
+                    update_transaction_details(storage, internal_id, position)?;
+                }
+                TrackedTx::ChannelFunding => {
+                    let tx = match chain.get_tx(&txid).await? {
+                        Some(tx) => Some(tx),
+                        None => wallet.get_transaction(txid)?.and_then(|t| t.transaction),
+                    };
+                    match tx {
+                        Some(tx) => wallet.watch_broadcast(&tx)?,
+                        None => {
+                            log_warn!(logger, "Could not find reorged funding tx {txid}")
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn update_transaction_details<S: MutinyStorage>(
+    storage: &S,
+    internal_id: Txid,
+    confirmation_time: ConfirmationTime,
+) -> Result<(), MutinyError> {
+    let key = transaction_details_key(internal_id);
+    let Some(mut details) = storage.get_data::<TransactionDetails>(&key)? else {
+        return Ok(());
+    };
+    details.confirmation_time = confirmation_time;
+    details.mempool_status = None;
+    persist_transaction_details(storage, &details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fees::MutinyFeeEstimator;
+    use crate::storage::{IndexItem, MemoryStorage};
+    use crate::test_utils::*;
+    use bitcoin::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
+    use std::sync::atomic::AtomicBool;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_wallet(
+        storage: MemoryStorage,
+        chain: Arc<MockChainSource>,
+    ) -> OnChainWallet<MemoryStorage> {
+        let esplora = Arc::new(
+            esplora_client::Builder::new("http://localhost:3002")
+                .build_async()
+                .unwrap(),
+        );
+        let logger = Arc::new(MutinyLogger::default());
+        let fees = Arc::new(MutinyFeeEstimator::new(
+            storage.clone(),
+            esplora.clone(),
+            logger.clone(),
+        ));
+        let stop = Arc::new(AtomicBool::new(false));
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[0; 32]).unwrap();
+
+        OnChainWallet::new(
+            xpriv,
+            storage,
+            Network::Regtest,
+            0,
+            esplora,
+            fees,
+            stop,
+            logger,
+        )
+        .unwrap()
+        .with_chain_source(chain)
+    }
+
+    fn details(storage: &MemoryStorage, txid: Txid) -> TransactionDetails {
+        let key = transaction_details_key(txid);
+        storage.get_data(key).unwrap().unwrap()
+    }
+
+    #[test]
+    async fn test_reorg_rolls_back_transaction_details() {
+        let storage = MemoryStorage::default();
+        let chain = Arc::new(MockChainSource::default());
+        let wallet = create_wallet(storage.clone(), chain.clone());
+        let logger = MutinyLogger::default();
+
+        let tx = create_dummy_tx(1_000);
+        let txid = tx.txid();
+        chain.add_to_mempool(tx);
+        let hash = chain.mine_blocks(1);
+        let transaction_details = TransactionDetails {
+            transaction: None,
+            txid: Some(txid),
+            internal_id: txid,
+            received: 1_000,
+            sent: 0,
+            fee: None,
+            confirmation_time: ConfirmationTime::Confirmed {
+                height: 1,
+                time: 100,
+            },
+            labels: vec![],
+            mempool_status: None,
+        };
+        persist_transaction_details(&storage, &transaction_details).unwrap();
+        let kind = TrackedTx::TransactionDetails(txid);
+        track_confirmation(&storage, txid, kind, Some((1, hash))).unwrap();
+
+        // nothing changed
+        let changes = check_confirmations(&storage, chain.as_ref(), &logger)
+            .await
+            .unwrap();
+        assert!(changes.is_empty());
+
+        chain.reorg(1, 2);
+        handle_reorgs(&storage, chain.as_ref(), &wallet, &logger)
+            .await
+            .unwrap();
+        assert!(matches!(
+            details(&storage, txid).confirmation_time,
+            ConfirmationTime::Unconfirmed { .. }
+        ));
+        let index = storage.activity_index();
+        let items: Vec<IndexItem> = index.read().unwrap().iter().cloned().collect();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].timestamp, None);
+
+        // confirms again in another block
+        chain.mine_blocks(1);
+        handle_reorgs(&storage, chain.as_ref(), &wallet, &logger)
+            .await
+            .unwrap();
+        match details(&storage, txid).confirmation_time {
+            ConfirmationTime::Confirmed { height, .. } => assert_eq!(height, 3),
+            ConfirmationTime::Unconfirmed { .. } => panic!("should be confirmed"),
+        }
+
+        // deep enough to stop tracking
+        chain.mine_blocks(REORG_SAFETY_DEPTH);
+        let changes = check_confirmations(&storage, chain.as_ref(), &logger)
+            .await
+            .unwrap();
+        assert!(changes.is_empty());
+        let tracked: HashMap<Txid, TrackedConfirmation> = storage
+            .get_data(TRACKED_CONFIRMATIONS_KEY)
+            .unwrap()
+            .unwrap();
+        assert!(tracked.is_empty());
+    }
+
+    #[test]
+    async fn test_reorged_funding_tx_is_rebroadcast() {
+        let storage = MemoryStorage::default();
+        let chain = Arc::new(MockChainSource::default());
+        let wallet = create_wallet(storage.clone(), chain.clone());
+        let logger = MutinyLogger::default();
+
+        let tx = create_dummy_tx(2_000);
+        let txid = tx.txid();
+        chain.add_to_mempool(tx);
+        chain.mine_blocks(1);
+
+        // the block is filled in on the first check
+        track_confirmation(&storage, txid, TrackedTx::ChannelFunding, None).unwrap();
+        let changes = check_confirmations(&storage, chain.as_ref(), &logger)
+            .await
+            .unwrap();
+        assert!(matches!(
+            changes.as_slice(),
+            [ConfirmationChange::Confirmed { height: 1, .. }]
+        ));
+
+        // reorged out and then evicted from the mempool
+        chain.reorg(1, 2);
+        handle_reorgs(&storage, chain.as_ref(), &wallet, &logger)
+            .await
+            .unwrap();
+        chain.evict(&txid);
+
+        wallet.check_mempool().await.unwrap();
+        assert!(chain.is_in_mempool(&txid));
+    }
+
+    #[test]
+    async fn test_finished_confirmations_are_pruned() {
+        let storage = MemoryStorage::default();
+        let chain = Arc::new(MockChainSource::default());
+        let logger = MutinyLogger::default();
+        chain.mine_blocks(1);
+
+        // never confirmed and replaced long ago
+        let replaced = create_dummy_tx(1_000).txid();
+        // its transaction details were deleted
+        let deleted = create_dummy_tx(2_000).txid();
+        let pending = create_dummy_tx(3_000).txid();
+        let tracked = HashMap::from([
+            (
+                replaced,
+                TrackedConfirmation {
+                    kind: TrackedTx::ChannelFunding,
+                    block: None,
+                    tracked_at: 0,
+                },
+            ),
+            (
+                deleted,
+                TrackedConfirmation {
+                    kind: TrackedTx::TransactionDetails(deleted),
+                    block: None,
+                    tracked_at: tracked_now(),
+                },
+            ),
+        ]);
+        storage
+            .set_data(TRACKED_CONFIRMATIONS_KEY.to_string(), tracked, None)
+            .unwrap();
+        track_confirmation(&storage, pending, TrackedTx::ChannelFunding, None).unwrap();
+
+        let changes = check_confirmations(&storage, chain.as_ref(), &logger)
+            .await
+            .unwrap();
+        assert!(changes.is_empty());
+        let tracked = get_tracked_confirmations(&storage).unwrap();
+        assert_eq!(tracked.keys().collect::<Vec<_>>(), vec![&pending]);
+    }
+}
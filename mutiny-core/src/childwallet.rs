@@ -0,0 +1,121 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bip39::Mnemonic;
+use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
+use bitcoin::hashes::{hmac, sha512, Hash, HashEngine};
+use bitcoin::secp256k1::Secp256k1;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const CHILD_WALLETS_KEY: &str = "child_wallets";
+/// BIP-85 derivation path of 12 word english BIP-39 seeds
+const BIP85_BIP39_PATH: &str = "m/83696968'/39'/0'/12'";
+
+/// A constrained wallet for a kid that is managed from the main wallet.
+///
+/// The child spends from our balance over its own NWC connection, up to the allowance
+/// of its budget. It also gets its own seed, derived from ours, that is kept in a
+/// separate storage namespace so it can become a full wallet later on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChildWallet {
+    pub id: String,
+    pub name: String,
+    /// BIP-85 index the seed of the child is derived with
+    pub key_index: u32,
+    /// Index of the NWC profile the child spends with
+    pub nwc_profile_index: u32,
+    /// Unix timestamp of when the child wallet was created
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ChildWalletStorage {
+    pub wallets: Vec<ChildWallet>,
+    /// Seeds are never reused, even after the child wallet is removed
+    pub next_key_index: u32,
+}
+
+pub(crate) fn get_child_wallet_storage<S: MutinyStorage>(
+    storage: &S,
+) -> Result<ChildWalletStorage, MutinyError> {
+    Ok(storage.get_data(CHILD_WALLETS_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn set_child_wallet_storage<S: MutinyStorage>(
+    storage: &S,
+    children: &ChildWalletStorage,
+) -> Result<(), MutinyError> {
+    storage.set_data(CHILD_WALLETS_KEY.to_string(), children, None)
+}
+
+/// Namespace of the child wallet in storage, see [crate::storage::NamespacedStorage]
+pub(crate) fn child_wallet_namespace(id: &str) -> String {
+    format!("child-{id}")
+}
+
+/// Derives the seed of a child wallet from our key with BIP-85,
+/// so it can always be recovered from our seed
+pub(crate) fn derive_child_mnemonic(
+    xprivkey: ExtendedPrivKey,
+    index: u32,
+) -> Result<Mnemonic, MutinyError> {
+    let path = DerivationPath::from_str(BIP85_BIP39_PATH)?
+        .extend([ChildNumber::from_hardened_idx(index)?]);
+    let context = Secp256k1::new();
+    let derived = xprivkey.derive_priv(&context, &path)?;
+
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(b"bip-entropy-from-k");
+    engine.input(&derived.private_key.secret_bytes());
+    let entropy = hmac::Hmac::<sha512::Hash>::from_engine(engine).to_byte_array();
+
+    // 16 bytes of entropy for 12 words
+    Ok(Mnemonic::from_entropy(&entropy[..16])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use hex_conservative::DisplayHex;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_derive_child_mnemonic() {
+        // test vector from BIP-85
+        let xprivkey = ExtendedPrivKey::from_str("xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb").unwrap();
+        let mnemonic = derive_child_mnemonic(xprivkey, 0).unwrap();
+        assert_eq!(mnemonic.word_count(), 12);
+        assert_eq!(
+            mnemonic.to_entropy().as_slice().to_lower_hex_string(),
+            "6250b68daf746d12a24d58b4787a714b"
+        );
+
+        let other = derive_child_mnemonic(xprivkey, 1).unwrap();
+        assert_ne!(mnemonic, other);
+    }
+
+    #[test]
+    fn test_child_wallet_storage() {
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_child_wallet_storage(&storage).unwrap(),
+            ChildWalletStorage::default()
+        );
+
+        let children = ChildWalletStorage {
+            wallets: vec![ChildWallet {
+                id: "id".to_string(),
+                name: "Satoshi".to_string(),
+                key_index: 0,
+                nwc_profile_index: 1001,
+                created_at: 1_700_000_000,
+            }],
+            next_key_index: 1,
+        };
+        set_child_wallet_storage(&storage, &children).unwrap();
+        assert_eq!(get_child_wallet_storage(&storage).unwrap(), children);
+        assert_eq!(child_wallet_namespace("id"), "child-id");
+    }
+}
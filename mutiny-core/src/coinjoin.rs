@@ -0,0 +1,351 @@
+//! A simple 2-party coinjoin over nostr DMs, the reference [CollaborativeSpend].
+
+use crate::error::MutinyError;
+use crate::nostr::client::NostrClient;
+use crate::nostr::primal::PrimalApi;
+use crate::nostr::NostrManager;
+use crate::onchain::{CollaborativeContribution, CollaborativeSpend};
+use crate::storage::MutinyStorage;
+use crate::utils;
+use ::nostr::prelude::rand::{rngs::OsRng, seq::SliceRandom};
+use ::nostr::{Filter, Kind, Timestamp};
+use async_trait::async_trait;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::{absolute, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_error, log_warn};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long we wait for the other party's next message
+const COINJOIN_TIMEOUT_SECS: u64 = 300;
+/// How often we check for the other party's messages
+const COINJOIN_POLL_MS: i32 = 5_000;
+
+/// Which side of the coinjoin we are
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinjoinRole {
+    /// Sends its inputs and outputs first
+    Initiator,
+    /// Builds the transaction from both parties' inputs and outputs
+    /// and sends the final transaction once both signed
+    Responder,
+}
+
+/// The messages of the protocol, sent as JSON in DMs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CoinjoinMessage {
+    /// The initiator's inputs and outputs
+    Propose {
+        session: String,
+        contribution: CollaborativeContribution,
+    },
+    /// The unsigned transaction the responder built, as a base64 PSBT
+    Unsigned { session: String, psbt: String },
+    /// The PSBT with the initiator's signatures
+    Signed { session: String, psbt: String },
+    /// The fully signed transaction
+    Complete { session: String, tx: Transaction },
+}
+
+impl CoinjoinMessage {
+    fn session(&self) -> &str {
+        match self {
+            CoinjoinMessage::Propose { session, .. } => session,
+            CoinjoinMessage::Unsigned { session, .. } => session,
+            CoinjoinMessage::Signed { session, .. } => session,
+            CoinjoinMessage::Complete { session, .. } => session,
+        }
+    }
+}
+
+/// A coinjoin with one other wallet over nostr DMs. Both parties send the same
+/// amount to a new address of their own, so those two outputs can't be told apart.
+///
+/// The session id and the amount need to be agreed on beforehand, one party is the
+/// [CoinjoinRole::Initiator] and the other the [CoinjoinRole::Responder].
+/// Use it with [crate::nodemanager::NodeManager::collaborative_spend].
+pub struct NostrCoinjoin<S: MutinyStorage, P: PrimalApi, C: NostrClient> {
+    nostr: Arc<NostrManager<S, P, C>>,
+    peer: ::nostr::PublicKey,
+    session: String,
+    amount: u64,
+    role: CoinjoinRole,
+    /// Messages from before we started belong to other sessions
+    started_at: Timestamp,
+}
+
+impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrCoinjoin<S, P, C> {
+    pub fn new(
+        nostr: Arc<NostrManager<S, P, C>>,
+        peer: ::nostr::PublicKey,
+        session: String,
+        amount: u64,
+        role: CoinjoinRole,
+    ) -> Self {
+        Self {
+            nostr,
+            peer,
+            session,
+            amount,
+            role,
+            started_at: Timestamp::now(),
+        }
+    }
+
+    async fn send(&self, message: CoinjoinMessage) -> Result<(), MutinyError> {
+        let message = serde_json::to_string(&message)?;
+        self.nostr.send_dm(self.peer, message).await?;
+        Ok(())
+    }
+
+    /// Waits for the next message of this session from the peer that `f` accepts
+    async fn wait_for<T>(
+        &self,
+        f: impl Fn(CoinjoinMessage) -> Option<T>,
+    ) -> Result<T, MutinyError> {
+        let filter = Filter::new()
+            .kind(Kind::EncryptedDirectMessage)
+            .author(self.peer)
+            .pubkey(self.nostr.get_npub().await)
+            .since(self.started_at);
+
+        let deadline = utils::now().as_secs() + COINJOIN_TIMEOUT_SECS;
+        while utils::now().as_secs() < deadline {
+            if self.nostr.stop.load(Ordering::Relaxed) {
+                return Err(MutinyError::NotRunning);
+            }
+
+            let events = self
+                .nostr
+                .client
+                .get_events_of(vec![filter.clone()], Some(Duration::from_secs(10)))
+                .await?;
+            for event in events {
+                // other DMs from the peer are just skipped
+                let Ok(decrypted) = self.nostr.decrypt_dm(self.peer, &event.content).await else {
+                    continue;
+                };
+                let Ok(message) = serde_json::from_str::<CoinjoinMessage>(&decrypted) else {
+                    continue;
+                };
+                if message.session() != self.session {
+                    continue;
+                }
+                if let Some(res) = f(message) {
+                    return Ok(res);
+                }
+            }
+
+            utils::sleep(COINJOIN_POLL_MS).await;
+        }
+
+        log_warn!(
+            self.nostr.logger,
+            "Timed out waiting for coinjoin peer {}",
+            self.peer
+        );
+        Err(MutinyError::CoinjoinFailed)
+    }
+
+    /// The responder puts both parties' inputs and outputs in a random order
+    fn build_psbt(
+        &self,
+        ours: &CollaborativeContribution,
+        theirs: &CollaborativeContribution,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let mut inputs: Vec<(OutPoint, TxOut)> =
+            ours.inputs.iter().chain(&theirs.inputs).cloned().collect();
+        let mut outputs: Vec<TxOut> = ours
+            .outputs
+            .iter()
+            .chain(&theirs.outputs)
+            .cloned()
+            .collect();
+        inputs.shuffle(&mut OsRng);
+        outputs.shuffle(&mut OsRng);
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|(outpoint, _)| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: Default::default(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs,
+        };
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx)
+            .map_err(|_| MutinyError::WalletOperationFailed)?;
+        for (input, (_, txout)) in psbt.inputs.iter_mut().zip(inputs) {
+            input.witness_utxo = Some(txout);
+        }
+        Ok(psbt)
+    }
+
+    /// Both parties need an output of the agreed amount, otherwise it isn't a coinjoin
+    fn check_equal_outputs(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        let count = tx.output.iter().filter(|o| o.value == self.amount).count();
+        if count < 2 {
+            log_error!(
+                self.nostr.logger,
+                "Coinjoin needs two outputs of {} sats",
+                self.amount
+            );
+            return Err(MutinyError::CoinjoinFailed);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> CollaborativeSpend for NostrCoinjoin<S, P, C> {
+    async fn build(
+        &self,
+        ours: &CollaborativeContribution,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let psbt = match self.role {
+            CoinjoinRole::Initiator => {
+                self.send(CoinjoinMessage::Propose {
+                    session: self.session.clone(),
+                    contribution: ours.clone(),
+                })
+                .await?;
+
+                let psbt = self
+                    .wait_for(|m| match m {
+                        CoinjoinMessage::Unsigned { psbt, .. } => Some(psbt),
+                        _ => None,
+                    })
+                    .await?;
+                PartiallySignedTransaction::from_str(&psbt)
+                    .map_err(|_| MutinyError::InvalidArgumentsError)?
+            }
+            CoinjoinRole::Responder => {
+                let theirs = self
+                    .wait_for(|m| match m {
+                        CoinjoinMessage::Propose { contribution, .. } => Some(contribution),
+                        _ => None,
+                    })
+                    .await?;
+
+                let psbt = self.build_psbt(ours, &theirs)?;
+                self.send(CoinjoinMessage::Unsigned {
+                    session: self.session.clone(),
+                    psbt: psbt.to_string(),
+                })
+                .await?;
+                psbt
+            }
+        };
+
+        self.check_equal_outputs(&psbt.unsigned_tx)?;
+        log_debug!(
+            self.nostr.logger,
+            "Built coinjoin {} with {}",
+            psbt.unsigned_tx.txid(),
+            self.peer
+        );
+        Ok(psbt)
+    }
+
+    async fn finalize(
+        &self,
+        signed: PartiallySignedTransaction,
+    ) -> Result<Transaction, MutinyError> {
+        match self.role {
+            CoinjoinRole::Initiator => {
+                self.send(CoinjoinMessage::Signed {
+                    session: self.session.clone(),
+                    psbt: signed.to_string(),
+                })
+                .await?;
+
+                self.wait_for(|m| match m {
+                    CoinjoinMessage::Complete { tx, .. } => Some(tx),
+                    _ => None,
+                })
+                .await
+            }
+            CoinjoinRole::Responder => {
+                let theirs = self
+                    .wait_for(|m| match m {
+                        CoinjoinMessage::Signed { psbt, .. } => {
+                            PartiallySignedTransaction::from_str(&psbt).ok()
+                        }
+                        _ => None,
+                    })
+                    .await?;
+
+                let mut psbt = signed;
+                psbt.combine(theirs)
+                    .map_err(|_| MutinyError::InvalidArgumentsError)?;
+                let tx = psbt.extract_tx();
+                if tx.input.iter().any(|i| i.witness.is_empty()) {
+                    log_error!(
+                        self.nostr.logger,
+                        "Coinjoin peer {} did not sign all their inputs",
+                        self.peer
+                    );
+                    return Err(MutinyError::CoinjoinFailed);
+                }
+
+                self.send(CoinjoinMessage::Complete {
+                    session: self.session.clone(),
+                    tx: tx.clone(),
+                })
+                .await?;
+                Ok(tx)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::ScriptBuf;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_coinjoin_message_serde() {
+        let contribution = CollaborativeContribution {
+            inputs: vec![(
+                OutPoint::null(),
+                TxOut {
+                    value: 100_000,
+                    script_pubkey: ScriptBuf::new(),
+                },
+            )],
+            outputs: vec![TxOut {
+                value: 50_000,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let message = CoinjoinMessage::Propose {
+            session: "session".to_string(),
+            contribution,
+        };
+
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains("\"type\":\"propose\""));
+        let parsed: CoinjoinMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, message);
+        assert_eq!(parsed.session(), "session");
+
+        // other DMs aren't coinjoin messages
+        assert!(serde_json::from_str::<CoinjoinMessage>("\"hello\"").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::error::MutinyError;
+use crate::event::{HTLCStatus, PaymentInfo};
+use crate::storage::{
+    get_payment_hash_from_key, MutinyStorage, PAYMENT_INBOUND_PREFIX_KEY,
+    PAYMENT_OUTBOUND_PREFIX_KEY, PAYMENT_PATHS_PREFIX_KEY,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const COMPACTION_POLICY_KEY: &str = "compaction_policy";
+const LAST_COMPACTION_KEY: &str = "last_compaction";
+/// How often storage is compacted automatically
+pub(crate) const COMPACTION_INTERVAL_SECS: u64 = 24 * 60 * 60;
+/// How often we check if an automatic compaction is due
+pub(crate) const COMPACTION_CHECK_INTERVAL_SECS: u64 = 60 * 60;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// What storage compaction is allowed to delete, see [crate::MutinyWallet::compact_storage]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Failed payments and expired unpaid invoices older than this many days are deleted,
+    /// `None` keeps them forever
+    pub failed_payment_max_age_days: Option<u64>,
+    /// Monitors of channels closed more than this many days ago are deleted once there is
+    /// nothing left to claim from them, `None` keeps them forever
+    pub resolved_monitor_min_age_days: Option<u64>,
+    /// If compaction runs in the background once a day
+    pub automatic: bool,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            failed_payment_max_age_days: Some(30),
+            resolved_monitor_min_age_days: Some(14),
+            automatic: true,
+        }
+    }
+}
+
+impl CompactionPolicy {
+    pub(crate) fn failed_payment_cutoff(&self, now: u64) -> Option<u64> {
+        self.failed_payment_max_age_days
+            .map(|days| now.saturating_sub(days * DAY_SECS))
+    }
+
+    pub(crate) fn resolved_monitor_min_age_secs(&self) -> Option<u64> {
+        self.resolved_monitor_min_age_days
+            .map(|days| days * DAY_SECS)
+    }
+}
+
+/// What a storage compaction deleted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub payments_removed: usize,
+    pub monitors_removed: usize,
+    /// Approximate size of the deleted data, before encryption
+    pub bytes_reclaimed: u64,
+    /// Unix timestamp of when the compaction ran
+    pub timestamp: u64,
+}
+
+impl CompactionStats {
+    pub(crate) fn merge(&mut self, other: CompactionStats) {
+        self.payments_removed += other.payments_removed;
+        self.monitors_removed += other.monitors_removed;
+        self.bytes_reclaimed += other.bytes_reclaimed;
+    }
+}
+
+pub(crate) fn get_compaction_policy<S: MutinyStorage>(
+    storage: &S,
+) -> Result<CompactionPolicy, MutinyError> {
+    Ok(storage.get_data(COMPACTION_POLICY_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn set_compaction_policy<S: MutinyStorage>(
+    storage: &S,
+    policy: CompactionPolicy,
+) -> Result<(), MutinyError> {
+    storage.set_data(COMPACTION_POLICY_KEY.to_string(), policy, None)
+}
+
+pub(crate) fn get_last_compaction<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<CompactionStats>, MutinyError> {
+    storage.get_data(LAST_COMPACTION_KEY)
+}
+
+pub(crate) fn set_last_compaction<S: MutinyStorage>(
+    storage: &S,
+    stats: CompactionStats,
+) -> Result<(), MutinyError> {
+    storage.set_data(LAST_COMPACTION_KEY.to_string(), stats, None)
+}
+
+/// Returns true if the payment will never succeed and was last updated before the cutoff
+fn is_stale_payment(payment: &PaymentInfo, inbound: bool, cutoff: u64) -> bool {
+    if payment.last_update >= cutoff {
+        return false;
+    }
+
+    match payment.status {
+        HTLCStatus::Failed => true,
+        // unpaid invoices can't be paid anymore once they expire
+        HTLCStatus::Pending if inbound => payment.bolt11.as_ref().is_some_and(|invoice| {
+            let expiry = invoice.duration_since_epoch() + invoice.expiry_time();
+            expiry.as_secs() < cutoff
+        }),
+        _ => false,
+    }
+}
+
+/// Deletes failed payments and expired unpaid invoices last updated before the cutoff,
+/// along with the paths we saved for them.
+///
+/// These are never in the activity index so it doesn't need to be updated.
+pub(crate) fn prune_stale_payments<S: MutinyStorage>(
+    storage: &S,
+    cutoff: u64,
+) -> Result<CompactionStats, MutinyError> {
+    let mut stats = CompactionStats::default();
+
+    for (prefix, inbound) in [
+        (PAYMENT_INBOUND_PREFIX_KEY, true),
+        (PAYMENT_OUTBOUND_PREFIX_KEY, false),
+    ] {
+        let payments: HashMap<String, PaymentInfo> = storage.scan(prefix, None)?;
+
+        let mut keys = vec![];
+        for (key, payment) in payments {
+            if !is_stale_payment(&payment, inbound, cutoff) {
+                continue;
+            }
+            stats.bytes_reclaimed += serde_json::to_vec(&payment)?.len() as u64;
+
+            let hash = get_payment_hash_from_key(&key, prefix);
+            let paths_key = format!("{PAYMENT_PATHS_PREFIX_KEY}{hash}");
+            if let Some(paths) = storage.get_data::<Value>(&paths_key)? {
+                stats.bytes_reclaimed += serde_json::to_vec(&paths)?.len() as u64;
+                keys.push(paths_key);
+            }
+
+            keys.push(key);
+            stats.payments_removed += 1;
+        }
+
+        if !keys.is_empty() {
+            storage.delete(&keys)?;
+        }
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::MillisatAmount;
+    use crate::logging::MutinyLogger;
+    use crate::storage::{persist_payment_info, read_payment_info, MemoryStorage};
+    use crate::PrivacyLevel;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn payment(status: HTLCStatus, last_update: u64) -> PaymentInfo {
+        PaymentInfo {
+            preimage: None,
+            secret: None,
+            status,
+            amt_msat: MillisatAmount(Some(1_000)),
+            fee_paid_msat: None,
+            bolt11: None,
+            payee_pubkey: None,
+            privacy_level: PrivacyLevel::NotAvailable,
+            last_update,
+            custom_tlvs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_prune_stale_payments() {
+        let storage = MemoryStorage::default();
+        let logger = MutinyLogger::default();
+
+        let old_failed = [1; 32];
+        let new_failed = [2; 32];
+        let old_succeeded = [3; 32];
+        persist_payment_info(
+            &storage,
+            &old_failed,
+            &payment(HTLCStatus::Failed, 10),
+            false,
+        )
+        .unwrap();
+        persist_payment_info(
+            &storage,
+            &new_failed,
+            &payment(HTLCStatus::Failed, 200),
+            false,
+        )
+        .unwrap();
+        persist_payment_info(
+            &storage,
+            &old_succeeded,
+            &payment(HTLCStatus::Succeeded, 10),
+            false,
+        )
+        .unwrap();
+
+        let stats = prune_stale_payments(&storage, 100).unwrap();
+        assert_eq!(stats.payments_removed, 1);
+        assert!(stats.bytes_reclaimed > 0);
+
+        assert!(read_payment_info(&storage, &old_failed, false, &logger).is_none());
+        assert!(read_payment_info(&storage, &new_failed, false, &logger).is_some());
+        assert!(read_payment_info(&storage, &old_succeeded, false, &logger).is_some());
+
+        // nothing left to prune
+        let stats = prune_stale_payments(&storage, 100).unwrap();
+        assert_eq!(stats, CompactionStats::default());
+    }
+
+    #[test]
+    fn test_compaction_policy() {
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_compaction_policy(&storage).unwrap(),
+            CompactionPolicy::default()
+        );
+
+        let policy = CompactionPolicy {
+            failed_payment_max_age_days: None,
+            resolved_monitor_min_age_days: Some(1),
+            automatic: false,
+        };
+        set_compaction_policy(&storage, policy).unwrap();
+        assert_eq!(get_compaction_policy(&storage).unwrap(), policy);
+        assert_eq!(policy.failed_payment_cutoff(1_000_000), None);
+        assert_eq!(policy.resolved_monitor_min_age_secs(), Some(DAY_SECS));
+    }
+}
@@ -0,0 +1,512 @@
+use crate::blindauth::TokenStorage;
+use crate::error::MutinyError;
+use crate::federation::{FederationStorage, FEDIMINTS_PREFIX_KEY};
+use crate::handoff::DEVICE_HANDOFF_KEY;
+use crate::labels::{
+    Contact, LabelItem, ADDRESS_LABELS_MAP_KEY, CONTACT_PREFIX, INVOICE_LABELS_MAP_KEY,
+    LABEL_PREFIX,
+};
+use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+use crate::logging::MutinyLogger;
+use crate::nodemanager::NodeStorage;
+use crate::storage::{
+    DeviceLock, MutinyStorage, VersionedValue, DEVICE_LOCK_KEY, FEDERATIONS_KEY, NODES_KEY,
+    SERVICE_TOKENS,
+};
+use crate::utils;
+use crate::vss::{KeyVersion, MutinyVssClient, VssKeyValueItem};
+use bitcoin::hashes::{sha256, Hash};
+use lightning::log_debug;
+use lightning::util::logger::Logger;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The last VSS value we took or merged for each key without a version, see [SyncState]
+pub const VSS_SYNC_STATE_KEY: &str = "vss_sync_state";
+/// Conflicts that need the user to pick a value, see [get_sync_conflicts]
+pub const SYNC_CONFLICTS_KEY: &str = "sync_conflicts";
+
+/// How a key changed on both this device and VSS is merged
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Keep every address, invoice and label either side has
+    Union,
+    /// Keep the contact that was used last
+    LastUsed,
+    /// Take the VSS value, it only keeps the newest version so it is the last write
+    LastWriterWins,
+}
+
+impl MergeStrategy {
+    /// `None` for keys that are synced on their own and never merged
+    pub fn for_key(key: &str) -> Option<Self> {
+        match key {
+            DEVICE_HANDOFF_KEY => None,
+            ADDRESS_LABELS_MAP_KEY | INVOICE_LABELS_MAP_KEY => Some(Self::Union),
+            k if k.starts_with(LABEL_PREFIX) => Some(Self::Union),
+            k if k.starts_with(CONTACT_PREFIX) => Some(Self::LastUsed),
+            _ => Some(Self::LastWriterWins),
+        }
+    }
+}
+
+/// The VSS value of a key as of the last sync, this is the base
+/// that tells which side changed the key since then
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct SyncState {
+    digest: String,
+    version: u32,
+}
+
+/// A key that was changed on both this device and VSS and couldn't be merged
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SyncConflict {
+    pub key: String,
+    pub local: Value,
+    pub remote: Value,
+    pub remote_version: u32,
+    /// Unix timestamp of when the conflict was found
+    pub timestamp: u64,
+}
+
+enum Merge {
+    /// Store this value locally
+    Use(Value),
+    KeepLocal,
+    Conflict,
+}
+
+/// If a key without a version changed on VSS since the last sync and needs to be fetched,
+/// VSS versions only go up so the same version means the same value
+pub fn needs_vss_fetch<S: MutinyStorage>(
+    storage: &S,
+    kv: &KeyVersion,
+) -> Result<bool, MutinyError> {
+    if MergeStrategy::for_key(&kv.key).is_none() {
+        return Ok(false);
+    }
+
+    let state: HashMap<String, SyncState> =
+        storage.get_data(VSS_SYNC_STATE_KEY)?.unwrap_or_default();
+    Ok(!state.get(&kv.key).is_some_and(|s| s.version == kv.version))
+}
+
+/// If the key changed on VSS since we last had it and needs to be fetched
+pub fn needs_vss_key<S: MutinyStorage>(
+    kv: &KeyVersion,
+    vss: &MutinyVssClient,
+    current: &S,
+    logger: &MutinyLogger,
+) -> Result<bool, MutinyError> {
+    if vss.is_excluded(&kv.key) {
+        return Ok(false);
+    }
+
+    let local_version = match kv.key.as_str() {
+        NODES_KEY => current.get_data::<NodeStorage>(&kv.key)?.map(|n| n.version),
+        FEDERATIONS_KEY => current
+            .get_data::<FederationStorage>(&kv.key)?
+            .map(|f| f.version),
+        // we use time as version for device lock
+        DEVICE_LOCK_KEY => current.get_data::<DeviceLock>(&kv.key)?.map(|l| l.time),
+        SERVICE_TOKENS => current
+            .get_data::<TokenStorage>(&kv.key)?
+            .map(|t| t.version),
+        key if key.starts_with(MONITORS_PREFIX_KEY) => {
+            // monitor versions don't fit in a u32, compare them as u64
+            let needed = match current.get::<Vec<u8>>(&kv.key)? {
+                Some(bytes) => utils::get_monitor_version(&bytes) < kv.version as u64,
+                None => true,
+            };
+            return Ok(needed);
+        }
+        key if key.starts_with(CHANNEL_MANAGER_KEY) || key.starts_with(FEDIMINTS_PREFIX_KEY) => {
+            current
+                .get_data::<VersionedValue>(&kv.key)?
+                .map(|v| v.version)
+        }
+        // no version to compare, fetch it if VSS has a new version since the last sync
+        _ => return needs_vss_fetch(current, kv),
+    };
+
+    match local_version {
+        Some(version) if version >= kv.version => {
+            log_debug!(
+                logger,
+                "Skipping vss key {} with version {}, current version is {version}",
+                kv.key,
+                kv.version
+            );
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Checks the value from VSS is valid, keys without a version are merged with ours
+pub fn handle_vss_object<S: MutinyStorage>(
+    obj: VssKeyValueItem,
+    current: &S,
+) -> Result<Option<(String, Value)>, MutinyError> {
+    let valid = match obj.key.as_str() {
+        NODES_KEY => serde_json::from_value::<NodeStorage>(obj.value.clone()).is_ok(),
+        FEDERATIONS_KEY => serde_json::from_value::<FederationStorage>(obj.value.clone()).is_ok(),
+        DEVICE_LOCK_KEY => serde_json::from_value::<DeviceLock>(obj.value.clone()).is_ok(),
+        SERVICE_TOKENS => serde_json::from_value::<TokenStorage>(obj.value.clone()).is_ok(),
+        key if key.starts_with(MONITORS_PREFIX_KEY) => true,
+        key if key.starts_with(CHANNEL_MANAGER_KEY) || key.starts_with(FEDIMINTS_PREFIX_KEY) => {
+            serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok()
+        }
+        _ => {
+            // no version to compare, check which side changed it and merge if both did
+            let key = obj.key.clone();
+            return Ok(reconcile_vss_key(current, obj)?.map(|value| (key, value)));
+        }
+    };
+
+    Ok(valid.then_some((obj.key, obj.value)))
+}
+
+/// Decides what to do with a key from VSS that has no version to compare.
+///
+/// Returns the value to store locally, or `None` to keep the local value.
+/// If only one side changed the key since the last sync that side wins,
+/// if both did, or it was never synced, it is merged by its [MergeStrategy].
+/// Values that can't be merged become a [SyncConflict] for the user to resolve.
+pub fn reconcile_vss_key<S: MutinyStorage>(
+    storage: &S,
+    remote: VssKeyValueItem,
+) -> Result<Option<Value>, MutinyError> {
+    let Some(strategy) = MergeStrategy::for_key(&remote.key) else {
+        return Ok(None);
+    };
+
+    let mut state: HashMap<String, SyncState> =
+        storage.get_data(VSS_SYNC_STATE_KEY)?.unwrap_or_default();
+    let base = state.get(&remote.key).map(|s| s.digest.clone());
+    let remote_digest = digest(&remote.value);
+    let remote_version = remote.version;
+
+    let value = match storage.get_data::<Value>(&remote.key)? {
+        // deleted on this device since the last sync, VSS can't delete keys
+        None if base.is_some() => return Ok(None),
+        None => Some(remote.value),
+        Some(local) => {
+            let local_digest = digest(&local);
+            if base.as_ref() == Some(&remote_digest) || local_digest == remote_digest {
+                // only this device changed it, or both are the same
+                None
+            } else if base.as_ref() == Some(&local_digest) {
+                // only VSS changed it
+                Some(remote.value)
+            } else {
+                match merge(strategy, &remote.key, &local, &remote.value) {
+                    Merge::Use(value) => Some(value),
+                    Merge::KeepLocal => None,
+                    Merge::Conflict => {
+                        // keep the base so this stays a conflict until it is resolved
+                        let conflict = SyncConflict {
+                            key: remote.key,
+                            local,
+                            remote: remote.value,
+                            remote_version,
+                            timestamp: utils::now().as_secs(),
+                        };
+                        add_conflict(storage, conflict)?;
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    };
+
+    let new_state = SyncState {
+        digest: remote_digest,
+        version: remote_version,
+    };
+    if state.get(&remote.key) != Some(&new_state) {
+        state.insert(remote.key, new_state);
+        storage.set_data(VSS_SYNC_STATE_KEY.to_string(), state, None)?;
+    }
+
+    Ok(value)
+}
+
+/// Keys that were changed on both this device and VSS and need the user to pick a value
+pub fn get_sync_conflicts<S: MutinyStorage>(storage: &S) -> Result<Vec<SyncConflict>, MutinyError> {
+    Ok(storage.get_data(SYNC_CONFLICTS_KEY)?.unwrap_or_default())
+}
+
+/// Resolves a conflict with either the VSS value or the local one,
+/// the chosen value is written back to VSS so every device ends up with it.
+pub fn resolve_sync_conflict<S: MutinyStorage>(
+    storage: &S,
+    key: &str,
+    use_remote: bool,
+) -> Result<(), MutinyError> {
+    let mut conflicts = get_sync_conflicts(storage)?;
+    let index = conflicts
+        .iter()
+        .position(|c| c.key == key)
+        .ok_or(MutinyError::NotFound)?;
+    let conflict = conflicts.remove(index);
+
+    // if writing the chosen value to VSS fails we still know only this device changed it
+    let mut state: HashMap<String, SyncState> =
+        storage.get_data(VSS_SYNC_STATE_KEY)?.unwrap_or_default();
+    let remote_state = SyncState {
+        digest: digest(&conflict.remote),
+        version: conflict.remote_version,
+    };
+    state.insert(conflict.key.clone(), remote_state);
+
+    let value = if use_remote {
+        conflict.remote
+    } else {
+        conflict.local
+    };
+    // a newer version than what VSS has, so it takes this value
+    let version = utils::now().as_secs() as u32;
+    storage.set_data(conflict.key, value, Some(version))?;
+    storage.set_data(VSS_SYNC_STATE_KEY.to_string(), state, None)?;
+    storage.set_data(SYNC_CONFLICTS_KEY.to_string(), conflicts, None)
+}
+
+fn merge(strategy: MergeStrategy, key: &str, local: &Value, remote: &Value) -> Merge {
+    let res: Result<Merge, serde_json::Error> = match strategy {
+        MergeStrategy::Union if key.starts_with(LABEL_PREFIX) => {
+            serde_json::from_value::<LabelItem>(local.clone()).and_then(|mut item| {
+                let remote: LabelItem = serde_json::from_value(remote.clone())?;
+                item.addresses.extend(remote.addresses);
+                item.invoices.extend(remote.invoices);
+                item.last_used_time = item.last_used_time.max(remote.last_used_time);
+                serde_json::to_value(item).map(Merge::Use)
+            })
+        }
+        MergeStrategy::Union => {
+            // the address and invoice label maps
+            serde_json::from_value::<HashMap<String, Vec<String>>>(local.clone()).and_then(
+                |mut map| {
+                    let remote: HashMap<String, Vec<String>> =
+                        serde_json::from_value(remote.clone())?;
+                    for (k, labels) in remote {
+                        let current = map.entry(k).or_default();
+                        for label in labels {
+                            if !current.contains(&label) {
+                                current.push(label);
+                            }
+                        }
+                    }
+                    serde_json::to_value(map).map(Merge::Use)
+                },
+            )
+        }
+        MergeStrategy::LastUsed => {
+            serde_json::from_value::<Contact>(local.clone()).and_then(|contact| {
+                let remote_contact: Contact = serde_json::from_value(remote.clone())?;
+                if remote_contact.last_used > contact.last_used {
+                    Ok(Merge::Use(remote.clone()))
+                } else {
+                    Ok(Merge::KeepLocal)
+                }
+            })
+        }
+        MergeStrategy::LastWriterWins => Ok(Merge::Use(remote.clone())),
+    };
+
+    // if either side can't be read the user needs to pick
+    res.unwrap_or(Merge::Conflict)
+}
+
+fn add_conflict<S: MutinyStorage>(storage: &S, conflict: SyncConflict) -> Result<(), MutinyError> {
+    let mut conflicts = get_sync_conflicts(storage)?;
+    if conflicts
+        .iter()
+        .any(|c| c.key == conflict.key && c.local == conflict.local && c.remote == conflict.remote)
+    {
+        return Ok(());
+    }
+
+    conflicts.retain(|c| c.key != conflict.key);
+    conflicts.push(conflict);
+    storage.set_data(SYNC_CONFLICTS_KEY.to_string(), conflicts, None)
+}
+
+/// Digest of a value, the order of lists is ignored since most of them are sets
+pub(crate) fn digest(value: &Value) -> String {
+    sha256::Hash::hash(canonical(value).to_string().as_bytes()).to_string()
+}
+
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Array(items) => {
+            let mut items: Vec<Value> = items.iter().map(canonical).collect();
+            items.sort_by_cached_key(|v| v.to_string());
+            Value::Array(items)
+        }
+        Value::Object(map) => {
+            Value::Object(map.iter().map(|(k, v)| (k.clone(), canonical(v))).collect())
+        }
+        v => v.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn item(key: &str, value: Value) -> VssKeyValueItem {
+        VssKeyValueItem {
+            key: key.to_string(),
+            value,
+            version: 1,
+        }
+    }
+
+    fn label_item(addresses: &[&str]) -> Value {
+        let item = LabelItem {
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        };
+        serde_json::to_value(item).unwrap()
+    }
+
+    #[test]
+    fn test_reconcile_changed_on_one_side() {
+        let storage = MemoryStorage::default();
+        let key = "some_key";
+
+        // new keys are taken from VSS
+        let res = reconcile_vss_key(&storage, item(key, json!(1))).unwrap();
+        assert_eq!(res, Some(json!(1)));
+        storage.set_data(key.to_string(), 1, None).unwrap();
+
+        // the same version doesn't need to be fetched again
+        let kv = KeyVersion {
+            key: key.to_string(),
+            version: 1,
+        };
+        assert!(!needs_vss_fetch(&storage, &kv).unwrap());
+        let kv = KeyVersion { version: 2, ..kv };
+        assert!(needs_vss_fetch(&storage, &kv).unwrap());
+
+        // only VSS changed it
+        let res = reconcile_vss_key(&storage, item(key, json!(2))).unwrap();
+        assert_eq!(res, Some(json!(2)));
+        storage.set_data(key.to_string(), 2, None).unwrap();
+
+        // only this device changed it
+        storage.set_data(key.to_string(), 3, None).unwrap();
+        let res = reconcile_vss_key(&storage, item(key, json!(2))).unwrap();
+        assert_eq!(res, None);
+
+        // deleted on this device
+        storage.delete(&[key]).unwrap();
+        let res = reconcile_vss_key(&storage, item(key, json!(2))).unwrap();
+        assert_eq!(res, None);
+        assert!(get_sync_conflicts(&storage).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_merge_labels_and_contacts() {
+        let storage = MemoryStorage::default();
+        let label_key = format!("{LABEL_PREFIX}friends");
+        let contact_key = format!("{CONTACT_PREFIX}id");
+
+        // both sides have values we never synced
+        storage
+            .set_data(label_key.clone(), label_item(&["addr1"]), None)
+            .unwrap();
+        let res = reconcile_vss_key(&storage, item(&label_key, label_item(&["addr2"]))).unwrap();
+        let merged: LabelItem = serde_json::from_value(res.unwrap()).unwrap();
+        let expected: HashSet<String> = ["addr1".to_string(), "addr2".to_string()].into();
+        assert_eq!(merged.addresses, expected);
+
+        let local = Contact {
+            name: "local".to_string(),
+            last_used: 2,
+            ..Default::default()
+        };
+        let remote = Contact {
+            name: "remote".to_string(),
+            last_used: 1,
+            ..Default::default()
+        };
+        storage.set_data(contact_key.clone(), local, None).unwrap();
+        let remote_value = serde_json::to_value(&remote).unwrap();
+        let res = reconcile_vss_key(&storage, item(&contact_key, remote_value)).unwrap();
+        assert_eq!(res, None);
+
+        let remote = Contact {
+            last_used: 3,
+            ..remote
+        };
+        let remote_value = serde_json::to_value(&remote).unwrap();
+        let res = reconcile_vss_key(&storage, item(&contact_key, remote_value.clone())).unwrap();
+        assert_eq!(res, Some(remote_value));
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        let storage = MemoryStorage::default();
+        let key = "some_key";
+
+        // never synced, VSS has the last write
+        storage.set_data(key.to_string(), 1, None).unwrap();
+        let res = reconcile_vss_key(&storage, item(key, json!(2))).unwrap();
+        assert_eq!(res, Some(json!(2)));
+        storage.set_data(key.to_string(), 2, None).unwrap();
+
+        // changed on both sides
+        storage.set_data(key.to_string(), 3, None).unwrap();
+        let res = reconcile_vss_key(&storage, item(key, json!(4))).unwrap();
+        assert_eq!(res, Some(json!(4)));
+        assert!(get_sync_conflicts(&storage).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sync_conflicts() {
+        let storage = MemoryStorage::default();
+        let key = format!("{LABEL_PREFIX}friends");
+
+        reconcile_vss_key(&storage, item(&key, label_item(&["addr1"]))).unwrap();
+        // a value that can't be merged
+        storage.set_data(key.clone(), 2, None).unwrap();
+        let remote = label_item(&["addr2"]);
+        let res = reconcile_vss_key(&storage, item(&key, remote.clone())).unwrap();
+        assert_eq!(res, None);
+
+        let conflicts = get_sync_conflicts(&storage).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].local, json!(2));
+        assert_eq!(conflicts[0].remote, remote);
+
+        // finding it again doesn't duplicate it
+        reconcile_vss_key(&storage, item(&key, remote.clone())).unwrap();
+        assert_eq!(get_sync_conflicts(&storage).unwrap(), conflicts);
+
+        resolve_sync_conflict(&storage, &key, true).unwrap();
+        assert_eq!(
+            storage.get_data::<Value>(&key).unwrap(),
+            Some(remote.clone())
+        );
+        assert!(get_sync_conflicts(&storage).unwrap().is_empty());
+        assert_eq!(
+            resolve_sync_conflict(&storage, &key, true),
+            Err(MutinyError::NotFound)
+        );
+
+        // VSS now has what we have
+        let res = reconcile_vss_key(&storage, item(&key, remote)).unwrap();
+        assert_eq!(res, None);
+        assert!(get_sync_conflicts(&storage).unwrap().is_empty());
+    }
+}
@@ -13,28 +13,95 @@ use bitcoin::secp256k1;
 use bitcoin::secp256k1::SecretKey;
 use cbc::{Decryptor, Encryptor};
 use getrandom::getrandom;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 type Aes256CbcEnc = Encryptor<Aes256>;
 type Aes256CbcDec = Decryptor<Aes256>;
 
+/// Prefix for ciphertexts that carry their own key stretching params
+const ARGON2ID_PREFIX: &str = "argon2id";
+
+/// Upper bound on the memory cost we accept from a ciphertext, 256 MiB
+const MAX_M_COST: u32 = 256 * 1024;
+
+/// Argon2id parameters used to derive the storage encryption key from the password
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyStretchingParams {
+    /// Memory cost in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl KeyStretchingParams {
+    /// Params used before they were stored with the ciphertext
+    pub const LEGACY: KeyStretchingParams = KeyStretchingParams {
+        m_cost: 7 * 1024,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn argon2(&self) -> Result<Argon2<'static>, MutinyError> {
+        if self.m_cost > MAX_M_COST {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let params = argon2::Params::new(self.m_cost, self.t_cost, self.p_cost, None)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        Ok(Argon2::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            params,
+        ))
+    }
+}
+
+impl Default for KeyStretchingParams {
+    /// OWASP recommended minimum for argon2id
+    fn default() -> Self {
+        Self {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Cipher {
     key: Arc<Aes256Gcm>,
     salt: [u8; 16],
+    params: KeyStretchingParams,
+}
+
+impl Cipher {
+    /// The key stretching params this cipher was derived with
+    pub fn params(&self) -> KeyStretchingParams {
+        self.params
+    }
 }
 
 pub fn encryption_key_from_pass(password: &str) -> Result<Cipher, MutinyError> {
+    encryption_key_from_pass_with_params(password, KeyStretchingParams::default())
+}
+
+pub fn encryption_key_from_pass_with_params(
+    password: &str,
+    params: KeyStretchingParams,
+) -> Result<Cipher, MutinyError> {
     let mut salt = [0u8; 16];
     getrandom(&mut salt).unwrap();
 
-    let key = get_encryption_key(password, &salt)?;
+    let key = get_encryption_key_with_params(password, &salt, params)?;
 
     // convert key to proper format for aes_gcm
     let key = GenericArray::clone_from_slice(&key);
     Ok(Cipher {
         key: Arc::new(Aes256Gcm::new(&key)),
         salt,
+        params,
     })
 }
 
@@ -53,10 +120,48 @@ pub fn encrypt(content: &str, c: Cipher) -> Result<String, MutinyError> {
     result.extend(nonce);
     result.extend(encrypted_data);
 
-    Ok(base64::encode(&result))
+    let KeyStretchingParams {
+        m_cost,
+        t_cost,
+        p_cost,
+    } = c.params;
+    Ok(format!(
+        "{ARGON2ID_PREFIX}:{m_cost},{t_cost},{p_cost}:{}",
+        base64::encode(&result)
+    ))
+}
+
+/// Splits the key stretching params off the ciphertext,
+/// values without them were encrypted with [KeyStretchingParams::LEGACY]
+fn parse_params(encrypted: &str) -> Result<(KeyStretchingParams, &str), MutinyError> {
+    let Some(rest) = encrypted.strip_prefix(ARGON2ID_PREFIX) else {
+        return Ok((KeyStretchingParams::LEGACY, encrypted));
+    };
+
+    let (params, data) = rest
+        .strip_prefix(':')
+        .and_then(|r| r.split_once(':'))
+        .ok_or(MutinyError::IncorrectPassword)?;
+    let params = params
+        .split(',')
+        .map(|p| p.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| MutinyError::IncorrectPassword)?;
+    match params.as_slice() {
+        [m_cost, t_cost, p_cost] => Ok((
+            KeyStretchingParams {
+                m_cost: *m_cost,
+                t_cost: *t_cost,
+                p_cost: *p_cost,
+            },
+            data,
+        )),
+        _ => Err(MutinyError::IncorrectPassword),
+    }
 }
 
 pub fn decrypt_with_password(encrypted: &str, password: &str) -> Result<String, MutinyError> {
+    let (params, encrypted) = parse_params(encrypted)?;
     let encrypted = base64::decode(encrypted)?;
     if encrypted.len() < 12 + 16 {
         return Err(MutinyError::IncorrectPassword);
@@ -65,7 +170,8 @@ pub fn decrypt_with_password(encrypted: &str, password: &str) -> Result<String,
     let (rest, encrypted_bytes) = encrypted.split_at(16 + 12);
     let (salt, nonce_bytes) = rest.split_at(16);
 
-    let key = get_encryption_key(password, salt)?;
+    let key = get_encryption_key_with_params(password, salt, params)
+        .map_err(|_| MutinyError::IncorrectPassword)?;
 
     // convert key and nonce to proper format for aes_gcm
     let key = GenericArray::clone_from_slice(&key);
@@ -84,19 +190,22 @@ pub fn decrypt_with_password(encrypted: &str, password: &str) -> Result<String,
 }
 
 pub fn get_encryption_key(password: &str, salt: &[u8]) -> Result<[u8; 32], MutinyError> {
+    get_encryption_key_with_params(password, salt, KeyStretchingParams::LEGACY)
+}
+
+pub fn get_encryption_key_with_params(
+    password: &str,
+    salt: &[u8],
+    params: KeyStretchingParams,
+) -> Result<[u8; 32], MutinyError> {
     let mut key = [0u8; 32];
-    argon2()
+    params
+        .argon2()?
         .hash_password_into(password.as_bytes(), salt, &mut key)
         .map_err(|_| MutinyError::IncorrectPassword)?;
     Ok(key)
 }
 
-fn argon2() -> Argon2<'static> {
-    let mut binding = argon2::ParamsBuilder::new();
-    let params = binding.m_cost(7 * 1024).t_cost(1).p_cost(1);
-    Argon2::from(params.build().expect("valid params"))
-}
-
 pub fn encrypt_with_key(encryption_key: &SecretKey, bytes: &[u8]) -> Vec<u8> {
     let iv: [u8; 16] = secp256k1::rand::random();
 
@@ -128,8 +237,11 @@ pub fn decrypt_with_key(
 mod tests {
     use crate::encrypt::{
         decrypt_with_key, decrypt_with_password, encrypt, encrypt_with_key,
-        encryption_key_from_pass,
+        encryption_key_from_pass, encryption_key_from_pass_with_params, get_encryption_key,
+        KeyStretchingParams,
     };
+    use aes_gcm::aead::{generic_array::GenericArray, Aead};
+    use aes_gcm::{Aes256Gcm, KeyInit};
     use bitcoin::secp256k1::SecretKey;
 
     #[test]
@@ -146,6 +258,49 @@ mod tests {
         assert_eq!(content, decrypted);
     }
 
+    #[test]
+    fn test_encryption_with_params() {
+        let password = "password";
+        let content = "hello world";
+        let params = KeyStretchingParams {
+            m_cost: 8 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        };
+        let cipher = encryption_key_from_pass_with_params(password, params).unwrap();
+        assert_eq!(cipher.params(), params);
+
+        let encrypted = encrypt(content, cipher).unwrap();
+        assert!(encrypted.starts_with("argon2id:8192,3,1:"));
+
+        let decrypted = decrypt_with_password(&encrypted, password).unwrap();
+        assert_eq!(content, decrypted);
+        assert!(decrypt_with_password(&encrypted, "wrong").is_err());
+
+        // we refuse params that would take too much memory
+        let encrypted = encrypted.replace("8192", "4194304");
+        assert!(decrypt_with_password(&encrypted, password).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_legacy() {
+        let password = "password";
+        let content = "hello world";
+
+        // values written before the params were stored with the ciphertext
+        let salt = [2u8; 16];
+        let nonce = [3u8; 12];
+        let key = get_encryption_key(password, &salt).unwrap();
+        let cipher = Aes256Gcm::new(&GenericArray::clone_from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(GenericArray::from_slice(&nonce), content.as_bytes())
+            .unwrap();
+        let encrypted = base64::encode([salt.to_vec(), nonce.to_vec(), ciphertext].concat());
+
+        let decrypted = decrypt_with_password(&encrypted, password).unwrap();
+        assert_eq!(content, decrypted);
+    }
+
     #[test]
     fn test_encryption_with_key() {
         let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
@@ -24,6 +24,9 @@ pub enum MutinyError {
     /// Returned when trying to stop Mutiny while it is not running.
     #[error("Mutiny is not running.")]
     NotRunning,
+    /// Returned when trying to make changes in a read-only session.
+    #[error("Cannot make changes in a read-only session.")]
+    ReadOnly,
     /// Returned when Mutiny tries to startup with a different network than the one it was
     /// previously running on.
     #[error("Incorrect expected network.")]
@@ -43,6 +46,12 @@ pub enum MutinyError {
     /// Payment of the given invoice has already been initiated.
     #[error("An invoice must not get payed twice.")]
     NonUniquePaymentHash,
+    /// A payment with the same idempotency key has not finished yet.
+    #[error("A payment with this idempotency key is already in progress.")]
+    PaymentInProgress,
+    /// The idempotency key was already used for a different payment.
+    #[error("This idempotency key was used for a different payment.")]
+    IdempotencyKeyReused,
     /// Payment Timed out
     #[error("Payment timed out.")]
     PaymentTimeout,
@@ -126,6 +135,21 @@ pub enum MutinyError {
     /// A chain access operation failed.
     #[error("Failed to conduct chain access operation.")]
     ChainAccessFailed,
+    /// A transaction did not get the confirmations we waited for in time.
+    #[error("Timed out waiting for the transaction to confirm.")]
+    ConfirmationTimeout,
+    /// The transaction could not be broadcast.
+    #[error("Failed to broadcast transaction.")]
+    BroadcastFailed,
+    /// The coinjoin peer did not respond or sent something we can't use.
+    #[error("Failed to complete the coinjoin.")]
+    CoinjoinFailed,
+    /// A request to the VSS server failed.
+    #[error("Failed to make a request to VSS.")]
+    VssError,
+    /// The storage was migrated by a newer version of the wallet.
+    #[error("The storage is from a newer version of the wallet.")]
+    StorageVersionTooNew,
     /// A failure to sync the on-chain wallet
     #[error("Failed to to sync on-chain wallet.")]
     WalletSyncError,
@@ -155,6 +179,9 @@ pub enum MutinyError {
     /// Incorrect password entered.
     #[error("Incorrect password entered.")]
     IncorrectPassword,
+    /// The BIP-39 passphrase doesn't match the wallet in storage.
+    #[error("Incorrect passphrase entered.")]
+    IncorrectPassphrase,
     /// Cannot change password to the same password
     #[error("Cannot change password to the same password.")]
     SamePassword,
@@ -185,6 +212,45 @@ pub enum MutinyError {
     /// Fedimint transaction too large
     #[error("Error constructing fedimint transaction, try lowering the amount.")]
     FederationTxTooLarge,
+    /// Paying an invoice without an amount requires confirmation.
+    #[error("Paying an invoice without an amount must be confirmed.")]
+    AmountlessInvoiceNotConfirmed,
+    /// The amount is over the maximum allowed for an invoice without an amount.
+    #[error("Amount is over the maximum allowed for an invoice without an amount.")]
+    AmountlessInvoiceTooLarge,
+    /// Invoices without an amount can only be paid to contacts.
+    #[error("Invoices without an amount can only be paid to contacts.")]
+    AmountlessInvoiceNotAllowed,
+    /// The LSP is paused for maintenance by the service operator.
+    #[error("The LSP is paused for maintenance, try again later.")]
+    LspMaintenance,
+    /// Failed to make a request to the swap provider.
+    #[error("Failed to make a request to the swap provider.")]
+    SwapProviderError,
+    /// The swap costs more than the maximum fee given.
+    #[error("The swap fee is higher than the maximum fee.")]
+    SwapFeeTooHigh,
+    /// The payment is over a limit of the spending policy.
+    #[error("The payment is over the spending limit.")]
+    SpendingLimitExceeded,
+    /// The spending policy only allows payments to whitelisted destinations.
+    #[error("The destination is not on the spending whitelist.")]
+    DestinationNotAllowed,
+    /// The spending policy requires the PIN for payments of this size.
+    #[error("The spending PIN is required for this payment.")]
+    SpendingPinRequired,
+    /// The spending PIN given is wrong.
+    #[error("Incorrect spending PIN.")]
+    IncorrectSpendingPin,
+    /// Too many wrong spending PINs were given, the next one can only be tried later.
+    #[error("Too many incorrect spending PINs, try again later.")]
+    SpendingPinLocked,
+    /// The invoice request is over a limit of the receive limits.
+    #[error("The invoice request is over the receive limit.")]
+    ReceiveLimitExceeded,
+    /// The receive limits don't allow the requester to create invoices.
+    #[error("The requester is not allowed to create invoices.")]
+    RequesterDenied,
     #[error(transparent)]
     Other(anyhow::Error),
 }
@@ -221,12 +287,15 @@ impl PartialEq for MutinyError {
         match (self, other) {
             (Self::AlreadyRunning, Self::AlreadyRunning) => true,
             (Self::NotRunning, Self::NotRunning) => true,
+            (Self::ReadOnly, Self::ReadOnly) => true,
             (Self::NetworkMismatch, Self::NetworkMismatch) => true,
             (Self::NotFound, Self::NotFound) => true,
             (Self::FundingTxCreationFailed, Self::FundingTxCreationFailed) => true,
             (Self::ConnectionFailed, Self::ConnectionFailed) => true,
             (Self::IncorrectNetwork, Self::IncorrectNetwork) => true,
             (Self::NonUniquePaymentHash, Self::NonUniquePaymentHash) => true,
+            (Self::PaymentInProgress, Self::PaymentInProgress) => true,
+            (Self::IdempotencyKeyReused, Self::IdempotencyKeyReused) => true,
             (Self::PaymentTimeout, Self::PaymentTimeout) => true,
             (Self::InvoiceInvalid, Self::InvoiceInvalid) => true,
             (Self::InvoiceExpired, Self::InvoiceExpired) => true,
@@ -258,7 +327,12 @@ impl PartialEq for MutinyError {
             (Self::WalletOperationFailed, Self::WalletOperationFailed) => true,
             (Self::WalletSigningFailed, Self::WalletSigningFailed) => true,
             (Self::ChainAccessFailed, Self::ChainAccessFailed) => true,
+            (Self::ConfirmationTimeout, Self::ConfirmationTimeout) => true,
             (Self::WalletSyncError, Self::WalletSyncError) => true,
+            (Self::BroadcastFailed, Self::BroadcastFailed) => true,
+            (Self::CoinjoinFailed, Self::CoinjoinFailed) => true,
+            (Self::VssError, Self::VssError) => true,
+            (Self::StorageVersionTooNew, Self::StorageVersionTooNew) => true,
             (Self::RapidGossipSyncError, Self::RapidGossipSyncError) => true,
             (Self::PubkeyInvalid, Self::PubkeyInvalid) => true,
             (Self::IncorrectLnUrlFunction, Self::IncorrectLnUrlFunction) => true,
@@ -267,6 +341,7 @@ impl PartialEq for MutinyError {
             (Self::DLCManagerError, Self::DLCManagerError) => true,
             (Self::NostrError, Self::NostrError) => true,
             (Self::IncorrectPassword, Self::IncorrectPassword) => true,
+            (Self::IncorrectPassphrase, Self::IncorrectPassphrase) => true,
             (Self::SamePassword, Self::SamePassword) => true,
             (Self::CashuMintError, Self::CashuMintError) => true,
             (Self::EmptyMintURLError, Self::EmptyMintURLError) => true,
@@ -274,6 +349,19 @@ impl PartialEq for MutinyError {
             (Self::FederationRequired, Self::FederationRequired) => true,
             (Self::FederationConnectionFailed, Self::FederationConnectionFailed) => true,
             (Self::FederationTxTooLarge, Self::FederationTxTooLarge) => true,
+            (Self::AmountlessInvoiceNotConfirmed, Self::AmountlessInvoiceNotConfirmed) => true,
+            (Self::AmountlessInvoiceTooLarge, Self::AmountlessInvoiceTooLarge) => true,
+            (Self::AmountlessInvoiceNotAllowed, Self::AmountlessInvoiceNotAllowed) => true,
+            (Self::LspMaintenance, Self::LspMaintenance) => true,
+            (Self::SwapProviderError, Self::SwapProviderError) => true,
+            (Self::SwapFeeTooHigh, Self::SwapFeeTooHigh) => true,
+            (Self::SpendingLimitExceeded, Self::SpendingLimitExceeded) => true,
+            (Self::DestinationNotAllowed, Self::DestinationNotAllowed) => true,
+            (Self::SpendingPinRequired, Self::SpendingPinRequired) => true,
+            (Self::IncorrectSpendingPin, Self::IncorrectSpendingPin) => true,
+            (Self::SpendingPinLocked, Self::SpendingPinLocked) => true,
+            (Self::ReceiveLimitExceeded, Self::ReceiveLimitExceeded) => true,
+            (Self::RequesterDenied, Self::RequesterDenied) => true,
             (Self::Other(e), Self::Other(e2)) => e.to_string() == e2.to_string(),
             _ => false,
         }
@@ -586,6 +674,12 @@ impl From<nostr_sdk::signer::Error> for MutinyError {
     }
 }
 
+impl From<nostr_sdk::signer::nip46::Error> for MutinyError {
+    fn from(_e: nostr_sdk::signer::nip46::Error) -> Self {
+        Self::NostrError
+    }
+}
+
 impl From<payjoin::send::CreateRequestError> for MutinyError {
     fn from(_e: payjoin::send::CreateRequestError) -> Self {
         Self::PayjoinCreateRequest
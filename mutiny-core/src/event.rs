@@ -1,10 +1,12 @@
-use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
+use crate::chain::{track_confirmation, TrackedTx};
+use crate::error::MutinyError;
+use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager, SpendableOutputSweep};
 use crate::logging::MutinyLogger;
 use crate::lsp::{AnyLsp, Lsp};
 use crate::node::BumpTxEventHandler;
-use crate::nodemanager::ChannelClosure;
+use crate::nodemanager::{ChannelClosure, ChannelLifecycleState};
 use crate::onchain::OnChainWallet;
-use crate::storage::MutinyStorage;
+use crate::storage::{persist_payment_path, MutinyStorage};
 use crate::utils::sleep;
 use crate::{fees::MutinyFeeEstimator, storage::read_payment_info, PrivacyLevel};
 use crate::{keymanager::PhantomKeysManager, storage::persist_payment_info};
@@ -12,8 +14,13 @@ use anyhow::anyhow;
 use bitcoin::absolute::LockTime;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::Secp256k1;
+use bitcoin::Txid;
 use core::fmt;
+use hex_conservative::{DisplayHex, FromHex};
+use lightning::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW;
+use lightning::events::bump_transaction::BumpTransactionEvent;
 use lightning::events::{Event, PaymentPurpose};
+use lightning::routing::router::Path;
 use lightning::sign::SpendableOutputDescriptor;
 use lightning::{
     log_debug, log_error, log_info, log_warn, util::errors::APIError, util::logger::Logger,
@@ -41,6 +48,8 @@ pub(crate) struct PaymentInfo {
     #[serde(default)]
     pub privacy_level: PrivacyLevel,
     pub last_update: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_tlvs: Vec<CustomTlv>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -52,6 +61,89 @@ impl MillisatAmount {
     }
 }
 
+/// A custom TLV record included in the onion of a received payment,
+/// such as the podcasting 2.0 boostagram records sent with keysends.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomTlv {
+    pub tlv_type: u64,
+    /// Hex encoded value
+    pub value: String,
+}
+
+impl CustomTlv {
+    /// Returns the value as a string if it is valid UTF-8,
+    /// most records, like boostagrams and sender names, are text or JSON.
+    pub fn value_utf8(&self) -> Option<String> {
+        let bytes: Vec<u8> = FromHex::from_hex(&self.value).ok()?;
+        String::from_utf8(bytes).ok()
+    }
+}
+
+/// A hop of an outbound payment path
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PaymentHop {
+    pub pubkey: PublicKey,
+    pub short_channel_id: u64,
+    /// Fee charged by this node for forwarding, zero for the recipient
+    pub fee_msat: u64,
+}
+
+/// One part of an outbound payment, multi-part payments are split over several paths
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PaymentPath {
+    pub status: HTLCStatus,
+    /// Amount delivered to the recipient over this path
+    pub amount_msat: u64,
+    pub fee_msat: u64,
+    pub hops: Vec<PaymentHop>,
+    /// The channel that failed the path, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_short_channel_id: Option<u64>,
+    pub last_update: u64,
+}
+
+impl PaymentPath {
+    pub(crate) fn new(path: &Path, status: HTLCStatus, failed_scid: Option<u64>) -> Self {
+        let last = path.hops.len().saturating_sub(1);
+        let hops = path
+            .hops
+            .iter()
+            .enumerate()
+            .map(|(i, hop)| PaymentHop {
+                pubkey: hop.pubkey,
+                short_channel_id: hop.short_channel_id,
+                // the last hop's fee_msat is the amount sent to the recipient
+                fee_msat: if i == last { 0 } else { hop.fee_msat },
+            })
+            .collect();
+
+        Self {
+            status,
+            amount_msat: path.final_value_msat(),
+            fee_msat: path.fee_msat(),
+            hops,
+            failed_short_channel_id: failed_scid,
+            last_update: crate::utils::now().as_secs(),
+        }
+    }
+
+    /// If this is the same attempt, ignoring when it was recorded
+    pub(crate) fn same_attempt(&self, other: &Self) -> bool {
+        self.status == other.status
+            && self.amount_msat == other.amount_msat
+            && self.hops == other.hops
+    }
+}
+
+impl From<&(u64, Vec<u8>)> for CustomTlv {
+    fn from((tlv_type, value): &(u64, Vec<u8>)) -> Self {
+        Self {
+            tlv_type: *tlv_type,
+            value: value.to_lower_hex_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum HTLCStatus {
     /// Our invoice has not been paid yet
@@ -221,6 +313,12 @@ impl<S: MutinyStorage> EventHandler<S> {
                     return;
                 }
 
+                let funding_txid = tx.txid();
+                self.persist_channel_lifecycle(
+                    user_channel_id,
+                    ChannelLifecycleState::FundingBroadcast { funding_txid },
+                );
+
                 if let Some(mut params) = params_opt {
                     params.opening_tx = Some(tx);
 
@@ -234,9 +332,11 @@ impl<S: MutinyStorage> EventHandler<S> {
             Event::PaymentClaimable {
                 receiver_node_id,
                 payment_hash,
+                onion_fields,
                 purpose,
                 amount_msat,
                 counterparty_skimmed_fee_msat,
+                via_user_channel_id,
                 ..
             } => {
                 log_debug!(self.logger, "EVENT: PaymentReceived received payment from payment hash {} of {amount_msat} millisatoshis to {receiver_node_id:?}", payment_hash);
@@ -254,6 +354,30 @@ impl<S: MutinyStorage> EventHandler<S> {
                     return;
                 }
 
+                let policy = match via_user_channel_id
+                    .map(|id| self.persister.get_channel_policy(id))
+                    .transpose()
+                {
+                    Ok(policy) => policy.flatten(),
+                    Err(e) => {
+                        log_error!(self.logger, "ERROR: could not read channel policy: {e}");
+                        None
+                    }
+                };
+                if policy.is_some_and(|p| !p.allows_htlc(amount_msat)) {
+                    log_warn!(self.logger, "Failing payment with hash {} of {amount_msat} millisatoshis, it is outside of the channel's HTLC limits", payment_hash);
+                    self.channel_manager.fail_htlc_backwards(&payment_hash);
+                    return;
+                }
+
+                // PaymentClaimed doesn't include the onion, so save any custom records now
+                let custom_tlvs: Vec<CustomTlv> = onion_fields
+                    .map(|f| f.custom_tlvs().iter().map(CustomTlv::from).collect())
+                    .unwrap_or_default();
+                if !custom_tlvs.is_empty() {
+                    self.persist_custom_tlvs(&payment_hash.0, receiver_node_id, custom_tlvs);
+                }
+
                 if let Some(payment_preimage) = match purpose {
                     PaymentPurpose::InvoicePayment {
                         payment_preimage, ..
@@ -325,6 +449,7 @@ impl<S: MutinyStorage> EventHandler<S> {
                             bolt11: None,
                             last_update,
                             privacy_level: PrivacyLevel::NotAvailable,
+                            custom_tlvs: vec![],
                         };
                         match persist_payment_info(
                             &self.persister.storage,
@@ -431,11 +556,35 @@ impl<S: MutinyStorage> EventHandler<S> {
                     log_result(result);
                 }
             }
+            Event::PaymentPathSuccessful {
+                payment_hash: Some(payment_hash),
+                path,
+                ..
+            } => {
+                log_debug!(self.logger, "EVENT: PaymentPathSuccessful: {payment_hash}");
+
+                let part = PaymentPath::new(&path, HTLCStatus::Succeeded, None);
+                let storage = &self.persister.storage;
+                if let Err(e) = persist_payment_path(storage, &payment_hash.0, part) {
+                    log_error!(self.logger, "ERROR: could not persist payment path: {e}");
+                }
+            }
             Event::PaymentPathSuccessful { .. } => {
                 log_debug!(self.logger, "EVENT: PaymentPathSuccessful, ignored");
             }
-            Event::PaymentPathFailed { .. } => {
-                log_debug!(self.logger, "EVENT: PaymentPathFailed, ignored");
+            Event::PaymentPathFailed {
+                payment_hash,
+                path,
+                short_channel_id,
+                ..
+            } => {
+                log_debug!(self.logger, "EVENT: PaymentPathFailed: {payment_hash}");
+
+                let part = PaymentPath::new(&path, HTLCStatus::Failed, short_channel_id);
+                let storage = &self.persister.storage;
+                if let Err(e) = persist_payment_path(storage, &payment_hash.0, part) {
+                    log_error!(self.logger, "ERROR: could not persist payment path: {e}");
+                }
             }
             Event::ProbeSuccessful { .. } => {
                 log_debug!(self.logger, "EVENT: ProbeSuccessful, ignored");
@@ -533,13 +682,27 @@ impl<S: MutinyStorage> EventHandler<S> {
                         "",
                     );
 
-                    params.failure_reason = Some(reason_str);
+                    params.failure_reason = Some(reason_str.clone());
                     let _ = self
                         .persister
                         .persist_channel_open_params(user_channel_id, params);
+                    self.persist_channel_lifecycle(
+                        user_channel_id,
+                        ChannelLifecycleState::Closed { reason: reason_str },
+                    );
                     return;
                 };
 
+                // keep a closure that was already recorded, like for an abandoned channel
+                if let Ok(Some(closure)) = self.persister.get_channel_closure(user_channel_id) {
+                    log_debug!(
+                        self.logger,
+                        "EVENT: Channel {channel_id} closed, already recorded: {}",
+                        closure.reason
+                    );
+                    return;
+                }
+
                 log_debug!(
                     self.logger,
                     "EVENT: Channel {} of size {} closed due to: {:?}",
@@ -550,6 +713,11 @@ impl<S: MutinyStorage> EventHandler<S> {
                     reason
                 );
 
+                let state = ChannelLifecycleState::Closed {
+                    reason: reason.to_string(),
+                };
+                self.persist_channel_lifecycle(user_channel_id, state);
+
                 let closure = ChannelClosure::new(user_channel_id, channel_id, node_id, reason);
                 if let Err(e) = self
                     .persister
@@ -576,11 +744,29 @@ impl<S: MutinyStorage> EventHandler<S> {
                     user_channel_id,
                     counterparty_node_id,
                     channel_type);
+
+                self.persist_channel_lifecycle(user_channel_id, ChannelLifecycleState::Ready);
+
+                // watch the funding tx for reorgs, the block is filled in on the next check
+                let funding_txo = self
+                    .channel_manager
+                    .list_channels()
+                    .into_iter()
+                    .find(|c| c.channel_id == channel_id)
+                    .and_then(|c| c.funding_txo);
+                if let Some(funding_txo) = funding_txo {
+                    let txid = funding_txo.txid;
+                    let kind = TrackedTx::ChannelFunding;
+                    if let Err(e) = track_confirmation(&self.persister.storage, txid, kind, None) {
+                        log_warn!(self.logger, "ERROR: Could not track funding tx {txid}: {e}");
+                    }
+                }
             }
             Event::ChannelPending {
                 channel_id,
                 user_channel_id,
                 counterparty_node_id,
+                funding_txo,
                 ..
             } => {
                 log_debug!(
@@ -590,6 +776,19 @@ impl<S: MutinyStorage> EventHandler<S> {
                     user_channel_id,
                     counterparty_node_id);
 
+                let confirmations_required = self
+                    .channel_manager
+                    .list_channels()
+                    .into_iter()
+                    .find(|c| c.channel_id == channel_id)
+                    .and_then(|c| c.confirmations_required);
+                let state = ChannelLifecycleState::WaitingConfirmations {
+                    funding_txid: funding_txo.txid,
+                    confirmations: 0,
+                    confirmations_required,
+                };
+                self.persist_channel_lifecycle(user_channel_id, state);
+
                 if let Err(e) = self.persister.delete_channel_open_params(user_channel_id) {
                     log_warn!(
                         self.logger,
@@ -598,8 +797,9 @@ impl<S: MutinyStorage> EventHandler<S> {
                 }
             }
             Event::HTLCIntercepted { .. } => {}
-            Event::BumpTransaction(event) => {
+            Event::BumpTransaction(mut event) => {
                 log_debug!(self.logger, "EVENT: BumpTransaction: {event:?}");
+                self.apply_close_fee_rate(&mut event);
                 self.bump_tx_event_handler.handle_event(&event);
             }
             Event::InvoiceRequestFailed { payment_id } => {
@@ -616,6 +816,83 @@ impl<S: MutinyStorage> EventHandler<S> {
         }
     }
 
+    /// Raises the fee rate LDK targets for the claims of a closed channel to the one
+    /// set with [crate::node::Node::bump_channel_close_fee], the anchor output is then
+    /// used to CPFP the commitment or HTLC transaction up to that fee rate.
+    fn apply_close_fee_rate(&self, event: &mut BumpTransactionEvent) {
+        let (channel_id, target_fee_rate) = match event {
+            BumpTransactionEvent::ChannelClose {
+                channel_id,
+                package_target_feerate_sat_per_1000_weight,
+                ..
+            } => (*channel_id, package_target_feerate_sat_per_1000_weight),
+            BumpTransactionEvent::HTLCResolution {
+                channel_id,
+                target_feerate_sat_per_1000_weight,
+                ..
+            } => (*channel_id, target_feerate_sat_per_1000_weight),
+        };
+
+        match self.persister.get_close_fee_rate(&channel_id) {
+            Ok(Some(fee_rate)) if fee_rate > *target_fee_rate => {
+                log_info!(
+                    self.logger,
+                    "Bumping claims of channel {channel_id} to {fee_rate} sats/kw"
+                );
+                *target_fee_rate = fee_rate;
+            }
+            Ok(_) => {}
+            Err(e) => log_warn!(self.logger, "Failed to read close fee rate: {e}"),
+        }
+    }
+
+    fn persist_channel_lifecycle(&self, user_channel_id: u128, state: ChannelLifecycleState) {
+        if let Err(e) = self
+            .persister
+            .persist_channel_lifecycle(user_channel_id, state)
+        {
+            log_warn!(
+                self.logger,
+                "ERROR: Could not persist channel lifecycle: {e}"
+            );
+        }
+    }
+
+    /// Saves the custom TLV records of a received payment with its payment info,
+    /// creating the payment info for keysends as they don't have an invoice.
+    fn persist_custom_tlvs(
+        &self,
+        payment_hash: &[u8; 32],
+        receiver_node_id: Option<PublicKey>,
+        custom_tlvs: Vec<CustomTlv>,
+    ) {
+        let payment_info =
+            match read_payment_info(&self.persister.storage, payment_hash, true, &self.logger) {
+                Some(mut saved_payment_info) => {
+                    saved_payment_info.custom_tlvs = custom_tlvs;
+                    saved_payment_info
+                }
+                None => PaymentInfo {
+                    preimage: None,
+                    secret: None,
+                    status: HTLCStatus::Pending,
+                    amt_msat: MillisatAmount(None),
+                    fee_paid_msat: None,
+                    payee_pubkey: receiver_node_id,
+                    bolt11: None,
+                    last_update: crate::utils::now().as_secs(),
+                    privacy_level: PrivacyLevel::NotAvailable,
+                    custom_tlvs,
+                },
+            };
+
+        if let Err(e) =
+            persist_payment_info(&self.persister.storage, payment_hash, &payment_info, true)
+        {
+            log_error!(self.logger, "ERROR: could not persist custom tlvs: {e}");
+        }
+    }
+
     // Separate function to handle spendable outputs
     // This is so we can return a result and handle errors
     // without having to use a lot of nested if statements
@@ -623,6 +900,156 @@ impl<S: MutinyStorage> EventHandler<S> {
         &self,
         outputs: &[SpendableOutputDescriptor],
     ) -> anyhow::Result<()> {
+        let tx_feerate = self.fee_estimator.get_normal_fee_rate();
+        self.sweep_spendable_outputs(outputs, tx_feerate).await?;
+        Ok(())
+    }
+
+    /// Sweeps the spendable outputs that failed before. They are kept until their sweep
+    /// confirms, if it hasn't by the next retry they are swept again with a higher fee,
+    /// replacing the previous sweep as it spends the same outputs.
+    ///
+    /// Outputs that weren't swept yet are swept in one transaction when possible.
+    /// Without a fee rate, in sats per 1000 weight, the fee of each set of outputs
+    /// goes up with every attempt, in case it was too low for the sweep to confirm.
+    pub(crate) async fn retry_failed_spendable_outputs(
+        &self,
+        fee_rate: Option<u32>,
+    ) -> Result<Vec<Txid>, MutinyError> {
+        let outputs = self.persister.get_failed_spendable_outputs()?;
+        if outputs.is_empty() {
+            return Ok(vec![]);
+        }
+        let normal_fee_rate = self.fee_estimator.get_normal_fee_rate();
+
+        // outputs whose sweep confirmed or that didn't need one
+        let mut done = vec![];
+        let mut unswept = outputs.clone();
+        let mut sweeps = vec![];
+        let mut txids = vec![];
+        for sweep in self.persister.get_spendable_output_sweeps()? {
+            let set: Vec<_> = outputs
+                .iter()
+                .filter(|d| sweep.outpoints.contains(&spendable_outpoint(d)))
+                .cloned()
+                .collect();
+            if set.is_empty() {
+                continue;
+            }
+            unswept.retain(|d| !set.contains(d));
+
+            if let Some(txid) = sweep.txid {
+                match self.wallet.is_confirmed(&txid).await {
+                    Ok(true) => {
+                        log_info!(self.logger, "Sweep of spendable outputs confirmed: {txid}");
+                        done.extend(set);
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        log_warn!(self.logger, "Failed to check sweep {txid}: {e}");
+                        sweeps.push(sweep);
+                        continue;
+                    }
+                }
+            }
+
+            // a replacement has to pay more than the sweep it replaces
+            let fee_rate = fee_rate.unwrap_or_else(|| {
+                let fee_rate = escalated_fee_rate(normal_fee_rate, sweep.attempts);
+                match sweep.txid {
+                    Some(_) => fee_rate.max(sweep.fee_rate + FEERATE_FLOOR_SATS_PER_KW),
+                    None => fee_rate,
+                }
+            });
+            log_info!(
+                self.logger,
+                "Sweeping {} spendable outputs again at {fee_rate} sats/kw",
+                set.len()
+            );
+            let attempts = sweep.attempts + 1;
+            match self.sweep_spendable_outputs(&set, fee_rate).await {
+                Ok(Some(txid)) => {
+                    txids.push(txid);
+                    sweeps.push(SpendableOutputSweep {
+                        outpoints: sweep.outpoints,
+                        txid: Some(txid),
+                        fee_rate,
+                        attempts,
+                    });
+                }
+                Ok(None) => done.extend(set),
+                Err(e) => {
+                    // the previous sweep can still confirm
+                    log_warn!(self.logger, "Failed to sweep spendable outputs: {e}");
+                    sweeps.push(SpendableOutputSweep { attempts, ..sweep });
+                }
+            }
+        }
+
+        if !unswept.is_empty() {
+            let fee_rate = fee_rate.unwrap_or_else(|| escalated_fee_rate(normal_fee_rate, 0));
+            log_info!(
+                self.logger,
+                "Sweeping {} spendable outputs at {fee_rate} sats/kw",
+                unswept.len()
+            );
+
+            // retry them individually so one bad output doesn't hold up the others,
+            // if there was only one we don't need to retry
+            let sets = match self.sweep_spendable_outputs(&unswept, fee_rate).await {
+                Ok(txid) => vec![(unswept, txid)],
+                Err(e) if unswept.len() == 1 => {
+                    log_warn!(self.logger, "Failed to sweep spendable output: {e}");
+                    vec![(unswept, None)]
+                }
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Failed to sweep spendable outputs together: {e}"
+                    );
+                    let mut sets = vec![];
+                    for o in unswept {
+                        let set = vec![o];
+                        match self.sweep_spendable_outputs(&set, fee_rate).await {
+                            Ok(txid) => sets.push((set, txid)),
+                            Err(_) => sets.push((set, None)),
+                        }
+                    }
+                    sets
+                }
+            };
+
+            for (set, txid) in sets {
+                let outpoints: Vec<_> = set.iter().map(spendable_outpoint).collect();
+                // nothing was broadcast when the outputs were already in our wallet
+                if txid.is_none() && set.iter().all(is_static_output) {
+                    done.extend(set);
+                    continue;
+                }
+                txids.extend(txid);
+                sweeps.push(SpendableOutputSweep {
+                    outpoints,
+                    txid,
+                    fee_rate,
+                    attempts: 1,
+                });
+            }
+        }
+
+        self.persister.remove_failed_spendable_outputs(&done)?;
+        self.persister.set_spendable_output_sweeps(sweeps)?;
+
+        Ok(txids)
+    }
+
+    /// Spends the spendable outputs to our wallet, returns the txid of the sweep
+    /// or none when there was nothing to sweep
+    async fn sweep_spendable_outputs(
+        &self,
+        outputs: &[SpendableOutputDescriptor],
+        tx_feerate: u32,
+    ) -> anyhow::Result<Option<Txid>> {
         // Filter out static outputs, we don't want to spend them
         // because they have gone to our BDK wallet.
         // This would only be a waste in fees.
@@ -637,7 +1064,7 @@ impl<S: MutinyStorage> EventHandler<S> {
 
         // If there are no spendable outputs, we don't need to do anything
         if output_descriptors.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
 
         log_debug!(
@@ -646,8 +1073,6 @@ impl<S: MutinyStorage> EventHandler<S> {
             output_descriptors.len()
         );
 
-        let tx_feerate = self.fee_estimator.get_normal_fee_rate();
-
         // We set nLockTime to the current height to discourage fee sniping.
         // Occasionally randomly pick a nLockTime even further back, so
         // that transactions that are delayed after signing for whatever reason,
@@ -678,15 +1103,42 @@ impl<S: MutinyStorage> EventHandler<S> {
             )
             .map_err(|_| anyhow!("Failed to spend spendable outputs"))?;
 
+        let txid = spending_tx.txid();
         self.wallet.broadcast_transaction(spending_tx).await?;
 
-        Ok(())
+        Ok(Some(txid))
     }
 }
 
+pub(crate) fn spendable_outpoint(desc: &SpendableOutputDescriptor) -> bitcoin::OutPoint {
+    match desc {
+        SpendableOutputDescriptor::StaticOutput { outpoint, .. } => {
+            outpoint.into_bitcoin_outpoint()
+        }
+        SpendableOutputDescriptor::DelayedPaymentOutput(desc) => {
+            desc.outpoint.into_bitcoin_outpoint()
+        }
+        SpendableOutputDescriptor::StaticPaymentOutput(desc) => {
+            desc.outpoint.into_bitcoin_outpoint()
+        }
+    }
+}
+
+/// Static outputs went to our wallet already, there is nothing to sweep
+fn is_static_output(desc: &SpendableOutputDescriptor) -> bool {
+    matches!(desc, SpendableOutputDescriptor::StaticOutput { .. })
+}
+
+/// Raises the fee rate by half of it for every failed attempt, up to 4 times the rate
+pub(crate) fn escalated_fee_rate(fee_rate: u32, attempts: u32) -> u32 {
+    let percent = 100 + 50 * attempts.min(6) as u64;
+    let escalated = fee_rate as u64 * percent / 100;
+    (escalated.min(u32::MAX as u64) as u32).max(FEERATE_FLOOR_SATS_PER_KW)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+    use crate::event::{escalated_fee_rate, CustomTlv, HTLCStatus, MillisatAmount, PaymentInfo};
     use crate::{utils, PrivacyLevel};
     use bitcoin::secp256k1::PublicKey;
     use std::str::FromStr;
@@ -712,6 +1164,7 @@ mod test {
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
+            custom_tlvs: vec![CustomTlv::from(&(7629169, b"boost".to_vec()))],
         };
 
         let serialized = serde_json::to_string(&payment_info).unwrap();
@@ -722,4 +1175,27 @@ mod test {
         let deserialized: PaymentInfo = serde_json::from_value(serialized).unwrap();
         assert_eq!(payment_info, deserialized);
     }
+
+    #[test]
+    fn test_escalated_fee_rate() {
+        assert_eq!(escalated_fee_rate(1_000, 0), 1_000);
+        assert_eq!(escalated_fee_rate(1_000, 1), 1_500);
+        assert_eq!(escalated_fee_rate(1_000, 2), 2_000);
+        // capped at 4 times the fee rate
+        assert_eq!(escalated_fee_rate(1_000, 6), 4_000);
+        assert_eq!(escalated_fee_rate(1_000, 100), 4_000);
+        // never under the minimum relay fee
+        assert_eq!(escalated_fee_rate(0, 1), 253);
+    }
+
+    #[test]
+    fn test_custom_tlv() {
+        let tlv = CustomTlv::from(&(7629169, br#"{"message":"gm"}"#.to_vec()));
+        assert_eq!(tlv.tlv_type, 7629169);
+        assert_eq!(tlv.value_utf8().unwrap(), r#"{"message":"gm"}"#);
+
+        let tlv = CustomTlv::from(&(696969, vec![0xff, 0xfe]));
+        assert_eq!(tlv.value, "fffe");
+        assert_eq!(tlv.value_utf8(), None);
+    }
 }
@@ -0,0 +1,282 @@
+use crate::error::MutinyError;
+use crate::federation::FederationActivity;
+use crate::labels::Contact;
+use crate::price::{activity_amount, FiatActivityItem};
+use crate::{ActivityItem, MutinyInvoice, TransactionDetails};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// File format of an activity export, see [crate::MutinyWallet::export_activity]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+const CSV_HEADER: &str = "timestamp,kind,federation_kind,direction,amount_sats,fees_sats,\
+fiat_currency,fiat_price,fiat_value,counterparty,labels,payment_hash,txid,federation_id,\
+description";
+
+/// One settled activity item in an accounting export
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ActivityExportRow {
+    /// Unix timestamp of when the activity settled
+    pub timestamp: u64,
+    /// `OnChain`, `Lightning`, `ChannelClosed` or `Federation`
+    pub kind: String,
+    /// What the federation did, only for federation activity
+    pub federation_kind: Option<String>,
+    /// `incoming`, `outgoing`, or `none` if no funds moved
+    pub direction: String,
+    pub amount_sats: Option<u64>,
+    pub fees_sats: Option<u64>,
+    pub fiat_currency: String,
+    /// Price of one bitcoin when the activity settled
+    pub fiat_price: Option<f32>,
+    /// Value of the activity, negative when sending
+    pub fiat_value: Option<f64>,
+    /// Contact the activity was tagged with, or the node we paid or closed a channel with
+    pub counterparty: Option<String>,
+    /// Labels besides the contact
+    pub labels: Vec<String>,
+    pub payment_hash: Option<String>,
+    pub txid: Option<String>,
+    pub federation_id: Option<String>,
+    pub description: Option<String>,
+}
+
+impl ActivityExportRow {
+    /// Returns `None` for activity that hasn't settled
+    pub(crate) fn new(item: FiatActivityItem, contacts: &HashMap<String, Contact>) -> Option<Self> {
+        let activity = &item.activity;
+        let timestamp = activity.last_updated()?;
+
+        let (kind, federation_kind, federation_id) = match activity {
+            ActivityItem::OnChain(_) => ("OnChain", None, None),
+            ActivityItem::Lightning(_) => ("Lightning", None, None),
+            ActivityItem::ChannelClosed(_) => ("ChannelClosed", None, None),
+            ActivityItem::Federation(f) => (
+                "Federation",
+                Some(f.kind().to_string()),
+                Some(f.federation_id().to_string()),
+            ),
+        };
+
+        let (direction, amount_sats) = match activity_amount(activity) {
+            Some((true, amount)) => ("incoming", Some(amount)),
+            Some((false, amount)) => ("outgoing", Some(amount)),
+            None => ("none", None),
+        };
+
+        let mut labels = activity.labels();
+        let contact = labels
+            .iter()
+            .find_map(|l| contacts.get(l))
+            .map(|c| c.name.clone());
+        labels.retain(|l| !contacts.contains_key(l));
+
+        let (fees_sats, payment_hash, txid, description, node) = match activity {
+            ActivityItem::OnChain(t) => onchain_details(t),
+            ActivityItem::Lightning(ln) => lightning_details(ln),
+            ActivityItem::ChannelClosed(c) => (
+                None,
+                None,
+                None,
+                Some(c.reason.clone()),
+                c.node_id.map(|n| n.to_string()),
+            ),
+            ActivityItem::Federation(f) => match f {
+                FederationActivity::Lightning { invoice, .. } => lightning_details(invoice),
+                FederationActivity::EcashReissue { fees, .. } => (*fees, None, None, None, None),
+                FederationActivity::PegIn { transaction, .. }
+                | FederationActivity::PegOut { transaction, .. } => onchain_details(transaction),
+            },
+        };
+
+        Some(Self {
+            timestamp,
+            kind: kind.to_string(),
+            federation_kind,
+            direction: direction.to_string(),
+            amount_sats,
+            fees_sats,
+            fiat_currency: item.currency,
+            fiat_price: item.price,
+            fiat_value: item.fiat_value,
+            counterparty: contact.or(node),
+            labels,
+            payment_hash,
+            txid,
+            federation_id,
+            description,
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        [
+            self.timestamp.to_string(),
+            self.kind.clone(),
+            opt(self.federation_kind.clone()),
+            self.direction.clone(),
+            opt(self.amount_sats.map(|a| a.to_string())),
+            opt(self.fees_sats.map(|a| a.to_string())),
+            self.fiat_currency.clone(),
+            opt(self.fiat_price.map(|p| p.to_string())),
+            opt(self.fiat_value.map(|v| format!("{v:.2}"))),
+            opt(self.counterparty.clone()),
+            self.labels.join(";"),
+            opt(self.payment_hash.clone()),
+            opt(self.txid.clone()),
+            opt(self.federation_id.clone()),
+            opt(self.description.clone()),
+        ]
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+type ExportDetails = (
+    Option<u64>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Fees, payment hash, txid, description and counterparty node of a payment
+fn lightning_details(invoice: &MutinyInvoice) -> ExportDetails {
+    let node = if invoice.inbound {
+        None
+    } else {
+        invoice.payee_pubkey.map(|p| p.to_string())
+    };
+    (
+        invoice.fees_paid,
+        Some(invoice.payment_hash.to_string()),
+        None,
+        invoice.description.clone(),
+        node,
+    )
+}
+
+/// Fees, payment hash, txid, description and counterparty node of a transaction
+fn onchain_details(transaction: &TransactionDetails) -> ExportDetails {
+    (
+        transaction.fee,
+        None,
+        transaction.txid.map(|t| t.to_string()),
+        None,
+        None,
+    )
+}
+
+/// Quotes the field if it contains anything that would break the csv
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Serializes the rows in the given format
+pub(crate) fn export_rows(
+    rows: &[ActivityExportRow],
+    format: ExportFormat,
+) -> Result<String, MutinyError> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string(rows)?),
+        ExportFormat::Csv => {
+            let mut csv = String::from(CSV_HEADER);
+            for row in rows {
+                csv.push('\n');
+                csv.push_str(&row.to_csv());
+            }
+            Ok(csv)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_export_rows() {
+        let invoice = MutinyInvoice {
+            amount_sats: Some(100_000),
+            description: Some("coffee, \"large\"".to_string()),
+            inbound: false,
+            status: crate::event::HTLCStatus::Succeeded,
+            labels: vec!["contact_id".to_string(), "food".to_string()],
+            last_updated: 1_700_000_000,
+            ..Default::default()
+        };
+        let item = FiatActivityItem::new(
+            ActivityItem::Lightning(Box::new(invoice)),
+            "usd".to_string(),
+            Some(50_000.0),
+        );
+        let contact = Contact {
+            name: "Alice".to_string(),
+            npub: None,
+            ln_address: None,
+            lnurl: None,
+            image_url: None,
+            node_pubkey: None,
+            last_used: 0,
+        };
+        let contacts = HashMap::from([("contact_id".to_string(), contact)]);
+
+        let row = ActivityExportRow::new(item, &contacts).unwrap();
+        assert_eq!(row.direction, "outgoing");
+        assert_eq!(row.counterparty, Some("Alice".to_string()));
+        assert_eq!(row.labels, vec!["food".to_string()]);
+        assert_eq!(row.fiat_value, Some(-50.0));
+
+        let csv = export_rows(&[row.clone()], ExportFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with("1700000000,Lightning,,outgoing,100000,,usd,50000,-50.00,"));
+        assert!(lines[1].ends_with(",\"coffee, \"\"large\"\"\""));
+
+        let json = export_rows(&[row.clone()], ExportFormat::Json).unwrap();
+        let parsed: Vec<ActivityExportRow> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, vec![row]);
+    }
+
+    #[test]
+    fn test_export_skips_pending() {
+        let invoice = MutinyInvoice {
+            status: crate::event::HTLCStatus::Pending,
+            ..Default::default()
+        };
+        let item = FiatActivityItem::new(
+            ActivityItem::Lightning(Box::new(invoice)),
+            "usd".to_string(),
+            None,
+        );
+        assert!(ActivityExportRow::new(item, &HashMap::new()).is_none());
+    }
+}
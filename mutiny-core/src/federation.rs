@@ -1,3 +1,4 @@
+use crate::chain::{track_confirmation, TrackedTx};
 use crate::storage::get_invoice_by_hash;
 use crate::utils::{
     convert_from_fedimint_invoice, convert_to_fedimint_invoice, fetch_with_timeout, now, spawn,
@@ -10,11 +11,12 @@ use crate::{
     logging::MutinyLogger,
     onchain::coin_type_from_network,
     storage::{
-        delete_transaction_details, get_transaction_details, list_payment_info,
-        persist_payment_info, persist_transaction_details, MutinyStorage, VersionedValue,
+        delete_transaction_details, get_transaction_details, list_payment_info, payment_key,
+        persist_payment_info, persist_transaction_details, transaction_details_key, MutinyStorage,
+        VersionedValue,
     },
     utils::sleep,
-    HTLCStatus, MutinyInvoice, DEFAULT_PAYMENT_TIMEOUT,
+    HTLCStatus, InvoiceParams, MutinyInvoice, DEFAULT_PAYMENT_TIMEOUT,
 };
 use crate::{labels::LabelStorage, storage::TRANSACTION_DETAILS_PREFIX_KEY};
 use async_lock::RwLock;
@@ -31,6 +33,7 @@ use core::fmt;
 use esplora_client::AsyncClient;
 use fedimint_bip39::Bip39RootSecretStrategy;
 use fedimint_client::{
+    backup::Metadata,
     derivable_secret::DerivableSecret,
     oplog::{OperationLogEntry, UpdateStreamOrOutcome},
     secret::{get_default_client_secret, RootSecretStrategy},
@@ -75,15 +78,16 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+use std::{
+    cmp::Ordering as CmpOrdering,
+    str::FromStr,
+    sync::atomic::{AtomicU32, Ordering},
+};
 use std::{
     collections::{HashMap, HashSet},
     fmt::Debug,
     sync::{atomic::AtomicBool, Arc},
 };
-use std::{
-    str::FromStr,
-    sync::atomic::{AtomicU32, Ordering},
-};
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
@@ -97,6 +101,13 @@ const PEG_IN_TIMEOUT_YEAR: Duration = Duration::from_secs(86400 * 365);
 
 pub const FEDIMINTS_PREFIX_KEY: &str = "fedimints/";
 
+/// Key for the user's [FederationPreference]
+pub const FEDERATION_PREFERENCE_KEY: &str = "federation_preference";
+
+/// Prefix for the tags that mark which federation handled an activity item.
+/// The rest of the key is the activity index key of the item.
+pub const FEDERATION_ACTIVITY_PREFIX_KEY: &str = "federation_activity/";
+
 // Default signet/mainnet federation gateway info
 const SIGNET_GATEWAY: &str = "0256f5ef1d986e9abf559651b7167de28bfd954683cd0f14703be12d1421aedc55";
 const MAINNET_GATEWAY: &str = "025b9f090d3daab012346701f27d1c220d6d290f6b498255cddc492c255532a09d";
@@ -227,16 +238,260 @@ impl From<RoutingFees> for GatewayFees {
     }
 }
 
+impl GatewayFees {
+    /// The fee in sats to route the given amount of sats through the gateway
+    pub fn fee_for_amount(&self, amount_sats: u64) -> u64 {
+        let proportional = amount_sats * self.proportional_millionths as u64 / 1_000_000;
+        self.base_msat as u64 / 1_000 + proportional
+    }
+}
+
+/// How to pick which federation to use when we have joined more than one
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum FederationRoutingPolicy {
+    /// Use the federation whose gateway charges the lowest fee
+    LowestFee,
+    /// Use the federation with the largest balance
+    LargestBalance,
+    /// Use the default federation first, then fall back to the largest balance
+    #[default]
+    PinnedDefault,
+}
+
+/// The user's federation routing preference, saved to storage
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct FederationPreference {
+    pub policy: FederationRoutingPolicy,
+    pub default_federation: Option<FederationId>,
+}
+
+/// A federation to consider when routing a payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FederationCandidate {
+    pub federation_id: FederationId,
+    /// Balance in sats
+    pub balance: u64,
+    /// Gateway fee in sats for the amount being routed, `None` without a gateway
+    pub fee: Option<u64>,
+}
+
+impl FederationPreference {
+    fn is_default(&self, candidate: &FederationCandidate) -> bool {
+        self.default_federation == Some(candidate.federation_id)
+    }
+
+    /// Orders the federations in the order they should be tried to send the amount.
+    /// Federations without a gateway or enough balance can't send it and go last.
+    pub(crate) fn sort_for_send(
+        &self,
+        amount_sats: u64,
+        mut candidates: Vec<FederationCandidate>,
+    ) -> Vec<FederationId> {
+        candidates.sort_by(|a, b| {
+            let can_send = |c: &FederationCandidate| c.fee.is_some() && c.balance >= amount_sats;
+            let order = match self.policy {
+                FederationRoutingPolicy::LowestFee => a.fee.cmp(&b.fee),
+                FederationRoutingPolicy::LargestBalance => CmpOrdering::Equal,
+                FederationRoutingPolicy::PinnedDefault => {
+                    self.is_default(b).cmp(&self.is_default(a))
+                }
+            };
+            can_send(b)
+                .cmp(&can_send(a))
+                .then(order)
+                .then(b.balance.cmp(&a.balance))
+        });
+
+        candidates.into_iter().map(|c| c.federation_id).collect()
+    }
+
+    /// Orders the federations in the order they should be tried to receive a payment.
+    /// The balance doesn't limit receiving, so the default federation falls back to
+    /// the lowest fee, and federations without a gateway go last.
+    pub(crate) fn sort_for_receive(
+        &self,
+        mut candidates: Vec<FederationCandidate>,
+    ) -> Vec<FederationId> {
+        candidates.sort_by(|a, b| {
+            let order = match self.policy {
+                FederationRoutingPolicy::LowestFee => CmpOrdering::Equal,
+                FederationRoutingPolicy::LargestBalance => b.balance.cmp(&a.balance),
+                FederationRoutingPolicy::PinnedDefault => {
+                    self.is_default(b).cmp(&self.is_default(a))
+                }
+            };
+            b.fee
+                .is_some()
+                .cmp(&a.fee.is_some())
+                .then(order)
+                .then(a.fee.cmp(&b.fee))
+        });
+
+        candidates.into_iter().map(|c| c.federation_id).collect()
+    }
+}
+
 // This is the FederationIndex reference that is saved to the DB
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct FederationIndex {
     pub federation_code: InviteCode,
 }
 
+/// How starting a federation client went during startup
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct FederationInitStatus {
+    pub uuid: String,
+    pub federation_id: FederationId,
+    /// Why the client failed to start, it is skipped until the wallet is started again
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
 pub struct FedimintBalance {
     pub amount: u64,
 }
 
+/// The type of operation a federation performed for an activity item
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum FederationActivityKind {
+    /// A lightning payment sent or received through a federation gateway
+    Lightning,
+    /// Ecash notes that were reissued into the federation
+    EcashReissue,
+    /// An on-chain deposit into the federation
+    PegIn,
+    /// An on-chain withdrawal out of the federation
+    PegOut,
+}
+
+impl fmt::Display for FederationActivityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FederationActivityKind::Lightning => write!(f, "Lightning"),
+            FederationActivityKind::EcashReissue => write!(f, "EcashReissue"),
+            FederationActivityKind::PegIn => write!(f, "PegIn"),
+            FederationActivityKind::PegOut => write!(f, "PegOut"),
+        }
+    }
+}
+
+/// Tag saved alongside a payment or transaction that was handled by a federation
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub(crate) struct FederationActivityTag {
+    pub federation_id: FederationId,
+    pub kind: FederationActivityKind,
+}
+
+/// Activity that was handled by a federation instead of our lightning node or on-chain wallet
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum FederationActivity {
+    /// A lightning payment sent or received through a federation gateway
+    Lightning {
+        federation_id: FederationId,
+        invoice: Box<MutinyInvoice>,
+    },
+    /// Ecash notes that were reissued into the federation
+    EcashReissue {
+        federation_id: FederationId,
+        amount_sats: u64,
+        fees: Option<u64>,
+        last_updated: u64,
+        labels: Vec<String>,
+    },
+    /// An on-chain deposit into the federation
+    PegIn {
+        federation_id: FederationId,
+        transaction: TransactionDetails,
+    },
+    /// An on-chain withdrawal out of the federation
+    PegOut {
+        federation_id: FederationId,
+        transaction: TransactionDetails,
+    },
+}
+
+impl FederationActivity {
+    pub fn federation_id(&self) -> FederationId {
+        match self {
+            FederationActivity::Lightning { federation_id, .. } => *federation_id,
+            FederationActivity::EcashReissue { federation_id, .. } => *federation_id,
+            FederationActivity::PegIn { federation_id, .. } => *federation_id,
+            FederationActivity::PegOut { federation_id, .. } => *federation_id,
+        }
+    }
+
+    pub fn kind(&self) -> FederationActivityKind {
+        match self {
+            FederationActivity::Lightning { .. } => FederationActivityKind::Lightning,
+            FederationActivity::EcashReissue { .. } => FederationActivityKind::EcashReissue,
+            FederationActivity::PegIn { .. } => FederationActivityKind::PegIn,
+            FederationActivity::PegOut { .. } => FederationActivityKind::PegOut,
+        }
+    }
+
+    pub fn last_updated(&self) -> Option<u64> {
+        match self {
+            FederationActivity::Lightning { invoice, .. } => match invoice.status {
+                HTLCStatus::Succeeded | HTLCStatus::Failed => Some(invoice.last_updated),
+                HTLCStatus::Pending | HTLCStatus::InFlight => None,
+            },
+            FederationActivity::EcashReissue { last_updated, .. } => Some(*last_updated),
+            FederationActivity::PegIn { transaction, .. }
+            | FederationActivity::PegOut { transaction, .. } => {
+                match transaction.confirmation_time {
+                    ConfirmationTime::Confirmed { time, .. } => Some(time),
+                    ConfirmationTime::Unconfirmed { .. } => None,
+                }
+            }
+        }
+    }
+
+    pub fn labels(&self) -> Vec<String> {
+        match self {
+            FederationActivity::Lightning { invoice, .. } => invoice.labels.clone(),
+            FederationActivity::EcashReissue { labels, .. } => labels.clone(),
+            FederationActivity::PegIn { transaction, .. }
+            | FederationActivity::PegOut { transaction, .. } => transaction.labels.clone(),
+        }
+    }
+
+    /// Fees paid to the federation or its gateway in sats, if known
+    pub fn fees(&self) -> Option<u64> {
+        match self {
+            FederationActivity::Lightning { invoice, .. } => invoice.fees_paid,
+            FederationActivity::EcashReissue { fees, .. } => *fees,
+            FederationActivity::PegIn { transaction, .. } => transaction.fee,
+            FederationActivity::PegOut { transaction, .. } => transaction.fee,
+        }
+    }
+}
+
+/// Tags the activity item with the given index key as handled by a federation
+pub(crate) fn persist_federation_activity_tag<S: MutinyStorage>(
+    storage: &S,
+    index_key: &str,
+    federation_id: FederationId,
+    kind: FederationActivityKind,
+) -> Result<(), MutinyError> {
+    let tag = FederationActivityTag {
+        federation_id,
+        kind,
+    };
+    storage.set_data(
+        format!("{FEDERATION_ACTIVITY_PREFIX_KEY}{index_key}"),
+        tag,
+        None,
+    )
+}
+
+/// Gets the federation tag for the activity item with the given index key, if any
+pub(crate) fn get_federation_activity_tag<S: MutinyStorage>(
+    storage: &S,
+    index_key: &str,
+) -> Result<Option<FederationActivityTag>, MutinyError> {
+    storage.get_data(format!("{FEDERATION_ACTIVITY_PREFIX_KEY}{index_key}"))
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait FedimintClient {
     async fn claim_external_receive(
@@ -590,6 +845,10 @@ impl<S: MutinyStorage> FederationClient<S> {
         Ok(())
     }
 
+    pub(crate) async fn has_gateway(&self) -> bool {
+        self.gateway.read().await.is_some()
+    }
+
     pub(crate) async fn gateway_fee(&self) -> Result<GatewayFees, MutinyError> {
         let gateway = self.gateway.read().await;
         Ok(gateway.as_ref().map(|x| x.fees.into()).unwrap_or_default())
@@ -599,6 +858,7 @@ impl<S: MutinyStorage> FederationClient<S> {
         &self,
         amount: u64,
         labels: Vec<String>,
+        params: &InvoiceParams,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling federation.get_invoice");
         let inbound = true;
@@ -613,13 +873,14 @@ impl<S: MutinyStorage> FederationClient<S> {
             self.fedimint_client.federation_id()
         );
 
-        let desc = Description::new(String::new()).expect("empty string is valid");
+        let desc = Description::new(params.description.clone().unwrap_or_default())
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
         let gateway = self.gateway.read().await;
         let (id, invoice, preimage) = lightning_module
             .create_bolt11_invoice(
                 Amount::from_sats(amount),
                 Bolt11InvoiceDescription::Direct(&desc),
-                None,
+                params.expiry_secs.map(u64::from),
                 (),
                 gateway.clone(),
             )
@@ -638,6 +899,12 @@ impl<S: MutinyStorage> FederationClient<S> {
         let hash = stored_payment.payment_hash.into_32();
         let payment_info = PaymentInfo::from(stored_payment);
         persist_payment_info(&self.storage, &hash, &payment_info, inbound)?;
+        persist_federation_activity_tag(
+            &self.storage,
+            &payment_key(inbound, &hash),
+            self.fedimint_client.federation_id(),
+            FederationActivityKind::Lightning,
+        )?;
         log_trace!(self.logger, "Persisted payment");
 
         // subscribe to updates for it
@@ -697,6 +964,15 @@ impl<S: MutinyStorage> FederationClient<S> {
         self.storage
             .set_address_labels(address.clone(), labels.clone())?;
 
+        // tag the deposit so it shows up as a peg-in once it is seen
+        let internal_id = Txid::from_slice(&op_id.0).map_err(|_| MutinyError::ChainAccessFailed)?;
+        persist_federation_activity_tag(
+            &self.storage,
+            &transaction_details_key(internal_id),
+            self.fedimint_client.federation_id(),
+            FederationActivityKind::PegIn,
+        )?;
+
         // subscribe
         let operation = self
             .fedimint_client
@@ -711,6 +987,15 @@ impl<S: MutinyStorage> FederationClient<S> {
         Ok(address)
     }
 
+    /// Uploads an encrypted backup of our ecash notes to the federation,
+    /// this speeds up recovering them from our seed.
+    pub(crate) async fn backup_to_federation(&self) -> Result<(), MutinyError> {
+        self.fedimint_client
+            .backup_to_federation(Metadata::empty())
+            .await?;
+        Ok(())
+    }
+
     /// Get the balance of this federation client in sats
     pub(crate) async fn get_balance(&self) -> Result<u64, MutinyError> {
         Ok(self.fedimint_client.get_balance().await.msats / 1_000)
@@ -752,6 +1037,12 @@ impl<S: MutinyStorage> FederationClient<S> {
         let hash = stored_payment.payment_hash.into_32();
         let payment_info = PaymentInfo::from(stored_payment.clone());
         persist_payment_info(&self.storage, &hash, &payment_info, inbound)?;
+        persist_federation_activity_tag(
+            &self.storage,
+            &payment_key(inbound, &hash),
+            self.fedimint_client.federation_id(),
+            FederationActivityKind::Lightning,
+        )?;
 
         // Subscribe and process outcome based on payment type
         let (mut inv, id) = match outgoing_payment.payment_type {
@@ -863,9 +1154,16 @@ impl<S: MutinyStorage> FederationClient<S> {
                 last_seen: now().as_secs(),
             },
             labels: labels.clone(),
+            mempool_status: None,
         };
 
         persist_transaction_details(&self.storage, &pending_transaction_details)?;
+        persist_federation_activity_tag(
+            &self.storage,
+            &transaction_details_key(internal_id),
+            self.fedimint_client.federation_id(),
+            FederationActivityKind::PegOut,
+        )?;
 
         // persist the labels
         self.storage.set_address_labels(send_to, labels)?;
@@ -1580,6 +1878,7 @@ async fn process_onchain_withdraw_outcome<S: MutinyStorage>(
                                 fee: Some(fee.to_sat()),
                                 confirmation_time: ConfirmationTime::Unconfirmed { last_seen: now().as_secs() },
                                 labels: labels.clone(),
+                                mempool_status: None,
                             };
 
                             match persist_transaction_details(&storage, &updated_transaction_details) {
@@ -1650,13 +1949,22 @@ async fn subscribe_onchain_confirmation_check<S: MutinyStorage>(
                 Ok(s) => {
                     if s.confirmed {
                         log_info!(logger, "Transaction confirmed");
+                        let height = s.block_height.expect("confirmed");
                         transaction_details.confirmation_time = ConfirmationTime::Confirmed {
-                            height: s.block_height.expect("confirmed"),
+                            height,
                             time: s.block_time.unwrap_or(now().as_secs()),
                         };
                         match persist_transaction_details(&storage, &transaction_details) {
                             Ok(_) => {
                                 log_info!(logger, "Transaction updated");
+
+                                // keep checking it in case of a reorg
+                                let kind =
+                                    TrackedTx::TransactionDetails(transaction_details.internal_id);
+                                let block = s.block_hash.map(|hash| (height, hash));
+                                if let Err(e) = track_confirmation(&storage, txid, kind, block) {
+                                    log_error!(logger, "Error tracking confirmation: {e}");
+                                }
                                 break;
                             }
                             Err(e) => {
@@ -1733,6 +2041,7 @@ async fn process_onchain_deposit_outcome<S: MutinyStorage>(
                                 fee: None,
                                 confirmation_time: ConfirmationTime::Unconfirmed { last_seen: now().as_secs() },
                                 labels: labels.clone(),
+                                mempool_status: None,
                             };
 
                             match persist_transaction_details(&storage, &updated_transaction_details) {
@@ -1761,6 +2070,7 @@ async fn process_onchain_deposit_outcome<S: MutinyStorage>(
                                 fee: None,
                                 confirmation_time: ConfirmationTime::Confirmed { height: 0, time: now().as_secs() },
                                 labels: labels.clone(),
+                                mempool_status: None,
                             };
 
                             // we need to get confirmations for this txid and update
@@ -2247,11 +2557,134 @@ fn gateway_preference() {
     );
 }
 
+#[cfg(test)]
+fn federation_preference_sort() {
+    let a = FederationCandidate {
+        federation_id: FederationId::from_str(SIGNET_FEDERATION).unwrap(),
+        balance: 1_000,
+        fee: Some(10),
+    };
+    let b = FederationCandidate {
+        federation_id: FederationId::from_str(MAINNET_FEDERATION).unwrap(),
+        balance: 5_000,
+        fee: Some(50),
+    };
+    let c = FederationCandidate {
+        federation_id: FederationId::dummy(),
+        balance: 2_000,
+        fee: Some(1),
+    };
+    // no gateway, can't be used even though it has the largest balance
+    let d = FederationCandidate {
+        federation_id: FederationId::from_str(&"11".repeat(32)).unwrap(),
+        balance: 10_000,
+        fee: None,
+    };
+    let candidates = vec![a, b, c, d];
+
+    let pref = FederationPreference {
+        policy: FederationRoutingPolicy::LowestFee,
+        default_federation: None,
+    };
+    assert_eq!(
+        pref.sort_for_send(500, candidates.clone()),
+        vec![
+            c.federation_id,
+            a.federation_id,
+            b.federation_id,
+            d.federation_id
+        ]
+    );
+    // federations that can't cover the amount go last
+    assert_eq!(
+        pref.sort_for_send(1_500, candidates.clone()),
+        vec![
+            c.federation_id,
+            b.federation_id,
+            d.federation_id,
+            a.federation_id
+        ]
+    );
+    assert_eq!(
+        pref.sort_for_receive(candidates.clone()),
+        vec![
+            c.federation_id,
+            a.federation_id,
+            b.federation_id,
+            d.federation_id
+        ]
+    );
+
+    let pref = FederationPreference {
+        policy: FederationRoutingPolicy::LargestBalance,
+        default_federation: Some(a.federation_id),
+    };
+    assert_eq!(
+        pref.sort_for_send(500, candidates.clone()),
+        vec![
+            b.federation_id,
+            c.federation_id,
+            a.federation_id,
+            d.federation_id
+        ]
+    );
+    assert_eq!(
+        pref.sort_for_receive(candidates.clone()),
+        vec![
+            b.federation_id,
+            c.federation_id,
+            a.federation_id,
+            d.federation_id
+        ]
+    );
+
+    let pref = FederationPreference {
+        policy: FederationRoutingPolicy::PinnedDefault,
+        default_federation: Some(a.federation_id),
+    };
+    assert_eq!(
+        pref.sort_for_send(500, candidates.clone()),
+        vec![
+            a.federation_id,
+            b.federation_id,
+            c.federation_id,
+            d.federation_id
+        ]
+    );
+    // receiving falls back to the lowest fee after the default
+    assert_eq!(
+        pref.sort_for_receive(candidates.clone()),
+        vec![
+            a.federation_id,
+            c.federation_id,
+            b.federation_id,
+            d.federation_id
+        ]
+    );
+
+    // without a default set, sending falls back to largest balance
+    let pref = FederationPreference::default();
+    assert_eq!(
+        pref.sort_for_send(500, candidates),
+        vec![
+            b.federation_id,
+            c.federation_id,
+            a.federation_id,
+            d.federation_id
+        ]
+    );
+}
+
 #[cfg(test)]
 #[cfg(not(target_arch = "wasm32"))]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_federation_preference_sort() {
+        federation_preference_sort();
+    }
+
     #[test]
     fn test_fedimint_seed_generation() {
         fedimint_seed_generation();
@@ -2277,6 +2710,11 @@ mod wasm_tests {
 
     wasm_bindgen_test_configure!(run_in_browser);
 
+    #[test]
+    fn test_federation_preference_sort() {
+        federation_preference_sort();
+    }
+
     #[test]
     fn test_fedimint_seed_generation() {
         fedimint_seed_generation();
@@ -1,19 +1,24 @@
 use crate::logging::MutinyLogger;
 use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, utils};
+use async_trait::async_trait;
 use bdk::FeeRate;
 use bitcoin::Weight;
 use esplora_client::AsyncClient;
-use futures::lock::Mutex;
 use lightning::chain::chaininterface::{
     ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW,
 };
 use lightning::log_trace;
 use lightning::util::logger::Logger;
+use reqwest::Client;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+const FEE_ESTIMATES_UPDATED_KEY: &str = "fee_estimates_updated_at";
+const DEFAULT_FEE_REFRESH_INTERVAL_SECS: u64 = 5 * 60;
+const DEFAULT_FEE_ESTIMATES_STALE_SECS: u64 = 60 * 60;
+
 // Constants for overhead, input, and output sizes
 pub(crate) const TX_OVERHEAD: usize = 10;
 pub(crate) const TAPROOT_INPUT_NON_WITNESS_SIZE: usize = 41;
@@ -22,12 +27,135 @@ pub(crate) const P2WSH_OUTPUT_SIZE: usize = 43;
 #[allow(dead_code)]
 pub(crate) const TAPROOT_OUTPUT_SIZE: usize = 43;
 
+/// A source of fee estimates.
+///
+/// [EsploraFeeProvider] is used by default, [MempoolFeeProvider] and
+/// [StaticFeeProvider] can be set with [crate::MutinyWalletConfigBuilder::with_fee_provider],
+/// implement this to get estimates from anywhere else.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait FeeProvider {
+    /// Gets fee rates in sats per vbyte keyed by the number of blocks to confirm in,
+    /// targets of 1, 6 and 1008 blocks should be included
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError>;
+}
+
+/// Gets fees from mempool.space's API on our esplora server,
+/// falling back to esplora's own estimates if it doesn't have one
+pub struct EsploraFeeProvider {
+    esplora: Arc<AsyncClient>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl EsploraFeeProvider {
+    pub fn new(esplora: Arc<AsyncClient>, logger: Arc<MutinyLogger>) -> Self {
+        Self { esplora, logger }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FeeProvider for EsploraFeeProvider {
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        // first try mempool.space's API
+        let mempool_fees =
+            get_mempool_recommended_fees(self.esplora.client(), self.esplora.url()).await;
+
+        // if that fails, fall back to esplora's API
+        match mempool_fees {
+            Ok(mempool_fees) => {
+                log_trace!(self.logger, "Retrieved fees from mempool");
+                Ok(mempool_fees)
+            }
+            Err(e) => {
+                log_trace!(
+                    self.logger,
+                    "Failed to retrieve fees from mempool, falling back to esplora: {e}"
+                );
+                Ok(self.esplora.get_fee_estimates().await.map_err(|e| {
+                    log_trace!(self.logger, "Failed to get esplora fee: {e}");
+                    e
+                })?)
+            }
+        }
+    }
+}
+
+/// Gets fees from a mempool.space compatible API
+pub struct MempoolFeeProvider {
+    client: Client,
+    /// Base url of the API, like `https://mempool.space/api`
+    url: String,
+}
+
+impl MempoolFeeProvider {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FeeProvider for MempoolFeeProvider {
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        Ok(get_mempool_recommended_fees(&self.client, &self.url).await?)
+    }
+}
+
+/// Fee rates set by the user, for when they don't want to trust a fee server
+pub struct StaticFeeProvider {
+    fee_estimates: HashMap<String, f64>,
+}
+
+impl StaticFeeProvider {
+    /// Fee rates are in sats per vbyte, `high` is used for next block,
+    /// `normal` for within 6 blocks and `low` for anything slower
+    pub fn new(high: f64, normal: f64, low: f64) -> Self {
+        let fee_estimates = HashMap::from([
+            ("1".to_string(), high),
+            ("6".to_string(), normal),
+            ("1008".to_string(), low),
+        ]);
+        Self { fee_estimates }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FeeProvider for StaticFeeProvider {
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        Ok(self.fee_estimates.clone())
+    }
+}
+
+/// How fee estimates are refreshed, see [crate::MutinyWalletConfigBuilder::with_fee_estimator_config]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeEstimatorConfig {
+    /// How often the fee estimates are refreshed
+    pub refresh_interval_secs: u64,
+    /// Estimates older than this are considered stale and are never used
+    /// below the fallback fee rates, so we don't underpay during a fee spike
+    pub stale_after_secs: u64,
+}
+
+impl Default for FeeEstimatorConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_secs: DEFAULT_FEE_REFRESH_INTERVAL_SECS,
+            stale_after_secs: DEFAULT_FEE_ESTIMATES_STALE_SECS,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MutinyFeeEstimator<S: MutinyStorage> {
     storage: S,
-    esplora: Arc<AsyncClient>,
+    provider: Arc<dyn FeeProvider + Send + Sync>,
+    config: FeeEstimatorConfig,
     logger: Arc<MutinyLogger>,
-    last_fee_update_time_secs: Arc<Mutex<Option<u64>>>,
 }
 
 impl<S: MutinyStorage> MutinyFeeEstimator<S> {
@@ -35,15 +163,29 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
         storage: S,
         esplora: Arc<AsyncClient>,
         logger: Arc<MutinyLogger>,
+    ) -> MutinyFeeEstimator<S> {
+        let provider = Arc::new(EsploraFeeProvider::new(esplora, logger.clone()));
+        Self::new_with_provider(storage, provider, logger)
+    }
+
+    pub fn new_with_provider(
+        storage: S,
+        provider: Arc<dyn FeeProvider + Send + Sync>,
+        logger: Arc<MutinyLogger>,
     ) -> MutinyFeeEstimator<S> {
         MutinyFeeEstimator {
             storage,
-            esplora,
+            provider,
+            config: FeeEstimatorConfig::default(),
             logger,
-            last_fee_update_time_secs: Arc::new(Mutex::new(None)),
         }
     }
 
+    pub fn with_config(mut self, config: FeeEstimatorConfig) -> MutinyFeeEstimator<S> {
+        self.config = config;
+        self
+    }
+
     /// Calculate the estimated fee in satoshis for a transaction.
     /// It is assumed that the inputs will be Taproot key spends.
     pub fn calculate_expected_fee(
@@ -71,9 +213,20 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
             .fee_wu(Weight::from_wu(expected_weight as u64))
     }
 
-    async fn get_last_sync_time(&self) -> Option<u64> {
-        let lock = self.last_fee_update_time_secs.lock().await;
-        *lock
+    /// Unix timestamp of when we last updated the fee estimates
+    pub fn last_updated(&self) -> Option<u64> {
+        self.storage
+            .get_data(FEE_ESTIMATES_UPDATED_KEY)
+            .ok()
+            .flatten()
+    }
+
+    /// Whether our fee estimates are too old to trust.
+    /// Estimates saved before we tracked their age are not considered stale.
+    pub fn is_stale(&self) -> bool {
+        self.last_updated().is_some_and(|time| {
+            utils::now().as_secs().saturating_sub(time) > self.config.stale_after_secs
+        })
     }
 }
 
@@ -87,62 +240,49 @@ struct MempoolFees {
     minimum_fee: f64,
 }
 
-impl<S: MutinyStorage> MutinyFeeEstimator<S> {
-    async fn get_mempool_recommended_fees(&self) -> anyhow::Result<HashMap<String, f64>> {
-        let client = self.esplora.client();
-        let request = client
-            .get(format!("{}/v1/fees/recommended", self.esplora.url()))
-            .build()?;
-
-        let fees_response = utils::fetch_with_timeout(client, request)
-            .await?
-            .error_for_status()?;
-        let fees = fees_response.json::<MempoolFees>().await?;
-
-        // convert to hashmap of num blocks -> fee rate
-        let mut fee_estimates = HashMap::new();
-        fee_estimates.insert("1".to_string(), fees.fastest_fee);
-        fee_estimates.insert("3".to_string(), fees.half_hour_fee);
-        fee_estimates.insert("6".to_string(), fees.hour_fee);
-        fee_estimates.insert("12".to_string(), fees.economy_fee);
-        fee_estimates.insert("1008".to_string(), fees.minimum_fee);
-
-        Ok(fee_estimates)
-    }
+async fn get_mempool_recommended_fees(
+    client: &Client,
+    url: &str,
+) -> anyhow::Result<HashMap<String, f64>> {
+    let request = client.get(format!("{url}/v1/fees/recommended")).build()?;
+
+    let fees_response = utils::fetch_with_timeout(client, request)
+        .await?
+        .error_for_status()?;
+    let fees = fees_response.json::<MempoolFees>().await?;
+
+    // convert to hashmap of num blocks -> fee rate
+    let mut fee_estimates = HashMap::new();
+    fee_estimates.insert("1".to_string(), fees.fastest_fee);
+    fee_estimates.insert("3".to_string(), fees.half_hour_fee);
+    fee_estimates.insert("6".to_string(), fees.hour_fee);
+    fee_estimates.insert("12".to_string(), fees.economy_fee);
+    fee_estimates.insert("1008".to_string(), fees.minimum_fee);
+
+    Ok(fee_estimates)
+}
 
+impl<S: MutinyStorage> MutinyFeeEstimator<S> {
     pub async fn update_fee_estimates_if_necessary(&self) -> Result<(), MutinyError> {
-        let last_sync = self.get_last_sync_time().await;
-        if last_sync.is_none() || utils::now().as_secs() > last_sync.unwrap() + 60 * 10 {
+        let now = utils::now().as_secs();
+        let needs_update = self.last_updated().map_or(true, |time| {
+            now.saturating_sub(time) >= self.config.refresh_interval_secs
+        });
+        if needs_update {
             self.update_fee_estimates().await?;
         }
         Ok(())
     }
 
     async fn update_fee_estimates(&self) -> Result<(), MutinyError> {
-        // first try mempool.space's API
-        let mempool_fees = self.get_mempool_recommended_fees().await;
-
-        // if that fails, fall back to esplora's API
-        let fee_estimates = match mempool_fees {
-            Ok(mempool_fees) => {
-                log_trace!(self.logger, "Retrieved fees from mempool");
-                mempool_fees
-            }
-            Err(e) => {
-                log_trace!(
-                    self.logger,
-                    "Failed to retrieve fees from mempool, falling back to esplora: {e}"
-                );
-                self.esplora.get_fee_estimates().await.map_err(|e| {
-                    log_trace!(self.logger, "Failed to get esplora fee: {e}");
-                    e
-                })?
-            }
-        };
+        let fee_estimates = self.provider.get_fee_estimates().await?;
 
         self.storage.insert_fee_estimates(fee_estimates)?;
-        let mut update_time_lock = self.last_fee_update_time_secs.lock().await;
-        *update_time_lock = Some(utils::now().as_secs());
+        self.storage.set_data(
+            FEE_ESTIMATES_UPDATED_KEY.to_string(),
+            utils::now().as_secs(),
+            None,
+        )?;
 
         Ok(())
     }
@@ -187,6 +327,23 @@ impl<S: MutinyStorage> FeeEstimator for MutinyFeeEstimator<S> {
             }
         };
 
+        // don't trust old estimates to be high enough, except for the lowest fee
+        // we allow our peers to use, raising that could force close our channels
+        let is_min_allowed = matches!(
+            confirmation_target,
+            ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee
+                | ConfirmationTarget::MinAllowedAnchorChannelRemoteFee
+        );
+        let fee = if !is_min_allowed && self.is_stale() {
+            log_trace!(
+                self.logger,
+                "Fee estimates are stale, using at least the fallback"
+            );
+            fee.max(fallback_fee)
+        } else {
+            fee
+        };
+
         // any post processing we do after we get the fee rate from the cache
         match confirmation_target {
             ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee => fee - 250, // helps with rounding errors
@@ -333,6 +490,50 @@ mod test {
         );
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_static_fee_provider() {
+        let test_name = "test_static_fee_provider";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let provider = Arc::new(StaticFeeProvider::new(30.0, 10.0, 2.0));
+        let fee_estimator = MutinyFeeEstimator::new_with_provider(storage, provider, logger);
+        assert_eq!(fee_estimator.last_updated(), None);
+
+        fee_estimator
+            .update_fee_estimates_if_necessary()
+            .await
+            .unwrap();
+        assert!(fee_estimator.last_updated().is_some());
+        assert!(!fee_estimator.is_stale());
+        assert_eq!(fee_estimator.get_high_fee_rate(), 7_500);
+        assert_eq!(fee_estimator.get_normal_fee_rate(), 2_500);
+
+        assert_eq!(fee_estimator.get_low_fee_rate(), 250);
+
+        // stale estimates aren't used below the fallback
+        let old = utils::now().as_secs() - DEFAULT_FEE_ESTIMATES_STALE_SECS - 1;
+        fee_estimator
+            .storage
+            .set_data(FEE_ESTIMATES_UPDATED_KEY.to_string(), old, None)
+            .unwrap();
+        assert!(fee_estimator.is_stale());
+        assert_eq!(fee_estimator.get_high_fee_rate(), 12_500);
+        assert_eq!(fee_estimator.get_normal_fee_rate(), 5_000);
+        // but the lowest fee we allow from peers isn't raised
+        assert_eq!(fee_estimator.get_low_fee_rate(), 250);
+
+        // how long until estimates are stale can be changed
+        let fee_estimator = fee_estimator.with_config(FeeEstimatorConfig {
+            stale_after_secs: 2 * DEFAULT_FEE_ESTIMATES_STALE_SECS,
+            ..Default::default()
+        });
+        assert!(!fee_estimator.is_stale());
+        assert_eq!(fee_estimator.get_high_fee_rate(), 7_500);
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     #[tokio::test]
     async fn test_estimate_expected_fee() {
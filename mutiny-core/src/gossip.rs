@@ -8,10 +8,10 @@ use hex_conservative::DisplayHex;
 use lightning::ln::msgs::NodeAnnouncement;
 use lightning::routing::gossip::NodeId;
 use lightning::util::logger::Logger;
-use lightning::util::ser::ReadableArgs;
-use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
+use lightning::util::ser::{ReadableArgs, Writeable};
+use lightning::{log_debug, log_error, log_info, log_trace};
 use reqwest::Client;
-use reqwest::{Method, Url};
+use reqwest::{Method, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -32,6 +32,17 @@ pub const GOSSIP_SYNC_TIME_KEY: &str = "last_sync_timestamp";
 pub const NETWORK_GRAPH_KEY: &str = "network_graph";
 pub const PROB_SCORER_KEY: &str = "prob_scorer";
 
+/// Key of the timestamp of the last RGS snapshot we applied for the network
+pub(crate) fn gossip_sync_time_key(network: Network) -> String {
+    format!("{GOSSIP_SYNC_TIME_KEY}/{network}")
+}
+
+/// Key of the network graph that earlier versions cached for the network,
+/// it is only deleted now, see [get_gossip_sync]
+pub(crate) fn network_graph_key(network: Network) -> String {
+    format!("{NETWORK_GRAPH_KEY}/{network}")
+}
+
 struct Gossip {
     pub last_sync_timestamp: u32,
     pub network_graph: Arc<NetworkGraph>,
@@ -66,50 +77,30 @@ async fn get_scorer(
     }
 }
 
-#[allow(dead_code)]
-async fn get_gossip_data(
-    storage: &impl MutinyStorage,
-    logger: Arc<MutinyLogger>,
-) -> Result<Option<Gossip>, MutinyError> {
-    // Get the `last_sync_timestamp`
-    let last_sync_timestamp: u32 = match storage.get_data(GOSSIP_SYNC_TIME_KEY)? {
-        Some(last_sync_timestamp) => last_sync_timestamp,
-        None => return Ok(None),
-    };
-
-    // Get the `network_graph`
-    let network_graph: Arc<NetworkGraph> = match storage.get_data::<String>(NETWORK_GRAPH_KEY)? {
-        Some(network_graph_str) => {
-            let network_graph_bytes: Vec<u8> = Vec::from_hex(&network_graph_str)?;
-            let mut readable_bytes = lightning::io::Cursor::new(network_graph_bytes);
-            Arc::new(NetworkGraph::read(&mut readable_bytes, logger.clone())?)
-        }
-        None => return Ok(None),
-    };
-
-    log_debug!(logger, "Got network graph, getting scorer...");
-
-    let scorer = get_scorer(storage, network_graph.clone(), logger.clone()).await?;
-
-    if scorer.is_none() {
-        log_warn!(logger, "Could not read probabilistic scorer from database");
-    }
-
-    let gossip = Gossip {
-        last_sync_timestamp,
-        network_graph,
-        scorer,
-    };
-
-    Ok(Some(gossip))
-}
-
-/// Scorer is the scorer that gets pulled remotely
+/// Scorer is the scorer that gets pulled remotely, the value is the base64 encoded scorer.
+/// Exported scorer snapshots use the same encoding.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Scorer {
     pub value: String,
 }
 
+/// Encodes the scorer the same way the scorer server does
+pub(crate) fn encode_scorer_snapshot(scorer: &impl Writeable) -> String {
+    base64::encode(scorer.encode())
+}
+
+/// Parses a base64 encoded scorer, see [encode_scorer_snapshot]
+pub(crate) fn parse_scorer_snapshot(
+    value: &str,
+    network_graph: Arc<NetworkGraph>,
+    logger: Arc<MutinyLogger>,
+) -> Result<ProbScorer, MutinyError> {
+    let bytes = base64::decode(value).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let mut readable_bytes = lightning::io::Cursor::new(bytes);
+    let args = (decay_params(), network_graph, logger);
+    ProbScorer::read(&mut readable_bytes, args).map_err(|_| MutinyError::InvalidArgumentsError)
+}
+
 async fn get_remote_scorer_bytes(
     auth_client: &MutinyAuthClient,
     base_url: &str,
@@ -131,13 +122,14 @@ async fn get_remote_scorer_bytes(
     Ok(decoded)
 }
 
-/// Gets the remote scorer from the server, parses it and returns it as a [`HubPreferentialScorer`]
+/// Gets the remote scorer from the server and parses it,
+/// see [HubPreferentialScorer::set_remote] for how it is used
 pub async fn get_remote_scorer(
     auth_client: &MutinyAuthClient,
     base_url: &str,
     network_graph: Arc<NetworkGraph>,
     logger: Arc<MutinyLogger>,
-) -> Result<HubPreferentialScorer, MutinyError> {
+) -> Result<ProbScorer, MutinyError> {
     let start = Instant::now();
     let scorer_bytes = get_remote_scorer_bytes(auth_client, base_url).await?;
     let mut readable_bytes = lightning::io::Cursor::new(scorer_bytes);
@@ -151,24 +143,25 @@ pub async fn get_remote_scorer(
         start.elapsed().as_millis()
     );
 
-    Ok(HubPreferentialScorer::new(scorer))
+    Ok(scorer)
 }
 
 fn write_gossip_data(
     storage: &impl MutinyStorage,
+    network: Network,
     last_sync_timestamp: u32,
-    _network_graph: &NetworkGraph,
 ) -> Result<(), MutinyError> {
-    // Save the last sync timestamp
-    storage.set_data(GOSSIP_SYNC_TIME_KEY.to_string(), last_sync_timestamp, None)?;
-
-    // Save the network graph
-    // skip for now, we don't read it currently
-    // storage.set_data(NETWORK_GRAPH_KEY, network_graph.encode().to_hex(), None)?;
+    // Only the timestamp is saved. The network graph is megabytes and every start
+    // downloads a full snapshot anyway, see get_gossip_sync
+    storage.set_data(gossip_sync_time_key(network), last_sync_timestamp, None)?;
 
     Ok(())
 }
 
+/// Creates the gossip sync and scorer.
+///
+/// The network graph always starts empty, so the first sync downloads a full snapshot.
+/// The syncs after it only download the changes since the last one.
 pub async fn get_gossip_sync(
     storage: &impl MutinyStorage,
     network: Network,
@@ -231,6 +224,7 @@ pub(crate) async fn fetch_updated_gossip(
     now: u64,
     last_sync_timestamp: u32,
     gossip_sync: &RapidGossipSync,
+    network: Network,
     storage: &impl MutinyStorage,
     logger: &MutinyLogger,
 ) -> Result<(), MutinyError> {
@@ -261,11 +255,7 @@ pub(crate) async fn fetch_updated_gossip(
 
     // save the network graph if has been updated
     if new_last_sync_timestamp_result != last_sync_timestamp {
-        write_gossip_data(
-            storage,
-            new_last_sync_timestamp_result,
-            gossip_sync.network_graph(),
-        )?;
+        write_gossip_data(storage, network, new_last_sync_timestamp_result)?;
     }
 
     Ok(())
@@ -279,6 +269,9 @@ pub struct LnPeerMetadata {
     pub alias: Option<String>,
     /// The node's color given from the node announcement
     pub color: Option<String>,
+    /// The node's addresses given from the node announcement
+    #[serde(default)]
+    pub addresses: Vec<String>,
     /// The label set by the user for this node
     pub label: Option<String>,
     /// The timestamp of when this information was last updated
@@ -317,6 +310,24 @@ impl LnPeerMetadata {
         }
     }
 
+    /// Gets the node's metadata from its announcement in the network graph,
+    /// `None` if we haven't seen an announcement for the node.
+    pub(crate) fn from_network_graph(
+        network_graph: &NetworkGraph,
+        node_id: &NodeId,
+    ) -> Option<Self> {
+        let graph = network_graph.read_only();
+        let info = graph.node(node_id)?.announcement_info.as_ref()?;
+
+        Some(Self {
+            alias: Some(info.alias.to_string()),
+            color: Some(info.rgb.to_lower_hex_string()),
+            addresses: info.addresses().iter().map(|a| a.to_string()).collect(),
+            timestamp: Some(info.last_update),
+            ..Default::default()
+        })
+    }
+
     pub(crate) fn merge_opt(&self, other: Option<&LnPeerMetadata>) -> LnPeerMetadata {
         match other {
             Some(other) => self.merge(other),
@@ -338,10 +349,17 @@ impl LnPeerMetadata {
         nodes.sort();
         nodes.dedup();
 
+        let addresses = if primary.addresses.is_empty() {
+            secondary.addresses
+        } else {
+            primary.addresses
+        };
+
         Self {
             connection_string: primary.connection_string.or(secondary.connection_string),
             alias: primary.alias.or(secondary.alias),
             color: primary.color.or(secondary.color),
+            addresses,
             label: primary.label.or(secondary.label),
             timestamp: primary.timestamp.or(secondary.timestamp),
             nodes,
@@ -352,9 +370,16 @@ impl LnPeerMetadata {
 impl From<NodeAnnouncement> for LnPeerMetadata {
     fn from(value: NodeAnnouncement) -> Self {
         Self {
-            connection_string: None, // todo get from addresses
+            // we keep connecting with what we were given, the addresses may not be reachable
+            connection_string: None,
             alias: Some(value.contents.alias.to_string()),
             color: Some(value.contents.rgb.to_lower_hex_string()),
+            addresses: value
+                .contents
+                .addresses
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
             label: None,
             timestamp: Some(value.contents.timestamp),
             nodes: vec![],
@@ -362,6 +387,62 @@ impl From<NodeAnnouncement> for LnPeerMetadata {
     }
 }
 
+/// A node from mempool.space's lightning API
+#[derive(Deserialize)]
+struct MempoolLightningNode {
+    alias: String,
+    color: String,
+    /// Comma separated addresses of the node
+    #[serde(default)]
+    sockets: String,
+}
+
+/// Looks up the node's alias, color and addresses from a mempool.space compatible
+/// esplora server, for when the node isn't in our network graph.
+/// Returns `None` if the server doesn't know about the node.
+pub(crate) async fn fetch_peer_metadata(
+    client: &Client,
+    url: &str,
+    node_id: &NodeId,
+) -> Result<Option<LnPeerMetadata>, MutinyError> {
+    let request = client
+        .get(format!("{url}/v1/lightning/nodes/{node_id}"))
+        .build()
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+    let response = utils::fetch_with_timeout(client, request).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let node: MempoolLightningNode = response
+        .error_for_status()
+        .map_err(|_| MutinyError::ConnectionFailed)?
+        .json()
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    Ok(Some(node.into()))
+}
+
+impl From<MempoolLightningNode> for LnPeerMetadata {
+    fn from(value: MempoolLightningNode) -> Self {
+        Self {
+            alias: Some(value.alias).filter(|a| !a.is_empty()),
+            color: Some(value.color.trim_start_matches('#').to_lowercase())
+                .filter(|c| !c.is_empty()),
+            addresses: value
+                .sockets
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            // no timestamp so it never replaces what we already have
+            timestamp: None,
+            ..Default::default()
+        }
+    }
+}
+
 pub(crate) fn read_peer_info(
     storage: &impl MutinyStorage,
     node_id: &NodeId,
@@ -380,6 +461,9 @@ pub(crate) fn get_all_peers(
         // remove the prefix from the key
         let key = key.replace(LN_PEER_METADATA_KEY_PREFIX, "");
         if let Ok(node_id) = NodeId::from_str(&key) {
+            // keys that aren't lowercase are the same peer, merge them instead of
+            // picking one at random and losing which of our nodes it is connected to
+            let value = value.merge_opt(peers.get(&node_id));
             peers.insert(node_id, value);
         }
     }
@@ -535,6 +619,7 @@ mod test {
             connection_string: Some("example.com:9735".to_string()),
             alias: Some("test alias".to_string()),
             color: Some("123456".to_string()),
+            addresses: vec!["127.0.0.1:9735".to_string()],
             label: Some("test label".to_string()),
             timestamp: Some(utils::now().as_secs() as u32),
             nodes: vec![uuid],
@@ -566,6 +651,41 @@ mod test {
         assert_eq!(max_timestamp.merge(&min_timestamp), max_timestamp);
     }
 
+    #[test]
+    fn test_mempool_peer_metadata() {
+        let node: MempoolLightningNode = serde_json::from_str(
+            r##"{"alias":"ACINQ","color":"#49DAAA","sockets":"3.33.236.230:9735,[2600::1]:9735"}"##,
+        )
+        .unwrap();
+        let metadata = LnPeerMetadata::from(node);
+        assert_eq!(metadata.alias, Some("ACINQ".to_string()));
+        assert_eq!(metadata.color, Some("49daaa".to_string()));
+        assert_eq!(
+            metadata.addresses,
+            vec!["3.33.236.230:9735", "[2600::1]:9735"]
+        );
+
+        // what we looked up only fills in what we are missing
+        let (_, data) = dummy_peer_info();
+        let saved = LnPeerMetadata {
+            alias: None,
+            addresses: vec![],
+            ..data.clone()
+        };
+        let merged = saved.merge(&metadata);
+        assert_eq!(merged.alias, metadata.alias);
+        assert_eq!(merged.color, data.color);
+        assert_eq!(merged.addresses, metadata.addresses);
+        assert_eq!(merged.timestamp, data.timestamp);
+
+        let node: MempoolLightningNode =
+            serde_json::from_str(r#"{"alias":"","color":""}"#).unwrap();
+        let metadata = LnPeerMetadata::from(node);
+        assert_eq!(metadata.alias, None);
+        assert_eq!(metadata.color, None);
+        assert!(metadata.addresses.is_empty());
+    }
+
     #[test]
     // hack to disable this test
     #[cfg(feature = "ignored_tests")]
@@ -578,10 +698,37 @@ mod test {
             .await
             .unwrap();
 
-        let data = get_gossip_data(&storage, logger).await.unwrap();
+        let timestamp: Option<u32> = storage
+            .get_data(gossip_sync_time_key(Network::Regtest))
+            .unwrap();
+        assert!(timestamp.unwrap() > 0);
+    }
 
-        assert!(data.is_some());
-        assert!(data.unwrap().last_sync_timestamp > 0);
+    #[test]
+    async fn test_gossip_starts_empty() {
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let network = Network::Signet;
+
+        let timestamp = utils::now().as_secs() as u32;
+        write_gossip_data(&storage, network, timestamp).unwrap();
+        let saved: Option<u32> = storage.get_data(gossip_sync_time_key(network)).unwrap();
+        assert_eq!(saved, Some(timestamp));
+        // snapshot timestamps are stored per network
+        let other: Option<u32> = storage
+            .get_data(gossip_sync_time_key(Network::Bitcoin))
+            .unwrap();
+        assert!(other.is_none());
+        // the graph itself isn't saved
+        let graph: Option<String> = storage.get_data(network_graph_key(network)).unwrap();
+        assert!(graph.is_none());
+
+        // the first sync is always a full snapshot
+        let (gossip_sync, _) = get_gossip_sync(&storage, network, logger).await.unwrap();
+        assert!(gossip_sync
+            .network_graph()
+            .get_last_rapid_gossip_sync_timestamp()
+            .is_none());
     }
 
     #[test]
@@ -606,6 +753,32 @@ mod test {
         assert!(read.is_none());
     }
 
+    #[test]
+    fn test_duplicate_peer_records() {
+        let storage = MemoryStorage::default();
+        let (node_id, data) = dummy_peer_info();
+        save_ln_peer_info(&storage, &node_id, &data).unwrap();
+
+        // the same peer saved under an uppercase key by another node
+        let other = LnPeerMetadata {
+            timestamp: data.timestamp.map(|t| t - 1),
+            nodes: vec!["other".to_string()],
+            ..Default::default()
+        };
+        let key = format!(
+            "{LN_PEER_METADATA_KEY_PREFIX}{}",
+            node_id.to_string().to_uppercase()
+        );
+        storage.set_data(key, other, None).unwrap();
+
+        let all = get_all_peers(&storage).unwrap();
+        assert_eq!(all.len(), 1);
+        let merged = all.get(&node_id).unwrap();
+        assert_eq!(merged.alias, data.alias);
+        assert_eq!(merged.nodes.len(), 2);
+        assert!(merged.nodes.contains(&"other".to_string()));
+    }
+
     #[test]
     fn test_delete_label() {
         let storage = MemoryStorage::default();
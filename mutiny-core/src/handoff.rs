@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+pub(crate) const DEVICE_HANDOFF_KEY: &str = "device_handoff";
+/// How often the device holding the lock checks for handoff requests
+pub(crate) const DEVICE_HANDOFF_CHECK_INTERVAL_SECS: u64 = 10;
+/// How long a device waits for the lock to be handed over before giving up
+pub(crate) const DEVICE_HANDOFF_TIMEOUT_SECS: u64 = 60;
+/// How often a device waiting for the lock checks if it was released
+pub(crate) const DEVICE_HANDOFF_POLL_MS: i32 = 3_000;
+
+/// A request from another device to take over the device lock,
+/// see [crate::storage::MutinyStorage::request_device_handoff]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeviceHandoffRequest {
+    /// Id of the device asking for the lock
+    pub device: String,
+    /// Unix timestamp of the request
+    pub time: u32,
+}
+
+/// What happened while handing the device lock to another device
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeviceHandoffEvent {
+    /// Another device asked for the lock, the wallet is stopping
+    Requested { device: String, time: u32 },
+    /// The wallet stopped and released the lock to the other device
+    Released { device: String },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_device_handoff_event_json() {
+        let event = DeviceHandoffEvent::Requested {
+            device: "device".to_string(),
+            time: 1,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"type": "requested", "device": "device", "time": 1})
+        );
+
+        let event: DeviceHandoffEvent =
+            serde_json::from_str(r#"{"type":"released","device":"device"}"#).unwrap();
+        assert_eq!(
+            event,
+            DeviceHandoffEvent::Released {
+                device: "device".to_string()
+            }
+        );
+    }
+}
@@ -28,10 +28,10 @@ use tbs::unblind_signature;
 use url::Url;
 
 use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
-use crate::federation::FedimintClient;
+use crate::federation::{persist_federation_activity_tag, FederationActivityKind, FedimintClient};
 use crate::labels::LabelStorage;
 use crate::nostr::RELAYS;
-use crate::storage::persist_payment_info;
+use crate::storage::{payment_key, persist_payment_info};
 use crate::{
     blindauth::{BlindAuthClient, SignedToken},
     error::MutinyError,
@@ -747,8 +747,16 @@ async fn claim_ecash_notification<S: MutinyStorage>(
                 privacy_level,
                 // use the notification event's created_at as last update so we can properly sort by time
                 last_update: created_at.as_u64(),
+                custom_tlvs: vec![],
             };
             persist_payment_info(storage, &payment_hash, &info, true)?;
+            // the claimed ecash is reissued into our federation, show it as such
+            persist_federation_activity_tag(
+                storage,
+                &payment_key(true, &payment_hash),
+                notification.federation_id,
+                FederationActivityKind::EcashReissue,
+            )?;
 
             // tag the invoice if we can
             let mut tags = Vec::with_capacity(2);
@@ -851,7 +859,7 @@ fn handle_private_zap(
 mod test {
     use super::*;
     use crate::auth::MutinyAuthClient;
-    use crate::federation::MockFedimintClient;
+    use crate::federation::{get_federation_activity_tag, MockFedimintClient};
     use crate::generate_seed;
     use crate::storage::MemoryStorage;
     use crate::test_utils::{create_dummy_invoice, create_manager};
@@ -1010,6 +1018,14 @@ mod test {
         let invoice_labels = storage.get_invoice_labels().unwrap();
         let labels = invoice_labels.get(&bolt11);
         assert!(labels.is_none()); // should not have been tagged
+
+        // and shows up as ecash reissued into the federation
+        let key = payment_key(true, &bolt11.payment_hash().into_32());
+        let tag = get_federation_activity_tag(&storage, &key)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tag.federation_id, FederationId::dummy());
+        assert_eq!(tag.kind, FederationActivityKind::EcashReissue);
     }
 
     #[tokio::test]
@@ -0,0 +1,295 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hashes::{sha256, Hash};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Mutex;
+
+const IDEMPOTENCY_PREFIX: &str = "idempotency/";
+/// How long we remember the outcome of a payment for its idempotency key
+const IDEMPOTENCY_EXPIRY_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Looking up a record and saving a new one happen under this lock,
+/// so two calls with the same key can't both start the payment
+static RECORD_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum IdempotentOutcome<T> {
+    /// The payment was started but has not finished, or timed out and can still complete
+    Pending,
+    Succeeded {
+        result: T,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct IdempotencyRecord<T> {
+    outcome: IdempotentOutcome<T>,
+    /// Hash of the request, the key can't be used for a different one
+    #[serde(default)]
+    request_hash: Option<sha256::Hash>,
+    created_at: u64,
+}
+
+/// Only what we need to know if a record expired, whatever its result is
+#[derive(Deserialize)]
+struct RecordAge {
+    created_at: u64,
+}
+
+/// What became of a payment whose record is still pending
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PendingPayment<T> {
+    /// It hasn't finished, or we can't tell
+    InFlight,
+    Succeeded(T),
+    Failed,
+}
+
+fn idempotency_storage_key(key: &str) -> String {
+    format!("{IDEMPOTENCY_PREFIX}{key}")
+}
+
+fn request_hash(request: &impl Serialize) -> Result<sha256::Hash, MutinyError> {
+    Ok(sha256::Hash::hash(&serde_json::to_vec(request)?))
+}
+
+/// Deletes the records that expired
+fn prune_expired_records<S: MutinyStorage>(storage: &S, now: u64) -> Result<(), MutinyError> {
+    let expired: Vec<String> = storage
+        .scan::<RecordAge>(IDEMPOTENCY_PREFIX, None)?
+        .into_iter()
+        .filter(|(_, r)| r.created_at + IDEMPOTENCY_EXPIRY_SECS <= now)
+        .map(|(key, _)| key)
+        .collect();
+    if expired.is_empty() {
+        return Ok(());
+    }
+    storage.delete(&expired)
+}
+
+/// Looks up the record of the key, or saves a pending one if there is none so the payment
+/// can start. Returns the record that was there.
+fn get_or_start<S, T>(
+    storage: &S,
+    storage_key: &str,
+    request_hash: sha256::Hash,
+    now: u64,
+) -> Result<Option<IdempotencyRecord<T>>, MutinyError>
+where
+    S: MutinyStorage,
+    T: Serialize + DeserializeOwned,
+{
+    let _lock = RECORD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    prune_expired_records(storage, now)?;
+
+    if let Some(record) = storage.get_data::<IdempotencyRecord<T>>(storage_key)? {
+        if record.request_hash.is_some_and(|h| h != request_hash) {
+            return Err(MutinyError::IdempotencyKeyReused);
+        }
+        return Ok(Some(record));
+    }
+
+    // save before paying, so a retry while we pay doesn't pay again
+    let record = IdempotencyRecord::<T> {
+        outcome: IdempotentOutcome::Pending,
+        request_hash: Some(request_hash),
+        created_at: now,
+    };
+    storage.set_data(storage_key.to_string(), record, None)?;
+    Ok(None)
+}
+
+/// Starts the payment again in place of a pending one that failed,
+/// unless another call did so since we looked at it
+fn restart<S, T>(
+    storage: &S,
+    storage_key: &str,
+    failed: &IdempotencyRecord<T>,
+    now: u64,
+) -> Result<IdempotencyRecord<T>, MutinyError>
+where
+    S: MutinyStorage,
+    T: Serialize + DeserializeOwned + Clone + PartialEq,
+{
+    let _lock = RECORD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if storage
+        .get_data::<IdempotencyRecord<T>>(storage_key)?
+        .as_ref()
+        != Some(failed)
+    {
+        return Err(MutinyError::PaymentInProgress);
+    }
+
+    let record = IdempotencyRecord {
+        created_at: now,
+        ..failed.clone()
+    };
+    storage.set_data(storage_key.to_string(), &record, None)?;
+    Ok(record)
+}
+
+/// Makes a payment at most once for the same idempotency key.
+///
+/// A retry with the key of a payment that succeeded returns its original result. Retrying
+/// a payment that is still pending checks what became of it with `reconcile`: one that
+/// succeeded returns its result, one that failed is paid again and otherwise it fails with
+/// [MutinyError::PaymentInProgress]. A payment that failed is forgotten so it can be
+/// retried, unless it timed out as that payment can still complete.
+///
+/// The key can't be used again for a different `request` while it is remembered.
+pub(crate) async fn with_idempotency<S, T, F, R>(
+    storage: &S,
+    key: Option<&str>,
+    request: &impl Serialize,
+    reconcile: R,
+    payment: F,
+) -> Result<T, MutinyError>
+where
+    S: MutinyStorage,
+    T: Serialize + DeserializeOwned + Clone + PartialEq,
+    F: Future<Output = Result<T, MutinyError>>,
+    R: FnOnce() -> Result<PendingPayment<T>, MutinyError>,
+{
+    let Some(key) = key else {
+        return payment.await;
+    };
+    let storage_key = idempotency_storage_key(key);
+    let now = utils::now().as_secs();
+    let request_hash = request_hash(request)?;
+
+    let mut record = match get_or_start::<S, T>(storage, &storage_key, request_hash, now)? {
+        Some(record) => match record.outcome.clone() {
+            IdempotentOutcome::Succeeded { result } => return Ok(result),
+            IdempotentOutcome::Pending => match reconcile()? {
+                PendingPayment::InFlight => return Err(MutinyError::PaymentInProgress),
+                PendingPayment::Succeeded(result) => {
+                    let record = IdempotencyRecord {
+                        outcome: IdempotentOutcome::Succeeded {
+                            result: result.clone(),
+                        },
+                        ..record
+                    };
+                    storage.set_data(storage_key, record, None)?;
+                    return Ok(result);
+                }
+                PendingPayment::Failed => restart(storage, &storage_key, &record, now)?,
+            },
+        },
+        None => IdempotencyRecord {
+            outcome: IdempotentOutcome::Pending,
+            request_hash: Some(request_hash),
+            created_at: now,
+        },
+    };
+
+    let res = payment.await;
+    match &res {
+        Ok(result) => {
+            record.outcome = IdempotentOutcome::Succeeded {
+                result: result.clone(),
+            };
+            storage.set_data(storage_key, &record, None)?;
+        }
+        Err(MutinyError::PaymentTimeout) => {}
+        Err(_) => storage.delete(&[storage_key])?,
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use serde_json::Value;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn in_flight() -> Result<PendingPayment<u64>, MutinyError> {
+        Ok(PendingPayment::InFlight)
+    }
+
+    #[test]
+    async fn test_with_idempotency() {
+        let storage = MemoryStorage::default();
+
+        // the retry returns the first result without paying again
+        let first = with_idempotency(&storage, Some("key"), &1, in_flight, async { Ok(1) }).await;
+        assert_eq!(first, Ok(1));
+        let retry = with_idempotency(&storage, Some("key"), &1, in_flight, async { Ok(2) }).await;
+        assert_eq!(retry, Ok(1));
+
+        // but not for a different request
+        let other = with_idempotency(&storage, Some("key"), &2, in_flight, async { Ok(2) }).await;
+        assert_eq!(other, Err(MutinyError::IdempotencyKeyReused));
+
+        // without a key every call pays
+        let res = with_idempotency(&storage, None, &1, in_flight, async { Ok(2) }).await;
+        assert_eq!(res, Ok(2));
+
+        // failed payments can be retried
+        let res: Result<u64, _> =
+            with_idempotency(&storage, Some("failed"), &1, in_flight, async {
+                Err(MutinyError::InsufficientBalance)
+            })
+            .await;
+        assert_eq!(res, Err(MutinyError::InsufficientBalance));
+        let res = with_idempotency(&storage, Some("failed"), &1, in_flight, async { Ok(3) }).await;
+        assert_eq!(res, Ok(3));
+
+        // timed out payments can still complete, so they aren't paid again
+        let res: Result<u64, _> =
+            with_idempotency(&storage, Some("timeout"), &1, in_flight, async {
+                Err(MutinyError::PaymentTimeout)
+            })
+            .await;
+        assert_eq!(res, Err(MutinyError::PaymentTimeout));
+        let res = with_idempotency(&storage, Some("timeout"), &1, in_flight, async { Ok(4) }).await;
+        assert_eq!(res, Err(MutinyError::PaymentInProgress));
+    }
+
+    #[test]
+    async fn test_pending_payments_are_reconciled() {
+        let storage = MemoryStorage::default();
+        let timeout = || async { Err(MutinyError::PaymentTimeout) };
+
+        // the timed out payment went through after all
+        let res: Result<u64, _> =
+            with_idempotency(&storage, Some("succeeded"), &1, in_flight, timeout()).await;
+        assert_eq!(res, Err(MutinyError::PaymentTimeout));
+        let succeeded = || Ok(PendingPayment::Succeeded(5));
+        let res = with_idempotency(&storage, Some("succeeded"), &1, succeeded, async { Ok(6) });
+        assert_eq!(res.await, Ok(5));
+        let res = with_idempotency(&storage, Some("succeeded"), &1, in_flight, async { Ok(6) });
+        assert_eq!(res.await, Ok(5));
+
+        // the timed out payment failed, so it is paid again
+        let res: Result<u64, _> =
+            with_idempotency(&storage, Some("failed"), &1, in_flight, timeout()).await;
+        assert_eq!(res, Err(MutinyError::PaymentTimeout));
+        let failed = || Ok(PendingPayment::Failed);
+        let res = with_idempotency(&storage, Some("failed"), &1, failed, async { Ok(7) });
+        assert_eq!(res.await, Ok(7));
+    }
+
+    #[test]
+    async fn test_expired_records_are_pruned() {
+        let storage = MemoryStorage::default();
+        let record = IdempotencyRecord::<u64> {
+            outcome: IdempotentOutcome::Succeeded { result: 1 },
+            request_hash: None,
+            created_at: 0,
+        };
+        let key = idempotency_storage_key("old");
+        storage.set_data(key.clone(), record, None).unwrap();
+
+        let res = with_idempotency(&storage, Some("new"), &1, in_flight, async { Ok(2) }).await;
+        assert_eq!(res, Ok(2));
+        assert!(storage.get_data::<Value>(&key).unwrap().is_none());
+    }
+}
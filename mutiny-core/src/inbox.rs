@@ -0,0 +1,280 @@
+use crate::error::MutinyError;
+use crate::nostr::client::NostrClient;
+use crate::nostr::nwc::{NwcProfileTag, PendingNwcInvoice};
+use crate::nostr::primal::PrimalApi;
+use crate::nostr::NostrManager;
+use crate::storage::MutinyStorage;
+use crate::InvoiceHandler;
+use bitcoin::hashes::sha256;
+use futures_util::lock::Mutex;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+/// Number of events we keep until they are taken, the oldest are dropped first
+const MAX_EVENTS: usize = 100;
+
+/// Where a payment request came from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaymentRequestSource {
+    /// A Nostr Wallet Connect profile that requires approval
+    Nwc { index: u32, name: String },
+    /// Renewal of our Mutiny+ subscription
+    SubscriptionRenewal { index: u32 },
+    /// Someone sent us an invoice in a direct message
+    DirectMessage { npub: nostr::PublicKey },
+}
+
+/// A payment waiting for the user to approve or reject it, see [PaymentRequestInbox]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// Payment hash of the invoice, used to approve or reject the request
+    pub id: sha256::Hash,
+    pub source: PaymentRequestSource,
+    pub invoice: Bolt11Invoice,
+    pub amount_sats: Option<u64>,
+    pub description: Option<String>,
+    /// Unix timestamp of when the invoice expires
+    pub expires_at: u64,
+}
+
+/// Something that happened in the [PaymentRequestInbox]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaymentRequestEvent {
+    /// A new payment request is waiting for approval
+    Received { request: PaymentRequest },
+}
+
+/// Collects the payments that need the user's approval before we send them:
+/// NWC requests over budget, invoices from DMs and subscription renewals.
+pub struct PaymentRequestInbox<S: MutinyStorage, P: PrimalApi, C: NostrClient> {
+    nostr: Arc<NostrManager<S, P, C>>,
+    /// Requests we already sent a [PaymentRequestEvent::Received] for
+    known: Mutex<HashSet<sha256::Hash>>,
+    events: Mutex<VecDeque<PaymentRequestEvent>>,
+}
+
+impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> PaymentRequestInbox<S, P, C> {
+    /// Requests that are already pending won't create events
+    pub(crate) fn new(nostr: Arc<NostrManager<S, P, C>>) -> Result<Self, MutinyError> {
+        let known = nostr
+            .get_pending_nwc_invoices()?
+            .iter()
+            .map(|inv| *inv.invoice.payment_hash())
+            .collect();
+
+        Ok(Self {
+            nostr,
+            known: Mutex::new(known),
+            events: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Lists the payment requests waiting for approval, expired ones are skipped
+    pub fn list_pending_requests(&self) -> Result<Vec<PaymentRequest>, MutinyError> {
+        let profiles = self.nostr.profiles();
+        let requests = self
+            .nostr
+            .get_pending_nwc_invoices()?
+            .into_iter()
+            .filter(|inv| !inv.is_expired())
+            .filter_map(|inv| {
+                let source = match inv.index {
+                    Some(index) => {
+                        // requests for deleted profiles can't be answered
+                        let profile = profiles.iter().find(|p| p.index == index)?;
+                        match profile.tag {
+                            NwcProfileTag::Subscription => {
+                                PaymentRequestSource::SubscriptionRenewal { index }
+                            }
+                            _ => PaymentRequestSource::Nwc {
+                                index,
+                                name: profile.name.clone(),
+                            },
+                        }
+                    }
+                    None => PaymentRequestSource::DirectMessage { npub: inv.pubkey },
+                };
+                Some(payment_request(inv, source))
+            })
+            .collect();
+
+        Ok(requests)
+    }
+
+    /// Pays the request and removes it from the inbox
+    pub async fn approve(
+        &self,
+        id: sha256::Hash,
+        invoice_handler: &impl InvoiceHandler,
+    ) -> Result<(), MutinyError> {
+        self.nostr.approve_invoice(id, invoice_handler).await?;
+        Ok(())
+    }
+
+    /// Removes the request from the inbox without paying it,
+    /// NWC clients are told it was rejected
+    pub async fn reject(&self, id: sha256::Hash) -> Result<(), MutinyError> {
+        self.nostr.deny_invoice(id).await
+    }
+
+    /// Queues a [PaymentRequestEvent::Received] for every request we haven't seen yet
+    pub(crate) async fn check_for_new_requests(&self) -> Result<(), MutinyError> {
+        let pending = self.list_pending_requests()?;
+
+        let mut known = self.known.lock().await;
+        let mut events = self.events.lock().await;
+        for request in pending.iter() {
+            if known.insert(request.id) {
+                queue_event(
+                    &mut events,
+                    PaymentRequestEvent::Received {
+                        request: request.clone(),
+                    },
+                );
+            }
+        }
+        // forget about requests that were approved, rejected or expired
+        known.retain(|id| pending.iter().any(|r| r.id == *id));
+
+        Ok(())
+    }
+
+    /// Returns the events since the last call, at most the last [MAX_EVENTS]
+    /// when they weren't taken in a while
+    pub async fn take_events(&self) -> Vec<PaymentRequestEvent> {
+        self.events.lock().await.drain(..).collect()
+    }
+}
+
+fn queue_event(events: &mut VecDeque<PaymentRequestEvent>, event: PaymentRequestEvent) {
+    if events.len() >= MAX_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+fn payment_request(inv: PendingNwcInvoice, source: PaymentRequestSource) -> PaymentRequest {
+    let description = match inv.invoice.description() {
+        Bolt11InvoiceDescription::Direct(desc) => Some(desc.to_string()),
+        Bolt11InvoiceDescription::Hash(_) => None,
+    };
+    let expires_at = (inv.invoice.duration_since_epoch() + inv.invoice.expiry_time()).as_secs();
+
+    PaymentRequest {
+        id: *inv.invoice.payment_hash(),
+        source,
+        amount_sats: inv.invoice.amount_milli_satoshis().map(|m| m / 1_000),
+        description,
+        expires_at,
+        invoice: inv.invoice,
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use crate::logging::MutinyLogger;
+    use crate::nostr::client::MockNostrClient;
+    use crate::nostr::primal::MockPrimalApi;
+    use crate::nostr::NostrKeySource;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::create_dummy_invoice;
+    use bip39::Mnemonic;
+    use bitcoin::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
+    use nostr::{EventId, Keys};
+    use std::str::FromStr;
+    use std::sync::atomic::AtomicBool;
+
+    async fn create_inbox() -> PaymentRequestInbox<MemoryStorage, MockPrimalApi, MockNostrClient> {
+        let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").expect("could not generate");
+        let xprivkey =
+            ExtendedPrivKey::new_master(Network::Bitcoin, &mnemonic.to_seed("")).unwrap();
+
+        #[allow(unused_mut)] // need this because of mockall
+        let mut client = MockNostrClient::new();
+        client.expect_set_signer().once().return_const(());
+
+        let nostr = NostrManager::from_mnemonic(
+            xprivkey,
+            NostrKeySource::Derived,
+            MemoryStorage::default(),
+            MockPrimalApi::new(),
+            client,
+            Arc::new(MutinyLogger::default()),
+            Arc::new(AtomicBool::new(false)),
+        )
+        .await
+        .unwrap();
+
+        PaymentRequestInbox::new(Arc::new(nostr)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_payment_request_inbox() {
+        let inbox = create_inbox().await;
+        assert!(inbox.list_pending_requests().unwrap().is_empty());
+
+        let (invoice, _) = create_dummy_invoice(Some(10_000_000), Network::Bitcoin, None);
+        let npub = Keys::generate().public_key();
+        let event_id = EventId::from_slice(&[0; 32]).unwrap();
+        inbox
+            .nostr
+            .save_pending_nwc_invoice(None, event_id, npub, invoice.clone(), None)
+            .await
+            .unwrap();
+
+        let pending = inbox.list_pending_requests().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, *invoice.payment_hash());
+        assert_eq!(
+            pending[0].source,
+            PaymentRequestSource::DirectMessage { npub }
+        );
+        assert_eq!(pending[0].amount_sats, Some(10_000));
+
+        // only new requests create events
+        inbox.check_for_new_requests().await.unwrap();
+        inbox.check_for_new_requests().await.unwrap();
+        let events = inbox.take_events().await;
+        assert_eq!(
+            events,
+            vec![PaymentRequestEvent::Received {
+                request: pending[0].clone()
+            }]
+        );
+        assert!(inbox.take_events().await.is_empty());
+
+        inbox.reject(*invoice.payment_hash()).await.unwrap();
+        assert!(inbox.list_pending_requests().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_events_are_capped() {
+        let mut events = VecDeque::new();
+        for i in 0..MAX_EVENTS as u64 + 5 {
+            let (invoice, _) = create_dummy_invoice(Some((i + 1) * 1_000), Network::Bitcoin, None);
+            let request = payment_request(
+                PendingNwcInvoice {
+                    index: None,
+                    invoice,
+                    event_id: EventId::from_slice(&[0; 32]).unwrap(),
+                    pubkey: Keys::generate().public_key(),
+                    identifier: None,
+                },
+                PaymentRequestSource::SubscriptionRenewal { index: 0 },
+            );
+            queue_event(&mut events, PaymentRequestEvent::Received { request });
+        }
+
+        assert_eq!(events.len(), MAX_EVENTS);
+        // the oldest ones were dropped
+        let PaymentRequestEvent::Received { request } = events.front().unwrap();
+        assert_eq!(request.amount_sats, Some(6));
+    }
+}
@@ -9,6 +9,9 @@ pub(crate) enum ChildKey {
     Node,
     Federation,
     BlindAuth,
+    Swap,
+    Backup,
+    AccountNode,
 }
 
 impl ChildKey {
@@ -17,6 +20,9 @@ impl ChildKey {
             ChildKey::Node => 0,
             ChildKey::Federation => 1,
             ChildKey::BlindAuth => 2,
+            ChildKey::Swap => 3,
+            ChildKey::Backup => 4,
+            ChildKey::AccountNode => 5,
         }
     }
 }
@@ -31,6 +37,24 @@ pub(crate) fn create_root_child_key(
     Ok(xprivkey.derive_priv(context, &DerivationPath::from(vec![child_number]))?)
 }
 
+/// Root of the node keys of the given account.
+///
+/// The first account keeps using `m/0'` so existing wallets derive the same nodes,
+/// other accounts use `m/5'/account'`.
+pub(crate) fn create_node_root_key(
+    context: &Secp256k1<bitcoin::secp256k1::All>,
+    xprivkey: ExtendedPrivKey,
+    account_index: u32,
+) -> Result<ExtendedPrivKey, MutinyError> {
+    if account_index == 0 {
+        return create_root_child_key(context, xprivkey, ChildKey::Node);
+    }
+
+    let root = create_root_child_key(context, xprivkey, ChildKey::AccountNode)?;
+    let account = ChildNumber::from_hardened_idx(account_index)?;
+    Ok(root.derive_priv(context, &DerivationPath::from(vec![account]))?)
+}
+
 #[cfg(test)]
 fn run_key_generation_tests() {
     use bip39::Mnemonic;
@@ -47,6 +71,15 @@ fn run_key_generation_tests() {
 
     let federation_root_key = create_root_child_key(&context, xpriv, ChildKey::Federation);
     assert_ne!(first_root_key, federation_root_key);
+
+    // the first account keeps the original node keys
+    let first_account_key = create_node_root_key(&context, xpriv, 0).unwrap();
+    assert_eq!(first_root_key.unwrap(), first_account_key);
+
+    let second_account_key = create_node_root_key(&context, xpriv, 1).unwrap();
+    let third_account_key = create_node_root_key(&context, xpriv, 2).unwrap();
+    assert_ne!(first_account_key, second_account_key);
+    assert_ne!(second_account_key, third_account_key);
 }
 
 #[cfg(test)]
@@ -1,8 +1,8 @@
+use crate::labels::LabelStorage;
 use crate::logging::MutinyLogger;
 use crate::onchain::OnChainWallet;
 use crate::storage::MutinyStorage;
-use crate::{error::MutinyError, key::create_root_child_key};
-use crate::{key::ChildKey, labels::LabelStorage};
+use crate::{error::MutinyError, key::create_node_root_key};
 use bdk::wallet::AddressIndex;
 use bip39::Mnemonic;
 use bitcoin::absolute::LockTime;
@@ -13,7 +13,7 @@ use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::ecdsa::RecoverableSignature;
 use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, Signing};
-use bitcoin::{ScriptBuf, Transaction, TxOut};
+use bitcoin::{Network, ScriptBuf, Transaction, TxOut};
 use lightning::ln::msgs::{DecodeError, UnsignedGossipMessage};
 use lightning::ln::script::ShutdownScript;
 use lightning::log_warn;
@@ -222,6 +222,19 @@ pub fn generate_seed(num_words: u8) -> Result<Mnemonic, MutinyError> {
     Ok(mnemonic)
 }
 
+/// Derives our root key from the mnemonic and its optional BIP-39 passphrase.
+///
+/// All of our keys, including the VSS and device lock keys, are derived from the root key,
+/// so each passphrase gives a separate hidden wallet. An empty passphrase is the same as none.
+pub fn xprivkey_from_mnemonic(
+    mnemonic: &Mnemonic,
+    passphrase: Option<&str>,
+    network: Network,
+) -> Result<ExtendedPrivKey, MutinyError> {
+    let seed = mnemonic.to_seed(passphrase.unwrap_or_default());
+    Ok(ExtendedPrivKey::new_master(network, &seed)?)
+}
+
 // A node private key will be derived from `m/0'/X'`, where its node pubkey will
 // be derived from the LDK default being `m/0'/X'/0'`. The PhantomKeysManager shared
 // key secret will be derived from `m/0'`.
@@ -233,7 +246,7 @@ pub(crate) fn create_keys_manager<S: MutinyStorage>(
 ) -> Result<PhantomKeysManager<S>, MutinyError> {
     let context = Secp256k1::new();
 
-    let shared_key = create_root_child_key(&context, xprivkey, ChildKey::Node)?;
+    let shared_key = create_node_root_key(&context, xprivkey, wallet.account_index)?;
 
     let xpriv = shared_key.derive_priv(
         &context,
@@ -290,7 +303,9 @@ mod tests {
         encrypt::encryption_key_from_pass, keymanager::pubkey_from_keys_manager, test_utils::*,
     };
 
-    use super::{create_keys_manager, deterministic_uuid_from_keys_manager};
+    use super::{
+        create_keys_manager, deterministic_uuid_from_keys_manager, xprivkey_from_mnemonic,
+    };
     use crate::fees::MutinyFeeEstimator;
     use crate::logging::MutinyLogger;
     use crate::onchain::OnChainWallet;
@@ -328,7 +343,7 @@ mod tests {
         let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
 
         let wallet = Arc::new(
-            OnChainWallet::new(xpriv, db, network, esplora, fees, stop, logger.clone()).unwrap(),
+            OnChainWallet::new(xpriv, db, network, 0, esplora, fees, stop, logger.clone()).unwrap(),
         );
 
         let km = create_keys_manager(wallet.clone(), xpriv, 1, logger.clone()).unwrap();
@@ -376,7 +391,7 @@ mod tests {
         let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
 
         let wallet = Arc::new(
-            OnChainWallet::new(xpriv, db, network, esplora, fees, stop, logger.clone()).unwrap(),
+            OnChainWallet::new(xpriv, db, network, 0, esplora, fees, stop, logger.clone()).unwrap(),
         );
 
         let km = create_keys_manager(wallet.clone(), xpriv, 1, logger.clone()).unwrap();
@@ -395,4 +410,31 @@ mod tests {
 
         assert_eq!(second_uuid, second_uuid_again);
     }
+
+    #[test]
+    fn test_xprivkey_from_mnemonic() {
+        let test_name = "test_xprivkey_from_mnemonic";
+        log!("{}", test_name);
+
+        let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").unwrap();
+        let network = Network::Bitcoin;
+
+        // test vector from BIP-39
+        let xpriv = xprivkey_from_mnemonic(&mnemonic, Some("TREZOR"), network).unwrap();
+        assert_eq!(
+            xpriv.to_string(),
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"
+        );
+
+        let no_passphrase = xprivkey_from_mnemonic(&mnemonic, None, network).unwrap();
+        assert_ne!(xpriv, no_passphrase);
+        assert_eq!(
+            no_passphrase,
+            xprivkey_from_mnemonic(&mnemonic, Some(""), network).unwrap()
+        );
+        assert_eq!(
+            no_passphrase,
+            ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap()
+        );
+    }
 }
@@ -1,21 +1,26 @@
 use crate::error::MutinyError;
 use crate::nodemanager::NodeManager;
+use crate::search::{
+    address_fields, contact_fields, reindex_invoice, update_search_index, ADDRESS_SEARCH_PREFIX,
+};
 use crate::storage::MutinyStorage;
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::Address;
 use lightning_invoice::Bolt11Invoice;
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
 use nostr::Metadata;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
 use uuid::Uuid;
 
-const ADDRESS_LABELS_MAP_KEY: &str = "address_labels";
-const INVOICE_LABELS_MAP_KEY: &str = "invoice_labels";
-const LABEL_PREFIX: &str = "label/";
-const CONTACT_PREFIX: &str = "contact/";
+pub(crate) const ADDRESS_LABELS_MAP_KEY: &str = "address_labels";
+pub(crate) const INVOICE_LABELS_MAP_KEY: &str = "invoice_labels";
+pub(crate) const LABEL_PREFIX: &str = "label/";
+pub(crate) const CONTACT_PREFIX: &str = "contact/";
+pub(crate) const CONTACT_GROUP_PREFIX: &str = "contact_group/";
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Default)]
 pub struct LabelItem {
@@ -38,6 +43,9 @@ pub struct Contact {
     pub lnurl: Option<LnUrl>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_url: Option<String>,
+    /// The lightning node of the contact, to keysend to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_pubkey: Option<PublicKey>,
     pub last_used: u64,
 }
 
@@ -92,12 +100,35 @@ impl Contact {
     }
 }
 
+/// A named group of contacts, to split payments with for example
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ContactGroup {
+    pub name: String,
+    /// The ids of the contacts in the group
+    pub members: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum TagItem {
     Label((String, LabelItem)),
     Contact((String, Contact)),
 }
 
+/// The last version given out by [vss_version]
+static LAST_VSS_VERSION: AtomicU32 = AtomicU32::new(0);
+
+/// Labels and contacts have no version, it follows the time so VSS keeps the last write
+/// and [crate::conflicts::reconcile_vss_key] merges changes made on other devices.
+/// It always goes up so a second write in the same second isn't dropped by VSS.
+pub(crate) fn vss_version() -> Option<u32> {
+    let now = crate::utils::now().as_secs() as u32;
+    let next = |last: u32| now.max(last.saturating_add(1));
+    let last = LAST_VSS_VERSION
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(next(last)))
+        .unwrap_or_else(|last| last);
+    Some(next(last))
+}
+
 pub(crate) fn get_label_item_key(label: impl AsRef<str>) -> String {
     format!("{}{}", LABEL_PREFIX, label.as_ref())
 }
@@ -106,6 +137,24 @@ pub(crate) fn get_contact_key(label: impl AsRef<str>) -> String {
     format!("{}{}", CONTACT_PREFIX, label.as_ref())
 }
 
+pub(crate) fn get_contact_group_key(id: impl AsRef<str>) -> String {
+    format!("{}{}", CONTACT_GROUP_PREFIX, id.as_ref())
+}
+
+/// Makes sure the members of the group are contacts we have, without duplicates
+fn validate_group_members<S: LabelStorage>(
+    storage: &S,
+    group: &ContactGroup,
+) -> Result<(), MutinyError> {
+    let contacts = storage.get_contacts()?;
+    let unique: HashSet<&String> = group.members.iter().collect();
+    if unique.len() != group.members.len() || unique.iter().any(|m| !contacts.contains_key(*m)) {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    Ok(())
+}
+
 pub trait LabelStorage {
     /// Get a map of addresses to labels. This can be used to get all the labels for an address
     fn get_address_labels(&self) -> Result<HashMap<String, Vec<String>>, MutinyError>;
@@ -145,6 +194,20 @@ pub trait LabelStorage {
     fn edit_contact(&self, id: impl AsRef<str>, contact: Contact) -> Result<(), MutinyError>;
     /// Gets all the existing tags (labels and contacts)
     fn get_tag_items(&self) -> Result<Vec<TagItem>, MutinyError>;
+    /// Get all the contact groups by their id
+    fn get_contact_groups(&self) -> Result<HashMap<String, ContactGroup>, MutinyError>;
+    /// Get a contact group by its id
+    fn get_contact_group(&self, id: impl AsRef<str>) -> Result<Option<ContactGroup>, MutinyError>;
+    /// Create a new contact group of existing contacts and return its id
+    fn create_contact_group(&self, group: ContactGroup) -> Result<String, MutinyError>;
+    /// Edits an existing contact group and replaces the existing group
+    fn edit_contact_group(
+        &self,
+        id: impl AsRef<str>,
+        group: ContactGroup,
+    ) -> Result<(), MutinyError>;
+    /// Deletes a contact group, its contacts are kept
+    fn delete_contact_group(&self, id: impl AsRef<str>) -> Result<(), MutinyError>;
     /// Finds a contact that has the given lnurl as either a lnurl or a lightning address
     fn get_contact_for_lnurl(&self, lnurl: &LnUrl) -> Result<Option<String>, MutinyError> {
         let contacts = self.get_contacts()?;
@@ -204,7 +267,15 @@ impl<S: MutinyStorage> LabelStorage for S {
         // update the labels map
         let mut address_labels = self.get_address_labels()?;
         address_labels.insert(address.to_string(), labels.clone());
-        self.set_data(ADDRESS_LABELS_MAP_KEY.to_string(), address_labels, None)?;
+        self.set_data(
+            ADDRESS_LABELS_MAP_KEY.to_string(),
+            address_labels,
+            vss_version(),
+        )?;
+        update_search_index(self, |index| {
+            let fields = address_fields(&address.to_string(), labels.clone());
+            index.update(&format!("{ADDRESS_SEARCH_PREFIX}{address}"), &fields)
+        })?;
 
         // update the label items
         let now = crate::utils::now().as_secs();
@@ -226,7 +297,7 @@ impl<S: MutinyStorage> LabelStorage for S {
                         self.edit_contact(&label, contact)?;
                     }
 
-                    self.set_data(key, label_item, None)?;
+                    self.set_data(key, label_item, vss_version())?;
                 }
                 None => {
                     let mut addresses = HashSet::with_capacity(1);
@@ -237,7 +308,7 @@ impl<S: MutinyStorage> LabelStorage for S {
                         invoices: HashSet::new(),
                         last_used_time: now,
                     };
-                    self.set_data(key, label_item, None)?;
+                    self.set_data(key, label_item, vss_version())?;
                 }
             }
         }
@@ -253,7 +324,12 @@ impl<S: MutinyStorage> LabelStorage for S {
         // update the labels map
         let mut invoice_labels = self.get_invoice_labels()?;
         invoice_labels.insert(invoice.clone(), labels.clone());
-        self.set_data(INVOICE_LABELS_MAP_KEY.to_string(), invoice_labels, None)?;
+        self.set_data(
+            INVOICE_LABELS_MAP_KEY.to_string(),
+            invoice_labels,
+            vss_version(),
+        )?;
+        reindex_invoice(self, &invoice)?;
 
         // update the label items
         let now = crate::utils::now().as_secs();
@@ -275,7 +351,7 @@ impl<S: MutinyStorage> LabelStorage for S {
                         self.edit_contact(&label, contact)?;
                     }
 
-                    self.set_data(key, label_item, None)?;
+                    self.set_data(key, label_item, vss_version())?;
                 }
                 None => {
                     // Create a new label item
@@ -285,7 +361,7 @@ impl<S: MutinyStorage> LabelStorage for S {
                         invoices,
                         last_used_time: now,
                     };
-                    self.set_data(key, label_item, None)?;
+                    self.set_data(key, label_item, vss_version())?;
                 }
             }
         }
@@ -320,7 +396,7 @@ impl<S: MutinyStorage> LabelStorage for S {
                 // convert label into a uuid for uniqueness
                 let id = Uuid::new_v4().to_string();
                 // create label item
-                self.set_data(get_label_item_key(&id), current, None)?;
+                self.set_data(get_label_item_key(&id), current, vss_version())?;
 
                 // replace label in address_labels with new uuid
                 let addr_labels = self.get_address_labels()?;
@@ -337,7 +413,13 @@ impl<S: MutinyStorage> LabelStorage for S {
                         updated.insert(addr, new_labels);
                     }
                 }
-                self.set_data(ADDRESS_LABELS_MAP_KEY.to_string(), updated, None)?;
+                update_search_index(self, |index| {
+                    for (address, labels) in updated.iter() {
+                        let fields = address_fields(address, labels.clone());
+                        index.update(&format!("{ADDRESS_SEARCH_PREFIX}{address}"), &fields);
+                    }
+                })?;
+                self.set_data(ADDRESS_LABELS_MAP_KEY.to_string(), updated, vss_version())?;
 
                 // replace label in invoice_labels with new uuid
                 let invoice_labels = self.get_invoice_labels()?;
@@ -354,11 +436,19 @@ impl<S: MutinyStorage> LabelStorage for S {
                         updated.insert(inv, new_labels);
                     }
                 }
-                self.set_data(INVOICE_LABELS_MAP_KEY.to_string(), updated, None)?;
+                self.set_data(
+                    INVOICE_LABELS_MAP_KEY.to_string(),
+                    updated.clone(),
+                    vss_version(),
+                )?;
+                for invoice in updated.keys() {
+                    reindex_invoice(self, invoice)?;
+                }
 
                 // create the contact
                 let key = get_contact_key(&id);
-                self.set_data(key, contact, None)?;
+                update_search_index(self, |index| index.update(&key, &contact_fields(&contact)))?;
+                self.set_data(key, contact, vss_version())?;
 
                 // delete old label item
                 self.delete(&[get_label_item_key(&label)])?;
@@ -372,14 +462,15 @@ impl<S: MutinyStorage> LabelStorage for S {
         // generate a uuid, this will be the "label" that we use to store the contact
         let id = Uuid::new_v4().to_string();
         let key = get_contact_key(&id);
-        self.set_data(key, contact, None)?;
+        update_search_index(self, |index| index.update(&key, &contact_fields(&contact)))?;
+        self.set_data(key, contact, vss_version())?;
 
         let key = get_label_item_key(&id);
         let label_item = LabelItem {
             last_used_time: crate::utils::now().as_secs(),
             ..Default::default()
         };
-        self.set_data(key, label_item, None)?;
+        self.set_data(key, label_item, vss_version())?;
         Ok(id)
     }
 
@@ -393,27 +484,37 @@ impl<S: MutinyStorage> LabelStorage for S {
         for value in addr_labels.values_mut() {
             value.retain(|s| *s != id.as_ref());
         }
-        let to_set: Vec<(String, Value)> = vec![
-            (
-                ADDRESS_LABELS_MAP_KEY.to_string(),
-                serde_json::to_value(addr_labels)?,
-            ),
-            (
-                INVOICE_LABELS_MAP_KEY.to_string(),
-                serde_json::to_value(inv_labels)?,
-            ),
-        ];
-        self.set(to_set)?;
+        self.set_data(
+            ADDRESS_LABELS_MAP_KEY.to_string(),
+            addr_labels,
+            vss_version(),
+        )?;
+        self.set_data(
+            INVOICE_LABELS_MAP_KEY.to_string(),
+            inv_labels,
+            vss_version(),
+        )?;
+
+        // and from the groups it is in
+        for (group_id, mut group) in self.get_contact_groups()? {
+            if group.members.iter().any(|m| m == id.as_ref()) {
+                group.members.retain(|m| m != id.as_ref());
+                self.set_data(get_contact_group_key(&group_id), group, vss_version())?;
+            }
+        }
 
         // then delete actual label
         let contact_key = get_contact_key(&id);
         let label_item_key = get_label_item_key(&id);
+        update_search_index(self, |index| index.remove(&contact_key))?;
         self.delete(&[contact_key, label_item_key])?;
         Ok(())
     }
 
     fn edit_contact(&self, id: impl AsRef<str>, contact: Contact) -> Result<(), MutinyError> {
-        self.set_data(get_contact_key(&id), contact, None)
+        let key = get_contact_key(&id);
+        update_search_index(self, |index| index.update(&key, &contact_fields(&contact)))?;
+        self.set_data(key, contact, vss_version())
     }
 
     fn get_tag_items(&self) -> Result<Vec<TagItem>, MutinyError> {
@@ -443,6 +544,46 @@ impl<S: MutinyStorage> LabelStorage for S {
 
         Ok(tag_items)
     }
+
+    fn get_contact_groups(&self) -> Result<HashMap<String, ContactGroup>, MutinyError> {
+        let all = self.scan::<ContactGroup>(CONTACT_GROUP_PREFIX, None)?;
+        // remove the prefix from the keys
+        let groups = all
+            .into_iter()
+            .map(|(key, group)| (key.replace(CONTACT_GROUP_PREFIX, ""), group))
+            .collect();
+
+        Ok(groups)
+    }
+
+    fn get_contact_group(&self, id: impl AsRef<str>) -> Result<Option<ContactGroup>, MutinyError> {
+        self.get_data(get_contact_group_key(id))
+    }
+
+    fn create_contact_group(&self, group: ContactGroup) -> Result<String, MutinyError> {
+        validate_group_members(self, &group)?;
+
+        let id = Uuid::new_v4().to_string();
+        self.set_data(get_contact_group_key(&id), group, vss_version())?;
+        Ok(id)
+    }
+
+    fn edit_contact_group(
+        &self,
+        id: impl AsRef<str>,
+        group: ContactGroup,
+    ) -> Result<(), MutinyError> {
+        if self.get_contact_group(&id)?.is_none() {
+            return Err(MutinyError::NotFound);
+        }
+        validate_group_members(self, &group)?;
+
+        self.set_data(get_contact_group_key(&id), group, vss_version())
+    }
+
+    fn delete_contact_group(&self, id: impl AsRef<str>) -> Result<(), MutinyError> {
+        self.delete(&[get_contact_group_key(id)])
+    }
 }
 
 impl<S: MutinyStorage> LabelStorage for NodeManager<S> {
@@ -505,6 +646,30 @@ impl<S: MutinyStorage> LabelStorage for NodeManager<S> {
     fn get_tag_items(&self) -> Result<Vec<TagItem>, MutinyError> {
         self.storage.get_tag_items()
     }
+
+    fn get_contact_groups(&self) -> Result<HashMap<String, ContactGroup>, MutinyError> {
+        self.storage.get_contact_groups()
+    }
+
+    fn get_contact_group(&self, id: impl AsRef<str>) -> Result<Option<ContactGroup>, MutinyError> {
+        self.storage.get_contact_group(id)
+    }
+
+    fn create_contact_group(&self, group: ContactGroup) -> Result<String, MutinyError> {
+        self.storage.create_contact_group(group)
+    }
+
+    fn edit_contact_group(
+        &self,
+        id: impl AsRef<str>,
+        group: ContactGroup,
+    ) -> Result<(), MutinyError> {
+        self.storage.edit_contact_group(id, group)
+    }
+
+    fn delete_contact_group(&self, id: impl AsRef<str>) -> Result<(), MutinyError> {
+        self.storage.delete_contact_group(id)
+    }
 }
 
 #[cfg(test)]
@@ -521,6 +686,14 @@ mod tests {
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
     wasm_bindgen_test_configure!(run_in_browser);
 
+    #[test]
+    fn test_vss_version_always_goes_up() {
+        let first = vss_version().unwrap();
+        let second = vss_version().unwrap();
+        assert!(second > first);
+        assert!(first >= crate::utils::now().as_secs() as u32 - 1);
+    }
+
     const ADDRESS: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
     const INVOICE: &str = "lnbc923720n1pj9nr6zpp5xmvlq2u5253htn52mflh2e6gn7pk5ht0d4qyhc62fadytccxw7hqhp5l4s6qwh57a7cwr7zrcz706qx0qy4eykcpr8m8dwz08hqf362egfscqzzsxqzfvsp5pr7yjvcn4ggrf6fq090zey0yvf8nqvdh2kq7fue0s0gnm69evy6s9qyyssqjyq0fwjr22eeg08xvmz88307yqu8tqqdjpycmermks822fpqyxgshj8hvnl9mkh6srclnxx0uf4ugfq43d66ak3rrz4dqcqd23vxwpsqf7dmhm";
 
@@ -600,6 +773,7 @@ mod tests {
                 ln_address: None,
                 lnurl: None,
                 image_url: None,
+                node_pubkey: None,
                 last_used: 0,
             },
         );
@@ -611,6 +785,7 @@ mod tests {
                 ln_address: None,
                 lnurl: None,
                 image_url: None,
+                node_pubkey: None,
                 last_used: 0,
             },
         );
@@ -622,6 +797,7 @@ mod tests {
                 ln_address: None,
                 lnurl: None,
                 image_url: None,
+                node_pubkey: None,
                 last_used: 0,
             },
         );
@@ -775,6 +951,7 @@ mod tests {
             ln_address: None,
             lnurl: None,
             image_url: None,
+            node_pubkey: None,
             last_used: 0,
         };
         let id = storage.create_new_contact(contact.clone()).unwrap();
@@ -796,6 +973,7 @@ mod tests {
             ln_address: None,
             lnurl: None,
             image_url: None,
+            node_pubkey: None,
             last_used: 0,
         };
         let id = storage.create_new_contact(contact).unwrap();
@@ -821,6 +999,7 @@ mod tests {
             ln_address: None,
             lnurl: None,
             image_url: None,
+            node_pubkey: None,
             last_used: 0,
         };
         let id = storage.create_new_contact(contact).unwrap();
@@ -1019,4 +1198,46 @@ mod tests {
         let contact = storage.get_contact(&id).unwrap().unwrap();
         assert_ne!(contact.last_used, 0)
     }
+
+    #[test]
+    fn test_contact_groups() {
+        let test_name = "test_contact_groups";
+        log!("{test_name}");
+
+        let storage = MemoryStorage::default();
+
+        let contacts = create_test_contacts();
+        let ids: Vec<String> = contacts
+            .values()
+            .map(|c| storage.create_new_contact(c.clone()).unwrap())
+            .collect();
+
+        let group = ContactGroup {
+            name: "Dinner".to_string(),
+            members: ids.clone(),
+        };
+        let group_id = storage.create_contact_group(group.clone()).unwrap();
+        assert_eq!(storage.get_contact_group(&group_id).unwrap(), Some(group));
+
+        // members have to be contacts, once
+        let unknown = ContactGroup {
+            name: "Unknown".to_string(),
+            members: vec!["not a contact".to_string()],
+        };
+        assert!(storage.create_contact_group(unknown).is_err());
+        let duplicate = ContactGroup {
+            name: "Duplicate".to_string(),
+            members: vec![ids[0].clone(), ids[0].clone()],
+        };
+        assert!(storage.edit_contact_group(&group_id, duplicate).is_err());
+
+        // deleted contacts leave their groups
+        storage.delete_contact(&ids[0]).unwrap();
+        let group = storage.get_contact_group(&group_id).unwrap().unwrap();
+        assert_eq!(group.members, ids[1..].to_vec());
+
+        storage.delete_contact_group(&group_id).unwrap();
+        assert!(storage.get_contact_groups().unwrap().is_empty());
+        assert!(storage.get_contact(&ids[1]).unwrap().is_some());
+    }
 }
@@ -0,0 +1,338 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hashes::sha256;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+pub(crate) const PAYMENT_LATENCY_PREFIX_KEY: &str = "payment_latency/";
+/// Most payment latencies we keep, the oldest are removed past this
+const MAX_PAYMENT_LATENCIES: usize = 500;
+/// Payment latencies older than this are removed, 30 days
+const PAYMENT_LATENCY_MAX_AGE_SECS: u64 = 60 * 60 * 24 * 30;
+
+/// A stage of the payment pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PaymentStage {
+    /// Validating the invoice and its amount
+    Decode,
+    /// Picking a federation or the lightning node to pay with
+    RailSelection,
+    /// Waiting for a usable channel and a connection to our peers, through the proxy and LSP
+    Connect,
+    /// Finding a route and sending the HTLC
+    RouteFind,
+    /// Waiting for the payment to succeed or fail
+    Settle,
+}
+
+/// What the payment was made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PaymentRail {
+    Federation,
+    Lightning,
+}
+
+impl fmt::Display for PaymentRail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Federation => write!(f, "Federation"),
+            Self::Lightning => write!(f, "Lightning"),
+        }
+    }
+}
+
+/// Timing breakdown of a single outbound payment
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentLatency {
+    pub payment_hash: String,
+    /// The rail the payment was last attempted with
+    pub rail: Option<PaymentRail>,
+    pub success: bool,
+    pub decode_ms: Option<u64>,
+    pub rail_selection_ms: Option<u64>,
+    pub connect_ms: Option<u64>,
+    pub route_find_ms: Option<u64>,
+    pub settle_ms: Option<u64>,
+    pub total_ms: u64,
+    /// Unix timestamp of when the payment finished
+    pub timestamp: u64,
+}
+
+impl PaymentLatency {
+    pub fn stage_ms(&self, stage: PaymentStage) -> Option<u64> {
+        match stage {
+            PaymentStage::Decode => self.decode_ms,
+            PaymentStage::RailSelection => self.rail_selection_ms,
+            PaymentStage::Connect => self.connect_ms,
+            PaymentStage::RouteFind => self.route_find_ms,
+            PaymentStage::Settle => self.settle_ms,
+        }
+    }
+
+    fn stage_ms_mut(&mut self, stage: PaymentStage) -> &mut Option<u64> {
+        match stage {
+            PaymentStage::Decode => &mut self.decode_ms,
+            PaymentStage::RailSelection => &mut self.rail_selection_ms,
+            PaymentStage::Connect => &mut self.connect_ms,
+            PaymentStage::RouteFind => &mut self.route_find_ms,
+            PaymentStage::Settle => &mut self.settle_ms,
+        }
+    }
+}
+
+/// Tracks how long each stage of a payment takes as it moves through the pipeline
+#[derive(Debug, Clone)]
+pub(crate) struct PaymentTrace {
+    start: Instant,
+    last: Instant,
+    latency: PaymentLatency,
+}
+
+impl PaymentTrace {
+    pub fn new(payment_hash: &sha256::Hash) -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last: now,
+            latency: PaymentLatency {
+                payment_hash: payment_hash.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Attributes the time since the last mark to the given stage.
+    /// Stages that happen more than once, like retrying with another federation, are summed.
+    pub fn mark(&mut self, stage: PaymentStage) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_millis() as u64;
+        let ms = self.latency.stage_ms_mut(stage);
+        *ms = Some(ms.unwrap_or(0) + elapsed);
+        self.last = now;
+    }
+
+    pub fn set_rail(&mut self, rail: PaymentRail) {
+        self.latency.rail = Some(rail);
+    }
+
+    pub fn finish(mut self, success: bool) -> PaymentLatency {
+        self.latency.success = success;
+        self.latency.total_ms = self.start.elapsed().as_millis() as u64;
+        self.latency.timestamp = utils::now().as_secs();
+        self.latency
+    }
+}
+
+pub(crate) fn persist_payment_latency<S: MutinyStorage>(
+    storage: &S,
+    latency: &PaymentLatency,
+) -> Result<(), MutinyError> {
+    let key = format!("{PAYMENT_LATENCY_PREFIX_KEY}{}", latency.payment_hash);
+    storage.set_data(key, latency, None)?;
+    prune_payment_latencies(
+        storage,
+        MAX_PAYMENT_LATENCIES,
+        latency
+            .timestamp
+            .saturating_sub(PAYMENT_LATENCY_MAX_AGE_SECS),
+    )
+}
+
+/// Removes the latencies from before `cutoff` and the oldest past `max` so they don't grow forever
+fn prune_payment_latencies<S: MutinyStorage>(
+    storage: &S,
+    max: usize,
+    cutoff: u64,
+) -> Result<(), MutinyError> {
+    let mut latencies: Vec<(String, u64)> = storage
+        .scan::<PaymentLatency>(PAYMENT_LATENCY_PREFIX_KEY, None)?
+        .into_iter()
+        .map(|(k, l)| (k, l.timestamp))
+        .collect();
+    latencies.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let to_delete: Vec<String> = latencies
+        .into_iter()
+        .enumerate()
+        .filter(|(i, (_, timestamp))| *i >= max || *timestamp < cutoff)
+        .map(|(_, (k, _))| k)
+        .collect();
+    if to_delete.is_empty() {
+        return Ok(());
+    }
+    storage.delete(&to_delete)
+}
+
+pub(crate) fn get_payment_latencies<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PaymentLatency>, MutinyError> {
+    let map = storage.scan::<PaymentLatency>(PAYMENT_LATENCY_PREFIX_KEY, None)?;
+    Ok(map.into_values().collect())
+}
+
+/// Aggregate timings for one stage of the payment pipeline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageStats {
+    /// Number of payments that went through this stage
+    pub count: usize,
+    pub avg_ms: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+impl StageStats {
+    fn from_samples(mut samples: Vec<u64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+
+        let count = samples.len();
+        let percentile = |p: usize| samples[((count - 1) * p) / 100];
+        Some(Self {
+            count,
+            avg_ms: samples.iter().sum::<u64>() / count as u64,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms: samples[count - 1],
+        })
+    }
+}
+
+/// Aggregate payment latency, broken down by stage, see [crate::MutinyWallet::get_latency_stats]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub payments: usize,
+    pub failed: usize,
+    pub decode: Option<StageStats>,
+    pub rail_selection: Option<StageStats>,
+    pub connect: Option<StageStats>,
+    pub route_find: Option<StageStats>,
+    pub settle: Option<StageStats>,
+    pub total: Option<StageStats>,
+}
+
+impl LatencyStats {
+    pub(crate) fn from_latencies(latencies: &[PaymentLatency]) -> Self {
+        let stage = |stage: PaymentStage| {
+            let samples = latencies.iter().filter_map(|l| l.stage_ms(stage)).collect();
+            StageStats::from_samples(samples)
+        };
+
+        Self {
+            payments: latencies.len(),
+            failed: latencies.iter().filter(|l| !l.success).count(),
+            decode: stage(PaymentStage::Decode),
+            rail_selection: stage(PaymentStage::RailSelection),
+            connect: stage(PaymentStage::Connect),
+            route_find: stage(PaymentStage::RouteFind),
+            settle: stage(PaymentStage::Settle),
+            total: StageStats::from_samples(latencies.iter().map(|l| l.total_ms).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use bitcoin::hashes::Hash;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn latency(settle_ms: Option<u64>, total_ms: u64, success: bool) -> PaymentLatency {
+        PaymentLatency {
+            payment_hash: sha256::Hash::hash(&total_ms.to_be_bytes()).to_string(),
+            rail: Some(PaymentRail::Lightning),
+            success,
+            decode_ms: Some(1),
+            settle_ms,
+            total_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_trace_sums_repeated_stages() {
+        let mut trace = PaymentTrace::new(&sha256::Hash::all_zeros());
+        trace.mark(PaymentStage::Decode);
+        trace.mark(PaymentStage::Settle);
+        trace.mark(PaymentStage::Settle);
+        trace.set_rail(PaymentRail::Federation);
+        let latency = trace.finish(true);
+
+        assert!(latency.success);
+        assert_eq!(latency.rail, Some(PaymentRail::Federation));
+        assert!(latency.decode_ms.is_some());
+        assert!(latency.settle_ms.is_some());
+        assert!(latency.connect_ms.is_none());
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let latencies: Vec<PaymentLatency> = (1..=20)
+            .map(|i| latency(Some(i * 100), i * 100 + 1, i != 20))
+            .collect();
+
+        let stats = LatencyStats::from_latencies(&latencies);
+        assert_eq!(stats.payments, 20);
+        assert_eq!(stats.failed, 1);
+        assert!(stats.connect.is_none());
+
+        let settle = stats.settle.unwrap();
+        assert_eq!(settle.count, 20);
+        assert_eq!(settle.avg_ms, 1_050);
+        assert_eq!(settle.p50_ms, 1_000);
+        assert_eq!(settle.p95_ms, 1_900);
+        assert_eq!(settle.max_ms, 2_000);
+
+        assert_eq!(LatencyStats::from_latencies(&[]), LatencyStats::default());
+    }
+
+    #[test]
+    fn test_persist_latency() {
+        let storage = MemoryStorage::default();
+        let l = latency(Some(5), 10, true);
+        persist_payment_latency(&storage, &l).unwrap();
+
+        assert_eq!(get_payment_latencies(&storage).unwrap(), vec![l]);
+    }
+
+    #[test]
+    fn test_prune_latencies() {
+        let storage = MemoryStorage::default();
+        for i in 1..=5 {
+            let mut l = latency(None, i, true);
+            l.timestamp = i * 100;
+            let key = format!("{PAYMENT_LATENCY_PREFIX_KEY}{}", l.payment_hash);
+            storage.set_data(key, l, None).unwrap();
+        }
+
+        // too old
+        prune_payment_latencies(&storage, 10, 150).unwrap();
+        let mut timestamps: Vec<u64> = get_payment_latencies(&storage)
+            .unwrap()
+            .iter()
+            .map(|l| l.timestamp)
+            .collect();
+        timestamps.sort();
+        assert_eq!(timestamps, vec![200, 300, 400, 500]);
+
+        // too many, the newest are kept
+        prune_payment_latencies(&storage, 2, 0).unwrap();
+        let mut timestamps: Vec<u64> = get_payment_latencies(&storage)
+            .unwrap()
+            .iter()
+            .map(|l| l.timestamp)
+            .collect();
+        timestamps.sort();
+        assert_eq!(timestamps, vec![400, 500]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::chain::{ChainSource, MutinyChain};
 use crate::error::{MutinyError, MutinyStorageError};
 use crate::fees::MutinyFeeEstimator;
 use crate::gossip::PROB_SCORER_KEY;
@@ -5,16 +6,16 @@ use crate::keymanager::PhantomKeysManager;
 use crate::logging::MutinyLogger;
 use crate::node::{default_user_config, ChainMonitor};
 use crate::node::{NetworkGraph, Router};
-use crate::nodemanager::ChannelClosure;
-use crate::storage::{IndexItem, MutinyStorage, VersionedValue};
+use crate::nodemanager::{ChannelClosure, ChannelLifecycle, ChannelLifecycleState, ChannelPolicy};
+use crate::scorer::HubPreferentialScorer;
+use crate::search::{closure_fields, update_search_index};
+use crate::storage::{decrypt_value, IndexItem, MutinyStorage, VersionedValue};
 use crate::utils;
 use crate::utils::{sleep, spawn};
-use crate::{chain::MutinyChain, scorer::HubPreferentialScorer};
 use anyhow::anyhow;
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::Network;
-use bitcoin::{BlockHash, Transaction};
-use esplora_client::AsyncClient;
+use bitcoin::{BlockHash, Transaction, Txid};
 use futures::{try_join, TryFutureExt};
 use futures_util::lock::Mutex;
 use hex_conservative::DisplayHex;
@@ -24,6 +25,7 @@ use lightning::io::Cursor;
 use lightning::ln::channelmanager::{
     self, ChainParameters, ChannelManager as LdkChannelManager, ChannelManagerReadArgs,
 };
+use lightning::ln::ChannelId;
 use lightning::sign::{InMemorySigner, SpendableOutputDescriptor};
 use lightning::util::logger::Logger;
 use lightning::util::persist::Persister;
@@ -38,16 +40,34 @@ use lightning::{
 };
 use lightning::{log_debug, log_error};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 pub const CHANNEL_MANAGER_KEY: &str = "manager";
 pub const MONITORS_PREFIX_KEY: &str = "monitors/";
 const CHANNEL_OPENING_PARAMS_PREFIX: &str = "chan_open_params/";
+const CHANNEL_POLICY_PREFIX: &str = "channel_policy/";
 pub const CHANNEL_CLOSURE_PREFIX: &str = "channel_closure/";
+const CHANNEL_LIFECYCLE_PREFIX: &str = "channel_lifecycle/";
 const FAILED_SPENDABLE_OUTPUT_DESCRIPTOR_KEY: &str = "failed_spendable_outputs";
+const SPENDABLE_OUTPUT_SWEEPS_KEY: &str = "spendable_output_sweeps";
+const CLOSE_FEE_RATE_PREFIX: &str = "close_fee_rate/";
+
+/// A set of failed spendable outputs swept together. The outputs are kept until
+/// the sweep confirms, if it doesn't they are swept again with a higher fee.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SpendableOutputSweep {
+    pub outpoints: Vec<bitcoin::OutPoint>,
+    /// The last sweep that was broadcast, none if it never was
+    pub txid: Option<Txid>,
+    /// The fee rate of the broadcast sweep in sats per kw
+    pub fee_rate: u32,
+    /// How many times we tried to sweep the outputs
+    pub attempts: u32,
+}
 
 pub(crate) type PhantomChannelManager<S: MutinyStorage> = LdkChannelManager<
     Arc<ChainMonitor<S>>,
@@ -66,6 +86,9 @@ pub struct MutinyNodePersister<S: MutinyStorage> {
     pub(crate) storage: S,
     manager_version: Arc<AtomicU32>,
     pub(crate) chain_monitor: Arc<Mutex<Option<Arc<ChainMonitor<S>>>>>,
+    /// Monitors deleted by storage compaction, the chain monitor still has them in memory
+    /// so we need to make sure we don't write them back
+    archived_monitors: Arc<utils::Mutex<HashSet<OutPoint>>>,
     logger: Arc<MutinyLogger>,
 }
 
@@ -82,6 +105,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
             storage,
             manager_version: Arc::new(AtomicU32::new(0)),
             chain_monitor: Arc::new(Mutex::new(None)),
+            archived_monitors: Arc::new(utils::Mutex::new(HashSet::new())),
             logger,
         }
     }
@@ -103,6 +127,52 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         self.get_key(&key)
     }
 
+    /// Lists the funding outpoints of all the channel monitors we have in storage,
+    /// including the ones we don't watch anymore.
+    pub(crate) fn list_stored_monitors(&self) -> Result<Vec<OutPoint>, MutinyError> {
+        let suffix = format!("_{}", self.node_id);
+        let keys = self.storage.scan_keys(MONITORS_PREFIX_KEY, Some(&suffix))?;
+
+        let outpoints = keys
+            .iter()
+            .filter_map(|key| {
+                let outpoint = key
+                    .trim_start_matches(MONITORS_PREFIX_KEY)
+                    .trim_end_matches(&suffix);
+                let (txid, index) = outpoint.split_once('_')?;
+                Some(OutPoint {
+                    txid: Txid::from_str(txid).ok()?,
+                    index: index.parse().ok()?,
+                })
+            })
+            .collect();
+
+        Ok(outpoints)
+    }
+
+    /// Deletes a channel monitor from storage and stops persisting updates for it.
+    /// This should only be used for monitors of closed channels that have nothing
+    /// left to claim.
+    ///
+    /// Returns the size of the deleted monitor in bytes.
+    pub(crate) fn archive_monitor(&self, funding_txo: &OutPoint) -> Result<u64, MutinyError> {
+        self.archived_monitors
+            .lock()
+            .map_err(|_| MutinyStorageError::LockError)?
+            .insert(*funding_txo);
+
+        let key = self.get_monitor_key(funding_txo);
+        let size = self
+            .storage
+            .get_data::<Vec<u8>>(&key)?
+            .map(|m| m.len() as u64)
+            .unwrap_or_default();
+        self.storage.delete(&[key])?;
+
+        log_debug!(self.logger, "Archived channel monitor {funding_txo:?}");
+        Ok(size)
+    }
+
     fn init_persist_monitor<W: Writeable>(
         &self,
         key: String,
@@ -213,7 +283,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         router: Arc<Router>,
         channel_monitors: Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>,
-        esplora: &AsyncClient,
+        chain_source: &(dyn ChainSource + Send + Sync),
     ) -> Result<ReadChannelManager<S>, MutinyError> {
         log_debug!(mutiny_logger, "Reading channel manager from storage");
         let key = self.get_key(CHANNEL_MANAGER_KEY);
@@ -260,7 +330,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
                     keys_manager,
                     router,
                     channel_monitors,
-                    esplora,
+                    chain_source,
                 )
                 .await
             }
@@ -314,7 +384,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
             mutiny_chain,
             router,
             mutiny_logger.clone(),
-            default_user_config(accept_underpaying_htlcs),
+            default_user_config(accept_underpaying_htlcs, false),
             channel_monitor_mut_references,
         );
 
@@ -337,6 +407,89 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         })
     }
 
+    /// Reads back the stored channel monitors and channel manager the way the node
+    /// does when it starts, returns the keys that can't be read and why.
+    /// Nothing is written, the channel manager that is read is dropped.
+    pub(crate) async fn check_channel_data(
+        &self,
+        keys_manager: Arc<PhantomKeysManager<S>>,
+        chain_monitor: Arc<ChainMonitor<S>>,
+        mutiny_chain: Arc<MutinyChain<S>>,
+        fee_estimator: Arc<MutinyFeeEstimator<S>>,
+        router: Arc<Router>,
+    ) -> Result<Vec<(String, String)>, MutinyError> {
+        let password = self.storage.password();
+        let mut errors = vec![];
+
+        let suffix = format!("_{}", self.node_id);
+        let mut monitors = vec![];
+        for (key, value) in self.storage.scan_persisted(MONITORS_PREFIX_KEY).await? {
+            if !key.ends_with(&suffix) {
+                continue;
+            }
+            let bytes: Vec<u8> = match decrypt_value(&key, value, password)
+                .and_then(|v| Ok(serde_json::from_value(v)?))
+            {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    errors.push((key, e.to_string()));
+                    continue;
+                }
+            };
+            match <(BlockHash, ChannelMonitor<InMemorySigner>)>::read(
+                &mut Cursor::new(bytes),
+                (keys_manager.as_ref(), keys_manager.as_ref()),
+            ) {
+                // the node only watches monitors with claimable balances
+                Ok(monitor) if !monitor.1.get_claimable_balances().is_empty() => {
+                    monitors.push(monitor)
+                }
+                Ok(_) => {}
+                Err(e) => errors.push((key, format!("Failed to deserialize ChannelMonitor: {e}"))),
+            }
+        }
+
+        // the channel manager can't be read without all of its monitors,
+        // so it is only checked if they could all be read
+        if !errors.is_empty() {
+            return Ok(errors);
+        }
+
+        let key = self.get_key(CHANNEL_MANAGER_KEY);
+        let Some(value) = self.storage.scan_persisted(&key).await?.remove(&key) else {
+            return Ok(errors);
+        };
+        let bytes = decrypt_value(&key, value, password).and_then(|value| {
+            match serde_json::from_value::<VersionedValue>(value.clone()) {
+                // new encoding is in hex
+                Ok(versioned) => {
+                    let hex: String = serde_json::from_value(versioned.value)?;
+                    Ok(Vec::<u8>::from_hex(&hex)?)
+                }
+                // old encoding with no version number and as an array of numbers
+                Err(_) => Ok(serde_json::from_value::<Vec<u8>>(value)?),
+            }
+        });
+        let res = bytes.and_then(|bytes| {
+            Self::parse_channel_manager(
+                bytes,
+                false,
+                chain_monitor,
+                mutiny_chain,
+                fee_estimator,
+                self.logger.clone(),
+                keys_manager,
+                router,
+                monitors,
+            )
+        });
+        if let Err(e) = res {
+            errors.push((key, e.to_string()));
+        }
+
+        Ok(errors)
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub(crate) async fn create_new_channel_manager(
         network: Network,
@@ -348,17 +501,17 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         router: Arc<Router>,
         channel_monitors: Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>,
-        esplora: &AsyncClient,
+        chain_source: &(dyn ChainSource + Send + Sync),
     ) -> Result<ReadChannelManager<S>, MutinyError> {
         // if regtest, we don't need to get the tip hash and can
         // just use genesis, this also lets us use regtest in tests
         let best_block = if network == Network::Regtest {
             BestBlock::from_network(network)
         } else {
-            let height_future = esplora
+            let height_future = chain_source
                 .get_height()
                 .map_err(|_| MutinyError::ChainAccessFailed);
-            let hash_future = esplora
+            let hash_future = chain_source
                 .get_tip_hash()
                 .map_err(|_| MutinyError::ChainAccessFailed);
             let (height, hash) = try_join!(height_future, hash_future)?;
@@ -378,7 +531,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
             keys_manager.clone(),
             keys_manager.clone(),
             keys_manager,
-            default_user_config(accept_underpaying_htlcs),
+            default_user_config(accept_underpaying_htlcs, false),
             chain_params,
             utils::now().as_secs() as u32,
         );
@@ -400,16 +553,17 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
             user_channel_id.to_be_bytes().to_lower_hex_string()
         ));
         self.storage.set_data(key.clone(), &closure, None)?;
-
-        let index = self.storage.activity_index();
-        let mut index = index.try_write()?;
-        index.retain(|i| i.key != key); // remove old version
-        index.insert(IndexItem {
-            timestamp: Some(closure.timestamp),
-            key,
-        });
-
-        Ok(())
+        update_search_index(&self.storage, |index| {
+            index.update(&key, &closure_fields(&closure))
+        })?;
+
+        self.storage.update_activity_index(|index| {
+            index.retain(|i| i.key != key); // remove old version
+            index.insert(IndexItem {
+                timestamp: Some(closure.timestamp),
+                key,
+            });
+        })
     }
 
     pub(crate) fn get_channel_closure(
@@ -423,6 +577,31 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         self.storage.get_channel_closure(&key)
     }
 
+    /// Saves the new lifecycle state of a channel, see [ChannelLifecycleState]
+    pub(crate) fn persist_channel_lifecycle(
+        &self,
+        user_channel_id: u128,
+        state: ChannelLifecycleState,
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(&format!(
+            "{CHANNEL_LIFECYCLE_PREFIX}{}",
+            user_channel_id.to_be_bytes().to_lower_hex_string()
+        ));
+        let lifecycle = ChannelLifecycle::new(user_channel_id, state);
+        self.storage.set_data(key, lifecycle, None)
+    }
+
+    pub(crate) fn get_channel_lifecycle(
+        &self,
+        user_channel_id: u128,
+    ) -> Result<Option<ChannelLifecycle>, MutinyError> {
+        let key = self.get_key(&format!(
+            "{CHANNEL_LIFECYCLE_PREFIX}{}",
+            user_channel_id.to_be_bytes().to_lower_hex_string()
+        ));
+        self.storage.get_data(key)
+    }
+
     pub(crate) fn list_channel_closures(&self) -> Result<Vec<ChannelClosure>, MutinyError> {
         let suffix = format!("_{}", self.node_id);
         let map: HashMap<String, ChannelClosure> =
@@ -512,6 +691,42 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         Ok(())
     }
 
+    /// Removes the given spendable outputs from the failed ones in storage.
+    /// Outputs that failed in the meantime are kept.
+    pub fn remove_failed_spendable_outputs(
+        &self,
+        swept: &[SpendableOutputDescriptor],
+    ) -> anyhow::Result<()> {
+        if swept.is_empty() {
+            return Ok(());
+        }
+
+        let remaining: Vec<_> = self
+            .get_failed_spendable_outputs()?
+            .into_iter()
+            .filter(|desc| !swept.contains(desc))
+            .collect();
+        if remaining.is_empty() {
+            return self.clear_failed_spendable_outputs();
+        }
+
+        self.set_failed_spendable_outputs(remaining)
+    }
+
+    /// The sweeps of the failed spendable outputs that haven't confirmed yet
+    pub fn get_spendable_output_sweeps(&self) -> Result<Vec<SpendableOutputSweep>, MutinyError> {
+        let key = self.get_key(SPENDABLE_OUTPUT_SWEEPS_KEY);
+        Ok(self.storage.get_data(key)?.unwrap_or_default())
+    }
+
+    pub fn set_spendable_output_sweeps(
+        &self,
+        sweeps: Vec<SpendableOutputSweep>,
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(SPENDABLE_OUTPUT_SWEEPS_KEY);
+        self.storage.set_data(key, sweeps, None)
+    }
+
     pub(crate) fn persist_channel_open_params(
         &self,
         id: u128,
@@ -533,6 +748,46 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         let key = self.get_key(&channel_open_params_key(id));
         self.storage.delete(&[key])
     }
+
+    pub(crate) fn persist_channel_policy(
+        &self,
+        user_channel_id: u128,
+        policy: ChannelPolicy,
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(&format!("{CHANNEL_POLICY_PREFIX}{user_channel_id}"));
+        self.storage.set_data(key, policy, None)
+    }
+
+    pub(crate) fn get_channel_policy(
+        &self,
+        user_channel_id: u128,
+    ) -> Result<Option<ChannelPolicy>, MutinyError> {
+        let key = self.get_key(&format!("{CHANNEL_POLICY_PREFIX}{user_channel_id}"));
+        self.storage.get_data(key)
+    }
+
+    /// Saves the fee rate, in sats per kw, the claims of a closed channel are bumped to
+    pub(crate) fn persist_close_fee_rate(
+        &self,
+        channel_id: &ChannelId,
+        fee_rate: u32,
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(&format!("{CLOSE_FEE_RATE_PREFIX}{channel_id}"));
+        self.storage.set_data(key, fee_rate, None)
+    }
+
+    pub(crate) fn get_close_fee_rate(
+        &self,
+        channel_id: &ChannelId,
+    ) -> Result<Option<u32>, MutinyError> {
+        let key = self.get_key(&format!("{CLOSE_FEE_RATE_PREFIX}{channel_id}"));
+        self.storage.get_data(key)
+    }
+
+    pub(crate) fn delete_close_fee_rate(&self, channel_id: &ChannelId) -> Result<(), MutinyError> {
+        let key = self.get_key(&format!("{CLOSE_FEE_RATE_PREFIX}{channel_id}"));
+        self.storage.delete(&[key])
+    }
 }
 
 fn channel_open_params_key(id: u128) -> String {
@@ -663,6 +918,16 @@ impl<S: MutinyStorage> Persist<InMemorySigner> for MutinyNodePersister<S> {
         monitor: &ChannelMonitor<InMemorySigner>,
         monitor_update_id: MonitorUpdateId,
     ) -> ChannelMonitorUpdateStatus {
+        // the chain monitor keeps updating archived monitors on every block,
+        // don't write them back to storage
+        if self
+            .archived_monitors
+            .lock()
+            .is_ok_and(|a| a.contains(&funding_txo))
+        {
+            return ChannelMonitorUpdateStatus::Completed;
+        }
+
         let key = self.get_monitor_key(&funding_txo);
         let update_id = monitor.get_latest_update_id();
         debug_assert!(update_id == utils::get_monitor_version(&monitor.encode()));
@@ -733,6 +998,7 @@ mod test {
     use esplora_client::Builder;
     use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
     use lightning::sign::EntropySource;
+    use lightning::util::config::ChannelConfig;
     use lightning::{ln::PaymentHash, routing::router::DefaultRouter};
     use lightning_transaction_sync::EsploraSyncClient;
     use std::str::FromStr;
@@ -776,6 +1042,41 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_archive_monitor() {
+        let test_name = "test_archive_monitor";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+        let outpoint = OutPoint {
+            txid: Txid::all_zeros(),
+            index: 1,
+        };
+        let key = persister.get_monitor_key(&outpoint);
+        persister
+            .storage
+            .set_data(key.clone(), vec![1u8; 10], None)
+            .unwrap();
+
+        // monitors of other nodes are not listed
+        let other = MutinyNodePersister::new(
+            Uuid::new_v4().to_string(),
+            persister.storage.clone(),
+            Arc::new(MutinyLogger::default()),
+        );
+        assert!(other.list_stored_monitors().unwrap().is_empty());
+        assert_eq!(persister.list_stored_monitors().unwrap(), vec![outpoint]);
+
+        let size = persister.archive_monitor(&outpoint).unwrap();
+        assert_eq!(size, 10);
+        assert!(persister.list_stored_monitors().unwrap().is_empty());
+        assert!(persister
+            .storage
+            .get_data::<Vec<u8>>(key)
+            .unwrap()
+            .is_none());
+    }
+
     #[test]
     fn test_persist_payment_info() {
         let test_name = "test_persist_payment_info";
@@ -799,6 +1100,7 @@ mod test {
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
+            custom_tlvs: vec![],
         };
         let result = persist_payment_info(&persister.storage, &payment_hash.0, &payment_info, true);
         assert!(result.is_ok());
@@ -861,6 +1163,77 @@ mod test {
         assert_eq!(result, Some(closure));
     }
 
+    #[test]
+    fn test_persist_channel_lifecycle() {
+        let test_name = "test_persist_channel_lifecycle";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+
+        let user_channel_id: u128 = 123456789;
+        assert_eq!(
+            persister.get_channel_lifecycle(user_channel_id).unwrap(),
+            None
+        );
+
+        persister
+            .persist_channel_lifecycle(user_channel_id, ChannelLifecycleState::Negotiating)
+            .unwrap();
+        let funding_txid = Txid::all_zeros();
+        let state = ChannelLifecycleState::FundingBroadcast { funding_txid };
+        persister
+            .persist_channel_lifecycle(user_channel_id, state.clone())
+            .unwrap();
+
+        let result = persister
+            .get_channel_lifecycle(user_channel_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.state, state);
+        assert_eq!(
+            result.user_chan_id,
+            user_channel_id.to_be_bytes().to_lower_hex_string()
+        );
+    }
+
+    #[test]
+    fn test_persist_channel_policy() {
+        let test_name = "test_persist_channel_policy";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+
+        let user_channel_id: u128 = 123456789;
+        assert_eq!(persister.get_channel_policy(user_channel_id).unwrap(), None);
+
+        let policy = ChannelPolicy {
+            forwarding_fee_base_msat: Some(1_000),
+            forwarding_fee_proportional_millionths: Some(100),
+            cltv_expiry_delta: None,
+            htlc_minimum_msat: Some(10_000),
+            htlc_maximum_msat: None,
+        };
+        persister
+            .persist_channel_policy(user_channel_id, policy)
+            .unwrap();
+
+        let result = persister.get_channel_policy(user_channel_id).unwrap();
+        assert_eq!(result, Some(policy));
+
+        let mut config = ChannelConfig::default();
+        policy.apply(&mut config);
+        assert_eq!(config.forwarding_fee_base_msat, 1_000);
+        assert_eq!(config.forwarding_fee_proportional_millionths, 100);
+        assert_eq!(
+            config.cltv_expiry_delta,
+            ChannelConfig::default().cltv_expiry_delta
+        );
+
+        assert!(!policy.allows_htlc(9_999));
+        assert!(policy.allows_htlc(10_000));
+        assert!(policy.allows_htlc(u64::MAX));
+    }
+
     #[test]
     fn test_persist_spendable_output_descriptor() {
         let test_name = "test_persist_spendable_output_descriptor";
@@ -894,7 +1267,31 @@ mod test {
         assert!(result.is_ok());
 
         let result = persister.get_failed_spendable_outputs().unwrap();
-        assert_eq!(result, vec![static_output_0, static_output_1]);
+        assert_eq!(
+            result,
+            vec![static_output_0.clone(), static_output_1.clone()]
+        );
+
+        let result = persister.remove_failed_spendable_outputs(&[static_output_0]);
+        assert!(result.is_ok());
+
+        let result = persister.get_failed_spendable_outputs().unwrap();
+        assert_eq!(result, vec![static_output_1]);
+
+        assert!(persister.get_spendable_output_sweeps().unwrap().is_empty());
+        let sweep = SpendableOutputSweep {
+            outpoints: vec![bitcoin::OutPoint::new(Txid::all_zeros(), 1)],
+            txid: Some(Txid::all_zeros()),
+            fee_rate: 253,
+            attempts: 2,
+        };
+        persister
+            .set_spendable_output_sweeps(vec![sweep.clone()])
+            .unwrap();
+        assert_eq!(
+            persister.get_spendable_output_sweeps().unwrap(),
+            vec![sweep]
+        );
 
         let result = persister.clear_failed_spendable_outputs();
         assert!(result.is_ok());
@@ -947,6 +1344,7 @@ mod test {
                 xpriv,
                 persister.storage.clone(),
                 network,
+                0,
                 esplora.clone(),
                 fees.clone(),
                 stop,
@@ -996,7 +1394,7 @@ mod test {
                 km.clone(),
                 router.clone(),
                 vec![],
-                &esplora,
+                esplora.as_ref(),
             )
             .await
             .unwrap();
@@ -1013,14 +1411,14 @@ mod test {
             .read_channel_manager(
                 network,
                 false,
-                chain_monitor,
+                chain_monitor.clone(),
                 chain.clone(),
                 fees.clone(),
                 logger.clone(),
-                km,
-                router,
+                km.clone(),
+                router.clone(),
                 vec![],
-                &esplora,
+                esplora.as_ref(),
             )
             .await
             .unwrap();
@@ -1028,5 +1426,40 @@ mod test {
         // should be same version
         assert_eq!(persister.manager_version(), 1);
         assert!(read.is_restarting);
+
+        // the audit reads it back the same way
+        let check = || {
+            persister.check_channel_data(
+                km.clone(),
+                chain_monitor.clone(),
+                chain.clone(),
+                fees.clone(),
+                router.clone(),
+            )
+        };
+        assert!(check().await.unwrap().is_empty());
+
+        // a monitor that can't be read is reported under its own key
+        let monitor_key = persister.get_monitor_key(&OutPoint {
+            txid: Txid::all_zeros(),
+            index: 0,
+        });
+        persister
+            .storage
+            .set_data(monitor_key.clone(), vec![1u8, 2, 3], None)
+            .unwrap();
+        let errors = check().await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, monitor_key);
+
+        persister.storage.delete(&[monitor_key]).unwrap();
+        let manager_key = persister.get_key(CHANNEL_MANAGER_KEY);
+        persister
+            .storage
+            .set_data(manager_key.clone(), vec![1u8, 2, 3], None)
+            .unwrap();
+        let errors = check().await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, manager_key);
     }
 }
@@ -9,51 +9,159 @@
 )]
 extern crate core;
 
+pub mod accounts;
+#[cfg(all(feature = "admin_api", not(target_arch = "wasm32")))]
+pub mod admin;
+pub mod audit;
 pub mod auth;
+pub mod backup;
 pub mod blindauth;
+pub mod budget_renewals;
 mod cashu;
 mod chain;
+pub mod childwallet;
+pub mod coinjoin;
+pub mod compaction;
+pub mod conflicts;
 pub mod encrypt;
 pub mod error;
 pub mod event;
+pub mod export;
 pub mod federation;
 mod fees;
 mod gossip;
+pub mod handoff;
 mod hermes;
+mod idempotency;
+pub mod inbox;
 mod key;
 mod keymanager;
 pub mod labels;
+pub mod latency;
 mod ldkstorage;
+pub mod lnaddress;
 pub mod lnurlauth;
+pub mod lnurlwithdraw;
 pub mod logging;
 pub mod lsp;
+pub mod maintenance;
 mod messagehandler;
+pub mod migrations;
 mod networking;
 mod node;
 pub mod nodemanager;
 pub mod nostr;
 mod onchain;
 mod peermanager;
+mod peerstorage;
+pub mod performance;
+pub mod policy;
+pub mod price;
+pub mod readiness;
+pub mod receivelimits;
+#[cfg(all(test, feature = "regtest_tests", not(target_arch = "wasm32")))]
+mod regtest;
+pub mod scb;
 pub mod scorer;
+pub mod search;
+pub mod split;
+pub mod stats;
 pub mod storage;
 mod subscription;
+pub mod swaps;
 pub mod utils;
 pub mod vss;
 
 #[cfg(test)]
 mod test_utils;
 
-use crate::federation::{get_federation_identity, ResyncProgress};
+use crate::audit::{audit_storage, repair_storage, RepairOptions, RepairReport, StorageAudit};
+use crate::backup::{
+    decrypt_backup, encrypt_backup, get_remote_backup_config, get_remote_backup_status,
+    set_remote_backup_config, set_remote_backup_status, BackupTarget, HttpBackupTarget,
+    RemoteBackupConfig, RemoteBackupStatus, REMOTE_BACKUP_CHECK_INTERVAL_SECS,
+    REMOTE_BACKUP_INTERVAL_SECS,
+};
+use crate::budget_renewals::{
+    check_renewals, get_subscriptions, next_renewal, set_subscriptions, Subscription,
+    SubscriptionEvent, SubscriptionPayment, SubscriptionStatus, SUBSCRIPTION_CHECK_INTERVAL_SECS,
+};
+use crate::compaction::{
+    get_compaction_policy, get_last_compaction, prune_stale_payments, set_compaction_policy,
+    set_last_compaction, CompactionPolicy, CompactionStats, COMPACTION_CHECK_INTERVAL_SECS,
+    COMPACTION_INTERVAL_SECS,
+};
+use crate::conflicts::{get_sync_conflicts, resolve_sync_conflict, SyncConflict};
+use crate::export::{export_rows, ActivityExportRow, ExportFormat};
+use crate::federation::{
+    get_federation_activity_tag, get_federation_identity, FederationActivity,
+    FederationActivityKind, FederationCandidate, FederationInitStatus, FederationPreference,
+    FederationRoutingPolicy, ResyncProgress, FEDERATION_PREFERENCE_KEY,
+};
+pub use crate::fees::{
+    EsploraFeeProvider, FeeEstimatorConfig, FeeProvider, MempoolFeeProvider, StaticFeeProvider,
+};
 pub use crate::gossip::{GOSSIP_SYNC_TIME_KEY, NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
-pub use crate::keymanager::generate_seed;
+use crate::handoff::{DeviceHandoffEvent, DEVICE_HANDOFF_CHECK_INTERVAL_SECS};
+use crate::idempotency::{with_idempotency, PendingPayment};
+use crate::inbox::{PaymentRequest, PaymentRequestEvent, PaymentRequestInbox};
+pub use crate::keymanager::{generate_seed, xprivkey_from_mnemonic};
+use crate::latency::{
+    get_payment_latencies, persist_payment_latency, LatencyStats, PaymentRail, PaymentStage,
+    PaymentTrace,
+};
 pub use crate::ldkstorage::{CHANNEL_CLOSURE_PREFIX, CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+use crate::lnaddress::{LnAddress, LnAddressClient, LnAddressPayment};
+use crate::lnurlwithdraw::{
+    get_withdrawals, save_withdrawals, PendingWithdrawal, WithdrawalStatus,
+    WITHDRAWAL_CHECK_INTERVAL_SECS,
+};
+use crate::maintenance::{
+    fetch_maintenance_event, maintenance_filter, parse_maintenance_event,
+    record_maintenance_event_time, MaintenanceEvent, MaintenanceNotice,
+    MAINTENANCE_CHECK_INTERVAL_SECS,
+};
+use crate::networking::websocket::{SimpleWebSocket, WebSocketImpl};
+use crate::node::DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA;
+use crate::nostr::listener::{dedup_filters, NostrListener, MAX_EVENT_BATCH};
+use crate::nostr::nip78::SOCIAL_BACKUP_CHECK_INTERVAL_SECS;
+pub use crate::onchain::{CollaborativeContribution, CollaborativeSpend, ExternalSweep};
+use crate::peerstorage::PEER_STORAGE_CHECK_INTERVAL_SECS;
+use crate::performance::{Operation, PerformanceReport, PerformanceTracker};
+use crate::policy::{SpendingPolicy, SpendingPolicyUsage, SpendingReservation};
+use crate::price::{
+    get_activity_price, record_activity_price, FiatActivityItem, DEFAULT_PRICE_CURRENCY,
+    PRICE_CHECK_INTERVAL_SECS, PRICE_RECORD_MAX_AGE_SECS,
+};
+use crate::readiness::{Readiness, ReadinessEvent, Subsystem, SubsystemStatus};
+use crate::receivelimits::{
+    get_receive_limit_stats, get_receive_limits, set_receive_limits, ReceiveLimitStats,
+    ReceiveLimits,
+};
+use crate::scb::{backup_encryption_key, restore_static_channel_backup, StaticChannelBackup};
+use crate::search::{SearchResults, ADDRESS_SEARCH_PREFIX, SEARCH_INDEX_BUILT_KEY};
+use crate::split::{
+    get_payment_splits, save_payment_splits, split_amount, PaymentSplit, SplitDirection, SplitShare,
+};
+use crate::stats::{
+    get_spending_stats, set_spending_stats, SpendingPeriod, SpendingStats, SpendingSummary,
+};
+use crate::swaps::{
+    build_refund_tx, get_swap_ins, next_refund_key_index, parse_script, persist_swap_in,
+    swap_refund_key, verify_swap_script, verify_swap_timeout, SwapClient, SwapIn, SwapStatus,
+    SWAP_CHECK_INTERVAL_SECS, SWAP_IN_LABEL,
+};
 use crate::utils::spawn;
-use crate::{auth::MutinyAuthClient, hermes::HermesClient, logging::MutinyLogger};
+use crate::{
+    auth::MutinyAuthClient,
+    hermes::HermesClient,
+    logging::{LoggingConfig, MutinyLogger},
+};
 use crate::{blindauth::BlindAuthClient, cashu::CashuHttpClient};
 use crate::{error::MutinyError, nostr::ReservedProfile};
 use crate::{
-    event::{HTLCStatus, MillisatAmount, PaymentInfo},
-    onchain::FULL_SYNC_STOP_GAP,
+    event::{CustomTlv, HTLCStatus, MillisatAmount, PaymentInfo, PaymentPath},
+    onchain::{MempoolStatus, FULL_SYNC_STOP_GAP},
 };
 use crate::{
     federation::{
@@ -62,15 +170,20 @@ use crate::{
     labels::{get_contact_key, Contact, LabelStorage},
     nodemanager::NodeBalance,
 };
+use crate::{lnurlauth::AuthManager, nostr::MUTINY_PLUS_SUBSCRIPTION_LABEL};
 use crate::{
-    lnurlauth::make_lnurl_auth_connection,
-    nodemanager::{ChannelClosure, MutinyBip21RawMaterials},
+    lnurlauth::{
+        get_lnurl_auth_identities, get_lnurl_auth_index, make_lnurl_auth_connection,
+        save_lnurl_auth_identity, LnUrlAuthIdentity,
+    },
+    nodemanager::{ChannelClosure, MutinyBip21RawMaterials, PaymentParametersOverride},
 };
-use crate::{lnurlauth::AuthManager, nostr::MUTINY_PLUS_SUBSCRIPTION_LABEL};
 use crate::{logging::LOGGING_KEY, nodemanager::NodeManagerBuilder};
 use crate::{nodemanager::NodeManager, nostr::ProfileType};
 use crate::{
-    nostr::nwc::{BudgetPeriod, BudgetedSpendingConditions, NwcProfileTag, SpendingConditions},
+    nostr::nwc::{
+        BudgetPeriod, BudgetedSpendingConditions, NwcProfile, NwcProfileTag, SpendingConditions,
+    },
     subscription::MutinySubscriptionClient,
 };
 use crate::{
@@ -81,10 +194,14 @@ use crate::{nostr::NostrManager, utils::sleep};
 use crate::{
     onchain::get_esplora_url,
     storage::{
-        get_payment_hash_from_key, get_transaction_details, list_payment_info,
-        persist_payment_info, update_nostr_contact_list, IndexItem, MutinyStorage, DEVICE_ID_KEY,
-        EXPECTED_NETWORK_KEY, NEED_FULL_SYNC_KEY, ONCHAIN_PREFIX, PAYMENT_INBOUND_PREFIX_KEY,
-        PAYMENT_OUTBOUND_PREFIX_KEY, SUBSCRIPTION_TIMESTAMP, TRANSACTION_DETAILS_PREFIX_KEY,
+        build_activity_index, duress_namespace, get_activity_index, get_duress_mnemonic,
+        get_payment_hash_from_key, get_payment_paths, get_transaction_details, list_payment_info,
+        persist_payment_info, set_duress_mnemonic, update_nostr_contact_list, IndexItem,
+        MutinyStorage, NamespacedStorage, ACCOUNT_INDEX_KEY, ACTIVITY_INDEX_BUILT_KEY,
+        DEVICE_ID_KEY, EXPECTED_NETWORK_KEY, LEGACY_ACTIVITY_INDEX_KEY, MNEMONIC_KEY,
+        NEED_FULL_SYNC_KEY, ONCHAIN_PREFIX, PAYMENT_INBOUND_PREFIX_KEY,
+        PAYMENT_OUTBOUND_PREFIX_KEY, ROOT_FINGERPRINT_KEY, SUBSCRIPTION_TIMESTAMP,
+        TRANSACTION_DETAILS_PREFIX_KEY,
     },
 };
 use ::nostr::nips::nip47::Method;
@@ -99,10 +216,11 @@ use async_lock::RwLock;
 use bdk_chain::ConfirmationTime;
 use bip39::Mnemonic;
 pub use bitcoin;
-use bitcoin::secp256k1::{PublicKey, ThirtyTwoByteHash};
-use bitcoin::{bip32::ExtendedPrivKey, Transaction};
+use bitcoin::bip32::{ExtendedPrivKey, Fingerprint};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, ThirtyTwoByteHash};
 use bitcoin::{hashes::sha256, Network, Txid};
 use bitcoin::{hashes::Hash, Address};
+use bitcoin::{BlockHash, OutPoint, Transaction};
 use esplora_client::AsyncClient;
 pub use fedimint_core;
 use fedimint_core::{api::InviteCode, config::FederationId};
@@ -114,7 +232,7 @@ use itertools::Itertools;
 pub use lightning;
 use lightning::chain::BestBlock;
 use lightning::ln::PaymentHash;
-use lightning::util::logger::Logger;
+use lightning::util::logger::{Level, Logger};
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
 pub use lightning_invoice;
 use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
@@ -136,21 +254,34 @@ use std::time::Duration;
 use std::time::Instant;
 use std::{collections::HashMap, sync::atomic::AtomicBool};
 use std::{str::FromStr, sync::atomic::Ordering};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use url::Url;
 use uuid::Uuid;
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
-use crate::labels::LabelItem;
-use crate::nostr::{NostrKeySource, RELAYS};
+use crate::childwallet::{
+    child_wallet_namespace, derive_child_mnemonic, get_child_wallet_storage,
+    set_child_wallet_storage, ChildWallet,
+};
+use crate::coinjoin::{CoinjoinRole, NostrCoinjoin};
+use crate::labels::{LabelItem, CONTACT_PREFIX};
+use crate::nostr::{connect_remote_signer, NostrKeySource, RELAYS};
 #[cfg(test)]
 use mockall::{automock, predicate::*};
 
 pub const DEVICE_LOCK_INTERVAL_SECS: u64 = 30;
 const BITCOIN_PRICE_CACHE_SEC: u64 = 300;
+const BLOCK_HEIGHT_CACHE_SEC: u64 = 30;
+const CONFIRMATION_POLL_INTERVAL_MS: i32 = 10_000;
 const DEFAULT_PAYMENT_TIMEOUT: u64 = 30;
 const SWAP_LABEL: &str = "SWAP";
 const MELT_CASHU_TOKEN: &str = "Cashu Token Melt";
 const DUST_LIMIT: u64 = 546;
+/// How often we back up our ecash to each federation
+const FEDERATION_BACKUP_INTERVAL_SECS: u64 = 60 * 60;
+/// Monthly budget of the Mutiny+ NWC profile
+const MUTINY_PLUS_BUDGET_SATS: u64 = 21_000;
 
 #[cfg_attr(test, automock)]
 pub trait InvoiceHandler {
@@ -170,6 +301,12 @@ pub trait InvoiceHandler {
         amount: u64,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError>;
+    async fn create_invoice_with_params(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+        params: InvoiceParams,
+    ) -> Result<MutinyInvoice, MutinyError>;
 }
 
 pub struct LnUrlParams {
@@ -189,7 +326,7 @@ pub struct Plan {
     pub amount_sat: u64,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone)]
 pub struct MutinyBalance {
     pub confirmed: u64,
     pub unconfirmed: u64,
@@ -221,11 +358,383 @@ pub struct FederationBalances {
     pub balances: Vec<FederationBalance>,
 }
 
+/// Aggregated payment stats for a contact
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ContactStats {
+    /// Total amount we have sent to the contact (sats)
+    pub total_sent: u64,
+    /// Total amount we have received from the contact (sats)
+    pub total_received: u64,
+    /// Total fees we paid when sending to the contact (sats)
+    pub total_fees: u64,
+    /// Number of completed payments sent to the contact
+    pub num_sent: u64,
+    /// Number of completed payments received from the contact
+    pub num_received: u64,
+    /// Unix timestamp of the latest completed payment with the contact
+    pub last_payment: Option<u64>,
+}
+
+impl ContactStats {
+    /// Aggregates the completed payments in the given activity.
+    /// If `since` is given, only payments completed at or after that unix timestamp are counted.
+    pub fn from_activity(activity: &[ActivityItem], since: Option<u64>) -> Self {
+        let mut stats = ContactStats::default();
+        for item in activity {
+            // skip pending items, we only want to count completed payments
+            let Some(time) = item.last_updated() else {
+                continue;
+            };
+            if since.is_some_and(|s| time < s) {
+                continue;
+            }
+
+            let counted = match item {
+                ActivityItem::OnChain(t) => stats.add_transaction(t),
+                ActivityItem::Lightning(i) => stats.add_invoice(i),
+                ActivityItem::ChannelClosed(_) => false,
+                ActivityItem::Federation(f) => match f {
+                    FederationActivity::Lightning { invoice, .. } => stats.add_invoice(invoice),
+                    FederationActivity::PegIn { transaction, .. }
+                    | FederationActivity::PegOut { transaction, .. } => {
+                        stats.add_transaction(transaction)
+                    }
+                    FederationActivity::EcashReissue {
+                        amount_sats, fees, ..
+                    } => {
+                        stats.total_received += amount_sats;
+                        stats.total_fees += fees.unwrap_or(0);
+                        stats.num_received += 1;
+                        true
+                    }
+                },
+            };
+            if !counted {
+                continue;
+            }
+
+            stats.last_payment = Some(stats.last_payment.map_or(time, |l| l.max(time)));
+        }
+
+        stats
+    }
+
+    fn add_transaction(&mut self, t: &TransactionDetails) -> bool {
+        let fee = t.fee.unwrap_or(0);
+        if t.sent > t.received {
+            self.total_sent += (t.sent - t.received).saturating_sub(fee);
+            self.total_fees += fee;
+            self.num_sent += 1;
+        } else {
+            self.total_received += t.received - t.sent;
+            self.num_received += 1;
+        }
+        true
+    }
+
+    fn add_invoice(&mut self, i: &MutinyInvoice) -> bool {
+        if !i.paid() {
+            return false;
+        }
+        let amount = i.amount_sats.unwrap_or(0);
+        if i.inbound {
+            self.total_received += amount;
+            self.num_received += 1;
+        } else {
+            self.total_sent += amount;
+            self.total_fees += i.fees_paid.unwrap_or(0);
+            self.num_sent += 1;
+        }
+        true
+    }
+}
+
+/// Per-call safeguards for paying an invoice, see [MutinyWallet::pay_invoice_with_options]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayInvoiceOptions {
+    /// Must be set to pay an invoice that does not specify an amount
+    pub confirm_amountless: bool,
+    /// Maximum amount to pay to an invoice without an amount.
+    /// If the wallet is configured with a lower maximum, that is used instead.
+    pub max_amountless_sats: Option<u64>,
+    /// Controls how the payment is routed, setting this skips paying with federations
+    #[serde(skip)]
+    pub payment_overrides: Option<PaymentParametersOverride>,
+    /// Retries with the same key return the outcome of the first payment
+    /// instead of paying again
+    pub idempotency_key: Option<String>,
+    /// How long to wait for a payment over our node before giving up on it,
+    /// defaults to 30 seconds
+    pub timeout_secs: Option<u64>,
+}
+
+/// Optional settings for the invoices we create, see [MutinyWallet::create_bip21_with_params]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct InvoiceParams {
+    /// How long the invoice can be paid for in seconds
+    pub expiry_secs: Option<u32>,
+    /// Description shown to the payer, defaults to none to keep the invoice small
+    pub description: Option<String>,
+    /// Hash of a description that is too long to put in the invoice,
+    /// can't be set together with `description`
+    pub description_hash: Option<sha256::Hash>,
+    /// Minimum CLTV delta of the final hop
+    pub min_final_cltv: Option<u16>,
+    /// How the route hints are picked, defaults to the wallet's config or LDK's selection
+    #[serde(default)]
+    pub route_hints: Option<RouteHintConfig>,
+}
+
+/// Limits what the route hints of our invoices reveal about our channels,
+/// see [MutinyWalletConfigBuilder::with_route_hint_config]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteHintConfig {
+    /// Most route hints to put in an invoice, one per peer.
+    /// With multiple nodes they are shared between the nodes of the phantom invoice.
+    pub max_hints: usize,
+    /// Only use channels with an SCID alias so the hints don't reveal the funding
+    /// transaction, channels without one are used if none have an alias
+    pub prefer_scid_alias: bool,
+    /// Pick the hints at random instead of the channels with the most inbound liquidity
+    pub randomize: bool,
+    /// Leave out channels that can't receive the whole amount, unless none can.
+    /// Nodes of a phantom invoice with no such channels are left out as well.
+    pub require_sufficient_inbound: bool,
+}
+
+impl Default for RouteHintConfig {
+    fn default() -> Self {
+        Self {
+            max_hints: 3,
+            prefer_scid_alias: false,
+            randomize: false,
+            require_sufficient_inbound: false,
+        }
+    }
+}
+
+impl RouteHintConfig {
+    /// Reveals as few channels as possible while keeping the invoice payable
+    pub fn private() -> Self {
+        Self {
+            max_hints: 1,
+            prefer_scid_alias: true,
+            randomize: true,
+            require_sufficient_inbound: true,
+        }
+    }
+}
+
+impl InvoiceParams {
+    pub(crate) fn validate(&self) -> Result<(), MutinyError> {
+        if self.description.is_some() && self.description_hash.is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if self.expiry_secs == Some(0) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if self.route_hints.is_some_and(|r| r.max_hints == 0) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if self
+            .min_final_cltv
+            .is_some_and(|cltv| cltv < lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA)
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        Ok(())
+    }
+
+    /// If the invoice can be created by a federation, they don't support
+    /// description hashes or a custom final CLTV delta
+    fn supported_by_federation(&self) -> bool {
+        self.description_hash.is_none() && self.min_final_cltv.is_none()
+    }
+}
+
+/// The status of a transaction on chain
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionStatus {
+    pub confirmed: bool,
+    /// Height of the block the transaction was confirmed in
+    pub block_height: Option<u32>,
+    /// Unix timestamp of the block the transaction was confirmed in
+    pub block_time: Option<u64>,
+    /// Number of confirmations, 0 if unconfirmed
+    pub confirmations: u32,
+}
+
+/// What the wallet is currently able to do, see [MutinyWallet::liveness]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalletLiveness {
+    /// The wallet has been started and not stopped
+    pub running: bool,
+    /// We can receive through a federation, our LSP, or a channel with inbound capacity
+    pub can_receive: bool,
+    /// We have a spendable balance in a federation, a usable channel, or on-chain
+    pub can_send: bool,
+    /// The chain source is reachable and the initial sync has completed
+    pub can_sync: bool,
+    /// A node is connected to its LSP
+    pub lsp_connected: bool,
+    /// The chain source responded to our last request
+    pub chain_reachable: bool,
+}
+
+/// The state of the wallet that [WalletLiveness] is worked out from
+#[derive(Debug, Clone, Copy, Default)]
+struct LivenessState {
+    running: bool,
+    initial_sync: bool,
+    chain_reachable: bool,
+    lsp_connected: bool,
+    has_federation: bool,
+    federation_balance: u64,
+    /// Inbound capacity of the usable channels
+    inbound: u64,
+    /// Balance of the usable channels
+    outbound: u64,
+    onchain_balance: u64,
+}
+
+impl From<LivenessState> for WalletLiveness {
+    fn from(state: LivenessState) -> Self {
+        let can_sync = state.running && state.initial_sync && state.chain_reachable;
+        let can_receive =
+            state.running && (state.has_federation || state.lsp_connected || state.inbound > 0);
+        let can_send = state.running
+            && (state.federation_balance > 0
+                || state.outbound > 0
+                || (can_sync && state.onchain_balance > 0));
+
+        Self {
+            running: state.running,
+            can_receive,
+            can_send,
+            can_sync,
+            lsp_connected: state.lsp_connected,
+            chain_reachable: state.chain_reachable,
+        }
+    }
+}
+
+/// Cache for chain queries so we don't hit esplora on every call
+#[derive(Debug, Default)]
+struct ChainCache {
+    /// Tip height, its block hash, and when it was fetched
+    tip: Option<(u32, BlockHash, u64)>,
+    /// Confirmed transactions, these won't change unless there is a reorg
+    /// so they are cleared when we see one
+    confirmed_txs: HashMap<Txid, TransactionStatus>,
+    /// How many reorgs we have seen, so a status fetched before one isn't cached after it
+    reorgs: u64,
+}
+
+/// How aggressively the wallet runs its background processes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerMode {
+    /// The app is being used, sync and listen for events as normal
+    #[default]
+    Foreground,
+    /// The app is in the background, sync less often to save battery and bandwidth
+    Background,
+}
+
+impl FromStr for PowerMode {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "foreground" => Ok(PowerMode::Foreground),
+            "background" => Ok(PowerMode::Background),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum ActivityItem {
     OnChain(TransactionDetails),
     Lightning(Box<MutinyInvoice>),
     ChannelClosed(ChannelClosure),
+    /// Activity that was handled by a federation rather than our node or on-chain wallet
+    Federation(FederationActivity),
+}
+
+/// The kind of an [ActivityItem]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    OnChain,
+    Lightning,
+    ChannelClosed,
+    Federation,
+}
+
+impl FromStr for ActivityKind {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "onchain" => Ok(ActivityKind::OnChain),
+            "lightning" => Ok(ActivityKind::Lightning),
+            "channel_closed" => Ok(ActivityKind::ChannelClosed),
+            "federation" => Ok(ActivityKind::Federation),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// Filters for [MutinyWallet::get_activity_page], every set field has to match
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActivityFilter {
+    pub kind: Option<ActivityKind>,
+    pub label: Option<String>,
+    pub contact_id: Option<String>,
+}
+
+impl ActivityFilter {
+    /// Returns false if the activity index key can't be a match,
+    /// so we don't need to load it
+    fn matches_key(&self, key: &str) -> bool {
+        let is_payment = key.starts_with(PAYMENT_INBOUND_PREFIX_KEY)
+            || key.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY);
+        let is_closure = key.starts_with(CHANNEL_CLOSURE_PREFIX);
+        let is_kind = match self.kind {
+            None => true,
+            Some(ActivityKind::OnChain) => {
+                key.starts_with(ONCHAIN_PREFIX) || key.starts_with(TRANSACTION_DETAILS_PREFIX_KEY)
+            }
+            Some(ActivityKind::Lightning) => is_payment,
+            Some(ActivityKind::ChannelClosed) => is_closure,
+            Some(ActivityKind::Federation) => {
+                is_payment || key.starts_with(TRANSACTION_DETAILS_PREFIX_KEY)
+            }
+        };
+
+        // channel closures don't have labels
+        let filters_labels = self.label.is_some() || self.contact_id.is_some();
+        is_kind && !(filters_labels && is_closure)
+    }
+
+    fn matches(&self, activity: &ActivityItem) -> bool {
+        if self.kind.is_some_and(|k| k != activity.kind()) {
+            return false;
+        }
+        let labels = activity.labels();
+        self.label.as_ref().map_or(true, |l| labels.contains(l))
+            && self
+                .contact_id
+                .as_ref()
+                .map_or(true, |c| labels.contains(c))
+    }
+}
+
+/// A page of activity, see [MutinyWallet::get_activity_page]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActivityPage {
+    pub items: Vec<ActivityItem>,
+    /// Cursor for the next page, `None` if this is the last page
+    pub next_until_ts: Option<u64>,
 }
 
 /// A wallet transaction
@@ -250,6 +759,10 @@ pub struct TransactionDetails {
     pub confirmation_time: ConfirmationTime,
     /// Labels associated with this transaction
     pub labels: Vec<String>,
+    /// Whether the transaction is still in the mempool, `None` if it is
+    /// confirmed or we haven't checked yet
+    #[serde(default)]
+    pub mempool_status: Option<MempoolStatus>,
 }
 
 impl PartialOrd for TransactionDetails {
@@ -280,6 +793,15 @@ impl Ord for TransactionDetails {
 }
 
 impl ActivityItem {
+    pub fn kind(&self) -> ActivityKind {
+        match self {
+            ActivityItem::OnChain(_) => ActivityKind::OnChain,
+            ActivityItem::Lightning(_) => ActivityKind::Lightning,
+            ActivityItem::ChannelClosed(_) => ActivityKind::ChannelClosed,
+            ActivityItem::Federation(_) => ActivityKind::Federation,
+        }
+    }
+
     pub fn last_updated(&self) -> Option<u64> {
         match self {
             ActivityItem::OnChain(t) => match t.confirmation_time {
@@ -292,6 +814,7 @@ impl ActivityItem {
                 HTLCStatus::Pending | HTLCStatus::InFlight => None,
             },
             ActivityItem::ChannelClosed(c) => Some(c.timestamp),
+            ActivityItem::Federation(f) => f.last_updated(),
         }
     }
 
@@ -300,6 +823,7 @@ impl ActivityItem {
             ActivityItem::OnChain(t) => t.labels.clone(),
             ActivityItem::Lightning(i) => i.labels.clone(),
             ActivityItem::ChannelClosed(_) => vec![],
+            ActivityItem::Federation(f) => f.labels(),
         }
     }
 
@@ -310,6 +834,7 @@ impl ActivityItem {
             }
             ActivityItem::Lightning(_) => false,
             ActivityItem::ChannelClosed(_) => false,
+            ActivityItem::Federation(_) => false,
         }
     }
 }
@@ -414,6 +939,9 @@ pub struct MutinyInvoice {
     pub inbound: bool,
     pub labels: Vec<String>,
     pub last_updated: u64,
+    /// Custom TLV records the sender included with a keysend payment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_tlvs: Vec<CustomTlv>,
 }
 
 #[cfg(test)]
@@ -433,6 +961,7 @@ impl Default for MutinyInvoice {
             inbound: false,
             labels: vec![],
             last_updated: 0,
+            custom_tlvs: vec![],
         }
     }
 }
@@ -478,6 +1007,7 @@ impl From<Bolt11Invoice> for MutinyInvoice {
             inbound: true,
             labels: vec![],
             last_updated: timestamp,
+            custom_tlvs: vec![],
         }
     }
 }
@@ -508,6 +1038,7 @@ impl From<MutinyInvoice> for PaymentInfo {
             payee_pubkey,
             privacy_level: invoice.privacy_level,
             last_update,
+            custom_tlvs: invoice.custom_tlvs,
         }
     }
 }
@@ -541,6 +1072,7 @@ impl MutinyInvoice {
                     preimage: i.preimage.map(|p| p.to_lower_hex_string()),
                     fees_paid: i.fee_paid_msat.map(|f| f / 1_000),
                     privacy_level: i.privacy_level,
+                    custom_tlvs: i.custom_tlvs,
                     ..invoice.into()
                 })
             }
@@ -563,6 +1095,7 @@ impl MutinyInvoice {
                     inbound,
                     labels,
                     last_updated: i.last_update,
+                    custom_tlvs: i.custom_tlvs,
                 };
                 Ok(invoice)
             }
@@ -570,6 +1103,51 @@ impl MutinyInvoice {
     }
 }
 
+/// An outbound payment broken down by the paths it was sent over,
+/// see [MutinyWallet::get_payment_details]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct PaymentDetails {
+    pub invoice: MutinyInvoice,
+    /// Every attempted path, including failed ones that were retried
+    pub paths: Vec<PaymentPath>,
+    pub parts_settled: usize,
+    pub parts_failed: usize,
+    /// Amount that has not settled yet while the payment is in flight.
+    /// LDK only reports paths once they resolve, so in flight parts are only known in aggregate.
+    pub in_flight_msat: u64,
+    /// Fees paid across all settled parts
+    pub fees_msat: u64,
+}
+
+impl PaymentDetails {
+    pub(crate) fn new(invoice: MutinyInvoice, paths: Vec<PaymentPath>) -> Self {
+        let settled = paths.iter().filter(|p| p.status == HTLCStatus::Succeeded);
+        let parts_settled = settled.clone().count();
+        let settled_msat: u64 = settled.clone().map(|p| p.amount_msat).sum();
+        let fees_msat = settled.map(|p| p.fee_msat).sum();
+        let parts_failed = paths
+            .iter()
+            .filter(|p| p.status == HTLCStatus::Failed)
+            .count();
+
+        let in_flight_msat = if invoice.status == HTLCStatus::InFlight {
+            let amount_msat = invoice.amount_sats.unwrap_or(0) * 1_000;
+            amount_msat.saturating_sub(settled_msat)
+        } else {
+            0
+        };
+
+        Self {
+            invoice,
+            paths,
+            parts_settled,
+            parts_failed,
+            in_flight_msat,
+            fees_msat,
+        }
+    }
+}
+
 /// FedimintSweepResult is the result of how much was swept and the fees paid.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FedimintSweepResult {
@@ -600,7 +1178,23 @@ pub struct MutinyWalletConfigBuilder {
     do_not_connect_peers: bool,
     skip_device_lock: bool,
     pub safe_mode: bool,
+    read_only: bool,
     skip_hodl_invoices: bool,
+    max_amountless_invoice_sats: Option<u64>,
+    allow_amountless_from_non_contacts: bool,
+    maintenance_pubkey: Option<::nostr::PublicKey>,
+    maintenance_url: Option<String>,
+    announce_channels: bool,
+    swap_provider_url: Option<String>,
+    ln_address_proxy_url: Option<String>,
+    fee_provider: Option<Arc<dyn FeeProvider + Send + Sync>>,
+    fee_estimator_config: Option<FeeEstimatorConfig>,
+    account_index: u32,
+    logging: LoggingConfig,
+    fast_start: bool,
+    disable_gossip: bool,
+    route_hint_config: Option<RouteHintConfig>,
+    gap_limit: Option<usize>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -650,7 +1244,23 @@ impl MutinyWalletConfigBuilder {
             do_not_connect_peers: false,
             skip_device_lock: false,
             safe_mode: false,
+            read_only: false,
             skip_hodl_invoices: true,
+            max_amountless_invoice_sats: None,
+            allow_amountless_from_non_contacts: false,
+            maintenance_pubkey: None,
+            maintenance_url: None,
+            announce_channels: false,
+            swap_provider_url: None,
+            ln_address_proxy_url: None,
+            fee_provider: None,
+            fee_estimator_config: None,
+            account_index: 0,
+            logging: LoggingConfig::default(),
+            fast_start: false,
+            disable_gossip: false,
+            route_hint_config: None,
+            gap_limit: None,
         }
     }
 
@@ -673,6 +1283,8 @@ impl MutinyWalletConfigBuilder {
         self.user_rgs_url = Some(user_rgs_url);
     }
 
+    /// Sets the LSP to use, either the url of a Voltage Flow LSP or the connection
+    /// string of any LSPS2 compliant LSP.
     pub fn with_lsp_url(&mut self, lsp_url: String) {
         self.lsp_url = Some(lsp_url);
     }
@@ -722,10 +1334,110 @@ impl MutinyWalletConfigBuilder {
         self.skip_device_lock = true;
     }
 
+    /// See [MutinyWalletBuilder::with_read_only]
+    pub fn with_read_only(&mut self) {
+        self.read_only = true;
+        self.safe_mode = true;
+        self.skip_device_lock = true;
+    }
+
     pub fn do_not_skip_hodl_invoices(&mut self) {
         self.skip_hodl_invoices = false;
     }
 
+    /// Caps the amount that can be paid to an invoice that does not specify an amount
+    pub fn with_max_amountless_invoice_sats(&mut self, max_sats: u64) {
+        self.max_amountless_invoice_sats = Some(max_sats);
+    }
+
+    /// Allows paying invoices without an amount that are not labeled with a contact
+    pub fn allow_amountless_invoices_from_non_contacts(&mut self) {
+        self.allow_amountless_from_non_contacts = true;
+    }
+
+    /// Key the service operator signs maintenance notices with.
+    /// Notices are only followed when this is set.
+    pub fn with_maintenance_pubkey(&mut self, maintenance_pubkey: ::nostr::PublicKey) {
+        self.maintenance_pubkey = Some(maintenance_pubkey);
+    }
+
+    /// URL serving the latest maintenance notice, otherwise it is fetched from nostr relays
+    pub fn with_maintenance_url(&mut self, maintenance_url: String) {
+        self.maintenance_url = Some(maintenance_url);
+    }
+
+    /// Open announced channels by default instead of private ones,
+    /// for embedders that want their nodes to be routable
+    pub fn with_announced_channels(&mut self) {
+        self.announce_channels = true;
+    }
+
+    /// URL of the submarine swap provider used to swap on-chain funds into lightning
+    pub fn with_swap_provider_url(&mut self, swap_provider_url: String) {
+        self.swap_provider_url = Some(swap_provider_url);
+    }
+
+    /// URL of the LNURL proxy that serves our lightning address, see [LnAddressClient]
+    pub fn with_ln_address_proxy_url(&mut self, ln_address_proxy_url: String) {
+        self.ln_address_proxy_url = Some(ln_address_proxy_url);
+    }
+
+    /// Where to get fee estimates from, defaults to our esplora server
+    pub fn with_fee_provider(&mut self, fee_provider: Arc<dyn FeeProvider + Send + Sync>) {
+        self.fee_provider = Some(fee_provider);
+    }
+
+    /// How often fee estimates are refreshed and when they are too old to trust
+    pub fn with_fee_estimator_config(&mut self, fee_estimator_config: FeeEstimatorConfig) {
+        self.fee_estimator_config = Some(fee_estimator_config);
+    }
+
+    /// BIP-32 account index used to derive the on-chain wallet and the node keys,
+    /// defaults to 0. Different accounts of the same seed are isolated wallets,
+    /// each one needs its own storage.
+    ///
+    /// The VSS, auth, federation and nostr keys come from the root key and are the same
+    /// for every account, so other accounts can't be used with VSS. Don't join the same
+    /// federation from two accounts of a seed, they would share its ecash.
+    pub fn with_account_index(&mut self, account_index: u32) {
+        self.account_index = account_index;
+    }
+
+    /// Lowest level of the logs we save to storage, defaults to trace
+    pub fn with_log_level(&mut self, level: Level) {
+        self.logging.level = level;
+    }
+
+    /// Number of log lines we keep in storage, older ones are dropped
+    pub fn with_max_log_items(&mut self, max_log_items: usize) {
+        self.logging.max_log_items = max_log_items;
+    }
+
+    /// Return the wallet as soon as storage and keys are loaded, the nodes, federations
+    /// and nostr are started in the background. See [MutinyWallet::ready]
+    pub fn with_fast_start(&mut self) {
+        self.fast_start = true;
+    }
+
+    /// Don't download the network graph or remote scores, payments are only
+    /// routed through our channels and the route hints of the invoice.
+    /// This saves a lot of bandwidth for wallets that only pay through their LSP.
+    pub fn with_gossip_disabled(&mut self) {
+        self.disable_gossip = true;
+    }
+
+    /// How the route hints of our invoices are picked, defaults to LDK's selection.
+    /// Invoices can override it with [InvoiceParams::route_hints].
+    pub fn with_route_hint_config(&mut self, route_hint_config: RouteHintConfig) {
+        self.route_hint_config = Some(route_hint_config);
+    }
+
+    /// How many unused addresses in a row are checked when syncing after a restore,
+    /// defaults to 20. Wallets that handed out many unused addresses need a higher one.
+    pub fn with_gap_limit(&mut self, gap_limit: usize) {
+        self.gap_limit = Some(gap_limit);
+    }
+
     pub fn build(self) -> MutinyWalletConfig {
         let network = self.network.expect("network is required");
 
@@ -748,7 +1460,23 @@ impl MutinyWalletConfigBuilder {
             do_not_connect_peers: self.do_not_connect_peers,
             skip_device_lock: self.skip_device_lock,
             safe_mode: self.safe_mode,
+            read_only: self.read_only,
             skip_hodl_invoices: self.skip_hodl_invoices,
+            max_amountless_invoice_sats: self.max_amountless_invoice_sats,
+            allow_amountless_from_non_contacts: self.allow_amountless_from_non_contacts,
+            maintenance_pubkey: self.maintenance_pubkey,
+            maintenance_url: self.maintenance_url,
+            announce_channels: self.announce_channels,
+            swap_provider_url: self.swap_provider_url,
+            ln_address_proxy_url: self.ln_address_proxy_url,
+            fee_provider: self.fee_provider,
+            fee_estimator_config: self.fee_estimator_config,
+            account_index: self.account_index,
+            logging: self.logging,
+            fast_start: self.fast_start,
+            disable_gossip: self.disable_gossip,
+            route_hint_config: self.route_hint_config,
+            gap_limit: self.gap_limit,
         }
     }
 }
@@ -773,7 +1501,23 @@ pub struct MutinyWalletConfig {
     do_not_connect_peers: bool,
     skip_device_lock: bool,
     pub safe_mode: bool,
+    read_only: bool,
     skip_hodl_invoices: bool,
+    max_amountless_invoice_sats: Option<u64>,
+    allow_amountless_from_non_contacts: bool,
+    maintenance_pubkey: Option<::nostr::PublicKey>,
+    maintenance_url: Option<String>,
+    announce_channels: bool,
+    swap_provider_url: Option<String>,
+    ln_address_proxy_url: Option<String>,
+    fee_provider: Option<Arc<dyn FeeProvider + Send + Sync>>,
+    fee_estimator_config: Option<FeeEstimatorConfig>,
+    account_index: u32,
+    logging: LoggingConfig,
+    fast_start: bool,
+    disable_gossip: bool,
+    route_hint_config: Option<RouteHintConfig>,
+    gap_limit: Option<usize>,
 }
 
 pub struct MutinyWalletBuilder<S: MutinyStorage> {
@@ -791,9 +1535,25 @@ pub struct MutinyWalletBuilder<S: MutinyStorage> {
     skip_hodl_invoices: bool,
     skip_device_lock: bool,
     safe_mode: bool,
+    read_only: bool,
 }
 
 impl<S: MutinyStorage> MutinyWalletBuilder<S> {
+    /// Creates a builder for the wallet of the mnemonic with the given BIP-39 passphrase.
+    ///
+    /// The passphrase isn't stored, it has to be given every time the wallet is started.
+    pub fn from_mnemonic(
+        mnemonic: &Mnemonic,
+        passphrase: Option<&str>,
+        network: Network,
+        storage: S,
+    ) -> Result<MutinyWalletBuilder<S>, MutinyError> {
+        let xprivkey = xprivkey_from_mnemonic(mnemonic, passphrase, network)?;
+        let mut builder = Self::new(xprivkey, storage);
+        builder.with_network(network);
+        Ok(builder)
+    }
+
     pub fn new(xprivkey: ExtendedPrivKey, storage: S) -> MutinyWalletBuilder<S> {
         MutinyWalletBuilder::<S> {
             xprivkey,
@@ -809,6 +1569,7 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             do_not_connect_peers: false,
             skip_device_lock: false,
             safe_mode: false,
+            read_only: false,
             skip_hodl_invoices: true,
         }
     }
@@ -819,6 +1580,7 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         self.skip_hodl_invoices = config.skip_hodl_invoices;
         self.skip_device_lock = config.skip_device_lock;
         self.safe_mode = config.safe_mode;
+        self.read_only = config.read_only;
         self.auth_client = config.auth_client.clone();
         self.subscription_url = config.subscription_url.clone();
         self.blind_auth_url = config.blind_auth_url.clone();
@@ -872,16 +1634,34 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         self.skip_device_lock = true;
     }
 
+    /// Opens the wallet without taking the device lock, so it can be viewed while it is
+    /// running somewhere else. All writes to the storage are blocked and nothing can be
+    /// signed or sent. The nodes are not started, lightning balances are read from the
+    /// channel state in storage.
+    pub fn with_read_only(&mut self) {
+        self.read_only = true;
+        self.safe_mode = true;
+        self.skip_device_lock = true;
+    }
+
     pub async fn build(self) -> Result<MutinyWallet<S>, MutinyError> {
+        let startup = Instant::now();
         let network = self
             .network
             .map_or_else(|| Err(MutinyError::InvalidArgumentsError), Ok)?;
-        let config = self.config.unwrap_or(
+        let mut config = self.config.unwrap_or(
             MutinyWalletConfigBuilder::new(self.xprivkey)
                 .with_network(network)
                 .build(),
         );
 
+        if self.read_only {
+            self.storage.set_read_only();
+            config.read_only = true;
+            config.safe_mode = true;
+            config.skip_device_lock = true;
+        }
+
         let expected_network = self.storage.get::<Network>(EXPECTED_NETWORK_KEY)?;
         match expected_network {
             Some(n) => {
@@ -894,12 +1674,40 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
                 .set_data(EXPECTED_NETWORK_KEY.to_string(), self.network, None)?,
         }
 
+        // a different BIP-39 passphrase gives a different wallet,
+        // don't start it on top of the state of this one
+        let fingerprint = self.xprivkey.fingerprint(&Secp256k1::new());
+        match self.storage.get_data::<Fingerprint>(ROOT_FINGERPRINT_KEY)? {
+            Some(f) if f != fingerprint => return Err(MutinyError::IncorrectPassphrase),
+            Some(_) => {}
+            None => self
+                .storage
+                .set_data(ROOT_FINGERPRINT_KEY.to_string(), fingerprint, None)?,
+        }
+
+        // VSS is keyed by the root key, every account would write over the others
+        if config.account_index != 0 && self.storage.vss_client().is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        // the storage only holds the state of a single account
+        match self.storage.get_data::<u32>(ACCOUNT_INDEX_KEY)? {
+            Some(i) if i != config.account_index => return Err(MutinyError::InvalidArgumentsError),
+            Some(_) => {}
+            None => {
+                self.storage
+                    .set_data(ACCOUNT_INDEX_KEY.to_string(), config.account_index, None)?
+            }
+        }
+
         let stop = Arc::new(AtomicBool::new(false));
         let logger = Arc::new(MutinyLogger::with_writer(
             stop.clone(),
             self.storage.clone(),
             self.session_id,
+            config.logging,
         ));
+        let performance = Arc::new(PerformanceTracker::default());
 
         // Need to prevent other devices from running at the same time
         log_trace!(logger, "checking device lock");
@@ -915,26 +1723,33 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
                 "Device lock set: took {}ms",
                 start.elapsed().as_millis()
             );
+            performance.record(Operation::DeviceLock, start, true);
         }
         log_trace!(logger, "finished checking device lock");
 
         // spawn thread to claim device lock
-        log_trace!(logger, "spawning claim device lock");
-        let storage_clone = self.storage.clone();
-        let logger_clone = logger.clone();
-        let stop_clone = stop.clone();
-        spawn(async move {
-            loop {
-                if stop_clone.load(Ordering::Relaxed) {
-                    break;
-                }
-                sleep((DEVICE_LOCK_INTERVAL_SECS * 1_000) as i32).await;
-                if let Err(e) = storage_clone.set_device_lock().await {
-                    log_error!(logger_clone, "Error setting device lock: {e}");
+        if !self.read_only {
+            log_trace!(logger, "spawning claim device lock");
+            let storage_clone = self.storage.clone();
+            let logger_clone = logger.clone();
+            let stop_clone = stop.clone();
+            spawn(async move {
+                loop {
+                    if stop_clone.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    sleep((DEVICE_LOCK_INTERVAL_SECS * 1_000) as i32).await;
+                    // don't take the lock back after it was handed off
+                    if stop_clone.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Err(e) = storage_clone.set_device_lock().await {
+                        log_error!(logger_clone, "Error setting device lock: {e}");
+                    }
                 }
-            }
-        });
-        log_trace!(logger, "finished spawning claim device lock");
+            });
+            log_trace!(logger, "finished spawning claim device lock");
+        }
 
         log_trace!(logger, "setting up esplora");
         let esplora_server_url = get_esplora_url(network, config.user_esplora_url.clone());
@@ -948,7 +1763,9 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             .with_config(config.clone());
         nm_builder.with_logger(logger.clone());
         nm_builder.with_esplora(esplora.clone());
+        nm_builder.with_performance(performance.clone());
         let node_manager = Arc::new(nm_builder.build().await?);
+        performance.record(Operation::NodeManagerInit, start, true);
 
         log_trace!(
             logger,
@@ -987,35 +1804,42 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         );
         log_trace!(logger, "finished creating nostr client");
 
-        // connect to relays when not in tests
+        let payment_requests = Arc::new(PaymentRequestInbox::new(nostr.clone())?);
+
+        // connect to relays when not in tests, with fast start this is done in the background
         #[cfg(not(test))]
-        nostr.connect().await?;
+        if !config.fast_start {
+            nostr.connect().await?;
+        }
 
         // create federation module if any exist
         log_trace!(logger, "creating federation modules");
         let federation_storage = self.storage.get_federations()?;
-        let federations = if !federation_storage.federations.is_empty() {
-            let start = Instant::now();
-            log_trace!(logger, "Building Federations");
-            let result = create_federations(
-                federation_storage.clone(),
-                &config,
-                self.storage.clone(),
-                esplora.clone(),
-                stop.clone(),
-                &logger,
-                self.safe_mode,
-            )
-            .await?;
-            log_debug!(
-                logger,
-                "Federations started, took: {}ms",
-                start.elapsed().as_millis()
-            );
-            result
-        } else {
-            Arc::new(RwLock::new(HashMap::new()))
-        };
+        let (federations, federation_init_statuses) =
+            if !federation_storage.federations.is_empty() && !config.fast_start {
+                let start = Instant::now();
+                log_trace!(logger, "Building Federations");
+                let (federations, statuses) = create_federations(
+                    federation_storage.clone(),
+                    &config,
+                    self.storage.clone(),
+                    esplora.clone(),
+                    stop.clone(),
+                    &logger,
+                    self.safe_mode,
+                )
+                .await;
+                log_debug!(
+                    logger,
+                    "Federations started, took: {}ms",
+                    start.elapsed().as_millis()
+                );
+                let success = statuses.iter().all(|s| s.error.is_none());
+                performance.record(Operation::FederationsInit, start, success);
+                (federations, statuses)
+            } else {
+                (Arc::new(RwLock::new(HashMap::new())), vec![])
+            };
         let federation_storage = Arc::new(RwLock::new(federation_storage));
         log_trace!(logger, "finished creating federation modules");
 
@@ -1036,6 +1860,11 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         );
         log_trace!(logger, "finished creating lnurl client");
 
+        let swap_client = config
+            .swap_provider_url
+            .clone()
+            .map(|url| Arc::new(SwapClient::new(url)));
+
         // auth manager, take from auth_client if it already exists
         log_trace!(logger, "creating auth manager");
         let auth = if let Some(auth_client) = self.auth_client.clone() {
@@ -1109,6 +1938,13 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         };
         log_trace!(logger, "finished creating hermes client");
 
+        let ln_address_client = config
+            .ln_address_proxy_url
+            .clone()
+            .map(|url| LnAddressClient::new(self.xprivkey, url, &self.storage, logger.clone()))
+            .transpose()?
+            .map(Arc::new);
+
         // populate the activity index
         log_trace!(logger, "populating activity index");
         let mut activity_index = node_manager
@@ -1124,67 +1960,56 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             })
             .collect::<Vec<_>>();
 
-        // add any transaction details stored from fedimint
-        let transaction_details = self
-            .storage
-            .scan::<TransactionDetails>(TRANSACTION_DETAILS_PREFIX_KEY, None)?
-            .into_iter()
-            .map(|(k, v)| {
-                let timestamp = match v.confirmation_time {
-                    ConfirmationTime::Confirmed { height: _, time } => Some(time), // confirmed timestamp
-                    ConfirmationTime::Unconfirmed { .. } => None, // unconfirmed timestamp
-                };
-                IndexItem { timestamp, key: k }
-            })
-            .collect::<Vec<_>>();
-        activity_index.extend(transaction_details);
-
-        // add the channel closures to the activity index
-        let closures = self
+        // the rest of the activity is in the stored index, wallets that didn't store it yet
+        // need a one-time migration from the index kept in one value, or from the stored activity
+        let migrate_index = self
             .storage
-            .scan::<ChannelClosure>(CHANNEL_CLOSURE_PREFIX, None)?
-            .into_iter()
-            .map(|(k, v)| IndexItem {
-                timestamp: Some(v.timestamp),
-                key: k,
-            })
-            .collect::<Vec<_>>();
-        activity_index.extend(closures);
-
-        // add inbound invoices to the activity index
-        let inbound = self
-            .storage
-            .scan::<PaymentInfo>(PAYMENT_INBOUND_PREFIX_KEY, None)?
-            .into_iter()
-            .filter(|(_, p)| matches!(p.status, HTLCStatus::Succeeded | HTLCStatus::InFlight))
-            .map(|(k, v)| IndexItem {
-                timestamp: Some(v.last_update),
-                key: k,
-            })
-            .collect::<Vec<_>>();
-
-        let outbound = self
-            .storage
-            .scan::<PaymentInfo>(PAYMENT_OUTBOUND_PREFIX_KEY, None)?
-            .into_iter()
-            .filter(|(_, p)| matches!(p.status, HTLCStatus::Succeeded | HTLCStatus::InFlight))
-            .map(|(k, v)| IndexItem {
-                timestamp: Some(v.last_update),
-                key: k,
-            })
-            .collect::<Vec<_>>();
-
-        activity_index.extend(inbound);
-        activity_index.extend(outbound);
+            .get_data::<bool>(ACTIVITY_INDEX_BUILT_KEY)?
+            .is_none();
+        if migrate_index {
+            log_info!(logger, "Migrating activity index");
+            match self
+                .storage
+                .get_data::<Vec<IndexItem>>(LEGACY_ACTIVITY_INDEX_KEY)?
+            {
+                Some(items) => {
+                    let items = items
+                        .into_iter()
+                        .filter(|i| !i.key.starts_with(ONCHAIN_PREFIX));
+                    activity_index.extend(items);
+                }
+                None => activity_index.extend(build_activity_index(&self.storage)?),
+            }
+        } else {
+            activity_index.extend(get_activity_index(&self.storage)?);
+        }
 
         // add the activity index to the storage
-        {
+        if migrate_index && !self.storage.is_read_only() {
+            self.storage
+                .update_activity_index(|index| index.extend(activity_index))?;
+            self.storage
+                .set_data(ACTIVITY_INDEX_BUILT_KEY.to_string(), true, None)?;
+            self.storage.delete(&[LEGACY_ACTIVITY_INDEX_KEY])?;
+        } else {
             let index = self.storage.activity_index();
             let mut read = index.try_write()?;
             read.extend(activity_index);
         }
         log_trace!(logger, "finished populating activity index");
 
+        // the search index is kept up to date as things are written,
+        // wallets from before it was kept need it built once
+        if self
+            .storage
+            .get_data::<bool>(SEARCH_INDEX_BUILT_KEY)?
+            .is_none()
+            && !self.storage.is_read_only()
+        {
+            log_info!(logger, "Building search index");
+            search::build_search_index(&self.storage)?;
+        }
+
         log_trace!(logger, "creating price cache");
         let price_cache = self
             .storage
@@ -1194,6 +2019,8 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             .collect();
         log_trace!(logger, "finished creating price cache");
 
+        let readiness = Arc::new(Readiness::new(config.fast_start && !self.safe_mode));
+
         log_trace!(logger, "creating mutiny wallet");
         let mw = MutinyWallet {
             xprivkey: self.xprivkey,
@@ -1201,12 +2028,16 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             storage: self.storage,
             node_manager,
             nostr,
+            payment_requests,
             federation_storage,
             federations,
+            federation_init_statuses: Arc::new(RwLock::new(federation_init_statuses)),
             lnurl_client,
             subscription_client,
             blind_auth_client,
             hermes_client,
+            ln_address_client,
+            swap_client,
             esplora,
             auth,
             stop,
@@ -1216,37 +2047,107 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             safe_mode: self.safe_mode,
             cashu_client: CashuHttpClient::new(),
             bitcoin_price_cache: Arc::new(Mutex::new(price_cache)),
+            background: Arc::new(AtomicBool::new(false)),
+            chain_cache: Arc::new(Mutex::new(ChainCache::default())),
+            maintenance: Arc::new(RwLock::new(None)),
+            maintenance_events: Arc::new(Mutex::new(vec![])),
+            device_handoff_events: Arc::new(Mutex::new(vec![])),
+            subscription_events: Arc::new(Mutex::new(vec![])),
+            readiness,
         };
         log_trace!(logger, "finished creating mutiny wallet");
         // if we are in safe mode, don't create any nodes or
         // start any nostr services
         if self.safe_mode {
+            performance.record(Operation::Startup, startup, true);
             return Ok(mw);
         }
 
-        // if we don't have any nodes, create one
-        log_trace!(logger, "listing nodes");
-        if mw.node_manager.list_nodes().await?.is_empty() {
-            log_trace!(logger, "going to create first node");
-            let nm = mw.node_manager.clone();
-            // spawn in background, this can take a while and we don't want to block
-            utils::spawn(async move {
-                if let Err(e) = nm.new_node().await {
-                    log_error!(nm.logger, "Failed to create first node: {e}");
-                }
-            })
-        };
-        log_trace!(logger, "finished listing nodes");
+        if mw.config.fast_start {
+            log_trace!(logger, "starting subsystems in the background");
+            mw.start_subsystems_in_background();
+        } else {
+            // if we don't have any nodes, create one. A node that failed to
+            // start still counts, we don't want to replace it with a new one
+            log_trace!(logger, "listing nodes");
+            let no_nodes = mw.node_manager.list_nodes().await?.is_empty()
+                && mw.node_manager.list_failed_nodes().await.is_empty();
+            if no_nodes {
+                log_trace!(logger, "going to create first node");
+                let nm = mw.node_manager.clone();
+                // spawn in background, this can take a while and we don't want to block
+                utils::spawn(async move {
+                    if let Err(e) = nm.new_node().await {
+                        log_error!(nm.logger, "Failed to create first node: {e}");
+                    }
+                })
+            };
+            log_trace!(logger, "finished listing nodes");
+
+            // start the nostr background process
+            log_trace!(logger, "starting nostr");
+            mw.start_nostr().await;
+            log_trace!(logger, "finished starting nostr");
 
-        // start the nostr background process
-        log_trace!(logger, "starting nostr");
-        mw.start_nostr().await;
-        log_trace!(logger, "finished starting nostr");
+            // start the federation background processor
+            log_trace!(logger, "starting fedimint background checker");
+            mw.start_fedimint_background_checker().await;
+            log_trace!(logger, "finished starting fedimint background checker");
+        }
 
-        // start the federation background processor
-        log_trace!(logger, "starting fedimint background checker");
-        mw.start_fedimint_background_checker().await;
-        log_trace!(logger, "finished starting fedimint background checker");
+        // start the federation backups
+        log_trace!(logger, "starting federation backups");
+        mw.start_federation_backups();
+        log_trace!(logger, "finished starting federation backups");
+
+        // start following the operator's maintenance notices
+        log_trace!(logger, "starting maintenance checker");
+        mw.start_maintenance_checker();
+        log_trace!(logger, "finished starting maintenance checker");
+
+        // start following our pending swaps
+        log_trace!(logger, "starting swap checker");
+        mw.start_swap_checker();
+        log_trace!(logger, "finished starting swap checker");
+
+        // start the scheduled remote backups
+        log_trace!(logger, "starting remote backup checker");
+        mw.start_remote_backup_checker();
+        log_trace!(logger, "finished starting remote backup checker");
+
+        // keep the backup our LSP holds for us up to date
+        log_trace!(logger, "starting peer storage checker");
+        mw.start_peer_storage_checker();
+        log_trace!(logger, "finished starting peer storage checker");
+
+        // back up our contacts, labels and NWC profiles to nostr when not using VSS
+        log_trace!(logger, "starting social backup checker");
+        mw.start_social_backup_checker();
+        log_trace!(logger, "finished starting social backup checker");
+
+        // start the automatic storage compaction
+        log_trace!(logger, "starting compaction checker");
+        mw.start_compaction_checker();
+        log_trace!(logger, "finished starting compaction checker");
+
+        log_trace!(logger, "starting device handoff checker");
+        mw.start_device_handoff_checker();
+        log_trace!(logger, "finished starting device handoff checker");
+
+        // remind of upcoming subscription renewals
+        log_trace!(logger, "starting subscription checker");
+        mw.start_subscription_checker();
+        log_trace!(logger, "finished starting subscription checker");
+
+        // follow up on LNURL withdrawals until they are paid
+        log_trace!(logger, "starting withdrawal checker");
+        mw.start_withdrawal_checker();
+        log_trace!(logger, "finished starting withdrawal checker");
+
+        // record the price of new activity for its fiat value
+        log_trace!(logger, "starting price checker");
+        mw.start_price_checker();
+        log_trace!(logger, "finished starting price checker");
 
         // start the blind auth fetching process
         log_trace!(logger, "checking blind tokens");
@@ -1254,24 +2155,22 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         log_trace!(logger, "finsihed checking blind tokens");
 
         // start the hermes background process
-        // get profile key if we have it, we need this to decrypt private zaps
-        log_trace!(logger, "getting nostr profile key");
-        let profile_key = match &mw.nostr.nostr_keys.read().await.signer {
-            NostrSigner::Keys(keys) => Some(keys.clone()),
-            #[cfg(target_arch = "wasm32")]
-            NostrSigner::NIP07(_) => None,
-        };
-        log_trace!(logger, "finished getting nostr profile key");
+        if !mw.config.fast_start {
+            log_trace!(logger, "starting hermes");
+            mw.start_hermes(mw.nostr_profile_key().await).await?;
+            log_trace!(logger, "finished starting hermes");
+        }
 
-        log_trace!(logger, "starting hermes");
-        mw.start_hermes(profile_key).await?;
-        log_trace!(logger, "finished starting hermes");
+        log_trace!(logger, "starting lightning address listener");
+        mw.start_ln_address_listener();
+        log_trace!(logger, "finished starting lightning address listener");
 
         log_info!(
             mw.logger,
             "Final setup took {}ms",
             start.elapsed().as_millis()
         );
+        performance.record(Operation::Startup, startup, true);
 
         Ok(mw)
     }
@@ -1287,13 +2186,19 @@ pub struct MutinyWallet<S: MutinyStorage> {
     pub(crate) storage: S,
     pub node_manager: Arc<NodeManager<S>>,
     pub nostr: Arc<NostrManager<S, PrimalClient, nostr_sdk::Client>>,
+    /// Payments waiting for approval, see [MutinyWallet::list_pending_requests]
+    pub payment_requests: Arc<PaymentRequestInbox<S, PrimalClient, nostr_sdk::Client>>,
     pub federation_storage: Arc<RwLock<FederationStorage>>,
     pub(crate) federations: Arc<RwLock<HashMap<FederationId, Arc<FederationClient<S>>>>>,
+    /// See [MutinyWallet::get_federation_init_statuses]
+    federation_init_statuses: Arc<RwLock<Vec<FederationInitStatus>>>,
     lnurl_client: Arc<LnUrlClient>,
     auth: AuthManager,
     subscription_client: Option<Arc<MutinySubscriptionClient>>,
     blind_auth_client: Option<Arc<BlindAuthClient<S>>>,
     hermes_client: Option<Arc<HermesClient<S>>>,
+    ln_address_client: Option<Arc<LnAddressClient<S>>>,
+    swap_client: Option<Arc<SwapClient>>,
     esplora: Arc<AsyncClient>,
     pub stop: Arc<AtomicBool>,
     pub logger: Arc<MutinyLogger>,
@@ -1302,6 +2207,19 @@ pub struct MutinyWallet<S: MutinyStorage> {
     safe_mode: bool,
     cashu_client: CashuHttpClient,
     bitcoin_price_cache: Arc<Mutex<HashMap<String, (f32, Duration)>>>,
+    /// If the app is in the background, see [PowerMode]
+    background: Arc<AtomicBool>,
+    chain_cache: Arc<Mutex<ChainCache>>,
+    /// The operator's current maintenance notice, see [MutinyWallet::get_maintenance_notice]
+    maintenance: Arc<RwLock<Option<MaintenanceNotice>>>,
+    /// See [MutinyWallet::take_maintenance_events]
+    maintenance_events: Arc<Mutex<Vec<MaintenanceEvent>>>,
+    /// See [MutinyWallet::take_device_handoff_events]
+    device_handoff_events: Arc<Mutex<Vec<DeviceHandoffEvent>>>,
+    /// See [MutinyWallet::take_subscription_events]
+    subscription_events: Arc<Mutex<Vec<SubscriptionEvent>>>,
+    /// Subsystems still starting in the background, see [MutinyWallet::ready]
+    readiness: Arc<Readiness>,
 }
 
 impl<S: MutinyStorage> MutinyWallet<S> {
@@ -1315,15 +2233,45 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let mut nm_builder = NodeManagerBuilder::new(self.xprivkey, self.storage.clone())
             .with_config(self.config.clone());
         nm_builder.with_logger(self.logger.clone());
+        nm_builder.with_performance(self.node_manager.performance.clone());
 
         // when we restart, gen a new session id
-        self.node_manager = Arc::new(nm_builder.build().await?);
+        let node_manager = nm_builder.build().await?;
+        // on restarts the nodes are started right away, even with fast start
+        if self.config.fast_start && !self.config.safe_mode {
+            node_manager.start_nodes().await?;
+        }
+        self.node_manager = Arc::new(node_manager);
+        self.node_manager
+            .set_background(self.background.load(Ordering::Relaxed));
         NodeManager::start_sync(self.node_manager.clone());
 
         log_trace!(self.logger, "finished calling start");
         Ok(())
     }
 
+    /// Sets the power mode of the wallet. In [PowerMode::Background] we sync less often,
+    /// pause gossip syncing, and only listen for NWC requests on nostr.
+    /// Switching back to [PowerMode::Foreground] resumes everything right away.
+    pub fn set_power_mode(&self, mode: PowerMode) {
+        log_trace!(self.logger, "calling set_power_mode");
+
+        let background = mode == PowerMode::Background;
+        self.background.store(background, Ordering::Relaxed);
+        self.node_manager.set_background(background);
+
+        log_trace!(self.logger, "finished calling set_power_mode");
+    }
+
+    /// Gets the current power mode of the wallet
+    pub fn get_power_mode(&self) -> PowerMode {
+        if self.background.load(Ordering::Relaxed) {
+            PowerMode::Background
+        } else {
+            PowerMode::Foreground
+        }
+    }
+
     /// Starts a background process that will watch for nostr events
     pub(crate) async fn start_nostr(&self) {
         log_trace!(self.logger, "calling start_nostr");
@@ -1332,16 +2280,26 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let nostr = self.nostr.clone();
         let logger = self.logger.clone();
         let stop = self.stop.clone();
+        let background = self.background.clone();
         let self_clone = self.clone();
         utils::spawn(async move {
+            // one client for as long as its relay pool is up, so we don't reconnect
+            // to every relay and resend every filter each time something changes
+            let mut listener = NostrListener::new();
             loop {
                 if stop.load(Ordering::Relaxed) {
                     break;
                 };
 
+                // in the background, only listen for NWC requests
+                let mut background_mode = background.load(Ordering::Relaxed);
+
                 // if we have no filters, then wait 10 seconds and see if we do again
-                let mut last_filters = nostr.get_filters().await.unwrap_or_default();
-                if last_filters.is_empty() {
+                let filters = nostr
+                    .get_filters_for_mode(background_mode)
+                    .await
+                    .unwrap_or_default();
+                if dedup_filters(filters.clone()).is_empty() {
                     utils::sleep(10_000).await;
                     continue;
                 }
@@ -1365,20 +2323,20 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     log_warn!(logger, "Failed to clear invalid NWC invoices: {e}");
                 }
 
-                let client = nostr_sdk::Client::default();
-
-                client
-                    .add_relays(nostr.get_relays())
-                    .await
-                    .expect("Failed to add relays");
-                client.connect().await;
-
-                client.subscribe(last_filters.clone(), None).await;
+                if let Err(e) = listener.connect(nostr.get_relays()).await {
+                    log_warn!(logger, "Failed to add relays: {e}");
+                    utils::sleep(10_000).await;
+                    continue;
+                }
+                listener.update_filters(filters).await;
 
                 // handle NWC requests
-                let mut notifications = client.notifications();
+                let mut notifications = listener.notifications();
 
-                let mut next_filter_check = crate::utils::now().as_secs() + 5;
+                let mut filter_check_interval = if background_mode { 60 } else { 5 };
+                let mut next_filter_check = crate::utils::now().as_secs() + filter_check_interval;
+                // if the relay pool shut down, we need a new client to reconnect
+                let mut shutdown = false;
                 loop {
                     let read_fut = notifications.recv().fuse();
                     let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
@@ -1396,86 +2354,98 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     pin_mut!(read_fut, delay_fut, filter_check_fut);
                     select! {
                         notification = read_fut => {
+                            let mut events = vec![];
+                            let mut lagged = None;
                             match notification {
-                                Ok(RelayPoolNotification::Event { event, .. }) => {
-                                    if event.verify().is_ok() {
-                                        match event.kind {
-                                            Kind::WalletConnectRequest => {
-                                                match nostr.handle_nwc_request(*event, &self_clone).await {
-                                                    Ok(Some(event)) => {
-                                                        if let Err(e) = client.send_event(event).await {
-                                                            log_warn!(logger, "Error sending NWC event: {e}");
-                                                        }
-                                                    }
-                                                    Ok(None) => {} // no response
-                                                    Err(e) => {
-                                                        log_error!(logger, "Error handling NWC request: {e}");
-                                                    }
-                                                }
-                                            }
-                                            Kind::EncryptedDirectMessage => {
-                                                if let Err(e) = nostr.handle_direct_message(*event, &self_clone).await {
-                                                        log_error!(logger, "Error handling dm: {e}");
-                                                }
-                                            }
-                                            Kind::ContactList => {
-                                                let event_pk = event.pubkey;
-                                                match update_nostr_contact_list(&nostr.storage, *event) {
-                                                    Err(e) =>log_error!(logger, "Error handling contact list: {e}"),
-                                                    Ok(true) => {
-                                                        log_debug!(logger, "Got new contact list, syncing...");
-
-                                                        // sync in background so we don't block processing other events
-                                                        let self_clone = self_clone.clone();
-                                                        utils::spawn(async move {
-                                                            match self_clone.sync_nostr_contacts(event_pk).await {
-                                                                Err(e) => log_error!(self_clone.logger, "Failed to sync nostr: {e}"),
-                                                                Ok(_) => log_debug!(self_clone.logger, "Successfully synced nostr contacts"),
-                                                            }
-                                                        });
-                                                    }
-                                                    Ok(false) => log_debug!(logger, "Got older contact list, ignoring..."),
-                                                }
-                                            }
-                                            kind => {
-                                                // ignore federation announcement events
-                                                if kind.as_u64() != 38000 && kind.as_u64() != 38173 {
-                                                    log_warn!(logger, "Received unexpected note of kind {kind}");
-                                                }
-                                            }
-                                        }
+                                Ok(RelayPoolNotification::Event { event, .. }) => events.push(event),
+                                Ok(RelayPoolNotification::Shutdown) => shutdown = true,
+                                Ok(_) => {} // ignore messages and relay status
+                                Err(RecvError::Lagged(skipped)) => lagged = Some(skipped),
+                                Err(RecvError::Closed) => shutdown = true,
+                            }
+                            if shutdown {
+                                break;
+                            }
+
+                            // handle the events that are already waiting in the same go
+                            while !events.is_empty() && events.len() < MAX_EVENT_BATCH {
+                                match notifications.try_recv() {
+                                    Ok(RelayPoolNotification::Event { event, .. }) => events.push(event),
+                                    Ok(RelayPoolNotification::Shutdown) => {
+                                        shutdown = true;
+                                        break;
+                                    }
+                                    Ok(_) => {}
+                                    Err(TryRecvError::Lagged(skipped)) => {
+                                        lagged = Some(skipped);
+                                        break;
+                                    }
+                                    Err(TryRecvError::Closed) => {
+                                        shutdown = true;
+                                        break;
                                     }
-                                },
-                                Ok(RelayPoolNotification::Message { .. }) => {}, // ignore messages
-                                Ok(RelayPoolNotification::Shutdown) => break, // if we disconnect, we restart to reconnect
-                                Ok(RelayPoolNotification::Stop) => {}, // Currently unused
-                                Ok(RelayPoolNotification::RelayStatus { .. }) => {}, // Currently unused
-                                Err(_) => break, // if we are erroring we should reconnect
+                                    Err(TryRecvError::Empty) => break,
+                                }
+                            }
+                            if !events.is_empty() {
+                                self_clone.handle_nostr_events(events).await;
+                            }
+                            if let Some(skipped) = lagged {
+                                // we fell behind the relays, get what we missed again
+                                // from our last sync times instead of reconnecting
+                                log_warn!(logger, "Skipped {skipped} nostr notifications, resubscribing");
+                                if let Ok(filters) = nostr.get_filters_for_mode(background_mode).await {
+                                    listener.resubscribe(filters).await;
+                                }
+                            }
+                            if shutdown {
+                                break;
                             }
                         }
                         _ = delay_fut => {
                             if stop.load(Ordering::Relaxed) {
                                 break;
                             }
+                            // switch to the filters of the new power mode right away
+                            if background.load(Ordering::Relaxed) != background_mode {
+                                log_debug!(logger, "power mode changed, updating filters");
+                                background_mode = !background_mode;
+                                filter_check_interval = if background_mode { 60 } else { 5 };
+                                next_filter_check = current_time;
+                            }
                         }
                         _ = filter_check_fut => {
-                            // Check if the filters have changed
-                            if let Ok(current_filters) = nostr.get_filters().await {
-                                if !utils::compare_filters_vec(&current_filters, &last_filters) {
-                                    log_debug!(logger, "subscribing to new nwc filters");
-                                    client.subscribe(current_filters.clone(), None).await;
-                                    last_filters = current_filters;
+                            // Check if the filters have changed, only then the relays get them
+                            if let Ok(current_filters) = nostr.get_filters_for_mode(background_mode).await {
+                                // restored or new NWC profiles can use relays we don't have yet,
+                                // those only get our subscription when it is sent again
+                                let new_relays = match listener.connect(nostr.get_relays()).await {
+                                    Ok(new_relays) => new_relays,
+                                    Err(e) => {
+                                        log_warn!(logger, "Failed to add relays: {e}");
+                                        false
+                                    }
+                                };
+                                if new_relays {
+                                    listener.resubscribe(current_filters).await;
+                                    log_debug!(logger, "subscribed to nostr filters on new relays");
+                                } else if listener.update_filters(current_filters).await {
+                                    log_debug!(logger, "subscribed to new nostr filters");
                                 }
                             }
+                            // try again to publish the events no relay acknowledged yet
+                            nostr.flush_outbox().await;
                             // Set the time for the next filter check
-                            next_filter_check = crate::utils::now().as_secs() + 5;
+                            next_filter_check = crate::utils::now().as_secs() + filter_check_interval;
                         }
                     }
                 }
 
-                if let Err(e) = client.disconnect().await {
+                if let Err(e) = listener.disconnect().await {
                     log_warn!(logger, "Error disconnecting from relays: {e}");
                 }
+                // a relay pool that shut down can't be connected again
+                listener = NostrListener::new();
             }
         });
 
@@ -1506,6 +2476,73 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         log_trace!(self.logger, "finished calling start_nostr");
     }
 
+    /// Handles a batch of events from our nostr subscription,
+    /// the responses to NWC requests are published over the shared relay pool.
+    async fn handle_nostr_events(&self, events: Vec<Box<::nostr::Event>>) {
+        let mut check_requests = false;
+        for event in events {
+            if event.verify().is_err() {
+                continue;
+            }
+
+            match event.kind {
+                Kind::WalletConnectRequest => {
+                    match self.nostr.handle_nwc_request(*event, self).await {
+                        Ok(Some(event)) => {
+                            self.nostr.publish_event(event).await;
+                        }
+                        Ok(None) => {} // no response
+                        Err(e) => log_error!(self.logger, "Error handling NWC request: {e}"),
+                    }
+                    check_requests = true;
+                }
+                Kind::EncryptedDirectMessage => {
+                    if let Err(e) = self.nostr.handle_direct_message(*event, self).await {
+                        log_error!(self.logger, "Error handling dm: {e}");
+                    }
+                    check_requests = true;
+                }
+                Kind::ContactList => {
+                    let event_pk = event.pubkey;
+                    match update_nostr_contact_list(&self.nostr.storage, *event) {
+                        Err(e) => log_error!(self.logger, "Error handling contact list: {e}"),
+                        Ok(true) => {
+                            log_debug!(self.logger, "Got new contact list, syncing...");
+
+                            // sync in background so we don't block processing other events
+                            let self_clone = self.clone();
+                            utils::spawn(async move {
+                                match self_clone.sync_nostr_contacts(event_pk).await {
+                                    Err(e) => {
+                                        log_error!(self_clone.logger, "Failed to sync nostr: {e}")
+                                    }
+                                    Ok(_) => log_debug!(
+                                        self_clone.logger,
+                                        "Successfully synced nostr contacts"
+                                    ),
+                                }
+                            });
+                        }
+                        Ok(false) => log_debug!(self.logger, "Got older contact list, ignoring..."),
+                    }
+                }
+                kind => {
+                    // ignore federation announcement events
+                    if kind.as_u64() != 38000 && kind.as_u64() != 38173 {
+                        log_warn!(self.logger, "Received unexpected note of kind {kind}");
+                    }
+                }
+            }
+        }
+
+        // NWC requests and DMs can bring new payment requests, one check covers the batch
+        if check_requests {
+            if let Err(e) = self.payment_requests.check_for_new_requests().await {
+                log_warn!(self.logger, "Error checking payment requests: {e}");
+            }
+        }
+    }
+
     /// Pays a lightning invoice from a federation (preferred) or node.
     /// An amount should only be provided if the invoice does not have an amount.
     /// Amountless invoices cannot be paid by a federation.
@@ -1515,6 +2552,109 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         inv: &Bolt11Invoice,
         amt_sats: Option<u64>,
         labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.pay_invoice_with_options(inv, amt_sats, labels, PayInvoiceOptions::default())
+            .await
+    }
+
+    /// Pays a lightning invoice with the given safeguards.
+    ///
+    /// Invoices without an amount are only paid if `confirm_amountless` is set,
+    /// the amount is under the configured and per-call maximums,
+    /// and the payment is labeled with a contact, unless the wallet allows
+    /// amount-less invoices from non-contacts.
+    ///
+    /// Payments with `payment_overrides` are always made over lightning.
+    pub async fn pay_invoice_with_options(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        options: PayInvoiceOptions,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let idempotency_key = options.idempotency_key.clone();
+        // a pending payment of the invoice is found by its hash
+        let reconcile =
+            || match get_invoice_by_hash(inv.payment_hash(), &self.storage, &self.logger) {
+                Ok(invoice) if invoice.status == HTLCStatus::Succeeded => {
+                    Ok(PendingPayment::Succeeded(invoice))
+                }
+                Ok(invoice) if invoice.status == HTLCStatus::Failed => Ok(PendingPayment::Failed),
+                Ok(_) | Err(MutinyError::NotFound) => Ok(PendingPayment::InFlight),
+                Err(e) => Err(e),
+            };
+        with_idempotency(
+            &self.storage,
+            idempotency_key.as_deref(),
+            &(inv.to_string(), amt_sats),
+            reconcile,
+            self.pay_invoice_internal(inv, amt_sats, labels, options, None),
+        )
+        .await
+    }
+
+    /// Pays the invoice, `lnurl` is set when the invoice came from one
+    /// so it is checked against the spending policy instead of the payee.
+    ///
+    /// Settles the spending reservation once the payment is done, and records the
+    /// payment's [PaymentTrace] as its latency and its [Operation::PayInvoice] timing.
+    async fn pay_invoice_internal(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        options: PayInvoiceOptions,
+        lnurl: Option<&LnUrl>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let mut trace = PaymentTrace::new(inv.payment_hash());
+        let mut reservation = None;
+        let res = self
+            .try_pay_invoice(
+                inv,
+                amt_sats,
+                labels,
+                options,
+                lnurl,
+                &mut trace,
+                &mut reservation,
+            )
+            .await;
+
+        // a timed out payment can still complete, so it counts towards the limits,
+        // otherwise dropping the reservation releases it
+        if let Some(reservation) = reservation {
+            if matches!(res, Ok(_) | Err(MutinyError::PaymentTimeout)) {
+                reservation.complete();
+            }
+        }
+
+        let latency = trace.finish(res.is_ok());
+        self.node_manager.performance.record_duration(
+            Operation::PayInvoice,
+            latency.total_ms,
+            latency.success,
+        );
+        // payments that failed validation never made it into the pipeline, don't persist them
+        if latency.decode_ms.is_some() {
+            if let Err(e) = persist_payment_latency(&self.storage, &latency) {
+                log_warn!(self.logger, "Failed to persist payment latency: {e}");
+            }
+        }
+
+        res
+    }
+
+    /// The payment itself, see [MutinyWallet::pay_invoice_internal]
+    #[allow(clippy::too_many_arguments)]
+    async fn try_pay_invoice<'a>(
+        &'a self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        options: PayInvoiceOptions,
+        lnurl: Option<&LnUrl>,
+        trace: &mut PaymentTrace,
+        reservation: &mut Option<SpendingReservation<'a, S>>,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling pay_invoice");
 
@@ -1527,28 +2667,64 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             return Err(MutinyError::InvoiceExpired);
         }
 
+        if inv.amount_milli_satoshis().is_none() {
+            self.check_amountless_invoice(amt_sats, &labels, &options)?;
+        }
+
         // Check the amount specified in the invoice, we need one to make the payment
         let send_msat = inv
             .amount_milli_satoshis()
             .or(amt_sats.map(|x| x * 1_000))
             .ok_or(MutinyError::InvoiceInvalid)?;
 
+        let destination = match lnurl {
+            Some(lnurl) => lnurl.encode(),
+            None => inv
+                .payee_pub_key()
+                .copied()
+                .unwrap_or_else(|| inv.recover_payee_pub_key())
+                .to_string(),
+        };
+        *reservation = Some(
+            self.node_manager
+                .spending_policy
+                .check_payment(send_msat / 1_000, &[destination])?,
+        );
+
         // set labels now, need to set it before in case the payment times out
         self.storage
             .set_invoice_labels(inv.clone(), labels.clone())?;
+        trace.mark(PaymentStage::Decode);
 
-        // Try each federation first
-        let federation_ids = self.list_federation_ids().await?;
+        // Try each federation first, in order of the user's preference,
+        // unless the caller wants control over the lightning route
+        let federation_ids = if options.payment_overrides.is_some() {
+            vec![]
+        } else {
+            self.send_federation_ids(send_msat / 1_000).await?
+        };
         let mut last_federation_error = None;
         for federation_id in federation_ids {
-            if let Some(fedimint_client) = self.federations.read().await.get(&federation_id) {
+            let fedimint_client = self.federations.read().await.get(&federation_id).cloned();
+            if let Some(fedimint_client) = fedimint_client {
                 // Check if the federation has enough balance
-                let balance = fedimint_client.get_balance().await?;
+                let balance = match fedimint_client.get_balance().await {
+                    Ok(balance) => balance,
+                    Err(e) => {
+                        log_warn!(self.logger, "could not get federation balance: {e}");
+                        last_federation_error = Some(e);
+                        continue;
+                    }
+                };
                 if balance >= send_msat / 1_000 {
+                    trace.mark(PaymentStage::RailSelection);
+                    trace.set_rail(PaymentRail::Federation);
+
                     // Try to pay the invoice using the federation
                     let payment_result = fedimint_client
                         .pay_invoice(inv.clone(), labels.clone())
                         .await;
+                    trace.mark(PaymentStage::Settle);
                     match payment_result {
                         Ok(r) => {
                             // spawn a task to remove the pending invoice if it exists
@@ -1603,9 +2779,20 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             .sum::<u64>()
             > 0
         {
+            trace.mark(PaymentStage::RailSelection);
+            trace.set_rail(PaymentRail::Lightning);
+
             let res = self
                 .node_manager
-                .pay_invoice(None, inv, amt_sats, labels)
+                .pay_invoice(
+                    None,
+                    inv,
+                    amt_sats,
+                    labels,
+                    options.payment_overrides.as_ref(),
+                    options.timeout_secs,
+                    trace,
+                )
                 .await?;
 
             // spawn a task to remove the pending invoice if it exists
@@ -1627,6 +2814,51 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Enforces the amount-less invoice safeguards, see [MutinyWallet::pay_invoice_with_options]
+    fn check_amountless_invoice(
+        &self,
+        amt_sats: Option<u64>,
+        labels: &[String],
+        options: &PayInvoiceOptions,
+    ) -> Result<(), MutinyError> {
+        let Some(amt_sats) = amt_sats else {
+            return Err(MutinyError::InvoiceInvalid);
+        };
+        if amt_sats == 0 {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        if !options.confirm_amountless {
+            return Err(MutinyError::AmountlessInvoiceNotConfirmed);
+        }
+
+        let max_sats = [
+            self.config.max_amountless_invoice_sats,
+            options.max_amountless_sats,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        if max_sats.is_some_and(|max| amt_sats > max) {
+            return Err(MutinyError::AmountlessInvoiceTooLarge);
+        }
+
+        if !self.config.allow_amountless_from_non_contacts {
+            let mut is_contact = false;
+            for label in labels {
+                if self.storage.get_contact(label)?.is_some() {
+                    is_contact = true;
+                    break;
+                }
+            }
+            if !is_contact {
+                return Err(MutinyError::AmountlessInvoiceNotAllowed);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Estimates the lightning fee for a transaction. Amount is either from the invoice
     /// if one is available or a passed in amount (priority). It will try to predict either
     /// sending the payment through a federation or through lightning, depending on balances.
@@ -1716,17 +2948,33 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         &self,
         amount: Option<u64>,
         labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyError> {
+        self.create_bip21_with_params(amount, labels, InvoiceParams::default())
+            .await
+    }
+
+    /// Creates a BIP 21 with the given settings for its lightning invoice
+    pub async fn create_bip21_with_params(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        params: InvoiceParams,
     ) -> Result<MutinyBip21RawMaterials, MutinyError> {
         log_trace!(self.logger, "calling create_bip21");
+        params.validate()?;
 
         let invoice = if self.safe_mode || amount.is_none() {
             None
         } else {
             Some(
-                self.create_lightning_invoice(amount.expect("just checked"), labels.clone())
-                    .await?
-                    .bolt11
-                    .ok_or(MutinyError::InvoiceCreationFailed)?,
+                self.create_lightning_invoice(
+                    amount.expect("just checked"),
+                    labels.clone(),
+                    &params,
+                )
+                .await?
+                .bolt11
+                .ok_or(MutinyError::InvoiceCreationFailed)?,
             )
         };
 
@@ -1844,7 +3092,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             // if the user provided amount, this is easy
             let (mut invoice, incoming_fee) = if let Some(fed_client) = to_federation_client {
                 let invoice = fed_client
-                    .get_invoice(amt, vec![SWAP_LABEL.to_string()])
+                    .get_invoice(amt, vec![SWAP_LABEL.to_string()], &InvoiceParams::default())
                     .await?;
                 (invoice, 0)
             } else {
@@ -1872,7 +3120,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
 
             let (mut invoice, incoming_fee) = if let Some(fed_client) = to_federation_client {
                 let invoice = fed_client
-                    .get_invoice(amt, vec![SWAP_LABEL.to_string()])
+                    .get_invoice(amt, vec![SWAP_LABEL.to_string()], &InvoiceParams::default())
                     .await?;
                 (invoice, 0)
             } else {
@@ -1894,15 +3142,44 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Sends on-chain from a federation (preferred) or the node.
+    ///
+    /// Retries with the same `idempotency_key` return the txid of the first send
+    /// instead of sending again. The key can't be used to send to another address
+    /// or amount, and a send that didn't finish stays pending until the key expires.
     pub async fn send_to_address(
         &self,
         send_to: Address,
         amount: u64,
         labels: Vec<String>,
         fee_rate: Option<f32>,
+        idempotency_key: Option<String>,
+    ) -> Result<Txid, MutinyError> {
+        with_idempotency(
+            &self.storage,
+            idempotency_key.as_deref(),
+            &(send_to.to_string(), amount),
+            // we don't know the txid before it is sent
+            || Ok(PendingPayment::InFlight),
+            self.send_to_address_internal(send_to, amount, labels, fee_rate),
+        )
+        .await
+    }
+
+    async fn send_to_address_internal(
+        &self,
+        send_to: Address,
+        amount: u64,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
     ) -> Result<Txid, MutinyError> {
         log_trace!(self.logger, "calling send_to_address");
 
+        let reservation = self
+            .node_manager
+            .spending_policy
+            .check_payment(amount, &[send_to.to_string()])?;
+
         // Try each federation first
         let federation_ids = self.list_federation_ids().await?;
         let mut last_federation_error = None;
@@ -1916,6 +3193,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                         .await
                     {
                         Ok(t) => {
+                            reservation.complete();
                             return Ok(t);
                         }
                         Err(e) => match e {
@@ -1940,6 +3218,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                 .node_manager
                 .send_to_address(send_to, amount, labels, fee_rate)
                 .await?;
+            reservation.complete();
             Ok(res)
         } else {
             Err(last_federation_error.unwrap_or(MutinyError::InsufficientBalance))
@@ -2004,50 +3283,344 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
-    /// Estimates the onchain fee for a transaction sweep our on-chain balance
-    /// to the given address. If the fedimint has a balance, sweep that first.
-    /// Do not sweep the on chain wallet unless that is empty.
+    /// Swaps on-chain funds into lightning through the swap provider,
+    /// using our existing inbound liquidity instead of opening a channel.
     ///
-    /// The fee rate is in sat/vbyte.
-    pub async fn estimate_sweep_tx_fee(
-        &self,
-        destination_address: Address,
-        fee_rate: Option<f32>,
-    ) -> Result<u64, MutinyError> {
-        log_trace!(self.logger, "calling estimate_sweep_tx_fee");
-
-        // Try each federation first
-        let federation_ids = self.list_federation_ids().await?;
-        for federation_id in federation_ids {
-            if let Some(fedimint_client) = self.federations.read().await.get(&federation_id) {
-                // Check if the federation has enough balance
-                let balance = fedimint_client.get_balance().await?;
-                match fedimint_client
-                    .estimate_tx_fee(destination_address.clone(), balance)
-                    .await
-                {
-                    Ok(t) => {
-                        return Ok(t);
-                    }
-                    Err(e) => return Err(e),
-                }
-                // If estimation fails or balance is not sufficient, continue to next federation
-            }
-            // If federation client is not found, continue to next federation
+    /// The amount is what we receive over lightning, in satoshis. The provider's fee and
+    /// the on-chain fee come on top of it and together can't be more than `max_fee`.
+    /// If the provider fails to pay us, the funds are refunded once the swap times out.
+    pub async fn swap_in(&self, amount: u64, max_fee: u64) -> Result<SwapIn, MutinyError> {
+        log_trace!(self.logger, "calling swap_in");
+
+        let swap_client = self
+            .swap_client
+            .as_ref()
+            .ok_or(MutinyError::SwapProviderError)?;
+        let pair = swap_client.get_pair().await?;
+        if amount < pair.limits.minimal || amount > pair.limits.maximal {
+            return Err(MutinyError::BadAmountError);
+        }
+        if pair.swap_in_fee(amount) > max_fee {
+            return Err(MutinyError::SwapFeeTooHigh);
         }
 
-        let b = self.node_manager.get_balance().await?;
-        let res = if b.confirmed + b.unconfirmed > 0 {
-            let res = self
-                .node_manager
-                .estimate_sweep_tx_fee(destination_address, fee_rate)?;
+        // the provider has to be able to pay us over our existing channels
+        let liquidity = self.node_manager.get_liquidity().await?;
+        if liquidity.max_receivable_msat < amount * 1_000 {
+            return Err(MutinyError::InsufficientBalance);
+        }
+        let labels = vec![SWAP_IN_LABEL.to_string()];
+        let (invoice, _) = self
+            .node_manager
+            .create_invoice(amount, labels.clone())
+            .await?;
+        let invoice = invoice.bolt11.ok_or(MutinyError::InvoiceCreationFailed)?;
 
-            Ok(res)
-        } else {
-            log_error!(self.logger, "node manager doesn't have a balance");
-            Err(MutinyError::InsufficientBalance)
-        };
-        log_trace!(self.logger, "calling estimate_sweep_tx_fee");
+        let refund_key_index = next_refund_key_index(&self.storage)?;
+        let refund_key = swap_refund_key(self.xprivkey, refund_key_index)?;
+        let refund_pubkey = refund_key.public_key(&Secp256k1::new());
+        let created = swap_client
+            .create_swap(&invoice, &refund_pubkey, &pair)
+            .await?;
+
+        let redeem_script = parse_script(&created.redeem_script)?;
+        let address = verify_swap_script(
+            &redeem_script,
+            &created.address,
+            invoice.payment_hash(),
+            &refund_pubkey,
+            created.timeout_block_height,
+            self.network,
+        )?;
+        let height = self.get_block_height().await?;
+        verify_swap_timeout(created.timeout_block_height, height)?;
+
+        let fee_sats = created.expected_amount.saturating_sub(amount);
+        let onchain_fee =
+            self.node_manager
+                .estimate_tx_fee(address.clone(), created.expected_amount, None)?;
+        if fee_sats + onchain_fee > max_fee {
+            return Err(MutinyError::SwapFeeTooHigh);
+        }
+
+        let mut swap = SwapIn {
+            id: created.id,
+            status: SwapStatus::Created,
+            invoice,
+            amount_sats: amount,
+            fee_sats,
+            address: created.address,
+            expected_amount: created.expected_amount,
+            redeem_script: created.redeem_script,
+            timeout_block_height: created.timeout_block_height,
+            refund_key_index,
+            lockup_txid: None,
+            refund_txid: None,
+            created_at: utils::now().as_secs(),
+        };
+        // save the swap before funding it so we can always refund it
+        persist_swap_in(&self.storage, &swap)?;
+
+        let txid = match self
+            .node_manager
+            .send_to_address(address, created.expected_amount, labels, None)
+            .await
+        {
+            Ok(txid) => txid,
+            Err(e) => {
+                // nothing was locked up, so there is nothing to refund later
+                swap.status = SwapStatus::Expired;
+                persist_swap_in(&self.storage, &swap)?;
+                return Err(e);
+            }
+        };
+        swap.lockup_txid = Some(txid);
+        swap.status = SwapStatus::Funded;
+        persist_swap_in(&self.storage, &swap)?;
+
+        log_trace!(self.logger, "finished calling swap_in");
+        Ok(swap)
+    }
+
+    /// Lists our swaps from on-chain to lightning, oldest first.
+    pub fn list_swaps(&self) -> Result<Vec<SwapIn>, MutinyError> {
+        get_swap_ins(&self.storage)
+    }
+
+    /// Sets where to send encrypted backups of the whole wallet, or disables them with `None`.
+    ///
+    /// A backup is made right away to check the target works, then every few hours.
+    /// This is meant for self-hosters that don't use VSS, unlike VSS the backups aren't
+    /// updated with every channel update, so restoring one with open channels can lose funds.
+    pub async fn set_remote_backup(
+        &self,
+        config: Option<RemoteBackupConfig>,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_remote_backup");
+
+        set_remote_backup_config(&self.storage, config.as_ref())?;
+        if config.is_some() {
+            if let Err(e) = self.backup_to_remote().await {
+                set_remote_backup_config(&self.storage, None)?;
+                return Err(e);
+            }
+        } else {
+            set_remote_backup_status(&self.storage, &RemoteBackupStatus::default())?;
+        }
+
+        log_trace!(self.logger, "finished calling set_remote_backup");
+        Ok(())
+    }
+
+    /// Backs up the whole wallet to the remote target set with [MutinyWallet::set_remote_backup].
+    pub async fn backup_to_remote(&self) -> Result<(), MutinyError> {
+        let config = get_remote_backup_config(&self.storage)?.ok_or(MutinyError::NotFound)?;
+        let target = HttpBackupTarget::new(config)?;
+        self.backup_to(&target).await
+    }
+
+    /// Exports the whole wallet, encrypts it with a key derived from our seed
+    /// and uploads it to the given target.
+    pub async fn backup_to(&self, target: &impl BackupTarget) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling backup_to");
+
+        let json = NodeManager::export_json(self.storage.clone()).await?;
+        let backup = encrypt_backup(&json, self.xprivkey)?;
+        let res = target.upload(backup).await;
+
+        let mut status = get_remote_backup_status(&self.storage)?;
+        match res.as_ref() {
+            Ok(_) => {
+                status.last_backup = Some(utils::now().as_secs());
+                status.last_error = None;
+            }
+            Err(e) => status.last_error = Some(e.to_string()),
+        }
+        set_remote_backup_status(&self.storage, &status)?;
+
+        log_trace!(self.logger, "finished calling backup_to");
+        res
+    }
+
+    /// Gets where we back up to and when the last backup was made.
+    pub fn get_remote_backup_status(&self) -> Result<RemoteBackupStatus, MutinyError> {
+        let mut status = get_remote_backup_status(&self.storage)?;
+        status.url = get_remote_backup_config(&self.storage)?.map(|c| c.url);
+        Ok(status)
+    }
+
+    /// Restores the contacts, labels and NWC profiles we don't have yet from the
+    /// backup we keep on the nostr relays. Returns how many were restored.
+    pub async fn restore_social_backup(&self) -> Result<usize, MutinyError> {
+        log_trace!(self.logger, "calling restore_social_backup");
+        self.storage.check_writable()?;
+
+        let key = backup_encryption_key(self.xprivkey)?;
+        let restored = match self.nostr.fetch_social_backup(&key).await? {
+            Some(backup) => self.nostr.restore_social_backup(&backup)?,
+            None => 0,
+        };
+
+        log_trace!(self.logger, "finished calling restore_social_backup");
+        Ok(restored)
+    }
+
+    /// Creates an encrypted static channel backup of our open channels.
+    ///
+    /// It should be exported after opening or closing a channel, so the channels can
+    /// still be recovered with [MutinyWallet::recover_from_scb] if our lightning state is lost.
+    pub async fn create_scb(&self) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling create_scb");
+
+        let backup = self.node_manager.create_static_channel_backup().await?;
+        let res = backup.encrypt(self.xprivkey);
+
+        log_trace!(self.logger, "finished calling create_scb");
+        res
+    }
+
+    /// Deletes data we will never need again, as allowed by the [CompactionPolicy]:
+    /// failed payments, expired unpaid invoices and the monitors of resolved channels.
+    pub async fn compact_storage(&self) -> Result<CompactionStats, MutinyError> {
+        log_trace!(self.logger, "calling compact_storage");
+
+        let policy = get_compaction_policy(&self.storage)?;
+        let now = utils::now().as_secs();
+
+        let mut stats = match policy.failed_payment_cutoff(now) {
+            Some(cutoff) => prune_stale_payments(&self.storage, cutoff)?,
+            None => CompactionStats::default(),
+        };
+        if let Some(min_age_secs) = policy.resolved_monitor_min_age_secs() {
+            let (monitors_removed, bytes_reclaimed) = self
+                .node_manager
+                .prune_resolved_monitors(min_age_secs)
+                .await?;
+            stats.merge(CompactionStats {
+                monitors_removed,
+                bytes_reclaimed,
+                ..Default::default()
+            });
+        }
+        stats.timestamp = now;
+        set_last_compaction(&self.storage, stats)?;
+
+        log_info!(
+            self.logger,
+            "Compacted storage, removed {} payments and {} monitors, reclaimed {} bytes",
+            stats.payments_removed,
+            stats.monitors_removed,
+            stats.bytes_reclaimed
+        );
+
+        log_trace!(self.logger, "finished calling compact_storage");
+        Ok(stats)
+    }
+
+    /// Gets the stats of the last storage compaction, if there was one
+    pub fn get_last_compaction(&self) -> Result<Option<CompactionStats>, MutinyError> {
+        get_last_compaction(&self.storage)
+    }
+
+    pub fn get_compaction_policy(&self) -> Result<CompactionPolicy, MutinyError> {
+        get_compaction_policy(&self.storage)
+    }
+
+    /// Sets what [MutinyWallet::compact_storage] is allowed to delete and
+    /// if it runs automatically.
+    pub fn set_compaction_policy(&self, policy: CompactionPolicy) -> Result<(), MutinyError> {
+        set_compaction_policy(&self.storage, policy)
+    }
+
+    /// Keys that were changed on both this device and VSS and couldn't be merged
+    pub fn get_sync_conflicts(&self) -> Result<Vec<SyncConflict>, MutinyError> {
+        get_sync_conflicts(&self.storage)
+    }
+
+    /// Keeps either the VSS or the local value of a key from [MutinyWallet::get_sync_conflicts]
+    pub fn resolve_sync_conflict(&self, key: &str, use_remote: bool) -> Result<(), MutinyError> {
+        log_info!(
+            self.logger,
+            "Resolving sync conflict for {key}, use remote: {use_remote}"
+        );
+        resolve_sync_conflict(&self.storage, key, use_remote)
+    }
+
+    /// Checks the stored data can be read and looks for orphaned entries.
+    /// The channel monitors and channel managers are read back with each node's keys.
+    pub async fn audit_storage(&self) -> Result<StorageAudit, MutinyError> {
+        log_trace!(self.logger, "calling audit_storage");
+
+        let mut audit = audit_storage(&self.storage)?;
+        audit.add_unreadable_channel_data(self.node_manager.check_channel_data().await?);
+        log_info!(
+            self.logger,
+            "Audited {} keys, found {} issues",
+            audit.keys_scanned,
+            audit.issues.len()
+        );
+
+        log_trace!(self.logger, "finished calling audit_storage");
+        Ok(audit)
+    }
+
+    /// Fixes the repairable issues found by [MutinyWallet::audit_storage]
+    pub fn repair_storage(&self, options: RepairOptions) -> Result<RepairReport, MutinyError> {
+        log_trace!(self.logger, "calling repair_storage");
+
+        let res = repair_storage(&self.storage, options);
+        if let Ok(report) = res.as_ref() {
+            log_info!(self.logger, "Repaired storage: {report:?}");
+        }
+
+        log_trace!(self.logger, "finished calling repair_storage");
+        res
+    }
+
+    /// Estimates the onchain fee for a transaction sweep our on-chain balance
+    /// to the given address. If the fedimint has a balance, sweep that first.
+    /// Do not sweep the on chain wallet unless that is empty.
+    ///
+    /// The fee rate is in sat/vbyte.
+    pub async fn estimate_sweep_tx_fee(
+        &self,
+        destination_address: Address,
+        fee_rate: Option<f32>,
+    ) -> Result<u64, MutinyError> {
+        log_trace!(self.logger, "calling estimate_sweep_tx_fee");
+
+        // Try each federation first
+        let federation_ids = self.list_federation_ids().await?;
+        for federation_id in federation_ids {
+            if let Some(fedimint_client) = self.federations.read().await.get(&federation_id) {
+                // Check if the federation has enough balance
+                let balance = fedimint_client.get_balance().await?;
+                match fedimint_client
+                    .estimate_tx_fee(destination_address.clone(), balance)
+                    .await
+                {
+                    Ok(t) => {
+                        return Ok(t);
+                    }
+                    Err(e) => return Err(e),
+                }
+                // If estimation fails or balance is not sufficient, continue to next federation
+            }
+            // If federation client is not found, continue to next federation
+        }
+
+        let b = self.node_manager.get_balance().await?;
+        let res = if b.confirmed + b.unconfirmed > 0 {
+            let res = self
+                .node_manager
+                .estimate_sweep_tx_fee(destination_address, fee_rate)?;
+
+            Ok(res)
+        } else {
+            log_error!(self.logger, "node manager doesn't have a balance");
+            Err(MutinyError::InsufficientBalance)
+        };
+        log_trace!(self.logger, "calling estimate_sweep_tx_fee");
 
         res
     }
@@ -2075,11 +3648,19 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     .await
                 {
                     Ok(f) => {
+                        let amount = balance - f;
+                        let reservation = self
+                            .node_manager
+                            .spending_policy
+                            .check_payment(amount, &[send_to.to_string()])?;
                         match fedimint_client
-                            .send_onchain(send_to.clone(), balance - f, labels)
+                            .send_onchain(send_to.clone(), amount, labels)
                             .await
                         {
-                            Ok(t) => return Ok(t),
+                            Ok(t) => {
+                                reservation.complete();
+                                return Ok(t);
+                            }
                             Err(e) => {
                                 log_error!(self.logger, "error sending the fedimint balance");
                                 return Err(e);
@@ -2095,10 +3676,16 @@ impl<S: MutinyStorage> MutinyWallet<S> {
 
         let b = self.node_manager.get_balance().await?;
         let res = if b.confirmed + b.unconfirmed > 0 {
+            let amount = b.confirmed + b.unconfirmed;
+            let reservation = self
+                .node_manager
+                .spending_policy
+                .check_payment(amount, &[send_to.to_string()])?;
             let res = self
                 .node_manager
                 .sweep_wallet(send_to.clone(), labels, fee_rate)
                 .await?;
+            reservation.complete();
 
             Ok(res)
         } else {
@@ -2110,6 +3697,29 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Sweeps the funds of a foreign private key (WIF) or BIP-39 seed into the wallet,
+    /// like a paper wallet or a seed from another wallet app.
+    /// The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// Returns what happened to each address type, see [ExternalSweep].
+    pub async fn sweep_external(
+        &self,
+        wif_or_mnemonic: &str,
+        fee_rate: Option<f32>,
+    ) -> Result<Vec<ExternalSweep>, MutinyError> {
+        log_trace!(self.logger, "calling sweep_external");
+
+        let res = self
+            .node_manager
+            .sweep_external(wif_or_mnemonic, fee_rate)
+            .await;
+
+        log_trace!(self.logger, "finished calling sweep_external");
+        res
+    }
+
     pub async fn create_address(
         &self,
         labels: Vec<String>,
@@ -2143,17 +3753,22 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         &self,
         amount: u64,
         labels: Vec<String>,
+        params: &InvoiceParams,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling create_lightning_invoice");
 
         // Attempt to create federation invoice if available
-        let federation_ids = self.list_federation_ids().await?;
+        let federation_ids = if params.supported_by_federation() {
+            self.receive_federation_ids(amount).await?
+        } else {
+            vec![]
+        };
         if !federation_ids.is_empty() {
             let federation_id = &federation_ids[0];
             let fedimint_client = self.federations.read().await.get(federation_id).cloned();
 
             if let Some(client) = fedimint_client {
-                if let Ok(inv) = client.get_invoice(amount, labels.clone()).await {
+                if let Ok(inv) = client.get_invoice(amount, labels.clone(), params).await {
                     self.storage
                         .set_invoice_labels(inv.bolt11.clone().expect("just created"), labels)?;
                     return Ok(inv);
@@ -2161,8 +3776,15 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             }
         }
 
-        // Fallback to node_manager invoice creation if no federation invoice created
-        let (inv, _fee) = self.node_manager.create_invoice(amount, labels).await?;
+        // Fallback to node_manager invoice creation if no federation invoice created,
+        // this goes through the LSP so it is paused during maintenance
+        if self.node_manager.lsp_config.is_some() && self.is_lsp_paused().await {
+            return Err(MutinyError::LspMaintenance);
+        }
+        let (inv, _fee) = self
+            .node_manager
+            .create_invoice_with_params(amount, labels, params)
+            .await?;
 
         log_trace!(self.logger, "finished calling create_lightning_invoice");
         Ok(inv)
@@ -2214,6 +3836,201 @@ impl<S: MutinyStorage> MutinyWallet<S> {
     ) -> Result<Vec<ActivityItem>, MutinyError> {
         log_trace!(self.logger, "calling get_activity");
 
+        let activities = self
+            .get_activity_with_keys(limit, offset)?
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect();
+        log_trace!(self.logger, "finished calling get_activity");
+
+        Ok(activities)
+    }
+
+    /// Get the sorted activity list with the value of each item in the given currency,
+    /// using the price recorded when it settled. Pending activity has no fiat value yet.
+    pub fn get_activity_with_fiat(
+        &self,
+        currency: String,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<Vec<FiatActivityItem>, MutinyError> {
+        log_trace!(self.logger, "calling get_activity_with_fiat");
+
+        let currency = currency.to_lowercase();
+        let mut activities = vec![];
+        for (key, item) in self.get_activity_with_keys(limit, offset)? {
+            let price = match item.last_updated() {
+                Some(_) => get_activity_price(&self.storage, &key)?
+                    .and_then(|p| p.prices.get(&currency).copied()),
+                None => None,
+            };
+            activities.push(FiatActivityItem::new(item, currency.clone(), price));
+        }
+        log_trace!(self.logger, "finished calling get_activity_with_fiat");
+
+        Ok(activities)
+    }
+
+    /// Exports the settled activity between the given unix timestamps for accounting,
+    /// with fiat values in the given currency, `usd` by default.
+    /// Includes on-chain, lightning, channel closure and federation activity.
+    pub fn export_activity(
+        &self,
+        format: ExportFormat,
+        from_ts: Option<u64>,
+        to_ts: Option<u64>,
+        currency: Option<String>,
+    ) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling export_activity");
+
+        let currency = currency.unwrap_or(DEFAULT_PRICE_CURRENCY.to_string());
+        let contacts = self.storage.get_contacts()?;
+        // activity is newest first, exports go in chronological order
+        let rows = self
+            .get_activity_with_fiat(currency, None, None)?
+            .into_iter()
+            .rev()
+            .filter_map(|item| ActivityExportRow::new(item, &contacts))
+            .filter(|row| from_ts.map_or(true, |from| row.timestamp >= from))
+            .filter(|row| to_ts.map_or(true, |to| row.timestamp <= to))
+            .collect::<Vec<_>>();
+        let res = export_rows(&rows, format);
+        log_trace!(self.logger, "finished calling export_activity");
+
+        res
+    }
+
+    /// Summarizes the settled activity by month, with totals by label, contact and type.
+    /// The summaries are updated with the activity that settled since the last call.
+    pub fn get_spending_summary(
+        &self,
+        period: SpendingPeriod,
+    ) -> Result<SpendingSummary, MutinyError> {
+        log_trace!(self.logger, "calling get_spending_summary");
+
+        let stats = self.update_spending_stats()?;
+        let res = stats.summary(period, utils::now().as_secs());
+        log_trace!(self.logger, "finished calling get_spending_summary");
+
+        Ok(res)
+    }
+
+    /// Returns the spending policy, see [SpendingPolicy]
+    pub fn get_spending_policy(&self) -> Result<SpendingPolicy, MutinyError> {
+        self.node_manager.spending_policy.get_policy()
+    }
+
+    /// Replaces the spending policy, the PIN is needed if one is set
+    pub fn set_spending_policy(
+        &self,
+        policy: SpendingPolicy,
+        pin: Option<String>,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_spending_policy");
+
+        let res = self
+            .node_manager
+            .spending_policy
+            .set_policy(policy, pin.as_deref());
+        log_trace!(self.logger, "finished calling set_spending_policy");
+
+        res
+    }
+
+    /// Sets the spending PIN, or removes it if `new_pin` is `None`.
+    /// The current PIN is needed if one is set.
+    pub fn set_spending_pin(
+        &self,
+        current_pin: Option<String>,
+        new_pin: Option<String>,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_spending_pin");
+
+        let res = self
+            .node_manager
+            .spending_policy
+            .set_pin(current_pin.as_deref(), new_pin.as_deref());
+        log_trace!(self.logger, "finished calling set_spending_pin");
+
+        res
+    }
+
+    /// Allows payments over the PIN threshold of the spending policy for a few minutes
+    pub fn unlock_spending(&self, pin: String) -> Result<(), MutinyError> {
+        self.node_manager.spending_policy.unlock(&pin)
+    }
+
+    /// Requires the PIN again for payments over the threshold
+    pub fn lock_spending(&self) {
+        self.node_manager.spending_policy.lock()
+    }
+
+    /// Returns how much was spent towards the spending policy limits
+    pub fn get_spending_policy_usage(&self) -> Result<SpendingPolicyUsage, MutinyError> {
+        self.node_manager.spending_policy.get_usage()
+    }
+
+    /// Returns the limits on invoices requested through NWC and our lightning address,
+    /// see [ReceiveLimits]
+    pub fn get_receive_limits(&self) -> Result<ReceiveLimits, MutinyError> {
+        get_receive_limits(&self.storage)
+    }
+
+    /// Replaces the limits on invoices requested through NWC and our lightning address
+    pub fn set_receive_limits(&self, limits: ReceiveLimits) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_receive_limits");
+        self.storage.check_writable()?;
+
+        let res = set_receive_limits(&self.storage, limits);
+        log_trace!(self.logger, "finished calling set_receive_limits");
+
+        res
+    }
+
+    /// Returns how many invoice requests were allowed and rejected by the receive limits
+    pub fn get_receive_limit_stats(&self) -> Result<ReceiveLimitStats, MutinyError> {
+        get_receive_limit_stats(&self.storage)
+    }
+
+    /// Counts the activity that settled since the spending stats were last updated
+    fn update_spending_stats(&self) -> Result<SpendingStats, MutinyError> {
+        let mut stats = get_spending_stats(&self.storage)?;
+
+        // the index has the pending items first, then the settled ones newest first,
+        // so we only need to load the items until the ones that were already counted
+        let cutoff = stats.recount_cutoff();
+        let count = {
+            let index = self.storage.activity_index();
+            let index = index.try_read()?;
+            index
+                .iter()
+                .take_while(|i| i.timestamp.map_or(true, |t| t >= cutoff))
+                .count()
+        };
+        if count == 0 {
+            return Ok(stats);
+        }
+
+        let contacts = self.storage.get_contacts()?;
+        let mut changed = false;
+        for (key, item) in self.get_activity_with_keys(Some(count), None)? {
+            changed |= stats.add(key, &item, &contacts);
+        }
+
+        if changed && !self.storage.is_read_only() {
+            stats.prune();
+            set_spending_stats(&self.storage, &stats)?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Gets the sorted activity list along with the activity index key of each item
+    fn get_activity_with_keys(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<Vec<(String, ActivityItem)>, MutinyError> {
         let index = {
             let index = self.storage.activity_index();
             let vec = index.try_read()?.clone().into_iter().collect_vec();
@@ -2244,67 +4061,188 @@ impl<S: MutinyStorage> MutinyWallet<S> {
 
         let mut activities = Vec::with_capacity(index.len());
         for item in index {
-            if item.key.starts_with(PAYMENT_INBOUND_PREFIX_KEY) {
-                if let Some(mutiny_invoice) =
-                    self.get_invoice_internal(&item.key, true, &labels_map)?
-                {
-                    activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
-                }
-            } else if item.key.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY) {
-                if let Some(mutiny_invoice) =
-                    self.get_invoice_internal(&item.key, false, &labels_map)?
-                {
-                    activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
-                }
-            } else if item.key.starts_with(CHANNEL_CLOSURE_PREFIX) {
-                if let Some(mut closure) = self.storage.get_data::<ChannelClosure>(&item.key)? {
-                    if closure.user_channel_id.is_none() {
-                        // convert keys to u128
-                        let user_channel_id_str = item
-                            .key
-                            .trim_start_matches(CHANNEL_CLOSURE_PREFIX)
-                            .splitn(2, '_') // Channel closures have `_{node_id}` at the end
-                            .collect::<Vec<&str>>()[0];
-                        let user_channel_id: [u8; 16] = FromHex::from_hex(user_channel_id_str)?;
-                        closure.user_channel_id = Some(user_channel_id);
-                    }
-                    activities.push(ActivityItem::ChannelClosed(closure));
-                }
-            } else if item.key.starts_with(ONCHAIN_PREFIX) {
-                // convert keys to txid
-                let txid_str = item.key.trim_start_matches(ONCHAIN_PREFIX);
-                let txid: Txid = Txid::from_str(txid_str)?;
-                if let Some(tx_details) = self.node_manager.get_transaction(txid)? {
-                    // make sure it is a relevant transaction
-                    if tx_details.sent != 0 || tx_details.received != 0 {
-                        activities.push(ActivityItem::OnChain(tx_details));
-                    }
-                }
-            } else if item.key.starts_with(TRANSACTION_DETAILS_PREFIX_KEY) {
-                // convert keys to internal transaction id
-                let internal_id_str = item.key.trim_start_matches(TRANSACTION_DETAILS_PREFIX_KEY);
-                let internal_id: Txid = Txid::from_str(internal_id_str)?;
-                if let Some(tx_details) =
-                    get_transaction_details(&self.storage, internal_id, &self.logger)
-                {
-                    // make sure it is a relevant transaction
-                    if tx_details.sent != 0 || tx_details.received != 0 {
-                        activities.push(ActivityItem::OnChain(tx_details));
-                    }
-                }
+            if let Some(activity) = self.load_activity_item(&item.key, &labels_map)? {
+                activities.push((item.key, activity));
             }
         }
-        log_trace!(self.logger, "finished calling get_activity");
 
         Ok(activities)
     }
 
-    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
-        log_trace!(self.logger, "calling get_transaction");
+    /// Gets a page of activity that settled before `until_ts`, newest first.
+    /// Without `until_ts` this is the first page, which starts with all the pending activity.
+    ///
+    /// Pass the `next_until_ts` of the page to get the next one. Activity that settled at the
+    /// same time is never split across pages, so a page can have more items than the limit.
+    pub fn get_activity_page(
+        &self,
+        limit: usize,
+        until_ts: Option<u64>,
+        filter: ActivityFilter,
+    ) -> Result<ActivityPage, MutinyError> {
+        log_trace!(self.logger, "calling get_activity_page");
 
-        // check our local cache/state for fedimint first
-        let res = match get_transaction_details(&self.storage, txid, &self.logger) {
-            Some(t) => Ok(Some(t)),
+        let index = {
+            let index = self.storage.activity_index();
+            let index = index.try_read()?;
+            index
+                .iter()
+                .filter(|i| match (until_ts, i.timestamp) {
+                    (None, _) => true,
+                    (Some(until), Some(t)) => t < until,
+                    (Some(_), None) => false,
+                })
+                .filter(|i| filter.matches_key(&i.key))
+                .cloned()
+                .collect_vec()
+        };
+
+        let labels_map = self.storage.get_invoice_labels()?;
+
+        let mut items = vec![];
+        let mut last_timestamp = None;
+        let mut has_more = false;
+        for item in index {
+            if items.len() >= limit {
+                // finish the pending activity, or the activity that settled at the same time
+                // as the last item
+                if item.timestamp.is_some() && item.timestamp != last_timestamp {
+                    has_more = true;
+                    break;
+                }
+            }
+
+            if let Some(activity) = self.load_activity_item(&item.key, &labels_map)? {
+                if filter.matches(&activity) {
+                    last_timestamp = item.timestamp;
+                    items.push(activity);
+                }
+            }
+        }
+
+        let next_until_ts = if has_more {
+            // pending activity is only on the first page
+            Some(last_timestamp.unwrap_or(u64::MAX))
+        } else {
+            None
+        };
+        log_trace!(self.logger, "finished calling get_activity_page");
+
+        Ok(ActivityPage {
+            items,
+            next_until_ts,
+        })
+    }
+
+    /// Loads the activity item with the given activity index key
+    fn load_activity_item(
+        &self,
+        key: &str,
+        labels_map: &HashMap<Bolt11Invoice, Vec<String>>,
+    ) -> Result<Option<ActivityItem>, MutinyError> {
+        if key.starts_with(PAYMENT_INBOUND_PREFIX_KEY) {
+            if let Some(mutiny_invoice) = self.get_invoice_internal(key, true, labels_map)? {
+                return Ok(Some(self.lightning_activity_item(key, mutiny_invoice)?));
+            }
+        } else if key.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY) {
+            if let Some(mutiny_invoice) = self.get_invoice_internal(key, false, labels_map)? {
+                return Ok(Some(self.lightning_activity_item(key, mutiny_invoice)?));
+            }
+        } else if key.starts_with(CHANNEL_CLOSURE_PREFIX) {
+            if let Some(mut closure) = self.storage.get_data::<ChannelClosure>(key)? {
+                if closure.user_channel_id.is_none() {
+                    // convert keys to u128
+                    let user_channel_id_str = key
+                        .trim_start_matches(CHANNEL_CLOSURE_PREFIX)
+                        .splitn(2, '_') // Channel closures have `_{node_id}` at the end
+                        .collect::<Vec<&str>>()[0];
+                    let user_channel_id: [u8; 16] = FromHex::from_hex(user_channel_id_str)?;
+                    closure.user_channel_id = Some(user_channel_id);
+                }
+                return Ok(Some(ActivityItem::ChannelClosed(closure)));
+            }
+        } else if key.starts_with(ONCHAIN_PREFIX) {
+            // convert keys to txid
+            let txid_str = key.trim_start_matches(ONCHAIN_PREFIX);
+            let txid: Txid = Txid::from_str(txid_str)?;
+            if let Some(tx_details) = self.node_manager.get_transaction(txid)? {
+                // make sure it is a relevant transaction
+                if tx_details.sent != 0 || tx_details.received != 0 {
+                    return Ok(Some(ActivityItem::OnChain(tx_details)));
+                }
+            }
+        } else if key.starts_with(TRANSACTION_DETAILS_PREFIX_KEY) {
+            // convert keys to internal transaction id
+            let internal_id_str = key.trim_start_matches(TRANSACTION_DETAILS_PREFIX_KEY);
+            let internal_id: Txid = Txid::from_str(internal_id_str)?;
+            if let Some(tx_details) =
+                get_transaction_details(&self.storage, internal_id, &self.logger)
+            {
+                // make sure it is a relevant transaction
+                if tx_details.sent != 0 || tx_details.received != 0 {
+                    return Ok(Some(self.transaction_activity_item(key, tx_details)?));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Wraps the payment as federation activity if it was handled by a federation
+    fn lightning_activity_item(
+        &self,
+        key: &str,
+        invoice: MutinyInvoice,
+    ) -> Result<ActivityItem, MutinyError> {
+        let item = match get_federation_activity_tag(&self.storage, key)? {
+            Some(tag) if tag.kind == FederationActivityKind::EcashReissue => {
+                ActivityItem::Federation(FederationActivity::EcashReissue {
+                    federation_id: tag.federation_id,
+                    amount_sats: invoice.amount_sats.unwrap_or_default(),
+                    fees: invoice.fees_paid,
+                    last_updated: invoice.last_updated,
+                    labels: invoice.labels,
+                })
+            }
+            Some(tag) => ActivityItem::Federation(FederationActivity::Lightning {
+                federation_id: tag.federation_id,
+                invoice: Box::new(invoice),
+            }),
+            None => ActivityItem::Lightning(Box::new(invoice)),
+        };
+        Ok(item)
+    }
+
+    /// Wraps the transaction as a federation peg-in or peg-out if it was handled by a federation
+    fn transaction_activity_item(
+        &self,
+        key: &str,
+        transaction: TransactionDetails,
+    ) -> Result<ActivityItem, MutinyError> {
+        let item = match get_federation_activity_tag(&self.storage, key)? {
+            Some(tag) if tag.kind == FederationActivityKind::PegIn => {
+                ActivityItem::Federation(FederationActivity::PegIn {
+                    federation_id: tag.federation_id,
+                    transaction,
+                })
+            }
+            Some(tag) if tag.kind == FederationActivityKind::PegOut => {
+                ActivityItem::Federation(FederationActivity::PegOut {
+                    federation_id: tag.federation_id,
+                    transaction,
+                })
+            }
+            _ => ActivityItem::OnChain(transaction),
+        };
+        Ok(item)
+    }
+
+    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
+        log_trace!(self.logger, "calling get_transaction");
+
+        // check our local cache/state for fedimint first
+        let res = match get_transaction_details(&self.storage, txid, &self.logger) {
+            Some(t) => Ok(Some(t)),
             None => {
                 // fall back to node manager
                 self.node_manager.get_transaction(txid)
@@ -2349,7 +4287,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     if let Some(mutiny_invoice) =
                         self.get_invoice_internal(&item.key, true, &labels_map)?
                     {
-                        activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
+                        activities.push(self.lightning_activity_item(&item.key, mutiny_invoice)?);
                     }
                 }
             } else if item.key.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY) {
@@ -2361,7 +4299,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     if let Some(mutiny_invoice) =
                         self.get_invoice_internal(&item.key, false, &labels_map)?
                     {
-                        activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
+                        activities.push(self.lightning_activity_item(&item.key, mutiny_invoice)?);
                     }
                 }
             }
@@ -2371,6 +4309,102 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(activities)
     }
 
+    /// Returns all the on-chain, lightning, and federation activity tagged with the given contact
+    pub fn get_contact_activity(&self, contact_id: &str) -> Result<Vec<ActivityItem>, MutinyError> {
+        log_trace!(self.logger, "calling get_contact_activity");
+
+        if self.storage.get_contact(contact_id)?.is_none() {
+            return Err(MutinyError::NotFound);
+        }
+
+        let res = self.get_activity(None, None).map(|activity| {
+            activity
+                .into_iter()
+                .filter(|a| a.labels().iter().any(|l| l == contact_id))
+                .collect()
+        });
+        log_trace!(self.logger, "finished calling get_contact_activity");
+
+        res
+    }
+
+    /// Returns aggregated payment stats for the given contact.
+    /// If `since` is given, only payments completed at or after that unix timestamp are counted.
+    pub fn get_contact_stats(
+        &self,
+        contact_id: &str,
+        since: Option<u64>,
+    ) -> Result<ContactStats, MutinyError> {
+        log_trace!(self.logger, "calling get_contact_stats");
+
+        let activity = self.get_contact_activity(contact_id)?;
+        let stats = ContactStats::from_activity(&activity, since);
+        log_trace!(self.logger, "finished calling get_contact_stats");
+
+        Ok(stats)
+    }
+
+    /// Searches activity, contacts, and labeled addresses for the given query.
+    /// Every whitespace separated term in the query must be the start of a word
+    /// of an item for it to be returned.
+    ///
+    /// Activity is matched on descriptions, labels, payment hashes, txids, and the names
+    /// and npubs of any contacts it is tagged with. Contacts are matched on their name, npub,
+    /// lightning address, and lnurl. Addresses are matched on the address and its labels.
+    ///
+    /// This uses the search index kept as things are written, only the matches are loaded.
+    pub fn search(&self, query: &str) -> Result<SearchResults, MutinyError> {
+        log_trace!(self.logger, "calling search");
+
+        let terms = search::query_terms(query);
+        if terms.is_empty() {
+            return Ok(SearchResults::default());
+        }
+
+        let keys = search::get_search_index(&self.storage)?.search(&terms);
+
+        // load the matching activity in the order of the activity index
+        let index = {
+            let index = self.storage.activity_index();
+            let index = index.try_read()?;
+            index
+                .iter()
+                .filter(|i| keys.contains(&i.key))
+                .cloned()
+                .collect_vec()
+        };
+        let labels_map = self.storage.get_invoice_labels()?;
+        let mut activity = Vec::with_capacity(index.len());
+        for item in index {
+            if let Some(item) = self.load_activity_item(&item.key, &labels_map)? {
+                activity.push(item);
+            }
+        }
+
+        let mut contacts = vec![];
+        for id in keys.iter().filter_map(|k| k.strip_prefix(CONTACT_PREFIX)) {
+            if let Some(contact) = self.storage.get_contact(id)? {
+                contacts.push((id.to_string(), contact));
+            }
+        }
+        contacts.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+        let mut address_labels = self.storage.get_address_labels()?;
+        let addresses = keys
+            .iter()
+            .filter_map(|k| k.strip_prefix(ADDRESS_SEARCH_PREFIX))
+            .filter_map(|a| address_labels.remove_entry(a))
+            .collect();
+
+        log_trace!(self.logger, "finished calling search");
+
+        Ok(SearchResults {
+            activity,
+            contacts,
+            addresses,
+        })
+    }
+
     pub fn list_invoices(&self) -> Result<Vec<MutinyInvoice>, MutinyError> {
         log_trace!(self.logger, "calling list_invoices");
 
@@ -2432,6 +4466,64 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Creates a fresh invoice to replace an expired one we created that was never paid.
+    ///
+    /// The new invoice has the same amount, description, expiry and labels as the old one.
+    pub async fn reissue_invoice(&self, hash: &sha256::Hash) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling reissue_invoice");
+
+        let invoice = get_invoice_by_hash(hash, &self.storage, &self.logger)?;
+        let bolt11 = invoice.bolt11.ok_or(MutinyError::NotFound)?;
+        if !invoice.inbound
+            || invoice.status == HTLCStatus::Succeeded
+            || !bolt11.would_expire(utils::now())
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let amount = invoice.amount_sats.ok_or(MutinyError::BadAmountError)?;
+
+        let description_hash = match bolt11.description() {
+            Bolt11InvoiceDescription::Direct(_) => None,
+            Bolt11InvoiceDescription::Hash(hash) => Some(hash.0),
+        };
+        // only a delta that was asked for, the default one can still go to a federation
+        let min_final_cltv = u16::try_from(bolt11.min_final_cltv_expiry_delta())
+            .ok()
+            .filter(|cltv| *cltv != DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA);
+        let params = InvoiceParams {
+            expiry_secs: u32::try_from(bolt11.expiry_time().as_secs()).ok(),
+            description: invoice.description,
+            description_hash,
+            min_final_cltv,
+            route_hints: None,
+        };
+        let labels = self
+            .storage
+            .get_invoice_labels()?
+            .remove(&bolt11)
+            .unwrap_or_default();
+
+        let res = self.create_lightning_invoice(amount, labels, &params).await;
+        log_trace!(self.logger, "finished calling reissue_invoice");
+
+        res
+    }
+
+    /// Looks up a lightning payment by hash along with a breakdown of
+    /// the paths it was sent over, including the hops and fees of each part.
+    pub async fn get_payment_details(
+        &self,
+        hash: &sha256::Hash,
+    ) -> Result<PaymentDetails, MutinyError> {
+        log_trace!(self.logger, "calling get_payment_details");
+
+        let invoice = get_invoice_by_hash(hash, &self.storage, &self.logger)?;
+        let paths = get_payment_paths(&self.storage, &hash.into_32())?;
+        log_trace!(self.logger, "finished calling get_payment_details");
+
+        Ok(PaymentDetails::new(invoice, paths))
+    }
+
     /// Checks whether or not the user is subscribed to Mutiny+.
     /// Submits a NWC string to keep the subscription active if not expired.
     ///
@@ -2545,39 +4637,39 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         if profile_opt.is_none() {
             log_debug!(self.logger, "Did not find a mutiny+ nwc profile");
             // profile with the reserved index does not exist, create a new one
-            let nwc = if autopay {
-                self.nostr
-                    .create_new_nwc_profile(
-                        ProfileType::Reserved(ReservedProfile::MutinySubscription),
-                        SpendingConditions::Budget(BudgetedSpendingConditions {
-                            budget: 21_000,
-                            single_max: None,
-                            payments: vec![],
-                            period: BudgetPeriod::Month,
-                        }),
-                        NwcProfileTag::Subscription,
-                        vec![Method::PayInvoice], // subscription only needs pay invoice
-                    )
-                    .await?
-                    .nwc_uri
-            } else {
-                self.nostr
-                    .create_new_nwc_profile(
-                        ProfileType::Reserved(ReservedProfile::MutinySubscription),
-                        SpendingConditions::RequireApproval,
-                        NwcProfileTag::Subscription,
-                        vec![Method::PayInvoice], // subscription only needs pay invoice
-                    )
-                    .await?
-                    .nwc_uri
-            };
+            let profile = self
+                .create_subscription_nwc_profile(
+                    ProfileType::Reserved(ReservedProfile::MutinySubscription),
+                    MUTINY_PLUS_BUDGET_SATS,
+                    BudgetPeriod::Month,
+                    autopay,
+                )
+                .await?;
 
-            if let Some(nwc) = nwc {
+            if let Some(nwc) = profile.nwc_uri {
                 // only should have to submit the NWC if never created locally before
                 subscription_client.submit_nwc(nwc).await?;
             }
         }
 
+        // Mutiny+ is listed with the other subscriptions
+        let payment = SubscriptionPayment::Nwc {
+            profile_index: reserved_profile_index,
+        };
+        let tracked = get_subscriptions(&self.storage)?
+            .iter()
+            .any(|s| s.payment == payment && s.status != SubscriptionStatus::Canceled);
+        if !tracked {
+            let expiry = self.storage.get_data::<u64>(SUBSCRIPTION_TIMESTAMP)?;
+            self.add_subscription(
+                MUTINY_PLUS_SUBSCRIPTION_LABEL.to_string(),
+                MUTINY_PLUS_BUDGET_SATS,
+                BudgetPeriod::Month,
+                payment,
+                expiry,
+            )?;
+        }
+
         // check if we have a contact, if not create one
         match self.storage.get_contact(MUTINY_PLUS_SUBSCRIPTION_LABEL)? {
             Some(_) => {}
@@ -2589,6 +4681,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     ln_address: None,
                     lnurl: None,
                     image_url: Some("https://void.cat/d/CZPXhnwjqRhULSjPJ3sXTE.webp".to_string()),
+                    node_pubkey: None,
                     last_used: utils::now().as_secs(),
                 };
                 self.storage.set_data(key, contact, None)?;
@@ -2598,6 +4691,243 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(())
     }
 
+    /// Creates the NWC profile a subscription service pays itself with. Without
+    /// autopay every payment needs the user's approval.
+    async fn create_subscription_nwc_profile(
+        &self,
+        profile_type: ProfileType,
+        amount_sats: u64,
+        period: BudgetPeriod,
+        autopay: bool,
+    ) -> Result<NwcProfile, MutinyError> {
+        let spending_conditions = if autopay {
+            SpendingConditions::Budget(BudgetedSpendingConditions {
+                budget: amount_sats,
+                single_max: None,
+                payments: vec![],
+                period,
+            })
+        } else {
+            SpendingConditions::RequireApproval
+        };
+
+        self.nostr
+            .create_new_nwc_profile(
+                profile_type,
+                spending_conditions,
+                NwcProfileTag::Subscription,
+                vec![Method::PayInvoice], // subscription only needs pay invoice
+            )
+            .await
+    }
+
+    fn add_subscription(
+        &self,
+        name: String,
+        amount_sats: u64,
+        period: BudgetPeriod,
+        payment: SubscriptionPayment,
+        renews_at: Option<u64>,
+    ) -> Result<Subscription, MutinyError> {
+        let now = utils::now().as_secs();
+        let next_renewal = renews_at
+            .filter(|t| *t > now)
+            .unwrap_or_else(|| next_renewal(now, &period));
+        let subscription = Subscription {
+            id: Uuid::new_v4().to_string(),
+            name,
+            amount_sats,
+            next_renewal,
+            first_renewal: Some(next_renewal),
+            period,
+            payment,
+            status: SubscriptionStatus::Active,
+            created_at: now,
+            reminded: false,
+        };
+
+        let mut subscriptions = get_subscriptions(&self.storage)?;
+        subscriptions.push(subscription.clone());
+        set_subscriptions(&self.storage, &subscriptions)?;
+
+        log_info!(self.logger, "Registered subscription {}", subscription.name);
+        Ok(subscription)
+    }
+
+    /// Registers a recurring payment to a service, renewing every period from now.
+    ///
+    /// With `nwc` the service gets its own NWC profile with a budget of the amount
+    /// every period, see [MutinyWallet::get_subscription_nwc_uri]. Otherwise the
+    /// service sends an invoice that the user pays after a renewal reminder,
+    /// see [MutinyWallet::take_subscription_events].
+    pub async fn register_subscription(
+        &self,
+        name: String,
+        amount_sats: u64,
+        period: BudgetPeriod,
+        nwc: bool,
+    ) -> Result<Subscription, MutinyError> {
+        log_trace!(self.logger, "calling register_subscription");
+        self.storage.check_writable()?;
+
+        if amount_sats == 0 || period == BudgetPeriod::Seconds(0) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let payment = if nwc {
+            let profile = self
+                .create_subscription_nwc_profile(
+                    ProfileType::Normal { name: name.clone() },
+                    amount_sats,
+                    period.clone(),
+                    true,
+                )
+                .await?;
+            SubscriptionPayment::Nwc {
+                profile_index: profile.index,
+            }
+        } else {
+            SubscriptionPayment::Invoice
+        };
+        let res = self.add_subscription(name, amount_sats, period, payment, None);
+
+        log_trace!(self.logger, "finished calling register_subscription");
+        res
+    }
+
+    /// Lists the subscriptions, including the paused and canceled ones
+    pub fn list_subscriptions(&self) -> Result<Vec<Subscription>, MutinyError> {
+        get_subscriptions(&self.storage)
+    }
+
+    /// The NWC connection string to hand to the service of a subscription
+    pub fn get_subscription_nwc_uri(&self, id: &str) -> Result<Option<String>, MutinyError> {
+        let subscription = get_subscriptions(&self.storage)?
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or(MutinyError::NotFound)?;
+        match subscription.payment {
+            SubscriptionPayment::Nwc { profile_index } => {
+                Ok(self.nostr.get_nwc_profile(profile_index)?.nwc_uri)
+            }
+            SubscriptionPayment::Invoice => Ok(None),
+        }
+    }
+
+    fn update_subscription(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut Subscription) -> Result<(), MutinyError>,
+    ) -> Result<Subscription, MutinyError> {
+        self.storage.check_writable()?;
+
+        let mut subscriptions = get_subscriptions(&self.storage)?;
+        let subscription = subscriptions
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or(MutinyError::NotFound)?;
+        f(subscription)?;
+        let subscription = subscription.clone();
+        set_subscriptions(&self.storage, &subscriptions)?;
+
+        Ok(subscription)
+    }
+
+    fn set_subscription_profile_enabled(
+        &self,
+        payment: &SubscriptionPayment,
+        enabled: bool,
+    ) -> Result<(), MutinyError> {
+        if let SubscriptionPayment::Nwc { profile_index } = payment {
+            let mut profile = self.nostr.get_nwc_profile(*profile_index)?;
+            profile.enabled = Some(enabled);
+            self.nostr.edit_nwc_profile(profile)?;
+        }
+        Ok(())
+    }
+
+    /// Pauses a subscription, its NWC profile can't pay anything until it is resumed
+    pub fn pause_subscription(&self, id: &str) -> Result<Subscription, MutinyError> {
+        self.update_subscription(id, |subscription| {
+            if subscription.status != SubscriptionStatus::Active {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+            self.set_subscription_profile_enabled(&subscription.payment, false)?;
+            subscription.status = SubscriptionStatus::Paused;
+            Ok(())
+        })
+    }
+
+    /// Resumes a paused subscription, the periods it was paused for are skipped
+    pub fn resume_subscription(&self, id: &str) -> Result<Subscription, MutinyError> {
+        self.update_subscription(id, |subscription| {
+            if subscription.status != SubscriptionStatus::Paused {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+            self.set_subscription_profile_enabled(&subscription.payment, true)?;
+            subscription.renew_after(utils::now().as_secs());
+            subscription.status = SubscriptionStatus::Active;
+            Ok(())
+        })
+    }
+
+    /// Cancels a subscription and deletes its NWC profile
+    pub fn cancel_subscription(&self, id: &str) -> Result<Subscription, MutinyError> {
+        self.update_subscription(id, |subscription| {
+            if subscription.status == SubscriptionStatus::Canceled {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+            if let SubscriptionPayment::Nwc { profile_index } = subscription.payment {
+                self.nostr.delete_nwc_profile(profile_index)?;
+            }
+            subscription.status = SubscriptionStatus::Canceled;
+            Ok(())
+        })
+    }
+
+    /// Returns the subscription events since the last call,
+    /// like reminders of upcoming renewals
+    pub async fn take_subscription_events(&self) -> Vec<SubscriptionEvent> {
+        std::mem::take(&mut *self.subscription_events.lock().await)
+    }
+
+    fn start_subscription_checker(&self) {
+        log_trace!(self.logger, "calling start_subscription_checker");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            SUBSCRIPTION_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.check_subscriptions().await {
+                        log_warn!(self_clone.logger, "Failed to check subscriptions: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_subscription_checker");
+    }
+
+    /// Moves the subscriptions to their next period and queues the renewal reminders
+    async fn check_subscriptions(&self) -> Result<(), MutinyError> {
+        let mut subscriptions = get_subscriptions(&self.storage)?;
+        let events = check_renewals(&mut subscriptions, utils::now().as_secs());
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        set_subscriptions(&self.storage, &subscriptions)?;
+        self.subscription_events.lock().await.extend(events);
+        Ok(())
+    }
+
     /// Uploads a profile pic to nostr.build and returns the uploaded file's URL
     pub async fn upload_profile_pic(&self, image_bytes: Vec<u8>) -> Result<String, MutinyError> {
         log_trace!(self.logger, "calling upload_profile_pic");
@@ -2687,6 +5017,24 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(new_pk)
     }
 
+    /// Signs with the remote signer of the `bunker://` URI over NIP-46 from now on,
+    /// see [connect_remote_signer]
+    pub async fn connect_nostr_remote_signer(
+        &self,
+        bunker_uri: String,
+    ) -> Result<::nostr::PublicKey, MutinyError> {
+        log_trace!(self.logger, "calling connect_nostr_remote_signer");
+
+        let source = connect_remote_signer(self.xprivkey, &bunker_uri).await?;
+        let new_pk = self.nostr.change_nostr_keys(source, self.xprivkey).await?;
+
+        // re-sync nostr profile data
+        self.sync_nostr().await?;
+
+        log_trace!(self.logger, "finished calling connect_nostr_remote_signer");
+        Ok(new_pk)
+    }
+
     /// Syncs all of our nostr data from the configured primal instance
     pub async fn sync_nostr(&self) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling sync_nostr");
@@ -2834,28 +5182,396 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(messages)
     }
 
-    /// Stops all of the nodes and background processes.
-    /// Returns after node has been stopped.
-    pub async fn stop(&self) -> Result<(), MutinyError> {
-        log_trace!(self.logger, "calling stop");
-
-        self.stop.store(true, Ordering::Relaxed);
+    /// Creates an invoice for the given amount and sends it to the given npub
+    /// as an encrypted direct message. If we have a contact for the npub, the
+    /// invoice will be labeled with the contact.
+    pub async fn request_payment_from_contact(
+        &self,
+        npub: ::nostr::PublicKey,
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling request_payment_from_contact");
 
-        self.node_manager.stop().await?;
+        let labels = self
+            .storage
+            .get_contact_for_npub(npub)?
+            .map(|(id, _)| vec![id])
+            .unwrap_or_default();
 
-        // stop the indexeddb object to close db connection
-        if self.storage.connected().unwrap_or(false) {
-            log_debug!(self.logger, "stopping storage");
-            self.storage.stop();
-            log_debug!(self.logger, "stopped storage");
-        }
+        let invoice = self
+            .create_lightning_invoice(amount, labels, &InvoiceParams::default())
+            .await?;
+        let bolt11 = invoice
+            .bolt11
+            .as_ref()
+            .ok_or(MutinyError::InvoiceCreationFailed)?;
+
+        // the receiving wallet will find the invoice by scanning the words of the message
+        let message = match memo.filter(|m| !m.trim().is_empty()) {
+            Some(memo) => format!("{memo}\n\n{bolt11}"),
+            None => bolt11.to_string(),
+        };
+        self.nostr.send_dm(npub, message).await?;
 
-        log_trace!(self.logger, "finished calling stop");
-        Ok(())
+        log_trace!(self.logger, "finished calling request_payment_from_contact");
+        Ok(invoice)
     }
 
-    pub async fn change_password(
-        &mut self,
+    /// Splits a payment in equal shares between the members of a contact group.
+    ///
+    /// With [SplitDirection::Request] we paid the bill, every member gets an invoice for
+    /// their share, sent to them as a DM when they have an npub. With [SplitDirection::Pay]
+    /// we pay out the whole total, every member gets their share to their lightning address
+    /// or LNURL, or keysent to their node when they have neither.
+    ///
+    /// A share that can't be requested or paid has its error recorded instead of failing
+    /// the whole split, use [MutinyWallet::list_payment_splits] to track who has paid.
+    pub async fn split_payment(
+        &self,
+        group_id: String,
+        total_sats: u64,
+        memo: Option<String>,
+        direction: SplitDirection,
+    ) -> Result<PaymentSplit, MutinyError> {
+        log_trace!(self.logger, "calling split_payment");
+        self.storage.check_writable()?;
+
+        let group = self
+            .storage
+            .get_contact_group(&group_id)?
+            .ok_or(MutinyError::NotFound)?;
+        let amounts = split_amount(total_sats, group.members.len())?;
+        let memo = memo.filter(|m| !m.trim().is_empty());
+
+        let mut shares = Vec::with_capacity(amounts.len());
+        for (contact_id, amount_sats) in group.members.into_iter().zip(amounts) {
+            let res = match self.storage.get_contact(&contact_id)? {
+                Some(contact) => match direction {
+                    SplitDirection::Request => {
+                        self.request_split_share(&contact_id, contact, amount_sats, &memo)
+                            .await
+                    }
+                    SplitDirection::Pay => {
+                        self.pay_split_share(&contact_id, contact, amount_sats, &memo)
+                            .await
+                    }
+                },
+                None => Err(MutinyError::NotFound),
+            };
+
+            let share = match res {
+                Ok(invoice) => SplitShare {
+                    contact_id,
+                    amount_sats,
+                    payment_hash: Some(invoice.payment_hash),
+                    paid: invoice.status == HTLCStatus::Succeeded,
+                    bolt11: invoice.bolt11,
+                    error: None,
+                },
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Could not split payment with {contact_id}: {e}"
+                    );
+                    SplitShare {
+                        contact_id,
+                        amount_sats,
+                        bolt11: None,
+                        payment_hash: None,
+                        paid: false,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            shares.push(share);
+        }
+
+        let split = PaymentSplit {
+            id: Uuid::new_v4().to_string(),
+            group_id,
+            memo,
+            total_sats,
+            direction,
+            shares,
+            created_at: utils::now().as_secs(),
+        };
+        save_payment_splits(&self.storage, &[split.clone()])?;
+
+        log_trace!(self.logger, "finished calling split_payment");
+        Ok(split)
+    }
+
+    async fn request_split_share(
+        &self,
+        contact_id: &str,
+        contact: Contact,
+        amount_sats: u64,
+        memo: &Option<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let params = InvoiceParams {
+            description: memo.clone(),
+            ..Default::default()
+        };
+        let invoice = self
+            .create_lightning_invoice(amount_sats, vec![contact_id.to_string()], &params)
+            .await?;
+
+        // members without an npub get the invoice shared some other way
+        if let Some(npub) = contact.npub {
+            let bolt11 = invoice
+                .bolt11
+                .as_ref()
+                .ok_or(MutinyError::InvoiceCreationFailed)?;
+            let message = match memo {
+                Some(memo) => format!("{memo}\n\n{bolt11}"),
+                None => bolt11.to_string(),
+            };
+            self.nostr.send_dm(npub, message).await?;
+        }
+
+        Ok(invoice)
+    }
+
+    async fn pay_split_share(
+        &self,
+        contact_id: &str,
+        contact: Contact,
+        amount_sats: u64,
+        memo: &Option<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let labels = vec![contact_id.to_string()];
+        match contact.lnurl.or(contact.ln_address.map(|a| a.lnurl())) {
+            Some(lnurl) => {
+                self.lnurl_pay(
+                    &lnurl,
+                    amount_sats,
+                    None,
+                    labels,
+                    memo.clone(),
+                    PrivacyLevel::default(),
+                )
+                .await
+            }
+            None => {
+                let node_pubkey = contact
+                    .node_pubkey
+                    .ok_or(MutinyError::InvalidArgumentsError)?;
+                self.node_manager
+                    .keysend(
+                        None,
+                        node_pubkey,
+                        amount_sats,
+                        memo.clone(),
+                        labels,
+                        None,
+                        None,
+                    )
+                    .await
+            }
+        }
+    }
+
+    /// Lists the payment splits we made, newest first. A share is shown as paid
+    /// once its payment succeeded, even if that was after the split was made.
+    pub fn list_payment_splits(&self) -> Result<Vec<PaymentSplit>, MutinyError> {
+        log_trace!(self.logger, "calling list_payment_splits");
+
+        let mut splits = get_payment_splits(&self.storage)?;
+        for share in splits
+            .iter_mut()
+            .flat_map(|s| s.shares.iter_mut())
+            .filter(|s| !s.paid)
+        {
+            share.paid = share.payment_hash.is_some_and(|hash| {
+                get_invoice_by_hash(&hash, &self.storage, &self.logger)
+                    .is_ok_and(|i| i.status == HTLCStatus::Succeeded)
+            });
+        }
+        splits.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        log_trace!(self.logger, "finished calling list_payment_splits");
+        Ok(splits)
+    }
+
+    /// Does a 2-party coinjoin with the given npub over nostr DMs, both parties
+    /// send `amount` to a new address of their own. The session id, the amount
+    /// and who is the initiator need to be agreed on beforehand.
+    pub async fn nostr_coinjoin(
+        &self,
+        npub: ::nostr::PublicKey,
+        session: String,
+        role: CoinjoinRole,
+        amount: u64,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling nostr_coinjoin");
+
+        let coinjoin = NostrCoinjoin::new(self.nostr.clone(), npub, session, amount, role);
+        let res = self
+            .node_manager
+            .collaborative_spend(&coinjoin, amount, labels, fee_rate)
+            .await;
+
+        log_trace!(self.logger, "finished calling nostr_coinjoin");
+        res
+    }
+
+    /// Creates a child wallet, a constrained wallet for a kid managed from this one.
+    /// The child spends from our balance with its own NWC connection, up to the allowance.
+    /// Its own seed is derived from ours with BIP-85, see [ChildWallet].
+    pub async fn create_child_wallet(
+        &self,
+        name: String,
+        allowance: u64,
+        period: BudgetPeriod,
+        single_max: Option<u64>,
+    ) -> Result<ChildWallet, MutinyError> {
+        log_trace!(self.logger, "calling create_child_wallet");
+        self.storage.check_writable()?;
+
+        let mut children = get_child_wallet_storage(&self.storage)?;
+        let key_index = children.next_key_index;
+        let mnemonic = derive_child_mnemonic(self.xprivkey, key_index)?;
+
+        let id = Uuid::new_v4().to_string();
+        self.child_wallet_storage(&id).insert_mnemonic(mnemonic)?;
+
+        let budget = BudgetedSpendingConditions {
+            budget: allowance,
+            single_max,
+            payments: vec![],
+            period,
+        };
+        let profile = self
+            .nostr
+            .create_new_nwc_profile(
+                ProfileType::Normal { name: name.clone() },
+                SpendingConditions::Budget(budget),
+                NwcProfileTag::General,
+                vec![
+                    Method::PayInvoice,
+                    Method::GetInfo,
+                    Method::GetBalance,
+                    Method::LookupInvoice,
+                    Method::MakeInvoice,
+                ],
+            )
+            .await?;
+
+        let child = ChildWallet {
+            id,
+            name,
+            key_index,
+            nwc_profile_index: profile.index,
+            created_at: utils::now().as_secs(),
+        };
+        children.wallets.push(child.clone());
+        children.next_key_index += 1;
+        set_child_wallet_storage(&self.storage, &children)?;
+
+        log_info!(self.logger, "Created child wallet {}", child.name);
+        log_trace!(self.logger, "finished calling create_child_wallet");
+        Ok(child)
+    }
+
+    /// Lists the child wallets managed from this wallet
+    pub fn list_child_wallets(&self) -> Result<Vec<ChildWallet>, MutinyError> {
+        Ok(get_child_wallet_storage(&self.storage)?.wallets)
+    }
+
+    fn child_wallet_storage(&self, id: &str) -> NamespacedStorage<S> {
+        NamespacedStorage::new(self.storage.clone(), Some(child_wallet_namespace(id)))
+    }
+
+    fn get_child_wallet(&self, id: &str) -> Result<ChildWallet, MutinyError> {
+        get_child_wallet_storage(&self.storage)?
+            .wallets
+            .into_iter()
+            .find(|c| c.id == id)
+            .ok_or(MutinyError::NotFound)
+    }
+
+    /// The balance of a child wallet, what is left of its allowance this period
+    pub fn get_child_wallet_balance(&self, id: &str) -> Result<u64, MutinyError> {
+        let child = self.get_child_wallet(id)?;
+        let profile = self.nostr.get_nwc_profile(child.nwc_profile_index)?;
+
+        match profile.spending_conditions {
+            SpendingConditions::Budget(budget) => Ok(budget.budget_remaining()),
+            _ => Ok(0),
+        }
+    }
+
+    /// Changes the allowance of a child wallet, payments made this period still count
+    pub fn set_child_wallet_allowance(
+        &self,
+        id: &str,
+        allowance: u64,
+        period: BudgetPeriod,
+        single_max: Option<u64>,
+    ) -> Result<NwcProfile, MutinyError> {
+        self.storage.check_writable()?;
+        let child = self.get_child_wallet(id)?;
+        self.nostr
+            .set_nwc_profile_budget(child.nwc_profile_index, allowance, period, single_max)
+    }
+
+    /// Gets the seed of a child wallet, to set it up on the kid's own device
+    pub fn get_child_wallet_mnemonic(&self, id: &str) -> Result<Mnemonic, MutinyError> {
+        let child = self.get_child_wallet(id)?;
+        self.child_wallet_storage(&child.id)
+            .get_mnemonic()?
+            .ok_or(MutinyError::NotFound)
+    }
+
+    /// Removes a child wallet, its NWC connection and its storage.
+    /// The seed of the child is never used again for a new child wallet.
+    pub async fn remove_child_wallet(&self, id: &str) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling remove_child_wallet");
+        self.storage.check_writable()?;
+
+        let mut children = get_child_wallet_storage(&self.storage)?;
+        let Some(index) = children.wallets.iter().position(|c| c.id == id) else {
+            return Err(MutinyError::NotFound);
+        };
+        let child = children.wallets.remove(index);
+
+        self.nostr.delete_nwc_profile(child.nwc_profile_index)?;
+        self.child_wallet_storage(&child.id).delete_all().await?;
+        set_child_wallet_storage(&self.storage, &children)?;
+
+        log_trace!(self.logger, "finished calling remove_child_wallet");
+        Ok(())
+    }
+
+    /// Stops all of the nodes and background processes.
+    /// Returns after node has been stopped.
+    pub async fn stop(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling stop");
+
+        self.stop.store(true, Ordering::Relaxed);
+
+        self.node_manager.stop().await?;
+
+        // stop the indexeddb object to close db connection
+        if self.storage.connected().unwrap_or(false) {
+            // make sure everything we wrote is saved before closing it
+            if let Err(e) = self.storage.flush().await {
+                log_error!(self.logger, "Failed to flush storage: {e}");
+            }
+            log_debug!(self.logger, "stopping storage");
+            self.storage.stop();
+            log_debug!(self.logger, "stopped storage");
+        }
+
+        log_trace!(self.logger, "finished calling stop");
+        Ok(())
+    }
+
+    pub async fn change_password(
+        &mut self,
         old: Option<String>,
         new: Option<String>,
     ) -> Result<(), MutinyError> {
@@ -2870,6 +5586,14 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             return Err(MutinyError::SamePassword);
         }
 
+        // the new password can't be the one that opens the decoy wallet
+        if new
+            .as_deref()
+            .is_some_and(|p| get_duress_mnemonic(&self.storage, p).is_ok())
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
         log_info!(self.logger, "Changing password");
 
         self.stop().await?;
@@ -2881,16 +5605,67 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             new.filter(|s| !s.is_empty()),
         )?;
 
-        // There's not a good way to check that all the indexeddb
-        // data is saved in the background. This should get better
-        // once we have async saving, but for now just make sure
-        // the user has saved their seed already.
-        sleep(5_000).await;
+        // wait for the rewritten values to be saved
+        self.storage.flush().await?;
 
         log_trace!(self.logger, "finished calling change_password");
         Ok(())
     }
 
+    /// Sets a second password that opens a decoy wallet with its own seed instead of
+    /// this one, so a password can be revealed under coercion without exposing the
+    /// funds of this wallet. The decoy wallet starts empty and is funded like any other.
+    ///
+    /// Setting a new duress password replaces the decoy wallet.
+    /// The wallet needs a password, otherwise any password would open it.
+    pub async fn set_duress_password(&self, password: String) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_duress_password");
+
+        let Some(current) = self.storage.password() else {
+            return Err(MutinyError::InvalidArgumentsError);
+        };
+        if password.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if password == current {
+            return Err(MutinyError::SamePassword);
+        }
+
+        self.remove_duress_password().await?;
+        set_duress_mnemonic(
+            &self.storage,
+            &duress_namespace(&self.xprivkey),
+            &password,
+            &generate_seed(12)?,
+        )?;
+        log_info!(self.logger, "Set duress password");
+
+        log_trace!(self.logger, "finished calling set_duress_password");
+        Ok(())
+    }
+
+    /// Removes the duress password and deletes the decoy wallet
+    pub async fn remove_duress_password(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling remove_duress_password");
+
+        self.storage.check_writable()?;
+        let namespace = duress_namespace(&self.xprivkey);
+        let res = NamespacedStorage::duress(self.storage.clone(), namespace)
+            .delete_all()
+            .await;
+        log_trace!(self.logger, "finished calling remove_duress_password");
+
+        res
+    }
+
+    /// Returns if a duress password is set
+    pub fn has_duress_password(&self) -> Result<bool, MutinyError> {
+        let namespace = duress_namespace(&self.xprivkey);
+        let seed: Option<Value> =
+            NamespacedStorage::duress(self.storage.clone(), namespace).get(MNEMONIC_KEY)?;
+        Ok(seed.is_some())
+    }
+
     /// Resets BDK's keychain tracker. This will require a re-sync of the blockchain.
     ///
     /// This can be useful if you get stuck in a bad state.
@@ -2898,9 +5673,8 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         log_trace!(self.logger, "calling reset_onchain_tracker");
 
         self.node_manager.reset_onchain_tracker().await?;
-        // sleep for 250ms to give time for the storage to write
-        utils::sleep(250).await;
 
+        // stopping waits for the storage to write
         self.stop().await?;
 
         // sleep for 250ms to give time for the node manager to stop
@@ -2908,12 +5682,27 @@ impl<S: MutinyStorage> MutinyWallet<S> {
 
         self.start().await?;
 
+        let gap = FULL_SYNC_STOP_GAP.max(self.node_manager.wallet.gap_limit);
+        self.node_manager.wallet.full_sync(gap).await?;
+
+        log_trace!(self.logger, "finished calling reset_onchain_tracker");
+        Ok(())
+    }
+
+    /// Looks for on-chain funds beyond the usual scan window, checking `gap_limit`
+    /// unused addresses in a row. Transactions confirmed before `from_height` are
+    /// ignored, use 0 to scan the whole chain.
+    ///
+    /// This can be useful after a restore if the seed handed out a lot of unused addresses.
+    pub async fn deep_rescan(&self, gap_limit: usize, from_height: u32) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling deep_rescan");
+
         self.node_manager
             .wallet
-            .full_sync(FULL_SYNC_STOP_GAP)
+            .deep_rescan(gap_limit, from_height)
             .await?;
 
-        log_trace!(self.logger, "finished calling reset_onchain_tracker");
+        log_trace!(self.logger, "finished calling deep_rescan");
         Ok(())
     }
 
@@ -2935,6 +5724,8 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         // Delete our storage but insert some device specific data
         let device_id = storage.get_device_id()?;
         let logs: Option<Vec<String>> = storage.get_data(LOGGING_KEY)?;
+        // pending writes would be saved after the clear otherwise
+        storage.flush().await?;
         storage.stop();
         S::clear().await?;
         storage.start().await?;
@@ -2942,10 +5733,49 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         storage.set_data(NEED_FULL_SYNC_KEY.to_string(), true, None)?;
         storage.set_data(DEVICE_ID_KEY.to_string(), device_id, None)?;
         storage.set_data(LOGGING_KEY.to_string(), logs, None)?;
+        storage.flush().await?;
 
         Ok(())
     }
 
+    /// Restores the mnemonic and the channels of a backup from [MutinyWallet::create_scb].
+    ///
+    /// This is an emergency recovery, once the wallet starts our peers force close the
+    /// channels and the funds are swept back to the on-chain wallet.
+    /// Same as [MutinyWallet::restore_mnemonic], the wallet should be stopped and then restarted.
+    pub async fn recover_from_scb(
+        storage: S,
+        m: Mnemonic,
+        passphrase: Option<&str>,
+        network: Network,
+        scb: &str,
+    ) -> Result<(), MutinyError> {
+        // decrypt before touching our storage, so a bad backup doesn't wipe it
+        let xprivkey = xprivkey_from_mnemonic(&m, passphrase, network)?;
+        let backup = StaticChannelBackup::decrypt(scb, xprivkey)?;
+
+        Self::restore_mnemonic(storage.clone(), m).await?;
+        let logger = Arc::new(MutinyLogger::default());
+        restore_static_channel_backup(&storage, backup, logger).await
+    }
+
+    /// Restores the whole wallet from a backup made with [MutinyWallet::set_remote_backup],
+    /// overwriting our storage.
+    ///
+    /// Should refresh or restart afterwards. Wallet should be stopped.
+    pub async fn restore_from_backup(
+        m: Mnemonic,
+        passphrase: Option<&str>,
+        network: Network,
+        target: &impl BackupTarget,
+    ) -> Result<(), MutinyError> {
+        let xprivkey = xprivkey_from_mnemonic(&m, passphrase, network)?;
+        let backup = target.download().await?.ok_or(MutinyError::NotFound)?;
+        let json = decrypt_backup(backup, xprivkey)?;
+
+        S::import(json).await
+    }
+
     /// Decodes a lightning invoice into useful information.
     /// Will return an error if the invoice is for a different network.
     pub fn decode_invoice(
@@ -3020,6 +5850,123 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(federation_identities)
     }
 
+    /// Gets the user's preference for which federation to use for payments
+    pub fn get_federation_preference(&self) -> Result<FederationPreference, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(FEDERATION_PREFERENCE_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Sets how we pick which federation to use when paying and receiving
+    pub fn set_federation_routing_policy(
+        &self,
+        policy: FederationRoutingPolicy,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_federation_routing_policy");
+
+        let mut preference = self.get_federation_preference()?;
+        preference.policy = policy;
+        self.storage
+            .set_data(FEDERATION_PREFERENCE_KEY.to_string(), preference, None)?;
+        log_trace!(
+            self.logger,
+            "finished calling set_federation_routing_policy"
+        );
+
+        Ok(())
+    }
+
+    /// Sets the federation to use first when paying and receiving with
+    /// [FederationRoutingPolicy::PinnedDefault]. Passing `None` clears the default.
+    pub async fn set_default_federation(
+        &self,
+        federation_id: Option<FederationId>,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_default_federation");
+
+        if let Some(id) = federation_id.as_ref() {
+            if !self.federations.read().await.contains_key(id) {
+                return Err(MutinyError::NotFound);
+            }
+        }
+
+        let mut preference = self.get_federation_preference()?;
+        preference.default_federation = federation_id;
+        self.storage
+            .set_data(FEDERATION_PREFERENCE_KEY.to_string(), preference, None)?;
+        log_trace!(self.logger, "finished calling set_default_federation");
+
+        Ok(())
+    }
+
+    /// Lists our federations in the order they should be tried to send the amount
+    async fn send_federation_ids(
+        &self,
+        amount_sats: u64,
+    ) -> Result<Vec<FederationId>, MutinyError> {
+        let preference = self.get_federation_preference()?;
+        let candidates = self.federation_candidates(amount_sats).await;
+        Ok(preference.sort_for_send(amount_sats, candidates))
+    }
+
+    /// Lists our federations in the order they should be tried to receive the amount
+    async fn receive_federation_ids(
+        &self,
+        amount_sats: u64,
+    ) -> Result<Vec<FederationId>, MutinyError> {
+        let preference = self.get_federation_preference()?;
+        let candidates = self.federation_candidates(amount_sats).await;
+        Ok(preference.sort_for_receive(candidates))
+    }
+
+    /// The balance and gateway fee of each federation, federations we can't get them
+    /// for are left out so one federation that is down doesn't stop the others
+    async fn federation_candidates(&self, amount_sats: u64) -> Vec<FederationCandidate> {
+        let federations: Vec<_> = self
+            .federations
+            .read()
+            .await
+            .iter()
+            .map(|(id, client)| (*id, client.clone()))
+            .collect();
+
+        let mut candidates = Vec::with_capacity(federations.len());
+        for (federation_id, client) in federations {
+            let balance = match client.get_balance().await {
+                Ok(balance) => balance,
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Skipping federation {federation_id}, could not get balance: {e}"
+                    );
+                    continue;
+                }
+            };
+            let fee = if client.has_gateway().await {
+                match client.gateway_fee().await {
+                    Ok(fees) => Some(fees.fee_for_amount(amount_sats)),
+                    Err(e) => {
+                        log_warn!(
+                            self.logger,
+                            "Skipping federation {federation_id}, could not get gateway fee: {e}"
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            candidates.push(FederationCandidate {
+                federation_id,
+                balance,
+                fee,
+            });
+        }
+
+        candidates
+    }
+
     /// Removes a federation by removing it from the user's federation list.
     pub async fn remove_federation(&self, federation_id: FederationId) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling remove_federation");
@@ -3113,31 +6060,90 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(FederationBalances { balances })
     }
 
-    pub async fn resync_federation(&self, federation_id: FederationId) -> Result<(), MutinyError> {
-        if !self.safe_mode {
-            // cannot safely run unless in safe mode
-            return Err(MutinyError::AlreadyRunning);
-        }
+    /// Gets an on-chain address to deposit (peg-in) directly into the given federation.
+    pub async fn get_federation_deposit_address(
+        &self,
+        federation_id: FederationId,
+        labels: Vec<String>,
+    ) -> Result<Address, MutinyError> {
+        log_trace!(self.logger, "calling get_federation_deposit_address");
 
-        let invite_code = self
-            .federation_storage
+        let fedimint_client = self
+            .federations
             .read()
             .await
-            .federations
-            .values()
-            .find(|f| f.federation_code.federation_id() == federation_id)
-            .ok_or(MutinyError::NotFound)?
-            .federation_code
-            .clone();
+            .get(&federation_id)
+            .cloned()
+            .ok_or(MutinyError::NotFound)?;
 
-        FederationClient::start_resync(
-            invite_code,
-            self.xprivkey,
-            self.storage.clone(),
-            self.network,
-            self.logger.clone(),
-        )
-        .await?;
+        let res = fedimint_client.get_new_address(labels).await;
+        log_trace!(
+            self.logger,
+            "finished calling get_federation_deposit_address"
+        );
+
+        res
+    }
+
+    /// Withdraws (peg-out) from the given federation to an on-chain address.
+    /// The amount is in satoshis, the federation's peg-out fee is paid on top of it.
+    pub async fn withdraw_from_federation(
+        &self,
+        federation_id: FederationId,
+        send_to: Address,
+        amount: u64,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling withdraw_from_federation");
+
+        if amount < DUST_LIMIT {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        let fedimint_client = self
+            .federations
+            .read()
+            .await
+            .get(&federation_id)
+            .cloned()
+            .ok_or(MutinyError::NotFound)?;
+
+        let balance = fedimint_client.get_balance().await?;
+        if balance < amount {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        let res = fedimint_client.send_onchain(send_to, amount, labels).await;
+        log_trace!(self.logger, "finished calling withdraw_from_federation");
+
+        res
+    }
+
+    pub async fn resync_federation(&self, federation_id: FederationId) -> Result<(), MutinyError> {
+        if !self.safe_mode {
+            // cannot safely run unless in safe mode
+            return Err(MutinyError::AlreadyRunning);
+        }
+
+        let invite_code = self
+            .federation_storage
+            .read()
+            .await
+            .federations
+            .values()
+            .find(|f| f.federation_code.federation_id() == federation_id)
+            .ok_or(MutinyError::NotFound)?
+            .federation_code
+            .clone();
+
+        FederationClient::start_resync(
+            invite_code,
+            self.xprivkey,
+            self.storage.clone(),
+            self.network,
+            self.logger.clone(),
+        )
+        .await?;
 
         Ok(())
     }
@@ -3150,6 +6156,737 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         self.storage.get_data(storage_key)
     }
 
+    /// Recovers the ecash of all our federations from our seed.
+    /// This should be called after restoring from a mnemonic so federation balances
+    /// are re-derived from what the federations know about us.
+    ///
+    /// Like [MutinyWallet::resync_federation], this can only be run in safe mode.
+    /// Progress can be checked with [MutinyWallet::get_federation_resync_progress].
+    pub async fn recover_federations(&self) -> Result<Vec<FederationId>, MutinyError> {
+        log_trace!(self.logger, "calling recover_federations");
+
+        if !self.safe_mode {
+            // cannot safely run unless in safe mode
+            return Err(MutinyError::AlreadyRunning);
+        }
+
+        let invite_codes = self
+            .federation_storage
+            .read()
+            .await
+            .federations
+            .values()
+            .map(|f| f.federation_code.clone())
+            .collect_vec();
+
+        let mut recovering = Vec::with_capacity(invite_codes.len());
+        for invite_code in invite_codes {
+            let federation_id = invite_code.federation_id();
+            log_info!(self.logger, "Recovering federation {federation_id}");
+            FederationClient::start_resync(
+                invite_code,
+                self.xprivkey,
+                self.storage.clone(),
+                self.network,
+                self.logger.clone(),
+            )
+            .await?;
+            recovering.push(federation_id);
+        }
+        log_trace!(self.logger, "finished calling recover_federations");
+
+        Ok(recovering)
+    }
+
+    /// Starts a background process that periodically backs up our ecash to each
+    /// federation, so it can be recovered with [MutinyWallet::recover_federations].
+    fn start_federation_backups(&self) {
+        log_trace!(self.logger, "calling start_federation_backups");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let federations = self.federations.clone();
+        let logger = self.logger.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            FEDERATION_BACKUP_INTERVAL_SECS,
+            move || {
+                let federations = federations.clone();
+                let logger = logger.clone();
+                async move {
+                    let clients = federations.read().await.values().cloned().collect_vec();
+                    for client in clients {
+                        match client.backup_to_federation().await {
+                            Ok(_) => log_debug!(logger, "backed up ecash to federation"),
+                            Err(e) => log_error!(logger, "error backing up ecash: {e}"),
+                        }
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_federation_backups");
+    }
+
+    /// Starts a background process that follows the operator's signed maintenance notices,
+    /// pausing flows that depend on the LSP while one is active.
+    fn start_maintenance_checker(&self) {
+        log_trace!(self.logger, "calling start_maintenance_checker");
+
+        if self.safe_mode || self.config.maintenance_pubkey.is_none() {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            MAINTENANCE_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.check_maintenance().await {
+                        log_warn!(self_clone.logger, "Failed to check maintenance notice: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_maintenance_checker");
+    }
+
+    /// Fetches the operator's latest maintenance notice and updates our state
+    async fn check_maintenance(&self) -> Result<(), MutinyError> {
+        let Some(operator) = self.config.maintenance_pubkey else {
+            return Ok(());
+        };
+
+        let event = match self.config.maintenance_url.as_ref() {
+            Some(url) => fetch_maintenance_event(url).await?,
+            None => self
+                .nostr
+                .client
+                .get_events_of(
+                    vec![maintenance_filter(operator)],
+                    Some(Duration::from_secs(10)),
+                )
+                .await
+                .map_err(|_| MutinyError::NostrError)?
+                .into_iter()
+                .max_by_key(|e| e.created_at),
+        };
+
+        let notice = match event {
+            Some(event) => {
+                let notice = parse_maintenance_event(&event, &operator)?;
+                if !record_maintenance_event_time(&self.storage, event.created_at.as_u64())? {
+                    log_warn!(
+                        self.logger,
+                        "Ignoring maintenance notice older than the last one"
+                    );
+                    return Ok(());
+                }
+                notice
+            }
+            None => None,
+        };
+
+        let mut current = self.maintenance.write().await;
+        if *current != notice {
+            let event = match notice.as_ref() {
+                Some(n) => {
+                    log_warn!(self.logger, "Service maintenance: {}", n.reason);
+                    MaintenanceEvent::Updated { notice: n.clone() }
+                }
+                None => {
+                    log_info!(self.logger, "Service maintenance cleared");
+                    MaintenanceEvent::Cleared
+                }
+            };
+            *current = notice;
+            self.maintenance_events.lock().await.push(event);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the service operator's active maintenance or incident notice, if any,
+    /// so the reason can be displayed to the user.
+    pub async fn get_maintenance_notice(&self) -> Option<MaintenanceNotice> {
+        self.maintenance
+            .read()
+            .await
+            .clone()
+            .filter(|n| n.is_active())
+    }
+
+    /// Returns the changes to the operator's maintenance notice since the last call,
+    /// so the app can show or hide the notice as it happens.
+    pub async fn take_maintenance_events(&self) -> Vec<MaintenanceEvent> {
+        std::mem::take(&mut *self.maintenance_events.lock().await)
+    }
+
+    async fn is_lsp_paused(&self) -> bool {
+        self.get_maintenance_notice()
+            .await
+            .is_some_and(|n| n.pause_lsp)
+    }
+
+    /// Starts a background process that follows our pending swaps,
+    /// refunding the ones the provider failed to pay once they time out.
+    fn start_swap_checker(&self) {
+        log_trace!(self.logger, "calling start_swap_checker");
+
+        if self.safe_mode || self.swap_client.is_none() {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(self.stop.clone(), SWAP_CHECK_INTERVAL_SECS, move || {
+            let self_clone = self_clone.clone();
+            async move {
+                if let Err(e) = self_clone.check_swaps().await {
+                    log_warn!(self_clone.logger, "Failed to check swaps: {e}");
+                }
+            }
+        });
+
+        log_trace!(self.logger, "finished calling start_swap_checker");
+    }
+
+    fn start_remote_backup_checker(&self) {
+        log_trace!(self.logger, "calling start_remote_backup_checker");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            REMOTE_BACKUP_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.check_remote_backup().await {
+                        log_warn!(self_clone.logger, "Failed to back up remotely: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_remote_backup_checker");
+    }
+
+    /// Sends our channel backups to our LSPs and recovers from the ones they give back,
+    /// see [crate::peerstorage]
+    fn start_peer_storage_checker(&self) {
+        log_trace!(self.logger, "calling start_peer_storage_checker");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            PEER_STORAGE_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.node_manager.check_peer_storage().await {
+                        log_warn!(self_clone.logger, "Failed to check peer storage: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_peer_storage_checker");
+    }
+
+    /// Keeps a backup of our contacts, labels and NWC profiles on the nostr relays,
+    /// see [crate::nostr::nip78]. With VSS they are already backed up.
+    fn start_social_backup_checker(&self) {
+        log_trace!(self.logger, "calling start_social_backup_checker");
+
+        if self.safe_mode || self.storage.vss_client().is_some() {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            SOCIAL_BACKUP_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.check_social_backup().await {
+                        log_warn!(self_clone.logger, "Failed to back up social data: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_social_backup_checker");
+    }
+
+    /// Watches for other devices asking for the device lock, when one does the wallet
+    /// is stopped and the lock released, see [MutinyStorage::request_device_handoff]
+    fn start_device_handoff_checker(&self) {
+        log_trace!(self.logger, "calling start_device_handoff_checker");
+
+        // other devices can only reach us through VSS
+        if self.safe_mode || self.storage.vss_client().is_none() {
+            return;
+        }
+
+        let started = utils::now().as_secs();
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            DEVICE_HANDOFF_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    // handing the lock off stops the wallet, and with it this checker
+                    if let Err(e) = self_clone.check_device_handoff(started).await {
+                        log_warn!(self_clone.logger, "Failed to check device handoff: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_device_handoff_checker");
+    }
+
+    /// Hands the device lock over if another device asked for it since we started.
+    /// Returns true if the wallet was stopped.
+    async fn check_device_handoff(&self, started: u64) -> Result<bool, MutinyError> {
+        let Some(request) = self.storage.fetch_device_handoff_request().await? else {
+            return Ok(false);
+        };
+        let device = self.storage.get_device_id()?;
+        if request.device == device || (request.time as u64) < started {
+            return Ok(false);
+        }
+
+        log_info!(
+            self.logger,
+            "Device {} requested the device lock, handing it off",
+            request.device
+        );
+        self.device_handoff_events
+            .lock()
+            .await
+            .push(DeviceHandoffEvent::Requested {
+                device: request.device.clone(),
+                time: request.time,
+            });
+
+        // stop everything that writes to storage before giving up the lock
+        self.stop.store(true, Ordering::Relaxed);
+        self.node_manager.stop().await?;
+        self.storage.release_device_lock().await?;
+        self.stop().await?;
+
+        self.device_handoff_events
+            .lock()
+            .await
+            .push(DeviceHandoffEvent::Released {
+                device: request.device,
+            });
+        log_info!(self.logger, "Released the device lock");
+
+        Ok(true)
+    }
+
+    /// Lists the payments waiting for approval: NWC requests that need approval,
+    /// invoices we got over DM and subscription renewals.
+    pub fn list_pending_requests(&self) -> Result<Vec<PaymentRequest>, MutinyError> {
+        self.payment_requests.list_pending_requests()
+    }
+
+    /// Pays a pending payment request, see [MutinyWallet::list_pending_requests]
+    pub async fn approve_payment_request(&self, id: sha256::Hash) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling approve_payment_request");
+        let res = self.payment_requests.approve(id, self).await;
+        log_trace!(self.logger, "finished calling approve_payment_request");
+
+        res
+    }
+
+    /// Rejects a pending payment request without paying it
+    pub async fn reject_payment_request(&self, id: sha256::Hash) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling reject_payment_request");
+        let res = self.payment_requests.reject(id).await;
+        log_trace!(self.logger, "finished calling reject_payment_request");
+
+        res
+    }
+
+    /// Returns the payment requests that arrived since the last call
+    pub async fn take_payment_request_events(&self) -> Vec<PaymentRequestEvent> {
+        self.payment_requests.take_events().await
+    }
+
+    /// Returns the device handoff events since the last call. When another device takes
+    /// over the wallet stops by itself, these let the app know why.
+    pub async fn take_device_handoff_events(&self) -> Vec<DeviceHandoffEvent> {
+        std::mem::take(&mut *self.device_handoff_events.lock().await)
+    }
+
+    /// How starting each federation client went. Federations that failed to start
+    /// are skipped until the wallet is started again.
+    pub async fn get_federation_init_statuses(&self) -> Vec<FederationInitStatus> {
+        self.federation_init_statuses.read().await.clone()
+    }
+
+    /// Waits until the nodes, federations and nostr are done starting.
+    /// Only needed with fast start, see [MutinyWalletConfigBuilder::with_fast_start].
+    /// Subsystems that failed to start are reported by [MutinyWallet::get_subsystem_status].
+    pub async fn ready(&self) {
+        self.readiness.ready().await
+    }
+
+    pub fn get_subsystem_status(&self, subsystem: Subsystem) -> SubsystemStatus {
+        self.readiness.status(subsystem)
+    }
+
+    /// Returns the subsystems that finished starting since the last call
+    pub fn take_readiness_events(&self) -> Vec<ReadinessEvent> {
+        self.readiness.take_events()
+    }
+
+    /// Starts the nodes, federations and nostr concurrently for fast start
+    fn start_subsystems_in_background(&self) {
+        log_trace!(self.logger, "calling start_subsystems_in_background");
+
+        let nm = self.node_manager.clone();
+        let readiness = self.readiness.clone();
+        utils::spawn(async move {
+            let res = async {
+                nm.start_nodes().await?;
+                // if we don't have any nodes, create one
+                if nm.list_nodes().await?.is_empty() && nm.list_failed_nodes().await.is_empty() {
+                    nm.new_node().await?;
+                }
+                Ok::<(), MutinyError>(())
+            }
+            .await;
+            if let Err(e) = &res {
+                log_error!(nm.logger, "Failed to start nodes: {e}");
+            }
+            readiness.set_result(Subsystem::Nodes, res);
+        });
+
+        let self_clone = self.clone();
+        utils::spawn(async move {
+            let federation_storage = self_clone.federation_storage.read().await.clone();
+            if !federation_storage.federations.is_empty() {
+                let start = Instant::now();
+                let (federations, statuses) = create_federations(
+                    federation_storage,
+                    &self_clone.config,
+                    self_clone.storage.clone(),
+                    self_clone.esplora.clone(),
+                    self_clone.stop.clone(),
+                    &self_clone.logger,
+                    self_clone.safe_mode,
+                )
+                .await;
+                let success = statuses.iter().all(|s| s.error.is_none());
+                self_clone.node_manager.performance.record(
+                    Operation::FederationsInit,
+                    start,
+                    success,
+                );
+
+                let federations = federations.read().await.clone();
+                self_clone.federations.write().await.extend(federations);
+                *self_clone.federation_init_statuses.write().await = statuses;
+            }
+            self_clone.start_fedimint_background_checker().await;
+            // federations that failed to start are in the init statuses
+            self_clone
+                .readiness
+                .set_status(Subsystem::Federations, SubsystemStatus::Ready);
+        });
+
+        let self_clone = self.clone();
+        utils::spawn(async move {
+            let res = async {
+                // connect to relays when not in tests
+                #[cfg(not(test))]
+                self_clone.nostr.connect().await?;
+                self_clone.start_nostr().await;
+                let profile_key = self_clone.nostr_profile_key().await;
+                self_clone.start_hermes(profile_key).await
+            }
+            .await;
+            if let Err(e) = &res {
+                log_error!(self_clone.logger, "Failed to start nostr: {e}");
+            }
+            self_clone.readiness.set_result(Subsystem::Nostr, res);
+        });
+
+        log_trace!(
+            self.logger,
+            "finished calling start_subsystems_in_background"
+        );
+    }
+
+    /// Gets the profile key if we have it, hermes needs this to decrypt private zaps
+    async fn nostr_profile_key(&self) -> Option<Keys> {
+        match &self.nostr.nostr_keys.read().await.signer {
+            NostrSigner::Keys(keys) => Some(keys.clone()),
+            #[cfg(target_arch = "wasm32")]
+            NostrSigner::NIP07(_) => None,
+            NostrSigner::NIP46(_) => None,
+        }
+    }
+
+    fn start_compaction_checker(&self) {
+        log_trace!(self.logger, "calling start_compaction_checker");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            COMPACTION_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.check_compaction().await {
+                        log_warn!(self_clone.logger, "Failed to compact storage: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_compaction_checker");
+    }
+
+    /// Compacts storage if automatic compaction is enabled and the last one is too old
+    async fn check_compaction(&self) -> Result<(), MutinyError> {
+        if !get_compaction_policy(&self.storage)?.automatic {
+            return Ok(());
+        }
+
+        let last = get_last_compaction(&self.storage)?.map(|s| s.timestamp);
+        let now = utils::now().as_secs();
+        if last.is_some_and(|t| now.saturating_sub(t) < COMPACTION_INTERVAL_SECS) {
+            return Ok(());
+        }
+
+        self.compact_storage().await?;
+        Ok(())
+    }
+
+    fn start_price_checker(&self) {
+        log_trace!(self.logger, "calling start_price_checker");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(self.stop.clone(), PRICE_CHECK_INTERVAL_SECS, move || {
+            let self_clone = self_clone.clone();
+            async move {
+                if let Err(e) = self_clone.record_activity_prices().await {
+                    log_warn!(self_clone.logger, "Failed to record activity prices: {e}");
+                }
+            }
+        });
+
+        log_trace!(self.logger, "finished calling start_price_checker");
+    }
+
+    /// Records the current price for activity that settled recently, in every currency
+    /// the price was looked up in
+    async fn record_activity_prices(&self) -> Result<(), MutinyError> {
+        let now = utils::now().as_secs();
+        let mut currencies: Vec<String> = {
+            let cache = self.bitcoin_price_cache.lock().await;
+            cache.keys().cloned().collect()
+        };
+        if !currencies.iter().any(|c| c == DEFAULT_PRICE_CURRENCY) {
+            currencies.push(DEFAULT_PRICE_CURRENCY.to_string());
+        }
+
+        // settled activity is sorted newest first after the pending items
+        let mut keys = vec![];
+        {
+            let index = self.storage.activity_index();
+            let index = index.try_read()?;
+            for item in index.iter() {
+                let Some(timestamp) = item.timestamp else {
+                    continue;
+                };
+                if now.saturating_sub(timestamp) > PRICE_RECORD_MAX_AGE_SECS {
+                    break;
+                }
+                keys.push(item.key.clone());
+            }
+        }
+
+        let mut missing = vec![];
+        for key in keys {
+            let recorded = get_activity_price(&self.storage, &key)?;
+            if recorded.map_or(true, |r| {
+                currencies.iter().any(|c| !r.prices.contains_key(c))
+            }) {
+                missing.push(key);
+            }
+        }
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut prices = HashMap::new();
+        for currency in currencies {
+            match self.get_bitcoin_price(Some(currency.clone())).await {
+                Ok(price) => {
+                    prices.insert(currency, price);
+                }
+                Err(e) => log_warn!(self.logger, "Failed to get {currency} price: {e}"),
+            }
+        }
+        if prices.is_empty() {
+            return Err(MutinyError::BitcoinPriceError);
+        }
+
+        for key in missing {
+            record_activity_price(&self.storage, &key, now, &prices)?;
+        }
+
+        Ok(())
+    }
+
+    /// Backs up to the configured remote target if the last backup is too old
+    async fn check_remote_backup(&self) -> Result<(), MutinyError> {
+        if get_remote_backup_config(&self.storage)?.is_none() {
+            return Ok(());
+        }
+
+        let last_backup = get_remote_backup_status(&self.storage)?.last_backup;
+        let now = utils::now().as_secs();
+        if last_backup.is_some_and(|t| now.saturating_sub(t) < REMOTE_BACKUP_INTERVAL_SECS) {
+            return Ok(());
+        }
+
+        self.backup_to_remote().await
+    }
+
+    /// Publishes our social backup when it changed, merging the one on the relays
+    /// first so we don't replace what another install backed up
+    async fn check_social_backup(&self) -> Result<(), MutinyError> {
+        if self.storage.is_read_only() {
+            return Ok(());
+        }
+
+        let key = backup_encryption_key(self.xprivkey)?;
+        self.nostr.sync_social_backup(&key).await?;
+        Ok(())
+    }
+
+    /// Updates our pending swaps from the provider and refunds the failed ones that timed out
+    async fn check_swaps(&self) -> Result<(), MutinyError> {
+        let Some(swap_client) = self.swap_client.as_ref() else {
+            return Ok(());
+        };
+
+        let pending: Vec<SwapIn> = get_swap_ins(&self.storage)?
+            .into_iter()
+            .filter(|s| s.status.is_pending())
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let height = self.esplora.get_height().await?;
+        // one swap failing to update doesn't hold up the others
+        for mut swap in pending {
+            if let Err(e) = self.check_swap(swap_client, &mut swap, height).await {
+                log_warn!(self.logger, "Failed to check swap {}: {e}", swap.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_swap(
+        &self,
+        swap_client: &SwapClient,
+        swap: &mut SwapIn,
+        height: u32,
+    ) -> Result<(), MutinyError> {
+        if swap.status != SwapStatus::Failed {
+            let status = swap_client.get_swap_status(&swap.id).await?;
+            if let Some(status) = status.filter(|s| *s != swap.status) {
+                log_info!(self.logger, "Swap {} is now {status:?}", swap.id);
+                swap.status = status;
+                persist_swap_in(&self.storage, swap)?;
+            }
+        }
+
+        if swap.status == SwapStatus::Failed && height >= swap.timeout_block_height {
+            self.refund_swap(swap).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Refunds the lockup transaction of a timed out swap back to our wallet
+    async fn refund_swap(&self, swap: &mut SwapIn) -> Result<(), MutinyError> {
+        let redeem_script = parse_script(&swap.redeem_script)?;
+        let script_pubkey = Address::p2wsh(&redeem_script, self.network).script_pubkey();
+        let lockup = self
+            .esplora
+            .scripthash_txs(&script_pubkey, None)
+            .await?
+            .into_iter()
+            .find_map(|tx| {
+                let vout = tx
+                    .vout
+                    .iter()
+                    .position(|o| o.scriptpubkey == script_pubkey)?;
+                Some((OutPoint::new(tx.txid, vout as u32), tx.vout[vout].value))
+            });
+
+        let Some((lockup, value)) = lockup else {
+            // we never funded the swap, so there is nothing to refund
+            swap.status = SwapStatus::Expired;
+            return persist_swap_in(&self.storage, swap);
+        };
+
+        let refund_key = swap_refund_key(self.xprivkey, swap.refund_key_index)?;
+        let destination = self
+            .node_manager
+            .get_new_address(vec![SWAP_IN_LABEL.to_string()])?;
+        let fee_rate = self.node_manager.estimate_fee_normal() as f32;
+        let tx = build_refund_tx(
+            &redeem_script,
+            swap.timeout_block_height,
+            lockup,
+            value,
+            &refund_key,
+            destination.script_pubkey(),
+            fee_rate,
+        )?;
+        let txid = tx.txid();
+        self.node_manager.broadcast_transaction(tx).await?;
+        log_info!(self.logger, "Refunded swap {} in {txid}", swap.id);
+
+        swap.refund_txid = Some(txid);
+        swap.status = SwapStatus::Refunded;
+        persist_swap_in(&self.storage, swap)
+    }
+
     /// Starts a background process that will check pending fedimint operations
     pub(crate) async fn start_fedimint_background_checker(&self) {
         log_trace!(self.logger, "calling start_fedimint_background_checker");
@@ -3279,14 +7016,14 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                             }
                             PrivacyLevel::Private => {
                                 // if we have access to the keys, use those
-                                // otherwise need to implement ourselves to use with NIP-07
+                                // otherwise need to implement ourselves to use with
+                                // NIP-07 or NIP-46
                                 let signer = &self.nostr.nostr_keys.read().await.signer;
                                 match signer {
                                     NostrSigner::Keys(keys) => {
                                         nip57::private_zap_request(data, keys)?
                                     }
-                                    #[cfg(target_arch = "wasm32")]
-                                    NostrSigner::NIP07(_) => {
+                                    _ => {
                                         // Generate encryption key
                                         // Since we are not doing deterministically, we will
                                         // not be able to decrypt this ourself in the future.
@@ -3363,7 +7100,15 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                         }
                     }
 
-                    let mut inv = self.pay_invoice(&invoice, None, labels).await?;
+                    let mut inv = self
+                        .pay_invoice_internal(
+                            &invoice,
+                            None,
+                            labels,
+                            PayInvoiceOptions::default(),
+                            Some(lnurl),
+                        )
+                        .await?;
                     // save privacy level to storage, can skip if its the default privacy level
                     if privacy_level != PrivacyLevel::default() {
                         inv.privacy_level = privacy_level;
@@ -3390,18 +7135,92 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
-    /// Calls upon a LNURL and withdraws from it.
-    /// This will fail if the LNURL is not a LNURL withdrawal.
-    pub async fn lnurl_withdraw(
+    /// Calls upon a LNURL and withdraws from it.
+    /// This will fail if the LNURL is not a LNURL withdrawal.
+    ///
+    /// Returns if the service accepted our invoice, errors reaching it are returned.
+    /// Either way the withdrawal is tracked until the invoice is paid and requested
+    /// again when it fails, see [MutinyWallet::list_pending_withdrawals].
+    pub async fn lnurl_withdraw(
+        &self,
+        lnurl: &LnUrl,
+        amount_sats: u64,
+    ) -> Result<bool, MutinyError> {
+        log_trace!(self.logger, "calling lnurl_withdraw");
+        self.storage.check_writable()?;
+
+        let res = match self.request_withdrawal(lnurl, amount_sats).await {
+            // not a withdrawal at all, nothing to track
+            Err(MutinyError::IncorrectLnUrlFunction) => Err(MutinyError::IncorrectLnUrlFunction),
+            res => {
+                let now = utils::now().as_secs();
+                let mut withdrawal = PendingWithdrawal::new(
+                    Uuid::new_v4().to_string(),
+                    lnurl.to_string(),
+                    amount_sats,
+                    now,
+                );
+                withdrawal.record_attempt(&res, now);
+                save_withdrawals(&self.storage, &[withdrawal.clone()], now)?;
+                match res {
+                    Ok(_) => Ok(true),
+                    // the service turned it down
+                    Err(MutinyError::LnUrlFailure) => Ok(false),
+                    // it's retried in the background, but the caller still needs to know
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        log_trace!(self.logger, "finished calling lnurl_withdraw");
+
+        res
+    }
+
+    /// Withdraws from multiple LNURLs at once, each with its own amount.
+    ///
+    /// The withdrawals are tracked until our invoices are paid,
+    /// see [MutinyWallet::list_pending_withdrawals].
+    pub async fn lnurl_withdraw_batch(
+        &self,
+        withdrawals: Vec<(LnUrl, u64)>,
+    ) -> Result<Vec<PendingWithdrawal>, MutinyError> {
+        log_trace!(self.logger, "calling lnurl_withdraw_batch");
+        self.storage.check_writable()?;
+
+        let now = utils::now().as_secs();
+        let futures = withdrawals.iter().map(|(lnurl, amount_sats)| async move {
+            let mut withdrawal = PendingWithdrawal::new(
+                Uuid::new_v4().to_string(),
+                lnurl.to_string(),
+                *amount_sats,
+                now,
+            );
+            let res = self.request_withdrawal(lnurl, *amount_sats).await;
+            withdrawal.record_attempt(&res, now);
+            withdrawal
+        });
+        let started = futures::future::join_all(futures).await;
+        save_withdrawals(&self.storage, &started, now)?;
+        log_trace!(self.logger, "finished calling lnurl_withdraw_batch");
+
+        Ok(started)
+    }
+
+    /// Lists the LNURL withdrawals we are tracking, including the recently finished ones
+    pub fn list_pending_withdrawals(&self) -> Result<Vec<PendingWithdrawal>, MutinyError> {
+        get_withdrawals(&self.storage)
+    }
+
+    /// Requests a withdrawal with a new invoice,
+    /// returns its payment hash and expiry when the service accepted it
+    async fn request_withdrawal(
         &self,
         lnurl: &LnUrl,
         amount_sats: u64,
-    ) -> Result<bool, MutinyError> {
-        log_trace!(self.logger, "calling lnurl_withdraw");
-
+    ) -> Result<(sha256::Hash, u64), MutinyError> {
         let response = self.lnurl_client.make_request(&lnurl.url).await?;
 
-        let res = match response {
+        match response {
             LnUrlResponse::LnUrlPayResponse(_) => Err(MutinyError::IncorrectLnUrlFunction),
             LnUrlResponse::LnUrlChannelResponse(_) => Err(MutinyError::IncorrectLnUrlFunction),
             LnUrlResponse::LnUrlWithdrawResponse(withdraw) => {
@@ -3410,42 +7229,176 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                 let mutiny_invoice = self
                     .create_invoice(amount_sats, vec!["LNURL Withdrawal".to_string()])
                     .await?;
-                let invoice_str = mutiny_invoice.bolt11.expect("Invoice should have bolt11");
+                let invoice = mutiny_invoice.bolt11.expect("Invoice should have bolt11");
                 let res = self
                     .lnurl_client
-                    .do_withdrawal(&withdraw, &invoice_str.to_string())
+                    .do_withdrawal(&withdraw, &invoice.to_string())
                     .await?;
                 match res {
-                    Response::Ok { .. } => Ok(true),
-                    Response::Error { .. } => Ok(false),
+                    Response::Ok { .. } => {
+                        let expires_at = invoice.duration_since_epoch() + invoice.expiry_time();
+                        Ok((*invoice.payment_hash(), expires_at.as_secs()))
+                    }
+                    Response::Error { reason } => {
+                        log_warn!(self.logger, "LNURL withdrawal rejected: {reason}");
+                        Err(MutinyError::LnUrlFailure)
+                    }
                 }
             }
-        };
-        log_trace!(self.logger, "finished calling lnurl_withdraw");
+        }
+    }
 
-        res
+    fn start_withdrawal_checker(&self) {
+        log_trace!(self.logger, "calling start_withdrawal_checker");
+
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn_periodic(
+            self.stop.clone(),
+            WITHDRAWAL_CHECK_INTERVAL_SECS,
+            move || {
+                let self_clone = self_clone.clone();
+                async move {
+                    if let Err(e) = self_clone.check_withdrawals().await {
+                        log_warn!(self_clone.logger, "Failed to check withdrawals: {e}");
+                    }
+                }
+            },
+        );
+
+        log_trace!(self.logger, "finished calling start_withdrawal_checker");
+    }
+
+    /// Settles the withdrawals whose invoice got paid and
+    /// requests the failed and expired ones again
+    async fn check_withdrawals(&self) -> Result<(), MutinyError> {
+        let now = utils::now().as_secs();
+        let mut updated = vec![];
+        for mut withdrawal in get_withdrawals(&self.storage)? {
+            match withdrawal.status {
+                WithdrawalStatus::Pending if withdrawal.next_attempt <= now => {
+                    let res = match LnUrl::from_str(&withdrawal.lnurl) {
+                        Ok(lnurl) => {
+                            self.request_withdrawal(&lnurl, withdrawal.amount_sats)
+                                .await
+                        }
+                        Err(_) => Err(MutinyError::InvalidArgumentsError),
+                    };
+                    withdrawal.record_attempt(&res, now);
+                }
+                WithdrawalStatus::Requested => {
+                    let Some(hash) = withdrawal.payment_hash else {
+                        continue;
+                    };
+                    let paid = self
+                        .get_invoice_by_hash(&hash)
+                        .await
+                        .is_ok_and(|i| i.status == HTLCStatus::Succeeded);
+                    if paid {
+                        withdrawal.settle(now);
+                    } else if withdrawal.invoice_expires_at.is_some_and(|t| t <= now) {
+                        withdrawal.record_attempt(&Err(MutinyError::InvoiceExpired), now);
+                    } else {
+                        continue;
+                    }
+                }
+                _ => continue,
+            }
+            log_debug!(
+                self.logger,
+                "LNURL withdrawal {} is now {:?}",
+                withdrawal.id,
+                withdrawal.status
+            );
+            updated.push(withdrawal);
+        }
+
+        save_withdrawals(&self.storage, &updated, now)
     }
 
-    /// Authenticate with a LNURL-auth
+    /// Authenticate with a LNURL-auth, with the identity chosen for the service
+    /// with [MutinyWallet::set_lnurl_auth_identity] or the default one.
     pub async fn lnurl_auth(&self, lnurl: LnUrl) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling lnurl_auth");
 
-        let res = make_lnurl_auth_connection(
-            self.auth.clone(),
-            self.lnurl_client.clone(),
-            lnurl,
-            self.logger.clone(),
-        )
-        .await;
+        let url = Url::parse(&lnurl.url)?;
+        let domain = url.host_str().ok_or(MutinyError::LnUrlFailure)?.to_string();
+        let index = get_lnurl_auth_index(&self.storage, &domain)?;
+        let auth = self.auth.identity(index)?;
+        let linking_key = auth.linking_key(url)?;
+
+        let res =
+            make_lnurl_auth_connection(auth, self.lnurl_client.clone(), lnurl, self.logger.clone())
+                .await;
+
+        if res.is_ok() {
+            let identity = LnUrlAuthIdentity {
+                domain,
+                index,
+                linking_key,
+                last_login: Some(utils::now().as_secs()),
+            };
+            // we are logged in even if we can't remember it
+            if let Err(e) = save_lnurl_auth_identity(&self.storage, identity) {
+                log_warn!(self.logger, "Failed to save LNURL-auth login: {e}");
+            }
+        }
         log_trace!(self.logger, "finished calling lnurl_auth");
 
         res
     }
 
+    /// Lists the services we logged in to with LNURL-auth or chose an identity for,
+    /// most recent login first.
+    pub fn list_lnurl_auth_identities(&self) -> Result<Vec<LnUrlAuthIdentity>, MutinyError> {
+        let mut identities = get_lnurl_auth_identities(&self.storage)?;
+        identities.sort_by(|a, b| b.last_login.cmp(&a.last_login));
+        Ok(identities)
+    }
+
+    /// Chooses the identity to log in to the domain with LNURL-auth,
+    /// the service sees a different linking key for every identity. 0 is the default identity.
+    pub fn set_lnurl_auth_identity(
+        &self,
+        domain: String,
+        index: u32,
+    ) -> Result<LnUrlAuthIdentity, MutinyError> {
+        log_trace!(self.logger, "calling set_lnurl_auth_identity");
+        self.storage.check_writable()?;
+
+        let url = Url::parse(&format!("https://{domain}"))?;
+        let domain = url
+            .host_str()
+            .ok_or(MutinyError::InvalidArgumentsError)?
+            .to_string();
+        let linking_key = self.auth.identity(index)?.linking_key(url)?;
+
+        // the last login was with the identity we had before
+        let identity = LnUrlAuthIdentity {
+            domain,
+            index,
+            linking_key,
+            last_login: None,
+        };
+        save_lnurl_auth_identity(&self.storage, identity.clone())?;
+        log_trace!(self.logger, "finished calling set_lnurl_auth_identity");
+
+        Ok(identity)
+    }
+
     pub fn is_safe_mode(&self) -> bool {
         self.safe_mode
     }
 
+    /// Returns if the wallet was opened in a read-only session,
+    /// see [MutinyWalletBuilder::with_read_only]
+    pub fn is_read_only(&self) -> bool {
+        self.storage.is_read_only()
+    }
+
     /// Calls upon a Cashu mint and redeems/melts the token.
     pub async fn melt_cashu_token(
         &self,
@@ -3570,6 +7523,241 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(())
     }
 
+    /// Registers a lightning address with the LNURL proxy, payments to it are
+    /// received by this wallet while it is running
+    pub async fn register_ln_address(&self, name: String) -> Result<LnAddress, MutinyError> {
+        log_trace!(self.logger, "calling register_ln_address");
+
+        let client = self
+            .ln_address_client
+            .as_ref()
+            .ok_or(MutinyError::NotFound)?;
+        let res = client.register(name).await;
+        log_trace!(self.logger, "finished calling register_ln_address");
+
+        res
+    }
+
+    /// Stops the LNURL proxy from serving our lightning address
+    pub async fn unregister_ln_address(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling unregister_ln_address");
+
+        let client = self
+            .ln_address_client
+            .as_ref()
+            .ok_or(MutinyError::NotFound)?;
+        let res = client.unregister().await;
+        log_trace!(self.logger, "finished calling unregister_ln_address");
+
+        res
+    }
+
+    /// The lightning address this wallet receives payments for, if one is registered
+    pub fn get_ln_address(&self) -> Result<Option<LnAddress>, MutinyError> {
+        match self.ln_address_client.as_ref() {
+            Some(client) => client.get_address(),
+            None => Ok(None),
+        }
+    }
+
+    /// Payments requested through our lightning addresses, newest first
+    pub fn list_ln_address_payments(&self) -> Result<Vec<LnAddressPayment>, MutinyError> {
+        log_trace!(self.logger, "calling list_ln_address_payments");
+
+        let Some(client) = self.ln_address_client.as_ref() else {
+            return Ok(vec![]);
+        };
+        let mut payments = client.list_payments()?;
+        for payment in payments.iter_mut() {
+            let hash = sha256::Hash::from_str(&payment.payment_hash)?;
+            payment.paid = get_invoice_by_hash(&hash, &self.storage, &self.logger)
+                .is_ok_and(|i| i.status == HTLCStatus::Succeeded);
+        }
+        log_trace!(self.logger, "finished calling list_ln_address_payments");
+
+        Ok(payments)
+    }
+
+    /// Listens for the invoice requests the LNURL proxy forwards to us,
+    /// over nostr and over a websocket to the proxy
+    fn start_ln_address_listener(&self) {
+        log_trace!(self.logger, "calling start_ln_address_listener");
+
+        let Some(ln_address_client) = self.ln_address_client.clone() else {
+            return;
+        };
+        if self.safe_mode {
+            return;
+        }
+
+        let self_clone = self.clone();
+        utils::spawn(async move {
+            let logger = self_clone.logger.clone();
+            loop {
+                if self_clone.stop.load(Ordering::Relaxed) {
+                    break;
+                };
+
+                // nothing to listen for until an address is registered
+                if !ln_address_client.get_address().is_ok_and(|a| a.is_some()) {
+                    sleep(10_000).await;
+                    continue;
+                }
+
+                let client = nostr_sdk::Client::default();
+                client
+                    .add_relays(self_clone.nostr.get_relays())
+                    .await
+                    .expect("Failed to add relays");
+                client.connect().await;
+                client
+                    .subscribe(vec![ln_address_client.filter()], None)
+                    .await;
+
+                let mut notifications = client.notifications();
+                loop {
+                    let read_fut = notifications.recv().fuse();
+                    let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
+
+                    pin_mut!(read_fut, delay_fut);
+                    select! {
+                        notification = read_fut => {
+                            match notification {
+                                Ok(RelayPoolNotification::Event { event, .. }) => {
+                                    if event.verify().is_ok() {
+                                        match ln_address_client.handle_invoice_request(*event, &self_clone).await {
+                                            Ok(Some(response)) => {
+                                                self_clone.nostr.publish_event(response).await;
+                                            }
+                                            Ok(None) => {}
+                                            Err(e) => {
+                                                log_error!(logger, "Error handling invoice request: {e}");
+                                            }
+                                        }
+                                    }
+                                }
+                                Ok(RelayPoolNotification::Shutdown) => break, // reconnect
+                                Ok(_) => {}
+                                Err(_) => break,
+                            }
+                        }
+                        _ = delay_fut => {
+                            if self_clone.stop.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            // stop listening once the address is unregistered
+                            if !ln_address_client.get_address().is_ok_and(|a| a.is_some()) {
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                if let Err(e) = client.disconnect().await {
+                    log_warn!(logger, "Error disconnecting from relays: {e}");
+                }
+            }
+        });
+        self.start_ln_address_websocket(ln_address_client);
+
+        log_trace!(self.logger, "finished calling start_ln_address_listener");
+    }
+
+    /// Keeps a websocket to the LNURL proxy open while an address is registered,
+    /// reconnecting when it drops
+    fn start_ln_address_websocket(&self, ln_address_client: Arc<LnAddressClient<S>>) {
+        let self_clone = self.clone();
+        utils::spawn(async move {
+            let logger = self_clone.logger.clone();
+            loop {
+                if self_clone.stop.load(Ordering::Relaxed) {
+                    break;
+                };
+
+                if !ln_address_client.get_address().is_ok_and(|a| a.is_some()) {
+                    sleep(10_000).await;
+                    continue;
+                }
+
+                let (url, listen) = match ln_address_client.websocket() {
+                    Ok(ws) => ws,
+                    Err(e) => {
+                        log_error!(logger, "Invalid lightning address websocket: {e}");
+                        break;
+                    }
+                };
+                let ws = match WebSocketImpl::new(url.to_string()).await {
+                    Ok(ws) => Some(ws),
+                    Err(e) => {
+                        log_warn!(logger, "Could not open lightning address websocket: {e}");
+                        None
+                    }
+                };
+                let Some(mut ws) = ws else {
+                    // nostr still gets the requests in the meantime
+                    sleep(10_000).await;
+                    continue;
+                };
+                if let Err(e) = ws.send(listen).await.map_err(|e| e.to_string()) {
+                    log_warn!(
+                        logger,
+                        "Could not listen on lightning address websocket: {e}"
+                    );
+                    sleep(10_000).await;
+                    continue;
+                }
+
+                loop {
+                    // the read borrows the websocket, so it's dropped before answering
+                    let message = {
+                        let read_fut = async { ws.recv().await.map_err(|e| e.to_string()) };
+                        let read_fut = read_fut.fuse();
+                        let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
+
+                        pin_mut!(read_fut, delay_fut);
+                        select! {
+                            message = read_fut => Some(message),
+                            _ = delay_fut => None,
+                        }
+                    };
+                    let Some(message) = message else {
+                        if self_clone.stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        // stop listening once the address is unregistered
+                        if !ln_address_client.get_address().is_ok_and(|a| a.is_some()) {
+                            break;
+                        }
+                        continue;
+                    };
+
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(e) => {
+                            log_warn!(logger, "Lightning address websocket closed: {e}");
+                            break; // reconnect
+                        }
+                    };
+                    match ln_address_client
+                        .handle_websocket_message(&message, &self_clone)
+                        .await
+                    {
+                        Ok(Some(response)) => {
+                            if let Err(e) = ws.send(response).await.map_err(|e| e.to_string()) {
+                                log_warn!(logger, "Could not answer invoice request: {e}");
+                                break;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            log_error!(logger, "Error handling invoice request: {e}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
     /// Checks available blind tokens
     /// Only needs to be ran once successfully on startup
     pub fn check_blind_tokens(&self) {
@@ -3594,11 +7782,203 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                         }
                     }
 
-                    sleep(10_000).await;
-                }
-            });
-        }
-        log_trace!(self.logger, "finished calling check_blind_tokens");
+                    sleep(10_000).await;
+                }
+            });
+        }
+        log_trace!(self.logger, "finished calling check_blind_tokens");
+    }
+
+    /// Gets the current block height from our chain source.
+    /// This is cached for a short time so it can be called often.
+    pub async fn get_block_height(&self) -> Result<u32, MutinyError> {
+        log_trace!(self.logger, "calling get_block_height");
+
+        let now = utils::now().as_secs();
+        // don't hold the cache while we wait on esplora
+        let previous_tip = {
+            let cache = self.chain_cache.lock().await;
+            if let Some((height, _, fetched_at)) = cache.tip {
+                if now.saturating_sub(fetched_at) < BLOCK_HEIGHT_CACHE_SEC {
+                    return Ok(height);
+                }
+            }
+            cache.tip
+        };
+
+        let height = self.esplora.get_height().await?;
+        let hash = self.esplora.get_block_hash(height).await?;
+
+        // if the last tip we saw is no longer in the best chain there was a reorg
+        let reorged = match previous_tip {
+            Some((prev_height, prev_hash, _)) if prev_height == height => prev_hash != hash,
+            Some((prev_height, prev_hash, _)) if prev_height < height => {
+                self.esplora.get_block_hash(prev_height).await? != prev_hash
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        let mut cache = self.chain_cache.lock().await;
+        if reorged {
+            log_warn!(
+                self.logger,
+                "Chain reorg detected, clearing confirmed transactions"
+            );
+            cache.confirmed_txs.clear();
+            cache.reorgs += 1;
+        }
+        cache.tip = Some((height, hash, now));
+        drop(cache);
+        log_trace!(self.logger, "finished calling get_block_height");
+
+        Ok(height)
+    }
+
+    /// Gets the status of the given transaction from our chain source.
+    pub async fn get_tx_status(&self, txid: Txid) -> Result<TransactionStatus, MutinyError> {
+        log_trace!(self.logger, "calling get_tx_status");
+
+        let (cached, reorgs) = {
+            let cache = self.chain_cache.lock().await;
+            (cache.confirmed_txs.get(&txid).copied(), cache.reorgs)
+        };
+        let (block_height, block_time) = match cached {
+            Some(status) => (status.block_height, status.block_time),
+            None => {
+                let status = self.esplora.get_tx_status(&txid).await?;
+                (status.block_height, status.block_time)
+            }
+        };
+
+        let res = match block_height {
+            Some(block_height) => {
+                let height = self.get_block_height().await?;
+                let status = TransactionStatus {
+                    confirmed: true,
+                    block_height: Some(block_height),
+                    block_time,
+                    confirmations: height.saturating_sub(block_height) + 1,
+                };
+                let mut cache = self.chain_cache.lock().await;
+                if cache.reorgs == reorgs {
+                    cache.confirmed_txs.insert(txid, status);
+                }
+                status
+            }
+            None => TransactionStatus {
+                confirmed: false,
+                block_height: None,
+                block_time: None,
+                confirmations: 0,
+            },
+        };
+        log_trace!(self.logger, "finished calling get_tx_status");
+
+        Ok(res)
+    }
+
+    /// Waits until the given transaction has at least the given number of confirmations.
+    /// Returns an error if the wallet is stopped or the timeout passes before then.
+    pub async fn wait_for_confirmation(
+        &self,
+        txid: Txid,
+        confirmations: u32,
+        timeout_secs: Option<u64>,
+    ) -> Result<TransactionStatus, MutinyError> {
+        log_trace!(self.logger, "calling wait_for_confirmation");
+
+        let deadline = timeout_secs.map(|t| utils::now().as_secs().saturating_add(t));
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(MutinyError::NotRunning);
+            }
+
+            match self.get_tx_status(txid).await {
+                Ok(status) if status.confirmations >= confirmations => {
+                    log_trace!(self.logger, "finished calling wait_for_confirmation");
+                    return Ok(status);
+                }
+                Ok(_) => {}
+                Err(e) => log_warn!(self.logger, "error checking tx status for {txid}: {e}"),
+            }
+
+            if deadline.is_some_and(|d| utils::now().as_secs() >= d) {
+                return Err(MutinyError::ConfirmationTimeout);
+            }
+
+            sleep(CONFIRMATION_POLL_INTERVAL_MS).await;
+        }
+    }
+
+    /// Reports what the wallet is currently able to do, so embedders can
+    /// gate actions on the real state of the wallet.
+    pub async fn liveness(&self) -> Result<WalletLiveness, MutinyError> {
+        log_trace!(self.logger, "calling liveness");
+
+        let has_federation = !self.federations.read().await.is_empty();
+        let federation_balance = if has_federation {
+            self.get_total_federation_balance().await?
+        } else {
+            0
+        };
+
+        let channels = self.node_manager.list_channels().await?;
+        let usable = channels.iter().filter(|c| c.is_usable);
+        let (inbound, outbound) = usable.fold((0, 0), |(i, o), c| (i + c.inbound, o + c.balance));
+
+        let state = LivenessState {
+            running: !self.stop.load(Ordering::Relaxed),
+            initial_sync: self
+                .node_manager
+                .has_done_initial_ldk_sync
+                .load(Ordering::Relaxed),
+            chain_reachable: self.get_block_height().await.is_ok(),
+            lsp_connected: self.node_manager.is_lsp_connected().await,
+            has_federation,
+            federation_balance,
+            inbound,
+            outbound,
+            onchain_balance: self.node_manager.get_wallet_balance()?,
+        };
+        log_trace!(self.logger, "finished calling liveness");
+
+        Ok(state.into())
+    }
+
+    /// Shows how long startup took and the slowest recent syncs, payments and channel opens,
+    /// to find out why the wallet is slow on some devices. Timings are kept in memory only.
+    pub fn get_performance_report(&self) -> PerformanceReport {
+        self.node_manager.performance.report()
+    }
+
+    /// Aggregates the timing breakdowns of past payments by stage, to see whether slow
+    /// payments are spent connecting to peers, finding routes, or waiting to settle.
+    pub fn get_latency_stats(&self) -> Result<LatencyStats, MutinyError> {
+        log_trace!(self.logger, "calling get_latency_stats");
+
+        let latencies = get_payment_latencies(&self.storage)?;
+        let res = LatencyStats::from_latencies(&latencies);
+        log_trace!(self.logger, "finished calling get_latency_stats");
+
+        Ok(res)
+    }
+
+    /// Returns true once the wallet is running and has completed its initial sync
+    /// with a reachable chain source. See [MutinyWallet::liveness] for what the
+    /// wallet can currently do.
+    pub async fn is_ready(&self) -> bool {
+        log_trace!(self.logger, "calling is_ready");
+
+        let res = !self.stop.load(Ordering::Relaxed)
+            && self
+                .node_manager
+                .has_done_initial_ldk_sync
+                .load(Ordering::Relaxed)
+            && self.get_block_height().await.is_ok();
+        log_trace!(self.logger, "finished calling is_ready");
+
+        res
     }
 
     /// Gets the current bitcoin price in USD.
@@ -3762,10 +8142,24 @@ impl<S: MutinyStorage> InvoiceHandler for MutinyWallet<S> {
         amount: u64,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
-        self.create_lightning_invoice(amount, labels).await
+        self.create_lightning_invoice(amount, labels, &InvoiceParams::default())
+            .await
+    }
+
+    async fn create_invoice_with_params(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+        params: InvoiceParams,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        params.validate()?;
+        self.create_lightning_invoice(amount, labels, &params).await
     }
 }
 
+/// Starts the clients of all the federations concurrently. A federation that fails to start
+/// is left out so it doesn't block the wallet, see [FederationInitStatus].
+#[allow(clippy::type_complexity)]
 async fn create_federations<S: MutinyStorage>(
     federation_storage: FederationStorage,
     c: &MutinyWalletConfig,
@@ -3774,28 +8168,62 @@ async fn create_federations<S: MutinyStorage>(
     stop: Arc<AtomicBool>,
     logger: &Arc<MutinyLogger>,
     safe_mode: bool,
-) -> Result<Arc<RwLock<HashMap<FederationId, Arc<FederationClient<S>>>>>, MutinyError> {
-    let mut federation_map = HashMap::with_capacity(federation_storage.federations.len());
-    for (uuid, federation_index) in federation_storage.federations {
-        let federation = FederationClient::new(
+) -> (
+    Arc<RwLock<HashMap<FederationId, Arc<FederationClient<S>>>>>,
+    Vec<FederationInitStatus>,
+) {
+    let futures = federation_storage
+        .federations
+        .into_iter()
+        .map(|(uuid, federation_index)| {
+            let storage = storage.clone();
+            let esplora = esplora.clone();
+            let stop = stop.clone();
+            async move {
+                let start = Instant::now();
+                let federation_id = federation_index.federation_code.federation_id();
+                let res = FederationClient::new(
+                    uuid.clone(),
+                    federation_index.federation_code,
+                    c.xprivkey,
+                    storage,
+                    esplora,
+                    c.network,
+                    stop,
+                    logger.clone(),
+                    safe_mode,
+                )
+                .await;
+                let duration_ms = start.elapsed().as_millis() as u64;
+                (uuid, federation_id, res, duration_ms)
+            }
+        });
+    let results = futures::future::join_all(futures).await;
+
+    let mut federation_map = HashMap::with_capacity(results.len());
+    let mut statuses = Vec::with_capacity(results.len());
+    for (uuid, federation_id, res, duration_ms) in results {
+        let error = match res {
+            Ok(federation) => {
+                let id = federation.fedimint_client.federation_id();
+                federation_map.insert(id, Arc::new(federation));
+                None
+            }
+            Err(e) => {
+                log_error!(logger, "Failed to start federation {federation_id}: {e}");
+                Some(e.to_string())
+            }
+        };
+        statuses.push(FederationInitStatus {
             uuid,
-            federation_index.federation_code,
-            c.xprivkey,
-            storage.clone(),
-            esplora.clone(),
-            c.network,
-            stop.clone(),
-            logger.clone(),
-            safe_mode,
-        )
-        .await?;
-
-        let id = federation.fedimint_client.federation_id();
-
-        federation_map.insert(id, Arc::new(federation));
+            federation_id,
+            error,
+            duration_ms,
+        });
     }
+
     let federations = Arc::new(RwLock::new(federation_map));
-    Ok(federations)
+    (federations, statuses)
 }
 
 // This will create a new federation and returns the Federation ID of the client created.
@@ -4057,6 +8485,117 @@ mod tests {
     fn test_max_routing_fee_amount() {
         max_routing_fee_amount();
     }
+
+    #[test]
+    fn test_contact_stats() {
+        let sent = MutinyInvoice {
+            amount_sats: Some(1_000),
+            fees_paid: Some(5),
+            status: HTLCStatus::Succeeded,
+            last_updated: 100,
+            ..Default::default()
+        };
+        let received = MutinyInvoice {
+            amount_sats: Some(2_000),
+            inbound: true,
+            status: HTLCStatus::Succeeded,
+            last_updated: 200,
+            ..Default::default()
+        };
+        let pending = MutinyInvoice {
+            amount_sats: Some(3_000),
+            status: HTLCStatus::InFlight,
+            last_updated: 300,
+            ..Default::default()
+        };
+        let onchain = TransactionDetails {
+            transaction: None,
+            txid: None,
+            internal_id: Txid::all_zeros(),
+            received: 1_000,
+            sent: 11_100,
+            fee: Some(100),
+            confirmation_time: ConfirmationTime::Confirmed {
+                height: 1,
+                time: 400,
+            },
+            labels: vec![],
+            mempool_status: None,
+        };
+
+        let activity = vec![
+            ActivityItem::Lightning(Box::new(sent)),
+            ActivityItem::Lightning(Box::new(received)),
+            ActivityItem::Lightning(Box::new(pending)),
+            ActivityItem::OnChain(onchain),
+        ];
+
+        let stats = ContactStats::from_activity(&activity, None);
+        assert_eq!(stats.total_sent, 11_000);
+        assert_eq!(stats.total_received, 2_000);
+        assert_eq!(stats.total_fees, 105);
+        assert_eq!(stats.num_sent, 2);
+        assert_eq!(stats.num_received, 1);
+        assert_eq!(stats.last_payment, Some(400));
+
+        let stats = ContactStats::from_activity(&activity, Some(150));
+        assert_eq!(stats.total_sent, 10_000);
+        assert_eq!(stats.total_received, 2_000);
+        assert_eq!(stats.total_fees, 100);
+        assert_eq!(stats.num_sent, 1);
+        assert_eq!(stats.num_received, 1);
+    }
+
+    #[test]
+    fn test_federation_activity() {
+        let federation_id = FederationId::dummy();
+        let invoice = MutinyInvoice {
+            amount_sats: Some(1_000),
+            fees_paid: Some(3),
+            status: HTLCStatus::Succeeded,
+            last_updated: 100,
+            labels: vec!["test".to_string()],
+            ..Default::default()
+        };
+        let peg_out = TransactionDetails {
+            transaction: None,
+            txid: None,
+            internal_id: Txid::all_zeros(),
+            received: 0,
+            sent: 10_000,
+            fee: Some(200),
+            confirmation_time: ConfirmationTime::Unconfirmed { last_seen: 200 },
+            labels: vec![],
+            mempool_status: None,
+        };
+
+        let lightning = ActivityItem::Federation(FederationActivity::Lightning {
+            federation_id,
+            invoice: Box::new(invoice),
+        });
+        let peg_out = ActivityItem::Federation(FederationActivity::PegOut {
+            federation_id,
+            transaction: peg_out,
+        });
+
+        assert_eq!(lightning.last_updated(), Some(100));
+        assert_eq!(lightning.labels(), vec!["test".to_string()]);
+        assert!(!lightning.is_channel_open());
+        assert_eq!(peg_out.last_updated(), None);
+
+        let ActivityItem::Federation(ref f) = peg_out else {
+            panic!("expected federation activity");
+        };
+        assert_eq!(f.federation_id(), federation_id);
+        assert_eq!(f.kind(), FederationActivityKind::PegOut);
+        assert_eq!(f.fees(), Some(200));
+
+        // pending peg-outs are not counted yet
+        let stats = ContactStats::from_activity(&[lightning, peg_out], None);
+        assert_eq!(stats.total_sent, 1_000);
+        assert_eq!(stats.total_fees, 3);
+        assert_eq!(stats.num_sent, 1);
+    }
 }
 
 #[cfg(test)]
@@ -4067,12 +8606,14 @@ mod tests {
         PAYMENT_OUTBOUND_PREFIX_KEY,
     };
     use crate::{
-        encrypt::encryption_key_from_pass, generate_seed, max_routing_fee_amount,
-        nodemanager::NodeManager, MutinyWallet, MutinyWalletBuilder, MutinyWalletConfigBuilder,
+        encrypt::encryption_key_from_pass, error::MutinyError, generate_seed,
+        max_routing_fee_amount, nodemanager::NodeManager, xprivkey_from_mnemonic, MutinyWallet,
+        MutinyWalletBuilder, MutinyWalletConfigBuilder,
     };
     use crate::{
         event::{HTLCStatus, MillisatAmount, PaymentInfo},
-        TransactionDetails,
+        ActivityFilter, ActivityKind, InvoiceParams, LivenessState, PowerMode, TransactionDetails,
+        WalletLiveness,
     };
     use crate::{ldkstorage::CHANNEL_CLOSURE_PREFIX, storage::persist_transaction_details};
     use crate::{nodemanager::ChannelClosure, storage::TRANSACTION_DETAILS_PREFIX_KEY};
@@ -4091,8 +8632,12 @@ mod tests {
 
     use crate::labels::{Contact, LabelStorage};
     use crate::nostr::NostrKeySource;
+    use crate::readiness::{Subsystem, SubsystemStatus};
     use crate::utils::{now, parse_npub, sleep};
+    use crate::{logging::MutinyLogger, vss::MutinyVssClient};
     use nostr::{Keys, Metadata};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -4122,6 +8667,150 @@ mod tests {
         assert!(NodeManager::has_node_manager(storage));
     }
 
+    #[test]
+    async fn create_mutiny_wallet_with_fast_start() {
+        let test_name = "create_mutiny_wallet_with_fast_start";
+        log!("{}", test_name);
+
+        let mnemonic = generate_seed(12).unwrap();
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
+
+        let storage = MemoryStorage::default();
+        let mut config_builder = MutinyWalletConfigBuilder::new(xpriv).with_network(network);
+        config_builder.with_fast_start();
+        let mw = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config_builder.build())
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+
+        mw.ready().await;
+        for subsystem in [Subsystem::Nodes, Subsystem::Federations, Subsystem::Nostr] {
+            assert_eq!(mw.get_subsystem_status(subsystem), SubsystemStatus::Ready);
+        }
+        assert_eq!(mw.take_readiness_events().len(), 3);
+        assert_eq!(mw.node_manager.list_nodes().await.unwrap().len(), 1);
+    }
+
+    #[test]
+    async fn create_mutiny_wallet_with_passphrase() {
+        let test_name = "create_mutiny_wallet_with_passphrase";
+        log!("{}", test_name);
+
+        let mnemonic = generate_seed(12).unwrap();
+        let network = Network::Regtest;
+        let storage = MemoryStorage::default();
+        let passphrase = Some("hidden");
+
+        let mw =
+            MutinyWalletBuilder::from_mnemonic(&mnemonic, passphrase, network, storage.clone())
+                .unwrap()
+                .build()
+                .await
+                .expect("mutiny wallet should initialize");
+        let xpriv = xprivkey_from_mnemonic(&mnemonic, passphrase, network).unwrap();
+        assert_eq!(mw.node_manager.xprivkey, xpriv);
+        mw.stop().await.unwrap();
+        drop(mw);
+
+        // can't open the wallet with another passphrase
+        let res = MutinyWalletBuilder::from_mnemonic(&mnemonic, None, network, storage)
+            .unwrap()
+            .build()
+            .await;
+        assert!(matches!(res, Err(MutinyError::IncorrectPassphrase)));
+    }
+
+    #[test]
+    async fn create_mutiny_wallet_read_only() {
+        let test_name = "create_mutiny_wallet_read_only";
+        log!("{}", test_name);
+
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &[0; 32]).unwrap();
+        let storage = MemoryStorage::default();
+
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let mw = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config)
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+        let address = mw.node_manager.get_new_address(vec![]).unwrap();
+        mw.stop().await.unwrap();
+        drop(mw);
+
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let mut builder = MutinyWalletBuilder::new(xpriv, storage.clone()).with_config(config);
+        builder.with_read_only();
+        let mw = builder.build().await.expect("read-only wallet should open");
+        assert!(mw.is_read_only());
+        assert!(mw.is_safe_mode());
+
+        // can view balances but not change anything
+        assert!(mw.get_balance().await.is_ok());
+        assert_eq!(
+            storage.set_data("key".to_string(), 1, None),
+            Err(MutinyError::ReadOnly)
+        );
+        let res = mw
+            .node_manager
+            .send_to_address(address, 10_000, vec![], None)
+            .await;
+        assert_eq!(res, Err(MutinyError::ReadOnly));
+    }
+
+    #[test]
+    async fn create_mutiny_wallet_with_account_index() {
+        let test_name = "create_mutiny_wallet_with_account_index";
+        log!("{}", test_name);
+
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &[0; 32]).unwrap();
+        let storage = MemoryStorage::default();
+
+        let mut config_builder = MutinyWalletConfigBuilder::new(xpriv).with_network(network);
+        config_builder.with_account_index(1);
+        let mw = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config_builder.build())
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+        assert_eq!(mw.node_manager.wallet.account_index, 1);
+        mw.stop().await.unwrap();
+        drop(mw);
+
+        // the storage belongs to the first account it was used with
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let res = MutinyWalletBuilder::new(xpriv, storage)
+            .with_config(config)
+            .build()
+            .await;
+        assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+
+        // other accounts would share the VSS store of the first one
+        let vss = MutinyVssClient::new_unauthenticated(
+            "http://localhost:8080".to_string(),
+            xpriv.private_key,
+            Arc::new(MutinyLogger::default()),
+        );
+        let storage = MemoryStorage::new(None, None, Some(Arc::new(vss)));
+        let mut config_builder = MutinyWalletConfigBuilder::new(xpriv).with_network(network);
+        config_builder.with_account_index(1);
+        let res = MutinyWalletBuilder::new(xpriv, storage)
+            .with_config(config_builder.build())
+            .build()
+            .await;
+        assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+    }
+
     #[test]
     async fn restart_mutiny_wallet() {
         let test_name = "restart_mutiny_wallet";
@@ -4149,6 +8838,51 @@ mod tests {
         assert_eq!(first_seed, mw.node_manager.xprivkey);
     }
 
+    #[test]
+    async fn power_mode_survives_restart() {
+        let test_name = "power_mode_survives_restart";
+        log!("{}", test_name);
+
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &[0; 32]).unwrap();
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let mut mw = MutinyWalletBuilder::new(xpriv, MemoryStorage::default())
+            .with_config(config)
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+        assert_eq!(mw.get_power_mode(), PowerMode::Foreground);
+        assert!(!mw.node_manager.background.load(Ordering::Relaxed));
+
+        mw.set_power_mode(PowerMode::Background);
+        assert_eq!(mw.get_power_mode(), PowerMode::Background);
+        assert!(mw.node_manager.background.load(Ordering::Relaxed));
+
+        // the new node manager picks up the mode we were in
+        assert!(mw.stop().await.is_ok());
+        assert!(mw.start().await.is_ok());
+        assert!(mw.node_manager.background.load(Ordering::Relaxed));
+
+        mw.set_power_mode(PowerMode::Foreground);
+        assert_eq!(mw.get_power_mode(), PowerMode::Foreground);
+        assert!(!mw.node_manager.background.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_power_mode_from_str() {
+        assert_eq!(
+            PowerMode::from_str("foreground").unwrap(),
+            PowerMode::Foreground
+        );
+        assert_eq!(
+            PowerMode::from_str("background").unwrap(),
+            PowerMode::Background
+        );
+        assert!(PowerMode::from_str("Background").is_err());
+    }
+
     #[test]
     async fn restart_mutiny_wallet_with_nodes() {
         let test_name = "restart_mutiny_wallet_with_nodes";
@@ -4437,11 +9171,179 @@ mod tests {
         assert!(profile.name.is_some());
     }
 
+    #[test]
+    async fn test_amountless_invoice_safeguards() {
+        let mnemonic = generate_seed(12).unwrap();
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
+        let storage = MemoryStorage::new(None, None, None);
+        let mut config_builder = MutinyWalletConfigBuilder::new(xpriv).with_network(network);
+        config_builder.with_max_amountless_invoice_sats(10_000);
+        let mw = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config_builder.build())
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+
+        let contact = Contact {
+            name: "Satoshi".to_string(),
+            ..Default::default()
+        };
+        let contact_id = storage.create_new_contact(contact).unwrap();
+        let labels = vec![contact_id];
+        let confirmed = PayInvoiceOptions {
+            confirm_amountless: true,
+            ..Default::default()
+        };
+
+        // need an amount and confirmation
+        assert_eq!(
+            mw.check_amountless_invoice(None, &labels, &confirmed),
+            Err(MutinyError::InvoiceInvalid)
+        );
+        assert_eq!(
+            mw.check_amountless_invoice(Some(0), &labels, &confirmed),
+            Err(MutinyError::BadAmountError)
+        );
+        assert_eq!(
+            mw.check_amountless_invoice(Some(1_000), &labels, &PayInvoiceOptions::default()),
+            Err(MutinyError::AmountlessInvoiceNotConfirmed)
+        );
+
+        // configured and per-call maximums
+        assert_eq!(
+            mw.check_amountless_invoice(Some(10_001), &labels, &confirmed),
+            Err(MutinyError::AmountlessInvoiceTooLarge)
+        );
+        let capped = PayInvoiceOptions {
+            max_amountless_sats: Some(500),
+            ..confirmed.clone()
+        };
+        assert_eq!(
+            mw.check_amountless_invoice(Some(1_000), &labels, &capped),
+            Err(MutinyError::AmountlessInvoiceTooLarge)
+        );
+
+        // only contacts by default
+        assert_eq!(
+            mw.check_amountless_invoice(Some(1_000), &["coffee".to_string()], &confirmed),
+            Err(MutinyError::AmountlessInvoiceNotAllowed)
+        );
+        assert!(mw
+            .check_amountless_invoice(Some(1_000), &labels, &confirmed)
+            .is_ok());
+    }
+
     #[test]
     fn test_max_routing_fee_amount() {
         max_routing_fee_amount();
     }
 
+    #[test]
+    fn test_wallet_liveness() {
+        let state = LivenessState {
+            running: true,
+            initial_sync: true,
+            chain_reachable: true,
+            ..Default::default()
+        };
+        let liveness = WalletLiveness::from(state);
+        assert!(liveness.running && liveness.can_sync && liveness.chain_reachable);
+        assert!(!liveness.can_receive);
+        assert!(!liveness.can_send);
+
+        // on-chain funds can only be sent once we are synced
+        let liveness = WalletLiveness::from(LivenessState {
+            onchain_balance: 1_000,
+            ..state
+        });
+        assert!(liveness.can_send);
+        let liveness = WalletLiveness::from(LivenessState {
+            onchain_balance: 1_000,
+            chain_reachable: false,
+            ..state
+        });
+        assert!(!liveness.can_sync);
+        assert!(!liveness.can_send);
+
+        // lightning and federations work without the chain source
+        let liveness = WalletLiveness::from(LivenessState {
+            chain_reachable: false,
+            outbound: 1_000,
+            lsp_connected: true,
+            ..state
+        });
+        assert!(liveness.can_send && liveness.can_receive && liveness.lsp_connected);
+        let liveness = WalletLiveness::from(LivenessState {
+            has_federation: true,
+            federation_balance: 1_000,
+            ..Default::default()
+        });
+        assert!(!liveness.can_send && !liveness.can_receive);
+        let liveness = WalletLiveness::from(LivenessState {
+            running: true,
+            has_federation: true,
+            ..Default::default()
+        });
+        assert!(liveness.can_receive && !liveness.can_send);
+        let liveness = WalletLiveness::from(LivenessState {
+            inbound: 1_000,
+            ..state
+        });
+        assert!(liveness.can_receive);
+    }
+
+    #[test]
+    async fn stopped_wallet_is_not_ready() {
+        let test_name = "stopped_wallet_is_not_ready";
+        log!("{}", test_name);
+
+        let mnemonic = generate_seed(12).unwrap();
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let mw = MutinyWalletBuilder::new(xpriv, MemoryStorage::default())
+            .with_config(config)
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+        // nothing to receive with or send yet
+        let liveness = mw.liveness().await.unwrap();
+        assert!(liveness.running);
+        assert!(!liveness.can_receive);
+        assert!(!liveness.can_send);
+
+        mw.stop().await.unwrap();
+        assert!(!mw.is_ready().await);
+    }
+
+    #[test]
+    fn test_invoice_params_validate() {
+        assert!(InvoiceParams::default().validate().is_ok());
+
+        let params = InvoiceParams {
+            description: Some("coffee".to_string()),
+            description_hash: Some(bitcoin::hashes::sha256::Hash::hash(b"coffee")),
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+
+        let params = InvoiceParams {
+            expiry_secs: Some(0),
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+
+        let params = InvoiceParams {
+            min_final_cltv: Some(1),
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+        assert!(!params.supported_by_federation());
+    }
+
     #[test]
     async fn test_sort_index_item() {
         let storage = MemoryStorage::new(None, None, None);
@@ -4543,6 +9445,7 @@ mod tests {
             secret: None,
             fee_paid_msat: None,
             privacy_level: Default::default(),
+            custom_tlvs: vec![],
         };
         persist_payment_info(&storage, &payment_hash1, &invoice1, false).unwrap();
 
@@ -4559,6 +9462,7 @@ mod tests {
             status: HTLCStatus::Succeeded,
             fee_paid_msat: None,
             privacy_level: Default::default(),
+            custom_tlvs: vec![],
         };
         persist_payment_info(&storage, &payment_hash2, &invoice2, false).unwrap();
 
@@ -4575,6 +9479,7 @@ mod tests {
             secret: None,
             fee_paid_msat: None,
             privacy_level: Default::default(),
+            custom_tlvs: vec![],
         };
         persist_payment_info(&storage, &payment_hash3, &invoice3, false).unwrap();
 
@@ -4591,6 +9496,7 @@ mod tests {
             last_update: 1581781585,
             secret: None,
             privacy_level: Default::default(),
+            custom_tlvs: vec![],
         };
         persist_payment_info(&storage, &payment_hash4, &invoice4, false).unwrap();
 
@@ -4605,6 +9511,7 @@ mod tests {
                 last_seen: now().as_secs(),
             },
             labels: vec![],
+            mempool_status: None,
         };
         persist_transaction_details(&storage, &transaction_details1).unwrap();
 
@@ -4694,6 +9601,40 @@ mod tests {
         let with_both_oob = mw.get_activity(Some(usize::MAX), Some(usize::MAX)).unwrap();
         assert!(with_both_oob.is_empty());
 
+        // the first page has all the pending activity
+        let page = mw
+            .get_activity_page(2, None, ActivityFilter::default())
+            .unwrap();
+        assert_eq!(page.items, activity[..4]);
+        assert_eq!(page.next_until_ts, Some(u64::MAX));
+        let page = mw
+            .get_activity_page(2, page.next_until_ts, ActivityFilter::default())
+            .unwrap();
+        assert_eq!(page.items, activity[4..6]);
+        assert_eq!(page.next_until_ts, Some(closure.timestamp));
+        let page = mw
+            .get_activity_page(2, page.next_until_ts, ActivityFilter::default())
+            .unwrap();
+        assert_eq!(page.items, activity[6..]);
+        assert_eq!(page.next_until_ts, None);
+
+        let filter = ActivityFilter {
+            kind: Some(ActivityKind::ChannelClosed),
+            ..Default::default()
+        };
+        let page = mw.get_activity_page(10, None, filter).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].kind(), ActivityKind::ChannelClosed);
+        let filter = ActivityFilter {
+            label: Some("nothing".to_string()),
+            ..Default::default()
+        };
+        assert!(mw
+            .get_activity_page(10, None, filter)
+            .unwrap()
+            .items
+            .is_empty());
+
         // update an inflight payment and make sure it isn't duplicated
         invoice4.last_update = now().as_secs();
         invoice4.status = HTLCStatus::Succeeded;
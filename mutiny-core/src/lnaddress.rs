@@ -0,0 +1,603 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bitcoin::bip32::ExtendedPrivKey;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::Secp256k1;
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_info, log_warn};
+use nostr::nips::nip04::{decrypt, encrypt};
+use nostr::{Event, EventBuilder, Filter, JsonUtil, Keys, Kind, Tag, Timestamp};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    error::MutinyError,
+    logging::MutinyLogger,
+    nostr::{derive_nostr_key, LN_ADDRESS_CHAIN_INDEX, SERVICE_ACCOUNT_INDEX},
+    receivelimits::{check_invoice_request, record_invoice_created},
+    storage::MutinyStorage,
+    utils, InvoiceHandler, InvoiceParams,
+};
+
+const LN_ADDRESS_KEY: &str = "ln_address";
+const LN_ADDRESS_PAYMENT_PREFIX_KEY: &str = "ln_address_payment/";
+
+const REGISTER_EVENT_KIND: Kind = Kind::Custom(93_190);
+const UNREGISTER_EVENT_KIND: Kind = Kind::Custom(93_191);
+/// Sent when opening the websocket, so the proxy knows whose requests to forward
+const LISTEN_EVENT_KIND: Kind = Kind::Custom(93_192);
+
+/// Invoice requests older than this are skipped, the payer has given up on them by now
+const INVOICE_REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Longest comment we accept with a payment, see LUD-12
+const COMMENT_ALLOWED: usize = 255;
+const MIN_SENDABLE_MSAT: u64 = 1_000;
+const MAX_SENDABLE_MSAT: u64 = 1_000_000_000;
+
+/// A lightning address this wallet receives payments for through an LNURL proxy
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnAddress {
+    /// The full address, `name@domain`
+    pub address: String,
+    /// The LNURL-pay metadata the proxy serves, our invoices commit to its hash
+    pub metadata: String,
+    /// Key the proxy sends us invoice requests from
+    pub proxy_pubkey: nostr::PublicKey,
+    pub min_sendable_msat: u64,
+    pub max_sendable_msat: u64,
+    /// Unix timestamp of when the address was registered
+    pub registered_at: u64,
+}
+
+/// A payment someone requested an invoice for through our lightning address
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnAddressPayment {
+    pub address: String,
+    pub payment_hash: String,
+    pub amount_sats: u64,
+    /// Comment from the payer, see LUD-12
+    pub comment: Option<String>,
+    /// Unix timestamp of when the invoice was requested
+    pub requested_at: u64,
+    /// Whether the invoice has been paid, filled in when listing
+    #[serde(default)]
+    pub paid: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegisterLnAddressRequest {
+    name: String,
+    min_sendable: u64,
+    max_sendable: u64,
+    comment_allowed: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegisterLnAddressResponse {
+    address: String,
+    metadata: String,
+    pubkey: nostr::PublicKey,
+}
+
+/// An invoice request the proxy forwards to us when someone pays the address
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct InvoiceRequest {
+    /// Id the proxy matches our response with
+    pub id: String,
+    pub amount_msat: u64,
+    pub comment: Option<String>,
+}
+
+/// Our answer to an [InvoiceRequest], the proxy passes it on to the payer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct InvoiceResponse {
+    pub id: String,
+    /// The invoice, named like in the LNURL-pay callback response
+    pub pr: Option<String>,
+    /// Why we couldn't create an invoice
+    pub reason: Option<String>,
+}
+
+impl InvoiceResponse {
+    fn error(id: String, reason: &str) -> Self {
+        Self {
+            id,
+            pr: None,
+            reason: Some(reason.to_string()),
+        }
+    }
+}
+
+/// Lets the wallet be the backend of a lightning address.
+///
+/// The address is registered with an LNURL proxy that serves the LNURL-pay endpoints
+/// and forwards invoice requests to us as encrypted nostr DMs, or over a websocket
+/// while we have one open. A request is only answered once however it reaches us.
+pub struct LnAddressClient<S: MutinyStorage> {
+    keys: Keys,
+    http_client: reqwest::Client,
+    proxy_url: String,
+    storage: S,
+    /// Ids of the requests we answered recently and when
+    handled_requests: Mutex<HashMap<String, u64>>,
+    pub logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> LnAddressClient<S> {
+    pub fn new(
+        xprivkey: ExtendedPrivKey,
+        proxy_url: String,
+        storage: &S,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<Self, MutinyError> {
+        let keys = derive_nostr_key(
+            &Secp256k1::new(),
+            xprivkey,
+            SERVICE_ACCOUNT_INDEX,
+            Some(LN_ADDRESS_CHAIN_INDEX),
+            None,
+        )?;
+
+        Ok(Self {
+            keys,
+            http_client: reqwest::Client::new(),
+            proxy_url,
+            storage: storage.clone(),
+            handled_requests: Mutex::new(HashMap::new()),
+            logger,
+        })
+    }
+
+    pub fn public_key(&self) -> nostr::PublicKey {
+        self.keys.public_key()
+    }
+
+    pub fn get_address(&self) -> Result<Option<LnAddress>, MutinyError> {
+        self.storage.get_data(LN_ADDRESS_KEY)
+    }
+
+    /// Registers the name with the proxy, replacing the address we had
+    pub async fn register(&self, name: String) -> Result<LnAddress, MutinyError> {
+        let req = RegisterLnAddressRequest {
+            name,
+            min_sendable: MIN_SENDABLE_MSAT,
+            max_sendable: MAX_SENDABLE_MSAT,
+            comment_allowed: COMMENT_ALLOWED,
+        };
+        let event = EventBuilder::new(REGISTER_EVENT_KIND, serde_json::to_string(&req)?, [])
+            .to_event(&self.keys)?;
+
+        let url = Url::parse(&format!("{}/v1/register", self.proxy_url))
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+        let request = self.http_client.request(Method::POST, url).json(&event);
+        let res = utils::fetch_with_timeout(
+            &self.http_client,
+            request.build().map_err(|_| MutinyError::ConnectionFailed)?,
+        )
+        .await?
+        .json::<RegisterLnAddressResponse>()
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+        let address = LnAddress {
+            address: res.address,
+            metadata: res.metadata,
+            proxy_pubkey: res.pubkey,
+            min_sendable_msat: MIN_SENDABLE_MSAT,
+            max_sendable_msat: MAX_SENDABLE_MSAT,
+            registered_at: utils::now().as_secs(),
+        };
+        self.storage
+            .set_data(LN_ADDRESS_KEY.to_string(), &address, None)?;
+        log_info!(
+            self.logger,
+            "registered lightning address: {}",
+            address.address
+        );
+
+        Ok(address)
+    }
+
+    /// Tells the proxy to stop serving our address and forgets it
+    pub async fn unregister(&self) -> Result<(), MutinyError> {
+        let Some(address) = self.get_address()? else {
+            return Ok(());
+        };
+
+        let event = EventBuilder::new(UNREGISTER_EVENT_KIND, "", []).to_event(&self.keys)?;
+        let url = Url::parse(&format!("{}/v1/unregister", self.proxy_url))
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+        let request = self.http_client.request(Method::POST, url).json(&event);
+        let _ = utils::fetch_with_timeout(
+            &self.http_client,
+            request.build().map_err(|_| MutinyError::ConnectionFailed)?,
+        )
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+        self.storage.delete(&[LN_ADDRESS_KEY])?;
+        log_info!(
+            self.logger,
+            "unregistered lightning address: {}",
+            address.address
+        );
+
+        Ok(())
+    }
+
+    /// Filter for the invoice requests that haven't timed out yet
+    pub(crate) fn filter(&self) -> Filter {
+        let since = utils::now().as_secs() - INVOICE_REQUEST_TIMEOUT_SECS;
+        Filter::new()
+            .kind(Kind::EncryptedDirectMessage)
+            .pubkey(self.public_key())
+            .since(Timestamp::from(since))
+    }
+
+    /// Answers an invoice request from the proxy, returns the response event to send
+    pub(crate) async fn handle_invoice_request(
+        &self,
+        event: Event,
+        invoice_handler: &impl InvoiceHandler,
+    ) -> Result<Option<Event>, MutinyError> {
+        let Some(address) = self.get_address()? else {
+            return Ok(None);
+        };
+        if event.pubkey != address.proxy_pubkey {
+            log_warn!(self.logger, "Ignoring DM from unknown key {}", event.pubkey);
+            return Ok(None);
+        }
+        let now = utils::now().as_secs();
+        if event.created_at.as_u64() + INVOICE_REQUEST_TIMEOUT_SECS < now {
+            log_debug!(self.logger, "Skipping expired invoice request {}", event.id);
+            return Ok(None);
+        }
+
+        let secret_key = self
+            .keys
+            .secret_key()
+            .expect("derived keys have a secret key");
+        let decrypted = decrypt(secret_key, &event.pubkey, &event.content)?;
+        let request: InvoiceRequest = serde_json::from_str(&decrypted)?;
+        if !self.first_time_handling(&request.id, now) {
+            log_debug!(
+                self.logger,
+                "Skipping answered invoice request {}",
+                request.id
+            );
+            return Ok(None);
+        }
+
+        let response = self
+            .create_invoice_for_request(&address, request, invoice_handler)
+            .await;
+
+        let encrypted = encrypt(secret_key, &event.pubkey, serde_json::to_string(&response)?)?;
+        let p_tag = Tag::public_key(event.pubkey);
+        let e_tag = Tag::event(event.id);
+        let response = EventBuilder::new(Kind::EncryptedDirectMessage, encrypted, [p_tag, e_tag])
+            .to_event(&self.keys)?;
+
+        Ok(Some(response))
+    }
+
+    /// The websocket the proxy forwards invoice requests over, along with the
+    /// message to open it with
+    pub(crate) fn websocket(&self) -> Result<(Url, String), MutinyError> {
+        let mut url = Url::parse(&format!("{}/v1/listen", self.proxy_url))
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+        let ws_scheme = match url.scheme() {
+            "http" => "ws",
+            "https" => "wss",
+            _ => return Err(MutinyError::ConnectionFailed),
+        };
+        url.set_scheme(ws_scheme)
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+
+        let event = EventBuilder::new(LISTEN_EVENT_KIND, "", []).to_event(&self.keys)?;
+        Ok((url, event.as_json()))
+    }
+
+    /// Answers an invoice request the proxy sent over the websocket,
+    /// returns the response to send back
+    pub(crate) async fn handle_websocket_message(
+        &self,
+        message: &str,
+        invoice_handler: &impl InvoiceHandler,
+    ) -> Result<Option<String>, MutinyError> {
+        let Some(address) = self.get_address()? else {
+            return Ok(None);
+        };
+        let request: InvoiceRequest = serde_json::from_str(message)?;
+        if !self.first_time_handling(&request.id, utils::now().as_secs()) {
+            log_debug!(
+                self.logger,
+                "Skipping answered invoice request {}",
+                request.id
+            );
+            return Ok(None);
+        }
+
+        let response = self
+            .create_invoice_for_request(&address, request, invoice_handler)
+            .await;
+        Ok(Some(serde_json::to_string(&response)?))
+    }
+
+    /// Marks the request as handled, false if it already was. The proxy can send
+    /// a request over both transports and relays send events again after reconnecting,
+    /// ids are kept until requests that old are skipped anyway.
+    fn first_time_handling(&self, id: &str, now: u64) -> bool {
+        let mut handled = self
+            .handled_requests
+            .lock()
+            .expect("handled requests poisoned");
+        handled.retain(|_, at| *at + INVOICE_REQUEST_TIMEOUT_SECS >= now);
+        handled.insert(id.to_string(), now).is_none()
+    }
+
+    async fn create_invoice_for_request(
+        &self,
+        address: &LnAddress,
+        request: InvoiceRequest,
+        invoice_handler: &impl InvoiceHandler,
+    ) -> InvoiceResponse {
+        if request.amount_msat < address.min_sendable_msat
+            || request.amount_msat > address.max_sendable_msat
+        {
+            return InvoiceResponse::error(request.id, "Amount out of range");
+        }
+        if request.amount_msat % 1_000 != 0 {
+            return InvoiceResponse::error(request.id, "Amount must be whole sats");
+        }
+        if request
+            .comment
+            .as_ref()
+            .is_some_and(|c| c.len() > COMMENT_ALLOWED)
+        {
+            return InvoiceResponse::error(request.id, "Comment too long");
+        }
+
+        let amount_sats = request.amount_msat / 1_000;
+        // the payer is behind the proxy, so only the amount and rate limits apply
+        if let Err(e) = check_invoice_request(&self.storage, amount_sats, None) {
+            log_warn!(
+                self.logger,
+                "Rejected lightning address invoice request: {e}"
+            );
+            return InvoiceResponse::error(request.id, &e.to_string());
+        }
+
+        let params = InvoiceParams {
+            description_hash: Some(sha256::Hash::hash(address.metadata.as_bytes())),
+            ..Default::default()
+        };
+        let invoice = match invoice_handler
+            .create_invoice_with_params(amount_sats, vec![address.address.clone()], params)
+            .await
+            .map(|i| i.bolt11)
+        {
+            Ok(Some(invoice)) => invoice,
+            Ok(None) | Err(_) => {
+                log_warn!(
+                    self.logger,
+                    "Failed to create invoice for lightning address"
+                );
+                return InvoiceResponse::error(request.id, "Failed to create invoice");
+            }
+        };
+        if let Err(e) = record_invoice_created(&self.storage) {
+            log_warn!(self.logger, "Failed to record created invoice: {e}");
+        }
+
+        let payment = LnAddressPayment {
+            address: address.address.clone(),
+            payment_hash: invoice.payment_hash().to_string(),
+            amount_sats,
+            comment: request.comment,
+            requested_at: utils::now().as_secs(),
+            paid: false,
+        };
+        let key = format!("{LN_ADDRESS_PAYMENT_PREFIX_KEY}{}", payment.payment_hash);
+        if let Err(e) = self.storage.set_data(key, payment, None) {
+            log_warn!(self.logger, "Failed to save lightning address payment: {e}");
+        }
+
+        InvoiceResponse {
+            id: request.id,
+            pr: Some(invoice.to_string()),
+            reason: None,
+        }
+    }
+
+    /// Payments requested through any address we've had, newest first
+    pub fn list_payments(&self) -> Result<Vec<LnAddressPayment>, MutinyError> {
+        let mut payments: Vec<LnAddressPayment> = self
+            .storage
+            .scan(LN_ADDRESS_PAYMENT_PREFIX_KEY, None)?
+            .into_values()
+            .collect();
+        payments.sort_by(|a, b| b.requested_at.cmp(&a.requested_at));
+        Ok(payments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receivelimits::{set_receive_limits, ReceiveLimits};
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::create_dummy_invoice;
+    use crate::{MockInvoiceHandler, MutinyInvoice};
+    use bitcoin::Network;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_client(storage: &MemoryStorage) -> LnAddressClient<MemoryStorage> {
+        let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
+        LnAddressClient::new(
+            xprivkey,
+            "https://proxy.example.com".to_string(),
+            storage,
+            Arc::new(MutinyLogger::default()),
+        )
+        .unwrap()
+    }
+
+    fn test_address() -> LnAddress {
+        LnAddress {
+            address: "satoshi@example.com".to_string(),
+            metadata: r#"[["text/plain","Pay to satoshi@example.com"]]"#.to_string(),
+            proxy_pubkey: Keys::generate().public_key(),
+            min_sendable_msat: MIN_SENDABLE_MSAT,
+            max_sendable_msat: MAX_SENDABLE_MSAT,
+            registered_at: 0,
+        }
+    }
+
+    #[test]
+    async fn test_create_invoice_for_request() {
+        let storage = MemoryStorage::default();
+        let client = create_client(&storage);
+        let address = test_address();
+
+        let invoice = create_dummy_invoice(Some(21_000), Network::Regtest, None).0;
+        let mutiny_inv: MutinyInvoice = invoice.clone().into();
+        let description_hash = sha256::Hash::hash(address.metadata.as_bytes());
+
+        let mut handler = MockInvoiceHandler::new();
+        handler
+            .expect_create_invoice_with_params()
+            .withf(move |amount, labels, params| {
+                *amount == 21
+                    && labels == &vec!["satoshi@example.com".to_string()]
+                    && params.description_hash == Some(description_hash)
+            })
+            .return_once(|_, _, _| Ok(mutiny_inv));
+
+        let request = InvoiceRequest {
+            id: "1".to_string(),
+            amount_msat: 21_000,
+            comment: Some("thanks".to_string()),
+        };
+        let response = client
+            .create_invoice_for_request(&address, request, &handler)
+            .await;
+        assert_eq!(response.pr, Some(invoice.to_string()));
+        assert_eq!(response.reason, None);
+
+        let payments = client.list_payments().unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].address, address.address);
+        assert_eq!(payments[0].amount_sats, 21);
+        assert_eq!(payments[0].comment, Some("thanks".to_string()));
+        assert_eq!(payments[0].payment_hash, invoice.payment_hash().to_string());
+    }
+
+    #[test]
+    async fn test_answer_websocket_request_once() {
+        let storage = MemoryStorage::default();
+        let client = create_client(&storage);
+        let address = test_address();
+        storage
+            .set_data(LN_ADDRESS_KEY.to_string(), &address, None)
+            .unwrap();
+
+        let invoice = create_dummy_invoice(Some(21_000), Network::Regtest, None).0;
+        let mutiny_inv: MutinyInvoice = invoice.clone().into();
+        let mut handler = MockInvoiceHandler::new();
+        handler
+            .expect_create_invoice_with_params()
+            .times(1)
+            .return_once(|_, _, _| Ok(mutiny_inv));
+
+        let request = InvoiceRequest {
+            id: "1".to_string(),
+            amount_msat: 21_000,
+            comment: None,
+        };
+        let message = serde_json::to_string(&request).unwrap();
+        let response = client
+            .handle_websocket_message(&message, &handler)
+            .await
+            .unwrap()
+            .unwrap();
+        let response: InvoiceResponse = serde_json::from_str(&response).unwrap();
+        assert_eq!(response.id, "1");
+        assert_eq!(response.pr, Some(invoice.to_string()));
+
+        // the same request again, from the other transport or a relay
+        let response = client
+            .handle_websocket_message(&message, &handler)
+            .await
+            .unwrap();
+        assert_eq!(response, None);
+        assert_eq!(client.list_payments().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_websocket_url() {
+        let storage = MemoryStorage::default();
+        let client = create_client(&storage);
+
+        let (url, listen) = client.websocket().unwrap();
+        assert_eq!(url.as_str(), "wss://proxy.example.com/v1/listen");
+        let event = Event::from_json(listen).unwrap();
+        assert_eq!(event.kind, LISTEN_EVENT_KIND);
+        assert_eq!(event.pubkey, client.public_key());
+        assert!(event.verify().is_ok());
+    }
+
+    #[test]
+    async fn test_reject_invalid_request() {
+        let storage = MemoryStorage::default();
+        let client = create_client(&storage);
+        let address = test_address();
+        let handler = MockInvoiceHandler::new();
+
+        let request = InvoiceRequest {
+            id: "1".to_string(),
+            amount_msat: MAX_SENDABLE_MSAT + 1_000,
+            comment: None,
+        };
+        let response = client
+            .create_invoice_for_request(&address, request, &handler)
+            .await;
+        assert_eq!(response.pr, None);
+        assert_eq!(response.reason, Some("Amount out of range".to_string()));
+
+        let request = InvoiceRequest {
+            id: "2".to_string(),
+            amount_msat: 1_500,
+            comment: None,
+        };
+        let response = client
+            .create_invoice_for_request(&address, request, &handler)
+            .await;
+        assert_eq!(
+            response.reason,
+            Some("Amount must be whole sats".to_string())
+        );
+
+        let limits = ReceiveLimits {
+            max_invoice_sats: Some(10),
+            ..Default::default()
+        };
+        set_receive_limits(&storage, limits).unwrap();
+        let request = InvoiceRequest {
+            id: "3".to_string(),
+            amount_msat: 21_000,
+            comment: None,
+        };
+        let response = client
+            .create_invoice_for_request(&address, request, &handler)
+            .await;
+        let reason = MutinyError::ReceiveLimitExceeded.to_string();
+        assert_eq!(response.reason, Some(reason));
+
+        assert!(client.list_payments().unwrap().is_empty());
+    }
+}
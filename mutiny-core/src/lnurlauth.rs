@@ -1,17 +1,34 @@
+use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, logging::MutinyLogger};
 use anyhow::anyhow;
 use bdk_chain::collections::HashMap;
-use bitcoin::bip32::{DerivationPath, ExtendedPrivKey};
+use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::secp256k1::{ecdsa, All, Message, PublicKey, Secp256k1, SecretKey};
 use lightning::util::logger::*;
 use lightning::{log_error, log_info};
 use lnurl::lnurl::LnUrl;
 use lnurl::{AsyncClient as LnUrlClient, Response};
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use std::sync::Arc;
 use url::Url;
 
+const LNURL_AUTH_IDENTITIES_KEY: &str = "lnurl_auth_identities";
+
+/// The identity we use to log in to a service with LNURL-auth, see
+/// [crate::MutinyWallet::list_lnurl_auth_identities]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnUrlAuthIdentity {
+    pub domain: String,
+    /// Index of the identity, 0 is the default one
+    pub index: u32,
+    /// The key the service knows us by
+    pub linking_key: PublicKey,
+    /// Unix timestamp of our last login, none if we haven't logged in with this identity
+    pub last_login: Option<u64>,
+}
+
 #[derive(Clone)]
 pub struct AuthManager {
     hashing_key: SecretKey,
@@ -34,6 +51,27 @@ impl AuthManager {
         })
     }
 
+    /// The manager of another identity, so we can log in to a service
+    /// with a different linking key. Identity 0 is this one.
+    pub(crate) fn identity(&self, index: u32) -> Result<Self, MutinyError> {
+        if index == 0 {
+            return Ok(self.clone());
+        }
+
+        // hardened, so it can't collide with the non-hardened linking key paths
+        let path = [
+            ChildNumber::from_hardened_idx(138)?,
+            ChildNumber::from_hardened_idx(index)?,
+        ];
+        let xprivkey = self.xprivkey.derive_priv(&self.context, &path)?;
+        Self::new(xprivkey)
+    }
+
+    /// The linking key the service at the url knows us by
+    pub(crate) fn linking_key(&self, url: Url) -> Result<PublicKey, MutinyError> {
+        Ok(self.get_secret_key(url)?.public_key(&self.context))
+    }
+
     pub(crate) fn get_secret_key(&self, url: Url) -> Result<SecretKey, MutinyError> {
         let path = lnurl::get_derivation_path(self.hashing_key.secret_bytes(), &url)?;
         let key = self
@@ -91,8 +129,44 @@ pub(crate) async fn make_lnurl_auth_connection(
     }
 }
 
+pub(crate) fn get_lnurl_auth_identities<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<LnUrlAuthIdentity>, MutinyError> {
+    Ok(storage
+        .get_data(LNURL_AUTH_IDENTITIES_KEY)?
+        .unwrap_or_default())
+}
+
+/// The identity index we log in to the domain with
+pub(crate) fn get_lnurl_auth_index<S: MutinyStorage>(
+    storage: &S,
+    domain: &str,
+) -> Result<u32, MutinyError> {
+    let identities = get_lnurl_auth_identities(storage)?;
+    Ok(identities
+        .iter()
+        .find(|i| i.domain == domain)
+        .map(|i| i.index)
+        .unwrap_or_default())
+}
+
+/// Saves the identity over the stored one of the same domain
+pub(crate) fn save_lnurl_auth_identity<S: MutinyStorage>(
+    storage: &S,
+    identity: LnUrlAuthIdentity,
+) -> Result<(), MutinyError> {
+    let mut identities = get_lnurl_auth_identities(storage)?;
+    match identities.iter_mut().find(|i| i.domain == identity.domain) {
+        Some(i) => *i = identity,
+        None => identities.push(identity),
+    }
+
+    storage.set_data(LNURL_AUTH_IDENTITIES_KEY.to_string(), identities, None)
+}
+
 #[cfg(test)]
 mod test {
+    use crate::storage::MemoryStorage;
     use crate::test_utils::*;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
     wasm_bindgen_test_configure!(run_in_browser);
@@ -116,4 +190,56 @@ mod test {
             .verify_ecdsa(&Message::from_slice(&k1).unwrap(), &sig, &pk)
             .unwrap();
     }
+
+    #[test]
+    async fn test_lnurl_auth_identities() {
+        let test_name = "test_lnurl_auth_identities";
+        log!("{}", test_name);
+
+        let auth = create_manager();
+        let url = Url::parse("https://mutinywallet.com").unwrap();
+
+        // identity 0 is the default, the others have their own linking keys
+        let default_key = auth.linking_key(url.clone()).unwrap();
+        assert_eq!(
+            auth.identity(0).unwrap().linking_key(url.clone()).unwrap(),
+            default_key
+        );
+        let other_key = auth.identity(1).unwrap().linking_key(url.clone()).unwrap();
+        assert_ne!(other_key, default_key);
+        assert_eq!(
+            auth.identity(1).unwrap().linking_key(url).unwrap(),
+            other_key
+        );
+
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_lnurl_auth_index(&storage, "mutinywallet.com").unwrap(),
+            0
+        );
+
+        let identity = LnUrlAuthIdentity {
+            domain: "mutinywallet.com".to_string(),
+            index: 1,
+            linking_key: other_key,
+            last_login: None,
+        };
+        save_lnurl_auth_identity(&storage, identity.clone()).unwrap();
+        assert_eq!(
+            get_lnurl_auth_index(&storage, "mutinywallet.com").unwrap(),
+            1
+        );
+        assert_eq!(get_lnurl_auth_index(&storage, "example.com").unwrap(), 0);
+
+        // a login updates the stored identity
+        let logged_in = LnUrlAuthIdentity {
+            last_login: Some(1_700_000_000),
+            ..identity
+        };
+        save_lnurl_auth_identity(&storage, logged_in.clone()).unwrap();
+        assert_eq!(
+            get_lnurl_auth_identities(&storage).unwrap(),
+            vec![logged_in]
+        );
+    }
 }
@@ -0,0 +1,211 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::sha256;
+use serde::{Deserialize, Serialize};
+
+const LNURL_WITHDRAWALS_KEY: &str = "lnurl_withdrawals";
+/// How often we check on the pending withdrawals
+pub(crate) const WITHDRAWAL_CHECK_INTERVAL_SECS: u64 = 30;
+/// How many times we request a withdrawal before giving up
+pub(crate) const MAX_WITHDRAWAL_ATTEMPTS: u32 = 5;
+/// How long finished withdrawals are still listed
+const WITHDRAWAL_HISTORY_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalStatus {
+    /// Waiting to request the withdrawal, again after a failed attempt
+    Pending,
+    /// The service accepted our invoice, waiting for it to be paid
+    Requested,
+    /// Our invoice was paid
+    Paid,
+    /// We gave up on the withdrawal
+    Failed,
+}
+
+/// A LNURL-withdraw we track until our invoice is paid, see
+/// [crate::MutinyWallet::list_pending_withdrawals]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PendingWithdrawal {
+    pub id: String,
+    pub lnurl: String,
+    pub amount_sats: u64,
+    pub status: WithdrawalStatus,
+    /// Payment hash of the invoice of the last accepted request
+    pub payment_hash: Option<sha256::Hash>,
+    /// Unix timestamp of when that invoice expires, we request again after that
+    pub invoice_expires_at: Option<u64>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Unix timestamp of when the withdrawal was started
+    pub created_at: u64,
+    /// Unix timestamp of the last change of the status
+    pub updated_at: u64,
+    /// Unix timestamp of when we request the withdrawal again
+    pub next_attempt: u64,
+}
+
+impl PendingWithdrawal {
+    pub(crate) fn new(id: String, lnurl: String, amount_sats: u64, now: u64) -> Self {
+        Self {
+            id,
+            lnurl,
+            amount_sats,
+            status: WithdrawalStatus::Pending,
+            payment_hash: None,
+            invoice_expires_at: None,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            next_attempt: now,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            WithdrawalStatus::Paid | WithdrawalStatus::Failed
+        )
+    }
+
+    /// Records the result of requesting the withdrawal, the payment hash and expiry
+    /// of our invoice when the service accepted it
+    pub(crate) fn record_attempt(
+        &mut self,
+        res: &Result<(sha256::Hash, u64), MutinyError>,
+        now: u64,
+    ) {
+        self.attempts += 1;
+        self.updated_at = now;
+        match res {
+            Ok((payment_hash, expires_at)) => {
+                self.status = WithdrawalStatus::Requested;
+                self.payment_hash = Some(*payment_hash);
+                self.invoice_expires_at = Some(*expires_at);
+                self.last_error = None;
+            }
+            Err(e) => {
+                let retryable = !matches!(
+                    e,
+                    MutinyError::IncorrectLnUrlFunction | MutinyError::InvalidArgumentsError
+                );
+                self.last_error = Some(e.to_string());
+                if retryable && self.attempts < MAX_WITHDRAWAL_ATTEMPTS {
+                    self.status = WithdrawalStatus::Pending;
+                    self.next_attempt = now + retry_delay(self.attempts);
+                } else {
+                    self.status = WithdrawalStatus::Failed;
+                }
+            }
+        }
+    }
+
+    /// Marks the withdrawal as paid
+    pub(crate) fn settle(&mut self, now: u64) {
+        self.status = WithdrawalStatus::Paid;
+        self.updated_at = now;
+    }
+}
+
+/// Backs off exponentially, starting at a minute and up to an hour
+fn retry_delay(attempts: u32) -> u64 {
+    (60 * 2u64.saturating_pow(attempts.saturating_sub(1))).min(60 * 60)
+}
+
+pub(crate) fn get_withdrawals<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PendingWithdrawal>, MutinyError> {
+    Ok(storage.get_data(LNURL_WITHDRAWALS_KEY)?.unwrap_or_default())
+}
+
+/// Saves the given withdrawals over the stored ones with the same id and drops the
+/// withdrawals that finished a while ago.
+pub(crate) fn save_withdrawals<S: MutinyStorage>(
+    storage: &S,
+    updated: &[PendingWithdrawal],
+    now: u64,
+) -> Result<(), MutinyError> {
+    let mut withdrawals = get_withdrawals(storage)?;
+    for withdrawal in updated {
+        match withdrawals.iter_mut().find(|w| w.id == withdrawal.id) {
+            Some(w) => *w = withdrawal.clone(),
+            None => withdrawals.push(withdrawal.clone()),
+        }
+    }
+    withdrawals.retain(|w| !w.is_finished() || w.updated_at + WITHDRAWAL_HISTORY_SECS > now);
+
+    storage.set_data(LNURL_WITHDRAWALS_KEY.to_string(), withdrawals, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use bitcoin::hashes::Hash;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn test_record_attempt() {
+        let mut withdrawal = PendingWithdrawal::new("id".to_string(), "lnurl".into(), 100, NOW);
+
+        // failures are retried with backoff
+        withdrawal.record_attempt(&Err(MutinyError::LnUrlFailure), NOW);
+        assert_eq!(withdrawal.status, WithdrawalStatus::Pending);
+        assert_eq!(withdrawal.next_attempt, NOW + 60);
+        assert!(withdrawal.last_error.is_some());
+        withdrawal.record_attempt(&Err(MutinyError::LnUrlFailure), NOW);
+        assert_eq!(withdrawal.next_attempt, NOW + 120);
+
+        let hash = sha256::Hash::hash(&[1]);
+        withdrawal.record_attempt(&Ok((hash, NOW + 3600)), NOW);
+        assert_eq!(withdrawal.status, WithdrawalStatus::Requested);
+        assert_eq!(withdrawal.payment_hash, Some(hash));
+        assert_eq!(withdrawal.last_error, None);
+
+        // gives up after too many attempts
+        while withdrawal.attempts < MAX_WITHDRAWAL_ATTEMPTS - 1 {
+            withdrawal.record_attempt(&Err(MutinyError::LnUrlFailure), NOW);
+            assert!(!withdrawal.is_finished());
+        }
+        withdrawal.record_attempt(&Err(MutinyError::LnUrlFailure), NOW);
+        assert_eq!(withdrawal.status, WithdrawalStatus::Failed);
+
+        // not a withdrawal is never retried
+        let mut withdrawal = PendingWithdrawal::new("id".to_string(), "lnurl".into(), 100, NOW);
+        withdrawal.record_attempt(&Err(MutinyError::IncorrectLnUrlFunction), NOW);
+        assert_eq!(withdrawal.status, WithdrawalStatus::Failed);
+    }
+
+    #[test]
+    fn test_save_withdrawals() {
+        let storage = MemoryStorage::default();
+        assert!(get_withdrawals(&storage).unwrap().is_empty());
+
+        let first = PendingWithdrawal::new("first".to_string(), "lnurl".into(), 100, NOW);
+        let second = PendingWithdrawal::new("second".to_string(), "lnurl".into(), 200, NOW);
+        save_withdrawals(&storage, &[first.clone(), second.clone()], NOW).unwrap();
+        assert_eq!(
+            get_withdrawals(&storage).unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+
+        // updates replace the stored withdrawal
+        let mut paid = first.clone();
+        paid.settle(NOW);
+        save_withdrawals(&storage, &[paid.clone()], NOW).unwrap();
+        assert_eq!(
+            get_withdrawals(&storage).unwrap(),
+            vec![paid.clone(), second.clone()]
+        );
+
+        // finished withdrawals are dropped after a while
+        save_withdrawals(&storage, &[], NOW + WITHDRAWAL_HISTORY_SECS).unwrap();
+        assert_eq!(get_withdrawals(&storage).unwrap(), vec![second]);
+    }
+}
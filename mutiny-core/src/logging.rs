@@ -10,15 +10,46 @@ use chrono::Utc;
 use hex_conservative::DisplayHex;
 use lightning::util::logger::{Level, Logger, Record};
 use log::*;
+use nostr::nips::nip04::encrypt;
+use nostr::{Keys, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 pub const LOGGING_KEY: &str = "logs";
 
 const MAX_LOG_ITEMS: usize = 10_000;
 
+const REDACTED: &str = "<redacted>";
+
+/// Prefixes of invoices, offers, lnurls, addresses and nostr keys
+const REDACTED_BECH32_PREFIXES: [&str; 6] = ["ln", "bc1", "tb1", "bcrt1", "npub1", "nsec1"];
+
+/// Words that mean the number after them is an amount
+const AMOUNT_WORDS: [&str; 5] = ["amount", "sat", "fee", "balance", "value"];
+
+/// How much we save to storage, see [MutinyLogger::with_writer]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggingConfig {
+    /// Lowest level that gets saved to storage
+    pub level: Level,
+    /// Number of log lines we keep, older ones are dropped
+    pub max_log_items: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::Trace,
+            max_log_items: MAX_LOG_ITEMS,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MutinyLogger {
     pub session_id: String,
     should_write_to_storage: bool,
+    level: Level,
     memory_logs: Arc<Mutex<Vec<String>>>,
 }
 
@@ -27,10 +58,13 @@ impl MutinyLogger {
         stop: Arc<AtomicBool>,
         logging_db: S,
         session_id: Option<String>,
+        config: LoggingConfig,
     ) -> Self {
         let l = MutinyLogger {
             session_id: session_id.unwrap_or_else(gen_session_id),
-            should_write_to_storage: true,
+            // logs can't be saved in read-only sessions
+            should_write_to_storage: !logging_db.is_read_only(),
+            level: config.level,
             memory_logs: Arc::new(Mutex::new(vec![])),
         };
 
@@ -61,7 +95,7 @@ impl MutinyLogger {
                 if let Some(logs) = memory_logs_clone {
                     if !logs.is_empty() {
                         // append them to storage
-                        match write_logging_data(&logging_db, logs).await {
+                        match write_logging_data(&logging_db, logs, config.max_log_items).await {
                             Ok(_) => {}
                             Err(_) => {
                                 error!("could not write logging data to storage, trying again next time, log entries may be lost");
@@ -84,6 +118,17 @@ impl MutinyLogger {
         }
         get_logging_data(storage)
     }
+
+    /// Retrieves the logs from storage and prepares them to be shared, see [export_logs]
+    pub(crate) fn export_logs<S: MutinyStorage>(
+        &self,
+        storage: &S,
+        encrypt_to: Option<PublicKey>,
+    ) -> Result<Option<String>, MutinyError> {
+        self.get_logs(storage)?
+            .map(|logs| export_logs(logs, encrypt_to))
+            .transpose()
+    }
 }
 
 impl Default for MutinyLogger {
@@ -91,11 +136,125 @@ impl Default for MutinyLogger {
         Self {
             session_id: gen_session_id(),
             should_write_to_storage: Default::default(),
+            level: LoggingConfig::default().level,
             memory_logs: Arc::new(Mutex::new(vec![])),
         }
     }
 }
 
+/// Logs from [export_logs] that only the owner of the key they were encrypted to can read
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedLogExport {
+    /// Ephemeral key the logs were encrypted with
+    pub pubkey: PublicKey,
+    /// NIP-04 encrypted logs
+    pub content: String,
+}
+
+/// Redacts the logs so they can be shared with support. If a key is given, the logs are
+/// encrypted to it and an [EncryptedLogExport] is returned as json.
+pub fn export_logs(
+    logs: Vec<String>,
+    encrypt_to: Option<PublicKey>,
+) -> Result<String, MutinyError> {
+    let redacted: String = logs.iter().map(|log| redact_log(log)).collect();
+
+    match encrypt_to {
+        None => Ok(redacted),
+        Some(pubkey) => {
+            let keys = Keys::generate();
+            let secret_key = keys.secret_key().expect("generated keys have a secret key");
+            let export = EncryptedLogExport {
+                pubkey: keys.public_key(),
+                content: encrypt(secret_key, &pubkey, redacted)?,
+            };
+            Ok(serde_json::to_string(&export)?)
+        }
+    }
+}
+
+/// Replaces pubkeys, hashes, invoices, addresses and amounts in the log line
+pub fn redact_log(log: &str) -> String {
+    let tokens = tokenize(log);
+    let words: Vec<(usize, &str)> = tokens
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, t)| t.starts_with(|c: char| c.is_ascii_alphanumeric()))
+        .collect();
+
+    let mut redacted = HashSet::new();
+    for (w, (index, word)) in words.iter().enumerate() {
+        // skip the `Some` in `amount: Some(1000)`
+        let prev = words[..w]
+            .iter()
+            .rev()
+            .map(|(_, p)| p.to_lowercase())
+            .find(|p| p != "some");
+        let next = words.get(w + 1).map(|(_, n)| n.to_lowercase());
+        if is_sensitive(word, prev.as_deref(), next.as_deref()) {
+            redacted.insert(*index);
+        }
+    }
+
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, t)| if redacted.contains(&i) { REDACTED } else { t })
+        .collect()
+}
+
+/// Splits the line into runs of alphanumeric and other characters
+fn tokenize(log: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut start = 0;
+    let mut chars = log.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let is_word = c.is_ascii_alphanumeric();
+        if chars
+            .peek()
+            .map_or(true, |(_, next)| next.is_ascii_alphanumeric() != is_word)
+        {
+            let end = i + c.len_utf8();
+            tokens.push(&log[start..end]);
+            start = end;
+        }
+    }
+    tokens
+}
+
+fn is_sensitive(word: &str, prev: Option<&str>, next: Option<&str>) -> bool {
+    // node ids, payment hashes, preimages and txids
+    if word.len() >= 32 && word.chars().all(|c| c.is_ascii_hexdigit()) {
+        return true;
+    }
+
+    let lower = word.to_lowercase();
+    if word.len() >= 20
+        && REDACTED_BECH32_PREFIXES
+            .iter()
+            .any(|p| lower.starts_with(p))
+    {
+        return true;
+    }
+
+    // base58 addresses
+    if (25..=35).contains(&word.len())
+        && word.starts_with(['1', '3', 'm', 'n', '2'])
+        && word.chars().any(|c| c.is_ascii_digit())
+        && word.chars().any(|c| c.is_ascii_alphabetic())
+    {
+        return true;
+    }
+
+    if word.chars().all(|c| c.is_ascii_digit()) {
+        return next.is_some_and(|n| n.starts_with("sat") || n.starts_with("msat"))
+            || prev.is_some_and(|p| AMOUNT_WORDS.iter().any(|a| p.contains(a)));
+    }
+
+    false
+}
+
 fn gen_session_id() -> String {
     let mut entropy = vec![0u8; 2];
     getrandom::getrandom(&mut entropy).unwrap();
@@ -120,7 +279,7 @@ impl Logger for MutinyLogger {
             raw_log
         );
 
-        if self.should_write_to_storage && record.level >= Level::Trace {
+        if self.should_write_to_storage && record.level >= self.level {
             if let Ok(mut memory_logs) = self.memory_logs.lock() {
                 memory_logs.push(log.clone());
             } else {
@@ -146,14 +305,15 @@ fn get_logging_data<S: MutinyStorage>(storage: &S) -> Result<Option<Vec<String>>
 async fn write_logging_data<S: MutinyStorage>(
     storage: &S,
     mut recent_logs: Vec<String>,
+    max_log_items: usize,
 ) -> Result<(), MutinyError> {
     // get the existing data so we can append to it, trimming if needed
     // Note there is a potential race condition here if the logs are being written to
     // concurrently, but we don't care about that for now.
     let mut existing_logs: Vec<String> = get_logging_data(storage)?.unwrap_or_default();
     existing_logs.append(&mut recent_logs);
-    if existing_logs.len() > MAX_LOG_ITEMS {
-        let start_index = existing_logs.len() - MAX_LOG_ITEMS;
+    if existing_logs.len() > max_log_items {
+        let start_index = existing_logs.len() - max_log_items;
         existing_logs.drain(..start_index);
     }
 
@@ -204,8 +364,12 @@ mod tests {
 
     use crate::{test_utils::*, utils::sleep};
 
-    use crate::logging::MutinyLogger;
+    use crate::logging::{
+        export_logs, redact_log, EncryptedLogExport, LoggingConfig, MutinyLogger, REDACTED,
+    };
     use crate::storage::MemoryStorage;
+    use nostr::nips::nip04::decrypt;
+    use nostr::Keys;
 
     #[test]
     async fn log_without_storage() {
@@ -231,7 +395,12 @@ mod tests {
         let storage = MemoryStorage::default();
 
         let stop = Arc::new(AtomicBool::new(false));
-        let logger = MutinyLogger::with_writer(stop.clone(), storage.clone(), None);
+        let logger = MutinyLogger::with_writer(
+            stop.clone(),
+            storage.clone(),
+            None,
+            LoggingConfig::default(),
+        );
 
         let log_str = "testing logging with storage";
         log_debug!(logger, "{}", log_str);
@@ -249,4 +418,55 @@ mod tests {
 
         stop.swap(true, Ordering::Relaxed);
     }
+
+    #[test]
+    fn test_redact_log() {
+        let pubkey = "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54";
+        let log = format!("Connected to peer {pubkey}@127.0.0.1:9735\n");
+        assert_eq!(
+            redact_log(&log),
+            format!("Connected to peer {REDACTED}@127.0.0.1:9735\n")
+        );
+
+        let invoice = "lnbc1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6twvus8g6rfwvs8qun0dfjkxaq9qrsgq357wnc5r2ueh7ck6q93dj32dlqnls087fxdwk8qakdyafkq3yap9us6v52vjjsrvywa6rt52cm9r9zqt8r2t7mlcwspyetp5h2tztugp9lfyql";
+        assert_eq!(
+            redact_log(&format!("Paying invoice: {invoice}")),
+            format!("Paying invoice: {REDACTED}")
+        );
+
+        let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        assert_eq!(
+            redact_log(&format!("Sending 50000 sats to {address}")),
+            format!("Sending {REDACTED} sats to {REDACTED}")
+        );
+        assert_eq!(
+            redact_log("payment failed, amount_msat: Some(1000), attempt 2"),
+            format!("payment failed, amount_msat: Some({REDACTED}), attempt 2")
+        );
+
+        // the log header is kept
+        let header = "2024-01-01 00:00:00.000 a1b2 DEBUG [mutiny_core::node:123] Starting\n";
+        assert_eq!(redact_log(header), header);
+    }
+
+    #[test]
+    fn test_export_logs() {
+        let logs = vec![
+            "Sending 1000 sats\n".to_string(),
+            "Payment succeeded\n".to_string(),
+        ];
+        let expected = format!("Sending {REDACTED} sats\nPayment succeeded\n");
+        assert_eq!(export_logs(logs.clone(), None).unwrap(), expected);
+
+        let support = Keys::generate();
+        let export = export_logs(logs, Some(support.public_key())).unwrap();
+        let export: EncryptedLogExport = serde_json::from_str(&export).unwrap();
+        let decrypted = decrypt(
+            support.secret_key().unwrap(),
+            &export.pubkey,
+            export.content,
+        )
+        .unwrap();
+        assert_eq!(decrypted, expected);
+    }
 }
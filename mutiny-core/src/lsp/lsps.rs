@@ -58,6 +58,12 @@ pub(crate) struct PendingPaymentInfo {
 type PendingFeeRequestSender = oneshot::Sender<Result<GetInfoResponse, MutinyError>>;
 type PendingBuyRequestSender = oneshot::Sender<Result<Bolt11Invoice, MutinyError>>;
 
+pub(crate) struct PendingBuyRequest {
+    pub sender: PendingBuyRequestSender,
+    /// Minimum CLTV delta of the final hop to put in the invoice
+    pub min_final_cltv: u16,
+}
+
 #[derive(Clone)]
 pub struct LspsClient<S: MutinyStorage> {
     pub pubkey: PublicKey,
@@ -69,7 +75,7 @@ pub struct LspsClient<S: MutinyStorage> {
     network: Network,
     logger: Arc<MutinyLogger>,
     pending_fee_requests: Arc<Mutex<HashMap<RequestId, PendingFeeRequestSender>>>,
-    pending_buy_requests: Arc<Mutex<HashMap<RequestId, PendingBuyRequestSender>>>,
+    pending_buy_requests: Arc<Mutex<HashMap<RequestId, PendingBuyRequest>>>,
     pending_channel_info: Arc<Mutex<HashMap<RequestId, JitChannelInfo>>>,
     pending_payments: Arc<Mutex<HashMap<PaymentHash, PendingPaymentInfo>>>,
     stop: Arc<AtomicBool>,
@@ -149,11 +155,15 @@ impl<S: MutinyStorage> LspsClient<S> {
 
                 let mut pending_buy_requests = self.pending_buy_requests.lock().unwrap();
 
-                if let Some(buy_response_sender) = pending_buy_requests.remove(&request_id) {
+                if let Some(PendingBuyRequest {
+                    sender: buy_response_sender,
+                    min_final_cltv: final_cltv,
+                }) = pending_buy_requests.remove(&request_id)
+                {
                     let invoice_expiry_delta_secs = 3600;
                     let (payment_hash, payment_secret) = match self
                         .channel_manager
-                        .create_inbound_payment(None, invoice_expiry_delta_secs, None)
+                        .create_inbound_payment(None, invoice_expiry_delta_secs, Some(final_cltv))
                     {
                         Ok((payment_hash, payment_secret)) => (payment_hash, payment_secret),
                         Err(_) => {
@@ -234,7 +244,7 @@ impl<S: MutinyStorage> LspsClient<S> {
                         .duration_since_epoch(utils::now())
                         .payee_pub_key(payee_pub_key)
                         .basic_mpp()
-                        .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA.into())
+                        .min_final_cltv_expiry_delta(final_cltv.into())
                         .private_route(lsp_route_hint);
 
                     let payment_size_msat = match payment_size_msat {
@@ -413,14 +423,20 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
         {
             let mut pending_buy_requests: std::sync::MutexGuard<
                 '_,
-                HashMap<RequestId, oneshot::Sender<Result<Bolt11Invoice, MutinyError>>>,
+                HashMap<RequestId, PendingBuyRequest>,
             > = self.pending_buy_requests.lock().unwrap();
 
             let request_id = lsps2_client_handler
                 .select_opening_params(self.pubkey, Some(payment_size_msat), fee_params.clone())
                 .map_err(|_| MutinyError::LspGenericError)?;
 
-            pending_buy_requests.insert(request_id.clone(), pending_buy_request_sender);
+            let pending = PendingBuyRequest {
+                sender: pending_buy_request_sender,
+                min_final_cltv: invoice_request
+                    .min_final_cltv
+                    .unwrap_or(MIN_FINAL_CLTV_EXPIRY_DELTA),
+            };
+            pending_buy_requests.insert(request_id.clone(), pending);
         }
 
         let invoice = pending_buy_request_receiver
@@ -88,6 +88,9 @@ pub struct InvoiceRequest {
     pub bolt11: Option<String>,
     // Map to previously fetched fee
     pub fee_id: String,
+    // Used only for LSPS, VoltageFlow wraps our invoice
+    #[serde(skip)]
+    pub min_final_cltv: Option<u16>,
 }
 
 #[derive(Serialize, Deserialize)]
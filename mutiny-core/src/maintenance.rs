@@ -0,0 +1,194 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils::{self, fetch_with_timeout};
+use nostr::{Event, Filter, Kind, PublicKey};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+/// Kind of the nostr event the service operator publishes maintenance notices with
+pub(crate) const MAINTENANCE_EVENT_KIND: Kind = Kind::ParameterizedReplaceable(30078);
+/// `d` tag of the maintenance notice event, so it replaces the previous notice
+pub(crate) const MAINTENANCE_EVENT_IDENTIFIER: &str = "mutiny-maintenance";
+/// How often we check the maintenance feed
+pub(crate) const MAINTENANCE_CHECK_INTERVAL_SECS: u64 = 300;
+/// Creation time of the newest maintenance notice event we have seen
+pub(crate) const MAINTENANCE_LAST_SEEN_KEY: &str = "maintenance_last_seen";
+
+/// A maintenance or incident notice signed by the service operator.
+///
+/// The operator publishes these as the content of a nostr event, either on relays or
+/// served over HTTPS, and clears them by publishing a notice that is no longer active.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceNotice {
+    pub active: bool,
+    /// Reason to display to the user
+    pub reason: String,
+    /// If flows that depend on the LSP, like receiving over lightning, should be paused
+    #[serde(default)]
+    pub pause_lsp: bool,
+    /// Unix timestamp after which the notice no longer applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Unix timestamp of when the operator published the notice
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+impl MaintenanceNotice {
+    /// Returns true if the notice is active and hasn't expired
+    pub fn is_active(&self) -> bool {
+        self.active
+            && self
+                .expires_at
+                .map_or(true, |expiry| expiry > utils::now().as_secs())
+    }
+}
+
+/// A change to the operator's maintenance notice, see
+/// [crate::MutinyWallet::take_maintenance_events]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaintenanceEvent {
+    /// There is a new or updated notice
+    Updated { notice: MaintenanceNotice },
+    /// The notice was cleared or expired
+    Cleared,
+}
+
+/// Records the creation time of a maintenance notice event.
+///
+/// Returns false if it is older than one we have already seen, so an old notice
+/// that is served again can't replace a newer one.
+pub(crate) fn record_maintenance_event_time<S: MutinyStorage>(
+    storage: &S,
+    created_at: u64,
+) -> Result<bool, MutinyError> {
+    let last_seen: u64 = storage
+        .get_data(MAINTENANCE_LAST_SEEN_KEY)?
+        .unwrap_or_default();
+    if created_at < last_seen {
+        return Ok(false);
+    }
+    if created_at > last_seen {
+        storage.set_data(MAINTENANCE_LAST_SEEN_KEY.to_string(), created_at, None)?;
+    }
+    Ok(true)
+}
+
+/// Filter for the operator's latest maintenance notice on nostr relays
+pub(crate) fn maintenance_filter(operator: PublicKey) -> Filter {
+    Filter::new()
+        .author(operator)
+        .kind(MAINTENANCE_EVENT_KIND)
+        .identifier(MAINTENANCE_EVENT_IDENTIFIER)
+        .limit(1)
+}
+
+/// Verifies that the event is a maintenance notice signed by the operator and parses it.
+///
+/// Returns `None` if the notice has been cleared or has expired.
+pub(crate) fn parse_maintenance_event(
+    event: &Event,
+    operator: &PublicKey,
+) -> Result<Option<MaintenanceNotice>, MutinyError> {
+    if event.pubkey != *operator
+        || event.kind != MAINTENANCE_EVENT_KIND
+        || event.identifier() != Some(MAINTENANCE_EVENT_IDENTIFIER)
+    {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    event
+        .verify()
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+
+    let mut notice: MaintenanceNotice = serde_json::from_str(&event.content)?;
+    notice.created_at = event.created_at.as_u64();
+
+    Ok(Some(notice).filter(|n| n.is_active()))
+}
+
+/// Fetches the latest maintenance notice event served over HTTPS.
+///
+/// A 404 or empty response means there is no notice.
+pub(crate) async fn fetch_maintenance_event(url: &str) -> Result<Option<Event>, MutinyError> {
+    let http_client = reqwest::Client::new();
+    let request = http_client
+        .request(Method::GET, url)
+        .build()
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    let response = fetch_with_timeout(&http_client, request).await?;
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => Ok(None),
+        status if status.is_success() => {
+            let event = response
+                .json::<Option<Event>>()
+                .await
+                .map_err(|_| MutinyError::ConnectionFailed)?;
+            Ok(event)
+        }
+        _ => Err(MutinyError::ConnectionFailed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use nostr::{EventBuilder, Keys, Tag};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn notice_event(keys: &Keys, content: &str) -> Event {
+        let d_tag = Tag::Identifier(MAINTENANCE_EVENT_IDENTIFIER.to_string());
+        EventBuilder::new(MAINTENANCE_EVENT_KIND, content, [d_tag])
+            .to_event(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_maintenance_event() {
+        let operator = Keys::generate();
+        let pubkey = operator.public_key();
+
+        let event = notice_event(
+            &operator,
+            r#"{"active":true,"reason":"LSP upgrade","pause_lsp":true}"#,
+        );
+        let notice = parse_maintenance_event(&event, &pubkey).unwrap().unwrap();
+        assert_eq!(notice.reason, "LSP upgrade");
+        assert!(notice.pause_lsp);
+        assert_eq!(notice.created_at, event.created_at.as_u64());
+
+        // cleared notices
+        let event = notice_event(&operator, r#"{"active":false,"reason":""}"#);
+        assert_eq!(parse_maintenance_event(&event, &pubkey).unwrap(), None);
+
+        // expired notices
+        let event = notice_event(
+            &operator,
+            r#"{"active":true,"reason":"old","expires_at":1}"#,
+        );
+        assert_eq!(parse_maintenance_event(&event, &pubkey).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reject_replayed_maintenance_event() {
+        let storage = MemoryStorage::default();
+
+        assert!(record_maintenance_event_time(&storage, 100).unwrap());
+        assert!(record_maintenance_event_time(&storage, 100).unwrap());
+        assert!(record_maintenance_event_time(&storage, 200).unwrap());
+        assert!(!record_maintenance_event_time(&storage, 100).unwrap());
+    }
+
+    #[test]
+    fn test_reject_maintenance_event_from_other_key() {
+        let operator = Keys::generate();
+        let attacker = Keys::generate();
+
+        let event = notice_event(&attacker, r#"{"active":true,"reason":"phish"}"#);
+        assert!(parse_maintenance_event(&event, &operator.public_key()).is_err());
+    }
+}
@@ -9,20 +9,26 @@ use lightning::ln::wire::{CustomMessageReader, Type};
 use lightning::util::ser::{Writeable, Writer};
 
 use crate::node::LiquidityManager;
+use crate::peerstorage::{
+    with_provide_storage_init, with_provide_storage_node, PeerStorageHandler, PeerStorageMessage,
+};
 use crate::storage::MutinyStorage;
 
 pub struct MutinyMessageHandler<S: MutinyStorage> {
     pub liquidity: Option<Arc<LiquidityManager<S>>>,
+    pub(crate) peer_storage: Arc<PeerStorageHandler>,
 }
 
 pub enum MutinyMessage<S: MutinyStorage> {
     Liquidity(<LiquidityManager<S> as CustomMessageReader>::CustomMessage),
+    PeerStorage(PeerStorageMessage),
 }
 
 impl<S: MutinyStorage> std::fmt::Debug for MutinyMessage<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Liquidity(arg0) => f.debug_tuple("Liquidity").field(arg0).finish(),
+            Self::PeerStorage(arg0) => f.debug_tuple("PeerStorage").field(arg0).finish(),
         }
     }
 }
@@ -43,35 +49,51 @@ impl<S: MutinyStorage> CustomMessageHandler for MutinyMessageHandler<S> {
                     );
                 }
             }
+            MutinyMessage::PeerStorage(PeerStorageMessage::YourPeerStorage { blob }) => {
+                self.peer_storage.received_backup(*sender_node_id, blob);
+            }
+            MutinyMessage::PeerStorage(PeerStorageMessage::PeerStorage { blob }) => {
+                self.peer_storage.hold_backup(*sender_node_id, blob);
+            }
         }
 
         Ok(())
     }
 
     fn get_and_clear_pending_msg(&self) -> Vec<(PublicKey, Self::CustomMessage)> {
+        let mut msgs: Vec<(PublicKey, Self::CustomMessage)> = self
+            .peer_storage
+            .get_and_clear_pending_msg()
+            .into_iter()
+            .map(|(pubkey, message)| (pubkey, MutinyMessage::PeerStorage(message)))
+            .collect();
+
         if let Some(liquidity) = &self.liquidity {
-            liquidity
-                .get_and_clear_pending_msg()
-                .into_iter()
-                .map(|(pubkey, message)| (pubkey, MutinyMessage::Liquidity(message)))
-                .collect()
-        } else {
-            vec![]
+            msgs.extend(
+                liquidity
+                    .get_and_clear_pending_msg()
+                    .into_iter()
+                    .map(|(pubkey, message)| (pubkey, MutinyMessage::Liquidity(message))),
+            );
         }
+
+        msgs
     }
 
     fn provided_node_features(&self) -> NodeFeatures {
-        match &self.liquidity {
+        let features = match &self.liquidity {
             Some(liquidity) => liquidity.provided_node_features(),
             None => NodeFeatures::empty(),
-        }
+        };
+        with_provide_storage_node(features)
     }
 
     fn provided_init_features(&self, their_node_id: &PublicKey) -> InitFeatures {
-        match &self.liquidity {
+        let features = match &self.liquidity {
             Some(liquidity) => liquidity.provided_init_features(their_node_id),
             None => InitFeatures::empty(),
-        }
+        };
+        with_provide_storage_init(features)
     }
 }
 
@@ -82,6 +104,10 @@ impl<S: MutinyStorage> CustomMessageReader for MutinyMessageHandler<S> {
         message_type: u16,
         buffer: &mut R,
     ) -> Result<Option<Self::CustomMessage>, DecodeError> {
+        if let Some(message) = PeerStorageMessage::read(message_type, buffer)? {
+            return Ok(Some(MutinyMessage::PeerStorage(message)));
+        }
+
         if let Some(liquidity) = &self.liquidity {
             match <LiquidityManager<S> as CustomMessageReader>::read(
                 liquidity,
@@ -101,6 +127,7 @@ impl<S: MutinyStorage> Type for MutinyMessage<S> {
     fn type_id(&self) -> u16 {
         match self {
             MutinyMessage::Liquidity(message) => message.type_id(),
+            MutinyMessage::PeerStorage(message) => message.type_id(),
         }
     }
 }
@@ -109,6 +136,7 @@ impl<S: MutinyStorage> Writeable for MutinyMessage<S> {
     fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
         match self {
             MutinyMessage::Liquidity(message) => message.write(writer),
+            MutinyMessage::PeerStorage(message) => message.write(writer),
         }
     }
 }
@@ -0,0 +1,363 @@
+use crate::error::MutinyError;
+use crate::nodemanager::NodeStorage;
+use crate::storage::{MutinyStorage, NODES_KEY};
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Schema version of the storage, the version of the last migration that ran
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+/// Every migration that ran on this storage, see [AppliedMigration]
+pub const MIGRATION_HISTORY_KEY: &str = "schema_migrations";
+
+/// Schema version after all the migrations ran, must match the last migration
+pub const LATEST_SCHEMA_VERSION: u32 = 1;
+
+/// Writes a migration wants to make. Values are written with [MutinyStorage::set_data],
+/// which encrypts them if needed, and the ones with a version are saved to VSS too.
+#[derive(Default)]
+struct MigrationChanges {
+    set: Vec<(String, Value, Option<u32>)>,
+    delete: Vec<String>,
+}
+
+impl MigrationChanges {
+    fn len(&self) -> usize {
+        self.set.len() + self.delete.len()
+    }
+}
+
+/// A step that moves the storage to `version`.
+///
+/// Migrations only read from storage and return their changes, so they
+/// can be validated with a dry-run and rolled back if a later step fails.
+struct Migration<S: MutinyStorage> {
+    version: u32,
+    description: &'static str,
+    run: fn(&S) -> Result<MigrationChanges, MutinyError>,
+}
+
+/// Every migration, in the order they run. New ones go at the end
+/// and [LATEST_SCHEMA_VERSION] needs to be bumped.
+fn migrations<S: MutinyStorage>() -> Vec<Migration<S>> {
+    vec![Migration {
+        version: 1,
+        description: "store node LSP configs as objects instead of urls",
+        run: migrate_node_lsp_configs,
+    }]
+}
+
+/// A migration that ran, or would run with a dry-run
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub description: String,
+    /// Number of keys the migration wrote or deleted
+    pub changed_keys: usize,
+    /// Unix timestamp of when the migration ran
+    pub timestamp: u64,
+}
+
+/// What [run_migrations] or [validate_migrations] did
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub dry_run: bool,
+    pub migrations: Vec<AppliedMigration>,
+}
+
+/// Runs the pending migrations in order, this is done when the storage is started.
+///
+/// If any migration fails every change is rolled back and the schema version is
+/// left as it was, so the migrations run again on the next start.
+/// Read-only storage only gets a dry-run. Storage from a newer version fails with
+/// [MutinyError::StorageVersionTooNew], this version can't know how to read it.
+pub fn run_migrations<S: MutinyStorage>(storage: &S) -> Result<MigrationReport, MutinyError> {
+    migrate(storage, migrations(), false)
+}
+
+/// Runs the pending migrations without writing anything, to check they would succeed.
+///
+/// Each migration sees the storage as it is now, not the changes of the migrations before it.
+pub fn validate_migrations<S: MutinyStorage>(storage: &S) -> Result<MigrationReport, MutinyError> {
+    migrate(storage, migrations(), true)
+}
+
+fn migrate<S: MutinyStorage>(
+    storage: &S,
+    migrations: Vec<Migration<S>>,
+    dry_run: bool,
+) -> Result<MigrationReport, MutinyError> {
+    // we can't write to read-only storage, only check the migrations
+    let dry_run = dry_run || storage.is_read_only();
+    let from_version: u32 = storage.get(SCHEMA_VERSION_KEY)?.unwrap_or(0);
+    let mut report = MigrationReport {
+        from_version,
+        to_version: from_version,
+        dry_run,
+        migrations: vec![],
+    };
+
+    // old code can't know how to handle a newer schema
+    if from_version > LATEST_SCHEMA_VERSION {
+        return Err(MutinyError::StorageVersionTooNew);
+    }
+
+    // the original values of every key we touched, None if it didn't exist
+    let mut backup: HashMap<String, Option<Value>> = HashMap::new();
+    let pending = migrations.into_iter().filter(|m| m.version > from_version);
+    for migration in pending {
+        let res = (migration.run)(storage).and_then(|changes| {
+            let changed_keys = changes.len();
+            if !dry_run {
+                apply_changes(storage, changes, &mut backup)?;
+            }
+            Ok(changed_keys)
+        });
+
+        match res {
+            Ok(changed_keys) => {
+                report.to_version = migration.version;
+                report.migrations.push(AppliedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    changed_keys,
+                    timestamp: utils::now().as_secs(),
+                });
+            }
+            Err(e) => {
+                if !dry_run {
+                    rollback(storage, backup)?;
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    if !dry_run && report.to_version != from_version {
+        let mut history: Vec<AppliedMigration> =
+            storage.get(MIGRATION_HISTORY_KEY)?.unwrap_or_default();
+        history.extend(report.migrations.iter().cloned());
+
+        let items = vec![
+            (
+                SCHEMA_VERSION_KEY.to_string(),
+                serde_json::to_value(report.to_version)?,
+            ),
+            (
+                MIGRATION_HISTORY_KEY.to_string(),
+                serde_json::to_value(history)?,
+            ),
+        ];
+        if let Err(e) = storage.set(items) {
+            rollback(storage, backup)?;
+            return Err(e);
+        }
+    }
+
+    Ok(report)
+}
+
+fn apply_changes<S: MutinyStorage>(
+    storage: &S,
+    changes: MigrationChanges,
+    backup: &mut HashMap<String, Option<Value>>,
+) -> Result<(), MutinyError> {
+    for key in changes
+        .set
+        .iter()
+        .map(|(k, _, _)| k)
+        .chain(changes.delete.iter())
+    {
+        if !backup.contains_key(key) {
+            backup.insert(key.clone(), storage.get(key)?);
+        }
+    }
+
+    for (key, value, version) in changes.set {
+        storage.set_data(key, value, version)?;
+    }
+    storage.delete(&changes.delete)?;
+    Ok(())
+}
+
+fn rollback<S: MutinyStorage>(
+    storage: &S,
+    backup: HashMap<String, Option<Value>>,
+) -> Result<(), MutinyError> {
+    let mut restore = vec![];
+    let mut delete = vec![];
+    for (key, value) in backup {
+        match value {
+            Some(value) => restore.push((key, value)),
+            None => delete.push(key),
+        }
+    }
+
+    storage.set(restore)?;
+    storage.delete(&delete)
+}
+
+/// Nodes used to store their LSP config as just the url, it is parsed
+/// by [crate::lsp::deserialize_lsp_config] but never written back.
+fn migrate_node_lsp_configs<S: MutinyStorage>(
+    storage: &S,
+) -> Result<MigrationChanges, MutinyError> {
+    let mut changes = MigrationChanges::default();
+    let Some(raw) = storage.get::<Value>(NODES_KEY)? else {
+        return Ok(changes);
+    };
+
+    let has_url_lsp = raw
+        .get("nodes")
+        .and_then(Value::as_object)
+        .is_some_and(|nodes| {
+            nodes
+                .values()
+                .any(|n| n.get("lsp").is_some_and(Value::is_string))
+        });
+    if has_url_lsp {
+        let mut node_storage: NodeStorage = serde_json::from_value(raw)?;
+        node_storage.version += 1; // update version for VSS
+        let version = Some(node_storage.version);
+        changes.set.push((
+            NODES_KEY.to_string(),
+            serde_json::to_value(node_storage)?,
+            version,
+        ));
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::voltage::VoltageConfig;
+    use crate::lsp::LspConfig;
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn legacy_nodes() -> Value {
+        json!({
+            "nodes": {
+                "uuid": {
+                    "child_index": 0,
+                    "lsp": "https://lsp.example.com",
+                    "archived": false,
+                }
+            },
+            "version": 3,
+        })
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        let migrations = migrations::<MemoryStorage>();
+        assert!(migrations.windows(2).all(|w| w[0].version < w[1].version));
+        assert_eq!(
+            migrations.last().map(|m| m.version),
+            Some(LATEST_SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn test_run_migrations() {
+        let storage = MemoryStorage::default();
+        storage
+            .set(vec![(NODES_KEY.to_string(), legacy_nodes())])
+            .unwrap();
+
+        // a dry-run doesn't write anything
+        let report = validate_migrations(&storage).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.to_version, LATEST_SCHEMA_VERSION);
+        assert_eq!(report.migrations[0].changed_keys, 1);
+        assert_eq!(
+            storage.get::<Value>(NODES_KEY).unwrap(),
+            Some(legacy_nodes())
+        );
+        assert_eq!(storage.get::<u32>(SCHEMA_VERSION_KEY).unwrap(), None);
+
+        let report = run_migrations(&storage).unwrap();
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, LATEST_SCHEMA_VERSION);
+
+        let raw: Value = storage.get(NODES_KEY).unwrap().unwrap();
+        assert!(raw["nodes"]["uuid"]["lsp"].is_object());
+        let nodes: NodeStorage = storage.get_data(NODES_KEY).unwrap().unwrap();
+        let expected = LspConfig::VoltageFlow(VoltageConfig {
+            url: "https://lsp.example.com".to_string(),
+            pubkey: None,
+            connection_string: None,
+        });
+        assert_eq!(nodes.nodes["uuid"].lsp, Some(expected));
+        assert_eq!(nodes.version, 4);
+
+        let history: Vec<AppliedMigration> = storage.get(MIGRATION_HISTORY_KEY).unwrap().unwrap();
+        assert_eq!(history, report.migrations);
+
+        // nothing left to do
+        let report = run_migrations(&storage).unwrap();
+        assert!(report.migrations.is_empty());
+        assert_eq!(report.from_version, LATEST_SCHEMA_VERSION);
+
+        // storage from a newer version can't be read
+        storage
+            .set_data(
+                SCHEMA_VERSION_KEY.to_string(),
+                LATEST_SCHEMA_VERSION + 1,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            run_migrations(&storage),
+            Err(MutinyError::StorageVersionTooNew)
+        );
+    }
+
+    fn set_test_key(_: &MemoryStorage) -> Result<MigrationChanges, MutinyError> {
+        Ok(MigrationChanges {
+            set: vec![("test_key".to_string(), json!("migrated"), None)],
+            delete: vec![NODES_KEY.to_string()],
+        })
+    }
+
+    fn fail(_: &MemoryStorage) -> Result<MigrationChanges, MutinyError> {
+        Err(MutinyError::InvalidArgumentsError)
+    }
+
+    #[test]
+    fn test_rollback_on_failure() {
+        let storage = MemoryStorage::default();
+        storage
+            .set(vec![(NODES_KEY.to_string(), legacy_nodes())])
+            .unwrap();
+
+        let migrations = vec![
+            Migration {
+                version: 1,
+                description: "set test key",
+                run: set_test_key,
+            },
+            Migration {
+                version: 2,
+                description: "fail",
+                run: fail,
+            },
+        ];
+        assert!(migrate(&storage, migrations, false).is_err());
+
+        assert_eq!(storage.get::<Value>("test_key").unwrap(), None);
+        assert_eq!(
+            storage.get::<Value>(NODES_KEY).unwrap(),
+            Some(legacy_nodes())
+        );
+        assert_eq!(storage.get::<u32>(SCHEMA_VERSION_KEY).unwrap(), None);
+    }
+}
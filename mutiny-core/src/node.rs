@@ -1,12 +1,24 @@
+use crate::latency::{PaymentStage, PaymentTrace};
 use crate::lsp::{InvoiceRequest, LspConfig};
-use crate::nodemanager::ChannelClosure;
+use crate::nodemanager::{
+    ChannelClosure, ChannelLifecycle, ChannelLifecycleState, ChannelPolicy, ForceCloseClaim,
+    LnFeeEstimate, PaymentParametersOverride, StuckChannel, UnsweptOutput,
+};
 use crate::peermanager::LspMessageRouter;
+use crate::peerstorage::{
+    provides_storage, PeerBackup, PeerStorageHandler, HELD_PEER_STORAGE_PREFIX,
+    MAX_PEER_STORAGE_LEN,
+};
+use crate::scb::{
+    add_recovering_channels, backup_encryption_key, get_recovering_channels,
+    remove_recovering_channel, ChannelBackup,
+};
 use crate::storage::MutinyStorage;
-use crate::utils::get_monitor_version;
+use crate::utils::{get_monitor_channel_keys_id, get_monitor_version};
 use crate::{
     chain::MutinyChain,
     error::{MutinyError, MutinyStorageError},
-    event::{EventHandler, HTLCStatus, MillisatAmount, PaymentInfo},
+    event::{spendable_outpoint, EventHandler, HTLCStatus, MillisatAmount, PaymentInfo},
     fees::MutinyFeeEstimator,
     gossip::{get_all_peers, read_peer_info, save_peer_connection_info},
     keymanager::{
@@ -17,48 +29,60 @@ use crate::{
     lsp::{AnyLsp, FeeRequest, Lsp},
     nodemanager::NodeIndex,
     onchain::OnChainWallet,
-    peermanager::{GossipMessageHandler, PeerManagerImpl},
+    peermanager::{ConnectionRegistry, GossipMessageHandler, PeerManagerImpl},
     utils::{self, sleep},
     MutinyInvoice, PrivacyLevel,
 };
-use crate::{fees::P2WSH_OUTPUT_SIZE, peermanager::connect_peer_if_necessary};
-use crate::{keymanager::PhantomKeysManager, scorer::HubPreferentialScorer};
-use crate::{labels::LabelStorage, DEFAULT_PAYMENT_TIMEOUT};
 use crate::{
+    federation::get_federation_activity_tag,
     ldkstorage::{persist_monitor, ChannelOpenParams},
-    storage::persist_payment_info,
+    storage::{payment_key, persist_payment_info},
 };
+use crate::{
+    fees::P2WSH_OUTPUT_SIZE,
+    peermanager::{connect_peer_if_necessary, discover_peers},
+};
+use crate::{keymanager::PhantomKeysManager, scorer::HubPreferentialScorer};
+use crate::{labels::LabelStorage, InvoiceParams, RouteHintConfig, DEFAULT_PAYMENT_TIMEOUT};
 use crate::{messagehandler::MutinyMessageHandler, storage::read_payment_info};
+use ::nostr::prelude::rand::{rngs::OsRng, seq::SliceRandom};
 use anyhow::{anyhow, Context};
 use bdk::FeeRate;
 use bitcoin::bip32::ExtendedPrivKey;
 use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::ThirtyTwoByteHash;
-use bitcoin::{hashes::Hash, secp256k1::PublicKey, Network, OutPoint};
+use bitcoin::{hashes::Hash, secp256k1::PublicKey, Network, OutPoint, Txid};
+use core::cmp::Reverse;
 use core::time::Duration;
-use esplora_client::AsyncClient;
+use esplora_client::{AsyncClient, OutputStatus, TxStatus};
 use futures_util::lock::Mutex;
-use hex_conservative::DisplayHex;
+use hex_conservative::{DisplayHex, FromHex};
 use lightning::events::bump_transaction::{BumpTransactionEventHandler, Wallet};
 use lightning::ln::channelmanager::ChannelDetails;
 use lightning::ln::PaymentSecret;
 use lightning::onion_message::messenger::OnionMessenger as LdkOnionMessenger;
 use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
-use lightning::sign::{EntropySource, InMemorySigner, NodeSigner, Recipient};
+use lightning::sign::{
+    ChannelSigner, EntropySource, InMemorySigner, NodeSigner, Recipient, SignerProvider,
+    SpendableOutputDescriptor,
+};
 use lightning::util::config::MaxDustHTLCExposure;
 use lightning::util::ser::Writeable;
 use lightning::{
     chain::{chainmonitor, Filter, Watch},
     ln::{
-        channelmanager::{PaymentId, PhantomRouteHints, Retry},
+        channelmanager::{PaymentId, PhantomRouteHints, RecentPaymentDetails, Retry},
         peer_handler::{IgnoringMessageHandler, MessageHandler as LdkMessageHandler},
-        PaymentHash, PaymentPreimage,
+        ChannelId, PaymentHash, PaymentPreimage,
     },
     log_debug, log_error, log_info, log_trace, log_warn,
     routing::{
         gossip,
-        gossip::NodeId,
-        router::{DefaultRouter, PaymentParameters, RouteParameters},
+        gossip::{NodeId, RoutingFees},
+        router::{
+            DefaultRouter, PaymentParameters, RouteHint, RouteHintHop, RouteParameters, Router as _,
+        },
     },
     util::{
         config::{ChannelHandshakeConfig, ChannelHandshakeLimits, UserConfig},
@@ -72,15 +96,20 @@ use lightning::{
 };
 use lightning_background_processor::process_events_async;
 use lightning_invoice::{
-    utils::{create_invoice_from_channelmanager_and_duration_since_epoch, create_phantom_invoice},
-    Bolt11Invoice,
+    utils::{
+        create_invoice_from_channelmanager_and_duration_since_epoch,
+        create_invoice_from_channelmanager_and_duration_since_epoch_with_description_hash,
+        create_phantom_invoice, create_phantom_invoice_with_description_hash,
+    },
+    Bolt11Invoice, Bolt11InvoiceDescription, CreationError, Description, InvoiceBuilder,
+    SignOrCreationError,
 };
 use lightning_liquidity::lsps2::client::LSPS2ClientConfig;
 use lightning_liquidity::{LiquidityClientConfig, LiquidityManager as LDKLSPLiquidityManager};
 
 #[cfg(test)]
 use mockall::predicate::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
 use std::{
@@ -94,7 +123,16 @@ use std::{
 use web_time::Instant;
 
 const INITIAL_RECONNECTION_DELAY: u64 = 10;
+/// How long our invoices can be paid for unless the caller asks otherwise
+pub(crate) const DEFAULT_INVOICE_EXPIRY_SECS: u32 = 3600;
+pub(crate) const DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA: u16 = 40;
 const MAX_RECONNECTION_DELAY: u64 = 60;
+/// How often we retry sweeping spendable outputs that failed before
+const SPENDABLE_OUTPUT_RETRY_INTERVAL_SECS: u64 = 60 * 60;
+/// How long an outbound channel can be pending before it is considered stuck
+const STUCK_CHANNEL_SECS: u64 = 60 * 60 * 24 * 3;
+/// How long an outbound channel can be pending with a disconnected peer
+const STUCK_CHANNEL_DISCONNECTED_SECS: u64 = 60 * 60 * 24;
 
 pub(crate) type BumpTxEventHandler<S: MutinyStorage> = BumpTransactionEventHandler<
     Arc<MutinyChain<S>>,
@@ -204,6 +242,7 @@ pub struct NodeBuilder<S: MutinyStorage> {
     // optional
     lsp_config: Option<LspConfig>,
     logger: Option<Arc<MutinyLogger>>,
+    connections: Option<ConnectionRegistry>,
     do_not_connect_peers: bool,
 }
 
@@ -226,6 +265,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             lsp_config: None,
             logger: None,
             network: None,
+            connections: None,
             do_not_connect_peers: false,
         }
     }
@@ -308,6 +348,12 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         self.logger = Some(logger);
     }
 
+    /// Shares the peer connections and channel opens with our other nodes,
+    /// see [ConnectionRegistry]
+    pub(crate) fn with_connection_registry(&mut self, connections: ConnectionRegistry) {
+        self.connections = Some(connections);
+    }
+
     pub fn do_not_connect_peers(&mut self) {
         self.do_not_connect_peers = true;
     }
@@ -493,7 +539,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
                 keys_manager.clone(),
                 router.clone(),
                 channel_monitors,
-                &esplora,
+                esplora.as_ref(),
             )
             .await?;
         log_trace!(logger, "finished initializing channel manager");
@@ -502,6 +548,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             Arc::new(read_channel_manager.channel_manager);
 
         let stop = Arc::new(AtomicBool::new(false));
+        let connections = self.connections.clone().unwrap_or_default();
 
         log_trace!(logger, "creating lsp client");
         let (lsp_client, lsp_client_pubkey, liquidity) = match lsp_config {
@@ -556,6 +603,10 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         });
         log_trace!(logger, "finished creating onion routers");
 
+        // our LSP holds a backup of our channels with it, encrypted like our SCBs
+        let peer_storage_key = backup_encryption_key(self.xprivkey)?;
+        let peer_storage = Arc::new(PeerStorageHandler::new(peer_storage_key));
+
         // init peer manager
         log_trace!(logger, "creating peer manager");
         let ln_msg_handler = MessageHandler {
@@ -564,6 +615,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             onion_message_handler,
             custom_message_handler: Arc::new(MutinyMessageHandler {
                 liquidity: liquidity.clone(),
+                peer_storage: peer_storage.clone(),
             }),
         };
         log_trace!(logger, "finished creating peer manager");
@@ -610,7 +662,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
 
         // sync to chain tip
         log_trace!(logger, "syncing chain to tip");
-        if read_channel_manager.is_restarting {
+        // monitors restored from a static channel backup come with a fresh channel manager
+        if read_channel_manager.is_restarting || !read_channel_manager.channel_monitors.is_empty() {
             let start = Instant::now();
             let mut chain_listener_channel_monitors =
                 Vec::with_capacity(read_channel_manager.channel_monitors.len());
@@ -651,91 +704,49 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         }
         log_trace!(logger, "finished syncing chain to tip");
 
-        // Before we start the background processor, retry previously failed
-        // spendable outputs. We should do this before we start the background
-        // processor so we prevent any race conditions.
-        // if we fail to read the spendable outputs, just log a warning and
-        // continue
-        log_trace!(logger, "retrying spendable outputs");
-        let retry_spendable_outputs = persister
-            .get_failed_spendable_outputs()
-            .map_err(|e| MutinyError::ReadError {
-                source: MutinyStorageError::Other(anyhow!(
-                    "failed to read retry spendable outputs: {e}"
-                )),
-            })
-            .unwrap_or_else(|e| {
-                log_warn!(logger, "Failed to read retry spendable outputs: {e}");
-                vec![]
-            });
-
-        if !retry_spendable_outputs.is_empty() {
-            let event_handler = event_handler.clone();
-            let persister = persister.clone();
-            let logger = logger.clone();
-
-            // We need to process our unhandled spendable outputs
-            // can do this in the background, no need to block on it
-            utils::spawn(async move {
-                let start = Instant::now();
-                log_info!(
-                    logger,
-                    "Retrying {} spendable outputs",
-                    retry_spendable_outputs.len()
-                );
-
-                match event_handler
-                    .handle_spendable_outputs(&retry_spendable_outputs)
-                    .await
-                {
-                    Ok(_) => {
-                        log_info!(logger, "Successfully retried spendable outputs");
-                        if let Err(e) = persister.clear_failed_spendable_outputs() {
-                            log_warn!(logger, "Failed to clear failed spendable outputs: {e}");
+        // Retry previously failed spendable outputs in the background until their sweep
+        // confirms, with a higher fee every time. The first retry happens right away.
+        log_trace!(logger, "starting spendable output sweeper");
+        let sweep_event_handler = event_handler.clone();
+        let sweep_logger = logger.clone();
+        utils::spawn_periodic(
+            stop.clone(),
+            SPENDABLE_OUTPUT_RETRY_INTERVAL_SECS,
+            move || {
+                let event_handler = sweep_event_handler.clone();
+                let logger = sweep_logger.clone();
+                async move {
+                    match event_handler.retry_failed_spendable_outputs(None).await {
+                        Ok(txids) if !txids.is_empty() => {
+                            log_info!(logger, "Swept spendable outputs in {txids:?}");
                         }
-                    }
-                    Err(_) => {
-                        // retry them individually then only save failed ones
-                        // if there was only one we don't need to retry
-                        if retry_spendable_outputs.len() > 1 {
-                            let mut failed = vec![];
-                            for o in retry_spendable_outputs {
-                                if event_handler
-                                    .handle_spendable_outputs(&[o.clone()])
-                                    .await
-                                    .is_err()
-                                {
-                                    failed.push(o);
-                                }
-                            }
-                            if let Err(e) = persister.set_failed_spendable_outputs(failed) {
-                                log_warn!(logger, "Failed to set failed spendable outputs: {e}");
-                            }
-                        };
+                        Ok(_) => {}
+                        Err(e) => log_warn!(logger, "Failed to retry spendable outputs: {e}"),
                     }
                 }
-
-                log_info!(
-                    logger,
-                    "Retrying spendable outputs took {}ms",
-                    start.elapsed().as_millis()
-                );
-            });
-        }
-        log_trace!(logger, "finished retrying spendable outputs");
+            },
+        );
+        log_trace!(logger, "finished starting spendable output sweeper");
 
         // Check all existing channels against default configs.
         // If we have default config changes, those should apply
-        // to all existing and new channels.
+        // to all existing and new channels, on top of the channel's saved policy.
         log_trace!(logger, "checking default user config against channels");
-        let default_config = default_user_config(accept_underpaying_htlcs).channel_config;
+        let default_config = default_user_config(accept_underpaying_htlcs, false).channel_config;
         for channel in channel_manager.list_channels() {
+            let mut expected_config = default_config;
+            match persister.get_channel_policy(channel.user_channel_id) {
+                Ok(Some(policy)) => policy.apply(&mut expected_config),
+                Ok(None) => {}
+                Err(e) => log_error!(logger, "error reading channel policy: {e}"),
+            }
+
             // unwrap is safe after LDK.0.0.109
-            if channel.config.unwrap() != default_config {
+            if channel.config.unwrap() != expected_config {
                 match channel_manager.update_channel_config(
                     &channel.counterparty.node_id,
                     &[channel.channel_id],
-                    &default_config,
+                    &expected_config,
                 ) {
                     Ok(_) => {
                         log_debug!(
@@ -831,7 +842,9 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             let reconnection_logger = logger.clone();
             let reconnection_uuid = uuid.clone();
             let reconnection_lsp_client = lsp_client.clone();
+            let reconnection_network_graph = gossip_sync.network_graph().clone();
             let reconnection_stop = stop.clone();
+            let reconnection_connections = connections.clone();
             let reconnection_stopped_comp = stopped_components.clone();
             reconnection_stopped_comp.try_write()?.push(false);
             utils::spawn(async move {
@@ -841,10 +854,12 @@ impl<S: MutinyStorage> NodeBuilder<S> {
                     #[cfg(target_arch = "wasm32")]
                     reconnection_proxy_addr,
                     reconnection_peer_man,
+                    reconnection_connections,
                     reconnection_fee,
                     &reconnection_logger,
                     reconnection_uuid,
                     reconnection_lsp_client.as_ref(),
+                    reconnection_network_graph,
                     reconnection_stop,
                     reconnection_stopped_comp,
                     network == Network::Regtest,
@@ -982,6 +997,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             channel_manager,
             chain_monitor,
             fee_estimator,
+            router,
+            scorer,
             network,
             persister,
             wallet,
@@ -990,6 +1007,9 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             sync_lock,
             stop,
             has_done_initial_sync,
+            connections,
+            event_handler,
+            peer_storage,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
         })
@@ -1006,6 +1026,8 @@ pub(crate) struct Node<S: MutinyStorage> {
     pub channel_manager: Arc<PhantomChannelManager<S>>,
     pub chain_monitor: Arc<ChainMonitor<S>>,
     pub fee_estimator: Arc<MutinyFeeEstimator<S>>,
+    router: Arc<Router>,
+    scorer: Arc<utils::Mutex<HubPreferentialScorer>>,
     network: Network,
     pub persister: Arc<MutinyNodePersister<S>>,
     wallet: Arc<OnChainWallet<S>>,
@@ -1014,6 +1036,9 @@ pub(crate) struct Node<S: MutinyStorage> {
     pub(crate) sync_lock: Arc<Mutex<()>>,
     stop: Arc<AtomicBool>,
     has_done_initial_sync: Arc<AtomicBool>,
+    connections: ConnectionRegistry,
+    event_handler: EventHandler<S>,
+    peer_storage: Arc<PeerStorageHandler>,
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: String,
 }
@@ -1085,6 +1110,7 @@ impl<S: MutinyStorage> Node<S> {
             #[cfg(target_arch = "wasm32")]
             &self.websocket_proxy_addr,
             &peer_connection_info,
+            &self.connections,
             &self.persister.storage,
             self.logger.clone(),
             self.peer_manager.clone(),
@@ -1148,6 +1174,71 @@ impl<S: MutinyStorage> Node<S> {
         log_trace!(self.logger, "finished calling disconnect_peer");
     }
 
+    /// Sets the forwarding policy of a channel and saves it so it is kept across restarts.
+    pub fn update_channel_policy(
+        &self,
+        channel: &ChannelDetails,
+        policy: ChannelPolicy,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling update_channel_policy");
+
+        if let (Some(min), Some(max)) = (policy.htlc_minimum_msat, policy.htlc_maximum_msat) {
+            if min > max {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+        }
+
+        let accept_underpaying_htlcs = self
+            .lsp_client
+            .as_ref()
+            .is_some_and(|l| l.accept_underpaying_htlcs());
+        let mut config = default_user_config(accept_underpaying_htlcs, false).channel_config;
+        policy.apply(&mut config);
+
+        self.channel_manager
+            .update_channel_config(
+                &channel.counterparty.node_id,
+                &[channel.channel_id],
+                &config,
+            )
+            .map_err(|e| {
+                log_error!(
+                    self.logger,
+                    "error updating config for channel {}: {e:?}",
+                    channel.channel_id
+                );
+                MutinyError::InvalidArgumentsError
+            })?;
+        self.persister
+            .persist_channel_policy(channel.user_channel_id, policy)?;
+
+        log_trace!(self.logger, "finished calling update_channel_policy");
+
+        Ok(())
+    }
+
+    /// Broadcasts our node announcement if we have any announced channels.
+    /// Peers will only relay the announcements for our channels once they know about our node.
+    pub fn broadcast_node_announcement(&self) {
+        log_trace!(self.logger, "calling broadcast_node_announcement");
+
+        let has_public_channels = self
+            .channel_manager
+            .list_channels()
+            .iter()
+            .any(|c| c.is_public && c.is_channel_ready);
+        if has_public_channels {
+            let mut alias = [0; 32];
+            let name = b"Mutiny";
+            alias[..name.len()].copy_from_slice(name);
+            // we don't accept inbound connections, so no addresses to announce
+            self.peer_manager
+                .broadcast_node_announcement([0; 3], alias, vec![]);
+        }
+
+        log_trace!(self.logger, "finished calling broadcast_node_announcement");
+    }
+
     pub fn get_phantom_route_hint(&self) -> PhantomRouteHints {
         log_trace!(self.logger, "calling get_phantom_route_hint");
         let res = self.channel_manager.get_phantom_route_hints();
@@ -1205,13 +1296,19 @@ impl<S: MutinyStorage> Node<S> {
         res
     }
 
+    /// Creates an invoice, through the LSP if we have one.
+    ///
+    /// Invoices the LSP creates for us when we need a new channel use the LSP's defaults
+    /// instead of the given params.
     pub async fn create_invoice(
         &self,
         amount_sat: u64,
         route_hints: Option<Vec<PhantomRouteHints>>,
         labels: Vec<String>,
+        params: &InvoiceParams,
     ) -> Result<(Bolt11Invoice, u64), MutinyError> {
         log_trace!(self.logger, "calling create_invoice");
+        params.validate()?;
 
         let res = match self.lsp_client.as_ref() {
             Some(lsp) => {
@@ -1274,6 +1371,7 @@ impl<S: MutinyStorage> Node<S> {
                                 Some(lsp_fee.fee_amount_msat),
                                 route_hints,
                                 labels,
+                                params,
                             )
                             .await?;
 
@@ -1281,6 +1379,7 @@ impl<S: MutinyStorage> Node<S> {
                             .get_lsp_invoice(InvoiceRequest {
                                 bolt11: Some(invoice.to_string()),
                                 fee_id: lsp_fee.id,
+                                min_final_cltv: None,
                             })
                             .await?;
 
@@ -1303,6 +1402,7 @@ impl<S: MutinyStorage> Node<S> {
                                     None,
                                     route_hints,
                                     labels,
+                                    params,
                                 )
                                 .await?,
                                 0,
@@ -1320,6 +1420,7 @@ impl<S: MutinyStorage> Node<S> {
                                 .get_lsp_invoice(InvoiceRequest {
                                     bolt11: None,
                                     fee_id: lsp_fee.id,
+                                    min_final_cltv: params.min_final_cltv,
                                 })
                                 .await
                             {
@@ -1345,7 +1446,7 @@ impl<S: MutinyStorage> Node<S> {
                 }
             }
             None => Ok((
-                self.create_internal_invoice(Some(amount_sat), None, route_hints, labels)
+                self.create_internal_invoice(Some(amount_sat), None, route_hints, labels, params)
                     .await?,
                 0,
             )),
@@ -1361,10 +1462,15 @@ impl<S: MutinyStorage> Node<S> {
         fee_amount_msat: Option<u64>,
         route_hints: Option<Vec<PhantomRouteHints>>,
         labels: Vec<String>,
+        params: &InvoiceParams,
     ) -> Result<Bolt11Invoice, MutinyError> {
         let amount_msat = amount_sat.map(|s| s * 1_000);
-        // Set description to empty string to make smallest possible invoice/QR code
-        let description = "".to_string();
+        // Default to an empty description to make smallest possible invoice/QR code
+        let description = params.description.clone().unwrap_or_default();
+        let expiry_secs = params.expiry_secs.unwrap_or(DEFAULT_INVOICE_EXPIRY_SECS);
+        let min_final_cltv = params
+            .min_final_cltv
+            .unwrap_or(DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA);
 
         // wait for first sync to complete
         for _ in 0..60 {
@@ -1380,33 +1486,73 @@ impl<S: MutinyStorage> Node<S> {
             sleep(1_000).await;
         }
 
-        let invoice_res = match route_hints {
-            None => {
-                let now = crate::utils::now();
-                create_invoice_from_channelmanager_and_duration_since_epoch(
+        let route_hints = match (route_hints, params.route_hints.as_ref()) {
+            (Some(hints), Some(config)) => {
+                Some(select_phantom_route_hints(hints, amount_msat, config))
+            }
+            (hints, _) => hints,
+        };
+
+        let now = crate::utils::now();
+        let invoice_res = match (route_hints, params.description_hash, params.route_hints) {
+            (None, hash, Some(config)) => self.create_invoice_with_route_hint_config(
+                amount_msat,
+                description,
+                hash,
+                expiry_secs,
+                min_final_cltv,
+                now,
+                &config,
+            ),
+            (None, None, None) => create_invoice_from_channelmanager_and_duration_since_epoch(
+                &self.channel_manager.clone(),
+                self.keys_manager.clone(),
+                self.logger.clone(),
+                self.network.into(),
+                amount_msat,
+                description,
+                now,
+                expiry_secs,
+                Some(min_final_cltv),
+            ),
+            (None, Some(hash), None) => {
+                create_invoice_from_channelmanager_and_duration_since_epoch_with_description_hash(
                     &self.channel_manager.clone(),
                     self.keys_manager.clone(),
                     self.logger.clone(),
                     self.network.into(),
                     amount_msat,
-                    description,
+                    lightning_invoice::Sha256(hash),
                     now,
-                    3600,
-                    Some(40),
+                    expiry_secs,
+                    Some(min_final_cltv),
                 )
             }
-            Some(r) => create_phantom_invoice(
+            (Some(r), None, _) => create_phantom_invoice(
                 amount_msat,
                 None,
                 description,
-                3600,
+                expiry_secs,
+                r,
+                self.keys_manager.clone(),
+                self.keys_manager.clone(),
+                self.logger.clone(),
+                self.network.into(),
+                Some(min_final_cltv),
+                now,
+            ),
+            (Some(r), Some(hash), _) => create_phantom_invoice_with_description_hash(
+                amount_msat,
+                None,
+                expiry_secs,
+                lightning_invoice::Sha256(hash),
                 r,
                 self.keys_manager.clone(),
                 self.keys_manager.clone(),
                 self.logger.clone(),
                 self.network.into(),
-                Some(40),
-                crate::utils::now(),
+                Some(min_final_cltv),
+                now,
             ),
         };
         let invoice = invoice_res.map_err(|e| {
@@ -1422,6 +1568,53 @@ impl<S: MutinyStorage> Node<S> {
         Ok(invoice)
     }
 
+    /// Creates an invoice for just this node with the route hints picked by the
+    /// [RouteHintConfig] instead of LDK's selection
+    #[allow(clippy::too_many_arguments)]
+    fn create_invoice_with_route_hint_config(
+        &self,
+        amount_msat: Option<u64>,
+        description: String,
+        description_hash: Option<Sha256>,
+        expiry_secs: u32,
+        min_final_cltv: u16,
+        now: Duration,
+        config: &RouteHintConfig,
+    ) -> Result<Bolt11Invoice, SignOrCreationError<()>> {
+        let (payment_hash, payment_secret) = self
+            .channel_manager
+            .create_inbound_payment(amount_msat, expiry_secs, Some(min_final_cltv))
+            .map_err(|_| SignOrCreationError::CreationError(CreationError::InvalidAmount))?;
+
+        let description =
+            Description::new(description).map_err(SignOrCreationError::CreationError)?;
+        let description_hash = description_hash.map(lightning_invoice::Sha256);
+        let invoice_description = match description_hash.as_ref() {
+            Some(hash) => Bolt11InvoiceDescription::Hash(hash),
+            None => Bolt11InvoiceDescription::Direct(&description),
+        };
+
+        let mut builder = InvoiceBuilder::new(self.network.into())
+            .invoice_description(invoice_description)
+            .payment_hash(Sha256::from_byte_array(payment_hash.0))
+            .payment_secret(payment_secret)
+            .duration_since_epoch(now)
+            .basic_mpp()
+            .min_final_cltv_expiry_delta(min_final_cltv.into())
+            .expiry_time(Duration::from_secs(expiry_secs.into()));
+        if let Some(amount_msat) = amount_msat {
+            builder = builder.amount_milli_satoshis(amount_msat);
+        }
+        let channels = self.channel_manager.list_channels();
+        for hint in select_route_hints(channels, amount_msat, config) {
+            builder = builder.private_route(hint);
+        }
+
+        let secp = Secp256k1::new();
+        let node_secret = self.keys_manager.get_node_secret_key();
+        builder.try_build_signed(|hash| Ok(secp.sign_ecdsa_recoverable(hash, &node_secret)))
+    }
+
     async fn save_invoice_payment_info(
         &self,
         invoice: Bolt11Invoice,
@@ -1441,6 +1634,7 @@ impl<S: MutinyStorage> Node<S> {
             payee_pubkey: None,
             privacy_level: PrivacyLevel::NotAvailable,
             last_update,
+            custom_tlvs: vec![],
         };
         persist_payment_info(
             &self.persister.storage,
@@ -1479,159 +1673,918 @@ impl<S: MutinyStorage> Node<S> {
         res
     }
 
-    fn retry_strategy() -> Retry {
-        Retry::Attempts(15)
-    }
-
-    /// init_invoice_payment sends off the payment but does not wait for results
-    /// use pay_invoice_with_timeout to wait for results
-    pub async fn init_invoice_payment(
+    /// Gets the lifecycle state of a channel, with the confirmations of its funding
+    /// transaction filled in from the channel manager.
+    ///
+    /// Channels we have no saved state for, like ones opened before we saved it,
+    /// get their state from the channel manager or their closure.
+    pub fn get_channel_lifecycle(
         &self,
-        invoice: &Bolt11Invoice,
-        amt_sats: Option<u64>,
-    ) -> Result<(PaymentId, PaymentHash), MutinyError> {
-        log_trace!(self.logger, "calling init_invoice_payment");
-
-        let payment_hash = invoice.payment_hash().into_32();
-
-        if read_payment_info(&self.persister.storage, &payment_hash, false, &self.logger)
-            .is_some_and(|p| p.status != HTLCStatus::Failed)
-        {
-            return Err(MutinyError::NonUniquePaymentHash);
-        }
+        user_channel_id: u128,
+    ) -> Result<Option<ChannelLifecycle>, MutinyError> {
+        let channel = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .find(|c| c.user_channel_id == user_channel_id);
+
+        let Some(mut lifecycle) = self.persister.get_channel_lifecycle(user_channel_id)? else {
+            let state = match channel {
+                Some(c) if c.is_channel_ready => ChannelLifecycleState::Ready,
+                Some(c) => match c.funding_txo {
+                    Some(funding_txo) => ChannelLifecycleState::WaitingConfirmations {
+                        funding_txid: funding_txo.txid,
+                        confirmations: c.confirmations.unwrap_or(0),
+                        confirmations_required: c.confirmations_required,
+                    },
+                    None => ChannelLifecycleState::Negotiating,
+                },
+                None => match self.persister.get_channel_closure(user_channel_id)? {
+                    Some(closure) => ChannelLifecycleState::Closed {
+                        reason: closure.reason,
+                    },
+                    None => return Ok(None),
+                },
+            };
+            return Ok(Some(ChannelLifecycle::new(user_channel_id, state)));
+        };
 
-        if read_payment_info(&self.persister.storage, &payment_hash, true, &self.logger)
-            .is_some_and(|p| p.status != HTLCStatus::Failed)
+        if let ChannelLifecycleState::WaitingConfirmations {
+            confirmations,
+            confirmations_required,
+            ..
+        } = &mut lifecycle.state
         {
-            return Err(MutinyError::NonUniquePaymentHash);
+            if let Some(c) = channel {
+                *confirmations = c.confirmations.unwrap_or(0);
+                *confirmations_required = c.confirmations_required;
+            }
         }
 
-        // get invoice amount or use amt_sats
-        let send_msats = invoice
-            .amount_milli_satoshis()
-            .or(amt_sats.map(|x| x * 1_000))
-            .ok_or(MutinyError::InvoiceInvalid)?;
+        Ok(Some(lifecycle))
+    }
 
-        // check if we have enough balance to send
-        let channels = self.channel_manager.list_channels();
-        if channels
-            .iter()
-            // only consider channels that are confirmed
-            .filter(|c| c.is_channel_ready)
-            .map(|c| c.balance_msat)
-            .sum::<u64>()
-            < send_msats
-        {
-            // Channels exist but not enough capacity
-            return Err(MutinyError::InsufficientBalance);
-        }
+    /// Lists our outbound channels that never became ready, pending for longer than
+    /// [STUCK_CHANNEL_SECS], or [STUCK_CHANNEL_DISCONNECTED_SECS] if the peer is offline.
+    pub fn list_stuck_channels(&self) -> Result<Vec<StuckChannel>, MutinyError> {
+        let now = utils::now().as_secs();
+        let connected: HashSet<PublicKey> = self
+            .peer_manager
+            .get_peer_node_ids()
+            .into_iter()
+            .map(|(pubkey, _)| pubkey)
+            .collect();
 
-        // make sure node at least has one connection before attempting payment
-        // wait for connection before paying, or otherwise instant fail anyways
-        // also check we've completed initial sync this run, otherwise we might create
-        // htlcs that can cause a channel to be closed
-        for _ in 0..DEFAULT_PAYMENT_TIMEOUT {
-            // check if we've been stopped
-            if self.stop.load(Ordering::Relaxed) {
-                return Err(MutinyError::NotRunning);
+        let mut stuck = vec![];
+        for channel in self.channel_manager.list_channels() {
+            if channel.is_channel_ready || !channel.is_outbound {
+                continue;
             }
-            let has_usable = !self.channel_manager.list_usable_channels().is_empty();
-            let init = self.has_done_initial_sync.load(Ordering::Relaxed);
-            if has_usable && init {
-                break;
+
+            let pending_since = self
+                .persister
+                .get_channel_lifecycle(channel.user_channel_id)?
+                .map(|l| l.updated_at);
+            let peer_connected = connected.contains(&channel.counterparty.node_id);
+            let timeout = if peer_connected {
+                STUCK_CHANNEL_SECS
+            } else {
+                STUCK_CHANNEL_DISCONNECTED_SECS
+            };
+            // without a saved state we can only go off the peer being gone
+            let is_stuck = match pending_since {
+                Some(since) => now.saturating_sub(since) > timeout,
+                None => !peer_connected,
+            };
+
+            if is_stuck {
+                stuck.push(StuckChannel {
+                    user_chan_id: channel.user_channel_id.to_be_bytes().to_lower_hex_string(),
+                    peer: channel.counterparty.node_id,
+                    size: channel.channel_value_satoshis,
+                    funding_txid: channel.funding_txo.map(|o| o.txid),
+                    confirmations: channel.confirmations.unwrap_or(0),
+                    pending_since,
+                    peer_connected,
+                });
             }
-            log_trace!(
+        }
+
+        Ok(stuck)
+    }
+
+    /// Abandons an outbound channel that never became ready, see [Node::list_stuck_channels].
+    ///
+    /// If the channel has a funding transaction it is double-spent back to the wallet first,
+    /// the channel is only closed once the double-spend made it to the mempool, returning
+    /// its txid. The channel is closed without broadcasting anything and the closure is
+    /// recorded. Channels with a confirmed funding transaction can't be abandoned,
+    /// they need to be closed instead.
+    pub async fn abandon_pending_channel(
+        &self,
+        user_channel_id: u128,
+    ) -> Result<Option<Txid>, MutinyError> {
+        let channel = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .find(|c| c.user_channel_id == user_channel_id)
+            .ok_or(MutinyError::NotFound)?;
+        if channel.is_channel_ready
+            || !channel.is_outbound
+            || channel.confirmations.unwrap_or(0) > 0
+        {
+            log_error!(
                 self.logger,
-                "waiting for channel to be usable, has usable channels: {has_usable} finished init sync:{init}"
+                "Can't abandon channel {user_channel_id}, it isn't a pending outbound channel"
             );
-            sleep(1_000).await;
+            return Err(MutinyError::ChannelClosingFailed);
         }
 
-        let (pay_result, amt_msat) = if invoice.amount_milli_satoshis().is_none() {
-            if amt_sats.is_none() {
-                return Err(MutinyError::InvoiceInvalid);
-            }
-            let amount_msats = amt_sats.unwrap() * 1_000;
-            (
-                self.pay_invoice_internal(invoice, amount_msats),
-                amount_msats,
-            )
-        } else {
-            if amt_sats.is_some() {
-                return Err(MutinyError::InvoiceInvalid);
-            }
-            let amount_msats = invoice.amount_milli_satoshis().unwrap();
-            (
-                self.pay_invoice_internal(invoice, amount_msats),
-                amount_msats,
-            )
+        let funding_tx = match channel.funding_txo {
+            Some(funding_txo) => self
+                .wallet
+                .get_transaction(funding_txo.txid)?
+                .and_then(|t| t.transaction),
+            None => None,
         };
 
-        let last_update = utils::now().as_secs();
-        let mut payment_info = PaymentInfo {
-            preimage: None,
-            secret: None,
-            status: HTLCStatus::InFlight,
-            amt_msat: MillisatAmount(Some(amt_msat)),
-            fee_paid_msat: None,
-            bolt11: Some(invoice.clone()),
-            payee_pubkey: None,
-            privacy_level: PrivacyLevel::NotAvailable,
-            last_update,
+        // the channel stays open until the funding tx can no longer confirm
+        let replacement_txid = match funding_tx {
+            Some(funding_tx) => {
+                let funding_txid = funding_tx.txid();
+                let txid = self
+                    .wallet
+                    .cancel_transaction(&funding_tx, None)
+                    .await
+                    .map_err(|e| {
+                        log_error!(
+                            self.logger,
+                            "Could not double-spend funding tx {funding_txid} of channel {user_channel_id}: {e}"
+                        );
+                        e
+                    })?;
+                if !self.wallet.is_in_mempool(&txid).await? {
+                    log_error!(
+                        self.logger,
+                        "Double-spend {txid} of funding tx {funding_txid} is not in the mempool, not abandoning channel {user_channel_id}"
+                    );
+                    return Err(MutinyError::ChannelClosingFailed);
+                }
+                // stop retrying it, it isn't queued if it made it to the mempool
+                let _ = self.wallet.abandon_pending_broadcast(&funding_txid);
+                Some(txid)
+            }
+            None => None,
         };
 
-        persist_payment_info(&self.persister.storage, &payment_hash, &payment_info, false)?;
-
-        let res = match pay_result {
-            Ok(id) => Ok((id, PaymentHash(payment_hash))),
-            Err(error) => {
-                log_error!(self.logger, "failed to make payment: {error:?}");
-                // call list channels to see what our channels are
-                let current_channels = self.channel_manager.list_channels();
-                log_debug!(
+        // without open params the ChannelClosed event handles this as a closure
+        self.persister.delete_channel_open_params(user_channel_id)?;
+        self.channel_manager
+            .force_close_without_broadcasting_txn(
+                &channel.channel_id,
+                &channel.counterparty.node_id,
+            )
+            .map_err(|e| {
+                log_error!(
                     self.logger,
-                    "current channel details: {:?}",
-                    current_channels
+                    "had an error abandoning channel {} with node {} : {e:?}",
+                    &channel.channel_id,
+                    &channel.counterparty.node_id
                 );
+                MutinyError::ChannelClosingFailed
+            })?;
 
-                payment_info.status = HTLCStatus::Failed;
-                persist_payment_info(&self.persister.storage, &payment_hash, &payment_info, false)?;
-
-                Err(map_sending_failure(error, amt_msat, &current_channels))
-            }
+        // the ChannelClosed event keeps a closure that is already recorded
+        let reason = "Abandoned while pending".to_string();
+        let closure = ChannelClosure {
+            user_channel_id: Some(user_channel_id.to_be_bytes()),
+            channel_id: Some(channel.channel_id.0),
+            node_id: Some(channel.counterparty.node_id),
+            reason: reason.clone(),
+            timestamp: utils::now().as_secs(),
         };
-        log_trace!(self.logger, "finished calling init_invoice_payment");
+        self.persister
+            .persist_channel_closure(user_channel_id, closure)?;
+        self.persister
+            .persist_channel_lifecycle(user_channel_id, ChannelLifecycleState::Closed { reason })?;
 
-        res
+        Ok(replacement_txid)
     }
 
-    // copied from LDK, modified to change a couple params
-    fn pay_invoice_internal(
-        &self,
-        invoice: &Bolt11Invoice,
-        amount_msats: u64,
-    ) -> Result<PaymentId, RetryableSendFailure> {
-        let payment_id = PaymentId(invoice.payment_hash().into_32());
-        let payment_hash = PaymentHash((*invoice.payment_hash()).into_32());
-        let mut recipient_onion = RecipientOnionFields::secret_only(*invoice.payment_secret());
-        recipient_onion.payment_metadata = invoice.payment_metadata().cloned();
-        let mut payment_params = PaymentParameters::from_node_id(
-            invoice.recover_payee_pub_key(),
-            invoice.min_final_cltv_expiry_delta() as u32,
-        )
-        .with_expiry_time(invoice.expires_at().unwrap().as_secs())
-        .with_route_hints(invoice.route_hints())
-        .unwrap();
-        if let Some(features) = invoice.features() {
+    /// Lists the outputs of closed channels whose sweep to our wallet failed or hasn't
+    /// confirmed yet, they are swept again in the background with a higher fee every time.
+    pub fn list_unswept_outputs(&self) -> Result<Vec<UnsweptOutput>, MutinyError> {
+        let sweeps = self.persister.get_spendable_output_sweeps()?;
+        let outputs = self
+            .persister
+            .get_failed_spendable_outputs()?
+            .into_iter()
+            .filter_map(|d| {
+                let amount_sats = match &d {
+                    SpendableOutputDescriptor::DelayedPaymentOutput(desc) => desc.output.value,
+                    SpendableOutputDescriptor::StaticPaymentOutput(desc) => desc.output.value,
+                    // these went to our wallet already
+                    SpendableOutputDescriptor::StaticOutput { .. } => return None,
+                };
+                let outpoint = spendable_outpoint(&d);
+                let sweep = sweeps.iter().find(|s| s.outpoints.contains(&outpoint));
+                Some(UnsweptOutput {
+                    outpoint,
+                    amount_sats,
+                    attempts: sweep.map(|s| s.attempts).unwrap_or_default(),
+                    sweep_txid: sweep.and_then(|s| s.txid),
+                })
+            })
+            .collect();
+
+        Ok(outputs)
+    }
+
+    /// Sweeps the outputs of [Node::list_unswept_outputs] now with the given fee rate
+    /// in sats per kw, returning the txids of the sweeps.
+    pub async fn force_sweep_outputs(&self, fee_rate: u32) -> Result<Vec<Txid>, MutinyError> {
+        self.event_handler
+            .retry_failed_spendable_outputs(Some(fee_rate))
+            .await
+    }
+
+    /// Gets what we need to recover the funds of our open channels, see [ChannelBackup]
+    pub(crate) async fn channel_backups(&self) -> Vec<ChannelBackup> {
+        log_trace!(self.logger, "calling channel_backups");
+
+        let lsp = match self.lsp_client.as_ref() {
+            Some(lsp) => {
+                let pubkey = lsp.get_lsp_pubkey().await;
+                Some((pubkey, lsp.get_lsp_connection_string().await))
+            }
+            None => None,
+        };
+
+        let backups = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .filter_map(|c| {
+                let funding_txo = c.funding_txo?;
+                let monitor = self.chain_monitor.get_monitor(funding_txo).ok()?;
+                let channel_keys_id = get_monitor_channel_keys_id(&monitor.encode())?;
+                let counterparty = c.counterparty.node_id;
+
+                let node_id = NodeId::from_pubkey(&counterparty);
+                let connection_string = read_peer_info(&self.persister.storage, &node_id)
+                    .ok()
+                    .flatten()
+                    .and_then(|p| p.connection_string)
+                    .or_else(|| {
+                        lsp.as_ref()
+                            .filter(|(pk, _)| *pk == counterparty)
+                            .map(|(_, conn)| conn.clone())
+                    });
+
+                Some(ChannelBackup {
+                    node_id: self.uuid.clone(),
+                    counterparty,
+                    connection_string,
+                    funding_outpoint: funding_txo.into_bitcoin_outpoint(),
+                    channel_value_sats: c.channel_value_satoshis,
+                    channel_keys_id: channel_keys_id.to_lower_hex_string(),
+                })
+            })
+            .collect();
+
+        log_trace!(self.logger, "finished calling channel_backups");
+        backups
+    }
+
+    /// Lists the claims of the balances of our closed channels that aren't swept yet
+    pub fn get_force_close_details(&self) -> Result<Vec<ForceCloseClaim>, MutinyError> {
+        let height = self.channel_manager.current_best_block().height();
+        let now = utils::now().as_secs();
+        let open: HashSet<_> = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .filter_map(|c| c.funding_txo)
+            .collect();
+
+        let mut claims = vec![];
+        for funding_txo in self.persister.list_stored_monitors()? {
+            if open.contains(&funding_txo) {
+                continue;
+            }
+            let Ok(monitor) = self.chain_monitor.get_monitor(funding_txo) else {
+                continue;
+            };
+
+            let peer = monitor.get_counterparty_node_id();
+            for balance in monitor.get_claimable_balances() {
+                claims.push(ForceCloseClaim::new(
+                    funding_txo.into_bitcoin_outpoint(),
+                    peer,
+                    &balance,
+                    height,
+                    now,
+                ));
+            }
+        }
+
+        Ok(claims)
+    }
+
+    /// Bumps the fee of the force close of a channel, and of its HTLC claims, to the given
+    /// fee rate in sats per kw. The anchor output of the commitment transaction is spent
+    /// by a child transaction paying for both, so the close confirms during fee spikes.
+    ///
+    /// Only works for anchor channels, the fee of other commitment transactions is fixed.
+    pub fn bump_channel_close_fee(
+        &self,
+        channel_id: &ChannelId,
+        fee_rate: u32,
+    ) -> Result<(), MutinyError> {
+        if self
+            .channel_manager
+            .list_channels()
+            .iter()
+            .any(|c| c.channel_id == *channel_id)
+        {
+            log_error!(
+                self.logger,
+                "Can't bump the close fee of channel {channel_id}, it isn't closed"
+            );
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let funding_txo = self
+            .persister
+            .list_stored_monitors()?
+            .into_iter()
+            .find(|o| o.to_channel_id() == *channel_id)
+            .ok_or(MutinyError::NotFound)?;
+        let monitor = self
+            .chain_monitor
+            .get_monitor(funding_txo)
+            .map_err(|_| MutinyError::NotFound)?;
+        if monitor.get_claimable_balances().is_empty() {
+            log_error!(
+                self.logger,
+                "Can't bump the close fee of channel {channel_id}, nothing left to claim"
+            );
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        self.persister
+            .persist_close_fee_rate(channel_id, fee_rate)?;
+        // gives new BumpTransaction events for the pending claims, at the new fee rate
+        self.chain_monitor.rebroadcast_pending_claims();
+        log_info!(
+            self.logger,
+            "Bumping close of channel {channel_id} to {fee_rate} sats/kw"
+        );
+
+        Ok(())
+    }
+
+    /// Recovers from the backups our peers gave back, and sends our latest backup
+    /// of the channels with the LSP to it when they changed, see [crate::peerstorage].
+    pub(crate) async fn check_peer_storage(&self) -> Result<(), MutinyError> {
+        for (peer, blob) in self.peer_storage.take_received_backups() {
+            match PeerBackup::decrypt(&blob, self.peer_storage.encryption_key()) {
+                Ok(backup) => {
+                    self.recover_from_peer_backup(backup)?;
+                }
+                Err(e) => log_warn!(self.logger, "Could not read backup from peer {peer}: {e}"),
+            }
+        }
+        self.recover_channels().await?;
+        self.hold_peer_backups()?;
+
+        let Some(lsp) = self.lsp_client.as_ref() else {
+            return Ok(());
+        };
+        let lsp_pubkey = lsp.get_lsp_pubkey().await;
+        let connected = self
+            .peer_manager
+            .get_peer_node_ids()
+            .iter()
+            .any(|(pubkey, _)| *pubkey == lsp_pubkey);
+        let provides_storage = self
+            .channel_manager
+            .list_channels_with_counterparty(&lsp_pubkey)
+            .first()
+            .is_some_and(|c| provides_storage(&c.counterparty.features));
+        if !connected || !provides_storage {
+            // it may have lost the backup in the meantime
+            self.peer_storage.reset_last_sent();
+            return Ok(());
+        }
+
+        let channels: Vec<ChannelBackup> = self
+            .channel_backups()
+            .await
+            .into_iter()
+            .filter(|c| c.counterparty == lsp_pubkey)
+            .collect();
+        // an empty backup would replace the one we need after a restore
+        if channels.is_empty() {
+            return Ok(());
+        }
+
+        let backup = PeerBackup {
+            channels,
+            created_at: utils::now().as_secs(),
+        };
+        match self.peer_storage.send_backup(lsp_pubkey, &backup) {
+            Ok(true) => log_debug!(self.logger, "Sent channel backup to {lsp_pubkey}"),
+            Ok(false) => {}
+            Err(e) => log_warn!(
+                self.logger,
+                "Channel backup of {} channels is too large for {lsp_pubkey}: {e}",
+                backup.channels.len()
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Persists the backups our peers sent us to hold, and gives them back
+    /// when the peers reconnect. We only hold backups of peers we have channels with.
+    fn hold_peer_backups(&self) -> Result<(), MutinyError> {
+        let storage = &self.persister.storage;
+        let channel_peers: HashSet<PublicKey> = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .map(|c| c.counterparty.node_id)
+            .collect();
+
+        for (peer, blob) in self.peer_storage.take_backups_to_hold() {
+            if !channel_peers.contains(&peer) || blob.len() > MAX_PEER_STORAGE_LEN {
+                continue;
+            }
+            let key = format!("{HELD_PEER_STORAGE_PREFIX}{peer}");
+            storage.set_data(key, blob.to_lower_hex_string(), None)?;
+        }
+
+        let connected = self
+            .peer_manager
+            .get_peer_node_ids()
+            .into_iter()
+            .map(|(pubkey, _)| pubkey)
+            .collect();
+        for peer in self.peer_storage.newly_connected(connected) {
+            let key = format!("{HELD_PEER_STORAGE_PREFIX}{peer}");
+            let Some(blob) = storage.get_data::<String>(key)? else {
+                continue;
+            };
+            if let Ok(blob) = FromHex::from_hex(&blob) {
+                self.peer_storage.give_back_backup(peer, blob);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts recovering the channels of the backup we don't have the monitor of, like
+    /// after restoring from seed, see [Node::recover_channels].
+    ///
+    /// Returns how many channels are newly recovered.
+    pub(crate) fn recover_from_peer_backup(
+        &self,
+        backup: PeerBackup,
+    ) -> Result<usize, MutinyError> {
+        let stored = self.persister.list_stored_monitors()?;
+        let channels: Vec<ChannelBackup> = backup
+            .channels
+            .into_iter()
+            .filter(|c| c.node_id == self.uuid)
+            .filter(|c| {
+                !stored
+                    .iter()
+                    .any(|o| o.into_bitcoin_outpoint() == c.funding_outpoint)
+            })
+            .collect();
+
+        let recovered = add_recovering_channels(&self.persister.storage, channels)?;
+        if recovered > 0 {
+            log_info!(
+                self.logger,
+                "Recovering {recovered} channels from peer backup"
+            );
+        }
+
+        Ok(recovered)
+    }
+
+    /// Sweeps our balances of the channels we are recovering from a backup. The peer force
+    /// closes them when we connect, as we don't know about them anymore, and once the
+    /// commitment transaction confirms our balance is swept with the other spendable
+    /// outputs, see [EventHandler::retry_failed_spendable_outputs].
+    ///
+    /// We never load a channel monitor from a backup, one that isn't the latest could
+    /// broadcast a revoked state, see [ChannelBackup].
+    pub(crate) async fn recover_channels(&self) -> Result<(), MutinyError> {
+        let storage = &self.persister.storage;
+        let channels: Vec<ChannelBackup> = get_recovering_channels(storage)?
+            .into_iter()
+            .filter(|c| c.node_id == self.uuid)
+            .collect();
+        if channels.is_empty() {
+            return Ok(());
+        }
+
+        let stored = self.persister.list_stored_monitors()?;
+        for channel in channels {
+            let outpoint = channel.funding_outpoint;
+            if stored.iter().any(|o| o.into_bitcoin_outpoint() == outpoint) {
+                // the channel is back, the monitor takes care of it
+                remove_recovering_channel(storage, &outpoint)?;
+                continue;
+            }
+
+            if let Err(e) = self.recover_channel(&channel).await {
+                log_warn!(self.logger, "Failed to recover channel {outpoint}: {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn recover_channel(&self, channel: &ChannelBackup) -> Result<(), MutinyError> {
+        let outpoint = channel.funding_outpoint;
+        let status = self
+            .wallet
+            .blockchain
+            .get_output_status(&outpoint.txid, outpoint.vout as u64)
+            .await?;
+        let commitment_txid = match status {
+            Some(OutputStatus {
+                spent: true,
+                txid: Some(txid),
+                status: Some(TxStatus {
+                    confirmed: true, ..
+                }),
+                ..
+            }) => txid,
+            // waiting for the peer to force close, or for the close to confirm
+            _ => return Ok(()),
+        };
+        let commitment_tx = self
+            .wallet
+            .blockchain
+            .get_tx(&commitment_txid)
+            .await?
+            .ok_or(MutinyError::ChainAccessFailed)?;
+
+        let signer = self
+            .keys_manager
+            .derive_channel_signer(channel.channel_value_sats, channel.channel_keys_id()?);
+        match channel.to_remote_output(signer.pubkeys(), &commitment_tx)? {
+            Some(descriptor) => {
+                self.persister
+                    .persist_failed_spendable_outputs(vec![descriptor])
+                    .map_err(|e| MutinyError::PersistenceFailed {
+                        source: MutinyStorageError::Other(e),
+                    })?;
+                log_info!(
+                    self.logger,
+                    "Sweeping our balance of channel {outpoint} closed in {commitment_txid}"
+                );
+            }
+            None => log_warn!(
+                self.logger,
+                "Channel {outpoint} closed in {commitment_txid} without a balance for us"
+            ),
+        }
+
+        remove_recovering_channel(&self.persister.storage, &outpoint)
+    }
+
+    /// Deletes the monitors of channels that closed more than `min_age_secs` ago
+    /// and have nothing left for us to claim.
+    ///
+    /// Returns the number of deleted monitors and their size in bytes.
+    pub(crate) fn prune_resolved_monitors(
+        &self,
+        min_age_secs: u64,
+    ) -> Result<(usize, u64), MutinyError> {
+        log_trace!(self.logger, "calling prune_resolved_monitors");
+
+        let cutoff = utils::now().as_secs().saturating_sub(min_age_secs);
+        let open: HashSet<_> = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .filter_map(|c| c.funding_txo)
+            .collect();
+        let closed: HashSet<[u8; 32]> = self
+            .persister
+            .list_channel_closures()?
+            .into_iter()
+            .filter(|c| c.timestamp < cutoff)
+            .filter_map(|c| c.channel_id)
+            .collect();
+
+        let mut removed = 0;
+        let mut bytes = 0;
+        for funding_txo in self.persister.list_stored_monitors()? {
+            if open.contains(&funding_txo) || !closed.contains(&funding_txo.to_channel_id().0) {
+                continue;
+            }
+
+            // monitors without claimable balances aren't watched after a restart,
+            // the ones we still watch need to be fully resolved
+            let resolved = match self.chain_monitor.get_monitor(funding_txo) {
+                Ok(monitor) => monitor.get_claimable_balances().is_empty(),
+                Err(_) => true,
+            };
+            if resolved {
+                bytes += self.persister.archive_monitor(&funding_txo)?;
+                self.persister
+                    .delete_close_fee_rate(&funding_txo.to_channel_id())?;
+                removed += 1;
+            }
+        }
+
+        log_trace!(self.logger, "finished calling prune_resolved_monitors");
+        Ok((removed, bytes))
+    }
+
+    fn retry_strategy() -> Retry {
+        Retry::Attempts(15)
+    }
+
+    /// init_invoice_payment sends off the payment but does not wait for results
+    /// use pay_invoice_with_timeout to wait for results
+    pub async fn init_invoice_payment(
+        &self,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        overrides: Option<&PaymentParametersOverride>,
+        trace: &mut PaymentTrace,
+    ) -> Result<(PaymentId, PaymentHash), MutinyError> {
+        log_trace!(self.logger, "calling init_invoice_payment");
+
+        let payment_hash = invoice.payment_hash().into_32();
+
+        if read_payment_info(&self.persister.storage, &payment_hash, false, &self.logger)
+            .is_some_and(|p| p.status != HTLCStatus::Failed)
+        {
+            return Err(MutinyError::NonUniquePaymentHash);
+        }
+
+        if read_payment_info(&self.persister.storage, &payment_hash, true, &self.logger)
+            .is_some_and(|p| p.status != HTLCStatus::Failed)
+        {
+            return Err(MutinyError::NonUniquePaymentHash);
+        }
+
+        // get invoice amount or use amt_sats
+        let send_msats = invoice
+            .amount_milli_satoshis()
+            .or(amt_sats.map(|x| x * 1_000))
+            .ok_or(MutinyError::InvoiceInvalid)?;
+
+        // check if we have enough balance to send
+        let channels = self.channel_manager.list_channels();
+        if channels
+            .iter()
+            // only consider channels that are confirmed
+            .filter(|c| c.is_channel_ready)
+            .map(|c| c.balance_msat)
+            .sum::<u64>()
+            < send_msats
+        {
+            // Channels exist but not enough capacity
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        // a forced first hop has to be able to carry the whole payment
+        if let Some(channel_id) = overrides.and_then(|o| o.first_hop_channel_id) {
+            let channel = channels
+                .iter()
+                .find(|c| c.channel_id == channel_id)
+                .ok_or(MutinyError::NotFound)?;
+            if channel.next_outbound_htlc_limit_msat < send_msats {
+                return Err(MutinyError::InsufficientBalance);
+            }
+        }
+
+        // make sure node at least has one connection before attempting payment
+        // wait for connection before paying, or otherwise instant fail anyways
+        // also check we've completed initial sync this run, otherwise we might create
+        // htlcs that can cause a channel to be closed
+        for _ in 0..DEFAULT_PAYMENT_TIMEOUT {
+            // check if we've been stopped
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(MutinyError::NotRunning);
+            }
+            let has_usable = !self.channel_manager.list_usable_channels().is_empty();
+            let init = self.has_done_initial_sync.load(Ordering::Relaxed);
+            if has_usable && init {
+                break;
+            }
+            log_trace!(
+                self.logger,
+                "waiting for channel to be usable, has usable channels: {has_usable} finished init sync:{init}"
+            );
+            sleep(1_000).await;
+        }
+        trace.mark(PaymentStage::Connect);
+
+        let (pay_result, amt_msat) = if invoice.amount_milli_satoshis().is_none() {
+            if amt_sats.is_none() {
+                return Err(MutinyError::InvoiceInvalid);
+            }
+            let amount_msats = amt_sats.unwrap() * 1_000;
+            (
+                self.pay_invoice_internal(invoice, amount_msats, overrides),
+                amount_msats,
+            )
+        } else {
+            if amt_sats.is_some() {
+                return Err(MutinyError::InvoiceInvalid);
+            }
+            let amount_msats = invoice.amount_milli_satoshis().unwrap();
+            (
+                self.pay_invoice_internal(invoice, amount_msats, overrides),
+                amount_msats,
+            )
+        };
+        trace.mark(PaymentStage::RouteFind);
+
+        let last_update = utils::now().as_secs();
+        let mut payment_info = PaymentInfo {
+            preimage: None,
+            secret: None,
+            status: HTLCStatus::InFlight,
+            amt_msat: MillisatAmount(Some(amt_msat)),
+            fee_paid_msat: None,
+            bolt11: Some(invoice.clone()),
+            payee_pubkey: None,
+            privacy_level: PrivacyLevel::NotAvailable,
+            last_update,
+            custom_tlvs: vec![],
+        };
+
+        persist_payment_info(&self.persister.storage, &payment_hash, &payment_info, false)?;
+
+        let res = match pay_result {
+            Ok(id) => Ok((id, PaymentHash(payment_hash))),
+            Err(error) => {
+                log_error!(self.logger, "failed to make payment: {error:?}");
+                // call list channels to see what our channels are
+                let current_channels = self.channel_manager.list_channels();
+                log_debug!(
+                    self.logger,
+                    "current channel details: {:?}",
+                    current_channels
+                );
+
+                payment_info.status = HTLCStatus::Failed;
+                persist_payment_info(&self.persister.storage, &payment_hash, &payment_info, false)?;
+
+                Err(map_sending_failure(error, amt_msat, &current_channels))
+            }
+        };
+        log_trace!(self.logger, "finished calling init_invoice_payment");
+
+        res
+    }
+
+    /// Estimates the fee of paying the invoice by finding the route we would take,
+    /// and sends probes along it so the scorer has fresh data by the time we pay.
+    pub fn estimate_ln_fee(
+        &self,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<LnFeeEstimate, MutinyError> {
+        log_trace!(self.logger, "calling estimate_ln_fee");
+
+        let amount_msats = match (invoice.amount_milli_satoshis(), amt_sats) {
+            (Some(msats), None) => msats,
+            (None, Some(sats)) => sats * 1_000,
+            _ => return Err(MutinyError::InvoiceInvalid),
+        };
+        let route_params = Self::invoice_route_params(invoice, amount_msats, &[]);
+
+        let find_route = |params: &RouteParameters| {
+            let first_hops = self.channel_manager.list_usable_channels();
+            self.router.find_route(
+                &self.pubkey,
+                params,
+                Some(&first_hops.iter().collect::<Vec<_>>()),
+                self.channel_manager.compute_inflight_htlcs(),
+            )
+        };
+
+        let route = find_route(&route_params).map_err(|e| {
+            log_debug!(self.logger, "could not find route for fee estimate: {e:?}");
+            MutinyError::RoutingFailed
+        })?;
+        let fee_msat = route.get_total_fees();
+
+        // if the first route fails we retry over other channels, estimate that as the high end
+        let mut retry_params = route_params.clone();
+        retry_params.payment_params.previously_failed_channels = route
+            .paths
+            .iter()
+            .flat_map(|p| p.hops.iter().skip(1).map(|h| h.short_channel_id))
+            .collect();
+        let max_fee_msat = find_route(&retry_params)
+            .map(|r| r.get_total_fees().max(fee_msat))
+            .unwrap_or(fee_msat);
+
+        // every part of the payment has to succeed
+        let success_probability = self.scorer.try_lock().ok().and_then(|scorer| {
+            let params = scoring_params();
+            route
+                .paths
+                .iter()
+                .map(|p| scorer.estimated_success_probability(p, &params))
+                .reduce(|a, b| match (a, b) {
+                    (Some(a), Some(b)) => Some(a * b),
+                    (a, b) => a.or(b),
+                })
+                .flatten()
+        });
+
+        let probes_sent = match self
+            .channel_manager
+            .send_preflight_probes(route_params, None)
+        {
+            Ok(probes) => probes.len(),
+            Err(e) => {
+                log_debug!(self.logger, "could not send preflight probes: {e:?}");
+                0
+            }
+        };
+
+        log_trace!(self.logger, "finished calling estimate_ln_fee");
+
+        Ok(LnFeeEstimate {
+            fee_msat,
+            max_fee_msat,
+            success_probability,
+            probes_sent,
+        })
+    }
+
+    // copied from LDK, modified to change a couple params
+    fn invoice_route_params(
+        invoice: &Bolt11Invoice,
+        amount_msats: u64,
+        extra_route_hints: &[RouteHint],
+    ) -> RouteParameters {
+        let mut route_hints = invoice.route_hints();
+        route_hints.extend_from_slice(extra_route_hints);
+        let mut payment_params = PaymentParameters::from_node_id(
+            invoice.recover_payee_pub_key(),
+            invoice.min_final_cltv_expiry_delta() as u32,
+        )
+        .with_expiry_time(invoice.expires_at().unwrap().as_secs())
+        .with_route_hints(route_hints)
+        .unwrap();
+        if let Some(features) = invoice.features() {
             payment_params = payment_params
                 .with_bolt11_features(features.clone())
                 .unwrap();
         }
-        let route_params = RouteParameters {
+        RouteParameters {
             payment_params,
             final_value_msat: amount_msats,
             max_total_routing_fee_msat: None, // main change from LDK, we just want payment to succeed
-        };
+        }
+    }
+
+    fn pay_invoice_internal(
+        &self,
+        invoice: &Bolt11Invoice,
+        amount_msats: u64,
+        overrides: Option<&PaymentParametersOverride>,
+    ) -> Result<PaymentId, RetryableSendFailure> {
+        let payment_id = PaymentId(invoice.payment_hash().into_32());
+        let payment_hash = PaymentHash((*invoice.payment_hash()).into_32());
+        let mut recipient_onion = RecipientOnionFields::secret_only(*invoice.payment_secret());
+        recipient_onion.payment_metadata = invoice.payment_metadata().cloned();
+        let extra_route_hints = overrides
+            .map(|o| o.route_hints.as_slice())
+            .unwrap_or_default();
+        let mut route_params = Self::invoice_route_params(invoice, amount_msats, extra_route_hints);
+        if let Some(overrides) = overrides {
+            route_params.max_total_routing_fee_msat = overrides.max_fee_msat;
+            if let Some(channel_id) = overrides.first_hop_channel_id {
+                // the router has no way to pick a first hop, so exclude our other channels.
+                // LDK keeps these when retrying, so the payment never leaves another way
+                route_params.payment_params.previously_failed_channels = self
+                    .channel_manager
+                    .list_channels()
+                    .iter()
+                    .filter(|c| c.channel_id != channel_id)
+                    .filter_map(|c| c.get_outbound_payment_scid())
+                    .collect();
+            }
+        }
 
         self.channel_manager
             .as_ref()
@@ -1691,16 +2644,21 @@ impl<S: MutinyStorage> Node<S> {
         amt_sats: Option<u64>,
         timeout_secs: Option<u64>,
         labels: Vec<String>,
+        overrides: Option<&PaymentParametersOverride>,
+        trace: &mut PaymentTrace,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling pay_invoice_with_timeout");
 
         // initiate payment
-        let (payment_id, payment_hash) = self.init_invoice_payment(invoice, amt_sats).await?;
+        let (payment_id, payment_hash) = self
+            .init_invoice_payment(invoice, amt_sats, overrides, trace)
+            .await?;
         let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
 
         let res = self
             .await_payment(payment_id, payment_hash, timeout, labels)
             .await;
+        trace.mark(PaymentStage::Settle);
         log_trace!(self.logger, "finished calling pay_invoice_with_timeout");
 
         res
@@ -1799,6 +2757,7 @@ impl<S: MutinyStorage> Node<S> {
             payee_pubkey: Some(to_node),
             privacy_level: PrivacyLevel::NotAvailable,
             last_update,
+            custom_tlvs: vec![],
         };
 
         persist_payment_info(
@@ -1861,6 +2820,81 @@ impl<S: MutinyStorage> Node<S> {
         res
     }
 
+    /// Stops retrying an outgoing payment of this node. It fails once the HTLCs that are
+    /// still in flight fail, but can still complete if the receiver already has them.
+    ///
+    /// Returns [MutinyError::NotFound] if LDK doesn't know the payment,
+    /// see [Node::fail_forgotten_payment].
+    pub fn abandon_payment(&self, payment_hash: &Sha256) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling abandon_payment");
+
+        let hash = PaymentHash(payment_hash.into_32());
+        let payment = self
+            .channel_manager
+            .list_recent_payments()
+            .into_iter()
+            .find(|p| match p {
+                RecentPaymentDetails::Pending { payment_hash, .. }
+                | RecentPaymentDetails::Abandoned { payment_hash, .. } => *payment_hash == hash,
+                RecentPaymentDetails::Fulfilled { payment_hash, .. } => *payment_hash == Some(hash),
+                RecentPaymentDetails::AwaitingInvoice { .. } => false,
+            });
+
+        let res = match payment {
+            Some(RecentPaymentDetails::Pending { payment_id, .. }) => {
+                log_info!(self.logger, "Abandoning payment {payment_hash}");
+                // LDK sends a PaymentFailed event once nothing is in flight anymore
+                self.channel_manager.abandon_payment(payment_id);
+                Ok(())
+            }
+            Some(RecentPaymentDetails::Fulfilled { .. }) => {
+                log_error!(
+                    self.logger,
+                    "Can't abandon payment {payment_hash}, it already succeeded"
+                );
+                Err(MutinyError::InvalidArgumentsError)
+            }
+            // already abandoned, it fails once its HTLCs do
+            Some(RecentPaymentDetails::Abandoned { .. }) => Ok(()),
+            Some(RecentPaymentDetails::AwaitingInvoice { .. }) | None => Err(MutinyError::NotFound),
+        };
+        log_trace!(self.logger, "finished calling abandon_payment");
+
+        res
+    }
+
+    /// Marks an outgoing lightning payment that no node knows about anymore as failed,
+    /// like one that was in flight when we shut down.
+    ///
+    /// Payments made by a federation are refused, they are handled by the federation.
+    pub fn fail_forgotten_payment(&self, payment_hash: &Sha256) -> Result<(), MutinyError> {
+        let hash = payment_hash.into_32();
+        let storage = &self.persister.storage;
+        if get_federation_activity_tag(storage, &payment_key(false, &hash))?.is_some() {
+            log_error!(
+                self.logger,
+                "Can't abandon payment {payment_hash}, it was made by a federation"
+            );
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let mut info =
+            read_payment_info(storage, &hash, false, &self.logger).ok_or(MutinyError::NotFound)?;
+        match info.status {
+            HTLCStatus::Succeeded => Err(MutinyError::InvalidArgumentsError),
+            HTLCStatus::Failed => Ok(()),
+            HTLCStatus::Pending | HTLCStatus::InFlight => {
+                log_info!(
+                    self.logger,
+                    "Marking forgotten payment {payment_hash} as failed"
+                );
+                info.status = HTLCStatus::Failed;
+                info.last_update = utils::now().as_secs();
+                persist_payment_info(storage, &hash, &info, false)
+            }
+        }
+    }
+
     async fn await_chan_funding_tx(
         &self,
         user_channel_id: u128,
@@ -1934,6 +2968,7 @@ impl<S: MutinyStorage> Node<S> {
         amount_sat: u64,
         fee_rate: Option<f32>,
         user_channel_id: Option<u128>,
+        announce: bool,
     ) -> Result<u128, MutinyError> {
         log_trace!(self.logger, "calling init_open_channel");
 
@@ -1941,7 +2976,7 @@ impl<S: MutinyStorage> Node<S> {
             .lsp_client
             .as_ref()
             .is_some_and(|l| l.accept_underpaying_htlcs());
-        let config = default_user_config(accept_underpaying_htlcs);
+        let config = default_user_config(accept_underpaying_htlcs, announce);
 
         let user_channel_id = user_channel_id.unwrap_or_else(|| {
             // generate random user channel id
@@ -1976,6 +3011,13 @@ impl<S: MutinyStorage> Node<S> {
                     self.logger,
                     "SUCCESS: channel initiated with peer: {pubkey:?}"
                 );
+                let state = ChannelLifecycleState::Negotiating;
+                if let Err(e) = self
+                    .persister
+                    .persist_channel_lifecycle(user_channel_id, state)
+                {
+                    log_warn!(self.logger, "Could not persist channel lifecycle: {e}");
+                }
                 Ok(user_channel_id)
             }
             Err(e) => {
@@ -1998,12 +3040,15 @@ impl<S: MutinyStorage> Node<S> {
         amount_sat: u64,
         fee_rate: Option<f32>,
         user_channel_id: Option<u128>,
+        announce: bool,
         timeout: u64,
     ) -> Result<OutPoint, MutinyError> {
         log_trace!(self.logger, "calling open_channel_with_timeout");
 
+        // one open at a time per peer so retries don't end up as extra channels
+        let _guard = self.connections.lock_channel_open(&pubkey).await;
         let init = self
-            .init_open_channel(pubkey, amount_sat, fee_rate, user_channel_id)
+            .init_open_channel(pubkey, amount_sat, fee_rate, user_channel_id, announce)
             .await?;
 
         let res = self.await_chan_funding_tx(init, &pubkey, timeout).await;
@@ -2017,6 +3062,7 @@ impl<S: MutinyStorage> Node<S> {
         user_chan_id: Option<u128>,
         utxos: &[OutPoint],
         pubkey: PublicKey,
+        announce: bool,
     ) -> Result<u128, MutinyError> {
         log_trace!(self.logger, "calling init_sweep_utxos_to_channel");
 
@@ -2053,7 +3099,7 @@ impl<S: MutinyStorage> Node<S> {
             .lsp_client
             .as_ref()
             .is_some_and(|l| l.accept_underpaying_htlcs());
-        let config = default_user_config(accept_underpaying_htlcs);
+        let config = default_user_config(accept_underpaying_htlcs, announce);
 
         let user_channel_id = user_chan_id.unwrap_or_else(|| {
             // generate random user channel id
@@ -2081,6 +3127,13 @@ impl<S: MutinyStorage> Node<S> {
                     self.logger,
                     "SUCCESS: channel initiated with peer: {pubkey:?}"
                 );
+                let state = ChannelLifecycleState::Negotiating;
+                if let Err(e) = self
+                    .persister
+                    .persist_channel_lifecycle(user_channel_id, state)
+                {
+                    log_warn!(self.logger, "Could not persist channel lifecycle: {e}");
+                }
                 Ok(user_channel_id)
             }
             Err(e) => {
@@ -2103,12 +3156,15 @@ impl<S: MutinyStorage> Node<S> {
         user_chan_id: Option<u128>,
         utxos: &[OutPoint],
         pubkey: PublicKey,
+        announce: bool,
         timeout: u64,
     ) -> Result<OutPoint, MutinyError> {
         log_trace!(self.logger, "calling sweep_utxos_to_channel_with_timeout");
 
+        // one open at a time per peer so retries don't end up as extra channels
+        let _guard = self.connections.lock_channel_open(&pubkey).await;
         let init = self
-            .init_sweep_utxos_to_channel(user_chan_id, utxos, pubkey)
+            .init_sweep_utxos_to_channel(user_chan_id, utxos, pubkey, announce)
             .await?;
 
         let res = self.await_chan_funding_tx(init, &pubkey, timeout).await;
@@ -2140,6 +3196,117 @@ pub(crate) fn decay_params() -> ProbabilisticScoringDecayParameters {
     }
 }
 
+/// The channels we can put in route hints that the [RouteHintConfig] allows, best first
+fn select_hint_channels(
+    channels: Vec<ChannelDetails>,
+    amount_msat: Option<u64>,
+    config: &RouteHintConfig,
+) -> Vec<ChannelDetails> {
+    let mut channels: Vec<ChannelDetails> = channels
+        .into_iter()
+        .filter(|c| {
+            c.is_channel_ready
+                && c.inbound_capacity_msat > 0
+                && c.counterparty.forwarding_info.is_some()
+        })
+        .collect();
+
+    // both of these fall back to all the channels so the invoice stays payable
+    if let Some(amount_msat) = amount_msat.filter(|_| config.require_sufficient_inbound) {
+        if channels
+            .iter()
+            .any(|c| c.inbound_capacity_msat >= amount_msat)
+        {
+            channels.retain(|c| c.inbound_capacity_msat >= amount_msat);
+        }
+    }
+    if config.prefer_scid_alias && channels.iter().any(|c| c.inbound_scid_alias.is_some()) {
+        channels.retain(|c| c.inbound_scid_alias.is_some());
+    }
+
+    if config.randomize {
+        channels.shuffle(&mut OsRng);
+    } else {
+        channels.sort_by_key(|c| Reverse(c.inbound_capacity_msat));
+    }
+    // channels with an online peer go first, the sort keeps the order otherwise
+    channels.sort_by_key(|c| !c.is_usable);
+
+    // one channel per peer, like LDK
+    let mut peers = HashSet::new();
+    channels.retain(|c| peers.insert(c.counterparty.node_id));
+    channels
+}
+
+/// The route hints for an invoice of a single node, there are none if we
+/// have a public channel because payers can find us in the network graph
+pub(crate) fn select_route_hints(
+    channels: Vec<ChannelDetails>,
+    amount_msat: Option<u64>,
+    config: &RouteHintConfig,
+) -> Vec<RouteHint> {
+    if channels.iter().any(|c| c.is_public && c.is_usable) {
+        return vec![];
+    }
+
+    select_hint_channels(channels, amount_msat, config)
+        .iter()
+        .filter_map(route_hint)
+        .take(config.max_hints)
+        .collect()
+}
+
+/// Narrows down the channels LDK picks the route hints of a phantom invoice from.
+/// The hints are split between the nodes, nodes without any allowed
+/// channels are left out if we require sufficient inbound liquidity.
+pub(crate) fn select_phantom_route_hints(
+    hints: Vec<PhantomRouteHints>,
+    amount_msat: Option<u64>,
+    config: &RouteHintConfig,
+) -> Vec<PhantomRouteHints> {
+    let mut hints: Vec<PhantomRouteHints> = hints
+        .into_iter()
+        .map(|mut h| {
+            h.channels = select_hint_channels(h.channels, amount_msat, config);
+            h
+        })
+        .collect();
+
+    if config.require_sufficient_inbound {
+        let can_receive = |h: &PhantomRouteHints| {
+            let amount_msat = amount_msat.unwrap_or(1);
+            h.channels
+                .iter()
+                .any(|c| c.inbound_capacity_msat >= amount_msat)
+        };
+        if hints.iter().any(can_receive) {
+            hints.retain(can_receive);
+        }
+    }
+
+    let per_node = (config.max_hints / hints.len().max(1)).max(1);
+    for h in hints.iter_mut() {
+        h.channels.truncate(per_node);
+    }
+    hints
+}
+
+fn route_hint(channel: &ChannelDetails) -> Option<RouteHint> {
+    let forwarding_info = channel.counterparty.forwarding_info.as_ref()?;
+    Some(RouteHint(vec![RouteHintHop {
+        src_node_id: channel.counterparty.node_id,
+        // the SCID alias if the channel has one
+        short_channel_id: channel.get_inbound_payment_scid()?,
+        fees: RoutingFees {
+            base_msat: forwarding_info.fee_base_msat,
+            proportional_millionths: forwarding_info.fee_proportional_millionths,
+        },
+        cltv_expiry_delta: forwarding_info.cltv_expiry_delta,
+        htlc_minimum_msat: channel.inbound_htlc_minimum_msat,
+        htlc_maximum_msat: channel.inbound_htlc_maximum_msat,
+    }]))
+}
+
 fn map_sending_failure(
     error: RetryableSendFailure,
     amt_msat: u64,
@@ -2187,10 +3354,12 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     node_pubkey: PublicKey,
     #[cfg(target_arch = "wasm32")] websocket_proxy_addr: String,
     peer_man: Arc<PeerManagerImpl<S>>,
+    connections: ConnectionRegistry,
     fee_estimator: Arc<MutinyFeeEstimator<S>>,
     logger: &Arc<MutinyLogger>,
     uuid: String,
     lsp_client: Option<&AnyLsp<S>>,
+    network_graph: Arc<NetworkGraph>,
     stop: Arc<AtomicBool>,
     stopped_components: Arc<RwLock<Vec<bool>>>,
     skip_fee_estimates: bool,
@@ -2238,6 +3407,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                 #[cfg(target_arch = "wasm32")]
                 &websocket_proxy_addr_copy_proxy,
                 &PubkeyConnectionInfo::new(connection_string.as_str()).unwrap(),
+                &connections,
                 &storage_copy,
                 proxy_logger.clone(),
                 peer_man_proxy.clone(),
@@ -2270,6 +3440,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                                     #[cfg(target_arch = "wasm32")]
                                     &websocket_proxy_addr_copy_proxy,
                                     &PubkeyConnectionInfo::new(connection_string.as_str()).unwrap(),
+                                    &connections,
                                     &storage_copy,
                                     proxy_logger.clone(),
                                     peer_man_proxy.clone(),
@@ -2330,6 +3501,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                 #[cfg(target_arch = "wasm32")]
                 &websocket_proxy_addr,
                 &peer_connection_info,
+                &connections,
                 &storage_copy,
                 proxy_logger.clone(),
                 peer_man_proxy.clone(),
@@ -2353,6 +3525,8 @@ async fn start_reconnection_handling<S: MutinyStorage>(
         // keep trying to connect each lightning peer if they get disconnected
         // hashMap to store backoff times for each pubkey
         let mut backoff_times = HashMap::new();
+        // discovered peers we failed to connect to, so we don't retry them
+        let mut discovery_failures = HashSet::new();
 
         // Only begin this process after 30s of running
         for _ in 0..30 {
@@ -2440,6 +3614,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                     #[cfg(target_arch = "wasm32")]
                     &websocket_proxy_addr,
                     &peer_connection_info,
+                    &connections,
                     &storage_copy,
                     proxy_logger.clone(),
                     peer_man_proxy.clone(),
@@ -2460,6 +3635,47 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                     }
                 }
             }
+
+            // top up our connections with well connected nodes from the network graph,
+            // these are only for gossip and aren't saved as peers
+            if !storage_copy.peer_discovery_enabled().unwrap_or(true) {
+                continue;
+            }
+            let connected: Vec<NodeId> = peer_man_proxy
+                .get_peer_node_ids()
+                .iter()
+                .map(|(c, _)| NodeId::from_pubkey(c))
+                .collect();
+            let discovered = discover_peers(&network_graph, &connected, &discovery_failures);
+            for peer_connection_info in discovered {
+                let pubkey = peer_connection_info.pubkey;
+                log_trace!(
+                    proxy_logger,
+                    "going to connect to discovered peer: {pubkey}"
+                );
+
+                let connect_res = connect_peer_if_necessary(
+                    #[cfg(target_arch = "wasm32")]
+                    &websocket_proxy_addr,
+                    &peer_connection_info,
+                    &connections,
+                    &storage_copy,
+                    proxy_logger.clone(),
+                    peer_man_proxy.clone(),
+                    proxy_fee_estimator.clone(),
+                    stop.clone(),
+                )
+                .await;
+                match connect_res {
+                    Ok(_) => {
+                        log_trace!(proxy_logger, "connected to discovered peer: {pubkey}");
+                    }
+                    Err(e) => {
+                        log_warn!(proxy_logger, "could not connect to discovered peer: {e}");
+                        discovery_failures.insert(NodeId::from_pubkey(&pubkey));
+                    }
+                }
+            }
         }
     });
 }
@@ -2519,7 +3735,10 @@ pub(crate) fn split_peer_connection_string(
     Ok((pubkey, peer_addr_str.to_string()))
 }
 
-pub(crate) fn default_user_config(accept_underpaying_htlcs: bool) -> UserConfig {
+pub(crate) fn default_user_config(
+    accept_underpaying_htlcs: bool,
+    announced_channel: bool,
+) -> UserConfig {
     UserConfig {
         channel_handshake_limits: ChannelHandshakeLimits {
             // lnd's max to_self_delay is 2016, so we want to be compatible.
@@ -2528,8 +3747,9 @@ pub(crate) fn default_user_config(accept_underpaying_htlcs: bool) -> UserConfig
         },
         channel_handshake_config: ChannelHandshakeConfig {
             minimum_depth: 1,
-            announced_channel: false,
-            negotiate_scid_privacy: true,
+            announced_channel,
+            // scid aliases are only used for private channels
+            negotiate_scid_privacy: !announced_channel,
             commit_upfront_shutdown_pubkey: false,
             negotiate_anchors_zero_fee_htlc_tx: true, // enable anchor channels
             max_inbound_htlc_value_in_flight_percent_of_channel: 100,
@@ -2555,17 +3775,51 @@ pub(crate) fn default_user_config(accept_underpaying_htlcs: bool) -> UserConfig
 #[cfg(not(target_arch = "wasm32"))]
 mod tests {
     use super::*;
+    use crate::federation::{persist_federation_activity_tag, FederationActivityKind};
     use crate::get_invoice_by_hash;
     use crate::node::{map_sending_failure, parse_peer_info};
     use crate::storage::MemoryStorage;
     use crate::test_utils::*;
     use bitcoin::secp256k1::PublicKey;
-    use lightning::ln::channelmanager::ChannelCounterparty;
+    use bitcoin::secp256k1::SecretKey;
+    use fedimint_core::config::FederationId;
+    use lightning::ln::channelmanager::{ChannelCounterparty, CounterpartyForwardingInfo};
     use lightning::ln::features::InitFeatures;
     use lightning::ln::ChannelId;
     use lightning_invoice::Bolt11InvoiceDescription;
     use std::str::FromStr;
 
+    #[test]
+    fn test_default_user_config_anchors() {
+        // anchors let us CPFP the commitment transaction when force closing
+        for announced in [true, false] {
+            let config = default_user_config(false, announced);
+            assert!(
+                config
+                    .channel_handshake_config
+                    .negotiate_anchors_zero_fee_htlc_tx
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_user_config_announced() {
+        let private = default_user_config(false, false).channel_handshake_config;
+        assert!(!private.announced_channel);
+        assert!(private.negotiate_scid_privacy);
+
+        // announced channels are known by their real scid, so no alias
+        let announced = default_user_config(false, true).channel_handshake_config;
+        assert!(announced.announced_channel);
+        assert!(!announced.negotiate_scid_privacy);
+
+        // only the handshake changes, not how we forward
+        assert_eq!(
+            default_user_config(false, false).channel_config,
+            default_user_config(false, true).channel_config
+        );
+    }
+
     #[test]
     fn test_parse_peer_info() {
         log!("test parse peer info");
@@ -2703,6 +3957,114 @@ mod tests {
         );
     }
 
+    fn hint_channel(peer: u8, inbound_capacity_msat: u64, alias: Option<u64>) -> ChannelDetails {
+        ChannelDetails {
+            channel_id: ChannelId::new_zero(),
+            counterparty: ChannelCounterparty {
+                node_id: SecretKey::from_slice(&[peer; 32])
+                    .unwrap()
+                    .public_key(&Secp256k1::new()),
+                features: InitFeatures::empty(),
+                unspendable_punishment_reserve: 0,
+                forwarding_info: Some(CounterpartyForwardingInfo {
+                    fee_base_msat: 1_000,
+                    fee_proportional_millionths: 100,
+                    cltv_expiry_delta: 144,
+                }),
+                outbound_htlc_minimum_msat: None,
+                outbound_htlc_maximum_msat: None,
+            },
+            funding_txo: None,
+            channel_type: None,
+            short_channel_id: Some(peer as u64),
+            outbound_scid_alias: None,
+            inbound_scid_alias: alias,
+            channel_value_satoshis: 1_000_000,
+            unspendable_punishment_reserve: None,
+            user_channel_id: peer as u128,
+            feerate_sat_per_1000_weight: None,
+            balance_msat: 0,
+            outbound_capacity_msat: 0,
+            next_outbound_htlc_limit_msat: 0,
+            next_outbound_htlc_minimum_msat: 0,
+            inbound_capacity_msat,
+            confirmations_required: None,
+            confirmations: None,
+            force_close_spend_delay: None,
+            is_outbound: false,
+            is_channel_ready: true,
+            channel_shutdown_state: None,
+            is_usable: true,
+            is_public: false,
+            inbound_htlc_minimum_msat: None,
+            inbound_htlc_maximum_msat: None,
+            config: None,
+        }
+    }
+
+    #[test]
+    fn test_select_route_hints() {
+        let channels = vec![
+            hint_channel(1, 10_000, None),
+            hint_channel(2, 500_000, None),
+            hint_channel(3, 100_000, Some(42)),
+        ];
+        let scids = |hints: Vec<RouteHint>| -> Vec<u64> {
+            hints.iter().map(|h| h.0[0].short_channel_id).collect()
+        };
+
+        // most inbound liquidity first
+        let config = RouteHintConfig::default();
+        let hints = select_route_hints(channels.clone(), None, &config);
+        assert_eq!(scids(hints), vec![2, 42, 1]);
+
+        let config = RouteHintConfig {
+            max_hints: 1,
+            ..Default::default()
+        };
+        let hints = select_route_hints(channels.clone(), None, &config);
+        assert_eq!(scids(hints), vec![2]);
+
+        // channels with an alias are preferred and the alias is used
+        let config = RouteHintConfig {
+            prefer_scid_alias: true,
+            ..Default::default()
+        };
+        let hints = select_route_hints(channels.clone(), None, &config);
+        assert_eq!(scids(hints), vec![42]);
+
+        // only channels that can receive the amount, unless none can
+        let config = RouteHintConfig {
+            require_sufficient_inbound: true,
+            ..Default::default()
+        };
+        let hints = select_route_hints(channels.clone(), Some(50_000), &config);
+        assert_eq!(scids(hints), vec![2, 42]);
+        let hints = select_route_hints(channels.clone(), Some(1_000_000), &config);
+        assert_eq!(scids(hints), vec![2, 42, 1]);
+
+        let config = RouteHintConfig::private();
+        let hints = select_route_hints(channels.clone(), Some(50_000), &config);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(scids(hints), vec![42]);
+
+        // one hint per peer
+        let hints = select_route_hints(
+            vec![hint_channel(1, 10_000, None), hint_channel(1, 20_000, None)],
+            None,
+            &RouteHintConfig::default(),
+        );
+        assert_eq!(hints.len(), 1);
+
+        // no hints with a public channel
+        let mut public = hint_channel(4, 10_000, None);
+        public.is_public = true;
+        let mut with_public = channels.clone();
+        with_public.push(public);
+        let hints = select_route_hints(with_public, None, &RouteHintConfig::default());
+        assert!(hints.is_empty());
+    }
+
     #[tokio::test]
     async fn test_create_node() {
         let storage = MemoryStorage::default();
@@ -2721,7 +4083,7 @@ mod tests {
         let amount_sats = 1_000;
 
         let (invoice, _) = node
-            .create_invoice(amount_sats, None, vec![])
+            .create_invoice(amount_sats, None, vec![], &InvoiceParams::default())
             .await
             .unwrap();
 
@@ -2749,15 +4111,89 @@ mod tests {
         assert!(from_storage.last_updated >= now);
     }
 
+    #[tokio::test]
+    async fn test_create_invoice_with_params() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage).await;
+
+        let params = InvoiceParams {
+            expiry_secs: Some(600),
+            description: Some("coffee".to_string()),
+            min_final_cltv: Some(144),
+            ..Default::default()
+        };
+        let (invoice, _) = node
+            .create_invoice(1_000, None, vec![], &params)
+            .await
+            .unwrap();
+
+        assert_eq!(invoice.expiry_time().as_secs(), 600);
+        assert_eq!(invoice.min_final_cltv_expiry_delta(), 144);
+        match invoice.description() {
+            Bolt11InvoiceDescription::Direct(desc) => assert_eq!(desc.to_string(), "coffee"),
+            _ => panic!("unexpected invoice description"),
+        }
+
+        let hash = bitcoin::hashes::sha256::Hash::hash(b"coffee");
+        let params = InvoiceParams {
+            description_hash: Some(hash),
+            ..Default::default()
+        };
+        let (invoice, _) = node
+            .create_invoice(1_000, None, vec![], &params)
+            .await
+            .unwrap();
+        match invoice.description() {
+            Bolt11InvoiceDescription::Hash(h) => assert_eq!(h.0, hash),
+            _ => panic!("unexpected invoice description"),
+        }
+
+        // our own route hint selection, there are no channels to put in the hints
+        let params = InvoiceParams {
+            description: Some("coffee".to_string()),
+            min_final_cltv: Some(144),
+            route_hints: Some(RouteHintConfig::private()),
+            ..Default::default()
+        };
+        let (invoice, _) = node
+            .create_invoice(1_000, None, vec![], &params)
+            .await
+            .unwrap();
+        assert_eq!(invoice.amount_milli_satoshis(), Some(1_000_000));
+        assert_eq!(invoice.min_final_cltv_expiry_delta(), 144);
+        assert_eq!(invoice.recover_payee_pub_key(), node.pubkey);
+        assert!(invoice.route_hints().is_empty());
+        match invoice.description() {
+            Bolt11InvoiceDescription::Direct(desc) => assert_eq!(desc.to_string(), "coffee"),
+            _ => panic!("unexpected invoice description"),
+        }
+
+        // a description and its hash can't both be set
+        let params = InvoiceParams {
+            description: Some("coffee".to_string()),
+            description_hash: Some(hash),
+            ..Default::default()
+        };
+        assert!(node
+            .create_invoice(1_000, None, vec![], &params)
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_fail_own_invoice() {
         let storage = MemoryStorage::default();
         let node = create_node(storage).await;
 
-        let invoice = node.create_invoice(10_000, None, vec![]).await.unwrap().0;
+        let invoice = node
+            .create_invoice(10_000, None, vec![], &InvoiceParams::default())
+            .await
+            .unwrap()
+            .0;
 
+        let mut trace = PaymentTrace::new(invoice.payment_hash());
         let result = node
-            .pay_invoice_with_timeout(&invoice, None, None, vec![])
+            .pay_invoice_with_timeout(&invoice, None, None, vec![], None, &mut trace)
             .await;
 
         match result {
@@ -2767,6 +4203,60 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_abandon_payment() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage.clone()).await;
+
+        let hash = Sha256::hash(b"abandon");
+        assert_eq!(node.abandon_payment(&hash), Err(MutinyError::NotFound));
+
+        // a payment that was in flight when we shut down
+        let mut info = PaymentInfo {
+            preimage: None,
+            secret: None,
+            status: HTLCStatus::InFlight,
+            amt_msat: MillisatAmount(Some(10_000)),
+            fee_paid_msat: None,
+            bolt11: None,
+            payee_pubkey: None,
+            privacy_level: PrivacyLevel::NotAvailable,
+            last_update: 0,
+            custom_tlvs: vec![],
+        };
+        persist_payment_info(&storage, &hash.into_32(), &info, false).unwrap();
+        assert_eq!(node.abandon_payment(&hash), Err(MutinyError::NotFound));
+        node.fail_forgotten_payment(&hash).unwrap();
+        let abandoned = read_payment_info(&storage, &hash.into_32(), false, &node.logger).unwrap();
+        assert_eq!(abandoned.status, HTLCStatus::Failed);
+
+        // completed payments can't be abandoned
+        info.status = HTLCStatus::Succeeded;
+        persist_payment_info(&storage, &hash.into_32(), &info, false).unwrap();
+        assert_eq!(
+            node.fail_forgotten_payment(&hash),
+            Err(MutinyError::InvalidArgumentsError)
+        );
+
+        // neither can payments made by a federation
+        let fed_hash = Sha256::hash(b"federation");
+        info.status = HTLCStatus::InFlight;
+        persist_payment_info(&storage, &fed_hash.into_32(), &info, false).unwrap();
+        persist_federation_activity_tag(
+            &storage,
+            &payment_key(false, &fed_hash.into_32()),
+            FederationId::dummy(),
+            FederationActivityKind::Lightning,
+        )
+        .unwrap();
+        assert_eq!(
+            node.fail_forgotten_payment(&fed_hash),
+            Err(MutinyError::InvalidArgumentsError)
+        );
+        let payment = read_payment_info(&storage, &fed_hash.into_32(), false, &node.logger);
+        assert_eq!(payment.unwrap().status, HTLCStatus::InFlight);
+    }
+
     #[tokio::test]
     async fn test_await_payment() {
         let storage = MemoryStorage::default();
@@ -2792,6 +4282,7 @@ mod tests {
             bolt11: None,
             payee_pubkey: None,
             last_update: crate::utils::now().as_secs(),
+            custom_tlvs: vec![],
         };
 
         // check that it still fails if it is inflight
@@ -2857,7 +4348,7 @@ mod wasm_test {
         storage::get_invoice_by_hash,
     };
     use crate::{labels::LabelStorage, logging::MutinyLogger};
-    use crate::{HTLCStatus, PrivacyLevel};
+    use crate::{HTLCStatus, InvoiceParams, PrivacyLevel};
     use itertools::Itertools;
     use lightning::ln::channelmanager::PaymentId;
     use lightning::ln::PaymentHash;
@@ -2887,7 +4378,7 @@ mod wasm_test {
         let labels = vec![label.clone()];
 
         let (invoice, _) = node
-            .create_invoice(amount_sats, None, labels.clone())
+            .create_invoice(amount_sats, None, labels.clone(), &InvoiceParams::default())
             .await
             .unwrap();
 
@@ -2933,10 +4424,15 @@ mod wasm_test {
         let storage = MemoryStorage::default();
         let node = create_node(storage).await;
 
-        let invoice = node.create_invoice(10_000, None, vec![]).await.unwrap().0;
+        let invoice = node
+            .create_invoice(10_000, None, vec![], &InvoiceParams::default())
+            .await
+            .unwrap()
+            .0;
 
+        let mut trace = PaymentTrace::new(invoice.payment_hash());
         let result = node
-            .pay_invoice_with_timeout(&invoice, None, None, vec![])
+            .pay_invoice_with_timeout(&invoice, None, None, vec![], None, &mut trace)
             .await;
 
         match result {
@@ -2971,6 +4467,7 @@ mod wasm_test {
             bolt11: None,
             payee_pubkey: None,
             last_update: crate::utils::now().as_secs(),
+            custom_tlvs: vec![],
         };
 
         // check that it still fails if it is inflight
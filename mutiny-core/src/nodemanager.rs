@@ -1,22 +1,30 @@
+use crate::idempotency::{with_idempotency, PendingPayment};
+use crate::keymanager::{create_keys_manager, pubkey_from_keys_manager};
 use crate::labels::LabelStorage;
-use crate::ldkstorage::CHANNEL_CLOSURE_PREFIX;
+use crate::latency::PaymentTrace;
+use crate::ldkstorage::{MutinyNodePersister, CHANNEL_CLOSURE_PREFIX};
 use crate::logging::LOGGING_KEY;
 use crate::lsp::voltage;
+use crate::peermanager::ConnectionRegistry;
+use crate::performance::{Operation, PerformanceTracker};
+use crate::policy::SpendingPolicyManager;
+use crate::scb::StaticChannelBackup;
 use crate::utils::{sleep, spawn};
-use crate::MutinyInvoice;
 use crate::MutinyWalletConfig;
 use crate::{auth::MutinyAuthClient, TransactionDetails};
 use crate::{
-    chain::MutinyChain,
-    error::MutinyError,
+    chain::{handle_reorgs, MutinyChain},
+    error::{MutinyError, MutinyStorageError},
     fees::MutinyFeeEstimator,
     gossip,
-    gossip::{fetch_updated_gossip, get_rgs_url},
+    gossip::{fetch_updated_gossip, get_rgs_url, gossip_sync_time_key, network_graph_key},
     logging::MutinyLogger,
     lsp::{deserialize_lsp_config, Lsp, LspConfig},
-    node::{Node, PubkeyConnectionInfo, RapidGossipSync},
+    node::{
+        parse_peer_info, scoring_params, ChainMonitor, Node, PubkeyConnectionInfo, RapidGossipSync,
+    },
     onchain::get_esplora_url,
-    onchain::OnChainWallet,
+    onchain::{CollaborativeSpend, ExternalSweep, OnChainWallet, PendingBroadcast},
     utils,
 };
 use crate::{gossip::*, scorer::HubPreferentialScorer};
@@ -24,6 +32,7 @@ use crate::{
     node::NodeBuilder,
     storage::{MutinyStorage, DEVICE_ID_KEY, KEYCHAIN_STORE_KEY, NEED_FULL_SYNC_KEY},
 };
+use crate::{InvoiceParams, MutinyInvoice, RouteHintConfig};
 use anyhow::anyhow;
 use async_lock::RwLock;
 use bdk::chain::{BlockId, ConfirmationTime};
@@ -32,20 +41,25 @@ use bitcoin::address::NetworkUnchecked;
 use bitcoin::bip32::ExtendedPrivKey;
 use bitcoin::blockdata::script;
 use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::sha256;
 use bitcoin::psbt::PartiallySignedTransaction;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
 use esplora_client::{AsyncClient, Builder};
 use futures::future::join_all;
 use hex_conservative::DisplayHex;
+use lightning::chain::channelmonitor::Balance;
 use lightning::chain::Confirm;
 use lightning::events::ClosureReason;
 use lightning::ln::channelmanager::{ChannelDetails, PhantomRouteHints};
 use lightning::ln::script::ShutdownScript;
 use lightning::ln::ChannelId;
 use lightning::routing::gossip::NodeId;
-use lightning::sign::{NodeSigner, Recipient};
+use lightning::routing::router::{DefaultRouter, RouteHint};
+use lightning::sign::{EntropySource, NodeSigner, Recipient};
+use lightning::util::config::ChannelConfig;
 use lightning::util::logger::*;
+use lightning::util::ser::Writeable;
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
 use lightning_invoice::Bolt11Invoice;
 use lightning_transaction_sync::EsploraSyncClient;
@@ -64,6 +78,13 @@ use url::Url;
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
+/// How much longer we wait between syncs while the app is in the background
+const BACKGROUND_SYNC_MULTIPLIER: u64 = 10;
+/// How often we re-broadcast our node announcement if we have announced channels
+const NODE_ANNOUNCEMENT_INTERVAL_SECS: u64 = 60 * 60;
+/// How often we download the latest remote scores
+const SCORER_SYNC_INTERVAL_SECS: u64 = 6 * 60 * 60;
+
 // This is the NodeStorage object saved to the DB
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct NodeStorage {
@@ -87,6 +108,15 @@ impl NodeIndex {
     }
 }
 
+/// A node that failed to start, it is skipped until the wallet is started again
+/// so the rest of the wallet can keep working.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct FailedNode {
+    pub uuid: String,
+    pub child_index: u32,
+    pub error: String,
+}
+
 // This is the NodeIdentity that refer to a specific node
 // Used for public facing identification.
 pub struct NodeIdentity {
@@ -108,6 +138,8 @@ pub struct MutinyPeer {
     pub connection_string: Option<String>,
     pub alias: Option<String>,
     pub color: Option<String>,
+    /// The addresses the node announced, for showing where the peer is
+    pub addresses: Vec<String>,
     pub label: Option<String>,
     pub is_connected: bool,
 }
@@ -177,6 +209,84 @@ impl From<&ChannelDetails> for MutinyChannel {
     }
 }
 
+/// Liquidity of a single channel, see [NodeManager::get_liquidity]
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ChannelLiquidity {
+    pub user_chan_id: String,
+    pub outpoint: Option<OutPoint>,
+    pub peer: PublicKey,
+    pub size: u64,
+    /// Amount we can send over the channel right now, after reserves and pending HTLCs
+    pub outbound_capacity_msat: u64,
+    /// Largest HTLC we can send over the channel right now
+    pub max_send_htlc_msat: u64,
+    /// Amount our peer can send us over the channel right now
+    pub inbound_capacity_msat: u64,
+    /// Largest HTLC our peer can send us over the channel right now
+    pub max_receive_htlc_msat: u64,
+    /// Amount of our own payments currently in flight over the channel
+    pub pending_outbound_htlc_msat: u64,
+    /// Reserve we have to keep in the channel, in sats
+    pub reserve: u64,
+    /// Reserve our peer has to keep in the channel, in sats
+    pub counterparty_reserve: u64,
+    pub is_usable: bool,
+}
+
+impl ChannelLiquidity {
+    fn new(c: &ChannelDetails, pending_outbound_htlc_msat: u64) -> Self {
+        let inbound = c.inbound_capacity_msat;
+        let max_receive_htlc_msat = c
+            .inbound_htlc_maximum_msat
+            .map_or(inbound, |max| max.min(inbound));
+
+        Self {
+            user_chan_id: c.user_channel_id.to_be_bytes().to_lower_hex_string(),
+            outpoint: c.funding_txo.map(|f| f.into_bitcoin_outpoint()),
+            peer: c.counterparty.node_id,
+            size: c.channel_value_satoshis,
+            outbound_capacity_msat: c.outbound_capacity_msat,
+            max_send_htlc_msat: c.next_outbound_htlc_limit_msat,
+            inbound_capacity_msat: inbound,
+            max_receive_htlc_msat,
+            pending_outbound_htlc_msat,
+            reserve: c.unspendable_punishment_reserve.unwrap_or(0),
+            counterparty_reserve: c.counterparty.unspendable_punishment_reserve,
+            is_usable: c.is_usable,
+        }
+    }
+}
+
+/// Liquidity across all of our channels, see [NodeManager::get_liquidity]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Liquidity {
+    pub channels: Vec<ChannelLiquidity>,
+    /// Largest payment we can send right now, split over all usable channels
+    pub max_sendable_msat: u64,
+    /// Largest payment we can receive right now without opening a new channel,
+    /// split over all usable channels
+    pub max_receivable_msat: u64,
+    /// Amount of our own payments currently in flight
+    pub pending_outbound_htlc_msat: u64,
+}
+
+impl Liquidity {
+    pub(crate) fn new(channels: Vec<ChannelLiquidity>) -> Self {
+        let usable = channels.iter().filter(|c| c.is_usable);
+        let max_sendable_msat = usable.clone().map(|c| c.max_send_htlc_msat).sum();
+        let max_receivable_msat = usable.map(|c| c.max_receive_htlc_msat).sum();
+        let pending_outbound_htlc_msat =
+            channels.iter().map(|c| c.pending_outbound_htlc_msat).sum();
+
+        Self {
+            channels,
+            max_sendable_msat,
+            max_receivable_msat,
+            pending_outbound_htlc_msat,
+        }
+    }
+}
+
 /// Information about a channel that was closed.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ChannelClosure {
@@ -232,6 +342,213 @@ impl Ord for ChannelClosure {
     }
 }
 
+/// Where a channel is in its life, see [NodeManager::get_channel_lifecycle]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ChannelLifecycleState {
+    /// The channel was requested and we are waiting on the peer to accept it
+    Negotiating,
+    /// The funding transaction was created, it is broadcast once the peer signs
+    FundingBroadcast { funding_txid: Txid },
+    /// The funding transaction was broadcast and is waiting to confirm
+    WaitingConfirmations {
+        funding_txid: Txid,
+        confirmations: u32,
+        confirmations_required: Option<u32>,
+    },
+    /// The channel can be used for payments
+    Ready,
+    /// We started closing the channel
+    Closing,
+    /// The channel closed, or failed to open
+    Closed { reason: String },
+}
+
+/// The lifecycle state of a channel and when it last changed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelLifecycle {
+    pub user_chan_id: String,
+    #[serde(flatten)]
+    pub state: ChannelLifecycleState,
+    /// Unix timestamp of the last state change
+    pub updated_at: u64,
+}
+
+impl ChannelLifecycle {
+    pub(crate) fn new(user_channel_id: u128, state: ChannelLifecycleState) -> Self {
+        Self {
+            user_chan_id: user_channel_id.to_be_bytes().to_lower_hex_string(),
+            state,
+            updated_at: utils::now().as_secs(),
+        }
+    }
+}
+
+/// An outbound channel that has been pending for too long, see [NodeManager::list_stuck_channels]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StuckChannel {
+    pub user_chan_id: String,
+    pub peer: PublicKey,
+    pub size: u64,
+    pub funding_txid: Option<Txid>,
+    pub confirmations: u32,
+    /// Unix timestamp of the channel's last state change, if we saved it
+    pub pending_since: Option<u64>,
+    pub peer_connected: bool,
+}
+
+/// An output of a closed channel we failed to sweep to our wallet,
+/// see [NodeManager::list_unswept_outputs]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UnsweptOutput {
+    pub outpoint: OutPoint,
+    pub amount_sats: u64,
+    /// How many times we tried to sweep it, the fee rate goes up with every attempt
+    pub attempts: u32,
+    /// The last sweep that was broadcast, the output is kept until it confirms
+    pub sweep_txid: Option<Txid>,
+}
+
+/// What kind of claim a [ForceCloseClaim] is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForceCloseClaimType {
+    /// Our balance of a channel whose closing transaction hasn't confirmed yet
+    AwaitingClose,
+    /// Our output of the closing transaction, spendable once its timelock passed
+    AwaitingConfirmations,
+    /// An HTLC we have the preimage of, we need to claim it before it times out
+    ContentiousHtlc,
+    /// An HTLC we sent that we get back once it times out
+    OutboundHtlc,
+    /// An HTLC we received, we only get it if we learn the preimage before it times out
+    InboundHtlc,
+    /// An output of a revoked commitment transaction the peer broadcast
+    RevokedOutput,
+}
+
+/// Funds of a closed channel that are still being claimed on-chain,
+/// see [NodeManager::get_force_close_details]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ForceCloseClaim {
+    /// Funding outpoint of the channel
+    pub channel: OutPoint,
+    pub peer: Option<PublicKey>,
+    pub claim_type: ForceCloseClaimType,
+    pub amount_sats: u64,
+    /// Blocks until the funds can be swept to our wallet, if we know when
+    pub confirmations_remaining: Option<u32>,
+    /// Unix timestamp of when we expect to sweep the funds, assuming 10 minute blocks
+    pub expected_sweep_time: Option<u64>,
+}
+
+impl ForceCloseClaim {
+    pub(crate) fn new(
+        channel: OutPoint,
+        peer: Option<PublicKey>,
+        balance: &Balance,
+        height: u32,
+        now: u64,
+    ) -> Self {
+        let claim_type = match balance {
+            Balance::ClaimableOnChannelClose { .. } => ForceCloseClaimType::AwaitingClose,
+            Balance::ClaimableAwaitingConfirmations { .. } => {
+                ForceCloseClaimType::AwaitingConfirmations
+            }
+            Balance::ContentiousClaimable { .. } => ForceCloseClaimType::ContentiousHtlc,
+            Balance::MaybeTimeoutClaimableHTLC { .. } => ForceCloseClaimType::OutboundHtlc,
+            Balance::MaybePreimageClaimableHTLC { .. } => ForceCloseClaimType::InboundHtlc,
+            Balance::CounterpartyRevokedOutputClaimable { .. } => {
+                ForceCloseClaimType::RevokedOutput
+            }
+        };
+        // the other claims are spendable now or depend on the peer
+        let unlock_height = match balance {
+            Balance::ClaimableAwaitingConfirmations {
+                confirmation_height,
+                ..
+            } => Some(*confirmation_height),
+            Balance::MaybeTimeoutClaimableHTLC {
+                claimable_height, ..
+            } => Some(*claimable_height),
+            _ => None,
+        };
+        let confirmations_remaining = unlock_height.map(|h| h.saturating_sub(height));
+
+        Self {
+            channel,
+            peer,
+            claim_type,
+            amount_sats: balance.claimable_amount_satoshis(),
+            confirmations_remaining,
+            expected_sweep_time: confirmations_remaining.map(|c| now + c as u64 * 10 * 60),
+        }
+    }
+}
+
+/// Expected cost of paying a lightning invoice, see [NodeManager::estimate_ln_fee]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LnFeeEstimate {
+    /// Fee of the route we would take
+    pub fee_msat: u64,
+    /// Fee if the first route fails and the payment is retried over other channels
+    pub max_fee_msat: u64,
+    /// Chance of the payment succeeding on the first route, from our history of the network.
+    /// None if we don't know anything about the channels on the route yet.
+    pub success_probability: Option<f64>,
+    /// Number of probes sent along the route to improve the estimate for the actual payment
+    pub probes_sent: usize,
+}
+
+/// Overrides for how a lightning payment is routed,
+/// for power users that want to control path construction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PaymentParametersOverride {
+    /// Only send the payment out over this channel
+    pub first_hop_channel_id: Option<ChannelId>,
+    /// Maximum total routing fee, across all parts and retries
+    pub max_fee_msat: Option<u64>,
+    /// Route hints to use in addition to the ones in the invoice
+    pub route_hints: Vec<RouteHint>,
+}
+
+/// Forwarding policy of a channel, fields that aren't set keep our defaults.
+///
+/// The HTLC limits the peer has to follow are negotiated when the channel is opened,
+/// so ours are checked when a payment comes in over the channel and it is failed back
+/// if it is outside of them. Forwards can't be checked, LDK forwards them on its own.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelPolicy {
+    pub forwarding_fee_base_msat: Option<u32>,
+    pub forwarding_fee_proportional_millionths: Option<u32>,
+    pub cltv_expiry_delta: Option<u16>,
+    pub htlc_minimum_msat: Option<u64>,
+    pub htlc_maximum_msat: Option<u64>,
+}
+
+impl ChannelPolicy {
+    pub(crate) fn apply(&self, config: &mut ChannelConfig) {
+        if let Some(base) = self.forwarding_fee_base_msat {
+            config.forwarding_fee_base_msat = base;
+        }
+        if let Some(proportional) = self.forwarding_fee_proportional_millionths {
+            config.forwarding_fee_proportional_millionths = proportional;
+        }
+        if let Some(delta) = self.cltv_expiry_delta {
+            config.cltv_expiry_delta = delta;
+        }
+    }
+
+    /// If a payment of the amount can be received over the channel
+    pub(crate) fn allows_htlc(&self, amount_msat: u64) -> bool {
+        self.htlc_minimum_msat
+            .map_or(true, |min| amount_msat >= min)
+            && self
+                .htlc_maximum_msat
+                .map_or(true, |max| amount_msat <= max)
+    }
+}
+
 pub struct NodeBalance {
     pub confirmed: u64,
     pub unconfirmed: u64,
@@ -246,6 +563,7 @@ pub struct NodeManagerBuilder<S: MutinyStorage> {
     config: Option<MutinyWalletConfig>,
     stop: Option<Arc<AtomicBool>>,
     logger: Option<Arc<MutinyLogger>>,
+    performance: Option<Arc<PerformanceTracker>>,
 }
 
 impl<S: MutinyStorage> NodeManagerBuilder<S> {
@@ -257,6 +575,7 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             config: None,
             stop: None,
             logger: None,
+            performance: None,
         }
     }
 
@@ -277,6 +596,10 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
         self.logger = Some(logger);
     }
 
+    pub fn with_performance(&mut self, performance: Arc<PerformanceTracker>) {
+        self.performance = Some(performance);
+    }
+
     /// Creates a new [NodeManager] with the given parameters.
     /// The mnemonic seed is read from storage, unless one is provided.
     /// If no mnemonic is provided, a new one is generated and stored.
@@ -311,23 +634,36 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
         log_trace!(logger, "finished creating tx sync client");
 
         log_trace!(logger, "creating fee estimator");
-        let fee_estimator = Arc::new(MutinyFeeEstimator::new(
-            self.storage.clone(),
-            esplora.clone(),
-            logger.clone(),
-        ));
+        let fee_estimator = Arc::new(
+            match c.fee_provider.clone() {
+                Some(provider) => MutinyFeeEstimator::new_with_provider(
+                    self.storage.clone(),
+                    provider,
+                    logger.clone(),
+                ),
+                None => {
+                    MutinyFeeEstimator::new(self.storage.clone(), esplora.clone(), logger.clone())
+                }
+            }
+            .with_config(c.fee_estimator_config.unwrap_or_default()),
+        );
         log_trace!(logger, "finished creating fee estimator");
 
         log_trace!(logger, "creating on chain wallet");
-        let wallet = Arc::new(OnChainWallet::new(
+        let mut wallet = OnChainWallet::new(
             self.xprivkey,
             self.storage.clone(),
             c.network,
+            c.account_index,
             esplora.clone(),
             fee_estimator.clone(),
             stop.clone(),
             logger.clone(),
-        )?);
+        )?;
+        if let Some(gap_limit) = c.gap_limit {
+            wallet = wallet.with_gap_limit(gap_limit);
+        }
+        let wallet = Arc::new(wallet);
         log_trace!(logger, "finished creating on chain wallet");
 
         log_trace!(logger, "creating chain");
@@ -371,102 +707,10 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             start.elapsed().as_millis()
         );
 
-        let has_done_initial_ldk_sync = Arc::new(AtomicBool::new(false));
-
-        let nodes = if c.safe_mode {
-            // If safe mode is enabled, we don't start any nodes
-            log_warn!(logger, "Safe mode enabled, not starting any nodes");
-            Arc::new(RwLock::new(HashMap::new()))
-        } else {
-            log_trace!(logger, "going through nodes");
-
-            // Remove the archived nodes, we don't need to start them up.
-            let unarchived_nodes = node_storage
-                .clone()
-                .nodes
-                .into_iter()
-                .filter(|(_, n)| !n.is_archived());
-
-            let start = Instant::now();
-            log_debug!(logger, "Building nodes");
-
-            let mut nodes_map = HashMap::new();
-
-            for node_item in unarchived_nodes {
-                let mut node_builder = NodeBuilder::new(self.xprivkey, self.storage.clone())
-                    .with_uuid(node_item.0)
-                    .with_node_index(node_item.1)
-                    .with_gossip_sync(gossip_sync.clone())
-                    .with_scorer(scorer.clone())
-                    .with_chain(chain.clone())
-                    .with_fee_estimator(fee_estimator.clone())
-                    .with_wallet(wallet.clone())
-                    .with_esplora(esplora.clone())
-                    .with_initial_sync(has_done_initial_ldk_sync.clone())
-                    .with_network(c.network);
-                node_builder.with_logger(logger.clone());
-
-                #[cfg(target_arch = "wasm32")]
-                node_builder.with_websocket_proxy_addr(websocket_proxy_addr.clone());
-
-                if let Some(l) = lsp_config.clone() {
-                    node_builder.with_lsp_config(l);
-                }
-                if c.do_not_connect_peers {
-                    node_builder.do_not_connect_peers();
-                }
-
-                let node = node_builder.build().await?;
-
-                let id = node
-                    .keys_manager
-                    .get_node_id(Recipient::Node)
-                    .expect("Failed to get node id");
-
-                nodes_map.insert(id, Arc::new(node));
-            }
-            log_trace!(
-                logger,
-                "Nodes built: took {}ms",
-                start.elapsed().as_millis()
-            );
-
-            // when we create the nodes we set the LSP if one is missing
-            // we need to save it to local storage after startup in case
-            // a LSP was set.
-            let mut updated_nodes: HashMap<String, NodeIndex> =
-                HashMap::with_capacity(nodes_map.len());
-            for n in nodes_map.values() {
-                updated_nodes.insert(n.uuid.clone(), n.node_index().await);
-            }
-
-            // insert updated nodes in background, isn't a huge deal if this fails,
-            // it is only for updating the LSP config
-            log_info!(logger, "inserting updated nodes");
-            let version = node_storage.version + 1;
-            let storage = self.storage.clone();
-            let logger_clone = logger.clone();
-            spawn(async move {
-                let start = Instant::now();
-                if let Err(e) = storage
-                    .insert_nodes(&NodeStorage {
-                        nodes: updated_nodes,
-                        version,
-                    })
-                    .await
-                {
-                    log_error!(logger_clone, "Failed to insert updated nodes: {e}");
-                } else {
-                    log_info!(
-                        logger_clone,
-                        "inserted updated nodes, took {}ms",
-                        start.elapsed().as_millis()
-                    );
-                }
-            });
-
-            Arc::new(RwLock::new(nodes_map))
-        };
+        let spending_policy = Arc::new(SpendingPolicyManager::new(
+            self.storage.clone(),
+            logger.clone(),
+        ));
 
         let nm = NodeManager {
             stop,
@@ -477,9 +721,12 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             scorer,
             chain,
             fee_estimator,
+            spending_policy,
+            performance: self.performance.unwrap_or_default(),
             storage: self.storage,
             node_storage: RwLock::new(node_storage),
-            nodes,
+            nodes: Arc::new(RwLock::new(HashMap::new())),
+            failed_nodes: RwLock::new(vec![]),
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
             user_rgs_url: c.user_rgs_url,
@@ -489,10 +736,24 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             lsp_config,
             logger,
             do_not_connect_peers: c.do_not_connect_peers,
+            announce_channels: c.announce_channels,
+            route_hint_config: c.route_hint_config,
             safe_mode: c.safe_mode,
-            has_done_initial_ldk_sync,
+            disable_gossip: c.disable_gossip,
+            has_done_initial_ldk_sync: Arc::new(AtomicBool::new(false)),
+            background: Arc::new(AtomicBool::new(false)),
+            connections: ConnectionRegistry::default(),
         };
 
+        if c.safe_mode {
+            // If safe mode is enabled, we don't start any nodes
+            log_warn!(nm.logger, "Safe mode enabled, not starting any nodes");
+        } else if c.fast_start {
+            log_info!(nm.logger, "Fast start enabled, nodes will be started later");
+        } else {
+            nm.start_nodes().await?;
+        }
+
         Ok(nm)
     }
 }
@@ -519,15 +780,31 @@ pub struct NodeManager<S: MutinyStorage> {
     scorer: Arc<utils::Mutex<HubPreferentialScorer>>,
     chain: Arc<MutinyChain<S>>,
     fee_estimator: Arc<MutinyFeeEstimator<S>>,
+    pub(crate) spending_policy: Arc<SpendingPolicyManager<S>>,
+    /// Timings of slow operations, see [crate::MutinyWallet::get_performance_report]
+    pub(crate) performance: Arc<PerformanceTracker>,
     pub(crate) storage: S,
     pub(crate) node_storage: RwLock<NodeStorage>,
     pub(crate) nodes: Arc<RwLock<HashMap<PublicKey, Arc<Node<S>>>>>,
+    /// Nodes that failed to start, see [NodeManager::list_failed_nodes]
+    pub(crate) failed_nodes: RwLock<Vec<FailedNode>>,
     pub(crate) lsp_config: Option<LspConfig>,
     pub(crate) logger: Arc<MutinyLogger>,
     do_not_connect_peers: bool,
+    /// Open announced channels unless specified otherwise when opening
+    announce_channels: bool,
+    /// How the route hints of our invoices are picked, unless the invoice sets it
+    route_hint_config: Option<RouteHintConfig>,
     pub safe_mode: bool,
+    /// Skip syncing the network graph and scorer, see
+    /// [crate::MutinyWalletConfigBuilder::with_gossip_disabled]
+    disable_gossip: bool,
     /// If we've completed an initial sync this instance
     pub(crate) has_done_initial_ldk_sync: Arc<AtomicBool>,
+    /// If the app is in the background, we sync less often and skip gossip syncing
+    pub(crate) background: Arc<AtomicBool>,
+    /// Peer connections and channel opens shared by all of our nodes
+    connections: ConnectionRegistry,
 }
 
 impl<S: MutinyStorage> NodeManager<S> {
@@ -537,6 +814,139 @@ impl<S: MutinyStorage> NodeManager<S> {
         storage.get_mnemonic().is_ok_and(|x| x.is_some())
     }
 
+    /// Starts all of the nodes that aren't archived, concurrently.
+    /// This is done while building, unless fast start is enabled.
+    ///
+    /// A node without channel monitors that fails to start doesn't stop the others from
+    /// starting, it is quarantined and can be found with [NodeManager::list_failed_nodes].
+    /// If it has channel monitors we fail to start instead, nothing would watch its channels
+    /// on-chain to respond to a breach or claim timed out HTLCs.
+    pub(crate) async fn start_nodes(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling start_nodes");
+
+        let node_storage = self.node_storage.read().await.clone();
+
+        // Remove the archived nodes, we don't need to start them up.
+        let unarchived_nodes = node_storage
+            .clone()
+            .nodes
+            .into_iter()
+            .filter(|(_, n)| !n.is_archived());
+
+        let start = Instant::now();
+        log_debug!(self.logger, "Building nodes");
+
+        let node_futures = unarchived_nodes.map(|(uuid, node_index)| async move {
+            let mut node_builder = NodeBuilder::new(self.xprivkey, self.storage.clone())
+                .with_uuid(uuid.clone())
+                .with_node_index(node_index.clone())
+                .with_gossip_sync(self.gossip_sync.clone())
+                .with_scorer(self.scorer.clone())
+                .with_chain(self.chain.clone())
+                .with_fee_estimator(self.fee_estimator.clone())
+                .with_wallet(self.wallet.clone())
+                .with_esplora(self.esplora.clone())
+                .with_initial_sync(self.has_done_initial_ldk_sync.clone())
+                .with_network(self.network);
+            node_builder.with_logger(self.logger.clone());
+            node_builder.with_connection_registry(self.connections.clone());
+
+            #[cfg(target_arch = "wasm32")]
+            node_builder.with_websocket_proxy_addr(self.websocket_proxy_addr.clone());
+
+            if let Some(l) = self.lsp_config.clone() {
+                node_builder.with_lsp_config(l);
+            }
+            if self.do_not_connect_peers {
+                node_builder.do_not_connect_peers();
+            }
+
+            let res = node_builder.build().await;
+            (uuid, node_index, res)
+        });
+
+        let mut nodes_map = HashMap::new();
+        let mut failed_nodes = vec![];
+        for (uuid, node_index, res) in join_all(node_futures).await {
+            match res {
+                Ok(node) => {
+                    let id = node
+                        .keys_manager
+                        .get_node_id(Recipient::Node)
+                        .expect("Failed to get node id");
+
+                    nodes_map.insert(id, Arc::new(node));
+                }
+                Err(e) => {
+                    log_error!(self.logger, "Failed to start node {uuid}: {e}");
+                    let persister = MutinyNodePersister::new(
+                        uuid.clone(),
+                        self.storage.clone(),
+                        self.logger.clone(),
+                    );
+                    if !persister.list_stored_monitors()?.is_empty() {
+                        log_error!(
+                            self.logger,
+                            "Node {uuid} has channels to watch, can't start without it"
+                        );
+                        return Err(e);
+                    }
+
+                    failed_nodes.push(FailedNode {
+                        uuid,
+                        child_index: node_index.child_index,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        log_trace!(
+            self.logger,
+            "Nodes built: took {}ms",
+            start.elapsed().as_millis()
+        );
+
+        // when we create the nodes we set the LSP if one is missing
+        // we need to save it to local storage after startup in case
+        // a LSP was set. Start from the stored nodes so we keep
+        // the archived nodes that we did not start.
+        let mut updated_nodes: HashMap<String, NodeIndex> = node_storage.nodes.clone();
+        for n in nodes_map.values() {
+            updated_nodes.insert(n.uuid.clone(), n.node_index().await);
+        }
+
+        // insert updated nodes in background, isn't a huge deal if this fails,
+        // it is only for updating the LSP config
+        log_info!(self.logger, "inserting updated nodes");
+        let version = node_storage.version + 1;
+        let storage = self.storage.clone();
+        let logger_clone = self.logger.clone();
+        spawn(async move {
+            let start = Instant::now();
+            if let Err(e) = storage
+                .insert_nodes(&NodeStorage {
+                    nodes: updated_nodes,
+                    version,
+                })
+                .await
+            {
+                log_error!(logger_clone, "Failed to insert updated nodes: {e}");
+            } else {
+                log_info!(
+                    logger_clone,
+                    "inserted updated nodes, took {}ms",
+                    start.elapsed().as_millis()
+                );
+            }
+        });
+
+        self.nodes.write().await.extend(nodes_map);
+        *self.failed_nodes.write().await = failed_nodes;
+        log_trace!(self.logger, "finished calling start_nodes");
+
+        Ok(())
+    }
+
     // New function to get a node by PublicKey or return the first node
     pub(crate) async fn get_node_by_key_or_first(
         &self,
@@ -585,6 +995,12 @@ impl<S: MutinyStorage> NodeManager<S> {
     pub fn start_sync(nm: Arc<NodeManager<S>>) {
         log_trace!(nm.logger, "calling start_sync");
 
+        // syncing writes to storage
+        if nm.storage.is_read_only() {
+            log_info!(nm.logger, "Skipping sync in read-only session");
+            return;
+        }
+
         // sync every second on regtest, this makes testing easier
         let sync_interval_secs = match nm.network {
             Network::Bitcoin | Network::Testnet | Network::Signet => 60,
@@ -593,23 +1009,41 @@ impl<S: MutinyStorage> NodeManager<S> {
         };
         utils::spawn(async move {
             let mut synced = false;
+            let mut gossip_synced = false;
+            let mut last_node_announcement = 0;
+            let mut last_scorer_sync = 0;
             loop {
                 // If we are stopped, don't sync
                 if nm.stop.load(Ordering::Relaxed) {
                     return;
                 }
 
-                if !synced {
-                    if let Err(e) = nm.sync_rgs().await {
+                let background = nm.background.load(Ordering::Relaxed);
+
+                // gossip is only needed for sending, skip it while in the background
+                if !gossip_synced && !background && !nm.disable_gossip {
+                    let res = nm
+                        .performance
+                        .time(Operation::GossipSync, nm.sync_rgs())
+                        .await;
+                    if let Err(e) = res {
                         log_error!(nm.logger, "Failed to sync RGS: {e}");
                     } else {
                         log_info!(nm.logger, "RGS Synced!");
                     }
+                }
 
+                // keep the remote scores fresh, retry on the next round if it fails
+                let now = utils::now().as_secs();
+                if !background
+                    && !nm.disable_gossip
+                    && now.saturating_sub(last_scorer_sync) >= SCORER_SYNC_INTERVAL_SECS
+                {
                     if let Err(e) = nm.sync_scorer().await {
                         log_error!(nm.logger, "Failed to sync scorer: {e}");
                     } else {
                         log_info!(nm.logger, "Scorer Synced!");
+                        last_scorer_sync = now;
                     }
                 }
 
@@ -621,29 +1055,64 @@ impl<S: MutinyStorage> NodeManager<S> {
                     log_info!(nm.logger, "Updated fee estimates!");
                 }
 
-                if let Err(e) = nm.sync().await {
+                if let Err(e) = nm.performance.time(Operation::Sync, nm.sync()).await {
                     log_error!(nm.logger, "Failed to sync: {e}");
                 } else if !synced {
                     // if this is the first sync, set the done_first_sync flag
                     let _ = nm.storage.set_done_first_sync();
                     synced = true;
                 }
+                gossip_synced |= synced && !background;
+
+                // peers only relay our channel announcements once we have announced ourselves
+                let now = utils::now().as_secs();
+                if synced
+                    && now.saturating_sub(last_node_announcement) >= NODE_ANNOUNCEMENT_INTERVAL_SECS
+                {
+                    nm.broadcast_node_announcements().await;
+                    last_node_announcement = now;
+                }
+
+                let wait_secs = if background {
+                    sync_interval_secs * BACKGROUND_SYNC_MULTIPLIER
+                } else {
+                    sync_interval_secs
+                };
 
                 // wait for next sync round, checking graceful shutdown check each second.
-                for _ in 0..sync_interval_secs {
+                // if we come back to the foreground, sync right away
+                for _ in 0..wait_secs {
                     if nm.stop.load(Ordering::Relaxed) {
                         return;
                     }
+                    if background && !nm.background.load(Ordering::Relaxed) {
+                        break;
+                    }
                     sleep(1_000).await;
                 }
             }
         });
     }
 
+    /// Broadcasts a node announcement for each node that has announced channels
+    pub(crate) async fn broadcast_node_announcements(&self) {
+        let nodes = self.nodes.read().await;
+        for node in nodes.values() {
+            node.broadcast_node_announcement();
+        }
+    }
+
+    /// Sets if the app is in the background. While in the background we
+    /// sync less often and skip gossip syncing until we are back in the foreground.
+    pub fn set_background(&self, background: bool) {
+        self.background.store(background, Ordering::Relaxed);
+    }
+
     /// Broadcast a transaction to the network.
     /// The transaction is broadcast through the configured esplora server.
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling broadcast_transaction");
+        self.storage.check_writable()?;
         let res = self.wallet.broadcast_transaction(tx).await;
         log_trace!(self.logger, "finished calling broadcast_transaction");
 
@@ -696,6 +1165,7 @@ impl<S: MutinyStorage> NodeManager<S> {
         fee_rate: Option<f32>,
     ) -> Result<Txid, MutinyError> {
         log_trace!(self.logger, "calling send_payjoin");
+        self.storage.check_writable()?;
 
         let uri = uri
             .require_network(self.network)
@@ -770,6 +1240,9 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     ///
     /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// If esplora can't be reached the signed transaction is queued and
+    /// broadcast on a later sync, see [NodeManager::list_pending_broadcasts].
     pub async fn send_to_address(
         &self,
         send_to: Address,
@@ -778,12 +1251,42 @@ impl<S: MutinyStorage> NodeManager<S> {
         fee_rate: Option<f32>,
     ) -> Result<Txid, MutinyError> {
         log_trace!(self.logger, "calling send_to_address");
+        self.storage.check_writable()?;
         let res = self.wallet.send(send_to, amount, labels, fee_rate).await;
         log_trace!(self.logger, "finished calling send_to_address");
 
         res
     }
 
+    /// Pays multiple recipients in a single on-chain transaction.
+    /// The amounts are in satoshis and the fee rate is in sat/vbyte.
+    /// The labels are added to every recipient, the change output is not labeled.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// The whole batch counts as one payment for the spending policy.
+    pub async fn batch_send(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        fee_rate: Option<f32>,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling batch_send");
+        self.storage.check_writable()?;
+
+        let total: u64 = recipients.iter().map(|(_, amount)| amount).sum();
+        let destinations: Vec<String> = recipients.iter().map(|(a, _)| a.to_string()).collect();
+        let reservation = self.spending_policy.check_payment(total, &destinations)?;
+
+        let res = self.wallet.batch_send(recipients, labels, fee_rate).await;
+        if res.is_ok() {
+            reservation.complete();
+        }
+        log_trace!(self.logger, "finished calling batch_send");
+
+        res
+    }
+
     /// Sweeps all the funds from the wallet to the given address.
     /// The fee rate is in sat/vbyte.
     ///
@@ -795,12 +1298,55 @@ impl<S: MutinyStorage> NodeManager<S> {
         fee_rate: Option<f32>,
     ) -> Result<Txid, MutinyError> {
         log_trace!(self.logger, "calling sweep_wallet");
+        self.storage.check_writable()?;
         let res = self.wallet.sweep(send_to, labels, fee_rate).await;
         log_trace!(self.logger, "calling sweep_wallet");
 
         res
     }
 
+    /// Sweeps the funds of a foreign private key (WIF) or BIP-39 seed into the wallet,
+    /// one transaction for every address type that has funds.
+    /// The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// Returns what happened to each address type, see [ExternalSweep].
+    pub async fn sweep_external(
+        &self,
+        wif_or_mnemonic: &str,
+        fee_rate: Option<f32>,
+    ) -> Result<Vec<ExternalSweep>, MutinyError> {
+        log_trace!(self.logger, "calling sweep_external");
+        self.storage.check_writable()?;
+        let res = self.wallet.sweep_external(wif_or_mnemonic, fee_rate).await;
+        log_trace!(self.logger, "finished calling sweep_external");
+
+        res
+    }
+
+    /// Sends `amount` to a new address of ours in a transaction built with
+    /// other wallets, like a coinjoin. The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    pub async fn collaborative_spend(
+        &self,
+        protocol: &impl CollaborativeSpend,
+        amount: u64,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling collaborative_spend");
+        self.storage.check_writable()?;
+        let res = self
+            .wallet
+            .collaborative_spend(protocol, amount, labels, fee_rate)
+            .await;
+        log_trace!(self.logger, "finished calling collaborative_spend");
+
+        res
+    }
+
     /// Estimates the onchain fee for a transaction sending to the given address.
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     pub(crate) fn estimate_tx_fee(
@@ -874,6 +1420,7 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// the new given fee rate in sats/vbyte
     pub async fn bump_fee(&self, txid: Txid, new_fee_rate: f32) -> Result<Txid, MutinyError> {
         log_trace!(self.logger, "calling bump_fee");
+        self.storage.check_writable()?;
 
         // check that this is not a funding tx for any channels,
         // bumping those can cause loss of funds
@@ -939,6 +1486,7 @@ impl<S: MutinyStorage> NodeManager<S> {
                 fee: None,
                 confirmation_time,
                 labels,
+                mempool_status: None,
             };
 
             let block_id = match tx.status.block_hash {
@@ -999,6 +1547,26 @@ impl<S: MutinyStorage> NodeManager<S> {
         tx
     }
 
+    /// Lists the signed on-chain transactions that couldn't be broadcast yet.
+    /// These are retried on every sync until esplora has seen them.
+    pub fn list_pending_broadcasts(&self) -> Result<Vec<PendingBroadcast>, MutinyError> {
+        log_trace!(self.logger, "calling list_pending_broadcasts");
+        let res = self.wallet.list_pending_broadcasts();
+        log_trace!(self.logger, "finished calling list_pending_broadcasts");
+
+        res
+    }
+
+    /// Stops retrying to broadcast the given transaction and frees up its inputs.
+    pub fn abandon_pending_broadcast(&self, txid: Txid) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling abandon_pending_broadcast");
+        self.storage.check_writable()?;
+        let res = self.wallet.abandon_pending_broadcast(&txid);
+        log_trace!(self.logger, "finished calling abandon_pending_broadcast");
+
+        res
+    }
+
     /// Lists all the on-chain transactions in the wallet.
     /// These are sorted by confirmation time.
     pub fn list_onchain(&self) -> Result<Vec<TransactionDetails>, MutinyError> {
@@ -1047,6 +1615,17 @@ impl<S: MutinyStorage> NodeManager<S> {
             return Err(MutinyError::WalletOperationFailed);
         };
 
+        // the nodes don't run in read-only sessions, use the channel state in storage
+        if self.storage.is_read_only() {
+            let (lightning, force_close) = self.get_stored_channel_balances().await?;
+            return Ok(NodeBalance {
+                confirmed: onchain.confirmed + onchain.trusted_pending,
+                unconfirmed: onchain.untrusted_pending + onchain.immature,
+                lightning,
+                force_close,
+            });
+        }
+
         let nodes = self.nodes.read().await;
         let lightning_msats: u64 = nodes
             .iter()
@@ -1084,6 +1663,46 @@ impl<S: MutinyStorage> NodeManager<S> {
         })
     }
 
+    /// Bumps the fee of a force closed anchor channel to the given fee rate in sats per
+    /// vbyte, by spending its anchor output in a child transaction (CPFP). The HTLC
+    /// claims of the channel are bumped too. LDK keeps bumping them until they confirm.
+    pub async fn bump_channel_close_fee(
+        &self,
+        channel_id: ChannelId,
+        fee_rate: f32,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling bump_channel_close_fee");
+        self.storage.check_writable()?;
+
+        let fee_rate = FeeRate::from_sat_per_vb(fee_rate).sat_per_kwu() as u32;
+        let mut res = Err(MutinyError::NotFound);
+        let nodes = self.nodes.read().await;
+        for (_, node) in nodes.iter() {
+            res = node.bump_channel_close_fee(&channel_id, fee_rate);
+            if !matches!(res, Err(MutinyError::NotFound)) {
+                break;
+            }
+        }
+
+        log_trace!(self.logger, "finished calling bump_channel_close_fee");
+        res
+    }
+
+    /// Lists every claim of the funds in limbo from force closes, the
+    /// [NodeBalance::force_close] balance, with when they can be swept to our wallet.
+    pub async fn get_force_close_details(&self) -> Result<Vec<ForceCloseClaim>, MutinyError> {
+        log_trace!(self.logger, "calling get_force_close_details");
+
+        let mut claims = vec![];
+        let nodes = self.nodes.read().await;
+        for (_, node) in nodes.iter() {
+            claims.extend(node.get_force_close_details()?);
+        }
+
+        log_trace!(self.logger, "finished calling get_force_close_details");
+        Ok(claims)
+    }
+
     /// Lists all the UTXOs in the wallet.
     pub fn list_utxos(&self) -> Result<Vec<LocalOutput>, MutinyError> {
         log_trace!(self.logger, "calling list_utxos");
@@ -1144,32 +1763,62 @@ impl<S: MutinyStorage> NodeManager<S> {
         if self.safe_mode {
             log_info!(self.logger, "Skipping rgs sync in safe mode");
         } else {
+            // only download the changes since our last snapshot
             let last_rgs_sync_timestamp = self
                 .gossip_sync
                 .network_graph()
                 .get_last_rapid_gossip_sync_timestamp();
+            self.fetch_rgs_snapshot(last_rgs_sync_timestamp).await?;
+        }
 
-            if let Some(rgs_url) = get_rgs_url(
-                self.network,
-                self.user_rgs_url.as_deref(),
-                last_rgs_sync_timestamp,
-            ) {
-                log_info!(self.logger, "RGS URL: {rgs_url}");
+        log_trace!(self.logger, "finished calling sync_rgs");
+        Ok(())
+    }
 
-                let now = utils::now().as_secs();
-                fetch_updated_gossip(
-                    rgs_url,
-                    now,
-                    last_rgs_sync_timestamp.unwrap_or_default(),
-                    &self.gossip_sync,
-                    &self.storage,
-                    &self.logger,
-                )
+    /// Downloads a full RGS snapshot instead of only the changes since the last sync.
+    /// This can be useful if the network graph is missing channels.
+    /// Will be skipped if in safe mode or if gossip is disabled.
+    pub async fn force_full_gossip_sync(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling force_full_gossip_sync");
+
+        if self.safe_mode || self.disable_gossip {
+            log_info!(self.logger, "Skipping full rgs sync, gossip is not enabled");
+        } else {
+            self.performance
+                .time(Operation::GossipSync, self.fetch_rgs_snapshot(None))
                 .await?;
-            }
         }
 
-        log_trace!(self.logger, "finished calling sync_rgs");
+        log_trace!(self.logger, "finished calling force_full_gossip_sync");
+        Ok(())
+    }
+
+    /// Fetches the RGS snapshot since the given timestamp and applies it to the network graph,
+    /// without a timestamp a full snapshot is downloaded.
+    async fn fetch_rgs_snapshot(
+        &self,
+        last_sync_timestamp: Option<u32>,
+    ) -> Result<(), MutinyError> {
+        if let Some(rgs_url) = get_rgs_url(
+            self.network,
+            self.user_rgs_url.as_deref(),
+            last_sync_timestamp,
+        ) {
+            log_info!(self.logger, "RGS URL: {rgs_url}");
+
+            let now = utils::now().as_secs();
+            fetch_updated_gossip(
+                rgs_url,
+                now,
+                last_sync_timestamp.unwrap_or_default(),
+                &self.gossip_sync,
+                self.network,
+                &self.storage,
+                &self.logger,
+            )
+            .await?;
+        }
+
         Ok(())
     }
 
@@ -1185,7 +1834,7 @@ impl<S: MutinyStorage> NodeManager<S> {
         }
 
         if let (Some(auth), Some(url)) = (self.auth_client.as_ref(), self.scorer_url.as_deref()) {
-            let scorer = get_remote_scorer(
+            let remote = get_remote_scorer(
                 auth,
                 url,
                 self.gossip_sync.network_graph().clone(),
@@ -1197,18 +1846,59 @@ impl<S: MutinyStorage> NodeManager<S> {
                 e
             })?;
 
-            // Replace the current scorer with the new one
+            // Merge the remote scores in, our own scores take precedence
             let mut lock = self
                 .scorer
                 .try_lock()
                 .map_err(|_| MutinyError::WalletSyncError)?;
-            *lock = scorer;
+            lock.set_remote(remote);
         }
 
         log_trace!(self.logger, "finished calling sync_scorer");
         Ok(())
     }
 
+    /// Exports our own pathfinding scores as a base64 encoded snapshot,
+    /// remote scores are not included.
+    pub fn export_scorer(&self) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling export_scorer");
+
+        let lock = self
+            .scorer
+            .try_lock()
+            .map_err(|_| MutinyError::WalletOperationFailed)?;
+        let snapshot = encode_scorer_snapshot(&*lock);
+
+        log_trace!(self.logger, "finished calling export_scorer");
+        Ok(snapshot)
+    }
+
+    /// Imports a scorer snapshot from [NodeManager::export_scorer] or the scorer server,
+    /// replacing our own pathfinding scores.
+    pub fn import_scorer(&self, snapshot: &str) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling import_scorer");
+
+        let scorer = parse_scorer_snapshot(
+            snapshot,
+            self.gossip_sync.network_graph().clone(),
+            self.logger.clone(),
+        )?;
+
+        let mut lock = self
+            .scorer
+            .try_lock()
+            .map_err(|_| MutinyError::WalletOperationFailed)?;
+        lock.set_local(scorer);
+
+        // persist it right away so it survives a restart before the next scorer persist
+        let scorer_hex = lock.encode().to_lower_hex_string();
+        self.storage
+            .set_data(PROB_SCORER_KEY.to_string(), scorer_hex, None)?;
+
+        log_trace!(self.logger, "finished calling import_scorer");
+        Ok(())
+    }
+
     /// Syncs the on-chain wallet and lightning wallet.
     /// This will update the on-chain wallet with any new
     /// transactions and update the lightning wallet with
@@ -1236,6 +1926,11 @@ impl<S: MutinyStorage> NodeManager<S> {
         // set has synced to true
         self.has_done_initial_ldk_sync.swap(true, Ordering::SeqCst);
 
+        // retry queued broadcasts before syncing so we pick them up right away
+        if let Err(e) = self.wallet.retry_pending_broadcasts().await {
+            log_error!(self.logger, "Failed to retry pending broadcasts: {e}");
+        }
+
         // sync bdk wallet
         let res = match self.wallet.sync().await {
             Ok(()) => Ok(log_info!(self.logger, "We are synced!")),
@@ -1244,6 +1939,19 @@ impl<S: MutinyStorage> NodeManager<S> {
                 Err(e)
             }
         };
+
+        // make sure our unconfirmed transactions didn't get evicted
+        if res.is_ok() {
+            if let Err(e) = self.wallet.check_mempool().await {
+                log_error!(self.logger, "Failed to check mempool: {e}");
+            }
+
+            // roll back anything that was confirmed in a block that got reorged out
+            let chain = self.esplora.as_ref();
+            if let Err(e) = handle_reorgs(&self.storage, chain, &self.wallet, &self.logger).await {
+                log_error!(self.logger, "Failed to check for reorgs: {e}");
+            }
+        }
         log_trace!(self.logger, "finished calling sync");
 
         res
@@ -1295,7 +2003,6 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Archives a node so it will not be started up next time the node manager is created.
     ///
     /// If the node has any active channels it will fail to archive
-    #[allow(dead_code)]
     pub(crate) async fn archive_node(&self, pubkey: PublicKey) -> Result<(), MutinyError> {
         if let Some(node) = self.nodes.read().await.get(&pubkey) {
             // disallow archiving nodes with active channels or
@@ -1314,23 +2021,202 @@ impl<S: MutinyStorage> NodeManager<S> {
 
     /// Archives a node so it will not be started up next time the node manager is created.
     ///
-    /// If the node has any active channels it will fail to archive
-    #[allow(dead_code)]
+    /// This doesn't check the node's channels, see [NodeManager::archive_node]
     pub(crate) async fn archive_node_by_uuid(&self, node_uuid: String) -> Result<(), MutinyError> {
-        let mut node_storage = self.node_storage.write().await;
+        match self.set_node_archived(node_uuid, true).await {
+            Err(MutinyError::NotFound) => Err(anyhow!("Could not find node to archive").into()),
+            res => res,
+        }
+    }
 
-        match node_storage.nodes.get(&node_uuid).map(|n| n.to_owned()) {
-            None => Err(anyhow!("Could not find node to archive").into()),
-            Some(mut node) => {
-                node.archived = Some(true);
-                let prev = node_storage.nodes.insert(node_uuid, node);
+    /// Enables or disables a node. The node's data is preserved either way.
+    ///
+    /// Disabling a node stops it and its background tasks, and it will not be
+    /// started up next time the node manager is created. Enabling a node starts it back up.
+    ///
+    /// If the node has any active channels or claimable funds it will fail to disable.
+    pub async fn set_node_enabled(
+        &self,
+        pubkey: PublicKey,
+        enabled: bool,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_node_enabled");
 
-                // Check that we did override the previous node index
-                debug_assert!(prev.is_some());
+        if self.safe_mode {
+            return Err(MutinyError::NotRunning);
+        }
 
-                Ok(())
+        let res = if enabled {
+            self.enable_node(pubkey).await
+        } else {
+            self.disable_node(pubkey).await
+        };
+        log_trace!(self.logger, "finished calling set_node_enabled");
+
+        res
+    }
+
+    async fn disable_node(&self, pubkey: PublicKey) -> Result<(), MutinyError> {
+        let node = self.nodes.read().await.get(&pubkey).cloned();
+        let node = match node {
+            Some(node) => node,
+            // already disabled
+            None if self.find_disabled_node(&pubkey).await?.is_some() => return Ok(()),
+            None => return Err(MutinyError::NotFound),
+        };
+
+        self.archive_node(pubkey).await?;
+
+        // stop the node and remove it so we no longer sync it
+        self.nodes.write().await.remove(&pubkey);
+        node.stop().await?;
+        log_info!(self.logger, "Disabled node: {pubkey}");
+
+        Ok(())
+    }
+
+    async fn enable_node(&self, pubkey: PublicKey) -> Result<(), MutinyError> {
+        if self.nodes.read().await.contains_key(&pubkey) {
+            // already enabled
+            return Ok(());
+        }
+
+        let (uuid, mut node_index) = self
+            .find_disabled_node(&pubkey)
+            .await?
+            .ok_or(MutinyError::NotFound)?;
+        node_index.archived = Some(false);
+
+        let mut node_builder = NodeBuilder::new(self.xprivkey, self.storage.clone())
+            .with_uuid(uuid.clone())
+            .with_node_index(node_index)
+            .with_gossip_sync(self.gossip_sync.clone())
+            .with_scorer(self.scorer.clone())
+            .with_chain(self.chain.clone())
+            .with_fee_estimator(self.fee_estimator.clone())
+            .with_wallet(self.wallet.clone())
+            .with_esplora(self.esplora.clone())
+            .with_network(self.network)
+            .with_initial_sync(self.has_done_initial_ldk_sync.clone());
+        node_builder.with_logger(self.logger.clone());
+        node_builder.with_connection_registry(self.connections.clone());
+
+        #[cfg(target_arch = "wasm32")]
+        node_builder.with_websocket_proxy_addr(self.websocket_proxy_addr.clone());
+
+        if let Some(l) = self.lsp_config.clone() {
+            node_builder.with_lsp_config(l);
+        }
+        if self.do_not_connect_peers {
+            node_builder.do_not_connect_peers();
+        }
+
+        let node = node_builder.build().await?;
+        self.set_node_archived(uuid, false).await?;
+        self.nodes.write().await.insert(node.pubkey, Arc::new(node));
+        log_info!(self.logger, "Enabled node: {pubkey}");
+
+        Ok(())
+    }
+
+    /// Gets the lightning and force close balances, in sats, from the channel monitors
+    /// in storage. Used when the nodes aren't running.
+    async fn get_stored_channel_balances(&self) -> Result<(u64, u64), MutinyError> {
+        let node_storage = self.node_storage.read().await;
+        let mut lightning = 0;
+        let mut force_close = 0;
+        for (uuid, node_index) in node_storage.nodes.iter().filter(|(_, n)| !n.is_archived()) {
+            let keys_manager = Arc::new(create_keys_manager(
+                self.wallet.clone(),
+                self.xprivkey,
+                node_index.child_index,
+                self.logger.clone(),
+            )?);
+            let persister =
+                MutinyNodePersister::new(uuid.clone(), self.storage.clone(), self.logger.clone());
+            let monitors = persister.read_channel_monitors(keys_manager).map_err(|e| {
+                MutinyError::ReadError {
+                    source: MutinyStorageError::Other(anyhow!(
+                        "failed to read channel monitors: {e}"
+                    )),
+                }
+            })?;
+
+            for balance in monitors
+                .iter()
+                .flat_map(|(_, m)| m.get_claimable_balances())
+            {
+                match balance {
+                    Balance::ClaimableOnChannelClose {
+                        amount_satoshis, ..
+                    } => lightning += amount_satoshis,
+                    b => force_close += b.claimable_amount_satoshis(),
+                }
+            }
+        }
+
+        Ok((lightning, force_close))
+    }
+
+    /// Finds the uuid and [NodeIndex] of a disabled node by its pubkey
+    async fn find_disabled_node(
+        &self,
+        pubkey: &PublicKey,
+    ) -> Result<Option<(String, NodeIndex)>, MutinyError> {
+        let node_storage = self.node_storage.read().await;
+        for (uuid, node_index) in node_storage.nodes.iter().filter(|(_, n)| n.is_archived()) {
+            let keys_manager = create_keys_manager(
+                self.wallet.clone(),
+                self.xprivkey,
+                node_index.child_index,
+                self.logger.clone(),
+            )?;
+            if pubkey_from_keys_manager(&keys_manager) == *pubkey {
+                return Ok(Some((uuid.clone(), node_index.clone())));
             }
         }
+
+        Ok(None)
+    }
+
+    /// Sets the archived status of a node and saves it to storage
+    async fn set_node_archived(
+        &self,
+        node_uuid: String,
+        archived: bool,
+    ) -> Result<(), MutinyError> {
+        let mut node_storage = self.node_storage.write().await;
+        match node_storage.nodes.get_mut(&node_uuid) {
+            None => return Err(MutinyError::NotFound),
+            Some(node) => node.archived = Some(archived),
+        }
+        node_storage.version += 1; // update version for VSS
+
+        self.storage.insert_nodes(&node_storage).await
+    }
+
+    /// Lists the pubkeys of the disabled lightning nodes in the manager.
+    pub async fn list_disabled_nodes(&self) -> Result<Vec<PublicKey>, MutinyError> {
+        log_trace!(self.logger, "calling list_disabled_nodes");
+
+        let node_storage = self.node_storage.read().await;
+        let pubkeys = node_storage
+            .nodes
+            .values()
+            .filter(|n| n.is_archived())
+            .map(|n| {
+                create_keys_manager(
+                    self.wallet.clone(),
+                    self.xprivkey,
+                    n.child_index,
+                    self.logger.clone(),
+                )
+                .map(|km| pubkey_from_keys_manager(&km))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        log_trace!(self.logger, "finished calling list_disabled_nodes");
+
+        pubkeys
     }
 
     /// Lists the pubkeys of the lightning node in the manager.
@@ -1344,6 +2230,65 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(peers)
     }
 
+    /// Lists the nodes that failed to start and why.
+    /// They are tried again the next time the wallet is started.
+    pub async fn list_failed_nodes(&self) -> Vec<FailedNode> {
+        self.failed_nodes.read().await.clone()
+    }
+
+    /// Reads back the channel monitors and channel manager of every node that isn't
+    /// archived, including the ones that failed to start. Returns the keys that can't
+    /// be read and why, see [MutinyNodePersister::check_channel_data].
+    pub(crate) async fn check_channel_data(&self) -> Result<Vec<(String, String)>, MutinyError> {
+        let nodes = self.node_storage.read().await.nodes.clone();
+        let mut errors = vec![];
+        for (uuid, node_index) in nodes {
+            if node_index.is_archived() {
+                continue;
+            }
+
+            let keys_manager = Arc::new(create_keys_manager(
+                self.wallet.clone(),
+                self.xprivkey,
+                node_index.child_index,
+                self.logger.clone(),
+            )?);
+            let persister = Arc::new(MutinyNodePersister::new(
+                uuid,
+                self.storage.clone(),
+                self.logger.clone(),
+            ));
+            // only needed to read the channel manager, it never watches anything
+            let chain_monitor = Arc::new(ChainMonitor::new(
+                None,
+                self.chain.clone(),
+                self.logger.clone(),
+                self.fee_estimator.clone(),
+                persister.clone(),
+            ));
+            let router = Arc::new(DefaultRouter::new(
+                self.gossip_sync.network_graph().clone(),
+                self.logger.clone(),
+                keys_manager.get_secure_random_bytes(),
+                self.scorer.clone(),
+                scoring_params(),
+            ));
+
+            let node_errors = persister
+                .check_channel_data(
+                    keys_manager,
+                    chain_monitor,
+                    self.chain.clone(),
+                    self.fee_estimator.clone(),
+                    router,
+                )
+                .await?;
+            errors.extend(node_errors);
+        }
+
+        Ok(errors)
+    }
+
     pub async fn get_configured_lsp(&self) -> Result<Option<LspConfig>, MutinyError> {
         let node = self.get_node_by_key_or_first(None).await?;
         Ok(node.node_index().await.lsp)
@@ -1483,6 +2428,22 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(())
     }
 
+    /// If our nodes connect to well connected nodes from the network graph
+    /// when they have few peers, this is enabled by default.
+    pub fn peer_discovery_enabled(&self) -> Result<bool, MutinyError> {
+        self.storage.peer_discovery_enabled()
+    }
+
+    /// Enables or disables connecting to peers discovered from the network graph.
+    /// Peers we are already connected to are kept until they disconnect.
+    pub fn set_peer_discovery_enabled(&self, enabled: bool) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_peer_discovery_enabled");
+        self.storage.set_peer_discovery_enabled(enabled)?;
+        log_trace!(self.logger, "finished calling set_peer_discovery_enabled");
+
+        Ok(())
+    }
+
     // all values in sats
 
     /// Creates a lightning invoice. The amount should be in satoshis.
@@ -1494,8 +2455,24 @@ impl<S: MutinyStorage> NodeManager<S> {
         &self,
         amount: u64,
         labels: Vec<String>,
+    ) -> Result<(MutinyInvoice, u64), MutinyError> {
+        self.create_invoice_with_params(amount, labels, &InvoiceParams::default())
+            .await
+    }
+
+    /// Creates an invoice like [NodeManager::create_invoice] with the given settings
+    pub async fn create_invoice_with_params(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+        params: &InvoiceParams,
     ) -> Result<(MutinyInvoice, u64), MutinyError> {
         log_trace!(self.logger, "calling create_invoice");
+        self.storage.check_writable()?;
+        let params = InvoiceParams {
+            route_hints: params.route_hints.or(self.route_hint_config),
+            ..params.clone()
+        };
 
         let nodes = self.nodes.read().await;
         let use_phantom = nodes.len() > 1 && self.lsp_config.is_none();
@@ -1520,7 +2497,7 @@ impl<S: MutinyStorage> NodeManager<S> {
             return Err(MutinyError::WalletOperationFailed);
         };
         let invoice = first_node
-            .create_invoice(amount, route_hints, labels)
+            .create_invoice(amount, route_hints, labels, &params)
             .await?;
         log_trace!(self.logger, "finished calling create_invoice");
 
@@ -1544,26 +2521,58 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Pays a lightning invoice from either a specified node or the first available node.
     /// An amount should only be provided if the invoice does not have an amount.
     /// The amount should be in satoshis.
+    ///
+    /// The route can be controlled with [PaymentParametersOverride].
+    /// Without a timeout we give up on the payment after 30 seconds.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn pay_invoice(
         &self,
         self_node_pubkey: Option<&PublicKey>,
         invoice: &Bolt11Invoice,
         amt_sats: Option<u64>,
         labels: Vec<String>,
+        overrides: Option<&PaymentParametersOverride>,
+        timeout_secs: Option<u64>,
+        trace: &mut PaymentTrace,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling pay_invoice");
+        self.storage.check_writable()?;
 
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
         let res = node
-            .pay_invoice_with_timeout(invoice, amt_sats, None, labels)
+            .pay_invoice_with_timeout(invoice, amt_sats, timeout_secs, labels, overrides, trace)
             .await;
         log_trace!(self.logger, "finished calling pay_invoice");
 
         res
     }
 
+    /// Estimates the fee of paying a lightning invoice from either a specified node
+    /// or the first available node, without paying it.
+    /// An amount should only be provided if the invoice does not have an amount.
+    /// The amount should be in satoshis.
+    pub async fn estimate_ln_fee(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<LnFeeEstimate, MutinyError> {
+        log_trace!(self.logger, "calling estimate_ln_fee");
+
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        let res = node.estimate_ln_fee(invoice, amt_sats);
+        log_trace!(self.logger, "finished calling estimate_ln_fee");
+
+        res
+    }
+
     /// Sends a spontaneous payment to a node from either a specified node or the first available node.
     /// The amount should be in satoshis.
+    ///
+    /// Retries with the same `idempotency_key` return the first payment instead of paying again.
+    /// The key can't be used for another payment, and one that didn't finish stays pending
+    /// until the key expires. Without a timeout we give up on the payment after 30 seconds.
+    #[allow(clippy::too_many_arguments)]
     pub async fn keysend(
         &self,
         self_node_pubkey: Option<&PublicKey>,
@@ -1571,19 +2580,85 @@ impl<S: MutinyStorage> NodeManager<S> {
         amt_sats: u64,
         message: Option<String>,
         labels: Vec<String>,
+        timeout_secs: Option<u64>,
+        idempotency_key: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        with_idempotency(
+            &self.storage,
+            idempotency_key.as_deref(),
+            &(to_node, amt_sats, &message),
+            // the payment hash is only made when paying
+            || Ok(PendingPayment::InFlight),
+            self.keysend_internal(
+                self_node_pubkey,
+                to_node,
+                amt_sats,
+                message,
+                labels,
+                timeout_secs,
+            ),
+        )
+        .await
+    }
+
+    async fn keysend_internal(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        to_node: PublicKey,
+        amt_sats: u64,
+        message: Option<String>,
+        labels: Vec<String>,
+        timeout_secs: Option<u64>,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling keysend");
+        self.storage.check_writable()?;
+        let reservation = self
+            .spending_policy
+            .check_payment(amt_sats, &[to_node.to_string()])?;
 
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
         log_debug!(self.logger, "Keysending to {to_node}");
         let res = node
-            .keysend_with_timeout(to_node, amt_sats, message, labels, None)
+            .keysend_with_timeout(to_node, amt_sats, message, labels, timeout_secs)
             .await;
+        // a timed out payment can still complete
+        if matches!(res, Ok(_) | Err(MutinyError::PaymentTimeout)) {
+            reservation.complete();
+        }
         log_trace!(self.logger, "finished calling keysend");
 
         res
     }
 
+    /// Stops retrying an outgoing lightning payment, like one that is stuck in flight.
+    ///
+    /// The payment fails once the HTLCs that are in flight fail,
+    /// it can still complete if the receiver already has them.
+    /// A payment none of the nodes knows about anymore is marked as failed right away,
+    /// federation payments can't be abandoned.
+    pub async fn abandon_payment(&self, payment_hash: &sha256::Hash) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling abandon_payment");
+        self.storage.check_writable()?;
+
+        let nodes = self.nodes.read().await;
+        let mut res = Err(MutinyError::NotFound);
+        for node in nodes.values() {
+            res = node.abandon_payment(payment_hash);
+            if !matches!(res, Err(MutinyError::NotFound)) {
+                break;
+            }
+        }
+        // only once no node owns it, the storage is shared between them
+        if matches!(res, Err(MutinyError::NotFound)) {
+            if let Some(node) = nodes.values().next() {
+                res = node.fail_forgotten_payment(payment_hash);
+            }
+        }
+        log_trace!(self.logger, "finished calling abandon_payment");
+
+        res
+    }
+
     pub async fn get_channel_closure(
         &self,
         user_channel_id: u128,
@@ -1617,9 +2692,108 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(channels)
     }
 
+    /// Gets where a channel is in its life, from requesting it until it is closed.
+    ///
+    /// Channels that are waiting on their funding transaction include how many
+    /// confirmations it has and how many are needed.
+    pub async fn get_channel_lifecycle(
+        &self,
+        user_channel_id: u128,
+    ) -> Result<ChannelLifecycle, MutinyError> {
+        log_trace!(self.logger, "calling get_channel_lifecycle");
+
+        let nodes = self.nodes.read().await;
+        for (_, node) in nodes.iter() {
+            if let Some(lifecycle) = node.get_channel_lifecycle(user_channel_id)? {
+                log_trace!(self.logger, "finished calling get_channel_lifecycle");
+                return Ok(lifecycle);
+            }
+        }
+
+        log_trace!(self.logger, "finished calling get_channel_lifecycle");
+        Err(MutinyError::NotFound)
+    }
+
+    /// Lists our outbound channels that have been pending for too long, because their
+    /// funding transaction isn't confirming or the peer went away.
+    pub async fn list_stuck_channels(&self) -> Result<Vec<StuckChannel>, MutinyError> {
+        log_trace!(self.logger, "calling list_stuck_channels");
+
+        let mut channels = vec![];
+        let nodes = self.nodes.read().await;
+        for (_, node) in nodes.iter() {
+            channels.extend(node.list_stuck_channels()?);
+        }
+
+        log_trace!(self.logger, "finished calling list_stuck_channels");
+        Ok(channels)
+    }
+
+    /// Abandons a pending outbound channel, see [NodeManager::list_stuck_channels].
+    ///
+    /// The funding transaction is double-spent back to the wallet before the channel is
+    /// closed, returning the txid of the double-spend. Fails without closing the channel
+    /// if the double-spend doesn't make it to the mempool.
+    pub async fn abandon_pending_channel(
+        &self,
+        user_channel_id: u128,
+    ) -> Result<Option<Txid>, MutinyError> {
+        log_trace!(self.logger, "calling abandon_pending_channel");
+        self.storage.check_writable()?;
+
+        let nodes = self.nodes.read().await;
+        let node = nodes.values().find(|n| {
+            n.channel_manager
+                .list_channels()
+                .iter()
+                .any(|c| c.user_channel_id == user_channel_id)
+        });
+        let res = match node {
+            Some(node) => node.abandon_pending_channel(user_channel_id).await,
+            None => Err(MutinyError::NotFound),
+        };
+
+        log_trace!(self.logger, "finished calling abandon_pending_channel");
+        res
+    }
+
+    /// Lists the outputs of closed channels that we failed to sweep to our wallet.
+    /// They are retried hourly with an increasing fee rate.
+    pub async fn list_unswept_outputs(&self) -> Result<Vec<UnsweptOutput>, MutinyError> {
+        log_trace!(self.logger, "calling list_unswept_outputs");
+
+        let mut outputs = vec![];
+        let nodes = self.nodes.read().await;
+        for (_, node) in nodes.iter() {
+            outputs.extend(node.list_unswept_outputs()?);
+        }
+
+        log_trace!(self.logger, "finished calling list_unswept_outputs");
+        Ok(outputs)
+    }
+
+    /// Sweeps the outputs of [NodeManager::list_unswept_outputs] now, with the given
+    /// fee rate in sats per vbyte. Returns the txids of the sweep transactions.
+    pub async fn force_sweep_outputs(&self, fee_rate: f32) -> Result<Vec<Txid>, MutinyError> {
+        log_trace!(self.logger, "calling force_sweep_outputs");
+        self.storage.check_writable()?;
+
+        let fee_rate = FeeRate::from_sat_per_vb(fee_rate).sat_per_kwu() as u32;
+        let mut txids = vec![];
+        let nodes = self.nodes.read().await;
+        for (_, node) in nodes.iter() {
+            txids.extend(node.force_sweep_outputs(fee_rate).await?);
+        }
+
+        log_trace!(self.logger, "finished calling force_sweep_outputs");
+        Ok(txids)
+    }
+
     /// Opens a channel from either a specified node or the first available node to the given pubkey.
     /// The amount is in satoshis.
     ///
+    /// The channel is announced if `announce` is set, otherwise the wallet's default is used.
+    ///
     /// The node must be online and have a connection to the peer.
     /// The wallet must have enough funds to open the channel.
     pub async fn open_channel(
@@ -1629,8 +2803,10 @@ impl<S: MutinyStorage> NodeManager<S> {
         amount: u64,
         fee_rate: Option<f32>,
         user_channel_id: Option<u128>,
+        announce: Option<bool>,
     ) -> Result<MutinyChannel, MutinyError> {
         log_trace!(self.logger, "calling open_channel");
+        self.storage.check_writable()?;
 
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
         let to_pubkey = match to_pubkey {
@@ -1644,8 +2820,20 @@ impl<S: MutinyStorage> NodeManager<S> {
             }
         };
 
-        let outpoint = node
-            .open_channel_with_timeout(to_pubkey, amount, fee_rate, user_channel_id, 60)
+        let announce = announce.unwrap_or(self.announce_channels);
+        let outpoint = self
+            .performance
+            .time(
+                Operation::OpenChannel,
+                node.open_channel_with_timeout(
+                    to_pubkey,
+                    amount,
+                    fee_rate,
+                    user_channel_id,
+                    announce,
+                    60,
+                ),
+            )
             .await?;
 
         let all_channels = node.channel_manager.list_channels();
@@ -1663,14 +2851,20 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Opens a channel from either a specified node or the first available node to the given pubkey.
     /// It will spend the given utxos in full to fund the channel.
     ///
+    /// The channel is announced if `announce` is set, otherwise the wallet's default is used.
+    ///
     /// The node must be online and have a connection to the peer.
     /// The UTXOs must all exist in the wallet.
     pub async fn sweep_utxos_to_channel(
         &self,
         utxos: &[OutPoint],
         to_pubkey: Option<PublicKey>,
+        announce: Option<bool>,
     ) -> Result<MutinyChannel, MutinyError> {
         log_trace!(self.logger, "calling sweep_utxos_to_channel");
+        self.storage.check_writable()?;
+        // a queued transaction already spends them
+        self.wallet.check_spendable(utxos)?;
 
         let node = self.get_node_by_key_or_first(None).await?;
         let to_pubkey = match to_pubkey {
@@ -1684,8 +2878,13 @@ impl<S: MutinyStorage> NodeManager<S> {
             }
         };
 
-        let outpoint = node
-            .sweep_utxos_to_channel_with_timeout(None, utxos, to_pubkey, 60)
+        let announce = announce.unwrap_or(self.announce_channels);
+        let outpoint = self
+            .performance
+            .time(
+                Operation::OpenChannel,
+                node.sweep_utxos_to_channel_with_timeout(None, utxos, to_pubkey, announce, 60),
+            )
             .await?;
 
         let all_channels = node.channel_manager.list_channels();
@@ -1703,20 +2902,27 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Opens a channel from our selected node to the given pubkey.
     /// It will spend the all the on-chain utxo in full to fund the channel.
     ///
+    /// The channel is announced if `announce` is set, otherwise the wallet's default is used.
+    ///
     /// The node must be online and have a connection to the peer.
     pub async fn sweep_all_to_channel(
         &self,
         to_pubkey: Option<PublicKey>,
+        announce: Option<bool>,
     ) -> Result<MutinyChannel, MutinyError> {
         log_trace!(self.logger, "calling sweep_all_to_channel");
+        self.storage.check_writable()?;
 
         let utxos = self
-            .list_utxos()?
+            .wallet
+            .list_spendable_utxos()?
             .iter()
             .map(|u| u.outpoint)
             .collect::<Vec<_>>();
 
-        let res = self.sweep_utxos_to_channel(&utxos, to_pubkey).await;
+        let res = self
+            .sweep_utxos_to_channel(&utxos, to_pubkey, announce)
+            .await;
         log_trace!(self.logger, "finished calling sweep_all_to_channel");
 
         res
@@ -1739,6 +2945,7 @@ impl<S: MutinyStorage> NodeManager<S> {
         abandon: bool,
     ) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling close_channel");
+        self.storage.check_writable()?;
 
         if force && abandon {
             return Err(MutinyError::ChannelClosingFailed);
@@ -1816,6 +3023,15 @@ impl<S: MutinyStorage> NodeManager<S> {
                         })?;
                 }
 
+                let state = ChannelLifecycleState::Closing;
+                let user_channel_id = channel.user_channel_id;
+                if let Err(e) = node
+                    .persister
+                    .persist_channel_lifecycle(user_channel_id, state)
+                {
+                    log_warn!(self.logger, "Could not persist channel lifecycle: {e}");
+                }
+
                 Ok(())
             }
             None => {
@@ -1831,6 +3047,33 @@ impl<S: MutinyStorage> NodeManager<S> {
         res
     }
 
+    /// Sets the forwarding fees, CLTV delta and HTLC limits of the channel with the given
+    /// outpoint. The policy is saved and kept when the node restarts.
+    pub async fn update_channel_config(
+        &self,
+        outpoint: &OutPoint,
+        policy: ChannelPolicy,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling update_channel_config");
+
+        let nodes = self.nodes.read().await;
+        let (node, channel) = nodes
+            .values()
+            .find_map(|n| {
+                n.channel_manager
+                    .list_channels()
+                    .into_iter()
+                    .find(|c| c.funding_txo.map(|f| f.into_bitcoin_outpoint()) == Some(*outpoint))
+                    .map(|c| (n, c))
+            })
+            .ok_or(MutinyError::NotFound)?;
+
+        let res = node.update_channel_policy(&channel, policy);
+        log_trace!(self.logger, "finished calling update_channel_config");
+
+        res
+    }
+
     /// Lists all the channels for all the nodes in the node manager.
     pub async fn list_channels(&self) -> Result<Vec<MutinyChannel>, MutinyError> {
         log_trace!(self.logger, "calling list_channels");
@@ -1848,6 +3091,114 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(mutiny_channels)
     }
 
+    /// Creates a static channel backup of all the nodes in the node manager.
+    pub async fn create_static_channel_backup(&self) -> Result<StaticChannelBackup, MutinyError> {
+        log_trace!(self.logger, "calling create_static_channel_backup");
+
+        let node_storage = self.node_storage.read().await.clone();
+        let nodes = self.nodes.read().await;
+        let mut channels = vec![];
+        for node in nodes.values() {
+            channels.extend(node.channel_backups().await);
+        }
+
+        let backup = StaticChannelBackup {
+            nodes: node_storage.nodes,
+            channels,
+            created_at: utils::now().as_secs(),
+        };
+
+        log_trace!(self.logger, "finished calling create_static_channel_backup");
+        Ok(backup)
+    }
+
+    /// Sends the LSP of every node the backup of our channels with it when they changed,
+    /// and recovers the channels of the backups they gave back that we lost.
+    pub(crate) async fn check_peer_storage(&self) -> Result<(), MutinyError> {
+        let nodes = self.nodes.read().await;
+        for node in nodes.values() {
+            if let Err(e) = node.check_peer_storage().await {
+                log_warn!(
+                    self.logger,
+                    "Failed to check peer storage of node {}: {e}",
+                    node.uuid
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the monitors of channels that closed more than `min_age_secs` ago and have
+    /// nothing left to claim, for all the nodes in the node manager.
+    ///
+    /// Returns the number of deleted monitors and their size in bytes.
+    pub(crate) async fn prune_resolved_monitors(
+        &self,
+        min_age_secs: u64,
+    ) -> Result<(usize, u64), MutinyError> {
+        log_trace!(self.logger, "calling prune_resolved_monitors");
+
+        let nodes = self.nodes.read().await;
+        let mut removed = 0;
+        let mut bytes = 0;
+        for node in nodes.values() {
+            let (node_removed, node_bytes) = node.prune_resolved_monitors(min_age_secs)?;
+            removed += node_removed;
+            bytes += node_bytes;
+        }
+
+        log_trace!(self.logger, "finished calling prune_resolved_monitors");
+        Ok((removed, bytes))
+    }
+
+    /// Gets the liquidity of all the channels for all the nodes in the node manager.
+    ///
+    /// Unlike [NodeBalance::lightning], this accounts for channel reserves, HTLC limits
+    /// and payments in flight, so it reflects what can actually be sent and received.
+    pub async fn get_liquidity(&self) -> Result<Liquidity, MutinyError> {
+        log_trace!(self.logger, "calling get_liquidity");
+
+        let nodes = self.nodes.read().await;
+        let mut channels = vec![];
+        for node in nodes.values() {
+            let inflight = node.channel_manager.compute_inflight_htlcs();
+            let our_node_id = NodeId::from_pubkey(&node.pubkey);
+            for c in node.channel_manager.list_channels() {
+                let pending = c
+                    .get_outbound_payment_scid()
+                    .and_then(|scid| {
+                        let peer = NodeId::from_pubkey(&c.counterparty.node_id);
+                        inflight.used_liquidity_msat(&our_node_id, &peer, scid)
+                    })
+                    .unwrap_or(0);
+                channels.push(ChannelLiquidity::new(&c, pending));
+            }
+        }
+
+        log_trace!(self.logger, "finished calling get_liquidity");
+        Ok(Liquidity::new(channels))
+    }
+
+    /// Returns true if any node is connected to its LSP
+    pub(crate) async fn is_lsp_connected(&self) -> bool {
+        let nodes = self.nodes.read().await;
+        for node in nodes.values() {
+            if let Some(lsp) = node.lsp_client.as_ref() {
+                let lsp_pubkey = lsp.get_lsp_pubkey().await;
+                if node
+                    .peer_manager
+                    .get_peer_node_ids()
+                    .iter()
+                    .any(|(pk, _)| *pk == lsp_pubkey)
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     /// Lists all the peers for all the nodes in the node manager.
     pub async fn list_peers(&self) -> Result<Vec<MutinyPeer>, MutinyError> {
         log_trace!(self.logger, "calling list_peers");
@@ -1857,14 +3208,18 @@ impl<S: MutinyStorage> NodeManager<S> {
         // get peers saved in storage
         let mut storage_peers: Vec<MutinyPeer> = peer_data
             .iter()
-            .map(|(node_id, metadata)| MutinyPeer {
-                // node id should be safe here
-                pubkey: PublicKey::from_slice(node_id.as_slice()).expect("Invalid pubkey"),
-                connection_string: metadata.connection_string.clone(),
-                alias: metadata.alias.clone(),
-                color: metadata.color.clone(),
-                label: metadata.label.clone(),
-                is_connected: false,
+            .map(|(node_id, metadata)| {
+                let metadata = self.resolve_peer_metadata(node_id, metadata);
+                MutinyPeer {
+                    // node id should be safe here
+                    pubkey: PublicKey::from_slice(node_id.as_slice()).expect("Invalid pubkey"),
+                    connection_string: metadata.connection_string,
+                    alias: metadata.alias,
+                    color: metadata.color,
+                    addresses: metadata.addresses,
+                    label: metadata.label,
+                    is_connected: false,
+                }
             })
             .collect();
 
@@ -1888,11 +3243,18 @@ impl<S: MutinyStorage> NodeManager<S> {
         let mut missing: Vec<MutinyPeer> = Vec::new();
         for peer in connected_peers {
             if !storage_peers.iter().any(|p| p.pubkey == peer) {
+                // not saved, so we only show what the network graph has
+                let announced = LnPeerMetadata::from_network_graph(
+                    self.gossip_sync.network_graph(),
+                    &NodeId::from_pubkey(&peer),
+                )
+                .unwrap_or_default();
                 let new = MutinyPeer {
                     pubkey: peer,
                     connection_string: None,
-                    alias: None,
-                    color: None,
+                    alias: announced.alias,
+                    color: announced.color,
+                    addresses: announced.addresses,
                     label: None,
                     is_connected: true,
                 };
@@ -1907,6 +3269,63 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(storage_peers)
     }
 
+    /// Fills in the peer's alias, color and addresses from its announcement in the
+    /// network graph if we are missing them. They are saved so we still have them
+    /// when the graph isn't synced.
+    fn resolve_peer_metadata(&self, node_id: &NodeId, metadata: &LnPeerMetadata) -> LnPeerMetadata {
+        if metadata.alias.is_some() && !metadata.addresses.is_empty() {
+            return metadata.clone();
+        }
+
+        let Some(announced) =
+            LnPeerMetadata::from_network_graph(self.gossip_sync.network_graph(), node_id)
+        else {
+            return metadata.clone();
+        };
+
+        let resolved = announced.merge(metadata);
+        if resolved != *metadata {
+            if let Err(e) = gossip::save_ln_peer_info(&self.storage, node_id, &announced) {
+                log_warn!(
+                    self.logger,
+                    "Failed to save metadata for peer {node_id}: {e}"
+                );
+            }
+        }
+        resolved
+    }
+
+    /// Looks up the alias, color and addresses of saved peers we have no alias for,
+    /// using the network graph and then the mempool.space lightning API of our esplora
+    /// server. This is useful when gossip is disabled or the peer isn't in the graph.
+    ///
+    /// Peers that can't be found are skipped, the results are saved for [Self::list_peers].
+    pub async fn lookup_peer_metadata(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling lookup_peer_metadata");
+
+        let peer_data = gossip::get_all_peers(&self.storage)?;
+        for (node_id, metadata) in peer_data {
+            if self
+                .resolve_peer_metadata(&node_id, &metadata)
+                .alias
+                .is_some()
+            {
+                continue;
+            }
+
+            match gossip::fetch_peer_metadata(self.esplora.client(), self.esplora.url(), &node_id)
+                .await
+            {
+                Ok(Some(info)) => gossip::save_ln_peer_info(&self.storage, &node_id, &info)?,
+                Ok(None) => log_debug!(self.logger, "No metadata found for peer {node_id}"),
+                Err(e) => log_warn!(self.logger, "Failed to look up peer {node_id}: {e}"),
+            }
+        }
+
+        log_trace!(self.logger, "finished calling lookup_peer_metadata");
+        Ok(())
+    }
+
     /// Retrieves the logs from storage.
     pub fn get_logs(
         storage: S,
@@ -1915,6 +3334,16 @@ impl<S: MutinyStorage> NodeManager<S> {
         logger.get_logs(&storage)
     }
 
+    /// Retrieves the logs from storage with pubkeys, invoices and amounts redacted.
+    /// If a key is given, they are encrypted to it so they can be sent to support.
+    pub fn export_logs(
+        storage: S,
+        logger: Arc<MutinyLogger>,
+        encrypt_to: Option<::nostr::PublicKey>,
+    ) -> Result<Option<String>, MutinyError> {
+        logger.export_logs(&storage, encrypt_to)
+    }
+
     /// Resets the scorer and network graph. This can be useful if you get stuck in a bad state.
     pub async fn reset_router(&self) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling reset_router");
@@ -1928,6 +3357,10 @@ impl<S: MutinyStorage> NodeManager<S> {
         // delete all the keys we use to store routing data
         self.storage
             .delete(&[GOSSIP_SYNC_TIME_KEY, NETWORK_GRAPH_KEY, PROB_SCORER_KEY])?;
+        self.storage.delete(&[
+            gossip_sync_time_key(self.network),
+            network_graph_key(self.network),
+        ])?;
 
         // shut back down after reading if it was already closed
         if needs_db_connection {
@@ -1980,7 +3413,7 @@ impl<S: MutinyStorage> NodeManager<S> {
             !matches!(
                 k.as_str(),
                 LOGGING_KEY | NETWORK_GRAPH_KEY | PROB_SCORER_KEY | DEVICE_ID_KEY
-            )
+            ) && !k.starts_with(NETWORK_GRAPH_KEY)
         }));
 
         // shut back down after reading if it was already closed
@@ -2034,6 +3467,7 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
         .with_network(node_manager.network)
         .with_initial_sync(node_manager.has_done_initial_ldk_sync.clone());
     node_builder.with_logger(node_manager.logger.clone());
+    node_builder.with_connection_registry(node_manager.connections.clone());
 
     #[cfg(target_arch = "wasm32")]
     node_builder.with_websocket_proxy_addr(node_manager.websocket_proxy_addr.clone());
@@ -2066,6 +3500,9 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
 }
 
 /// Turn parameterized LSP options into a [`LspConfig`].
+///
+/// The LSP url can also be the connection string of any LSPS2 compliant LSP,
+/// in which case it is used the same as `lsp_connection_string`.
 pub fn create_lsp_config(
     lsp_url: Option<String>,
     lsp_connection_string: Option<String>,
@@ -2075,6 +3512,11 @@ pub fn create_lsp_config(
         (Some(lsp_url), None) => {
             let trimmed = lsp_url.trim().to_string();
             if !trimmed.is_empty() {
+                // LSPS2 LSPs are identified by their connection string instead of a url
+                if parse_peer_info(&trimmed).is_ok() {
+                    return Ok(Some(LspConfig::new_lsps(trimmed, lsp_token)));
+                }
+
                 // make sure url is valid
                 if Url::parse(&trimmed).is_err() {
                     return Err(MutinyError::InvalidArgumentsError);
@@ -2101,17 +3543,24 @@ pub fn create_lsp_config(
 mod tests {
     use crate::{
         encrypt::encryption_key_from_pass,
-        nodemanager::{ChannelClosure, MutinyInvoice, NodeManager, TransactionDetails},
+        nodemanager::{
+            ChannelClosure, ChannelLiquidity, ForceCloseClaim, ForceCloseClaimType, Liquidity,
+            MutinyInvoice, NodeManager, TransactionDetails,
+        },
         ActivityItem, MutinyWalletConfigBuilder, PrivacyLevel,
     };
-    use crate::{keymanager::generate_seed, nodemanager::NodeManagerBuilder};
+    use crate::{
+        keymanager::generate_seed,
+        nodemanager::{create_lsp_config, NodeManagerBuilder},
+    };
     use bdk::chain::ConfirmationTime;
     use bitcoin::bip32::ExtendedPrivKey;
     use bitcoin::hashes::hex::FromHex;
     use bitcoin::hashes::{sha256, Hash};
     use bitcoin::secp256k1::{PublicKey, ThirtyTwoByteHash};
-    use bitcoin::{absolute, Network, Transaction, TxOut, Txid};
+    use bitcoin::{absolute, Network, OutPoint, Transaction, TxOut, Txid};
     use hex_conservative::DisplayHex;
+    use lightning::chain::channelmonitor::Balance;
     use lightning::ln::PaymentHash;
     use lightning_invoice::Bolt11Invoice;
     use std::collections::HashMap;
@@ -2120,9 +3569,11 @@ mod tests {
     use crate::test_utils::*;
 
     use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+    use crate::gossip::PROB_SCORER_KEY;
+    use crate::ldkstorage::{MutinyNodePersister, CHANNEL_MANAGER_KEY};
     use crate::lsp::voltage::VoltageConfig;
     use crate::nodemanager::{LspConfig, NodeIndex, NodeStorage};
-    use crate::storage::{MemoryStorage, MutinyStorage};
+    use crate::storage::{MemoryStorage, MutinyStorage, VersionedValue};
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -2198,6 +3649,174 @@ mod tests {
         }
     }
 
+    #[test]
+    async fn announce_channels_from_config() {
+        let test_name = "announce_channels_from_config";
+        log!("{}", test_name);
+
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &seed.to_seed("")).unwrap();
+
+        for announce in [false, true] {
+            let storage = MemoryStorage::new(None, None, None);
+            let mut config = MutinyWalletConfigBuilder::new(xpriv).with_network(network);
+            if announce {
+                config.with_announced_channels();
+            }
+            let nm = NodeManagerBuilder::new(xpriv, storage)
+                .with_config(config.build())
+                .build()
+                .await
+                .expect("node manager should initialize");
+            assert_eq!(nm.announce_channels, announce);
+
+            // without any channels there is nothing to announce
+            nm.new_node().await.expect("should create new node");
+            nm.broadcast_node_announcements().await;
+        }
+    }
+
+    #[test]
+    async fn disable_and_enable_node() {
+        let test_name = "disable_and_enable_node";
+        log!("{}", test_name);
+
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &seed.to_seed("")).unwrap();
+        let c = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c)
+            .build()
+            .await
+            .expect("node manager should initialize");
+
+        let node_identity = nm.new_node().await.expect("should create new node");
+
+        nm.set_node_enabled(node_identity.pubkey, false)
+            .await
+            .expect("should disable node");
+        assert!(nm.list_nodes().await.unwrap().is_empty());
+        assert_eq!(
+            nm.list_disabled_nodes().await.unwrap(),
+            vec![node_identity.pubkey]
+        );
+
+        // node data should be preserved in storage
+        let stored = storage.get_nodes().unwrap();
+        assert!(stored.nodes.get(&node_identity.uuid).unwrap().is_archived());
+
+        nm.set_node_enabled(node_identity.pubkey, true)
+            .await
+            .expect("should enable node");
+        assert_eq!(nm.list_nodes().await.unwrap(), vec![node_identity.pubkey]);
+        assert!(nm.list_disabled_nodes().await.unwrap().is_empty());
+
+        let stored = storage.get_nodes().unwrap();
+        assert!(!stored.nodes.get(&node_identity.uuid).unwrap().is_archived());
+    }
+
+    #[test]
+    async fn quarantine_node_that_fails_to_start() {
+        let test_name = "quarantine_node_that_fails_to_start";
+        log!("{}", test_name);
+
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &seed.to_seed("")).unwrap();
+        let c = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c.clone())
+            .build()
+            .await
+            .expect("node manager should initialize");
+
+        let broken = nm.new_node().await.expect("should create new node");
+        let working = nm.new_node().await.expect("should create new node");
+        nm.stop().await.unwrap();
+
+        // corrupt the channel manager of the first node
+        let value = VersionedValue {
+            version: u32::MAX,
+            value: serde_json::to_value("00").unwrap(),
+        };
+        let key = format!("{CHANNEL_MANAGER_KEY}_{}", broken.uuid);
+        storage.set_data(key, value, None).unwrap();
+
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c.clone())
+            .build()
+            .await
+            .expect("node manager should initialize with a broken node");
+
+        assert_eq!(nm.list_nodes().await.unwrap(), vec![working.pubkey]);
+        let failed = nm.list_failed_nodes().await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].uuid, broken.uuid);
+        assert_eq!(failed[0].child_index, 0);
+
+        // the broken node is kept in storage
+        assert_eq!(storage.get_nodes().unwrap().nodes.len(), 2);
+        nm.stop().await.unwrap();
+
+        // once it has channels to watch, we don't start without it
+        let persister =
+            MutinyNodePersister::new(broken.uuid.clone(), storage.clone(), nm.logger.clone());
+        let key = persister.get_monitor_key(&lightning::chain::transaction::OutPoint {
+            txid: Txid::all_zeros(),
+            index: 0,
+        });
+        storage.set_data(key, "00", None).unwrap();
+
+        let res = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c)
+            .build()
+            .await;
+        assert!(res.is_err());
+    }
+
+    #[test]
+    async fn export_and_import_scorer() {
+        let test_name = "export_and_import_scorer";
+        log!("{}", test_name);
+
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &seed.to_seed("")).unwrap();
+        let storage = MemoryStorage::default();
+        let c = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c)
+            .build()
+            .await
+            .expect("node manager should initialize");
+
+        let snapshot = nm.export_scorer().unwrap();
+        assert!(!snapshot.is_empty());
+
+        nm.import_scorer(&snapshot).unwrap();
+        assert_eq!(nm.export_scorer().unwrap(), snapshot);
+        assert!(storage
+            .get_data::<String>(PROB_SCORER_KEY)
+            .unwrap()
+            .is_some());
+
+        assert!(nm.import_scorer("not a scorer").is_err());
+    }
+
     #[test]
     async fn created_label_transaction() {
         let test_name = "created_new_nodes";
@@ -2290,6 +3909,7 @@ mod tests {
             bolt11: Some(invoice.clone()),
             payee_pubkey: None,
             last_update: 1681781585,
+            custom_tlvs: vec![],
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
@@ -2306,6 +3926,7 @@ mod tests {
             inbound: true,
             labels: labels.clone(),
             last_updated: 1681781585,
+            custom_tlvs: vec![],
         };
 
         let actual = MutinyInvoice::from(
@@ -2345,6 +3966,7 @@ mod tests {
             bolt11: None,
             payee_pubkey: Some(pubkey),
             last_update: 1681781585,
+            custom_tlvs: vec![],
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
@@ -2361,6 +3983,7 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1681781585,
+            custom_tlvs: vec![],
         };
 
         let actual = MutinyInvoice::from(
@@ -2374,6 +3997,100 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_create_lsp_config() {
+        let url = "https://signet-lsp.mutinywallet.com".to_string();
+        let connection_string =
+            "0371d6fd7d75de2d0372d03ea00e8bacdacb50c27d0eaea0a76a0622eff1f5ef2b@44.219.111.31:39735"
+                .to_string();
+
+        let config = create_lsp_config(Some(url.clone()), None, None).unwrap();
+        assert_eq!(config, Some(LspConfig::new_voltage_flow(url.clone())));
+
+        let token = Some("token".to_string());
+        let expected = Some(LspConfig::new_lsps(
+            connection_string.clone(),
+            token.clone(),
+        ));
+        let config = create_lsp_config(None, Some(connection_string.clone()), token.clone());
+        assert_eq!(config.unwrap(), expected);
+
+        // an LSPS2 LSP can be given as the lsp url
+        let config = create_lsp_config(Some(connection_string.clone()), None, token);
+        assert_eq!(config.unwrap(), expected);
+
+        assert!(create_lsp_config(Some("not a url".to_string()), None, None).is_err());
+        assert!(create_lsp_config(Some(url), Some(connection_string), None).is_err());
+        assert_eq!(
+            create_lsp_config(Some("".to_string()), None, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_liquidity() {
+        let channel =
+            |outbound: u64, inbound: u64, pending: u64, is_usable: bool| ChannelLiquidity {
+                user_chan_id: "".to_string(),
+                outpoint: None,
+                peer: PublicKey::from_str(
+                    "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+                )
+                .unwrap(),
+                size: 100_000,
+                outbound_capacity_msat: outbound,
+                max_send_htlc_msat: outbound,
+                inbound_capacity_msat: inbound,
+                max_receive_htlc_msat: inbound,
+                pending_outbound_htlc_msat: pending,
+                reserve: 1_000,
+                counterparty_reserve: 1_000,
+                is_usable,
+            };
+
+        let liquidity = Liquidity::new(vec![
+            channel(10_000_000, 80_000_000, 5_000_000, true),
+            channel(20_000_000, 70_000_000, 0, true),
+            // not usable, counts for pending but can't be used for new payments
+            channel(50_000_000, 40_000_000, 1_000_000, false),
+        ]);
+
+        assert_eq!(liquidity.channels.len(), 3);
+        assert_eq!(liquidity.max_sendable_msat, 30_000_000);
+        assert_eq!(liquidity.max_receivable_msat, 150_000_000);
+        assert_eq!(liquidity.pending_outbound_htlc_msat, 6_000_000);
+
+        assert_eq!(Liquidity::new(vec![]), Liquidity::default());
+    }
+
+    #[test]
+    fn test_force_close_claim() {
+        let now = 1_700_000_000;
+        let balance = Balance::ClaimableAwaitingConfirmations {
+            amount_satoshis: 50_000,
+            confirmation_height: 800_144,
+        };
+        let claim = ForceCloseClaim::new(OutPoint::null(), None, &balance, 800_000, now);
+        assert_eq!(claim.claim_type, ForceCloseClaimType::AwaitingConfirmations);
+        assert_eq!(claim.amount_sats, 50_000);
+        assert_eq!(claim.confirmations_remaining, Some(144));
+        assert_eq!(claim.expected_sweep_time, Some(now + 144 * 10 * 60));
+
+        // already past the timelock
+        let claim = ForceCloseClaim::new(OutPoint::null(), None, &balance, 800_200, now);
+        assert_eq!(claim.confirmations_remaining, Some(0));
+        assert_eq!(claim.expected_sweep_time, Some(now));
+
+        // we don't know when the closing transaction confirms
+        let balance = Balance::ClaimableOnChannelClose {
+            amount_satoshis: 50_000,
+        };
+        let claim = ForceCloseClaim::new(OutPoint::null(), None, &balance, 800_000, now);
+        assert_eq!(claim.claim_type, ForceCloseClaimType::AwaitingClose);
+        assert_eq!(claim.confirmations_remaining, None);
+        assert_eq!(claim.expected_sweep_time, None);
+    }
+
     #[test]
     fn test_serialize_node_storage() {
         let old1: NodeStorage = serde_json::from_str("{\"nodes\":{\"93ca1ee3-d5f1-42ed-8bd9-042b298c70dc\":{\"archived\":false,\"child_index\":0,\"lsp\":\"https://signet-lsp.mutinywallet.com\"}},\"version\":11}").unwrap();
@@ -2432,6 +4149,7 @@ mod tests {
             fee: None,
             confirmation_time: ConfirmationTime::Unconfirmed { last_seen: 0_u64 },
             labels: vec![],
+            mempool_status: None,
         };
 
         let tx2: TransactionDetails = TransactionDetails {
@@ -2446,6 +4164,7 @@ mod tests {
                 time: 1234,
             },
             labels: vec![],
+            mempool_status: None,
         };
 
         let invoice1: MutinyInvoice = MutinyInvoice {
@@ -2462,6 +4181,7 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1681781585,
+            custom_tlvs: vec![],
         };
 
         let invoice2: MutinyInvoice = MutinyInvoice {
@@ -2478,6 +4198,7 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1781781585,
+            custom_tlvs: vec![],
         };
 
         let invoice3: MutinyInvoice = MutinyInvoice {
@@ -2494,6 +4215,7 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1581781585,
+            custom_tlvs: vec![],
         };
 
         let invoice4: MutinyInvoice = MutinyInvoice {
@@ -2510,6 +4232,7 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1581781585,
+            custom_tlvs: vec![],
         };
 
         let invoice5: MutinyInvoice = MutinyInvoice {
@@ -2526,6 +4249,7 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1781781585,
+            custom_tlvs: vec![],
         };
 
         let mut vec = vec![
@@ -0,0 +1,137 @@
+//! The relay subscription the wallet listens on for NWC requests, DMs and contact lists,
+//! see [crate::MutinyWallet::start_nostr].
+
+use crate::error::MutinyError;
+use crate::utils::compare_filters_vec;
+use nostr::{Filter, SubscriptionId};
+use nostr_sdk::{Client, RelayPoolNotification};
+use std::collections::HashSet;
+use tokio::sync::broadcast::Receiver;
+
+/// Id of our subscription, subscribing with it again replaces its filters on the relays
+/// instead of opening another subscription next to it
+const LISTENER_SUBSCRIPTION_ID: &str = "mutiny-listener";
+/// Most events we handle in one go before checking the filters and if we stopped again
+pub(crate) const MAX_EVENT_BATCH: usize = 50;
+
+/// Keeps one client connected to our relays for as long as its relay pool is up,
+/// and only sends the relays our filters again when they changed.
+pub(crate) struct NostrListener {
+    client: Client,
+    id: SubscriptionId,
+    /// The filters we are subscribed to, empty if we aren't
+    filters: Vec<Filter>,
+    /// The relays we added to the client
+    relays: HashSet<String>,
+}
+
+impl NostrListener {
+    pub(crate) fn new() -> Self {
+        Self {
+            client: Client::default(),
+            id: SubscriptionId::new(LISTENER_SUBSCRIPTION_ID),
+            filters: vec![],
+            relays: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn notifications(&self) -> Receiver<RelayPoolNotification> {
+        self.client.notifications()
+    }
+
+    /// Connects to the relays we don't have yet, the others stay connected.
+    /// Returns if there were new relays.
+    pub(crate) async fn connect(&mut self, relays: Vec<String>) -> Result<bool, MutinyError> {
+        let new: Vec<String> = relays
+            .into_iter()
+            .filter(|r| !self.relays.contains(r))
+            .collect();
+        if new.is_empty() {
+            return Ok(false);
+        }
+
+        self.client.add_relays(new.clone()).await?;
+        self.client.connect().await;
+        self.relays.extend(new);
+        Ok(true)
+    }
+
+    /// Subscribes to the filters if they are different from the ones we have.
+    /// Returns if the subscription was updated.
+    pub(crate) async fn update_filters(&mut self, filters: Vec<Filter>) -> bool {
+        let filters = dedup_filters(filters);
+        if !self.filters.is_empty() && compare_filters_vec(&filters, &self.filters) {
+            return false;
+        }
+
+        self.subscribe(filters).await;
+        true
+    }
+
+    /// Subscribes to the filters even if we already have them, so the relays send us
+    /// the events since the `since` of the filters again
+    pub(crate) async fn resubscribe(&mut self, filters: Vec<Filter>) {
+        self.subscribe(dedup_filters(filters)).await;
+    }
+
+    async fn subscribe(&mut self, filters: Vec<Filter>) {
+        if filters.is_empty() {
+            self.client.unsubscribe(self.id.clone()).await;
+        } else {
+            self.client
+                .subscribe_with_id(self.id.clone(), filters.clone(), None)
+                .await;
+        }
+        self.filters = filters;
+    }
+
+    /// Disconnects from the relays, a new listener is needed after the relay pool shut down
+    pub(crate) async fn disconnect(&mut self) -> Result<(), MutinyError> {
+        self.filters.clear();
+        self.relays.clear();
+        Ok(self.client.disconnect().await?)
+    }
+}
+
+/// Removes duplicate filters and filters that can't match anything,
+/// like the DM filter when none of our contacts have an npub.
+pub(crate) fn dedup_filters(filters: Vec<Filter>) -> Vec<Filter> {
+    let mut deduped: Vec<Filter> = Vec::with_capacity(filters.len());
+    for filter in filters {
+        if filter.authors.as_ref().is_some_and(|a| a.is_empty()) {
+            continue;
+        }
+        if !deduped.contains(&filter) {
+            deduped.push(filter);
+        }
+    }
+    deduped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nostr::{Keys, Kind, Timestamp};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_dedup_filters() {
+        let pk = Keys::generate().public_key();
+        let since = Timestamp::from(1_700_000_000);
+        let nwc = Filter::new()
+            .kind(Kind::WalletConnectRequest)
+            .author(pk)
+            .since(since);
+        let contacts = Filter::new().kind(Kind::ContactList).author(pk);
+        let empty_dm = Filter::new()
+            .kind(Kind::EncryptedDirectMessage)
+            .authors(vec![])
+            .pubkey(pk);
+
+        let filters = vec![nwc.clone(), contacts.clone(), nwc.clone(), empty_dm];
+        assert_eq!(dedup_filters(filters), vec![nwc, contacts]);
+        assert!(dedup_filters(vec![]).is_empty());
+    }
+}
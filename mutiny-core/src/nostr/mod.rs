@@ -8,6 +8,7 @@ use crate::nostr::nwc::{
     NwcProfile, NwcProfileTag, PendingNwcInvoice, Profile, SingleUseSpendingConditions,
     SpendingConditions, PENDING_NWC_EVENTS_KEY,
 };
+use crate::nostr::outbox::Outbox;
 use crate::nostr::primal::PrimalApi;
 use crate::storage::{update_nostr_contact_list, MutinyStorage, NOSTR_CONTACT_LIST};
 use crate::utils::fetch_with_timeout;
@@ -30,10 +31,11 @@ use nostr::nips::nip47::*;
 use nostr::prelude::{Coordinate, EventIdOrCoordinate};
 use nostr::{
     nips::nip04::{decrypt, encrypt},
+    nips::nip46,
     Alphabet, Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Metadata, SecretKey,
-    SingleLetterTag, Tag, TagKind, Timestamp,
+    SingleLetterTag, Tag, TagKind, Timestamp, UnsignedEvent,
 };
-use nostr_sdk::{Client, NostrSigner, RelayPoolNotification};
+use nostr_sdk::{Client, Nip46Signer, NostrSigner, RelayPoolNotification};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
@@ -42,9 +44,12 @@ use std::time::Duration;
 use std::{str::FromStr, sync::atomic::AtomicBool};
 use url::Url;
 
-mod client;
+pub(crate) mod client;
+pub(crate) mod listener;
 pub mod nip49;
+pub(crate) mod nip78;
 pub mod nwc;
+pub mod outbox;
 pub(crate) mod primal;
 
 const PROFILE_ACCOUNT_INDEX: u32 = 0;
@@ -52,13 +57,18 @@ const NWC_ACCOUNT_INDEX: u32 = 1;
 pub(crate) const SERVICE_ACCOUNT_INDEX: u32 = 2;
 
 pub(crate) const HERMES_CHAIN_INDEX: u32 = 0;
+pub(crate) const LN_ADDRESS_CHAIN_INDEX: u32 = 1;
+const NIP46_CHAIN_INDEX: u32 = 2;
+
+/// How long we wait for a remote signer to answer
+const REMOTE_SIGNER_TIMEOUT_SECS: u64 = 60;
 
 const USER_NWC_PROFILE_START_INDEX: u32 = 1000;
 
 /// The number of trusted users we query for mint recommendations
 const NUM_TRUSTED_USERS: u32 = 1_000;
 
-const NWC_STORAGE_KEY: &str = "nwc_profiles";
+pub(crate) const NWC_STORAGE_KEY: &str = "nwc_profiles";
 
 const DEFAULT_RELAY: &str = "wss://relay.mutinywallet.com";
 
@@ -104,6 +114,87 @@ pub enum NostrKeySource {
     /// Get keys from NIP-07 extension
     #[cfg(target_arch = "wasm32")]
     Extension(nostr::PublicKey),
+    /// Sign with a NIP-46 remote signer, see [connect_remote_signer]
+    RemoteSigner {
+        public_key: nostr::PublicKey,
+        signer: Box<Nip46Signer>,
+    },
+}
+
+/// Connects to the remote signer of a `bunker://` URI over NIP-46.
+///
+/// Only the primary nostr key is remote, NWC and service keys are still derived
+/// from our seed. The app key the signer sees is derived from our seed too,
+/// so it stays the same across restarts.
+pub async fn connect_remote_signer(
+    xprivkey: ExtendedPrivKey,
+    bunker_uri: &str,
+) -> Result<NostrKeySource, MutinyError> {
+    let (signer_public_key, relay_url, secret) = parse_bunker_uri(bunker_uri)?;
+    let app_keys = derive_nostr_key(
+        &Secp256k1::new(),
+        xprivkey,
+        SERVICE_ACCOUNT_INDEX,
+        Some(NIP46_CHAIN_INDEX),
+        None,
+    )?;
+
+    let signer = Nip46Signer::new(
+        relay_url,
+        app_keys,
+        Some(signer_public_key),
+        Duration::from_secs(REMOTE_SIGNER_TIMEOUT_SECS),
+    )
+    .await?;
+    // the signer only accepts us with the secret of the URI, when it has one
+    let connect = nip46::Request::Connect {
+        public_key: signer_public_key,
+        secret,
+    };
+    if signer
+        .send_req_to_signer(connect, None)
+        .await?
+        .error
+        .is_some()
+    {
+        return Err(MutinyError::NostrError);
+    }
+    // the signer can hold a different key than the one it talks to us with
+    let public_key = NostrSigner::NIP46(Box::new(signer.clone()))
+        .public_key()
+        .await?;
+
+    Ok(NostrKeySource::RemoteSigner {
+        public_key,
+        signer: Box::new(signer),
+    })
+}
+
+/// Parses the signer's public key, relay and optional secret from a
+/// `bunker://<pubkey>?relay=<url>&secret=<secret>` URI
+fn parse_bunker_uri(
+    bunker_uri: &str,
+) -> Result<(nostr::PublicKey, Url, Option<String>), MutinyError> {
+    let uri = Url::parse(bunker_uri).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    if uri.scheme() != "bunker" {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let public_key = uri
+        .host_str()
+        .and_then(|pk| nostr::PublicKey::from_str(pk).ok())
+        .ok_or(MutinyError::InvalidArgumentsError)?;
+    let relay_url = uri
+        .query_pairs()
+        .find(|(key, _)| key == "relay")
+        .and_then(|(_, relay)| Url::parse(&relay).ok())
+        .ok_or(MutinyError::InvalidArgumentsError)?;
+    let secret = uri
+        .query_pairs()
+        .find(|(key, _)| key == "secret")
+        .map(|(_, secret)| secret.into_owned());
+
+    Ok((public_key, relay_url, secret))
 }
 
 /// Keys we use to sign nostr events
@@ -145,6 +236,9 @@ impl NostrKeys {
                 let signer = NostrSigner::NIP07(nip07);
                 (signer, public_key)
             }
+            NostrKeySource::RemoteSigner { public_key, signer } => {
+                (NostrSigner::NIP46(signer), public_key)
+            }
         };
 
         Ok(NostrKeys { signer, public_key })
@@ -171,6 +265,8 @@ pub struct NostrManager<S: MutinyStorage, P: PrimalApi, C: NostrClient> {
     pub stop: Arc<AtomicBool>,
     /// Nostr client
     pub client: C,
+    /// Events waiting to be published over the client, see [NostrManager::publish_event]
+    pub(crate) outbox: Arc<Outbox>,
     /// Primal client
     pub primal_client: P,
 }
@@ -345,6 +441,7 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
             NostrSigner::Keys(keys) => keys.secret_key().ok().cloned(),
             #[cfg(target_arch = "wasm32")]
             NostrSigner::NIP07(_) => None,
+            NostrSigner::NIP46(_) => None,
         }
     }
 
@@ -482,6 +579,19 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
         Ok(nwc)
     }
 
+    /// Gets the filters for the given power mode, while the app is in the background
+    /// we only listen for NWC requests to reduce relay subscriptions.
+    pub(crate) async fn get_filters_for_mode(
+        &self,
+        background: bool,
+    ) -> Result<Vec<Filter>, MutinyError> {
+        if background {
+            self.get_nwc_filters()
+        } else {
+            self.get_filters().await
+        }
+    }
+
     /// Sets the user's nostr profile metadata
     pub async fn edit_profile(
         &self,
@@ -602,14 +712,7 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
         }
 
         // create real relay list
-        {
-            let builder = EventBuilder::relay_list(
-                RELAYS
-                    .iter()
-                    .map(|x| (nostr::UncheckedUrl::from(x.to_string()), None)),
-            );
-            self.client.send_event_builder(builder).await?;
-        }
+        self.publish_relay_list().await?;
 
         // create metadata
         let metadata = {
@@ -640,6 +743,32 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
         Ok(metadata)
     }
 
+    /// Publishes the given metadata as the user's nostr profile, replacing the current one,
+    /// along with our NIP-65 relay list so others know where to find our events
+    pub async fn set_nostr_profile(&self, metadata: Metadata) -> Result<Metadata, MutinyError> {
+        let builder = EventBuilder::metadata(&metadata);
+        let event_id = self.client.send_event_builder(builder).await?;
+        log_info!(self.logger, "New kind 0: {event_id}");
+        self.storage.set_nostr_profile(&metadata)?;
+
+        self.publish_relay_list().await?;
+
+        Ok(metadata)
+    }
+
+    /// Publishes the relays we read from and write to as a NIP-65 relay list
+    async fn publish_relay_list(&self) -> Result<(), MutinyError> {
+        let builder = EventBuilder::relay_list(
+            RELAYS
+                .iter()
+                .map(|x| (nostr::UncheckedUrl::from(x.to_string()), None)),
+        );
+        let event_id = self.client.send_event_builder(builder).await?;
+        log_info!(self.logger, "New relay list: {event_id}");
+
+        Ok(())
+    }
+
     /// Sets the user's nostr profile metadata as deleted
     pub async fn delete_profile(&self) -> Result<Metadata, MutinyError> {
         let metadata = Metadata::default()
@@ -1013,6 +1142,20 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
         Ok(nwc_profile)
     }
 
+    /// Requires the app connecting to the profile to present a NIP-13 proof of work
+    /// of the given difficulty on its first request before the profile activates.
+    /// Passing `None` removes the requirement.
+    pub fn set_nwc_profile_required_pow(
+        &self,
+        profile_index: u32,
+        difficulty: Option<u8>,
+    ) -> Result<NwcProfile, MutinyError> {
+        let mut profile = self.get_nwc_profile(profile_index)?;
+        profile.required_pow = difficulty;
+        profile.pow_verified = false;
+        self.edit_nwc_profile(profile)
+    }
+
     pub fn get_nwc_profile(&self, index: u32) -> Result<NwcProfile, MutinyError> {
         let profiles = self.nwc.read().unwrap();
 
@@ -1087,6 +1230,8 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
             commands: Some(commands),
             tag,
             label,
+            required_pow: None,
+            pow_verified: false,
         };
 
         let nwc = NostrWalletConnect::new(&Secp256k1::new(), self.xprivkey, profile)?;
@@ -1133,6 +1278,8 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
             client_key: None,
             label: None,
             commands: Some(commands),
+            required_pow: None,
+            pow_verified: false,
         };
         let nwc = NostrWalletConnect::new(&Secp256k1::new(), self.xprivkey, profile)?;
 
@@ -1244,18 +1391,12 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
             }
 
             if let Some(event) = nwc.create_auth_confirmation_event(relay, secret, commands)? {
-                let id = self.client.send_event(event).await.map_err(|e| {
-                    MutinyError::Other(anyhow::anyhow!(
-                        "Failed to send nwa confirmation event: {e:?}"
-                    ))
-                })?;
+                let id = self.publish_event(event).await;
                 log_info!(self.logger, "Broadcast NWA confirmation event: {id}");
             }
 
             let info_event = nwc.create_nwc_info_event()?;
-            let id = self.client.send_event(info_event).await.map_err(|e| {
-                MutinyError::Other(anyhow::anyhow!("Failed to send info event: {e:?}"))
-            })?;
+            let id = self.publish_event(info_event).await;
             log_info!(self.logger, "Broadcast NWC info event: {id}");
         } else {
             log_error!(self.logger, "Failed to create info & auth event");
@@ -1275,6 +1416,16 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
             .unwrap_or_default())
     }
 
+    /// Lists all pending payment requests that were received over direct messages.
+    /// These are stored with the pending NWC invoices but are not tied to a NWC profile.
+    pub fn get_pending_dm_requests(&self) -> Result<Vec<PendingNwcInvoice>, MutinyError> {
+        let pending = self.get_pending_nwc_invoices()?;
+        Ok(pending
+            .into_iter()
+            .filter(|inv| inv.index.is_none() && !inv.is_expired())
+            .collect())
+    }
+
     fn find_nwc_data(
         &self,
         hash: &sha256::Hash,
@@ -1486,9 +1637,7 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
                                 MutinyError::Other(anyhow::anyhow!("Failed to create event: {e:?}"))
                             })?;
 
-                    self.client.send_event(response).await.map_err(|e| {
-                        MutinyError::Other(anyhow::anyhow!("Failed to send info event: {e:?}"))
-                    })?;
+                    self.publish_event(response).await;
                 }
             }
         }
@@ -1903,7 +2052,47 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
                 let decrypted = nip07.nip04_decrypt(pubkey, message).await?;
                 Ok(decrypted)
             }
+            signer @ NostrSigner::NIP46(_) => {
+                let decrypted = signer.nip04_decrypt(pubkey, message).await?;
+                Ok(decrypted)
+            }
+        }
+    }
+
+    /// Encrypts a DM using the primary key
+    pub async fn encrypt_dm(
+        &self,
+        pubkey: nostr::PublicKey,
+        message: &str,
+    ) -> Result<String, MutinyError> {
+        match &self.nostr_keys.read().await.signer {
+            NostrSigner::Keys(key) => {
+                let secret = key.secret_key().expect("must have");
+                let encrypted = encrypt(secret, &pubkey, message)?;
+                Ok(encrypted)
+            }
+            #[cfg(target_arch = "wasm32")]
+            NostrSigner::NIP07(nip07) => {
+                let encrypted = nip07.nip04_encrypt(pubkey, message).await?;
+                Ok(encrypted)
+            }
+            signer @ NostrSigner::NIP46(_) => {
+                let encrypted = signer.nip04_encrypt(pubkey, message).await?;
+                Ok(encrypted)
+            }
+        }
+    }
+
+    /// Signs an event with the primary key, so the wallet can act as
+    /// the user's signer for other nostr apps. The event must be from our npub.
+    pub async fn sign_event(&self, unsigned: UnsignedEvent) -> Result<Event, MutinyError> {
+        let keys = self.nostr_keys.read().await;
+        if unsigned.pubkey != keys.public_key {
+            return Err(MutinyError::InvalidArgumentsError);
         }
+
+        let event = keys.signer.sign_event(unsigned).await?;
+        Ok(event)
     }
 
     pub async fn send_dm(
@@ -2345,6 +2534,7 @@ impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
             logger,
             stop,
             client,
+            outbox: Arc::new(Outbox::default()),
         })
     }
 }
@@ -2525,7 +2715,6 @@ mod test {
             .return_const(MutinyLogger::default());
         inv_handler.expect_skip_hodl_invoices().return_const(true);
 
-        #[allow(irrefutable_let_patterns)] // need this because enum with single variant
         let nostr_keys =
             if let NostrSigner::Keys(ref keys) = nostr_manager.nostr_keys.read().await.signer {
                 keys.clone()
@@ -2621,7 +2810,20 @@ mod test {
         .unwrap();
         block_on(nostr_manager.handle_direct_message(dm, &inv_handler)).unwrap();
         let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
-        assert!(!pending.is_empty())
+        assert!(!pending.is_empty());
+
+        // dm'd invoices should show up as payment requests
+        let requests = nostr_manager.get_pending_dm_requests().unwrap();
+        assert!(!requests.is_empty());
+        assert!(requests.iter().all(|r| r.pubkey == user.public_key()));
+
+        // declining the request should remove it
+        nostr_manager
+            .deny_invoice(*invoice.payment_hash())
+            .await
+            .unwrap();
+        let requests = nostr_manager.get_pending_dm_requests().unwrap();
+        assert!(requests.is_empty());
     }
 
     #[tokio::test]
@@ -2734,6 +2936,8 @@ mod test {
             commands: None,
             tag: Default::default(),
             label: None,
+            required_pow: None,
+            pow_verified: false,
         };
         let mut profiles = nostr_manager.nwc.write().unwrap();
         let nwc = NostrWalletConnect::new(
@@ -2879,6 +3083,87 @@ mod test {
         assert_eq!(profiles[0].index, 1000);
     }
 
+    #[tokio::test]
+    async fn test_set_required_pow() {
+        let nostr_manager = create_nostr_manager().await;
+
+        let profile = nostr_manager
+            .create_new_nwc_profile_internal(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::default(),
+                Default::default(),
+                vec![Method::PayInvoice],
+            )
+            .unwrap();
+        assert_eq!(profile.required_pow, None);
+
+        let mut profile = nostr_manager
+            .set_nwc_profile_required_pow(profile.index, Some(16))
+            .unwrap();
+        assert_eq!(profile.required_pow, Some(16));
+        assert!(!profile.pow_verified);
+
+        // a new difficulty has to be met again
+        profile.pow_verified = true;
+        nostr_manager.edit_nwc_profile(profile.clone()).unwrap();
+        let profile = nostr_manager
+            .set_nwc_profile_required_pow(profile.index, Some(20))
+            .unwrap();
+        assert_eq!(profile.required_pow, Some(20));
+        assert!(!profile.pow_verified);
+
+        let profile = nostr_manager
+            .set_nwc_profile_required_pow(profile.index, None)
+            .unwrap();
+        assert_eq!(profile.required_pow, None);
+
+        let profiles: Vec<Profile> = nostr_manager
+            .storage
+            .get_data(NWC_STORAGE_KEY)
+            .unwrap()
+            .unwrap();
+        assert_eq!(profiles[0].required_pow, None);
+        assert!(!profiles[0].pow_verified);
+
+        // profiles saved before the requirement existed have none
+        let mut json = serde_json::to_value(&profiles[0]).unwrap();
+        json.as_object_mut().unwrap().remove("pow_verified");
+        let old: Profile = serde_json::from_value(json).unwrap();
+        assert_eq!(old.required_pow, None);
+        assert!(!old.pow_verified);
+    }
+
+    #[tokio::test]
+    async fn test_background_filters() {
+        let nostr_manager = create_nostr_manager().await;
+
+        nostr_manager
+            .create_new_nwc_profile_internal(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::default(),
+                Default::default(),
+                vec![Method::PayInvoice],
+            )
+            .unwrap();
+
+        // the foreground also listens for DMs and contact list updates
+        let filters = nostr_manager.get_filters_for_mode(false).await.unwrap();
+        assert_eq!(filters.len(), 3);
+
+        // the background only listens for NWC requests
+        let filters = nostr_manager.get_filters_for_mode(true).await.unwrap();
+        assert_eq!(filters.len(), 1);
+        let kinds: Vec<Kind> = filters
+            .into_iter()
+            .flat_map(|f| f.kinds.unwrap_or_default())
+            .collect();
+        assert_eq!(kinds, vec![Kind::WalletConnectRequest]);
+    }
+
     #[tokio::test]
     async fn test_delete_profile() {
         let nostr_manager = create_nostr_manager().await;
@@ -2951,6 +3236,24 @@ mod test {
         assert_eq!(pending.len(), 0);
     }
 
+    #[test]
+    fn test_parse_bunker_uri() {
+        let pk = Keys::generate().public_key();
+        let uri = format!("bunker://{pk}?relay=wss%3A%2F%2Frelay.example.com&secret=abc");
+        let (public_key, relay, secret) = parse_bunker_uri(&uri).unwrap();
+        assert_eq!(public_key, pk);
+        assert_eq!(relay.as_str(), "wss://relay.example.com/");
+        assert_eq!(secret.as_deref(), Some("abc"));
+
+        // the secret is optional
+        let uri = format!("bunker://{pk}?relay=wss%3A%2F%2Frelay.example.com");
+        assert_eq!(parse_bunker_uri(&uri).unwrap().2, None);
+
+        // needs a relay to reach the signer
+        assert!(parse_bunker_uri(&format!("bunker://{pk}")).is_err());
+        assert!(parse_bunker_uri(&format!("nostrconnect://{pk}?relay=wss://a.com")).is_err());
+    }
+
     #[test]
     fn test_sort_discovered_federations() {
         let most_recommendations_newer = NostrDiscoveredFedimint {
@@ -3290,6 +3593,34 @@ mod test {
         assert_eq!(list.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_sync_social_backup_waits_for_relays() {
+        let mut nostr_manager = create_nostr_manager().await;
+        nostr_manager
+            .client
+            .expect_get_events_of()
+            .times(3)
+            .returning(|_, _| Ok(vec![]));
+        nostr_manager
+            .client
+            .expect_send_event_builder()
+            .once()
+            .returning(|e| Ok(e.to_event(&Keys::generate()).unwrap().id));
+        nostr_manager
+            .storage
+            .create_new_contact(Contact {
+                name: "Alice".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // the relays might have timed out, a new install doesn't publish right away
+        let key = crate::scb::backup_encryption_key(nostr_manager.xprivkey).unwrap();
+        assert!(!nostr_manager.sync_social_backup(&key).await.unwrap());
+        assert!(!nostr_manager.sync_social_backup(&key).await.unwrap());
+        assert!(nostr_manager.sync_social_backup(&key).await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_change_nostr_keys() {
         let mut nostr_manager = create_nostr_manager().await;
@@ -3425,6 +3756,18 @@ mod test {
         assert_eq!(profile.about, None);
         assert!(profile.custom.is_empty());
 
+        // replace the whole profile
+        let metadata = Metadata::default()
+            .name("new name")
+            .about("about me")
+            .lud16("satoshi@example.com");
+        let set = nostr_manager
+            .set_nostr_profile(metadata.clone())
+            .await
+            .unwrap();
+        assert_eq!(set, metadata);
+        assert_eq!(nostr_manager.get_profile().unwrap(), metadata);
+
         // delete profile
         let deleted = nostr_manager.delete_profile().await.unwrap();
 
@@ -3440,4 +3783,35 @@ mod test {
         assert_eq!(profile.custom.len(), 1);
         assert_eq!(profile.custom.get("deleted").unwrap().as_bool(), Some(true));
     }
+
+    #[tokio::test]
+    async fn test_sign_event_and_encrypt_dm() {
+        let nostr_manager = create_nostr_manager().await;
+        let npub = nostr_manager.get_npub().await;
+
+        let unsigned = EventBuilder::new_text_note("hello", []).to_unsigned_event(npub);
+        let event = nostr_manager.sign_event(unsigned).await.unwrap();
+        assert_eq!(event.pubkey, npub);
+        assert_eq!(event.content, "hello");
+        event.verify().unwrap();
+
+        // can't sign for someone else
+        let other = Keys::generate();
+        let unsigned =
+            EventBuilder::new_text_note("hello", []).to_unsigned_event(other.public_key());
+        assert!(nostr_manager.sign_event(unsigned).await.is_err());
+
+        // encrypted dms can be read by the receiver and decrypted by us
+        let encrypted = nostr_manager
+            .encrypt_dm(other.public_key(), "secret")
+            .await
+            .unwrap();
+        let decrypted = decrypt(other.secret_key().unwrap(), &npub, &encrypted).unwrap();
+        assert_eq!(decrypted, "secret");
+        let decrypted = nostr_manager
+            .decrypt_dm(other.public_key(), &encrypted)
+            .await
+            .unwrap();
+        assert_eq!(decrypted, "secret");
+    }
 }
@@ -0,0 +1,301 @@
+//! Encrypted backups of our social data, contacts, labels and NWC profiles, to the relays
+//! as [NIP-78](https://github.com/nostr-protocol/nips/blob/master/78.md) application data.
+//! This lets a new install recover them when VSS isn't used.
+
+use crate::encrypt::{decrypt_with_key, encrypt_with_key};
+use crate::error::MutinyError;
+use crate::labels::{
+    ADDRESS_LABELS_MAP_KEY, CONTACT_GROUP_PREFIX, CONTACT_PREFIX, INVOICE_LABELS_MAP_KEY,
+    LABEL_PREFIX,
+};
+use crate::nostr::client::NostrClient;
+use crate::nostr::nwc::{NostrWalletConnect, Profile};
+use crate::nostr::primal::PrimalApi;
+use crate::nostr::{NostrManager, NWC_STORAGE_KEY};
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_info};
+use nostr::{EventBuilder, Filter, Kind, Tag};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Kind of NIP-78 application data events
+const APP_DATA_KIND: u64 = 30078;
+/// The `d` tag of our backup event, it replaces the previous backup
+const SOCIAL_BACKUP_IDENTIFIER: &str = "mutiny/social-backup";
+/// Hash of the last backup we published, we only publish again when it changes
+const SOCIAL_BACKUP_HASH_KEY: &str = "social_backup_hash";
+/// How often we check if our social data changed and needs a new backup
+pub(crate) const SOCIAL_BACKUP_CHECK_INTERVAL_SECS: u64 = 60 * 60;
+/// How long we wait for the relays to give us our backup
+const SOCIAL_BACKUP_FETCH_TIMEOUT_SECS: u64 = 10;
+/// How many checks in a row have to find no backup before a new install publishes its own,
+/// relays that time out look the same as not having one
+const SOCIAL_BACKUP_EMPTY_FETCHES: u32 = 3;
+/// How many checks in a row found no backup on the relays
+const SOCIAL_BACKUP_EMPTY_FETCHES_KEY: &str = "social_backup_empty_fetches";
+
+/// Label maps that are merged with the ones we have on restore, instead of only added
+const MERGED_KEYS: [&str; 2] = [ADDRESS_LABELS_MAP_KEY, INVOICE_LABELS_MAP_KEY];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SocialBackup {
+    /// The stored contacts, contact groups and labels by their storage key
+    pub items: BTreeMap<String, Value>,
+    pub nwc_profiles: Vec<Profile>,
+    /// Unix timestamp of when the backup was created
+    pub created_at: u64,
+}
+
+impl SocialBackup {
+    pub(crate) fn from_storage<S: MutinyStorage>(
+        storage: &S,
+        created_at: u64,
+    ) -> Result<Self, MutinyError> {
+        let mut items: BTreeMap<String, Value> = BTreeMap::new();
+        items.extend(storage.scan::<Value>(CONTACT_PREFIX, None)?);
+        items.extend(storage.scan::<Value>(CONTACT_GROUP_PREFIX, None)?);
+        items.extend(storage.scan::<Value>(LABEL_PREFIX, None)?);
+        for key in MERGED_KEYS {
+            if let Some(value) = storage.get_data::<Value>(key)? {
+                items.insert(key.to_string(), value);
+            }
+        }
+        let nwc_profiles = storage.get_data(NWC_STORAGE_KEY)?.unwrap_or_default();
+
+        Ok(Self {
+            items,
+            nwc_profiles,
+            created_at,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.nwc_profiles.is_empty()
+    }
+
+    /// Hash of the data in the backup, without when it was created
+    fn hash(&self) -> Result<sha256::Hash, MutinyError> {
+        let bytes = serde_json::to_vec(&(&self.items, &self.nwc_profiles))?;
+        Ok(sha256::Hash::hash(&bytes))
+    }
+
+    /// Encrypts the backup with a key derived from our seed, so only we can read it
+    pub(crate) fn encrypt(&self, key: &SecretKey) -> Result<String, MutinyError> {
+        let bytes = serde_json::to_vec(self)?;
+        Ok(base64::encode(encrypt_with_key(key, &bytes)))
+    }
+
+    pub(crate) fn decrypt(content: &str, key: &SecretKey) -> Result<Self, MutinyError> {
+        let decrypted = decrypt_with_key(key, base64::decode(content)?)?;
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+
+    /// Saves the contacts and labels we don't have, keeping ours when we have both.
+    /// Returns how many items were restored.
+    pub(crate) fn restore_items<S: MutinyStorage>(
+        &self,
+        storage: &S,
+    ) -> Result<usize, MutinyError> {
+        let mut restored = 0;
+        for (key, value) in &self.items {
+            let current: Option<Value> = storage.get_data(key)?;
+            let value = match (current, value) {
+                (None, value) => value.clone(),
+                (Some(Value::Object(mut current)), Value::Object(backup))
+                    if MERGED_KEYS.contains(&key.as_str()) =>
+                {
+                    let len = current.len();
+                    for (k, v) in backup {
+                        current.entry(k.clone()).or_insert_with(|| v.clone());
+                    }
+                    if current.len() == len {
+                        continue;
+                    }
+                    Value::Object(current)
+                }
+                (Some(_), _) => continue,
+            };
+
+            storage.set_data(key.clone(), value, None)?;
+            restored += 1;
+        }
+
+        Ok(restored)
+    }
+}
+
+impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
+    /// Publishes the backup of our social data, unless it didn't change since the last one.
+    /// Returns if a backup was published.
+    pub(crate) async fn backup_social_data(&self, key: &SecretKey) -> Result<bool, MutinyError> {
+        let backup = SocialBackup::from_storage(&self.storage, crate::utils::now().as_secs())?;
+        // don't replace a backup with nothing
+        if backup.is_empty() {
+            return Ok(false);
+        }
+
+        let hash = backup.hash()?;
+        let last: Option<sha256::Hash> = self.storage.get_data(SOCIAL_BACKUP_HASH_KEY)?;
+        if last == Some(hash) {
+            return Ok(false);
+        }
+
+        let tags = [Tag::Identifier(SOCIAL_BACKUP_IDENTIFIER.to_string())];
+        let builder = EventBuilder::new(Kind::from(APP_DATA_KIND), backup.encrypt(key)?, tags);
+        let event_id = self.client.send_event_builder(builder).await?;
+        self.storage
+            .set_data(SOCIAL_BACKUP_HASH_KEY.to_string(), hash, None)?;
+        log_debug!(self.logger, "Published social backup: {event_id}");
+
+        Ok(true)
+    }
+
+    /// Merges the backup on the relays into our data when it isn't the one we published,
+    /// then publishes ours if it changed. Returns if a backup was published.
+    ///
+    /// We don't publish when the relays couldn't be asked, and a new install waits until
+    /// a few checks found no backup so it doesn't replace one the relays were slow to send.
+    pub(crate) async fn sync_social_backup(&self, key: &SecretKey) -> Result<bool, MutinyError> {
+        let last: Option<sha256::Hash> = self.storage.get_data(SOCIAL_BACKUP_HASH_KEY)?;
+        match self.fetch_social_backup(key).await? {
+            Some(backup) if Some(backup.hash()?) == last => {}
+            Some(backup) => {
+                self.restore_social_backup(&backup)?;
+            }
+            None if last.is_none() => {
+                let empty_fetches = self
+                    .storage
+                    .get_data::<u32>(SOCIAL_BACKUP_EMPTY_FETCHES_KEY)?
+                    .unwrap_or_default()
+                    + 1;
+                if empty_fetches < SOCIAL_BACKUP_EMPTY_FETCHES {
+                    self.storage.set_data(
+                        SOCIAL_BACKUP_EMPTY_FETCHES_KEY.to_string(),
+                        empty_fetches,
+                        None,
+                    )?;
+                    return Ok(false);
+                }
+            }
+            None => {}
+        }
+
+        self.backup_social_data(key).await
+    }
+
+    /// Gets our latest backup from the relays
+    pub(crate) async fn fetch_social_backup(
+        &self,
+        key: &SecretKey,
+    ) -> Result<Option<SocialBackup>, MutinyError> {
+        let filter = Filter::new()
+            .author(self.get_npub().await)
+            .kind(Kind::from(APP_DATA_KIND))
+            .identifier(SOCIAL_BACKUP_IDENTIFIER);
+        let timeout = Duration::from_secs(SOCIAL_BACKUP_FETCH_TIMEOUT_SECS);
+        let events = self
+            .client
+            .get_events_of(vec![filter], Some(timeout))
+            .await?;
+
+        let Some(event) = events.into_iter().max_by_key(|e| e.created_at) else {
+            return Ok(None);
+        };
+        Ok(Some(SocialBackup::decrypt(&event.content, key)?))
+    }
+
+    /// Restores the contacts, labels and NWC profiles we don't have from the backup.
+    /// Returns how many were restored.
+    pub(crate) fn restore_social_backup(
+        &self,
+        backup: &SocialBackup,
+    ) -> Result<usize, MutinyError> {
+        let mut restored = backup.restore_items(&self.storage)?;
+
+        // the NWC keys are derived from our seed, so the profiles work again as they were
+        let context = Secp256k1::new();
+        let mut nwc = self.nwc.write().unwrap();
+        for profile in &backup.nwc_profiles {
+            if nwc.iter().any(|n| n.profile.index == profile.index) {
+                continue;
+            }
+            nwc.push(NostrWalletConnect::new(
+                &context,
+                self.xprivkey,
+                profile.clone(),
+            )?);
+            restored += 1;
+        }
+        let profiles = nwc.iter().map(|x| x.profile.clone()).collect::<Vec<_>>();
+        self.storage
+            .set_data(NWC_STORAGE_KEY.to_string(), profiles, None)?;
+
+        log_info!(self.logger, "Restored {restored} items from social backup");
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::labels::{Contact, LabelStorage};
+    use crate::storage::MemoryStorage;
+    use bitcoin::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_social_backup_restore() {
+        let storage = MemoryStorage::default();
+        let contact = Contact {
+            name: "Alice".to_string(),
+            ..Default::default()
+        };
+        let id = storage.create_new_contact(contact.clone()).unwrap();
+        storage
+            .set_data(
+                ADDRESS_LABELS_MAP_KEY.to_string(),
+                serde_json::json!({ "address": ["Alice"] }),
+                None,
+            )
+            .unwrap();
+
+        let backup = SocialBackup::from_storage(&storage, 1_700_000_000).unwrap();
+        assert!(!backup.is_empty());
+
+        // only we can read it
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[0; 32]).unwrap();
+        let key = crate::scb::backup_encryption_key(xpriv).unwrap();
+        let encrypted = backup.encrypt(&key).unwrap();
+        assert_eq!(SocialBackup::decrypt(&encrypted, &key).unwrap(), backup);
+
+        // restore on a new install that already has a label of its own
+        let new_storage = MemoryStorage::default();
+        new_storage
+            .set_data(
+                ADDRESS_LABELS_MAP_KEY.to_string(),
+                serde_json::json!({ "other": ["Bob"] }),
+                None,
+            )
+            .unwrap();
+        // the contact, its label item and the address labels
+        assert_eq!(backup.restore_items(&new_storage).unwrap(), 3);
+        assert_eq!(
+            new_storage.get_contact(&id).unwrap().unwrap().name,
+            contact.name
+        );
+        let labels: Option<Value> = new_storage.get_data(ADDRESS_LABELS_MAP_KEY).unwrap();
+        let expected = serde_json::json!({ "address": ["Alice"], "other": ["Bob"] });
+        assert_eq!(labels, Some(expected));
+
+        // nothing left to restore
+        assert_eq!(backup.restore_items(&new_storage).unwrap(), 0);
+    }
+}
@@ -4,9 +4,10 @@ use crate::nostr::client::NostrClient;
 use crate::nostr::nip49::NIP49Confirmation;
 use crate::nostr::primal::PrimalApi;
 use crate::nostr::{derive_nwc_keys, NostrManager};
+use crate::receivelimits::{check_invoice_request, record_invoice_created};
 use crate::storage::MutinyStorage;
 use crate::utils;
-use crate::InvoiceHandler;
+use crate::{InvoiceHandler, InvoiceParams};
 use anyhow::anyhow;
 use bitcoin::bip32::ExtendedPrivKey;
 use bitcoin::hashes::hex::FromHex;
@@ -190,6 +191,13 @@ pub(crate) struct Profile {
     pub tag: NwcProfileTag,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
+    /// NIP-13 proof of work difficulty the connecting app must present on its
+    /// first request before the profile activates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_pow: Option<u8>,
+    /// If the connecting app has met the proof of work requirement
+    #[serde(default)]
+    pub pow_verified: bool,
 }
 
 impl Profile {
@@ -455,6 +463,25 @@ impl NostrWalletConnect {
                     .map(Some);
             }
 
+            // hardened profiles only activate once the connecting app
+            // presents enough proof of work, so leaked URIs are harder to abuse
+            if let Some(difficulty) = self.profile.required_pow {
+                if !self.profile.pow_verified {
+                    if !event.check_pow(difficulty) {
+                        return self
+                            .get_skipped_error_event(
+                                &event,
+                                req.method,
+                                ErrorCode::Unauthorized,
+                                "Proof of work required.".to_string(),
+                            )
+                            .map(Some);
+                    }
+                    self.profile.pow_verified = true;
+                    needs_save = true;
+                }
+            }
+
             // only respond to commands that are allowed by the profile
             if !self.profile.available_commands().contains(&req.method) {
                 return self
@@ -480,7 +507,7 @@ impl NostrWalletConnect {
                     .await?
                 }
                 RequestParams::MakeInvoice(params) => {
-                    self.handle_make_invoice_request(event, node, params)
+                    self.handle_make_invoice_request(event, node, &nostr_manager.storage, params)
                         .await?
                 }
                 RequestParams::LookupInvoice(params) => {
@@ -592,18 +619,47 @@ impl NostrWalletConnect {
         &mut self,
         event: Event,
         node: &impl InvoiceHandler,
+        storage: &impl MutinyStorage,
         params: MakeInvoiceRequestParams,
     ) -> anyhow::Result<Option<Event>> {
-        // FIXME currently we are ignoring the description and expiry params
         let amount_sats = params.amount / 1_000;
 
+        if let Err(e) = check_invoice_request(storage, amount_sats, Some(&event.pubkey)) {
+            let code = match e {
+                MutinyError::RequesterDenied => ErrorCode::Unauthorized,
+                _ => ErrorCode::QuotaExceeded,
+            };
+            return self
+                .get_skipped_error_event(&event, Method::MakeInvoice, code, e.to_string())
+                .map(Some);
+        }
+
+        // the description hash commits to the description, so only one goes in the invoice
+        let description_hash = params
+            .description_hash
+            .map(|hash| {
+                FromHex::from_hex(&hash)
+                    .map_err(|e| anyhow!("Failed to parse description_hash {hash}: {e}"))
+            })
+            .transpose()?;
+        let invoice_params = InvoiceParams {
+            expiry_secs: params.expiry.and_then(|e| u32::try_from(e).ok()),
+            description: params.description.filter(|_| description_hash.is_none()),
+            description_hash,
+            min_final_cltv: None,
+            route_hints: None,
+        };
+
         let label = self
             .profile
             .label
             .clone()
             .unwrap_or(self.profile.name.clone());
 
-        let response = match node.create_invoice(amount_sats, vec![label]).await {
+        let response = match node
+            .create_invoice_with_params(amount_sats, vec![label], invoice_params)
+            .await
+        {
             Err(e) => self.get_skipped_error_event(
                 &event,
                 Method::MakeInvoice,
@@ -612,6 +668,9 @@ impl NostrWalletConnect {
             )?,
             Ok(invoice) => {
                 let bolt11 = invoice.bolt11.expect("just made");
+                if let Err(e) = record_invoice_created(storage) {
+                    log_warn!(node.logger(), "Failed to record created invoice: {e}");
+                }
 
                 let content = Response {
                     result_type: Method::MakeInvoice,
@@ -822,6 +881,15 @@ impl NostrWalletConnect {
                                         );
                                         code = ErrorCode::Internal;
                                     } else {
+                                        // the spending policy can be unlocked before approving
+                                        if matches!(
+                                            e,
+                                            MutinyError::SpendingLimitExceeded
+                                                | MutinyError::DestinationNotAllowed
+                                                | MutinyError::SpendingPinRequired
+                                        ) {
+                                            code = ErrorCode::QuotaExceeded;
+                                        }
                                         // for non-timeout errors, add to manual approval list
                                         self.save_pending_nwc_invoice(
                                             nostr_manager,
@@ -1042,6 +1110,9 @@ impl NostrWalletConnect {
                                     MutinyError::InsufficientBalance => {
                                         ErrorCode::InsufficientBalance
                                     }
+                                    MutinyError::SpendingLimitExceeded
+                                    | MutinyError::DestinationNotAllowed
+                                    | MutinyError::SpendingPinRequired => ErrorCode::QuotaExceeded,
                                     MutinyError::PaymentTimeout => return Ok(None), // don't send error message for timeout, it can still complete
                                     _ => ErrorCode::PaymentFailed,
                                 };
@@ -1098,6 +1169,8 @@ impl NostrWalletConnect {
             child_key_index: self.profile.child_key_index,
             tag: self.profile.tag,
             label: self.profile.label.clone(),
+            required_pow: self.profile.required_pow,
+            pow_verified: self.profile.pow_verified,
         }
     }
 }
@@ -1127,6 +1200,13 @@ pub struct NwcProfile {
     pub tag: NwcProfileTag,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
+    /// NIP-13 proof of work difficulty the connecting app must present on its
+    /// first request before the profile activates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_pow: Option<u8>,
+    /// If the connecting app has met the proof of work requirement
+    #[serde(default)]
+    pub pow_verified: bool,
 }
 
 impl NwcProfile {
@@ -1143,6 +1223,8 @@ impl NwcProfile {
             child_key_index: self.child_key_index,
             tag: self.tag,
             label: self.label.clone(),
+            required_pow: self.required_pow,
+            pow_verified: self.pow_verified,
         }
     }
 }
@@ -1521,9 +1603,11 @@ mod wasm_test {
     use crate::storage::MemoryStorage;
     use crate::test_utils::{
         create_dummy_invoice, create_mutiny_wallet, create_nwc_request, sign_nwc_request,
+        sign_nwc_request_with_pow,
     };
     use crate::MockInvoiceHandler;
     use crate::MutinyInvoice;
+    use bitcoin::hashes::Hash;
     use bitcoin::{BlockHash, Network};
     use lightning::chain::BestBlock;
     use mockall::predicate::eq;
@@ -2296,6 +2380,107 @@ mod wasm_test {
         assert_eq!(info.methods, vec!["get_info"]);
     }
 
+    #[test]
+    async fn test_required_pow() {
+        let storage = MemoryStorage::default();
+
+        let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let nostr_manager = NostrManager::from_mnemonic(
+            xprivkey,
+            NostrKeySource::Derived,
+            storage.clone(),
+            MockPrimalApi::new(),
+            get_mock_nostr_client(),
+            Arc::new(MutinyLogger::default()),
+            stop,
+        )
+        .await
+        .unwrap();
+
+        let best_block = BestBlock::new(
+            BlockHash::from_str("000000000000000000017dfbca2b8c975abcf0f86a6b19f38b3e4cafeabf56b0")
+                .unwrap(),
+            6969,
+        );
+
+        let mut node = MockInvoiceHandler::new();
+        node.expect_logger().return_const(MutinyLogger::default());
+        node.expect_get_network().return_const(Network::Regtest);
+        node.expect_get_best_block()
+            .returning(move || Ok(best_block));
+
+        let profile = nostr_manager
+            .create_new_nwc_profile_internal(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::RequireApproval,
+                NwcProfileTag::General,
+                vec![Method::GetInfo],
+            )
+            .unwrap();
+        let profile = nostr_manager
+            .set_nwc_profile_required_pow(profile.index, Some(16))
+            .unwrap();
+        assert_eq!(profile.required_pow, Some(16));
+        assert!(!profile.pow_verified);
+
+        let secp = Secp256k1::new();
+        let mut nwc = NostrWalletConnect::new(&secp, xprivkey, profile.profile()).unwrap();
+        let uri = nwc.get_nwc_uri().unwrap().unwrap();
+
+        // request without proof of work is rejected
+        let event = sign_nwc_request(&uri, Request::get_info());
+        let result = nwc
+            .handle_nwc_request(event, &node, &nostr_manager)
+            .await
+            .unwrap()
+            .unwrap();
+        check_nwc_error_response(
+            result,
+            &uri.secret,
+            NIP47Error {
+                code: ErrorCode::Unauthorized,
+                message: "Proof of work required.".to_string(),
+            },
+        );
+        assert!(
+            !nostr_manager
+                .get_nwc_profile(profile.index)
+                .unwrap()
+                .pow_verified
+        );
+
+        // request with proof of work activates the profile
+        let event = sign_nwc_request_with_pow(&uri, Request::get_info(), 16);
+        let result = nwc
+            .handle_nwc_request(event, &node, &nostr_manager)
+            .await
+            .unwrap()
+            .unwrap();
+        let content = decrypt(&uri.secret, &result.pubkey, &result.content).unwrap();
+        let response: Response = Response::from_json(content).unwrap();
+        assert!(response.error.is_none());
+        assert!(
+            nostr_manager
+                .get_nwc_profile(profile.index)
+                .unwrap()
+                .pow_verified
+        );
+
+        // later requests don't need proof of work
+        let event = sign_nwc_request(&uri, Request::get_info());
+        let result = nwc
+            .handle_nwc_request(event, &node, &nostr_manager)
+            .await
+            .unwrap()
+            .unwrap();
+        let content = decrypt(&uri.secret, &result.pubkey, &result.content).unwrap();
+        let response: Response = Response::from_json(content).unwrap();
+        assert!(response.error.is_none());
+    }
+
     #[test]
     async fn test_make_invoice() {
         let storage = MemoryStorage::default();
@@ -2319,8 +2504,14 @@ mod wasm_test {
 
         let mut node = MockInvoiceHandler::new();
         let mutiny_inv: MutinyInvoice = invoice.clone().into();
-        node.expect_create_invoice()
-            .return_once(|_, _| Ok(mutiny_inv));
+        let description_hash = bitcoin::hashes::sha256::Hash::hash(b"lnurl metadata");
+        node.expect_create_invoice_with_params()
+            .withf(move |_, _, params| {
+                params.description_hash == Some(description_hash)
+                    && params.description.is_none()
+                    && params.expiry_secs == Some(600)
+            })
+            .return_once(|_, _, _| Ok(mutiny_inv));
 
         let profile = nostr_manager
             .create_new_nwc_profile_internal(
@@ -2343,9 +2534,9 @@ mod wasm_test {
             &uri,
             Request::make_invoice(MakeInvoiceRequestParams {
                 amount,
-                description: None,
-                description_hash: None,
-                expiry: None,
+                description: Some("lnurl metadata".to_string()),
+                description_hash: Some(description_hash.to_string()),
+                expiry: Some(600),
             }),
         );
         let result = nwc
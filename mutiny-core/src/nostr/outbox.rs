@@ -0,0 +1,227 @@
+//! Publishing of our events over the relay pool of the [NostrManager], shared by everything
+//! that sends events. Events no relay acknowledges are queued and retried in the background.
+
+use crate::nostr::client::NostrClient;
+use crate::nostr::primal::PrimalApi;
+use crate::nostr::NostrManager;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_warn};
+use nostr::{Event, EventId};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// How long we wait before the first retry, doubled for every retry after it
+const RETRY_BASE_DELAY_SECS: u64 = 5;
+/// Longest we wait between two retries of an event
+const MAX_RETRY_DELAY_SECS: u64 = 2 * 60;
+/// Queued events older than this are dropped, whoever waited for them has given up
+const MAX_QUEUED_SECS: u64 = 10 * 60;
+/// Most events we keep queued, the oldest are dropped first
+const MAX_QUEUED_EVENTS: usize = 100;
+
+/// Counters of the events we published over the relay pool
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RelayPoolMetrics {
+    /// Events a relay acknowledged
+    pub sent: u64,
+    /// Attempts to publish an event that failed and were tried again
+    pub retries: u64,
+    /// Events dropped because no relay acknowledged them in time
+    pub failed: u64,
+    /// Events waiting to be published again
+    pub queued: usize,
+}
+
+struct QueuedEvent {
+    event: Event,
+    /// Unix timestamp of when the event was first queued
+    queued_at: u64,
+    /// How many times sending the event failed
+    attempts: u32,
+    /// Unix timestamp of when we try to send the event again
+    retry_at: u64,
+}
+
+impl QueuedEvent {
+    fn new(event: Event, queued_at: u64) -> Self {
+        let mut queued = Self {
+            event,
+            queued_at,
+            attempts: 0,
+            retry_at: queued_at,
+        };
+        queued.failed(queued_at);
+        queued
+    }
+
+    /// Schedules the next attempt after one failed
+    fn failed(&mut self, now: u64) {
+        let delay = RETRY_BASE_DELAY_SECS.saturating_mul(1 << self.attempts.min(16));
+        self.attempts += 1;
+        self.retry_at = now + delay.min(MAX_RETRY_DELAY_SECS);
+    }
+}
+
+/// Events that no relay acknowledged yet, and the counters of what we published
+#[derive(Default)]
+pub(crate) struct Outbox {
+    queue: Mutex<VecDeque<QueuedEvent>>,
+    metrics: Mutex<RelayPoolMetrics>,
+}
+
+impl Outbox {
+    /// Queues the event, returns the events that were dropped to make room
+    fn enqueue(&self, queued: QueuedEvent) -> u64 {
+        let mut queue = self.queue.lock().unwrap();
+        queue.push_back(queued);
+
+        let mut dropped = 0;
+        while queue.len() > MAX_QUEUED_EVENTS {
+            queue.pop_front();
+            dropped += 1;
+        }
+        dropped
+    }
+
+    /// Takes the queued events that are due to be sent again, the others stay queued.
+    /// Returns them and how many were too old to send.
+    fn take_due(&self, now: u64) -> (Vec<QueuedEvent>, u64) {
+        let mut queue = self.queue.lock().unwrap();
+        let len = queue.len();
+        queue.retain(|q| q.queued_at + MAX_QUEUED_SECS >= now);
+        let expired = (len - queue.len()) as u64;
+
+        let (due, waiting): (Vec<_>, VecDeque<_>) = std::mem::take(&mut *queue)
+            .into_iter()
+            .partition(|q| q.retry_at <= now);
+        *queue = waiting;
+        (due, expired)
+    }
+
+    fn update_metrics(&self, f: impl FnOnce(&mut RelayPoolMetrics)) {
+        f(&mut self.metrics.lock().unwrap())
+    }
+
+    pub(crate) fn metrics(&self) -> RelayPoolMetrics {
+        let mut metrics = *self.metrics.lock().unwrap();
+        metrics.queued = self.queue.lock().unwrap().len();
+        metrics
+    }
+}
+
+impl<S: MutinyStorage, P: PrimalApi, C: NostrClient> NostrManager<S, P, C> {
+    /// Publishes the event over our relay pool. If no relay acknowledges it, the event is
+    /// queued to be sent again with backoff by [NostrManager::flush_outbox], so this
+    /// returns once the event is either sent or queued.
+    pub(crate) async fn publish_event(&self, event: Event) -> EventId {
+        let id = event.id;
+        match self.client.send_event(event.clone()).await {
+            Ok(_) => self.outbox.update_metrics(|m| m.sent += 1),
+            Err(e) => {
+                log_warn!(self.logger, "Failed to publish event {id}, queueing: {e}");
+                let dropped = self
+                    .outbox
+                    .enqueue(QueuedEvent::new(event, utils::now().as_secs()));
+                self.outbox.update_metrics(|m| m.failed += dropped);
+            }
+        }
+        id
+    }
+
+    /// Sends the queued events that are due again, the ones that still fail stay
+    /// queued until they are too old. Returns how many were sent.
+    pub(crate) async fn flush_outbox(&self) -> usize {
+        let now = utils::now().as_secs();
+        let (due, expired) = self.outbox.take_due(now);
+        self.outbox.update_metrics(|m| m.failed += expired);
+
+        let mut sent = 0;
+        for mut queued in due {
+            self.outbox.update_metrics(|m| m.retries += 1);
+            match self.client.send_event(queued.event.clone()).await {
+                Ok(_) => {
+                    self.outbox.update_metrics(|m| m.sent += 1);
+                    sent += 1;
+                }
+                Err(e) => {
+                    log_debug!(
+                        self.logger,
+                        "Failed to publish event {}: {e}",
+                        queued.event.id
+                    );
+                    queued.failed(now);
+                    let dropped = self.outbox.enqueue(queued);
+                    self.outbox.update_metrics(|m| m.failed += dropped);
+                }
+            }
+        }
+        if sent > 0 {
+            log_debug!(self.logger, "Published {sent} queued events");
+        }
+
+        sent
+    }
+
+    /// Counters of the events we published, see [RelayPoolMetrics]
+    pub fn get_relay_pool_metrics(&self) -> RelayPoolMetrics {
+        self.outbox.metrics()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nostr::{EventBuilder, Keys};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_event(content: &str) -> Event {
+        EventBuilder::new_text_note(content, [])
+            .to_event(&Keys::generate())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_outbox_queue() {
+        let outbox = Outbox::default();
+        let now = 1_700_000_000;
+
+        let old = create_event("old");
+        let old_at = now - MAX_QUEUED_SECS - 1;
+        assert_eq!(outbox.enqueue(QueuedEvent::new(old, old_at)), 0);
+        let event = create_event("new");
+        assert_eq!(outbox.enqueue(QueuedEvent::new(event.clone(), now)), 0);
+        assert_eq!(outbox.metrics().queued, 2);
+
+        // the old event is dropped, the new one waits for its retry
+        let (due, expired) = outbox.take_due(now);
+        assert!(due.is_empty());
+        assert_eq!(expired, 1);
+        assert_eq!(outbox.metrics().queued, 1);
+
+        let (mut due, _) = outbox.take_due(now + RETRY_BASE_DELAY_SECS);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].event, event);
+        assert_eq!(outbox.metrics().queued, 0);
+
+        // every failure doubles the wait, up to the max
+        let mut queued = due.remove(0);
+        queued.failed(now);
+        assert_eq!(queued.retry_at, now + RETRY_BASE_DELAY_SECS * 2);
+        for _ in 0..10 {
+            queued.failed(now);
+        }
+        assert_eq!(queued.retry_at, now + MAX_RETRY_DELAY_SECS);
+
+        // the oldest events make room for new ones
+        for _ in 0..MAX_QUEUED_EVENTS {
+            outbox.enqueue(QueuedEvent::new(event.clone(), now));
+        }
+        assert_eq!(outbox.enqueue(QueuedEvent::new(event, now)), 1);
+        assert_eq!(outbox.metrics().queued, MAX_QUEUED_EVENTS);
+    }
+}
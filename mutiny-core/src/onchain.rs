@@ -1,30 +1,37 @@
-use anyhow::anyhow;
-use std::collections::HashSet;
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 
-use bdk::chain::{BlockId, ConfirmationTime};
+use bdk::chain::{BlockId, ConfirmationTime, ConfirmationTimeHeightAnchor, TxGraph};
 use bdk::psbt::PsbtUtils;
-use bdk::template::DescriptorTemplateOut;
+use bdk::template::{Bip44, Bip49, Bip84, Bip86, DescriptorTemplate, DescriptorTemplateOut};
 use bdk::wallet::{AddressIndex, Update};
-use bdk::{FeeRate, LocalOutput, SignOptions, Wallet};
+use bdk::{FeeRate, KeychainKind, LocalOutput, SignOptions, Wallet};
 use bdk_chain::indexed_tx_graph::Indexer;
 use bdk_esplora::EsploraAsyncExt;
+use bip39::Mnemonic;
 use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
 use bitcoin::consensus::serialize;
 use bitcoin::psbt::{Input, PartiallySignedTransaction};
-use bitcoin::{Address, Network, OutPoint, ScriptBuf, Transaction, Txid};
+use bitcoin::{
+    absolute, Address, Network, OutPoint, PrivateKey, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
 use esplora_client::AsyncClient;
 use hex_conservative::DisplayHex;
 use lightning::events::bump_transaction::{Utxo, WalletSource};
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
+use serde::{Deserialize, Serialize};
 
+use crate::chain::ChainSource;
 use crate::error::MutinyError;
 use crate::fees::MutinyFeeEstimator;
 use crate::labels::*;
 use crate::logging::MutinyLogger;
+use crate::search::{transaction_fields, update_search_index};
 use crate::storage::{
     IndexItem, MutinyStorage, OnChainStorage, KEYCHAIN_STORE_KEY, NEED_FULL_SYNC_KEY,
     ONCHAIN_PREFIX,
@@ -34,31 +41,120 @@ use crate::TransactionDetails;
 
 pub(crate) const FULL_SYNC_STOP_GAP: usize = 150;
 pub(crate) const RESTORE_SYNC_STOP_GAP: usize = 20;
+/// Every address is a request to esplora, so deep rescans are capped
+pub(crate) const MAX_GAP_LIMIT: usize = 5_000;
+const PENDING_BROADCAST_PREFIX: &str = "pending_broadcast/";
+const MEMPOOL_STATUS_KEY: &str = "mempool_status";
+const WATCHED_BROADCAST_PREFIX: &str = "watched_broadcast/";
+
+/// Whether one of our unconfirmed transactions is still in the mempool
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MempoolStatus {
+    InMempool,
+    /// The transaction was evicted from the mempool and rebroadcasting it failed,
+    /// we keep rebroadcasting it every sync
+    Dropped,
+}
+
+/// A signed transaction we couldn't broadcast yet, it is retried every sync
+/// until esplora has seen it or it is abandoned.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PendingBroadcast {
+    pub txid: Txid,
+    pub transaction: Transaction,
+    /// Unix timestamp of when the transaction was queued
+    pub created_at: u64,
+    /// How many times we retried broadcasting it
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+/// What sweeping one address type of a foreign key did, see [OnChainWallet::sweep_external]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ExternalSweep {
+    /// The address type that was swept, like `wpkh`
+    pub address_type: String,
+    /// Sats that were swept, fees included
+    pub amount_sats: u64,
+    /// The sweep transaction, None if the sweep failed
+    pub txid: Option<Txid>,
+    /// Why the sweep failed
+    pub error: Option<String>,
+}
+
+/// Our inputs and outputs in a collaborative transaction, see [CollaborativeSpend]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CollaborativeContribution {
+    /// The UTXOs we spend and the outputs they spend, needed to sign
+    pub inputs: Vec<(OutPoint, TxOut)>,
+    /// Our outputs, the change pays our share of the fee
+    pub outputs: Vec<TxOut>,
+}
+
+/// Builds a transaction together with other wallets, like a coinjoin, so privacy
+/// tooling can be built on top of the wallet. The implementation talks to the
+/// other parties, [OnChainWallet::collaborative_spend] makes sure our inputs and
+/// outputs are in the transaction before we sign it.
+///
+/// [crate::coinjoin::NostrCoinjoin] is a 2-party coinjoin over nostr DMs.
+// not Send on every target, implementations usually wait on the nostr client
+#[async_trait(?Send)]
+pub trait CollaborativeSpend {
+    /// Combines our inputs and outputs with the other parties' into an unsigned PSBT,
+    /// every input needs its witness UTXO for us to sign
+    async fn build(
+        &self,
+        ours: &CollaborativeContribution,
+    ) -> Result<PartiallySignedTransaction, MutinyError>;
+
+    /// Gets the other parties' signatures for the PSBT we signed
+    /// and returns the final transaction
+    async fn finalize(
+        &self,
+        signed: PartiallySignedTransaction,
+    ) -> Result<Transaction, MutinyError>;
+}
+
+fn pending_broadcast_key(txid: &Txid) -> String {
+    format!("{PENDING_BROADCAST_PREFIX}{txid}")
+}
+
+fn watched_broadcast_key(txid: &Txid) -> String {
+    format!("{WATCHED_BROADCAST_PREFIX}{txid}")
+}
 
 #[derive(Clone)]
 pub struct OnChainWallet<S: MutinyStorage> {
     pub wallet: Arc<RwLock<Wallet<OnChainStorage<S>>>>,
     pub(crate) storage: S,
     pub network: Network,
+    /// BIP-32 account the wallet and node keys are derived from
+    pub(crate) account_index: u32,
     pub blockchain: Arc<AsyncClient>,
+    /// Used for broadcasting and checking transactions, the esplora client unless replaced
+    /// with [OnChainWallet::with_chain_source]
+    pub(crate) chain_source: Arc<dyn ChainSource + Send + Sync>,
     pub fees: Arc<MutinyFeeEstimator<S>>,
     pub(crate) stop: Arc<AtomicBool>,
+    /// How many unused addresses in a row we look at when syncing after a restore
+    pub(crate) gap_limit: usize,
     logger: Arc<MutinyLogger>,
 }
 
 impl<S: MutinyStorage> OnChainWallet<S> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         xprivkey: ExtendedPrivKey,
         db: S,
         network: Network,
+        account_index: u32,
         esplora: Arc<AsyncClient>,
         fees: Arc<MutinyFeeEstimator<S>>,
         stop: Arc<AtomicBool>,
         logger: Arc<MutinyLogger>,
     ) -> Result<OnChainWallet<S>, MutinyError> {
-        let account_number = 0;
         let (receive_descriptor_template, change_descriptor_template) =
-            get_tr_descriptors_for_extended_key(xprivkey, network, account_number)?;
+            get_tr_descriptors_for_extended_key(xprivkey, network, account_index)?;
 
         // if we have a keychain set, load the wallet, otherwise create one
         let load_wallet_res = Wallet::load(
@@ -98,35 +194,317 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             wallet: Arc::new(RwLock::new(wallet)),
             storage: db,
             network,
+            account_index,
+            chain_source: esplora.clone(),
             blockchain: esplora,
             fees,
             stop,
+            gap_limit: RESTORE_SYNC_STOP_GAP,
             logger,
         })
     }
 
+    /// Sets how many unused addresses in a row we look at when syncing after a restore,
+    /// for wallets that handed out a lot of addresses that were never used
+    pub(crate) fn with_gap_limit(mut self, gap_limit: usize) -> Self {
+        self.gap_limit = gap_limit.clamp(1, MAX_GAP_LIMIT);
+        self
+    }
+
+    /// Replaces the esplora client for broadcasting and checking transactions
+    #[cfg(test)]
+    pub(crate) fn with_chain_source(
+        mut self,
+        chain_source: Arc<dyn ChainSource + Send + Sync>,
+    ) -> Self {
+        self.chain_source = chain_source;
+        self
+    }
+
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
         let txid = tx.txid();
         log_info!(self.logger, "Broadcasting transaction: {txid}");
         log_debug!(self.logger, "Transaction: {}", serialize(&tx).as_hex());
 
-        if let Err(e) = self.blockchain.broadcast(&tx).await {
+        if let Err(e) = self.chain_source.broadcast(&tx).await {
             log_error!(self.logger, "Failed to broadcast transaction ({txid}): {e}");
-            return Err(MutinyError::Other(anyhow!(
-                "Failed to broadcast transaction ({txid}): {e}"
-            )));
-        } else if let Err(e) = self
-            .insert_tx(
-                tx,
-                ConfirmationTime::Unconfirmed {
-                    last_seen: now().as_secs(),
-                },
-                None,
-            )
-            .await
-        {
+            return Err(MutinyError::BroadcastFailed);
+        }
+        self.insert_broadcasted_tx(tx).await;
+
+        Ok(())
+    }
+
+    /// Broadcasts the transaction, if esplora can't be reached the transaction
+    /// is queued and broadcast again on the next sync.
+    /// Transactions rejected by esplora are not queued.
+    async fn broadcast_or_queue(&self, tx: Transaction) -> Result<(), MutinyError> {
+        let txid = tx.txid();
+        log_info!(self.logger, "Broadcasting transaction: {txid}");
+        log_debug!(self.logger, "Transaction: {}", serialize(&tx).as_hex());
+
+        match self.chain_source.broadcast(&tx).await {
+            Ok(()) => {
+                self.insert_broadcasted_tx(tx).await;
+                Ok(())
+            }
+            Err(esplora_client::Error::Reqwest(e)) => {
+                log_warn!(
+                    self.logger,
+                    "Could not reach esplora to broadcast transaction ({txid}), queueing it: {e}"
+                );
+                self.queue_broadcast(tx, e.to_string())
+            }
+            Err(e) => {
+                log_error!(self.logger, "Failed to broadcast transaction ({txid}): {e}");
+                Err(MutinyError::BroadcastFailed)
+            }
+        }
+    }
+
+    /// Adds a transaction we just broadcast to the wallet so we don't have to wait for a sync
+    async fn insert_broadcasted_tx(&self, tx: Transaction) {
+        let txid = tx.txid();
+        let position = ConfirmationTime::Unconfirmed {
+            last_seen: now().as_secs(),
+        };
+        if let Err(e) = self.insert_tx(tx, position, None).await {
             log_warn!(self.logger, "ERROR: Could not sync broadcasted tx ({txid}), will be synced in next iteration: {e:?}");
         }
+    }
+
+    pub(crate) fn queue_broadcast(
+        &self,
+        tx: Transaction,
+        error: String,
+    ) -> Result<(), MutinyError> {
+        let txid = tx.txid();
+        let pending = PendingBroadcast {
+            txid,
+            transaction: tx,
+            created_at: now().as_secs(),
+            attempts: 0,
+            last_error: Some(error),
+        };
+        self.storage
+            .set_data(pending_broadcast_key(&txid), pending, None)
+    }
+
+    /// Lists the transactions waiting to be broadcast, oldest first
+    pub fn list_pending_broadcasts(&self) -> Result<Vec<PendingBroadcast>, MutinyError> {
+        let mut pending: Vec<PendingBroadcast> = self
+            .storage
+            .scan(PENDING_BROADCAST_PREFIX, None)?
+            .into_values()
+            .collect();
+        pending.sort_by_key(|p| p.created_at);
+
+        Ok(pending)
+    }
+
+    /// Stops retrying to broadcast the given transaction, its inputs can be spent again.
+    /// If it was already broadcast it can still confirm.
+    pub fn abandon_pending_broadcast(&self, txid: &Txid) -> Result<(), MutinyError> {
+        let key = pending_broadcast_key(txid);
+        if self.storage.get_data::<PendingBroadcast>(&key)?.is_none() {
+            return Err(MutinyError::NotFound);
+        }
+        log_info!(self.logger, "Abandoning pending broadcast: {txid}");
+
+        self.storage.delete(&[key])
+    }
+
+    /// Checks with esplora that the transaction is in the mempool or confirmed
+    pub(crate) async fn is_in_mempool(&self, txid: &Txid) -> Result<bool, MutinyError> {
+        Ok(self.chain_source.get_tx(txid).await?.is_some())
+    }
+
+    /// Checks with esplora that the transaction is confirmed
+    pub(crate) async fn is_confirmed(&self, txid: &Txid) -> Result<bool, MutinyError> {
+        Ok(self.chain_source.get_tx_status(txid).await?.confirmed)
+    }
+
+    /// Watches a transaction that isn't necessarily in our wallet, like a
+    /// channel close, so it is rebroadcast if it gets evicted before confirming.
+    pub(crate) fn watch_broadcast(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        self.storage
+            .set_data(watched_broadcast_key(&tx.txid()), tx, None)
+    }
+
+    /// Gets the last known mempool status of our unconfirmed transactions
+    pub(crate) fn get_mempool_statuses(&self) -> Result<HashMap<Txid, MempoolStatus>, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(MEMPOOL_STATUS_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Checks that our unconfirmed transactions and the watched ones are still
+    /// in the mempool, rebroadcasting any that were evicted.
+    ///
+    /// A transaction is dropped once another transaction spends one of its inputs,
+    /// it can't confirm anymore. Errors are logged per transaction and it keeps
+    /// its last known status so it is checked again next time.
+    pub(crate) async fn check_mempool(&self) -> Result<(), MutinyError> {
+        let mut txs: HashMap<Txid, Transaction> = {
+            let wallet = self.wallet.try_read()?;
+            wallet
+                .transactions()
+                .filter(|tx| {
+                    !tx.chain_position.is_confirmed()
+                        && wallet.spk_index().is_tx_relevant(tx.tx_node.tx)
+                })
+                .map(|tx| (tx.tx_node.txid, tx.tx_node.tx.clone()))
+                .collect()
+        };
+
+        let previous = self.get_mempool_statuses()?;
+        let mut statuses = HashMap::with_capacity(txs.len());
+
+        let watched: HashMap<String, Transaction> =
+            self.storage.scan(WATCHED_BROADCAST_PREFIX, None)?;
+        for tx in watched.into_values() {
+            let txid = tx.txid();
+            // stop watching once it confirms
+            match self.chain_source.get_tx_status(&txid).await {
+                Ok(status) if status.confirmed => {
+                    self.storage.delete(&[watched_broadcast_key(&txid)])?;
+                }
+                Ok(_) => {
+                    txs.insert(txid, tx);
+                }
+                Err(e) => {
+                    log_warn!(self.logger, "Could not check watched tx {txid}: {e}");
+                    if let Some(status) = previous.get(&txid) {
+                        statuses.insert(txid, *status);
+                    }
+                }
+            }
+        }
+
+        for (txid, tx) in txs {
+            match self.check_mempool_tx(&tx).await {
+                Ok(Some(status)) => {
+                    statuses.insert(txid, status);
+                }
+                Ok(None) => {
+                    log_warn!(
+                        self.logger,
+                        "Transaction {txid} was replaced, no longer rebroadcasting it"
+                    );
+                    self.storage.delete(&[watched_broadcast_key(&txid)])?;
+                }
+                Err(e) => {
+                    log_warn!(self.logger, "Could not check mempool for {txid}: {e}");
+                    if let Some(status) = previous.get(&txid) {
+                        statuses.insert(txid, *status);
+                    }
+                }
+            }
+        }
+
+        self.storage
+            .set_data(MEMPOOL_STATUS_KEY.to_string(), statuses, None)
+    }
+
+    /// Rebroadcasts the transaction if it was evicted, returns `None` if one of its
+    /// inputs was spent by another transaction
+    async fn check_mempool_tx(
+        &self,
+        tx: &Transaction,
+    ) -> Result<Option<MempoolStatus>, MutinyError> {
+        let txid = tx.txid();
+        if self.chain_source.get_tx(&txid).await?.is_some() {
+            return Ok(Some(MempoolStatus::InMempool));
+        }
+
+        for input in tx.input.iter() {
+            let spending = self
+                .chain_source
+                .get_spending_txid(&input.previous_output)
+                .await?;
+            if spending.is_some_and(|spending| spending != txid) {
+                return Ok(None);
+            }
+        }
+
+        log_warn!(
+            self.logger,
+            "Transaction {txid} is not in the mempool, rebroadcasting"
+        );
+        let status = match self.chain_source.broadcast(tx).await {
+            Ok(()) => MempoolStatus::InMempool,
+            // it may have made it back in since we checked
+            Err(e) => match self.chain_source.get_tx(&txid).await {
+                Ok(Some(_)) => MempoolStatus::InMempool,
+                _ => {
+                    log_error!(self.logger, "Failed to rebroadcast {txid}: {e}");
+                    MempoolStatus::Dropped
+                }
+            },
+        };
+        Ok(Some(status))
+    }
+
+    /// Outpoints spent by queued transactions, these are
+    /// not in the wallet yet so we need to exclude them ourselves
+    fn pending_broadcast_outpoints(&self) -> Result<Vec<OutPoint>, MutinyError> {
+        Ok(self
+            .list_pending_broadcasts()?
+            .into_iter()
+            .flat_map(|p| p.transaction.input)
+            .map(|input| input.previous_output)
+            .collect())
+    }
+
+    /// Fails if a queued transaction already spends one of the outpoints
+    pub(crate) fn check_spendable(&self, outpoints: &[OutPoint]) -> Result<(), MutinyError> {
+        let unspendable = self.pending_broadcast_outpoints()?;
+        if outpoints.iter().any(|o| unspendable.contains(o)) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        Ok(())
+    }
+
+    /// Retries broadcasting the queued transactions, they are
+    /// removed from the queue once esplora has seen them.
+    pub(crate) async fn retry_pending_broadcasts(&self) -> Result<(), MutinyError> {
+        for mut pending in self.list_pending_broadcasts()? {
+            let txid = pending.txid;
+            let key = pending_broadcast_key(&txid);
+
+            let error = match self.chain_source.broadcast(&pending.transaction).await {
+                Ok(()) => None,
+                // broadcasting fails if the tx is already in the mempool or confirmed
+                Err(e) => match self.chain_source.get_tx(&txid).await {
+                    Ok(Some(_)) => None,
+                    _ => Some(e.to_string()),
+                },
+            };
+
+            // make sure it wasn't abandoned while we were broadcasting
+            if self.storage.get_data::<PendingBroadcast>(&key)?.is_none() {
+                continue;
+            }
+
+            match error {
+                None => {
+                    log_info!(self.logger, "Broadcast queued transaction: {txid}");
+                    self.storage.delete(&[key])?;
+                    self.insert_broadcasted_tx(pending.transaction).await;
+                }
+                Some(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Failed to broadcast queued transaction ({txid}): {e}"
+                    );
+                    pending.attempts += 1;
+                    pending.last_error = Some(e);
+                    self.storage.set_data(key, pending, None)?;
+                }
+            }
+        }
 
         Ok(())
     }
@@ -144,8 +522,9 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                     // update the activity index, just get the list of transactions
                     // and insert them into the index, this is done in background so shouldn't
                     // block the wallet update
-                    let index_items = self
-                        .list_transactions(false)?
+                    let transactions = self.list_transactions(true)?;
+                    self.index_onchain_transactions(&transactions)?;
+                    let index_items = transactions
                         .into_iter()
                         .map(|t| IndexItem {
                             timestamp: match t.confirmation_time {
@@ -156,18 +535,18 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                         })
                         .collect::<Vec<_>>();
 
-                    let index = self.storage.activity_index();
-                    let mut index = index.try_write()?;
-                    // remove old-onchain txs
-                    index.retain(|i| !i.key.starts_with(ONCHAIN_PREFIX));
-                    index.extend(index_items);
+                    self.storage.update_activity_index(|index| {
+                        // remove old-onchain txs
+                        index.retain(|i| !i.key.starts_with(ONCHAIN_PREFIX));
+                        index.extend(index_items);
+                    })?;
 
                     Ok(true)
                 }
                 Err(e) => {
                     // failed to apply wallet update
                     log_error!(self.logger, "Could not apply wallet update: {e}");
-                    Err(MutinyError::Other(anyhow!("Could not apply update: {e}")))
+                    Err(MutinyError::WalletSyncError)
                 }
             },
             Err(e) => {
@@ -189,7 +568,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     pub async fn sync(&self) -> Result<(), MutinyError> {
         // if we need a full sync from a restore
         if self.storage.get(NEED_FULL_SYNC_KEY)?.unwrap_or_default() {
-            self.full_sync(RESTORE_SYNC_STOP_GAP).await?;
+            self.full_sync(self.gap_limit).await?;
             self.storage.delete(&[NEED_FULL_SYNC_KEY])?;
         }
         // get first wallet lock that only needs to read
@@ -254,6 +633,25 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     }
 
     pub async fn full_sync(&self, gap: usize) -> Result<(), MutinyError> {
+        self.full_scan(gap, 0).await
+    }
+
+    /// Looks for funds beyond the usual scan window, checking `gap_limit` unused
+    /// addresses in a row on both keychains. The history of an address is only fetched
+    /// back to `from_height`, like the wallet's birthday, use 0 to fetch all of it.
+    pub async fn deep_rescan(&self, gap_limit: usize, from_height: u32) -> Result<(), MutinyError> {
+        if gap_limit == 0 || gap_limit > MAX_GAP_LIMIT {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        log_info!(
+            self.logger,
+            "Rescanning the wallet with a gap limit of {gap_limit} from height {from_height}"
+        );
+        self.full_scan(gap_limit, from_height).await
+    }
+
+    async fn full_scan(&self, gap: usize, from_height: u32) -> Result<(), MutinyError> {
         // get first wallet lock that only needs to read
         let (spks, prev_tip, chain) = {
             if let Ok(wallet) = self.wallet.try_read() {
@@ -268,7 +666,11 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             }
         };
 
-        let (update_graph, last_active_indices) = self.blockchain.full_scan(spks, gap, 5).await?;
+        let (update_graph, last_active_indices) = if from_height > 0 {
+            scan_from_height(&self.blockchain, spks, gap, 5, from_height).await?
+        } else {
+            self.blockchain.full_scan(spks, gap, 5).await?
+        };
         let missing_heights = update_graph.missing_heights(&chain);
         let chain_update = self
             .blockchain
@@ -338,31 +740,61 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         }
 
         // update activity index
-        let index = self.storage.activity_index();
-        let mut index = index.try_write()?;
         let key = format!("{ONCHAIN_PREFIX}{txid}");
-        index.retain(|i| i.key != key); // remove old version
-
-        // then insert the new version
-        index.insert(IndexItem {
-            timestamp: match position {
-                ConfirmationTime::Confirmed { time, .. } => Some(time),
-                ConfirmationTime::Unconfirmed { .. } => None,
-            },
-            key,
-        });
+        self.storage.update_activity_index(|index| {
+            index.retain(|i| i.key != key); // remove old version
+
+            // then insert the new version
+            index.insert(IndexItem {
+                timestamp: match position {
+                    ConfirmationTime::Confirmed { time, .. } => Some(time),
+                    ConfirmationTime::Unconfirmed { .. } => None,
+                },
+                key,
+            });
+        })
+    }
 
-        Ok(())
+    /// Indexes the wallet transactions for search, by their ids and output addresses
+    /// so they can be found by the labels of the addresses
+    fn index_onchain_transactions(
+        &self,
+        transactions: &[TransactionDetails],
+    ) -> Result<(), MutinyError> {
+        update_search_index(&self.storage, |index| {
+            index.remove_prefix(ONCHAIN_PREFIX);
+            for t in transactions {
+                let mut fields = transaction_fields(t);
+                let outputs = t.transaction.iter().flat_map(|tx| tx.output.iter());
+                fields.extend(outputs.filter_map(|o| {
+                    Address::from_script(&o.script_pubkey, self.network)
+                        .ok()
+                        .map(|a| a.to_string())
+                }));
+                index.update(&format!("{ONCHAIN_PREFIX}{}", t.internal_id), &fields);
+            }
+        })
     }
 
     pub fn list_utxos(&self) -> Result<Vec<LocalOutput>, MutinyError> {
         Ok(self.wallet.try_read()?.list_unspent().collect())
     }
 
+    /// Lists the utxos that aren't spent by a queued transaction, see [Self::list_pending_broadcasts]
+    pub(crate) fn list_spendable_utxos(&self) -> Result<Vec<LocalOutput>, MutinyError> {
+        let unspendable = self.pending_broadcast_outpoints()?;
+        Ok(self
+            .list_utxos()?
+            .into_iter()
+            .filter(|u| !unspendable.contains(&u.outpoint))
+            .collect())
+    }
+
     pub fn list_transactions(
         &self,
         include_raw: bool,
     ) -> Result<Vec<TransactionDetails>, MutinyError> {
+        let mempool_statuses = self.get_mempool_statuses()?;
         if let Ok(wallet) = self.wallet.try_read() {
             let txs = wallet
                 .transactions()
@@ -378,6 +810,11 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                         };
 
                         let fee = wallet.calculate_fee(tx.tx_node.tx).ok();
+                        let mempool_status = if tx.chain_position.is_confirmed() {
+                            None
+                        } else {
+                            mempool_statuses.get(&tx.tx_node.txid).copied()
+                        };
 
                         Some(TransactionDetails {
                             transaction,
@@ -388,6 +825,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                             fee,
                             confirmation_time: tx.chain_position.cloned().into(),
                             labels: vec![],
+                            mempool_status,
                         })
                     } else {
                         None
@@ -404,6 +842,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     }
 
     pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
+        let mempool_statuses = self.get_mempool_statuses()?;
         let wallet = self.wallet.try_read()?;
         let bdk_tx = wallet.get_tx(txid);
 
@@ -412,6 +851,11 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             Some(tx) => {
                 let (sent, received) = wallet.sent_and_received(tx.tx_node.tx);
                 let fee = wallet.calculate_fee(tx.tx_node.tx).ok();
+                let mempool_status = if tx.chain_position.is_confirmed() {
+                    None
+                } else {
+                    mempool_statuses.get(&txid).copied()
+                };
                 let details = TransactionDetails {
                     transaction: Some(tx.tx_node.tx.to_owned()),
                     txid: Some(txid),
@@ -421,6 +865,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                     fee,
                     confirmation_time: tx.chain_position.cloned().into(),
                     labels: vec![],
+                    mempool_status,
                 };
 
                 Ok(Some(details))
@@ -467,6 +912,23 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         &self,
         psbt: &PartiallySignedTransaction,
         labels: Vec<String>,
+    ) -> Result<(), MutinyError> {
+        // add output addresses to previous addresses
+        let addresses = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .filter_map(|o| Address::from_script(&o.script_pubkey, self.network).ok())
+            .collect::<Vec<_>>();
+
+        self.label_addresses(addresses, labels)
+    }
+
+    /// Sets the labels on each of the addresses
+    fn label_addresses(
+        &self,
+        addresses: Vec<Address>,
+        labels: Vec<String>,
     ) -> Result<(), MutinyError> {
         let mut prev_labels = vec![];
 
@@ -481,14 +943,6 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             .filter(|s| seen.insert(s.clone()))
             .collect::<Vec<_>>();
 
-        // add output addresses to previous addresses
-        let addresses = psbt
-            .unsigned_tx
-            .output
-            .iter()
-            .filter_map(|o| Address::from_script(&o.script_pubkey, self.network).ok())
-            .collect::<Vec<_>>();
-
         // set label for send to address
         for addr in addresses {
             self.storage.set_address_labels(addr, agg_labels.clone())?;
@@ -512,18 +966,29 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<PartiallySignedTransaction, MutinyError> {
+        self.create_signed_batch_psbt(vec![(spk, amount)], fee_rate)
+    }
+
+    /// Creates a signed PSBT paying all the given outputs,
+    /// any change goes back to the wallet.
+    pub fn create_signed_batch_psbt(
+        &self,
+        recipients: Vec<(ScriptBuf, u64)>,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if recipients.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let unspendable = self.pending_broadcast_outpoints()?;
         let mut wallet = self.wallet.try_write()?;
 
-        let fee_rate = if let Some(rate) = fee_rate {
-            FeeRate::from_sat_per_vb(rate)
-        } else {
-            let sat_per_kwu = self.fees.get_normal_fee_rate();
-            FeeRate::from_sat_per_kwu(sat_per_kwu as f32)
-        };
+        let fee_rate = self.fee_rate_or_default(fee_rate);
         let mut psbt = {
             let mut builder = wallet.build_tx();
             builder
-                .add_recipient(spk, amount)
+                .set_recipients(recipients)
+                .unspendable(unspendable)
                 .enable_rbf()
                 .fee_rate(fee_rate);
             builder.finish()?
@@ -534,6 +999,15 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(psbt)
     }
 
+    fn fee_rate_or_default(&self, fee_rate: Option<f32>) -> FeeRate {
+        if let Some(rate) = fee_rate {
+            FeeRate::from_sat_per_vb(rate)
+        } else {
+            let sat_per_kwu = self.fees.get_normal_fee_rate();
+            FeeRate::from_sat_per_kwu(sat_per_kwu as f32)
+        }
+    }
+
     pub async fn send(
         &self,
         destination_address: Address,
@@ -547,11 +1021,35 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         let raw_transaction = psbt.extract_tx();
         let txid = raw_transaction.txid();
 
-        self.broadcast_transaction(raw_transaction).await?;
+        self.broadcast_or_queue(raw_transaction).await?;
         log_debug!(self.logger, "Transaction broadcast! TXID: {txid}");
         Ok(txid)
     }
 
+    /// Pays all the recipients in a single transaction,
+    /// the labels are set on the recipients' addresses but not on the change
+    pub async fn batch_send(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        let addresses = recipients.iter().map(|(a, _)| a.clone()).collect();
+        let recipients = recipients
+            .into_iter()
+            .map(|(address, amount)| (address.script_pubkey(), amount))
+            .collect();
+        let psbt = self.create_signed_batch_psbt(recipients, fee_rate)?;
+        self.label_addresses(addresses, labels)?;
+
+        let raw_transaction = psbt.extract_tx();
+        let txid = raw_transaction.txid();
+
+        self.broadcast_or_queue(raw_transaction).await?;
+        log_debug!(self.logger, "Batch transaction broadcast! TXID: {txid}");
+        Ok(txid)
+    }
+
     pub async fn send_payjoin(
         &self,
         mut original_psbt: PartiallySignedTransaction,
@@ -605,32 +1103,190 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(payjoin)
     }
 
-    pub fn create_sweep_psbt(
+    /// Sends `amount` to a new address of ours in a transaction built with other wallets,
+    /// see [CollaborativeSpend]. Our change pays the fee for our inputs and outputs.
+    ///
+    /// We only sign if all our inputs and outputs are in the transaction and
+    /// none of our other UTXOs are spent.
+    pub async fn collaborative_spend(
         &self,
-        spk: ScriptBuf,
+        protocol: &impl CollaborativeSpend,
+        amount: u64,
+        labels: Vec<String>,
         fee_rate: Option<f32>,
-    ) -> Result<PartiallySignedTransaction, MutinyError> {
+    ) -> Result<Txid, MutinyError> {
+        let ours = self.create_collaborative_contribution(amount, fee_rate)?;
+        let mut psbt = protocol.build(&ours).await?;
+        self.check_collaborative_psbt(&psbt, &ours)?;
+
+        {
+            let wallet = self.wallet.try_read()?;
+            let sign_options = SignOptions {
+                trust_witness_utxo: true,
+                ..Default::default()
+            };
+            wallet.sign(&mut psbt, sign_options)?;
+        }
+
+        let unsigned_txid = psbt.unsigned_tx.txid();
+        let tx = protocol.finalize(psbt).await?;
+        let txid = tx.txid();
+        if txid != unsigned_txid {
+            log_error!(
+                self.logger,
+                "Collaborative transaction {txid} is not the one we signed ({unsigned_txid})"
+            );
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        for output in ours.outputs.iter() {
+            if let Ok(address) = Address::from_script(&output.script_pubkey, self.network) {
+                self.storage.set_address_labels(address, labels.clone())?;
+            }
+        }
+
+        self.broadcast_transaction(tx).await?;
+        log_info!(
+            self.logger,
+            "Collaborative transaction broadcast! TXID: {txid}"
+        );
+        Ok(txid)
+    }
+
+    /// Picks the UTXOs for sending `amount` to a new address of ours,
+    /// like a normal transaction that we don't sign
+    fn create_collaborative_contribution(
+        &self,
+        amount: u64,
+        fee_rate: Option<f32>,
+    ) -> Result<CollaborativeContribution, MutinyError> {
+        let unspendable = self.pending_broadcast_outpoints()?;
         let mut wallet = self.wallet.try_write()?;
 
-        let fee_rate = if let Some(rate) = fee_rate {
-            FeeRate::from_sat_per_vb(rate)
-        } else {
-            let sat_per_kwu = self.fees.get_normal_fee_rate();
-            FeeRate::from_sat_per_kwu(sat_per_kwu as f32)
-        };
-        let mut psbt = {
+        let fee_rate = self.fee_rate_or_default(fee_rate);
+        let address = wallet.try_get_address(AddressIndex::New)?.address;
+        let psbt = {
             let mut builder = wallet.build_tx();
             builder
-                .drain_wallet() // Spend all outputs in this wallet.
-                .drain_to(spk)
+                .add_recipient(address.script_pubkey(), amount)
+                .unspendable(unspendable)
                 .enable_rbf()
                 .fee_rate(fee_rate);
             builder.finish()?
         };
-        log_debug!(self.logger, "Unsigned PSBT: {psbt}");
-        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-        log_debug!(self.logger, "finalized: {finalized}");
-        Ok(psbt)
+
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter())
+            .map(|(txin, input)| {
+                let txout = input
+                    .witness_utxo
+                    .clone()
+                    .ok_or(MutinyError::WalletOperationFailed)?;
+                Ok((txin.previous_output, txout))
+            })
+            .collect::<Result<Vec<_>, MutinyError>>()?;
+
+        Ok(CollaborativeContribution {
+            inputs,
+            outputs: psbt.unsigned_tx.output,
+        })
+    }
+
+    /// Makes sure the other parties kept our inputs and outputs as they are
+    /// and didn't add any other UTXO of ours for us to sign
+    fn check_collaborative_psbt(
+        &self,
+        psbt: &PartiallySignedTransaction,
+        ours: &CollaborativeContribution,
+    ) -> Result<(), MutinyError> {
+        let tx = &psbt.unsigned_tx;
+        if psbt.inputs.len() != tx.input.len() || psbt.outputs.len() != tx.output.len() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let wallet = self.wallet.try_read()?;
+        for (txin, input) in tx.input.iter().zip(psbt.inputs.iter()) {
+            // taproot signatures commit to every spent output
+            let Some(txout) = input.witness_utxo.as_ref() else {
+                return Err(MutinyError::InvalidArgumentsError);
+            };
+            let contributed = ours
+                .inputs
+                .iter()
+                .find(|(outpoint, _)| *outpoint == txin.previous_output);
+            match contributed {
+                Some((_, expected)) if expected != txout => {
+                    return Err(MutinyError::InvalidArgumentsError);
+                }
+                Some(_) => {}
+                None => {
+                    if wallet.is_mine(&txout.script_pubkey)
+                        || wallet.get_utxo(txin.previous_output).is_some()
+                    {
+                        log_error!(
+                            self.logger,
+                            "Collaborative transaction spends our UTXO {}",
+                            txin.previous_output
+                        );
+                        return Err(MutinyError::InvalidArgumentsError);
+                    }
+                }
+            }
+        }
+        if ours
+            .inputs
+            .iter()
+            .any(|(outpoint, _)| !tx.input.iter().any(|i| i.previous_output == *outpoint))
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        // outputs can have the same amount and script, so each one counts once
+        let mut outputs: Vec<&TxOut> = tx.output.iter().collect();
+        for output in ours.outputs.iter() {
+            match outputs.iter().position(|o| *o == output) {
+                Some(index) => {
+                    outputs.remove(index);
+                }
+                None => {
+                    log_error!(
+                        self.logger,
+                        "Collaborative transaction is missing our output"
+                    );
+                    return Err(MutinyError::InvalidArgumentsError);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn create_sweep_psbt(
+        &self,
+        spk: ScriptBuf,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let unspendable = self.pending_broadcast_outpoints()?;
+        let mut wallet = self.wallet.try_write()?;
+
+        let fee_rate = self.fee_rate_or_default(fee_rate);
+        let mut psbt = {
+            let mut builder = wallet.build_tx();
+            builder
+                .drain_wallet() // Spend all outputs in this wallet.
+                .unspendable(unspendable)
+                .drain_to(spk)
+                .enable_rbf()
+                .fee_rate(fee_rate);
+            builder.finish()?
+        };
+        log_debug!(self.logger, "Unsigned PSBT: {psbt}");
+        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+        log_debug!(self.logger, "finalized: {finalized}");
+        Ok(psbt)
     }
 
     pub async fn sweep(
@@ -645,11 +1301,130 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         let raw_transaction = psbt.extract_tx();
         let txid = raw_transaction.txid();
 
-        self.broadcast_transaction(raw_transaction).await?;
+        self.broadcast_or_queue(raw_transaction).await?;
         log_debug!(self.logger, "Transaction broadcast! TXID: {txid}");
         Ok(txid)
     }
 
+    /// Sweeps the funds of a foreign private key (WIF) or seed into new addresses of ours,
+    /// like a paper wallet or another wallet app. The standard single-sig descriptors of
+    /// the key are scanned with esplora and each one with funds is swept in its own
+    /// transaction. Seeds with a BIP-39 passphrase aren't supported.
+    ///
+    /// Returns the outcome for every address type that had funds or failed, a failure
+    /// doesn't stop the other address types from being swept.
+    /// Fails with [MutinyError::InsufficientBalance] if the key has no funds at all.
+    pub async fn sweep_external(
+        &self,
+        wif_or_mnemonic: &str,
+        fee_rate: Option<f32>,
+    ) -> Result<Vec<ExternalSweep>, MutinyError> {
+        let descriptors = external_descriptors(wif_or_mnemonic, self.network)?;
+        let fee_rate = self.fee_rate_or_default(fee_rate);
+
+        let mut sweeps = vec![];
+        for external in descriptors {
+            let address_type = external.address_type.to_string();
+            let sweep = match self.sweep_external_descriptor(external, fee_rate).await {
+                Ok(None) => continue,
+                Ok(Some((amount_sats, txid))) => ExternalSweep {
+                    address_type,
+                    amount_sats,
+                    txid: Some(txid),
+                    error: None,
+                },
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Failed to sweep {address_type} funds of an external key: {e}"
+                    );
+                    ExternalSweep {
+                        address_type,
+                        amount_sats: 0,
+                        txid: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            sweeps.push(sweep);
+        }
+
+        if sweeps.is_empty() {
+            return Err(MutinyError::InsufficientBalance);
+        }
+        Ok(sweeps)
+    }
+
+    /// Sweeps the funds of one descriptor of a foreign key,
+    /// returns the amount and txid of the sweep or None if it has no funds
+    async fn sweep_external_descriptor(
+        &self,
+        external: ExternalDescriptor,
+        fee_rate: FeeRate,
+    ) -> Result<Option<(u64, Txid)>, MutinyError> {
+        let mut wallet = Wallet::new_no_persist(
+            external.descriptor,
+            external.change_descriptor,
+            self.network,
+        )
+        .map_err(|_| MutinyError::WalletOperationFailed)?;
+        self.scan_external(&mut wallet).await?;
+        let balance = wallet.get_balance().total();
+        if balance == 0 {
+            return Ok(None);
+        }
+
+        let address = {
+            let mut ours = self.wallet.try_write()?;
+            ours.try_get_address(AddressIndex::New)?.address
+        };
+        let mut psbt = {
+            let mut builder = wallet.build_tx();
+            builder
+                .drain_wallet()
+                .drain_to(address.script_pubkey())
+                .enable_rbf()
+                .fee_rate(fee_rate);
+            builder.finish()?
+        };
+        if !wallet.sign(&mut psbt, SignOptions::default())? {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        let tx = psbt.extract_tx();
+        let txid = tx.txid();
+        self.broadcast_transaction(tx).await?;
+        log_info!(
+            self.logger,
+            "Swept {balance} sats from an external key to {address}: {txid}"
+        );
+
+        Ok(Some((balance, txid)))
+    }
+
+    /// Full scan of a wallet that isn't ours, nothing is persisted
+    async fn scan_external(&self, wallet: &mut Wallet) -> Result<(), MutinyError> {
+        let spks = wallet.all_unbounded_spk_iters();
+        let (update_graph, last_active_indices) =
+            self.blockchain.full_scan(spks, self.gap_limit, 5).await?;
+        let missing_heights = update_graph.missing_heights(wallet.local_chain());
+        let chain_update = self
+            .blockchain
+            .update_local_chain(wallet.latest_checkpoint(), missing_heights)
+            .await?;
+        let update = Update {
+            last_active_indices,
+            graph: update_graph,
+            chain: Some(chain_update),
+        };
+        wallet.apply_update(update).map_err(|e| {
+            log_error!(self.logger, "Could not apply wallet update: {e}");
+            MutinyError::WalletSyncError
+        })?;
+
+        Ok(())
+    }
+
     /// Creates a PSBT that spends all the selected utxos a given output.
     /// A fee rate is not specified because it should be precalculated
     /// in the output's amount.
@@ -660,6 +1435,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount_sats: u64,
         absolute_fee: u64,
     ) -> Result<PartiallySignedTransaction, MutinyError> {
+        self.check_spendable(utxos)?;
         let mut wallet = self.wallet.try_write()?;
         let mut psbt = {
             let mut builder = wallet.build_tx();
@@ -718,6 +1494,93 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         log_debug!(self.logger, "Fee bump Transaction broadcast! TXID: {txid}");
         Ok(txid)
     }
+
+    /// Double-spends the inputs of an unconfirmed transaction back to the wallet,
+    /// so the funds are ours again if it never confirms.
+    ///
+    /// Every input needs to be ours. The replacement pays at least the given fee rate
+    /// in sats/vbyte and more than the original, so it can replace it in the mempool.
+    pub async fn cancel_transaction(
+        &self,
+        tx: &Transaction,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        let replacement = {
+            let mut wallet = self.wallet.try_write()?;
+            let mut prevouts = Vec::with_capacity(tx.input.len());
+            for input in tx.input.iter() {
+                let outpoint = input.previous_output;
+                let txout = wallet
+                    .get_tx(outpoint.txid)
+                    .and_then(|t| t.tx_node.tx.output.get(outpoint.vout as usize).cloned())
+                    .filter(|o| wallet.is_mine(&o.script_pubkey))
+                    .ok_or(MutinyError::WalletOperationFailed)?;
+                prevouts.push(txout);
+            }
+            let input_value: u64 = prevouts.iter().map(|o| o.value).sum();
+            let output_value: u64 = tx.output.iter().map(|o| o.value).sum();
+            let original_fee = input_value.saturating_sub(output_value);
+
+            let address = wallet.try_get_address(AddressIndex::New)?.address;
+            let mut replacement = Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::ZERO,
+                input: tx
+                    .input
+                    .iter()
+                    .map(|i| TxIn {
+                        previous_output: i.previous_output,
+                        script_sig: ScriptBuf::new(),
+                        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                        witness: Witness::new(),
+                    })
+                    .collect(),
+                output: vec![TxOut {
+                    value: 0,
+                    script_pubkey: address.script_pubkey(),
+                }],
+            };
+
+            // our inputs are taproot key spends, a 64 byte signature each
+            let weight = replacement.weight().to_wu() + 2 + 66 * prevouts.len() as u64;
+            let vsize = (weight + 3) / 4;
+            let sats_per_vbyte = match fee_rate {
+                Some(rate) => rate,
+                None => {
+                    let sat_per_kwu = self.fees.get_normal_fee_rate();
+                    FeeRate::from_sat_per_kwu(sat_per_kwu as f32).as_sat_per_vb()
+                }
+            };
+            // a replacement needs to pay the original fee plus its own relay fee
+            let fee = ((sats_per_vbyte * vsize as f32).ceil() as u64).max(original_fee + vsize);
+            let value = input_value.saturating_sub(fee);
+            if value < address.script_pubkey().dust_value().to_sat() {
+                return Err(MutinyError::InsufficientBalance);
+            }
+            replacement.output[0].value = value;
+
+            let mut psbt = PartiallySignedTransaction::from_unsigned_tx(replacement)
+                .map_err(|_| MutinyError::WalletOperationFailed)?;
+            for (input, txout) in psbt.inputs.iter_mut().zip(prevouts) {
+                input.witness_utxo = Some(txout);
+            }
+            let sign_options = SignOptions {
+                trust_witness_utxo: true,
+                ..Default::default()
+            };
+            wallet.sign(&mut psbt, sign_options)?;
+            psbt.extract_tx()
+        };
+
+        let txid = replacement.txid();
+        self.broadcast_transaction(replacement).await?;
+        log_info!(
+            self.logger,
+            "Canceled transaction {} with replacement {txid}",
+            tx.txid()
+        );
+        Ok(txid)
+    }
 }
 
 fn get_tr_descriptors_for_extended_key(
@@ -745,6 +1608,204 @@ fn get_tr_descriptors_for_extended_key(
     Ok((receive_descriptor_template, change_descriptor_template))
 }
 
+/// A standard single-sig descriptor of a foreign key, see [external_descriptors]
+struct ExternalDescriptor {
+    /// Name of the address type, like `wpkh`
+    address_type: &'static str,
+    descriptor: DescriptorTemplateOut,
+    change_descriptor: Option<DescriptorTemplateOut>,
+}
+
+impl ExternalDescriptor {
+    fn new(
+        address_type: &'static str,
+        descriptor: DescriptorTemplateOut,
+        change_descriptor: Option<DescriptorTemplateOut>,
+    ) -> Self {
+        Self {
+            address_type,
+            descriptor,
+            change_descriptor,
+        }
+    }
+}
+
+/// The standard single-sig descriptors a foreign WIF key or BIP-39 seed can have funds on,
+/// with their change descriptors
+fn external_descriptors(
+    wif_or_mnemonic: &str,
+    network: Network,
+) -> Result<Vec<ExternalDescriptor>, MutinyError> {
+    let key = wif_or_mnemonic.trim();
+
+    if let Ok(private_key) = PrivateKey::from_wif(key) {
+        // WIF only knows mainnet and testnet
+        if (private_key.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(MutinyError::IncorrectNetwork);
+        }
+
+        let mut descriptors = vec![ExternalDescriptor::new(
+            "pkh",
+            bdk::descriptor!(pkh(private_key))?,
+            None,
+        )];
+        // uncompressed keys can only be used for legacy addresses
+        if private_key.compressed {
+            descriptors.extend([
+                ExternalDescriptor::new("wpkh", bdk::descriptor!(wpkh(private_key))?, None),
+                ExternalDescriptor::new("sh-wpkh", bdk::descriptor!(sh(wpkh(private_key)))?, None),
+                ExternalDescriptor::new("tr", bdk::descriptor!(tr(private_key))?, None),
+            ]);
+        }
+        return Ok(descriptors);
+    }
+
+    let mnemonic = Mnemonic::from_str(key).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let xprv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))?;
+    Ok(vec![
+        ExternalDescriptor::new(
+            "pkh",
+            Bip44(xprv, KeychainKind::External).build(network)?,
+            Some(Bip44(xprv, KeychainKind::Internal).build(network)?),
+        ),
+        ExternalDescriptor::new(
+            "sh-wpkh",
+            Bip49(xprv, KeychainKind::External).build(network)?,
+            Some(Bip49(xprv, KeychainKind::Internal).build(network)?),
+        ),
+        ExternalDescriptor::new(
+            "wpkh",
+            Bip84(xprv, KeychainKind::External).build(network)?,
+            Some(Bip84(xprv, KeychainKind::Internal).build(network)?),
+        ),
+        ExternalDescriptor::new(
+            "tr",
+            Bip86(xprv, KeychainKind::External).build(network)?,
+            Some(Bip86(xprv, KeychainKind::Internal).build(network)?),
+        ),
+    ])
+}
+
+/// Esplora gives the history of a script in pages of this many confirmed transactions
+const ESPLORA_PAGE_SIZE: usize = 25;
+
+/// Scans the scripts like [EsploraAsyncExt::full_scan], but stops fetching the history of
+/// a script once it reaches transactions confirmed before `from_height`. Everything that
+/// was fetched is kept, so the balance of the addresses we look at stays right.
+async fn scan_from_height<K: Ord + Clone>(
+    client: &AsyncClient,
+    keychain_spks: std::collections::BTreeMap<K, impl IntoIterator<Item = (u32, ScriptBuf)>>,
+    stop_gap: usize,
+    parallel_requests: usize,
+    from_height: u32,
+) -> Result<
+    (
+        TxGraph<ConfirmationTimeHeightAnchor>,
+        std::collections::BTreeMap<K, u32>,
+    ),
+    MutinyError,
+> {
+    let stop_gap = stop_gap.max(1) as u32;
+    let mut graph = TxGraph::default();
+    let mut last_active_indices = std::collections::BTreeMap::new();
+
+    for (keychain, spks) in keychain_spks {
+        let mut spks = spks.into_iter();
+        let mut last_active_index: Option<u32> = None;
+        loop {
+            let batch: Vec<_> = spks.by_ref().take(parallel_requests.max(1)).collect();
+            let Some(last_index) = batch.last().map(|(index, _)| *index) else {
+                break;
+            };
+
+            let histories = futures::future::try_join_all(
+                batch
+                    .into_iter()
+                    .map(|(index, spk)| script_history(client, spk, from_height, index)),
+            )
+            .await?;
+            for (index, txs) in histories {
+                if !txs.is_empty() {
+                    last_active_index = Some(index);
+                }
+                for tx in txs {
+                    insert_esplora_tx(&mut graph, tx);
+                }
+            }
+
+            let gap_reached = match last_active_index {
+                Some(active) => last_index >= active.saturating_add(stop_gap),
+                None => last_index + 1 >= stop_gap,
+            };
+            if gap_reached {
+                break;
+            }
+        }
+
+        if let Some(index) = last_active_index {
+            last_active_indices.insert(keychain, index);
+        }
+    }
+
+    Ok((graph, last_active_indices))
+}
+
+/// Gets the history of a script, newest first, back to the first page that
+/// reaches transactions confirmed before `from_height`
+async fn script_history(
+    client: &AsyncClient,
+    spk: ScriptBuf,
+    from_height: u32,
+    index: u32,
+) -> Result<(u32, Vec<esplora_client::Tx>), MutinyError> {
+    let mut txs = vec![];
+    let mut last_seen = None;
+    loop {
+        let page = client.scripthash_txs(&spk, last_seen).await?;
+        let full_page = page.len() >= ESPLORA_PAGE_SIZE;
+        let reached_height = page
+            .last()
+            .is_some_and(|tx| confirmed_before(&tx.status, from_height));
+        last_seen = page.last().map(|tx| tx.txid);
+        txs.extend(page);
+        if !full_page || reached_height {
+            return Ok((index, txs));
+        }
+    }
+}
+
+fn confirmed_before(status: &esplora_client::TxStatus, height: u32) -> bool {
+    status.block_height.is_some_and(|h| h < height)
+}
+
+/// Adds a transaction from esplora to the graph with its anchor and the outputs it spends
+fn insert_esplora_tx(graph: &mut TxGraph<ConfirmationTimeHeightAnchor>, tx: esplora_client::Tx) {
+    let _ = graph.insert_tx(tx.to_tx());
+    if let (Some(height), Some(hash), Some(time)) = (
+        tx.status.block_height,
+        tx.status.block_hash,
+        tx.status.block_time,
+    ) {
+        let anchor = ConfirmationTimeHeightAnchor {
+            anchor_block: BlockId { height, hash },
+            confirmation_height: height,
+            confirmation_time: time,
+        };
+        let _ = graph.insert_anchor(tx.txid, anchor);
+    }
+    // the prevouts are only used for fees
+    for vin in tx.vin {
+        if let Some(prevout) = vin.prevout {
+            let outpoint = OutPoint::new(vin.txid, vin.vout);
+            let txout = TxOut {
+                value: prevout.value,
+                script_pubkey: prevout.scriptpubkey,
+            };
+            let _ = graph.insert_txout(outpoint, txout);
+        }
+    }
+}
+
 pub(crate) fn coin_type_from_network(network: Network) -> u32 {
     match network {
         Network::Bitcoin => 0,
@@ -772,9 +1833,10 @@ pub(crate) fn get_esplora_url(network: Network, user_provided_url: Option<String
 
 impl<S: MutinyStorage> WalletSource for OnChainWallet<S> {
     fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
-        let wallet = self.wallet.try_read().map_err(|_| ())?;
-        let utxos = wallet
-            .list_unspent()
+        let utxos = self
+            .list_spendable_utxos()
+            .map_err(|_| ())?
+            .into_iter()
             .map(|u| Utxo {
                 outpoint: u.outpoint,
                 output: u.txout,
@@ -820,8 +1882,8 @@ mod tests {
     use super::*;
     use crate::test_utils::*;
     use crate::{encrypt::encryption_key_from_pass, storage::MemoryStorage};
-    use bip39::Mnemonic;
-    use bitcoin::Address;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Address, BlockHash};
     use esplora_client::Builder;
     use std::str::FromStr;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
@@ -846,7 +1908,7 @@ mod tests {
         let stop = Arc::new(AtomicBool::new(false));
         let xpriv = ExtendedPrivKey::new_master(Network::Testnet, &mnemonic.to_seed("")).unwrap();
 
-        OnChainWallet::new(xpriv, db, Network::Testnet, esplora, fees, stop, logger).unwrap()
+        OnChainWallet::new(xpriv, db, Network::Testnet, 0, esplora, fees, stop, logger).unwrap()
     }
 
     #[test]
@@ -908,4 +1970,424 @@ mod tests {
             .contains(&send_to_addr.to_string()));
         assert!(label.unwrap().addresses.contains(&change_addr.to_string()));
     }
+
+    #[test]
+    async fn test_pending_broadcasts() {
+        let test_name = "pending_broadcasts";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+        assert!(wallet.list_pending_broadcasts().unwrap().is_empty());
+
+        let psbt = PartiallySignedTransaction::from_str("cHNidP8BAKACAAAAAqsJSaCMWvfEm4IS9Bfi8Vqz9cM9zxU4IagTn4d6W3vkAAAAAAD+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEHakcwRAIgR1lmF5fAGwNrJZKJSGhiGDR9iYZLcZ4ff89X0eURZYcCIFMJ6r9Wqk2Ikf/REf3xM286KdqGbX+EhtdVRs7tr5MZASEDXNxh/HupccC1AaZGoqg7ECy0OIEhfKaC3Ibi1z+ogpIAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIAAAA").unwrap();
+        let tx = psbt.extract_tx();
+        let txid = tx.txid();
+
+        wallet
+            .queue_broadcast(tx.clone(), "offline".to_string())
+            .unwrap();
+        let pending = wallet.list_pending_broadcasts().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].txid, txid);
+        assert_eq!(pending[0].transaction, tx);
+        assert_eq!(pending[0].last_error, Some("offline".to_string()));
+
+        // the inputs can't be used while the tx is queued
+        let outpoints = wallet.pending_broadcast_outpoints().unwrap();
+        assert_eq!(outpoints.len(), tx.input.len());
+        assert!(outpoints.contains(&tx.input[0].previous_output));
+        assert_eq!(
+            wallet.check_spendable(&[tx.input[0].previous_output]),
+            Err(MutinyError::InvalidArgumentsError)
+        );
+
+        wallet.abandon_pending_broadcast(&txid).unwrap();
+        assert!(wallet.list_pending_broadcasts().unwrap().is_empty());
+        assert!(wallet.pending_broadcast_outpoints().unwrap().is_empty());
+        wallet
+            .check_spendable(&[tx.input[0].previous_output])
+            .unwrap();
+        assert_eq!(
+            wallet.abandon_pending_broadcast(&txid),
+            Err(MutinyError::NotFound)
+        );
+    }
+
+    #[test]
+    async fn test_create_signed_batch_psbt() {
+        let test_name = "create_signed_batch_psbt";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        assert_eq!(
+            wallet.create_signed_batch_psbt(vec![], None),
+            Err(MutinyError::InvalidArgumentsError)
+        );
+
+        // an empty wallet can't pay anyone
+        let address = Address::from_str("mrKjeffvbnmKJURrLNdqLkfrptLrFtnkFx")
+            .unwrap()
+            .assume_checked();
+        let recipients = vec![
+            (address.script_pubkey(), 10_000),
+            (address.script_pubkey(), 20_000),
+        ];
+        assert_eq!(
+            wallet.create_signed_batch_psbt(recipients, None),
+            Err(MutinyError::WalletOperationFailed)
+        );
+    }
+
+    #[test]
+    async fn test_mempool_status() {
+        let test_name = "mempool_status";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+        assert!(wallet.get_mempool_statuses().unwrap().is_empty());
+
+        let psbt = PartiallySignedTransaction::from_str("cHNidP8BAKACAAAAAqsJSaCMWvfEm4IS9Bfi8Vqz9cM9zxU4IagTn4d6W3vkAAAAAAD+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEHakcwRAIgR1lmF5fAGwNrJZKJSGhiGDR9iYZLcZ4ff89X0eURZYcCIFMJ6r9Wqk2Ikf/REf3xM286KdqGbX+EhtdVRs7tr5MZASEDXNxh/HupccC1AaZGoqg7ECy0OIEhfKaC3Ibi1z+ogpIAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIAAAA").unwrap();
+        let tx = psbt.extract_tx();
+        wallet.watch_broadcast(&tx).unwrap();
+        let watched: Option<Transaction> = wallet
+            .storage
+            .get_data(watched_broadcast_key(&tx.txid()))
+            .unwrap();
+        assert_eq!(watched, Some(tx.clone()));
+
+        let statuses = HashMap::from([(tx.txid(), MempoolStatus::Dropped)]);
+        wallet
+            .storage
+            .set_data(MEMPOOL_STATUS_KEY.to_string(), statuses.clone(), None)
+            .unwrap();
+        assert_eq!(wallet.get_mempool_statuses().unwrap(), statuses);
+
+        // details saved before we tracked the mempool have no status
+        let details = TransactionDetails {
+            transaction: None,
+            txid: Some(tx.txid()),
+            internal_id: tx.txid(),
+            received: 0,
+            sent: 0,
+            fee: None,
+            confirmation_time: ConfirmationTime::Unconfirmed { last_seen: 0 },
+            labels: vec![],
+            mempool_status: Some(MempoolStatus::InMempool),
+        };
+        let mut json = serde_json::to_value(&details).unwrap();
+        json.as_object_mut().unwrap().remove("mempool_status");
+        let old: TransactionDetails = serde_json::from_value(json).unwrap();
+        assert_eq!(old.mempool_status, None);
+    }
+
+    #[test]
+    async fn test_cancel_transaction() {
+        let test_name = "cancel_transaction";
+        log!("{}", test_name);
+        let chain = Arc::new(MockChainSource::default());
+        let wallet = create_wallet().await.with_chain_source(chain.clone());
+
+        // fund the wallet with an unconfirmed tx
+        let mut funding = create_dummy_tx(100_000);
+        funding.input.push(TxIn::default());
+        let address = {
+            let mut bdk_wallet = wallet.wallet.try_write().unwrap();
+            bdk_wallet
+                .try_get_address(AddressIndex::New)
+                .unwrap()
+                .address
+        };
+        funding.output[0].script_pubkey = address.script_pubkey();
+        let position = ConfirmationTime::Unconfirmed { last_seen: 1 };
+        wallet.insert_tx(funding, position, None).await.unwrap();
+
+        let spk = Address::from_str("mrKjeffvbnmKJURrLNdqLkfrptLrFtnkFx")
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let psbt = wallet
+            .create_signed_psbt_to_spk(spk, 50_000, Some(1.0))
+            .unwrap();
+        let tx = psbt.extract_tx();
+        wallet.broadcast_transaction(tx.clone()).await.unwrap();
+        let original_fee = 100_000 - tx.output.iter().map(|o| o.value).sum::<u64>();
+
+        let txid = wallet.cancel_transaction(&tx, Some(1.0)).await.unwrap();
+        assert!(chain.is_in_mempool(&txid));
+        let replacement = chain.get_tx(&txid).await.unwrap().unwrap();
+        assert_eq!(replacement.input.len(), tx.input.len());
+        assert_eq!(
+            replacement.input[0].previous_output,
+            tx.input[0].previous_output
+        );
+
+        // everything comes back to us, paying more than the original
+        assert_eq!(replacement.output.len(), 1);
+        assert!(100_000 - replacement.output[0].value > original_fee);
+        let bdk_wallet = wallet.wallet.try_read().unwrap();
+        assert!(bdk_wallet.is_mine(&replacement.output[0].script_pubkey));
+    }
+
+    /// Adds another wallet's input and output, like a coinjoin peer would
+    struct MockCollaborator {
+        input: (OutPoint, TxOut),
+        output: TxOut,
+        drop_our_outputs: bool,
+    }
+
+    #[async_trait(?Send)]
+    impl CollaborativeSpend for MockCollaborator {
+        async fn build(
+            &self,
+            ours: &CollaborativeContribution,
+        ) -> Result<PartiallySignedTransaction, MutinyError> {
+            let mut inputs = ours.inputs.clone();
+            inputs.push(self.input.clone());
+            let mut output = vec![self.output.clone()];
+            if !self.drop_our_outputs {
+                output.extend(ours.outputs.clone());
+            }
+
+            let tx = Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::ZERO,
+                input: inputs
+                    .iter()
+                    .map(|(outpoint, _)| TxIn {
+                        previous_output: *outpoint,
+                        ..Default::default()
+                    })
+                    .collect(),
+                output,
+            };
+            let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+            for (input, (_, txout)) in psbt.inputs.iter_mut().zip(inputs) {
+                input.witness_utxo = Some(txout);
+            }
+            Ok(psbt)
+        }
+
+        async fn finalize(
+            &self,
+            signed: PartiallySignedTransaction,
+        ) -> Result<Transaction, MutinyError> {
+            Ok(signed.extract_tx())
+        }
+    }
+
+    #[test]
+    async fn test_collaborative_spend() {
+        let test_name = "collaborative_spend";
+        log!("{}", test_name);
+        let chain = Arc::new(MockChainSource::default());
+        let wallet = create_wallet().await.with_chain_source(chain.clone());
+
+        let mut funding = create_dummy_tx(100_000);
+        funding.input.push(TxIn::default());
+        let address = {
+            let mut bdk_wallet = wallet.wallet.try_write().unwrap();
+            bdk_wallet
+                .try_get_address(AddressIndex::New)
+                .unwrap()
+                .address
+        };
+        funding.output[0].script_pubkey = address.script_pubkey();
+        let position = ConfirmationTime::Unconfirmed { last_seen: 1 };
+        wallet.insert_tx(funding, position, None).await.unwrap();
+
+        let spk = Address::from_str("mrKjeffvbnmKJURrLNdqLkfrptLrFtnkFx")
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let foreign = create_dummy_tx(60_000);
+        let mut peer = MockCollaborator {
+            input: (
+                OutPoint::new(foreign.txid(), 0),
+                TxOut {
+                    value: 60_000,
+                    script_pubkey: spk.clone(),
+                },
+            ),
+            output: TxOut {
+                value: 50_000,
+                script_pubkey: spk,
+            },
+            drop_our_outputs: true,
+        };
+
+        // the peer left out our outputs, so we don't sign
+        assert_eq!(
+            wallet
+                .collaborative_spend(&peer, 50_000, vec![], Some(1.0))
+                .await,
+            Err(MutinyError::InvalidArgumentsError)
+        );
+
+        peer.drop_our_outputs = false;
+        let labels = vec!["coinjoin".to_string()];
+        let txid = wallet
+            .collaborative_spend(&peer, 50_000, labels.clone(), Some(1.0))
+            .await
+            .unwrap();
+        assert!(chain.is_in_mempool(&txid));
+
+        let tx = chain.get_tx(&txid).await.unwrap().unwrap();
+        assert_eq!(tx.input.len(), 2);
+        let equal: Vec<&TxOut> = tx.output.iter().filter(|o| o.value == 50_000).collect();
+        assert_eq!(equal.len(), 2);
+        let ours = {
+            let bdk_wallet = wallet.wallet.try_read().unwrap();
+            *equal
+                .iter()
+                .find(|o| bdk_wallet.is_mine(&o.script_pubkey))
+                .unwrap()
+        };
+        let address = Address::from_script(&ours.script_pubkey, Network::Testnet).unwrap();
+        let saved = wallet.storage.get_address_labels().unwrap();
+        assert_eq!(saved.get(&address.to_string()), Some(&labels));
+    }
+
+    #[test]
+    async fn test_deep_rescan_gap_limit() {
+        let test_name = "deep_rescan_gap_limit";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+        assert_eq!(wallet.gap_limit, RESTORE_SYNC_STOP_GAP);
+
+        let wallet = wallet.with_gap_limit(500);
+        assert_eq!(wallet.gap_limit, 500);
+        let wallet = wallet.with_gap_limit(0);
+        assert_eq!(wallet.gap_limit, 1);
+
+        // rejected before we ask esplora anything
+        assert_eq!(
+            wallet.deep_rescan(0, 0).await,
+            Err(MutinyError::InvalidArgumentsError)
+        );
+        assert_eq!(
+            wallet.deep_rescan(MAX_GAP_LIMIT + 1, 0).await,
+            Err(MutinyError::InvalidArgumentsError)
+        );
+    }
+
+    #[test]
+    async fn test_confirmed_before() {
+        let test_name = "confirmed_before";
+        log!("{}", test_name);
+        let status = |height: Option<u32>| esplora_client::TxStatus {
+            confirmed: height.is_some(),
+            block_height: height,
+            block_hash: height.map(|_| BlockHash::all_zeros()),
+            block_time: height.map(|_| 0),
+        };
+
+        // the scan of a script stops at the page that reaches the height
+        assert!(confirmed_before(&status(Some(100)), 150));
+        assert!(!confirmed_before(&status(Some(150)), 150));
+        assert!(!confirmed_before(&status(Some(200)), 150));
+        assert!(!confirmed_before(&status(None), 150));
+    }
+
+    #[test]
+    async fn test_external_descriptors() {
+        let test_name = "external_descriptors";
+        log!("{}", test_name);
+
+        let wif = "cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy";
+        let descriptors = external_descriptors(wif, Network::Testnet).unwrap();
+        assert_eq!(descriptors.len(), 4);
+        assert!(descriptors.iter().all(|d| d.change_descriptor.is_none()));
+        assert_eq!(
+            external_descriptors(wif, Network::Bitcoin).err(),
+            Some(MutinyError::IncorrectNetwork)
+        );
+
+        let mainnet_wif = " L1aW4aubDFB7yfras2S1mN3bqg9nwySY8nkoLmJebSLD5BWv3ENZ\n";
+        assert_eq!(
+            external_descriptors(mainnet_wif, Network::Bitcoin)
+                .unwrap()
+                .len(),
+            4
+        );
+
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let descriptors = external_descriptors(mnemonic, Network::Signet).unwrap();
+        assert_eq!(descriptors.len(), 4);
+        assert!(descriptors.iter().all(|d| d.change_descriptor.is_some()));
+
+        assert_eq!(
+            external_descriptors("not a key", Network::Testnet).err(),
+            Some(MutinyError::InvalidArgumentsError)
+        );
+    }
+
+    #[test]
+    async fn test_check_mempool_with_mock_chain() {
+        let chain = Arc::new(MockChainSource::default());
+        let wallet = create_wallet().await.with_chain_source(chain.clone());
+        let tx = create_dummy_tx(1_000);
+        let txid = tx.txid();
+        wallet.watch_broadcast(&tx).unwrap();
+
+        // esplora doesn't have it, so it is rebroadcast
+        wallet.check_mempool().await.unwrap();
+        assert!(chain.is_in_mempool(&txid));
+        let statuses = wallet.get_mempool_statuses().unwrap();
+        assert_eq!(statuses.get(&txid), Some(&MempoolStatus::InMempool));
+
+        // evicted and the rebroadcast is rejected
+        chain.evict(&txid);
+        chain.set_reject_broadcasts(true);
+        wallet.check_mempool().await.unwrap();
+        let statuses = wallet.get_mempool_statuses().unwrap();
+        assert_eq!(statuses.get(&txid), Some(&MempoolStatus::Dropped));
+
+        chain.set_reject_broadcasts(false);
+        chain.add_to_mempool(tx);
+        chain.mine_blocks(2);
+        assert_eq!(chain.confirmations(&txid), 2);
+
+        // a reorg puts it back in the mempool
+        chain.reorg(2, 3);
+        assert_eq!(chain.confirmations(&txid), 0);
+        assert!(chain.is_in_mempool(&txid));
+        assert_eq!(chain.height(), 3);
+
+        // once confirmed it isn't watched anymore
+        chain.mine_blocks(1);
+        wallet.check_mempool().await.unwrap();
+        let watched = wallet
+            .storage
+            .get_data::<Transaction>(watched_broadcast_key(&txid));
+        assert_eq!(watched.unwrap(), None);
+        assert!(wallet.get_mempool_statuses().unwrap().is_empty());
+    }
+
+    #[test]
+    async fn test_check_mempool_drops_replaced_tx() {
+        let chain = Arc::new(MockChainSource::default());
+        let wallet = create_wallet().await.with_chain_source(chain.clone());
+        let mut tx = create_dummy_tx(1_000);
+        tx.input.push(TxIn {
+            previous_output: OutPoint::new(create_dummy_tx(5_000).txid(), 0),
+            ..Default::default()
+        });
+        let txid = tx.txid();
+        wallet.watch_broadcast(&tx).unwrap();
+
+        wallet.check_mempool().await.unwrap();
+        chain.evict(&txid);
+
+        // another transaction spends the same input
+        let mut replacement = create_dummy_tx(900);
+        replacement.input = tx.input.clone();
+        chain.add_to_mempool(replacement);
+
+        // it isn't rebroadcast or watched anymore
+        wallet.check_mempool().await.unwrap();
+        assert!(!chain.is_in_mempool(&txid));
+        assert!(wallet.get_mempool_statuses().unwrap().is_empty());
+        let watched = wallet
+            .storage
+            .get_data::<Transaction>(watched_broadcast_key(&txid));
+        assert_eq!(watched.unwrap(), None);
+    }
 }
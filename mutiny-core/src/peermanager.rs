@@ -4,11 +4,13 @@ use crate::messagehandler::MutinyMessageHandler;
 use crate::networking::socket::{schedule_descriptor_read, MutinySocketDescriptor};
 use crate::node::{NetworkGraph, OnionMessenger};
 use crate::storage::MutinyStorage;
+use crate::utils::sleep;
 use crate::{error::MutinyError, fees::MutinyFeeEstimator};
 use crate::{gossip, ldkstorage::PhantomChannelManager, logging::MutinyLogger};
 use crate::{gossip::read_peer_info, node::PubkeyConnectionInfo};
 use bitcoin::key::{Secp256k1, Verification};
 use bitcoin::secp256k1::{PublicKey, Signing};
+use futures::lock::{Mutex as AsyncMutex, OwnedMutexGuard};
 use lightning::blinded_path::BlindedPath;
 use lightning::events::{MessageSendEvent, MessageSendEventsProvider};
 use lightning::ln::features::{InitFeatures, NodeFeatures};
@@ -21,8 +23,10 @@ use lightning::routing::gossip::NodeId;
 use lightning::sign::EntropySource;
 use lightning::util::logger::Logger;
 use lightning::{ln::msgs::SocketAddress, log_warn};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[cfg(target_arch = "wasm32")]
 use crate::networking::ws_socket::WsTcpSocketDescriptor;
@@ -33,6 +37,45 @@ use lightning::ln::peer_handler::SocketDescriptor as LdkSocketDescriptor;
 #[cfg(target_arch = "wasm32")]
 use crate::networking::proxy::WsProxy;
 
+/// Number of peers we try to stay connected to, topped up with nodes from the network graph
+pub(crate) const MIN_CONNECTED_PEERS: usize = 3;
+/// Nodes with fewer channels than this aren't considered when discovering peers
+const MIN_DISCOVERY_CHANNELS: usize = 20;
+/// How long other attempts to connect to a peer wait for the handshake of a new connection
+const HANDSHAKE_WAIT_MS: i32 = 10_000;
+
+type PeerLocks = Mutex<HashMap<PublicKey, Arc<AsyncMutex<()>>>>;
+
+/// Makes sure we only do one thing at a time with each peer, shared by all of our nodes.
+///
+/// Connecting to a peer that is already being connected to waits for that attempt
+/// instead of opening another socket, and channel opens to the same peer run one
+/// after the other so a retried open doesn't race the first one.
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionRegistry {
+    connecting: Arc<PeerLocks>,
+    opening: Arc<PeerLocks>,
+}
+
+impl ConnectionRegistry {
+    /// Waits for any other connection attempt to the peer to finish
+    pub(crate) async fn lock_connection(&self, pubkey: &PublicKey) -> OwnedMutexGuard<()> {
+        Self::peer_lock(&self.connecting, pubkey).lock_owned().await
+    }
+
+    /// Waits for any other channel open to the peer to finish
+    pub(crate) async fn lock_channel_open(&self, pubkey: &PublicKey) -> OwnedMutexGuard<()> {
+        Self::peer_lock(&self.opening, pubkey).lock_owned().await
+    }
+
+    fn peer_lock(locks: &PeerLocks, pubkey: &PublicKey) -> Arc<AsyncMutex<()>> {
+        let mut locks = locks.lock().expect("peer locks poisoned");
+        // forget the peers nobody is waiting on anymore
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(*pubkey).or_default().clone()
+    }
+}
+
 pub trait PeerManager: Send + Sync + 'static {
     fn get_peer_node_ids(&self) -> Vec<PublicKey>;
 
@@ -355,18 +398,25 @@ pub(crate) async fn connect_peer_if_necessary<
 >(
     #[cfg(target_arch = "wasm32")] websocket_proxy_addr: &str,
     peer_connection_info: &PubkeyConnectionInfo,
+    connections: &ConnectionRegistry,
     storage: &S,
     logger: Arc<MutinyLogger>,
     peer_manager: Arc<P>,
     fee_estimator: Arc<MutinyFeeEstimator<S>>,
     stop: Arc<AtomicBool>,
 ) -> Result<(), MutinyError> {
+    // if someone else is connecting to them, wait and use their connection
+    let _guard = connections
+        .lock_connection(&peer_connection_info.pubkey)
+        .await;
     if peer_manager
         .get_peer_node_ids()
         .contains(&peer_connection_info.pubkey)
     {
         Ok(())
     } else {
+        let handshake_peer_manager = peer_manager.clone();
+
         // make sure we have the device lock before connecting
         // otherwise we could cause force closes.
         // If we didn't have the lock last, we need to panic because
@@ -447,10 +497,87 @@ pub(crate) async fn connect_peer_if_necessary<
             }
         };
 
+        // hold off the other attempts until they can see the connection
+        if ret.is_ok() {
+            wait_for_handshake(
+                handshake_peer_manager.as_ref(),
+                &peer_connection_info.pubkey,
+            )
+            .await;
+        }
+
         ret
     }
 }
 
+async fn wait_for_handshake<P: PeerManager>(peer_manager: &P, pubkey: &PublicKey) {
+    for _ in 0..HANDSHAKE_WAIT_MS / 100 {
+        if peer_manager.get_peer_node_ids().contains(pubkey) {
+            return;
+        }
+        sleep(100).await;
+    }
+}
+
+/// Picks well connected nodes from the network graph to connect to when we have fewer than
+/// [MIN_CONNECTED_PEERS] peers, most connected first.
+///
+/// Only nodes announcing a clearnet address are considered, and the ones in `exclude`,
+/// like the ones we already failed to connect to, are skipped.
+pub(crate) fn discover_peers(
+    network_graph: &NetworkGraph,
+    connected: &[NodeId],
+    exclude: &HashSet<NodeId>,
+) -> Vec<PubkeyConnectionInfo> {
+    let graph = network_graph.read_only();
+    let nodes = graph
+        .nodes()
+        .unordered_iter()
+        .filter_map(|(node_id, node)| {
+            let addresses = node.announcement_info.as_ref()?.addresses();
+            Some((*node_id, node.channels.len(), addresses))
+        });
+    pick_discovered_peers(nodes, connected, exclude)
+}
+
+/// Picks from nodes given as their id, number of channels and announced addresses,
+/// see [discover_peers]
+fn pick_discovered_peers<'a>(
+    nodes: impl Iterator<Item = (NodeId, usize, &'a [SocketAddress])>,
+    connected: &[NodeId],
+    exclude: &HashSet<NodeId>,
+) -> Vec<PubkeyConnectionInfo> {
+    let limit = MIN_CONNECTED_PEERS.saturating_sub(connected.len());
+    if limit == 0 {
+        return vec![];
+    }
+
+    let mut candidates: Vec<(usize, PubkeyConnectionInfo)> = nodes
+        .filter(|(node_id, channels, _)| {
+            *channels >= MIN_DISCOVERY_CHANNELS
+                && !connected.contains(node_id)
+                && !exclude.contains(node_id)
+        })
+        .filter_map(|(node_id, channels, addresses)| {
+            let pubkey = node_id.as_pubkey().ok()?;
+            let addr = addresses.iter().find_map(|a| match a {
+                SocketAddress::TcpIpV4 { addr, port } => Some(SocketAddr::from((*addr, *port))),
+                SocketAddress::TcpIpV6 { addr, port } => Some(SocketAddr::from((*addr, *port))),
+                _ => None,
+            })?;
+            let info = PubkeyConnectionInfo::new(&format!("{pubkey}@{addr}")).ok()?;
+            Some((channels, info))
+        })
+        .collect();
+
+    candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    candidates
+        .into_iter()
+        .take(limit)
+        .map(|(_, info)| info)
+        .collect()
+}
+
 #[cfg(target_arch = "wasm32")]
 async fn connect_peer<P: PeerManager>(
     #[cfg(target_arch = "wasm32")] websocket_proxy_addr: &str,
@@ -504,7 +631,6 @@ async fn connect_peer<P: PeerManager>(
 
 #[cfg(target_arch = "wasm32")]
 fn try_parse_addr_string(addr: &str) -> (Option<std::net::SocketAddr>, Option<SocketAddress>) {
-    use std::net::SocketAddr;
     let socket_addr = addr.parse::<SocketAddr>().ok();
     let net_addr = socket_addr.map(|socket_addr| match socket_addr {
         SocketAddr::V4(sockaddr) => SocketAddress::TcpIpV4 {
@@ -518,3 +644,72 @@ fn try_parse_addr_string(addr: &str) -> (Option<std::net::SocketAddr>, Option<So
     });
     (socket_addr, net_addr)
 }
+
+#[cfg(test)]
+mod test {
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn node_id(byte: u8) -> NodeId {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from_pubkey(&secret_key.public_key(&secp))
+    }
+
+    fn clearnet(port: u16) -> SocketAddress {
+        SocketAddress::TcpIpV4 {
+            addr: [127, 0, 0, 1],
+            port,
+        }
+    }
+
+    #[test]
+    fn test_discover_peers() {
+        let onion = SocketAddress::OnionV2([0; 12]);
+        let small = [clearnet(1)];
+        let hub = [clearnet(2)];
+        let bigger_hub = [onion.clone(), clearnet(3)];
+        let onion_hub = [onion];
+        let nodes = [
+            (node_id(1), MIN_DISCOVERY_CHANNELS - 1, &small[..]),
+            (node_id(2), MIN_DISCOVERY_CHANNELS, &hub[..]),
+            (node_id(3), MIN_DISCOVERY_CHANNELS + 5, &bigger_hub[..]),
+            (node_id(4), MIN_DISCOVERY_CHANNELS + 10, &onion_hub[..]),
+            (node_id(5), MIN_DISCOVERY_CHANNELS + 10, &[][..]),
+        ];
+
+        // small nodes and ones without a clearnet address are skipped, biggest first
+        let peers = pick_discovered_peers(nodes.iter().cloned(), &[], &HashSet::new());
+        let pubkeys: Vec<NodeId> = peers
+            .iter()
+            .map(|p| NodeId::from_pubkey(&p.pubkey))
+            .collect();
+        assert_eq!(pubkeys, vec![node_id(3), node_id(2)]);
+        assert_eq!(
+            peers[0].original_connection_string,
+            format!("{}@127.0.0.1:3", peers[0].pubkey)
+        );
+
+        // peers we are connected to or failed to connect to are skipped
+        let peers = pick_discovered_peers(
+            nodes.iter().cloned(),
+            &[node_id(3)],
+            &HashSet::from([node_id(2)]),
+        );
+        assert!(peers.is_empty());
+
+        // only top up to the minimum number of peers
+        let connected = [node_id(6), node_id(7)];
+        let peers = pick_discovered_peers(nodes.iter().cloned(), &connected, &HashSet::new());
+        assert_eq!(peers.len(), MIN_CONNECTED_PEERS - connected.len());
+        assert_eq!(NodeId::from_pubkey(&peers[0].pubkey), node_id(3));
+
+        let connected: Vec<NodeId> = (6..6 + MIN_CONNECTED_PEERS as u8).map(node_id).collect();
+        let peers = pick_discovered_peers(nodes.iter().cloned(), &connected, &HashSet::new());
+        assert!(peers.is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+//! Peer backups with `option_provide_storage`. Our LSP holds an encrypted backup of
+//! our channels with it and gives it back every time we reconnect, so we can recover
+//! the funds of the channels we lost when restoring from seed.
+//!
+//! The backup only has the static data of the channels, see [ChannelBackup]. We never
+//! load a channel monitor given by a peer, it's our counterparty and could give us a
+//! revoked state to have us broadcast it.
+//!
+//! We hold the backups of the peers we have channels with in turn, as we advertise
+//! the feature.
+
+use crate::encrypt::{decrypt_with_key, encrypt_with_key};
+use crate::error::MutinyError;
+use crate::scb::ChannelBackup;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use lightning::io::{Error, Read};
+use lightning::ln::features::{InitFeatures, NodeFeatures};
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::wire::Type;
+use lightning::util::ser::{Readable, Writeable, Writer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Message type of `peer_storage`, a backup for the peer to hold
+pub(crate) const PEER_STORAGE_TYPE: u16 = 7;
+/// Message type of `your_peer_storage`, the backup the peer holds for us
+pub(crate) const YOUR_PEER_STORAGE_TYPE: u16 = 9;
+/// Largest backup a peer holds for us
+pub(crate) const MAX_PEER_STORAGE_LEN: usize = 65531;
+/// How often we send our backup when it changed and recover from the ones we got back
+pub(crate) const PEER_STORAGE_CHECK_INTERVAL_SECS: u64 = 60;
+/// Optional feature bit of `option_provide_storage`
+pub(crate) const PROVIDE_STORAGE_FEATURE_BIT: usize = 43;
+/// Prefix of the backups we hold for our peers, keyed by their pubkey
+pub(crate) const HELD_PEER_STORAGE_PREFIX: &str = "peer_storage/";
+
+/// Sets `option_provide_storage` on features
+fn set_provide_storage(flags: &[u8]) -> Vec<u8> {
+    let byte = PROVIDE_STORAGE_FEATURE_BIT / 8;
+    let mut flags = flags.to_vec();
+    if flags.len() <= byte {
+        flags.resize(byte + 1, 0);
+    }
+    flags[byte] |= 1 << (PROVIDE_STORAGE_FEATURE_BIT % 8);
+    flags
+}
+
+pub(crate) fn with_provide_storage_init(features: InitFeatures) -> InitFeatures {
+    InitFeatures::from_le_bytes(set_provide_storage(features.le_flags()))
+}
+
+pub(crate) fn with_provide_storage_node(features: NodeFeatures) -> NodeFeatures {
+    NodeFeatures::from_le_bytes(set_provide_storage(features.le_flags()))
+}
+
+/// If the peer holds backups for us, either as an optional or required feature
+pub(crate) fn provides_storage(features: &InitFeatures) -> bool {
+    let byte = PROVIDE_STORAGE_FEATURE_BIT / 8;
+    // the required bit is the even one below the optional one
+    let mask = 0b11 << ((PROVIDE_STORAGE_FEATURE_BIT - 1) % 8);
+    features
+        .le_flags()
+        .get(byte)
+        .is_some_and(|flags| flags & mask != 0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerStorageMessage {
+    /// Our backup for the peer to hold
+    PeerStorage { blob: Vec<u8> },
+    /// The backup the peer holds for us, sent when we connect
+    YourPeerStorage { blob: Vec<u8> },
+}
+
+impl PeerStorageMessage {
+    pub(crate) fn read<R: Read>(
+        message_type: u16,
+        buffer: &mut R,
+    ) -> Result<Option<Self>, DecodeError> {
+        if message_type != PEER_STORAGE_TYPE && message_type != YOUR_PEER_STORAGE_TYPE {
+            return Ok(None);
+        }
+
+        let len: u16 = Readable::read(buffer)?;
+        let mut blob = vec![0; len as usize];
+        buffer.read_exact(&mut blob)?;
+
+        let message = if message_type == PEER_STORAGE_TYPE {
+            PeerStorageMessage::PeerStorage { blob }
+        } else {
+            PeerStorageMessage::YourPeerStorage { blob }
+        };
+        Ok(Some(message))
+    }
+
+    fn blob(&self) -> &[u8] {
+        match self {
+            PeerStorageMessage::PeerStorage { blob } => blob,
+            PeerStorageMessage::YourPeerStorage { blob } => blob,
+        }
+    }
+}
+
+impl Type for PeerStorageMessage {
+    fn type_id(&self) -> u16 {
+        match self {
+            PeerStorageMessage::PeerStorage { .. } => PEER_STORAGE_TYPE,
+            PeerStorageMessage::YourPeerStorage { .. } => YOUR_PEER_STORAGE_TYPE,
+        }
+    }
+}
+
+impl Writeable for PeerStorageMessage {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+        let blob = self.blob();
+        (blob.len() as u16).write(writer)?;
+        writer.write_all(blob)
+    }
+}
+
+/// The backup of our channels with a peer that the peer holds for us
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerBackup {
+    pub channels: Vec<ChannelBackup>,
+    /// Unix timestamp of when the backup was created
+    pub created_at: u64,
+}
+
+impl PeerBackup {
+    /// Encrypts the backup with a key derived from our seed, so the peer can't read it
+    pub(crate) fn encrypt(&self, key: &SecretKey) -> Result<Vec<u8>, MutinyError> {
+        let bytes = serde_json::to_vec(self)?;
+        Ok(encrypt_with_key(key, &bytes))
+    }
+
+    pub(crate) fn decrypt(blob: &[u8], key: &SecretKey) -> Result<Self, MutinyError> {
+        let decrypted = decrypt_with_key(key, blob.to_vec())?;
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+/// Queues our backups for the peers and keeps the backups they give back to us,
+/// for the [crate::messagehandler::MutinyMessageHandler]
+pub(crate) struct PeerStorageHandler {
+    encryption_key: SecretKey,
+    pending_msgs: Mutex<Vec<(PublicKey, PeerStorageMessage)>>,
+    /// Backups our peers gave back, until we recover from them
+    received: Mutex<Vec<(PublicKey, Vec<u8>)>>,
+    /// Backups our peers want us to hold, until they are persisted
+    to_hold: Mutex<Vec<(PublicKey, Vec<u8>)>>,
+    /// The peers that were connected the last time we checked, so we give
+    /// back the backups we hold when they reconnect
+    connected: Mutex<HashSet<PublicKey>>,
+    /// The peer and hash of the channels of the last backup we sent,
+    /// we only send it again once the channels change
+    last_sent: Mutex<Option<(PublicKey, sha256::Hash)>>,
+}
+
+impl PeerStorageHandler {
+    pub(crate) fn new(encryption_key: SecretKey) -> Self {
+        Self {
+            encryption_key,
+            pending_msgs: Mutex::new(vec![]),
+            received: Mutex::new(vec![]),
+            to_hold: Mutex::new(vec![]),
+            connected: Mutex::new(HashSet::new()),
+            last_sent: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn encryption_key(&self) -> &SecretKey {
+        &self.encryption_key
+    }
+
+    /// Queues the backup for the peer, unless it already has this one.
+    /// Returns if the backup was queued.
+    pub(crate) fn send_backup(
+        &self,
+        peer: PublicKey,
+        backup: &PeerBackup,
+    ) -> Result<bool, MutinyError> {
+        let hash = sha256::Hash::hash(&serde_json::to_vec(&backup.channels)?);
+        let mut last_sent = self.last_sent.lock().expect("peer storage poisoned");
+        if *last_sent == Some((peer, hash)) {
+            return Ok(false);
+        }
+
+        let blob = backup.encrypt(&self.encryption_key)?;
+        // don't try again until the channels change
+        *last_sent = Some((peer, hash));
+        if blob.len() > MAX_PEER_STORAGE_LEN {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        self.pending_msgs
+            .lock()
+            .expect("peer storage poisoned")
+            .push((peer, PeerStorageMessage::PeerStorage { blob }));
+        Ok(true)
+    }
+
+    /// Forgets what we sent, so the backup is sent again,
+    /// in case the peer lost it while we were disconnected
+    pub(crate) fn reset_last_sent(&self) {
+        *self.last_sent.lock().expect("peer storage poisoned") = None;
+    }
+
+    pub(crate) fn received_backup(&self, peer: PublicKey, blob: Vec<u8>) {
+        let mut received = self.received.lock().expect("peer storage poisoned");
+        // only the latest backup of every peer matters
+        received.retain(|(p, _)| *p != peer);
+        received.push((peer, blob));
+    }
+
+    pub(crate) fn take_received_backups(&self) -> Vec<(PublicKey, Vec<u8>)> {
+        std::mem::take(&mut *self.received.lock().expect("peer storage poisoned"))
+    }
+
+    pub(crate) fn hold_backup(&self, peer: PublicKey, blob: Vec<u8>) {
+        let mut to_hold = self.to_hold.lock().expect("peer storage poisoned");
+        to_hold.retain(|(p, _)| *p != peer);
+        to_hold.push((peer, blob));
+    }
+
+    pub(crate) fn take_backups_to_hold(&self) -> Vec<(PublicKey, Vec<u8>)> {
+        std::mem::take(&mut *self.to_hold.lock().expect("peer storage poisoned"))
+    }
+
+    /// Updates the connected peers, returning the ones that connected since the last time
+    pub(crate) fn newly_connected(&self, peers: HashSet<PublicKey>) -> Vec<PublicKey> {
+        let mut connected = self.connected.lock().expect("peer storage poisoned");
+        let new = peers.difference(&connected).copied().collect();
+        *connected = peers;
+        new
+    }
+
+    /// Queues the backup we hold for the peer, to give it back
+    pub(crate) fn give_back_backup(&self, peer: PublicKey, blob: Vec<u8>) {
+        self.pending_msgs
+            .lock()
+            .expect("peer storage poisoned")
+            .push((peer, PeerStorageMessage::YourPeerStorage { blob }));
+    }
+
+    pub(crate) fn get_and_clear_pending_msg(&self) -> Vec<(PublicKey, PeerStorageMessage)> {
+        std::mem::take(&mut *self.pending_msgs.lock().expect("peer storage poisoned"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scb::backup_encryption_key;
+    use bitcoin::bip32::ExtendedPrivKey;
+    use bitcoin::{Network, OutPoint, Txid};
+    use lightning::io::Cursor;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn peer() -> PublicKey {
+        PublicKey::from_str("02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b")
+            .unwrap()
+    }
+
+    fn backup() -> PeerBackup {
+        PeerBackup {
+            channels: vec![ChannelBackup {
+                node_id: "node".to_string(),
+                counterparty: peer(),
+                connection_string: None,
+                funding_outpoint: OutPoint::new(Txid::all_zeros(), 1),
+                channel_value_sats: 100_000,
+                channel_keys_id: "07".repeat(32),
+            }],
+            created_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_peer_storage_message_serialization() {
+        let message = PeerStorageMessage::YourPeerStorage {
+            blob: vec![1, 2, 3],
+        };
+        let bytes = message.encode();
+        assert_eq!(bytes, vec![0, 3, 1, 2, 3]);
+
+        let read = PeerStorageMessage::read(YOUR_PEER_STORAGE_TYPE, &mut Cursor::new(&bytes));
+        assert_eq!(read.unwrap(), Some(message));
+
+        // other messages are left to the other handlers
+        let read = PeerStorageMessage::read(32768, &mut Cursor::new(&bytes));
+        assert_eq!(read.unwrap(), None);
+    }
+
+    #[test]
+    fn test_send_peer_backup() {
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[0; 32]).unwrap();
+        let key = backup_encryption_key(xpriv).unwrap();
+        let handler = PeerStorageHandler::new(key);
+        let backup = backup();
+
+        // only sent again once the channels change
+        assert!(handler.send_backup(peer(), &backup).unwrap());
+        let mut newer = backup.clone();
+        newer.created_at += 60;
+        assert!(!handler.send_backup(peer(), &newer).unwrap());
+        handler.reset_last_sent();
+        assert!(handler.send_backup(peer(), &newer).unwrap());
+
+        let msgs = handler.get_and_clear_pending_msg();
+        assert_eq!(msgs.len(), 2);
+        assert!(handler.get_and_clear_pending_msg().is_empty());
+
+        // the peer gives it back, which only we can read
+        handler.received_backup(peer(), msgs[0].1.blob().to_vec());
+        let received = handler.take_received_backups();
+        assert_eq!(received.len(), 1);
+        assert_eq!(PeerBackup::decrypt(&received[0].1, &key).unwrap(), backup);
+
+        let other = ExtendedPrivKey::new_master(Network::Regtest, &[1; 32]).unwrap();
+        let other = backup_encryption_key(other).unwrap();
+        assert!(PeerBackup::decrypt(&received[0].1, &other).is_err());
+    }
+
+    #[test]
+    fn test_provide_storage_feature() {
+        let features = with_provide_storage_init(InitFeatures::empty());
+        assert!(provides_storage(&features));
+        assert!(!features.requires_unknown_bits());
+        assert!(!provides_storage(&InitFeatures::empty()));
+
+        // keeps the features we already had
+        let mut features = InitFeatures::empty();
+        features.set_optional_custom_bit(729).unwrap();
+        let features = with_provide_storage_init(features);
+        assert!(provides_storage(&features));
+        assert!(features.supports_unknown_bits());
+        assert_eq!(features.le_flags().len(), 92);
+    }
+
+    #[test]
+    fn test_hold_peer_backup() {
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[0; 32]).unwrap();
+        let handler = PeerStorageHandler::new(backup_encryption_key(xpriv).unwrap());
+
+        // only the latest backup of the peer is held
+        handler.hold_backup(peer(), vec![1]);
+        handler.hold_backup(peer(), vec![2]);
+        assert_eq!(handler.take_backups_to_hold(), vec![(peer(), vec![2])]);
+        assert!(handler.take_backups_to_hold().is_empty());
+
+        // given back once when the peer connects
+        let peers = HashSet::from([peer()]);
+        assert_eq!(handler.newly_connected(peers.clone()), vec![peer()]);
+        assert!(handler.newly_connected(peers.clone()).is_empty());
+        assert!(handler.newly_connected(HashSet::new()).is_empty());
+        assert_eq!(handler.newly_connected(peers), vec![peer()]);
+
+        handler.give_back_backup(peer(), vec![2]);
+        let msgs = handler.get_and_clear_pending_msg();
+        assert_eq!(
+            msgs,
+            vec![(
+                peer(),
+                PeerStorageMessage::YourPeerStorage { blob: vec![2] }
+            )]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::utils;
+use crate::utils::Mutex;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Number of timings we keep in memory
+const MAX_TIMINGS: usize = 500;
+
+/// Number of timings in [PerformanceReport::slowest_operations]
+const SLOWEST_OPERATIONS: usize = 10;
+
+/// Something the wallet does that can take a while
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Operation {
+    /// Building the wallet, from loading storage until it is returned
+    Startup,
+    /// Waiting for the device lock during startup
+    DeviceLock,
+    /// Loading the node manager and starting the nodes during startup
+    NodeManagerInit,
+    /// Starting the federation clients during startup
+    FederationsInit,
+    /// Syncing the lightning nodes and the on-chain wallet
+    Sync,
+    /// Syncing the network graph and scorer
+    GossipSync,
+    /// Paying a lightning invoice
+    PayInvoice,
+    /// Opening a channel, until the funding transaction is broadcast
+    OpenChannel,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Startup => write!(f, "startup"),
+            Self::DeviceLock => write!(f, "device_lock"),
+            Self::NodeManagerInit => write!(f, "node_manager_init"),
+            Self::FederationsInit => write!(f, "federations_init"),
+            Self::Sync => write!(f, "sync"),
+            Self::GossipSync => write!(f, "gossip_sync"),
+            Self::PayInvoice => write!(f, "pay_invoice"),
+            Self::OpenChannel => write!(f, "open_channel"),
+        }
+    }
+}
+
+/// How long a single run of an [Operation] took
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationTiming {
+    pub operation: Operation,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Unix timestamp of when the operation finished
+    pub timestamp: u64,
+}
+
+/// Summary of the recent runs of an [Operation]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationStats {
+    pub operation: Operation,
+    pub count: usize,
+    pub failures: usize,
+    pub avg_ms: u64,
+    pub max_ms: u64,
+}
+
+/// See [crate::MutinyWallet::get_performance_report]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PerformanceReport {
+    /// How long building the wallet took this session
+    pub startup_ms: Option<u64>,
+    /// Slowest recent operations, slowest first
+    pub slowest_operations: Vec<OperationTiming>,
+    /// Stats of the recent runs of each operation
+    pub operations: Vec<OperationStats>,
+}
+
+/// Keeps the timings of the recent operations in memory.
+///
+/// With the `tracing` feature every timed operation also runs in a span
+/// and its timing is emitted as a tracing event.
+pub struct PerformanceTracker {
+    timings: Mutex<VecDeque<OperationTiming>>,
+}
+
+impl Default for PerformanceTracker {
+    fn default() -> Self {
+        Self {
+            timings: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl PerformanceTracker {
+    /// Runs the future and records how long it took
+    pub(crate) async fn time<T, E>(
+        &self,
+        operation: Operation,
+        fut: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let start = Instant::now();
+
+        #[cfg(feature = "tracing")]
+        let res = fut
+            .instrument(tracing::info_span!("mutiny", operation = %operation))
+            .await;
+        #[cfg(not(feature = "tracing"))]
+        let res = fut.await;
+
+        self.record(operation, start, res.is_ok());
+        res
+    }
+
+    /// Records an operation that started at `start` and just finished
+    pub(crate) fn record(&self, operation: Operation, start: Instant, success: bool) {
+        self.record_duration(operation, start.elapsed().as_millis() as u64, success)
+    }
+
+    /// Records an operation that was timed elsewhere, like payments by their [crate::latency::PaymentTrace]
+    pub(crate) fn record_duration(&self, operation: Operation, duration_ms: u64, success: bool) {
+        #[cfg(feature = "tracing")]
+        tracing::info!(operation = %operation, duration_ms, success, "operation finished");
+
+        let timing = OperationTiming {
+            operation,
+            duration_ms,
+            success,
+            timestamp: utils::now().as_secs(),
+        };
+        if let Ok(mut timings) = self.timings.lock() {
+            if timings.len() >= MAX_TIMINGS {
+                timings.pop_front();
+            }
+            timings.push_back(timing);
+        }
+    }
+
+    pub fn report(&self) -> PerformanceReport {
+        let timings: Vec<OperationTiming> = match self.timings.lock() {
+            Ok(timings) => timings.iter().cloned().collect(),
+            Err(_) => return PerformanceReport::default(),
+        };
+
+        let startup_ms = timings
+            .iter()
+            .rev()
+            .find(|t| t.operation == Operation::Startup)
+            .map(|t| t.duration_ms);
+
+        let mut slowest_operations = timings.clone();
+        slowest_operations.sort_by(|a, b| b.duration_ms.cmp(&a.duration_ms));
+        slowest_operations.truncate(SLOWEST_OPERATIONS);
+
+        let mut by_operation: HashMap<Operation, Vec<&OperationTiming>> = HashMap::new();
+        for timing in timings.iter() {
+            by_operation
+                .entry(timing.operation)
+                .or_default()
+                .push(timing);
+        }
+        let mut operations: Vec<OperationStats> = by_operation
+            .into_iter()
+            .map(|(operation, timings)| {
+                let total_ms: u64 = timings.iter().map(|t| t.duration_ms).sum();
+                OperationStats {
+                    operation,
+                    count: timings.len(),
+                    failures: timings.iter().filter(|t| !t.success).count(),
+                    avg_ms: total_ms / timings.len() as u64,
+                    max_ms: timings
+                        .iter()
+                        .map(|t| t.duration_ms)
+                        .max()
+                        .unwrap_or_default(),
+                }
+            })
+            .collect();
+        operations.sort_by(|a, b| b.max_ms.cmp(&a.max_ms));
+
+        PerformanceReport {
+            startup_ms,
+            slowest_operations,
+            operations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MutinyError;
+    use crate::utils::sleep;
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    async fn test_performance_report() {
+        let tracker = PerformanceTracker::default();
+        assert_eq!(tracker.report(), PerformanceReport::default());
+
+        let res: Result<(), MutinyError> = tracker
+            .time(Operation::Sync, async {
+                sleep(50).await;
+                Ok(())
+            })
+            .await;
+        assert!(res.is_ok());
+        let res: Result<(), MutinyError> = tracker
+            .time(Operation::Sync, async {
+                Err(MutinyError::ChainAccessFailed)
+            })
+            .await;
+        assert!(res.is_err());
+        tracker.record(Operation::Startup, Instant::now(), true);
+
+        let report = tracker.report();
+        assert!(report.startup_ms.is_some());
+        assert_eq!(report.slowest_operations.len(), 3);
+        assert_eq!(report.slowest_operations[0].operation, Operation::Sync);
+        assert!(report.slowest_operations[0].duration_ms >= 50);
+
+        let sync = report
+            .operations
+            .iter()
+            .find(|s| s.operation == Operation::Sync)
+            .unwrap();
+        assert_eq!(sync.count, 2);
+        assert_eq!(sync.failures, 1);
+        assert!(sync.max_ms >= 50);
+    }
+}
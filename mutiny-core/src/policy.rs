@@ -0,0 +1,625 @@
+use crate::encrypt::{get_encryption_key_with_params, KeyStretchingParams};
+use crate::error::MutinyError;
+use crate::labels::LabelStorage;
+use crate::logging::MutinyLogger;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::secp256k1;
+use lightning::util::logger::Logger;
+use lightning::{log_info, log_warn};
+use lnurl::lnurl::LnUrl;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+const SPENDING_POLICY_KEY: &str = "spending_policy";
+const SPENDING_POLICY_PIN_KEY: &str = "spending_policy_pin";
+const POLICY_SPENDS_KEY: &str = "spending_policy_spends";
+const PIN_ATTEMPTS_KEY: &str = "spending_policy_pin_attempts";
+/// Wrong PINs allowed before the next attempt has to wait
+const FREE_PIN_ATTEMPTS: u32 = 3;
+/// Wait after the first wrong PIN over the free attempts, doubled for every one after it
+const PIN_BACKOFF_SECS: u64 = 30;
+const MAX_PIN_BACKOFF_SECS: u64 = DAY_SECS;
+const DAY_SECS: u64 = 24 * 60 * 60;
+const WEEK_SECS: u64 = 7 * DAY_SECS;
+/// How long payments over the PIN threshold are allowed after entering the PIN
+pub const SPENDING_PIN_UNLOCK_SECS: u64 = 5 * 60;
+
+/// Limits on everything that leaves the wallet: lightning payments, keysends,
+/// on-chain sends and NWC payments. Every limit is optional.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpendingPolicy {
+    /// Maximum sats sent in the last 24 hours
+    pub daily_limit_sats: Option<u64>,
+    /// Maximum sats sent in the last 7 days
+    pub weekly_limit_sats: Option<u64>,
+    /// Maximum sats for a single payment
+    pub max_payment_sats: Option<u64>,
+    /// If set, payments can only go to these node pubkeys, addresses, lnurls or contact ids
+    pub whitelist: Option<Vec<String>>,
+    /// Payments over this amount need the wallet to be unlocked with the PIN,
+    /// see [SpendingPolicyManager::unlock]
+    pub pin_threshold_sats: Option<u64>,
+}
+
+/// How much of the velocity limits has been used
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpendingPolicyUsage {
+    pub spent_last_day: u64,
+    pub spent_last_week: u64,
+    /// Unix timestamp until which payments over the PIN threshold are allowed
+    pub unlocked_until: Option<u64>,
+}
+
+/// A payment counted towards the velocity limits
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+struct PolicySpend {
+    timestamp: u64,
+    amount_sats: u64,
+}
+
+/// The stored spending PIN, hashed with argon2
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct PinHash {
+    salt: [u8; 16],
+    hash: [u8; 32],
+    params: KeyStretchingParams,
+}
+
+impl PinHash {
+    fn new(pin: &str) -> Result<Self, MutinyError> {
+        let salt: [u8; 16] = secp256k1::rand::random();
+        let params = KeyStretchingParams::default();
+        let hash = get_encryption_key_with_params(pin, &salt, params)?;
+        Ok(Self { salt, hash, params })
+    }
+
+    fn matches(&self, pin: &str) -> Result<bool, MutinyError> {
+        let key = get_encryption_key_with_params(pin, &self.salt, self.params)?;
+        Ok(utils::constant_time_eq(&key, &self.hash))
+    }
+}
+
+/// Wrong PINs entered since the last right one, stored so a restart doesn't reset them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct PinAttempts {
+    failed: u32,
+    /// Unix timestamp of the last wrong PIN
+    last_failed: u64,
+}
+
+impl PinAttempts {
+    /// Unix timestamp until which no PIN is checked, `None` while there are free attempts left
+    fn locked_until(&self) -> Option<u64> {
+        let over = self.failed.checked_sub(FREE_PIN_ATTEMPTS)?;
+        let backoff = 2u64
+            .checked_pow(over)
+            .map_or(MAX_PIN_BACKOFF_SECS, |m| PIN_BACKOFF_SECS.saturating_mul(m))
+            .min(MAX_PIN_BACKOFF_SECS);
+        Some(self.last_failed + backoff)
+    }
+}
+
+/// The amount of a payment that passed the policy, held against the velocity limits
+/// while it is being made so payments made at the same time can't go over them together.
+///
+/// Dropping it releases the amount, [SpendingReservation::complete] counts it as spent.
+pub(crate) struct SpendingReservation<'a, S: MutinyStorage> {
+    manager: &'a SpendingPolicyManager<S>,
+    id: u64,
+}
+
+impl<S: MutinyStorage> SpendingReservation<'_, S> {
+    /// Counts the payment towards the velocity limits, called after the payment was made
+    /// so failing to record it is only logged
+    pub(crate) fn complete(self) {
+        let mut reservations = self.manager.reservations.lock().unwrap();
+        if let Some(amount_sats) = reservations.remove(&self.id) {
+            if let Err(e) = self.manager.add_spend(amount_sats) {
+                log_warn!(
+                    self.manager.logger,
+                    "Failed to record payment for spending policy: {e}"
+                );
+            }
+        }
+    }
+}
+
+impl<S: MutinyStorage> Drop for SpendingReservation<'_, S> {
+    fn drop(&mut self) {
+        if let Ok(mut reservations) = self.manager.reservations.lock() {
+            reservations.remove(&self.id);
+        }
+    }
+}
+
+/// Enforces the [SpendingPolicy] and keeps track of what was spent
+pub struct SpendingPolicyManager<S: MutinyStorage> {
+    storage: S,
+    /// Unix timestamp until which payments over the PIN threshold are allowed,
+    /// only kept in memory so a restart locks the wallet again
+    unlocked_until: AtomicU64,
+    /// Amounts of the payments being made, by reservation id, see [SpendingReservation]
+    reservations: Mutex<HashMap<u64, u64>>,
+    next_reservation: AtomicU64,
+    /// Held while checking a PIN so guesses made at the same time all count
+    pin_check: Mutex<()>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> SpendingPolicyManager<S> {
+    pub(crate) fn new(storage: S, logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            storage,
+            unlocked_until: AtomicU64::new(0),
+            reservations: Mutex::new(HashMap::new()),
+            next_reservation: AtomicU64::new(0),
+            pin_check: Mutex::new(()),
+            logger,
+        }
+    }
+
+    pub fn get_policy(&self) -> Result<SpendingPolicy, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(SPENDING_POLICY_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Replaces the policy, if a PIN is set it is needed to make changes
+    pub fn set_policy(&self, policy: SpendingPolicy, pin: Option<&str>) -> Result<(), MutinyError> {
+        self.verify_pin(pin)?;
+        self.storage
+            .set_data(SPENDING_POLICY_KEY.to_string(), policy, None)?;
+        log_info!(self.logger, "Updated spending policy");
+        Ok(())
+    }
+
+    pub fn has_pin(&self) -> Result<bool, MutinyError> {
+        Ok(self.get_pin_hash()?.is_some())
+    }
+
+    /// Sets a new PIN, or removes it if `new_pin` is `None`.
+    /// The current PIN is needed if one is set.
+    pub fn set_pin(
+        &self,
+        current_pin: Option<&str>,
+        new_pin: Option<&str>,
+    ) -> Result<(), MutinyError> {
+        self.verify_pin(current_pin)?;
+        match new_pin {
+            Some(pin) if pin.is_empty() => return Err(MutinyError::InvalidArgumentsError),
+            Some(pin) => {
+                self.storage.set_data(
+                    SPENDING_POLICY_PIN_KEY.to_string(),
+                    PinHash::new(pin)?,
+                    None,
+                )?;
+            }
+            None => self.storage.delete(&[SPENDING_POLICY_PIN_KEY])?,
+        }
+        self.lock();
+        Ok(())
+    }
+
+    /// Allows payments over the PIN threshold for [SPENDING_PIN_UNLOCK_SECS]
+    pub fn unlock(&self, pin: &str) -> Result<(), MutinyError> {
+        self.verify_pin(Some(pin))?;
+        let until = utils::now().as_secs() + SPENDING_PIN_UNLOCK_SECS;
+        self.unlocked_until.store(until, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Requires the PIN again for payments over the threshold
+    pub fn lock(&self) {
+        self.unlocked_until.store(0, Ordering::Relaxed);
+    }
+
+    /// What was spent, payments that are still being made count as spent
+    pub fn get_usage(&self) -> Result<SpendingPolicyUsage, MutinyError> {
+        let reserved: u64 = self.reservations.lock().unwrap().values().sum();
+        self.usage(reserved)
+    }
+
+    fn usage(&self, reserved: u64) -> Result<SpendingPolicyUsage, MutinyError> {
+        let now = utils::now().as_secs();
+        let spends = self.get_spends()?;
+        let unlocked_until = self.unlocked_until.load(Ordering::Relaxed);
+
+        Ok(SpendingPolicyUsage {
+            spent_last_day: spent_since(&spends, now.saturating_sub(DAY_SECS)) + reserved,
+            spent_last_week: spent_since(&spends, now.saturating_sub(WEEK_SECS)) + reserved,
+            unlocked_until: (unlocked_until > now).then_some(unlocked_until),
+        })
+    }
+
+    /// Checks if a payment is allowed by the policy and reserves its amount
+    /// until it is made, see [SpendingReservation].
+    ///
+    /// The destinations are the node pubkeys, addresses or lnurls being paid.
+    /// Contacts on the whitelist allow the destinations we know are theirs,
+    /// their lnurl or lightning address and addresses labeled with them.
+    pub(crate) fn check_payment(
+        &self,
+        amount_sats: u64,
+        destinations: &[String],
+    ) -> Result<SpendingReservation<'_, S>, MutinyError> {
+        let policy = self.get_policy()?;
+
+        if policy.max_payment_sats.is_some_and(|max| amount_sats > max) {
+            log_warn!(
+                self.logger,
+                "Payment of {amount_sats} sats is over the maximum"
+            );
+            return Err(MutinyError::SpendingLimitExceeded);
+        }
+
+        if let Some(whitelist) = policy.whitelist.as_ref() {
+            for destination in destinations {
+                if !self.is_whitelisted(whitelist, destination)? {
+                    log_warn!(self.logger, "Payment destination is not whitelisted");
+                    return Err(MutinyError::DestinationNotAllowed);
+                }
+            }
+        }
+
+        // hold the reservations while checking so concurrent payments see each other
+        let mut reservations = self.reservations.lock().unwrap();
+        if policy.daily_limit_sats.is_some() || policy.weekly_limit_sats.is_some() {
+            let usage = self.usage(reservations.values().sum())?;
+            let over_daily = policy
+                .daily_limit_sats
+                .is_some_and(|limit| usage.spent_last_day + amount_sats > limit);
+            let over_weekly = policy
+                .weekly_limit_sats
+                .is_some_and(|limit| usage.spent_last_week + amount_sats > limit);
+            if over_daily || over_weekly {
+                log_warn!(
+                    self.logger,
+                    "Payment of {amount_sats} sats is over the spend cap"
+                );
+                return Err(MutinyError::SpendingLimitExceeded);
+            }
+        }
+
+        if policy
+            .pin_threshold_sats
+            .is_some_and(|threshold| amount_sats > threshold)
+            && self.has_pin()?
+            && self.unlocked_until.load(Ordering::Relaxed) <= utils::now().as_secs()
+        {
+            return Err(MutinyError::SpendingPinRequired);
+        }
+
+        let id = self.next_reservation.fetch_add(1, Ordering::Relaxed);
+        reservations.insert(id, amount_sats);
+        Ok(SpendingReservation { manager: self, id })
+    }
+
+    /// If the destination is on the whitelist or belongs to a contact that is on it
+    fn is_whitelisted(&self, whitelist: &[String], destination: &str) -> Result<bool, MutinyError> {
+        if whitelist.iter().any(|w| w == destination) {
+            return Ok(true);
+        }
+
+        let contact_ids: Vec<String> = match LnUrl::from_str(destination) {
+            Ok(lnurl) => self
+                .storage
+                .get_contacts()?
+                .into_iter()
+                .filter(|(_, c)| c.has_lnurl(&lnurl))
+                .map(|(id, _)| id)
+                .collect(),
+            Err(_) => self
+                .storage
+                .get_address_labels()?
+                .remove(destination)
+                .unwrap_or_default(),
+        };
+        for id in contact_ids {
+            if whitelist.contains(&id) && self.storage.get_contact(&id)?.is_some() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn add_spend(&self, amount_sats: u64) -> Result<(), MutinyError> {
+        let now = utils::now().as_secs();
+        let mut spends = self.get_spends()?;
+        // we only need a week of history for the limits
+        spends.retain(|s| s.timestamp >= now.saturating_sub(WEEK_SECS));
+        spends.push(PolicySpend {
+            timestamp: now,
+            amount_sats,
+        });
+        self.storage
+            .set_data(POLICY_SPENDS_KEY.to_string(), spends, None)
+    }
+
+    fn get_spends(&self) -> Result<Vec<PolicySpend>, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(POLICY_SPENDS_KEY)?
+            .unwrap_or_default())
+    }
+
+    fn get_pin_hash(&self) -> Result<Option<PinHash>, MutinyError> {
+        self.storage.get_data(SPENDING_POLICY_PIN_KEY)
+    }
+
+    fn get_pin_attempts(&self) -> Result<PinAttempts, MutinyError> {
+        Ok(self.storage.get_data(PIN_ATTEMPTS_KEY)?.unwrap_or_default())
+    }
+
+    /// Succeeds if no PIN is set or the given one matches.
+    ///
+    /// After [FREE_PIN_ATTEMPTS] wrong PINs every attempt has to wait,
+    /// twice as long for each wrong one, until the right PIN is given.
+    fn verify_pin(&self, pin: Option<&str>) -> Result<(), MutinyError> {
+        let _guard = self.pin_check.lock().unwrap();
+        let Some(hash) = self.get_pin_hash()? else {
+            return Ok(());
+        };
+        let Some(pin) = pin else {
+            return Err(MutinyError::IncorrectSpendingPin);
+        };
+
+        let mut attempts = self.get_pin_attempts()?;
+        let now = utils::now().as_secs();
+        if attempts.locked_until().is_some_and(|until| until > now) {
+            return Err(MutinyError::SpendingPinLocked);
+        }
+
+        if !hash.matches(pin)? {
+            attempts.failed += 1;
+            attempts.last_failed = now;
+            self.storage
+                .set_data(PIN_ATTEMPTS_KEY.to_string(), attempts, None)?;
+            log_warn!(
+                self.logger,
+                "Incorrect spending PIN, {} failed attempts",
+                attempts.failed
+            );
+            return Err(MutinyError::IncorrectSpendingPin);
+        }
+
+        if attempts.failed > 0 {
+            self.storage.delete(&[PIN_ATTEMPTS_KEY])?;
+        }
+        Ok(())
+    }
+}
+
+fn spent_since(spends: &[PolicySpend], cutoff: u64) -> u64 {
+    spends
+        .iter()
+        .filter(|s| s.timestamp >= cutoff)
+        .map(|s| s.amount_sats)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::Contact;
+    use crate::storage::MemoryStorage;
+    use bitcoin::Address;
+    use lnurl::lightning_address::LightningAddress;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_manager() -> SpendingPolicyManager<MemoryStorage> {
+        SpendingPolicyManager::new(MemoryStorage::default(), Arc::new(MutinyLogger::default()))
+    }
+
+    #[test]
+    fn test_velocity_limits() {
+        let manager = create_manager();
+        let dest = vec!["destination".to_string()];
+        manager.check_payment(1_000_000, &dest).unwrap();
+
+        let policy = SpendingPolicy {
+            daily_limit_sats: Some(10_000),
+            max_payment_sats: Some(6_000),
+            ..Default::default()
+        };
+        manager.set_policy(policy.clone(), None).unwrap();
+        assert_eq!(manager.get_policy().unwrap(), policy);
+
+        assert_eq!(
+            manager.check_payment(7_000, &dest).err(),
+            Some(MutinyError::SpendingLimitExceeded)
+        );
+        manager.check_payment(6_000, &dest).unwrap().complete();
+        assert_eq!(manager.get_usage().unwrap().spent_last_day, 6_000);
+
+        assert_eq!(
+            manager.check_payment(5_000, &dest).err(),
+            Some(MutinyError::SpendingLimitExceeded)
+        );
+        manager.check_payment(4_000, &dest).unwrap();
+    }
+
+    #[test]
+    fn test_reservations() {
+        let manager = create_manager();
+        let dest = vec!["destination".to_string()];
+        let policy = SpendingPolicy {
+            daily_limit_sats: Some(10_000),
+            ..Default::default()
+        };
+        manager.set_policy(policy, None).unwrap();
+
+        // a payment in flight counts against the limit
+        let reservation = manager.check_payment(6_000, &dest).unwrap();
+        assert_eq!(manager.get_usage().unwrap().spent_last_day, 6_000);
+        assert_eq!(
+            manager.check_payment(6_000, &dest).err(),
+            Some(MutinyError::SpendingLimitExceeded)
+        );
+
+        // and is released if it fails
+        drop(reservation);
+        assert_eq!(manager.get_usage().unwrap().spent_last_day, 0);
+        manager.check_payment(6_000, &dest).unwrap().complete();
+        assert_eq!(manager.get_usage().unwrap().spent_last_day, 6_000);
+    }
+
+    #[test]
+    fn test_whitelist() {
+        let manager = create_manager();
+        let ln_address = LightningAddress::from_str("satoshi@example.com").unwrap();
+        let contact_id = manager
+            .storage
+            .create_new_contact(Contact {
+                name: "Satoshi".to_string(),
+                ln_address: Some(ln_address.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        let address = Address::from_str("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")
+            .unwrap()
+            .assume_checked();
+        manager
+            .storage
+            .set_address_labels(address.clone(), vec![contact_id.clone()])
+            .unwrap();
+
+        let policy = SpendingPolicy {
+            whitelist: Some(vec!["node".to_string(), contact_id.clone()]),
+            ..Default::default()
+        };
+        manager.set_policy(policy, None).unwrap();
+
+        manager.check_payment(1_000, &["node".to_string()]).unwrap();
+        assert_eq!(
+            manager.check_payment(1_000, &["other".to_string()]).err(),
+            Some(MutinyError::DestinationNotAllowed)
+        );
+        // every output of a batch needs to be whitelisted
+        let batch = ["node".to_string(), "other".to_string()];
+        assert_eq!(
+            manager.check_payment(1_000, &batch).err(),
+            Some(MutinyError::DestinationNotAllowed)
+        );
+
+        // the contact's lightning address and addresses labeled with it are allowed
+        let lnurl = ln_address.lnurl();
+        manager.check_payment(1_000, &[lnurl.encode()]).unwrap();
+        manager
+            .check_payment(1_000, &[address.to_string()])
+            .unwrap();
+        let other_lnurl = LightningAddress::from_str("other@example.com")
+            .unwrap()
+            .lnurl();
+        assert_eq!(
+            manager.check_payment(1_000, &[other_lnurl.encode()]).err(),
+            Some(MutinyError::DestinationNotAllowed)
+        );
+    }
+
+    #[test]
+    fn test_spending_pin() {
+        let manager = create_manager();
+        let dest = vec!["destination".to_string()];
+        manager.set_pin(None, Some("1234")).unwrap();
+        assert!(manager.has_pin().unwrap());
+
+        let policy = SpendingPolicy {
+            pin_threshold_sats: Some(1_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            manager.set_policy(policy.clone(), None),
+            Err(MutinyError::IncorrectSpendingPin)
+        );
+        manager.set_policy(policy, Some("1234")).unwrap();
+
+        manager.check_payment(1_000, &dest).unwrap();
+        assert_eq!(
+            manager.check_payment(1_001, &dest).err(),
+            Some(MutinyError::SpendingPinRequired)
+        );
+
+        assert_eq!(
+            manager.unlock("0000"),
+            Err(MutinyError::IncorrectSpendingPin)
+        );
+        manager.unlock("1234").unwrap();
+        manager.check_payment(1_001, &dest).unwrap();
+
+        manager.lock();
+        assert_eq!(
+            manager.check_payment(1_001, &dest).err(),
+            Some(MutinyError::SpendingPinRequired)
+        );
+
+        manager.set_pin(Some("1234"), None).unwrap();
+        assert!(!manager.has_pin().unwrap());
+        manager.check_payment(1_001, &dest).unwrap();
+    }
+
+    #[test]
+    fn test_spending_pin_hash() {
+        let manager = create_manager();
+        manager.set_pin(None, Some("1234")).unwrap();
+        let stored = manager.get_pin_hash().unwrap().unwrap();
+        assert!(stored.matches("1234").unwrap());
+        assert!(!stored.matches("4321").unwrap());
+
+        // the same PIN gets a different salt
+        manager.set_pin(Some("1234"), Some("1234")).unwrap();
+        assert_ne!(manager.get_pin_hash().unwrap().unwrap(), stored);
+    }
+
+    #[test]
+    fn test_spending_pin_backoff() {
+        let manager = create_manager();
+        manager.set_pin(None, Some("1234")).unwrap();
+
+        for _ in 0..FREE_PIN_ATTEMPTS {
+            assert_eq!(
+                manager.unlock("0000"),
+                Err(MutinyError::IncorrectSpendingPin)
+            );
+        }
+        // even the right PIN has to wait now
+        assert_eq!(manager.unlock("1234"), Err(MutinyError::SpendingPinLocked));
+
+        // once the wait is over every wrong PIN doubles it
+        let mut attempts = manager.get_pin_attempts().unwrap();
+        attempts.last_failed -= PIN_BACKOFF_SECS;
+        manager
+            .storage
+            .set_data(PIN_ATTEMPTS_KEY.to_string(), attempts, None)
+            .unwrap();
+        assert_eq!(
+            manager.unlock("0000"),
+            Err(MutinyError::IncorrectSpendingPin)
+        );
+        let attempts = manager.get_pin_attempts().unwrap();
+        assert_eq!(
+            attempts.locked_until(),
+            Some(attempts.last_failed + 2 * PIN_BACKOFF_SECS)
+        );
+        assert_eq!(manager.unlock("1234"), Err(MutinyError::SpendingPinLocked));
+
+        // the right PIN resets the attempts
+        let attempts = PinAttempts {
+            last_failed: 0,
+            ..attempts
+        };
+        manager
+            .storage
+            .set_data(PIN_ATTEMPTS_KEY.to_string(), attempts, None)
+            .unwrap();
+        manager.unlock("1234").unwrap();
+        assert_eq!(manager.get_pin_attempts().unwrap(), PinAttempts::default());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::MutinyError;
+use crate::federation::FederationActivity;
+use crate::storage::MutinyStorage;
+use crate::{ActivityItem, TransactionDetails};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const ACTIVITY_PRICE_PREFIX_KEY: &str = "activity_price/";
+/// The currency prices are always recorded in
+pub(crate) const DEFAULT_PRICE_CURRENCY: &str = "usd";
+/// How often we check for settled activity that doesn't have a price yet
+pub(crate) const PRICE_CHECK_INTERVAL_SECS: u64 = 60;
+/// Prices are only recorded for activity that settled this recently,
+/// the current price would be wrong for anything older
+pub(crate) const PRICE_RECORD_MAX_AGE_SECS: u64 = 60 * 60;
+
+const SATS_PER_BTC: f64 = 100_000_000.0;
+
+/// The bitcoin price around the time an activity item settled
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ActivityPrice {
+    /// Unix timestamp of when the prices were recorded
+    pub timestamp: u64,
+    /// Price of one bitcoin by lowercase currency code
+    pub prices: HashMap<String, f32>,
+}
+
+/// An activity item with its value in fiat at the time it settled,
+/// see [crate::MutinyWallet::get_activity_with_fiat]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FiatActivityItem {
+    pub activity: ActivityItem,
+    pub currency: String,
+    /// Price of one bitcoin when the activity settled, `None` if it wasn't recorded
+    pub price: Option<f32>,
+    /// Value of the activity, negative when sending, `None` if the price wasn't recorded
+    pub fiat_value: Option<f64>,
+}
+
+impl FiatActivityItem {
+    pub(crate) fn new(activity: ActivityItem, currency: String, price: Option<f32>) -> Self {
+        let fiat_value = price
+            .zip(activity_amount(&activity))
+            .map(|(price, (inbound, sats))| {
+                let value = sats as f64 / SATS_PER_BTC * price as f64;
+                if inbound {
+                    value
+                } else {
+                    -value
+                }
+            });
+
+        Self {
+            activity,
+            currency,
+            price,
+            fiat_value,
+        }
+    }
+}
+
+fn activity_price_key(index_key: &str) -> String {
+    format!("{ACTIVITY_PRICE_PREFIX_KEY}{index_key}")
+}
+
+pub(crate) fn get_activity_price<S: MutinyStorage>(
+    storage: &S,
+    index_key: &str,
+) -> Result<Option<ActivityPrice>, MutinyError> {
+    storage.get_data(activity_price_key(index_key))
+}
+
+/// Records the prices for the activity item with the given index key.
+///
+/// Currencies that were already recorded are kept, so the first price we saw wins.
+pub(crate) fn record_activity_price<S: MutinyStorage>(
+    storage: &S,
+    index_key: &str,
+    timestamp: u64,
+    prices: &HashMap<String, f32>,
+) -> Result<(), MutinyError> {
+    let mut record = get_activity_price(storage, index_key)?.unwrap_or(ActivityPrice {
+        timestamp,
+        prices: HashMap::new(),
+    });
+
+    let mut changed = false;
+    for (currency, price) in prices {
+        if !record.prices.contains_key(currency) {
+            record.prices.insert(currency.clone(), *price);
+            changed = true;
+        }
+    }
+
+    if changed {
+        storage.set_data(activity_price_key(index_key), record, None)?;
+    }
+
+    Ok(())
+}
+
+/// Returns if the activity is inbound and its amount in sats, if it moved any funds
+pub(crate) fn activity_amount(activity: &ActivityItem) -> Option<(bool, u64)> {
+    match activity {
+        ActivityItem::OnChain(t) => Some(onchain_amount(t)),
+        ActivityItem::Lightning(ln) => ln.amount_sats.map(|a| (ln.inbound, a)),
+        ActivityItem::ChannelClosed(_) => None,
+        ActivityItem::Federation(f) => match f {
+            FederationActivity::Lightning { invoice, .. } => {
+                invoice.amount_sats.map(|a| (invoice.inbound, a))
+            }
+            FederationActivity::EcashReissue { amount_sats, .. } => Some((true, *amount_sats)),
+            FederationActivity::PegIn { transaction, .. }
+            | FederationActivity::PegOut { transaction, .. } => Some(onchain_amount(transaction)),
+        },
+    }
+}
+
+fn onchain_amount(t: &TransactionDetails) -> (bool, u64) {
+    if t.received > t.sent {
+        (true, t.received - t.sent)
+    } else {
+        (false, t.sent - t.received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::MutinyInvoice;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_record_activity_price() {
+        let storage = MemoryStorage::default();
+        let key = "payment_inbound/abc";
+        assert_eq!(get_activity_price(&storage, key).unwrap(), None);
+
+        let prices = HashMap::from([("usd".to_string(), 50_000.0)]);
+        record_activity_price(&storage, key, 100, &prices).unwrap();
+
+        // new currencies are added, recorded ones are kept
+        let prices = HashMap::from([("usd".to_string(), 60_000.0), ("eur".to_string(), 45_000.0)]);
+        record_activity_price(&storage, key, 200, &prices).unwrap();
+
+        let record = get_activity_price(&storage, key).unwrap().unwrap();
+        assert_eq!(record.timestamp, 100);
+        assert_eq!(record.prices.get("usd"), Some(&50_000.0));
+        assert_eq!(record.prices.get("eur"), Some(&45_000.0));
+    }
+
+    #[test]
+    fn test_fiat_value() {
+        let invoice = MutinyInvoice {
+            amount_sats: Some(100_000),
+            inbound: false,
+            ..Default::default()
+        };
+        let activity = ActivityItem::Lightning(Box::new(invoice));
+
+        let item = FiatActivityItem::new(activity.clone(), "usd".to_string(), Some(50_000.0));
+        assert_eq!(item.fiat_value, Some(-50.0));
+
+        let item = FiatActivityItem::new(activity, "usd".to_string(), None);
+        assert_eq!(item.fiat_value, None);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::error::MutinyError;
+use crate::utils::{sleep, Mutex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How often [Readiness::ready] checks if the subsystems are done starting
+const READY_POLL_MS: i32 = 100;
+
+/// A part of the wallet that is started in the background with fast start,
+/// see [crate::MutinyWalletConfigBuilder::with_fast_start]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// The lightning nodes
+    Nodes,
+    /// The fedimint clients
+    Federations,
+    /// Relay connections, NWC and hermes
+    Nostr,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 3] = [Subsystem::Nodes, Subsystem::Federations, Subsystem::Nostr];
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SubsystemStatus {
+    Starting,
+    Ready,
+    /// The subsystem could not start, the rest of the wallet keeps working
+    Failed {
+        error: String,
+    },
+}
+
+/// A subsystem finished starting, see [Readiness::take_events]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReadinessEvent {
+    pub subsystem: Subsystem,
+    pub status: SubsystemStatus,
+}
+
+/// Tracks which subsystems are still starting in the background
+pub struct Readiness {
+    statuses: Mutex<HashMap<Subsystem, SubsystemStatus>>,
+    events: Mutex<Vec<ReadinessEvent>>,
+}
+
+impl Readiness {
+    /// Without fast start everything is ready once the wallet is built
+    pub(crate) fn new(fast_start: bool) -> Self {
+        let status = if fast_start {
+            SubsystemStatus::Starting
+        } else {
+            SubsystemStatus::Ready
+        };
+        let statuses = Subsystem::ALL
+            .into_iter()
+            .map(|s| (s, status.clone()))
+            .collect();
+
+        Self {
+            statuses: Mutex::new(statuses),
+            events: Mutex::new(vec![]),
+        }
+    }
+
+    pub(crate) fn set_status(&self, subsystem: Subsystem, status: SubsystemStatus) {
+        if let Ok(mut statuses) = self.statuses.lock() {
+            statuses.insert(subsystem, status.clone());
+        }
+        if let Ok(mut events) = self.events.lock() {
+            events.push(ReadinessEvent { subsystem, status });
+        }
+    }
+
+    /// Marks the subsystem as ready, or failed if it returned an error
+    pub(crate) fn set_result<T>(&self, subsystem: Subsystem, res: Result<T, MutinyError>) {
+        let status = match res {
+            Ok(_) => SubsystemStatus::Ready,
+            Err(e) => SubsystemStatus::Failed {
+                error: e.to_string(),
+            },
+        };
+        self.set_status(subsystem, status);
+    }
+
+    pub fn status(&self, subsystem: Subsystem) -> SubsystemStatus {
+        self.statuses
+            .lock()
+            .ok()
+            .and_then(|s| s.get(&subsystem).cloned())
+            .unwrap_or(SubsystemStatus::Starting)
+    }
+
+    /// If every subsystem is done starting, even if some of them failed
+    pub fn is_settled(&self) -> bool {
+        Subsystem::ALL
+            .into_iter()
+            .all(|s| self.status(s) != SubsystemStatus::Starting)
+    }
+
+    /// Waits until every subsystem is done starting
+    pub async fn ready(&self) {
+        while !self.is_settled() {
+            sleep(READY_POLL_MS).await;
+        }
+    }
+
+    /// Returns the events since the last call
+    pub fn take_events(&self) -> Vec<ReadinessEvent> {
+        self.events
+            .lock()
+            .map(|mut events| std::mem::take(&mut *events))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    async fn test_readiness() {
+        let readiness = Readiness::new(false);
+        assert!(readiness.is_settled());
+        readiness.ready().await;
+        assert!(readiness.take_events().is_empty());
+
+        let readiness = Readiness::new(true);
+        assert!(!readiness.is_settled());
+        assert_eq!(
+            readiness.status(Subsystem::Nodes),
+            SubsystemStatus::Starting
+        );
+
+        readiness.set_status(Subsystem::Nodes, SubsystemStatus::Ready);
+        readiness.set_status(Subsystem::Nostr, SubsystemStatus::Ready);
+        let failed = SubsystemStatus::Failed {
+            error: "Failed to connect".to_string(),
+        };
+        readiness.set_status(Subsystem::Federations, failed.clone());
+        assert!(readiness.is_settled());
+        readiness.ready().await;
+
+        let events = readiness.take_events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[2],
+            ReadinessEvent {
+                subsystem: Subsystem::Federations,
+                status: failed
+            }
+        );
+        assert!(readiness.take_events().is_empty());
+    }
+}
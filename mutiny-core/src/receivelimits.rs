@@ -0,0 +1,180 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+
+const RECEIVE_LIMITS_KEY: &str = "receive_limits";
+const RECEIVE_LIMIT_STATS_KEY: &str = "receive_limit_stats";
+const HOUR_SECS: u64 = 60 * 60;
+
+/// Limits on the invoices others can have us create, through our NWC connections
+/// or lightning address, so they can't spam us with invoices. Every limit is optional.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReceiveLimits {
+    /// Maximum sats of a single invoice
+    pub max_invoice_sats: Option<u64>,
+    /// Maximum invoices created for requests in the last hour
+    pub max_invoices_per_hour: Option<u32>,
+    /// Npubs that can't have us create invoices, like the keys of NWC apps
+    #[serde(default)]
+    pub denied_npubs: Vec<nostr::PublicKey>,
+}
+
+/// How many invoice requests were allowed and rejected by the [ReceiveLimits]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReceiveLimitStats {
+    /// Invoices created for requests in the last hour
+    pub invoices_last_hour: u32,
+    /// Requests rejected for being over the maximum amount
+    pub rejected_amount: u64,
+    /// Requests rejected for being over the hourly limit
+    pub rejected_rate: u64,
+    /// Requests rejected for coming from a denied npub
+    pub rejected_denied: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+struct StoredStats {
+    /// Unix timestamps of the allowed requests of the last hour
+    allowed: Vec<u64>,
+    rejected_amount: u64,
+    rejected_rate: u64,
+    rejected_denied: u64,
+}
+
+pub(crate) fn get_receive_limits<S: MutinyStorage>(
+    storage: &S,
+) -> Result<ReceiveLimits, MutinyError> {
+    Ok(storage.get_data(RECEIVE_LIMITS_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn set_receive_limits<S: MutinyStorage>(
+    storage: &S,
+    limits: ReceiveLimits,
+) -> Result<(), MutinyError> {
+    storage.set_data(RECEIVE_LIMITS_KEY.to_string(), limits, None)
+}
+
+pub(crate) fn get_receive_limit_stats<S: MutinyStorage>(
+    storage: &S,
+) -> Result<ReceiveLimitStats, MutinyError> {
+    let stats = get_stored_stats(storage)?;
+    let cutoff = utils::now().as_secs().saturating_sub(HOUR_SECS);
+
+    Ok(ReceiveLimitStats {
+        invoices_last_hour: stats.allowed.iter().filter(|t| **t >= cutoff).count() as u32,
+        rejected_amount: stats.rejected_amount,
+        rejected_rate: stats.rejected_rate,
+        rejected_denied: stats.rejected_denied,
+    })
+}
+
+/// Checks a request for an invoice against the [ReceiveLimits], the requester is
+/// the npub that asked for it when we know it.
+///
+/// A rejected request counts towards the stats. An allowed one only counts towards the
+/// hourly limit once its invoice is created, see [record_invoice_created].
+pub(crate) fn check_invoice_request<S: MutinyStorage>(
+    storage: &S,
+    amount_sats: u64,
+    requester: Option<&nostr::PublicKey>,
+) -> Result<(), MutinyError> {
+    let limits = get_receive_limits(storage)?;
+    let mut stats = get_stored_stats(storage)?;
+    let now = utils::now().as_secs();
+    stats
+        .allowed
+        .retain(|t| *t >= now.saturating_sub(HOUR_SECS));
+
+    let res = if requester.is_some_and(|npub| limits.denied_npubs.contains(npub)) {
+        stats.rejected_denied += 1;
+        Err(MutinyError::RequesterDenied)
+    } else if limits.max_invoice_sats.is_some_and(|max| amount_sats > max) {
+        stats.rejected_amount += 1;
+        Err(MutinyError::ReceiveLimitExceeded)
+    } else if limits
+        .max_invoices_per_hour
+        .is_some_and(|max| stats.allowed.len() >= max as usize)
+    {
+        stats.rejected_rate += 1;
+        Err(MutinyError::ReceiveLimitExceeded)
+    } else {
+        return Ok(());
+    };
+
+    storage.set_data(RECEIVE_LIMIT_STATS_KEY.to_string(), stats, None)?;
+    res
+}
+
+/// Counts an invoice created for an allowed request towards the hourly limit
+pub(crate) fn record_invoice_created<S: MutinyStorage>(storage: &S) -> Result<(), MutinyError> {
+    let mut stats = get_stored_stats(storage)?;
+    let now = utils::now().as_secs();
+    stats
+        .allowed
+        .retain(|t| *t >= now.saturating_sub(HOUR_SECS));
+    stats.allowed.push(now);
+
+    storage.set_data(RECEIVE_LIMIT_STATS_KEY.to_string(), stats, None)
+}
+
+fn get_stored_stats<S: MutinyStorage>(storage: &S) -> Result<StoredStats, MutinyError> {
+    Ok(storage
+        .get_data(RECEIVE_LIMIT_STATS_KEY)?
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use nostr::Keys;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_check_invoice_request() {
+        let storage = MemoryStorage::default();
+        let npub = Keys::generate().public_key();
+        let denied = Keys::generate().public_key();
+
+        // no limits by default
+        check_invoice_request(&storage, 1_000_000, Some(&npub)).unwrap();
+
+        let limits = ReceiveLimits {
+            max_invoice_sats: Some(10_000),
+            max_invoices_per_hour: Some(2),
+            denied_npubs: vec![denied],
+        };
+        set_receive_limits(&storage, limits.clone()).unwrap();
+        assert_eq!(get_receive_limits(&storage).unwrap(), limits);
+
+        assert_eq!(
+            check_invoice_request(&storage, 1_000, Some(&denied)),
+            Err(MutinyError::RequesterDenied)
+        );
+        assert_eq!(
+            check_invoice_request(&storage, 10_001, None),
+            Err(MutinyError::ReceiveLimitExceeded)
+        );
+        // allowed requests only count once their invoice is created
+        check_invoice_request(&storage, 10_000, None).unwrap();
+        check_invoice_request(&storage, 10_000, None).unwrap();
+        record_invoice_created(&storage).unwrap();
+        record_invoice_created(&storage).unwrap();
+        assert_eq!(
+            check_invoice_request(&storage, 1_000, Some(&npub)),
+            Err(MutinyError::ReceiveLimitExceeded)
+        );
+
+        let stats = get_receive_limit_stats(&storage).unwrap();
+        let expected = ReceiveLimitStats {
+            invoices_last_hour: 2,
+            rejected_amount: 1,
+            rejected_rate: 1,
+            rejected_denied: 1,
+        };
+        assert_eq!(stats, expected);
+    }
+}
@@ -0,0 +1,71 @@
+use bitcoin::{Address, Amount, Network, Txid};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// Just enough of the bitcoind JSON-RPC to fund wallets and mine blocks
+pub(crate) struct Bitcoind {
+    client: reqwest::Client,
+    url: String,
+    user: String,
+    password: String,
+}
+
+impl Bitcoind {
+    pub fn new(url: String, user: String, password: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            user,
+            password,
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Value {
+        let body = json!({ "jsonrpc": "1.0", "id": "mutiny", "method": method, "params": params });
+        let res: Value = self
+            .client
+            .post(&self.url)
+            .basic_auth(&self.user, Some(&self.password))
+            .json(&body)
+            .send()
+            .await
+            .unwrap_or_else(|e| panic!("bitcoind {method} failed: {e}"))
+            .json()
+            .await
+            .unwrap_or_else(|e| panic!("bitcoind {method} returned invalid json: {e}"));
+
+        if !res["error"].is_null() {
+            panic!("bitcoind {method} returned an error: {}", res["error"]);
+        }
+        res["result"].clone()
+    }
+
+    pub async fn get_new_address(&self) -> Address {
+        let res = self.call("getnewaddress", json!([])).await;
+        parse_address(res.as_str().expect("address should be a string"))
+    }
+
+    pub async fn block_count(&self) -> u64 {
+        let res = self.call("getblockcount", json!([])).await;
+        res.as_u64().expect("block count should be a number")
+    }
+
+    pub async fn mine(&self, blocks: u64) {
+        let address = self.get_new_address().await;
+        self.call("generatetoaddress", json!([blocks, address.to_string()]))
+            .await;
+    }
+
+    pub async fn send_to_address(&self, address: &Address, amount: Amount) -> Txid {
+        let params = json!([address.to_string(), amount.to_btc()]);
+        let res = self.call("sendtoaddress", params).await;
+        Txid::from_str(res.as_str().expect("txid should be a string")).expect("invalid txid")
+    }
+}
+
+pub(crate) fn parse_address(address: &str) -> Address {
+    Address::from_str(address)
+        .expect("invalid address")
+        .require_network(Network::Regtest)
+        .expect("address should be for regtest")
+}
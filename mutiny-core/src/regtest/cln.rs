@@ -0,0 +1,79 @@
+use super::bitcoind::parse_address;
+use super::Counterparty;
+use async_trait::async_trait;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Address;
+use lightning_invoice::Bolt11Invoice;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// A Core Lightning node reached over the `clnrest` plugin
+pub(crate) struct Cln {
+    client: reqwest::Client,
+    rest_url: String,
+    rune: String,
+    p2p_addr: String,
+}
+
+impl Cln {
+    pub fn new(rest_url: String, rune: String, p2p_addr: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            rest_url,
+            rune,
+            p2p_addr,
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Value {
+        self.client
+            .post(format!("{}/v1/{method}", self.rest_url))
+            .header("Rune", &self.rune)
+            .json(&params)
+            .send()
+            .await
+            .unwrap_or_else(|e| panic!("cln {method} failed: {e}"))
+            .json()
+            .await
+            .unwrap_or_else(|e| panic!("cln {method} returned invalid json: {e}"))
+    }
+}
+
+#[async_trait]
+impl Counterparty for Cln {
+    fn name(&self) -> &'static str {
+        "cln"
+    }
+
+    fn p2p_addr(&self) -> &str {
+        &self.p2p_addr
+    }
+
+    async fn pubkey(&self) -> PublicKey {
+        let info = self.call("getinfo", json!({})).await;
+        PublicKey::from_str(info["id"].as_str().expect("missing pubkey")).expect("invalid pubkey")
+    }
+
+    async fn new_address(&self) -> Address {
+        let res = self.call("newaddr", json!({})).await;
+        parse_address(res["bech32"].as_str().expect("missing address"))
+    }
+
+    async fn create_invoice(&self, amount_sats: u64) -> Bolt11Invoice {
+        let params = json!({
+            "amount_msat": amount_sats * 1_000,
+            "label": uuid::Uuid::new_v4().to_string(),
+            "description": "mutiny regtest",
+        });
+        let res = self.call("invoice", params).await;
+        Bolt11Invoice::from_str(res["bolt11"].as_str().expect("missing invoice"))
+            .expect("invalid invoice")
+    }
+
+    async fn pay_invoice(&self, invoice: &Bolt11Invoice) {
+        let res = self
+            .call("pay", json!({ "bolt11": invoice.to_string() }))
+            .await;
+        assert_eq!(res["status"], "complete", "cln failed to pay: {res}");
+    }
+}
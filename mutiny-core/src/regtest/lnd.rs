@@ -0,0 +1,84 @@
+use super::bitcoind::parse_address;
+use super::Counterparty;
+use async_trait::async_trait;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Address;
+use lightning_invoice::Bolt11Invoice;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// An LND node reached over its REST API, it needs to run with `--no-rest-tls`
+pub(crate) struct Lnd {
+    client: reqwest::Client,
+    rest_url: String,
+    /// Hex encoded admin macaroon
+    macaroon: String,
+    p2p_addr: String,
+}
+
+impl Lnd {
+    pub fn new(rest_url: String, macaroon: String, p2p_addr: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            rest_url,
+            macaroon,
+            p2p_addr,
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Value {
+        let mut request = self
+            .client
+            .request(method, format!("{}{path}", self.rest_url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        request
+            .send()
+            .await
+            .unwrap_or_else(|e| panic!("lnd {path} failed: {e}"))
+            .json()
+            .await
+            .unwrap_or_else(|e| panic!("lnd {path} returned invalid json: {e}"))
+    }
+}
+
+#[async_trait]
+impl Counterparty for Lnd {
+    fn name(&self) -> &'static str {
+        "lnd"
+    }
+
+    fn p2p_addr(&self) -> &str {
+        &self.p2p_addr
+    }
+
+    async fn pubkey(&self) -> PublicKey {
+        let info = self.request(Method::GET, "/v1/getinfo", None).await;
+        let pubkey = info["identity_pubkey"].as_str().expect("missing pubkey");
+        PublicKey::from_str(pubkey).expect("invalid pubkey")
+    }
+
+    async fn new_address(&self) -> Address {
+        let res = self.request(Method::GET, "/v1/newaddress", None).await;
+        parse_address(res["address"].as_str().expect("missing address"))
+    }
+
+    async fn create_invoice(&self, amount_sats: u64) -> Bolt11Invoice {
+        let body = json!({ "value": amount_sats.to_string() });
+        let res = self.request(Method::POST, "/v1/invoices", Some(body)).await;
+        let invoice = res["payment_request"].as_str().expect("missing invoice");
+        Bolt11Invoice::from_str(invoice).expect("invalid invoice")
+    }
+
+    async fn pay_invoice(&self, invoice: &Bolt11Invoice) {
+        let body = json!({ "payment_request": invoice.to_string() });
+        let path = "/v1/channels/transactions";
+        let res = self.request(Method::POST, path, Some(body)).await;
+        let error = res["payment_error"].as_str().unwrap_or_default();
+        assert!(error.is_empty(), "lnd failed to pay: {error}");
+    }
+}
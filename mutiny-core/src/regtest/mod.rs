@@ -0,0 +1,306 @@
+//! End-to-end tests against a regtest bitcoind, esplora and lightning counterparties.
+//!
+//! These only run with the `regtest_tests` feature and need the services running,
+//! they are configured with environment variables:
+//! - `REGTEST_ESPLORA_URL`, defaults to `http://localhost:3002`
+//! - `REGTEST_BITCOIND_URL`, `REGTEST_BITCOIND_USER` and `REGTEST_BITCOIND_PASSWORD`,
+//!   default to `http://localhost:18443` with `admin1` / `123`
+//! - `REGTEST_LND_REST_URL`, `REGTEST_LND_MACAROON` (hex) and `REGTEST_LND_P2P_ADDR`
+//! - `REGTEST_CLN_REST_URL`, `REGTEST_CLN_RUNE` and `REGTEST_CLN_P2P_ADDR`
+//!
+//! Every flow runs against each configured counterparty.
+
+mod bitcoind;
+mod cln;
+mod lnd;
+
+use crate::nodemanager::MutinyChannel;
+use crate::storage::MemoryStorage;
+use crate::utils::sleep;
+use crate::{generate_seed, MutinyWallet, MutinyWalletBuilder, MutinyWalletConfigBuilder};
+use async_trait::async_trait;
+use bitcoin::bip32::ExtendedPrivKey;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Address, Amount, Network};
+use bitcoind::Bitcoind;
+use cln::Cln;
+use lightning_invoice::Bolt11Invoice;
+use lnd::Lnd;
+use std::future::Future;
+
+/// How long to wait for the wallet to see a change before failing the test
+const WAIT_TIMEOUT_SECS: u64 = 120;
+
+/// A lightning node we open channels to and make payments with
+#[async_trait]
+pub(crate) trait Counterparty: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Where other nodes can reach it, `host:port`
+    fn p2p_addr(&self) -> &str;
+
+    async fn pubkey(&self) -> PublicKey;
+
+    async fn new_address(&self) -> Address;
+
+    async fn create_invoice(&self, amount_sats: u64) -> Bolt11Invoice;
+
+    /// Pays the invoice, panics if the payment fails
+    async fn pay_invoice(&self, invoice: &Bolt11Invoice);
+
+    async fn connection_string(&self) -> String {
+        format!("{}@{}", self.pubkey().await, self.p2p_addr())
+    }
+}
+
+pub(crate) struct RegtestHarness {
+    pub bitcoind: Bitcoind,
+    pub esplora_url: String,
+    pub counterparties: Vec<Box<dyn Counterparty>>,
+    client: reqwest::Client,
+}
+
+impl RegtestHarness {
+    pub fn from_env() -> Self {
+        let bitcoind = Bitcoind::new(
+            env_or("REGTEST_BITCOIND_URL", "http://localhost:18443"),
+            env_or("REGTEST_BITCOIND_USER", "admin1"),
+            env_or("REGTEST_BITCOIND_PASSWORD", "123"),
+        );
+
+        let mut counterparties: Vec<Box<dyn Counterparty>> = vec![];
+        if let Ok(url) = std::env::var("REGTEST_LND_REST_URL") {
+            counterparties.push(Box::new(Lnd::new(
+                url,
+                env_required("REGTEST_LND_MACAROON"),
+                env_required("REGTEST_LND_P2P_ADDR"),
+            )));
+        }
+        if let Ok(url) = std::env::var("REGTEST_CLN_REST_URL") {
+            counterparties.push(Box::new(Cln::new(
+                url,
+                env_required("REGTEST_CLN_RUNE"),
+                env_required("REGTEST_CLN_P2P_ADDR"),
+            )));
+        }
+        assert!(
+            !counterparties.is_empty(),
+            "No counterparty configured, set REGTEST_LND_REST_URL or REGTEST_CLN_REST_URL"
+        );
+
+        Self {
+            bitcoind,
+            esplora_url: env_or("REGTEST_ESPLORA_URL", "http://localhost:3002"),
+            counterparties,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Creates a new wallet with fresh storage that uses the regtest esplora
+    pub async fn new_wallet(&self) -> MutinyWallet<MemoryStorage> {
+        let mnemonic = generate_seed(12).unwrap();
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
+
+        let mut config = MutinyWalletConfigBuilder::new(xpriv).with_network(network);
+        config.with_user_esplora_url(self.esplora_url.clone());
+        config.with_gossip_disabled();
+
+        MutinyWalletBuilder::new(xpriv, MemoryStorage::default())
+            .with_config(config.build())
+            .build()
+            .await
+            .expect("mutiny wallet should initialize")
+    }
+
+    /// Mines blocks and waits for esplora to index them
+    pub async fn mine(&self, blocks: u64) {
+        self.bitcoind.mine(blocks).await;
+        let height = self.bitcoind.block_count().await;
+        wait_for("esplora to index the blocks", || async move {
+            self.esplora_height().await >= height
+        })
+        .await;
+    }
+
+    async fn esplora_height(&self) -> u64 {
+        let url = format!("{}/blocks/tip/height", self.esplora_url);
+        let res = self.client.get(url).send().await;
+        match res {
+            Ok(res) => res
+                .text()
+                .await
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            Err(_) => 0,
+        }
+    }
+
+    /// Sends on-chain funds to the wallet and waits until they are confirmed
+    pub async fn fund(&self, wallet: &MutinyWallet<MemoryStorage>, amount_sats: u64) {
+        let address = wallet.node_manager.get_new_address(vec![]).unwrap();
+        let amount = Amount::from_sat(amount_sats);
+        self.bitcoind.send_to_address(&address, amount).await;
+        self.mine(1).await;
+
+        wait_for("the wallet to see the funds", || async move {
+            let balance = wallet.get_balance().await.unwrap();
+            balance.confirmed >= amount_sats
+        })
+        .await;
+    }
+
+    /// Opens a channel to the counterparty and waits until it can be used
+    pub async fn open_channel(
+        &self,
+        wallet: &MutinyWallet<MemoryStorage>,
+        counterparty: &dyn Counterparty,
+        amount_sats: u64,
+    ) -> MutinyChannel {
+        let connection_string = counterparty.connection_string().await;
+        wallet
+            .node_manager
+            .connect_to_peer(None, &connection_string, None)
+            .await
+            .expect("should connect to the counterparty");
+
+        let pubkey = counterparty.pubkey().await;
+        let channel = wallet
+            .node_manager
+            .open_channel(None, Some(pubkey), amount_sats, None, None, None)
+            .await
+            .expect("should open a channel");
+        self.mine(6).await;
+
+        let id = channel.user_chan_id.as_str();
+        wait_for("the channel to be usable", || async move {
+            usable_channel(wallet, id).await.is_some()
+        })
+        .await;
+        usable_channel(wallet, id).await.unwrap()
+    }
+}
+
+async fn usable_channel(wallet: &MutinyWallet<MemoryStorage>, id: &str) -> Option<MutinyChannel> {
+    let channels = wallet.node_manager.list_channels().await.unwrap();
+    channels
+        .into_iter()
+        .find(|c| c.user_chan_id == id && c.is_usable)
+}
+
+/// Polls until the condition is true, panics after [WAIT_TIMEOUT_SECS]
+pub(crate) async fn wait_for<F, Fut>(description: &str, mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let start = crate::utils::now().as_secs();
+    while !condition().await {
+        if crate::utils::now().as_secs() - start > WAIT_TIMEOUT_SECS {
+            panic!("Timed out waiting for {description}");
+        }
+        sleep(500).await;
+    }
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn env_required(name: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| panic!("{name} must be set"))
+}
+
+mod tests {
+    use super::*;
+    use crate::event::HTLCStatus;
+
+    const CHANNEL_SIZE_SATS: u64 = 100_000;
+
+    #[tokio::test]
+    async fn test_open_pay_and_receive() {
+        let harness = RegtestHarness::from_env();
+        for counterparty in harness.counterparties.iter() {
+            let name = counterparty.name();
+            let wallet = &harness.new_wallet().await;
+            harness.fund(wallet, 200_000).await;
+            let channel = harness
+                .open_channel(wallet, counterparty.as_ref(), CHANNEL_SIZE_SATS)
+                .await;
+            assert_eq!(channel.size, CHANNEL_SIZE_SATS, "{name}");
+
+            // pay the counterparty, this also gives us inbound liquidity
+            let invoice = counterparty.create_invoice(10_000).await;
+            let paid = wallet.pay_invoice(&invoice, None, vec![]).await.unwrap();
+            assert_eq!(paid.status, HTLCStatus::Succeeded, "{name}");
+            assert!(!paid.inbound);
+
+            // receive some of it back
+            let (invoice, _) = wallet
+                .node_manager
+                .create_invoice(5_000, vec![])
+                .await
+                .unwrap();
+            let bolt11 = invoice.bolt11.expect("should have a bolt11 invoice");
+            counterparty.pay_invoice(&bolt11).await;
+            let hash = *bolt11.payment_hash();
+            wait_for("the payment to be received", || async move {
+                let received = wallet.get_invoice_by_hash(&hash).await;
+                received.is_ok_and(|i| i.status == HTLCStatus::Succeeded)
+            })
+            .await;
+
+            wallet.stop().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_force_close_and_sweep() {
+        let harness = RegtestHarness::from_env();
+        for counterparty in harness.counterparties.iter() {
+            let wallet = &harness.new_wallet().await;
+            harness.fund(wallet, 200_000).await;
+            let channel = harness
+                .open_channel(wallet, counterparty.as_ref(), CHANNEL_SIZE_SATS)
+                .await;
+
+            let outpoint = channel.outpoint.expect("usable channel has an outpoint");
+            wallet
+                .node_manager
+                .close_channel(&outpoint, None, true, false)
+                .await
+                .unwrap();
+            harness.mine(1).await;
+            wait_for("the force close balance", || async move {
+                wallet.get_balance().await.unwrap().force_close > 0
+            })
+            .await;
+
+            // wait out the to_self_delay so the funds get swept back to the wallet
+            harness.mine(200).await;
+            wait_for("the force close funds to be swept", || async move {
+                let balance = wallet.get_balance().await.unwrap();
+                balance.force_close == 0 && balance.lightning == 0
+            })
+            .await;
+            harness.mine(1).await;
+            wait_for("the swept funds to confirm", || async move {
+                wallet.get_balance().await.unwrap().confirmed > 100_000
+            })
+            .await;
+
+            // sweep everything out of the wallet
+            let address = counterparty.new_address().await;
+            wallet.sweep_wallet(address, vec![], None).await.unwrap();
+            harness.mine(1).await;
+            wait_for("the wallet to be empty", || async move {
+                let balance = wallet.get_balance().await.unwrap();
+                balance.confirmed == 0 && balance.unconfirmed == 0
+            })
+            .await;
+
+            wallet.stop().await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use crate::encrypt::{decrypt_with_key, encrypt_with_key};
+use crate::error::MutinyError;
+use crate::gossip::save_peer_connection_info;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::logging::MutinyLogger;
+use crate::nodemanager::{NodeIndex, NodeStorage};
+use crate::storage::MutinyStorage;
+use bitcoin::bip32::ExtendedPrivKey;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, ScriptBuf, Transaction, WPubkeyHash};
+use hex_conservative::FromHex;
+use lightning::ln::chan_utils::{
+    get_to_countersignatory_with_anchors_redeemscript, ChannelPublicKeys,
+    ChannelTransactionParameters, CounterpartyChannelTransactionParameters,
+};
+use lightning::ln::features::ChannelTypeFeatures;
+use lightning::log_info;
+use lightning::routing::gossip::NodeId;
+use lightning::sign::{SpendableOutputDescriptor, StaticPaymentOutputDescriptor};
+use lightning::util::logger::Logger;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The channels of a restored backup that we don't have the monitor of,
+/// until their funds are swept back, see [ChannelBackup]
+pub(crate) const RECOVERING_CHANNELS_KEY: &str = "recovering_channels";
+
+/// A last resort backup of our channels, independent of our lightning state and VSS.
+///
+/// It can't restore the channels themselves, but it has what we need to have our peers
+/// force close them and to claim our funds back on-chain, see
+/// [crate::MutinyWallet::recover_from_scb].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticChannelBackup {
+    /// Our nodes, keyed by their uuid
+    pub nodes: HashMap<String, NodeIndex>,
+    pub channels: Vec<ChannelBackup>,
+    /// Unix timestamp of when the backup was created
+    pub created_at: u64,
+}
+
+/// Backup of one of our channels, see [StaticChannelBackup].
+///
+/// It only has what doesn't change over the life of the channel. A channel monitor goes
+/// stale with the next commitment update, and loading an old one could broadcast a revoked
+/// state and lose the whole channel to a penalty. Instead our peer force closes the channel
+/// when we reconnect without knowing about it, and we sweep our balance from its commitment
+/// transaction with the channel keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelBackup {
+    /// Uuid of our node the channel belongs to
+    pub node_id: String,
+    pub counterparty: PublicKey,
+    /// Where to reach the counterparty so it can force close the channel
+    pub connection_string: Option<String>,
+    pub funding_outpoint: OutPoint,
+    pub channel_value_sats: u64,
+    /// Hex encoded id our keys for the channel are derived from
+    pub channel_keys_id: String,
+}
+
+impl ChannelBackup {
+    pub(crate) fn channel_keys_id(&self) -> Result<[u8; 32], MutinyError> {
+        FromHex::from_hex(&self.channel_keys_id).map_err(|_| MutinyError::InvalidArgumentsError)
+    }
+
+    /// Finds our balance in the commitment transaction our peer broadcast to close the
+    /// channel, given our keys for the channel. It pays to our payment key, behind a one
+    /// block CSV for anchor channels.
+    pub(crate) fn to_remote_output(
+        &self,
+        keys: &ChannelPublicKeys,
+        commitment_tx: &Transaction,
+    ) -> Result<Option<SpendableOutputDescriptor>, MutinyError> {
+        let channel_keys_id = self.channel_keys_id()?;
+        let payment_point = keys.payment_point;
+        let static_remote_key =
+            ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()));
+        let anchors =
+            get_to_countersignatory_with_anchors_redeemscript(&payment_point).to_v0_p2wsh();
+
+        let found = commitment_tx
+            .output
+            .iter()
+            .enumerate()
+            .find_map(|(index, output)| {
+                if output.script_pubkey == static_remote_key {
+                    Some((index, output, false))
+                } else if output.script_pubkey == anchors {
+                    Some((index, output, true))
+                } else {
+                    None
+                }
+            });
+        let Some((index, output, anchors)) = found else {
+            return Ok(None);
+        };
+
+        let funding_outpoint = lightning::chain::transaction::OutPoint {
+            txid: self.funding_outpoint.txid,
+            index: self.funding_outpoint.vout as u16,
+        };
+        // only the channel type and our payment key are used to sweep our balance,
+        // the other parameters are lost with the channel state
+        let channel_transaction_parameters = anchors.then(|| ChannelTransactionParameters {
+            holder_pubkeys: keys.clone(),
+            holder_selected_contest_delay: 0,
+            is_outbound_from_holder: false,
+            counterparty_parameters: Some(CounterpartyChannelTransactionParameters {
+                pubkeys: keys.clone(),
+                selected_contest_delay: 0,
+            }),
+            funding_outpoint: Some(funding_outpoint),
+            channel_type_features: ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies(),
+        });
+
+        let descriptor = StaticPaymentOutputDescriptor {
+            outpoint: lightning::chain::transaction::OutPoint {
+                txid: commitment_tx.txid(),
+                index: index as u16,
+            },
+            output: output.clone(),
+            channel_keys_id,
+            channel_value_satoshis: self.channel_value_sats,
+            channel_transaction_parameters,
+        };
+        Ok(Some(SpendableOutputDescriptor::StaticPaymentOutput(
+            descriptor,
+        )))
+    }
+}
+
+impl StaticChannelBackup {
+    /// Encrypts the backup with a key derived from our seed, so only our wallet can read it
+    pub fn encrypt(&self, xprivkey: ExtendedPrivKey) -> Result<String, MutinyError> {
+        let key = backup_encryption_key(xprivkey)?;
+        let bytes = serde_json::to_vec(self)?;
+        Ok(base64::encode(encrypt_with_key(&key, &bytes)))
+    }
+
+    pub fn decrypt(encrypted: &str, xprivkey: ExtendedPrivKey) -> Result<Self, MutinyError> {
+        let key = backup_encryption_key(xprivkey)?;
+        let bytes = base64::decode(encrypted.trim())?;
+        let decrypted = decrypt_with_key(&key, bytes)?;
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+pub(crate) fn backup_encryption_key(xprivkey: ExtendedPrivKey) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    let key = create_root_child_key(&context, xprivkey, ChildKey::Backup)?;
+    Ok(key.private_key)
+}
+
+/// The channels we are recovering the funds of, see [ChannelBackup]
+pub(crate) fn get_recovering_channels<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<ChannelBackup>, MutinyError> {
+    Ok(storage
+        .get_data(RECOVERING_CHANNELS_KEY)?
+        .unwrap_or_default())
+}
+
+/// Adds the channels to the ones we are recovering, returns how many weren't there yet
+pub(crate) fn add_recovering_channels<S: MutinyStorage>(
+    storage: &S,
+    channels: Vec<ChannelBackup>,
+) -> Result<usize, MutinyError> {
+    let mut recovering = get_recovering_channels(storage)?;
+    let before = recovering.len();
+    for channel in channels {
+        if !recovering
+            .iter()
+            .any(|c| c.funding_outpoint == channel.funding_outpoint)
+        {
+            recovering.push(channel);
+        }
+    }
+
+    let added = recovering.len() - before;
+    if added > 0 {
+        storage.set_data(RECOVERING_CHANNELS_KEY.to_string(), recovering, None)?;
+    }
+    Ok(added)
+}
+
+/// Stops recovering the channel, once its funds are swept or we have its monitor again
+pub(crate) fn remove_recovering_channel<S: MutinyStorage>(
+    storage: &S,
+    funding_outpoint: &OutPoint,
+) -> Result<(), MutinyError> {
+    let mut recovering = get_recovering_channels(storage)?;
+    recovering.retain(|c| c.funding_outpoint != *funding_outpoint);
+    storage.set_data(RECOVERING_CHANNELS_KEY.to_string(), recovering, None)
+}
+
+/// Writes the nodes, peers and channels of a backup into a fresh storage.
+///
+/// When the nodes start, they reconnect to the peers, which force close the channels
+/// they have with us since we don't know about them. Our balances are then swept from
+/// their commitment transactions, see [crate::node::Node::recover_channels].
+pub(crate) async fn restore_static_channel_backup<S: MutinyStorage>(
+    storage: &S,
+    backup: StaticChannelBackup,
+    logger: Arc<MutinyLogger>,
+) -> Result<(), MutinyError> {
+    // restoring on top of existing channels could broadcast a revoked state
+    if !storage.get_nodes()?.nodes.is_empty() {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    for channel in backup.channels.iter() {
+        // fail before writing anything
+        channel.channel_keys_id()?;
+    }
+
+    for channel in backup.channels.iter() {
+        if let Some(connection_string) = channel.connection_string.as_ref() {
+            save_peer_connection_info(
+                storage,
+                &channel.node_id,
+                &NodeId::from_pubkey(&channel.counterparty),
+                connection_string,
+                None,
+            )?;
+        }
+    }
+    let recovering = add_recovering_channels(storage, backup.channels)?;
+    log_info!(logger, "Recovering {recovering} channels from backup");
+
+    let nodes = NodeStorage {
+        nodes: backup.nodes,
+        ..Default::default()
+    };
+    storage.insert_nodes(&nodes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::read_peer_info;
+    use crate::ldkstorage::MONITORS_PREFIX_KEY;
+    use crate::storage::MemoryStorage;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::{Network, TxIn, TxOut, Txid};
+    use hex_conservative::DisplayHex;
+    use lightning::sign::{ChannelSigner, KeysManager, SignerProvider};
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn backup() -> StaticChannelBackup {
+        let node = NodeIndex {
+            child_index: 0,
+            lsp: None,
+            archived: Some(false),
+        };
+        let channel = ChannelBackup {
+            node_id: "node".to_string(),
+            counterparty: PublicKey::from_str(
+                "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+            )
+            .unwrap(),
+            connection_string: Some("example.com:9735".to_string()),
+            funding_outpoint: OutPoint::new(Txid::all_zeros(), 1),
+            channel_value_sats: 100_000,
+            channel_keys_id: [7; 32].to_lower_hex_string(),
+        };
+
+        StaticChannelBackup {
+            nodes: HashMap::from([("node".to_string(), node)]),
+            channels: vec![channel],
+            created_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_encrypt_static_channel_backup() {
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &[0; 32]).unwrap();
+        let backup = backup();
+
+        let encrypted = backup.encrypt(xpriv).unwrap();
+        let decrypted = StaticChannelBackup::decrypt(&encrypted, xpriv).unwrap();
+        assert_eq!(decrypted, backup);
+
+        let other = ExtendedPrivKey::new_master(Network::Regtest, &[1; 32]).unwrap();
+        assert!(StaticChannelBackup::decrypt(&encrypted, other).is_err());
+    }
+
+    #[test]
+    async fn test_restore_static_channel_backup() {
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let backup = backup();
+
+        restore_static_channel_backup(&storage, backup.clone(), logger.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(storage.get_nodes().unwrap().nodes, backup.nodes);
+        assert_eq!(get_recovering_channels(&storage).unwrap(), backup.channels);
+
+        // no monitor is restored, they would be stale
+        assert!(storage
+            .scan_keys(MONITORS_PREFIX_KEY, None)
+            .unwrap()
+            .is_empty());
+
+        let channel = &backup.channels[0];
+
+        let peer = read_peer_info(&storage, &NodeId::from_pubkey(&channel.counterparty))
+            .unwrap()
+            .unwrap();
+        assert_eq!(peer.connection_string, channel.connection_string);
+        assert_eq!(peer.nodes, vec!["node".to_string()]);
+
+        // can't restore over existing nodes
+        assert!(restore_static_channel_backup(&storage, backup, logger)
+            .await
+            .is_err());
+
+        // the same channel is only recovered once
+        let channels = get_recovering_channels(&storage).unwrap();
+        assert_eq!(add_recovering_channels(&storage, channels).unwrap(), 0);
+        remove_recovering_channel(&storage, &channel.funding_outpoint).unwrap();
+        assert!(get_recovering_channels(&storage).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_channel_backup_to_remote_output() {
+        let channel = backup().channels[0].clone();
+        let keys_manager = KeysManager::new(&[0; 32], 0, 0);
+        let keys_id = channel.channel_keys_id().unwrap();
+        let signer = keys_manager.derive_channel_signer(channel.channel_value_sats, keys_id);
+        let keys = signer.pubkeys();
+
+        let payment_point = keys.payment_point;
+        let static_remote_key =
+            ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()));
+        let anchors =
+            get_to_countersignatory_with_anchors_redeemscript(&payment_point).to_v0_p2wsh();
+        let commitment_tx = |script_pubkey: ScriptBuf| Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: channel.funding_outpoint,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+                },
+                TxOut {
+                    value: 40_000,
+                    script_pubkey,
+                },
+            ],
+        };
+
+        let tx = commitment_tx(static_remote_key);
+        let Some(SpendableOutputDescriptor::StaticPaymentOutput(descriptor)) =
+            channel.to_remote_output(keys, &tx).unwrap()
+        else {
+            panic!("should find our balance");
+        };
+        assert_eq!(descriptor.outpoint.txid, tx.txid());
+        assert_eq!(descriptor.outpoint.index, 1);
+        assert_eq!(descriptor.output.value, 40_000);
+        assert_eq!(descriptor.channel_keys_id, keys_id);
+        assert!(descriptor.channel_transaction_parameters.is_none());
+
+        let tx = commitment_tx(anchors);
+        let Some(SpendableOutputDescriptor::StaticPaymentOutput(descriptor)) =
+            channel.to_remote_output(keys, &tx).unwrap()
+        else {
+            panic!("should find our balance");
+        };
+        assert!(descriptor
+            .channel_transaction_parameters
+            .unwrap()
+            .channel_type_features
+            .supports_anchors_zero_fee_htlc_tx());
+
+        // nothing for us when our balance was below dust
+        let tx = commitment_tx(ScriptBuf::new());
+        assert!(channel.to_remote_output(keys, &tx).unwrap().is_none());
+    }
+}
@@ -279,8 +279,14 @@ fn build_preferred_hubs_set() -> HashSet<NodeId> {
 
 pub type ProbScorer = ProbabilisticScorer<Arc<NetworkGraph>, Arc<MutinyLogger>>;
 
+/// Scores channels with our own payment history, preferring routes through well known hubs.
+///
+/// Scores from the scorer server can be merged in with [HubPreferentialScorer::set_remote].
+/// When both have data for a channel our own data wins, the remote scores are only used
+/// for the channels we haven't learned anything about yet. Only our own data is persisted.
 pub struct HubPreferentialScorer {
     inner: ProbScorer,
+    remote: Option<ProbScorer>,
     preferred_hubs_set: HashSet<NodeId>,
 }
 
@@ -288,10 +294,39 @@ impl HubPreferentialScorer {
     pub(crate) fn new(inner: ProbScorer) -> Self {
         Self {
             inner,
+            remote: None,
             preferred_hubs_set: build_preferred_hubs_set(),
         }
     }
 
+    /// Replaces the remote scores with a newer snapshot
+    pub(crate) fn set_remote(&mut self, remote: ProbScorer) {
+        self.remote = Some(remote);
+    }
+
+    /// Replaces our own scores, used when importing a scorer snapshot
+    pub(crate) fn set_local(&mut self, inner: ProbScorer) {
+        self.inner = inner;
+    }
+
+    /// Our own scorer if we have data for the channel, otherwise the remote one if we have it
+    fn scorer_for(&self, short_channel_id: Option<u64>, target: Option<NodeId>) -> &ProbScorer {
+        let (Some(remote), Some(scid), Some(target)) = (&self.remote, short_channel_id, target)
+        else {
+            return &self.inner;
+        };
+
+        if self
+            .inner
+            .estimated_channel_liquidity_range(scid, &target)
+            .is_some()
+        {
+            &self.inner
+        } else {
+            remote
+        }
+    }
+
     fn is_source_preferred_hub(&self, candidate: &CandidateRouteHop) -> bool {
         match candidate {
             CandidateRouteHop::FirstHop(_) => false, // source of first hop is us
@@ -318,6 +353,35 @@ impl HubPreferentialScorer {
         }
     }
 
+    /// Estimates the probability of the path succeeding from the historical liquidity
+    /// of its channels, our own first hop is assumed to succeed.
+    ///
+    /// Returns None if we have no history for any of the channels.
+    pub(crate) fn estimated_success_probability(
+        &self,
+        path: &Path,
+        score_params: &ProbabilisticScoringFeeParameters,
+    ) -> Option<f64> {
+        let mut probability = None;
+        // walk backwards, each channel carries the fees of every hop after it
+        let mut amount_msat = 0;
+        for hop in path.hops.iter().skip(1).rev() {
+            amount_msat += hop.fee_msat;
+            let target = NodeId::from_pubkey(&hop.pubkey);
+            let scorer = self.scorer_for(Some(hop.short_channel_id), Some(target));
+            if let Some(p) = scorer.historical_estimated_payment_success_probability(
+                hop.short_channel_id,
+                &target,
+                amount_msat,
+                score_params,
+            ) {
+                probability = Some(probability.unwrap_or(1.0) * p);
+            }
+        }
+
+        probability
+    }
+
     fn is_target_preferred_hub(&self, candidate: &CandidateRouteHop) -> bool {
         match candidate {
             CandidateRouteHop::FirstHop(hop) => self.preferred_hubs_set.contains(hop.payer_node_id),
@@ -342,9 +406,9 @@ impl ScoreLookUp for HubPreferentialScorer {
         usage: ChannelUsage,
         score_params: &Self::ScoreParams,
     ) -> u64 {
-        // normal penalty from the inner scorer
+        // normal penalty from our own scorer, or the remote one if we don't know the channel
         let mut penalty = self
-            .inner
+            .scorer_for(candidate.short_channel_id(), candidate.target())
             .channel_penalty_msat(candidate, usage, score_params);
 
         let hub_to_hub_min_penalty = (score_params.base_penalty_msat as f64 * 0.5) as u64;
@@ -404,7 +468,10 @@ impl ScoreUpdate for HubPreferentialScorer {
     }
 
     fn time_passed(&mut self, duration_since_epoch: Duration) {
-        self.inner.time_passed(duration_since_epoch)
+        self.inner.time_passed(duration_since_epoch);
+        if let Some(remote) = self.remote.as_mut() {
+            remote.time_passed(duration_since_epoch)
+        }
     }
 }
 
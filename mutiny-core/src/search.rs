@@ -0,0 +1,497 @@
+use crate::error::MutinyError;
+use crate::event::PaymentInfo;
+use crate::labels::{get_contact_key, Contact, LabelStorage, CONTACT_PREFIX};
+use crate::nodemanager::ChannelClosure;
+use crate::storage::{
+    get_payment_hash_from_key, payment_key, MutinyStorage, PAYMENT_INBOUND_PREFIX_KEY,
+    PAYMENT_OUTBOUND_PREFIX_KEY, TRANSACTION_DETAILS_PREFIX_KEY,
+};
+use crate::{ActivityItem, MutinyInvoice, TransactionDetails, CHANNEL_CLOSURE_PREFIX};
+use bitcoin::secp256k1::ThirtyTwoByteHash;
+use hex_conservative::{DisplayHex, FromHex};
+use lightning::ln::PaymentHash;
+use lightning_invoice::Bolt11Invoice;
+use nostr::ToBech32;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Prefix of the words of each indexed item, stored by the key of the item
+const SEARCH_INDEX_PREFIX: &str = "search_index/";
+/// Set once the index was built for the items stored before it was kept
+pub(crate) const SEARCH_INDEX_BUILT_KEY: &str = "search_index_built";
+/// The whole index used to be stored in one value under this key
+const LEGACY_SEARCH_INDEX_KEY: &str = "search_index";
+/// Prefix of the labeled addresses in the search index, they aren't stored on their own
+pub(crate) const ADDRESS_SEARCH_PREFIX: &str = "address/";
+
+/// Results of a search across the wallet's activity, contacts, and labeled addresses
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SearchResults {
+    pub activity: Vec<ActivityItem>,
+    /// Contact id and contact
+    pub contacts: Vec<(String, Contact)>,
+    /// Address and its labels
+    pub addresses: Vec<(String, Vec<String>)>,
+}
+
+/// Index from the words of everything searchable to the keys of the items they are in,
+/// so a search doesn't need to load all of the items.
+///
+/// The words of each item are stored on their own as the item is written, see
+/// [update_search_index], and the index is put together from them for a search.
+///
+/// Activity is indexed by its activity index key, contacts by their storage key and
+/// labeled addresses by the address with [ADDRESS_SEARCH_PREFIX].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct SearchIndex {
+    /// Lowercase words and the keys of the items that have them
+    words: BTreeMap<String, BTreeSet<String>>,
+    /// Keys of the items and their words, so they can be removed when the item changes
+    items: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl SearchIndex {
+    /// Replaces the words of the item with the words of the fields
+    pub(crate) fn update(&mut self, key: &str, fields: &[String]) {
+        self.insert(key, words(fields));
+    }
+
+    fn insert(&mut self, key: &str, words: BTreeSet<String>) {
+        self.remove(key);
+
+        for word in words.iter() {
+            self.words
+                .entry(word.clone())
+                .or_default()
+                .insert(key.to_string());
+        }
+        self.items.insert(key.to_string(), words);
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        let Some(words) = self.items.remove(key) else {
+            return;
+        };
+        for word in words {
+            if let Some(keys) = self.words.get_mut(&word) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Removes every item with a key that starts with the prefix
+    pub(crate) fn remove_prefix(&mut self, prefix: &str) {
+        let keys: Vec<String> = self
+            .items
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    /// Keys of the items that have a word starting with every term.
+    ///
+    /// A matching contact or labeled address also matches the items tagged with it,
+    /// so activity can be found by the name of the contact or the label of the address
+    /// without being indexed again when those change.
+    pub(crate) fn search(&self, terms: &[String]) -> BTreeSet<String> {
+        let mut results: Option<BTreeSet<String>> = None;
+        for term in terms {
+            let mut keys: BTreeSet<String> = self
+                .words
+                .range(term.clone()..)
+                .take_while(|(word, _)| word.starts_with(term.as_str()))
+                .flat_map(|(_, keys)| keys.iter().cloned())
+                .collect();
+
+            let linked: Vec<String> = keys
+                .iter()
+                .filter_map(|k| {
+                    k.strip_prefix(CONTACT_PREFIX)
+                        .or_else(|| k.strip_prefix(ADDRESS_SEARCH_PREFIX))
+                })
+                .map(|word| word.to_lowercase())
+                .collect();
+            for word in linked {
+                if let Some(tagged) = self.words.get(&word) {
+                    keys.extend(tagged.iter().cloned());
+                }
+            }
+
+            let results = results.get_or_insert_with(|| keys.clone());
+            results.retain(|k| keys.contains(k));
+            if results.is_empty() {
+                break;
+            }
+        }
+
+        results.unwrap_or_default()
+    }
+}
+
+/// Splits a query into lowercase terms, all of which need to match for an item to be returned
+pub(crate) fn query_terms(query: &str) -> Vec<String> {
+    query.split_whitespace().map(|t| t.to_lowercase()).collect()
+}
+
+/// The lowercase words of the fields. Words with punctuation, like lightning addresses,
+/// are also split so they can be found by any of their parts.
+fn words(fields: &[String]) -> BTreeSet<String> {
+    let mut words = BTreeSet::new();
+    for word in fields.iter().flat_map(|f| f.split_whitespace()) {
+        let word = word.to_lowercase();
+        words.extend(
+            word.split(|c: char| !c.is_alphanumeric())
+                .filter(|part| !part.is_empty() && *part != word)
+                .map(|part| part.to_string()),
+        );
+        words.insert(word);
+    }
+    words
+}
+
+fn search_index_key(key: &str) -> String {
+    format!("{SEARCH_INDEX_PREFIX}{key}")
+}
+
+/// Puts the search index together from the words of every item
+pub(crate) fn get_search_index<S: MutinyStorage>(storage: &S) -> Result<SearchIndex, MutinyError> {
+    let mut index = SearchIndex::default();
+    for (key, words) in storage.scan::<BTreeSet<String>>(SEARCH_INDEX_PREFIX, None)? {
+        if let Some(key) = key.strip_prefix(SEARCH_INDEX_PREFIX) {
+            index.insert(key, words);
+        }
+    }
+    Ok(index)
+}
+
+/// Changes to the search index, made in order like on a [SearchIndex]
+#[derive(Debug, Default)]
+pub(crate) struct SearchIndexChanges {
+    updated: BTreeMap<String, BTreeSet<String>>,
+    removed: BTreeSet<String>,
+    removed_prefixes: Vec<String>,
+}
+
+impl SearchIndexChanges {
+    /// Replaces the words of the item with the words of the fields
+    pub(crate) fn update(&mut self, key: &str, fields: &[String]) {
+        self.removed.remove(key);
+        self.updated.insert(key.to_string(), words(fields));
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.updated.remove(key);
+        self.removed.insert(key.to_string());
+    }
+
+    /// Removes every item with a key that starts with the prefix
+    pub(crate) fn remove_prefix(&mut self, prefix: &str) {
+        self.updated.retain(|k, _| !k.starts_with(prefix));
+        self.removed.retain(|k| !k.starts_with(prefix));
+        self.removed_prefixes.push(prefix.to_string());
+    }
+}
+
+/// Changes the search index, only the items that changed are saved
+/// so writes don't depend on the size of the index
+pub(crate) fn update_search_index<S: MutinyStorage>(
+    storage: &S,
+    f: impl FnOnce(&mut SearchIndexChanges),
+) -> Result<(), MutinyError> {
+    let mut changes = SearchIndexChanges::default();
+    f(&mut changes);
+
+    let mut deleted: Vec<String> = changes
+        .removed
+        .iter()
+        .map(|k| search_index_key(k))
+        .collect();
+    for prefix in changes.removed_prefixes {
+        deleted.extend(
+            storage
+                .scan_keys(&search_index_key(&prefix), None)?
+                .into_iter()
+                .filter(|k| {
+                    let key = &k[SEARCH_INDEX_PREFIX.len()..];
+                    !changes.updated.contains_key(key)
+                }),
+        );
+    }
+    if !deleted.is_empty() {
+        storage.delete(&deleted)?;
+    }
+
+    for (key, words) in changes.updated {
+        let key = search_index_key(&key);
+        if storage.get_data::<BTreeSet<String>>(&key)?.as_ref() != Some(&words) {
+            storage.set_data(key, words, None)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Indexes a payment with the labels of its invoice
+pub(crate) fn index_payment<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    info: &PaymentInfo,
+    inbound: bool,
+) -> Result<(), MutinyError> {
+    let labels_map = storage.get_invoice_labels()?;
+    let fields = payment_fields(payment_hash, info, inbound, &labels_map);
+    let key = payment_key(inbound, payment_hash);
+    update_search_index(storage, |index| index.update(&key, &fields))
+}
+
+/// Indexes the payments of the invoice again, after its labels changed
+pub(crate) fn reindex_invoice<S: MutinyStorage>(
+    storage: &S,
+    invoice: &Bolt11Invoice,
+) -> Result<(), MutinyError> {
+    let payment_hash = invoice.payment_hash().into_32();
+    for inbound in [true, false] {
+        let key = payment_key(inbound, &payment_hash);
+        if let Some(info) = storage.get_data::<PaymentInfo>(key)? {
+            index_payment(storage, &payment_hash, &info, inbound)?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the search index from everything stored, for wallets from before it was kept.
+/// On-chain wallet transactions are added when the wallet syncs.
+pub(crate) fn build_search_index<S: MutinyStorage>(storage: &S) -> Result<(), MutinyError> {
+    let mut index = SearchIndexChanges::default();
+
+    let labels_map = storage.get_invoice_labels()?;
+    for (prefix, inbound) in [
+        (PAYMENT_INBOUND_PREFIX_KEY, true),
+        (PAYMENT_OUTBOUND_PREFIX_KEY, false),
+    ] {
+        for (key, info) in storage.scan::<PaymentInfo>(prefix, None)? {
+            let Ok(hash) = <[u8; 32]>::from_hex(get_payment_hash_from_key(&key, prefix)) else {
+                continue;
+            };
+            index.update(&key, &payment_fields(&hash, &info, inbound, &labels_map));
+        }
+    }
+
+    for (key, transaction) in
+        storage.scan::<TransactionDetails>(TRANSACTION_DETAILS_PREFIX_KEY, None)?
+    {
+        index.update(&key, &transaction_fields(&transaction));
+    }
+    for (key, closure) in storage.scan::<ChannelClosure>(CHANNEL_CLOSURE_PREFIX, None)? {
+        index.update(&key, &closure_fields(&closure));
+    }
+    for (id, contact) in storage.get_contacts()? {
+        index.update(&get_contact_key(id), &contact_fields(&contact));
+    }
+    for (address, labels) in storage.get_address_labels()? {
+        index.update(
+            &format!("{ADDRESS_SEARCH_PREFIX}{address}"),
+            &address_fields(&address, labels),
+        );
+    }
+
+    update_search_index(storage, |changes| *changes = index)?;
+    storage.delete(&[LEGACY_SEARCH_INDEX_KEY])?;
+    storage.set_data(SEARCH_INDEX_BUILT_KEY.to_string(), true, None)
+}
+
+/// The searchable text for a contact: its name, npub, lightning address, and lnurl
+pub(crate) fn contact_fields(contact: &Contact) -> Vec<String> {
+    let mut fields = vec![contact.name.clone()];
+    if let Some(npub) = contact.npub {
+        fields.push(npub.to_bech32().expect("bech32"));
+        fields.push(npub.to_string());
+    }
+    if let Some(ln_address) = contact.ln_address.as_ref() {
+        fields.push(ln_address.to_string());
+    }
+    if let Some(lnurl) = contact.lnurl.as_ref() {
+        fields.push(lnurl.to_string());
+    }
+    fields
+}
+
+/// The searchable text for a labeled address: the address and its labels
+pub(crate) fn address_fields(address: &str, mut labels: Vec<String>) -> Vec<String> {
+    labels.push(address.to_string());
+    labels
+}
+
+/// The searchable text for a payment: its invoice, payment hash and labels.
+/// Labels that belong to a contact link the payment to the contact.
+fn payment_fields(
+    payment_hash: &[u8; 32],
+    info: &PaymentInfo,
+    inbound: bool,
+    labels_map: &HashMap<Bolt11Invoice, Vec<String>>,
+) -> Vec<String> {
+    let labels = info
+        .bolt11
+        .as_ref()
+        .and_then(|i| labels_map.get(i).cloned())
+        .unwrap_or_default();
+    let mut fields = labels.clone();
+    match MutinyInvoice::from(info.clone(), PaymentHash(*payment_hash), inbound, labels) {
+        Ok(invoice) => fields.extend(invoice_fields(&invoice)),
+        Err(_) => fields.push(payment_hash.to_lower_hex_string()),
+    }
+    fields
+}
+
+fn invoice_fields(invoice: &MutinyInvoice) -> Vec<String> {
+    let mut fields = vec![invoice.payment_hash.into_32().to_lower_hex_string()];
+    if let Some(description) = invoice.description.as_ref() {
+        fields.push(description.clone());
+    }
+    if let Some(bolt11) = invoice.bolt11.as_ref() {
+        fields.push(bolt11.to_string());
+    }
+    if let Some(payee) = invoice.payee_pubkey {
+        fields.push(payee.to_string());
+    }
+    fields
+}
+
+/// The searchable text for a transaction: its ids and labels.
+/// On-chain wallet transactions are linked to their labels by their output addresses instead.
+pub(crate) fn transaction_fields(transaction: &TransactionDetails) -> Vec<String> {
+    let mut fields = transaction.labels.clone();
+    fields.push(transaction.internal_id.to_string());
+    if let Some(txid) = transaction.txid {
+        fields.push(txid.to_string());
+    }
+    fields
+}
+
+/// The searchable text for a channel closure: why it closed and who it was with
+pub(crate) fn closure_fields(closure: &ChannelClosure) -> Vec<String> {
+    let mut fields = vec![closure.reason.clone()];
+    if let Some(node_id) = closure.node_id {
+        fields.push(node_id.to_string());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NPUB: &str = "npub18s7md9ytv8r240jmag5j037huupk5jnsk94adykeaxtvc6lyftesuw5ydl";
+
+    #[test]
+    fn test_search_index() {
+        let mut index = SearchIndex::default();
+        index.update(
+            "payment",
+            &["Satoshi".to_string(), "coffee money".to_string()],
+        );
+        index.update("other", &["alice@getalby.com".to_string()]);
+
+        let search = |query: &str| index.search(&query_terms(query));
+        let payment = BTreeSet::from(["payment".to_string()]);
+        assert_eq!(search("satoshi"), payment);
+        assert_eq!(search("SAT coffee"), payment);
+        assert!(search("satoshi tea").is_empty());
+        assert!(search("   ").is_empty());
+        assert_eq!(search("getalby"), BTreeSet::from(["other".to_string()]));
+
+        // changing an item replaces its words
+        index.update("payment", &["tea".to_string()]);
+        assert!(search("coffee").is_empty());
+        assert_eq!(search("tea"), payment);
+
+        index.remove("payment");
+        assert!(search("tea").is_empty());
+        index.remove_prefix("oth");
+        assert_eq!(index, SearchIndex::default());
+    }
+
+    #[test]
+    fn test_search_linked_items() {
+        let storage = MemoryStorage::default();
+        let contact = Contact {
+            name: "Alice".to_string(),
+            npub: Some(nostr::PublicKey::from_str(NPUB).unwrap()),
+            ln_address: None,
+            lnurl: None,
+            image_url: None,
+            node_pubkey: None,
+            last_used: 0,
+        };
+        let id = storage.create_new_contact(contact).unwrap();
+
+        // a payment tagged with the contact
+        update_search_index(&storage, |index| {
+            index.update("payment", &[id.clone(), "pizza".to_string()]);
+            index.update("onchain", &["bc1qaddress".to_string()]);
+        })
+        .unwrap();
+        // and a transaction to a labeled address
+        let address = "bc1qaddress";
+        update_search_index(&storage, |index| {
+            let fields = address_fields(address, vec!["Savings".to_string()]);
+            index.update(&format!("{ADDRESS_SEARCH_PREFIX}{address}"), &fields);
+        })
+        .unwrap();
+
+        let index = get_search_index(&storage).unwrap();
+        let search = |query: &str| index.search(&query_terms(query));
+        let contact_key = get_contact_key(&id);
+        let expected = BTreeSet::from([contact_key.clone(), "payment".to_string()]);
+        assert_eq!(search("alice"), expected);
+        assert_eq!(search(&NPUB[..20]), expected);
+        assert_eq!(
+            search("alice pizza"),
+            BTreeSet::from(["payment".to_string()])
+        );
+        assert!(search("bob").is_empty());
+
+        let address_key = format!("{ADDRESS_SEARCH_PREFIX}{address}");
+        let expected = BTreeSet::from([address_key, "onchain".to_string()]);
+        assert_eq!(search("savings"), expected);
+    }
+
+    #[test]
+    fn test_search_index_changes() {
+        let storage = MemoryStorage::default();
+        update_search_index(&storage, |index| {
+            index.update("onchain/a", &["coffee".to_string()]);
+            index.update("onchain/b", &["tea".to_string()]);
+            index.update("payment", &["pizza".to_string()]);
+        })
+        .unwrap();
+
+        // each item is stored on its own
+        let keys = storage.scan_keys(SEARCH_INDEX_PREFIX, None).unwrap();
+        assert_eq!(keys.len(), 3);
+
+        // the on-chain items are replaced, the others are kept
+        update_search_index(&storage, |index| {
+            index.remove_prefix("onchain/");
+            index.update("onchain/b", &["cake".to_string()]);
+            index.remove("payment");
+        })
+        .unwrap();
+
+        let mut expected = SearchIndex::default();
+        expected.update("onchain/b", &["cake".to_string()]);
+        assert_eq!(get_search_index(&storage).unwrap(), expected);
+    }
+}
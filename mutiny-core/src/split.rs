@@ -0,0 +1,146 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::sha256;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+
+const PAYMENT_SPLITS_KEY: &str = "payment_splits";
+
+/// Who pays in a [PaymentSplit]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitDirection {
+    /// We paid the bill, every member gets an invoice for their share
+    Request,
+    /// We pay out the whole total, every member gets an equal share of it
+    Pay,
+}
+
+/// The share of a member of the group
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SplitShare {
+    /// The id of the contact
+    pub contact_id: String,
+    pub amount_sats: u64,
+    /// The invoice the member has to pay when requesting,
+    /// or the one we paid when paying, none for a keysend
+    pub bolt11: Option<Bolt11Invoice>,
+    pub payment_hash: Option<sha256::Hash>,
+    pub paid: bool,
+    /// Why we couldn't request or pay the share
+    pub error: Option<String>,
+}
+
+/// A payment split in equal shares between the members of a contact group,
+/// see [crate::MutinyWallet::split_payment]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PaymentSplit {
+    pub id: String,
+    pub group_id: String,
+    pub memo: Option<String>,
+    pub total_sats: u64,
+    pub direction: SplitDirection,
+    pub shares: Vec<SplitShare>,
+    /// Unix timestamp of when the split was made
+    pub created_at: u64,
+}
+
+impl PaymentSplit {
+    /// If every member paid or got paid their share
+    pub fn is_settled(&self) -> bool {
+        self.shares.iter().all(|s| s.paid)
+    }
+}
+
+/// Splits the total in equal shares, the first shares get the sats that are left over
+pub(crate) fn split_amount(total_sats: u64, members: usize) -> Result<Vec<u64>, MutinyError> {
+    if members == 0 || total_sats < members as u64 {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let share = total_sats / members as u64;
+    let remainder = (total_sats % members as u64) as usize;
+    Ok((0..members)
+        .map(|i| if i < remainder { share + 1 } else { share })
+        .collect())
+}
+
+pub(crate) fn get_payment_splits<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PaymentSplit>, MutinyError> {
+    Ok(storage.get_data(PAYMENT_SPLITS_KEY)?.unwrap_or_default())
+}
+
+/// Saves the given splits over the stored ones with the same id
+pub(crate) fn save_payment_splits<S: MutinyStorage>(
+    storage: &S,
+    updated: &[PaymentSplit],
+) -> Result<(), MutinyError> {
+    let mut splits = get_payment_splits(storage)?;
+    for split in updated {
+        match splits.iter_mut().find(|s| s.id == split.id) {
+            Some(s) => *s = split.clone(),
+            None => splits.push(split.clone()),
+        }
+    }
+
+    storage.set_data(PAYMENT_SPLITS_KEY.to_string(), splits, None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_split_amount() {
+        assert_eq!(split_amount(9_000, 3).unwrap(), vec![3_000, 3_000, 3_000]);
+        assert_eq!(split_amount(10_000, 3).unwrap(), vec![3_334, 3_333, 3_333]);
+        assert_eq!(split_amount(1, 1).unwrap(), vec![1]);
+
+        assert!(split_amount(1_000, 0).is_err());
+        assert!(split_amount(2, 3).is_err());
+    }
+
+    #[test]
+    fn test_save_payment_splits() {
+        let storage = MemoryStorage::default();
+        let mut split = PaymentSplit {
+            id: "split".to_string(),
+            group_id: "group".to_string(),
+            memo: Some("Dinner".to_string()),
+            total_sats: 2_000,
+            direction: SplitDirection::Request,
+            shares: vec![
+                SplitShare {
+                    contact_id: "alice".to_string(),
+                    amount_sats: 1_000,
+                    bolt11: None,
+                    payment_hash: None,
+                    paid: false,
+                    error: None,
+                },
+                SplitShare {
+                    contact_id: "bob".to_string(),
+                    amount_sats: 1_000,
+                    bolt11: None,
+                    payment_hash: None,
+                    paid: true,
+                    error: None,
+                },
+            ],
+            created_at: 1_700_000_000,
+        };
+        save_payment_splits(&storage, &[split.clone()]).unwrap();
+        assert!(!get_payment_splits(&storage).unwrap()[0].is_settled());
+
+        split.shares[0].paid = true;
+        save_payment_splits(&storage, &[split.clone()]).unwrap();
+        let splits = get_payment_splits(&storage).unwrap();
+        assert_eq!(splits, vec![split]);
+        assert!(splits[0].is_settled());
+    }
+}
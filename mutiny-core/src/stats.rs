@@ -0,0 +1,368 @@
+use crate::error::MutinyError;
+use crate::federation::FederationActivity;
+use crate::labels::Contact;
+use crate::price::activity_amount;
+use crate::storage::MutinyStorage;
+use crate::ActivityItem;
+use chrono::{DateTime, Datelike};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const SPENDING_STATS_KEY: &str = "spending_stats";
+/// Activity that settled this long before the newest counted item is checked again,
+/// on-chain transactions get the time of their block so they can settle out of order
+pub(crate) const SPENDING_RECOUNT_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Which months to include in a [SpendingSummary]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpendingPeriod {
+    /// The current month and the ones before it, `LastMonths(1)` is only the current month
+    LastMonths(u32),
+    AllTime,
+}
+
+/// Amounts that moved in and out of the wallet, in sats
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlowTotals {
+    /// Total sent, without fees
+    pub sent: u64,
+    pub received: u64,
+    pub fees: u64,
+    pub num_sent: u64,
+    pub num_received: u64,
+}
+
+impl FlowTotals {
+    /// Received minus sent and fees, negative if more left the wallet than came in
+    pub fn net_flow(&self) -> i64 {
+        self.received as i64 - self.sent as i64 - self.fees as i64
+    }
+
+    fn add(&mut self, inbound: bool, amount: u64, fees: u64) {
+        if inbound {
+            self.received += amount;
+            self.num_received += 1;
+        } else {
+            self.sent += amount;
+            self.num_sent += 1;
+        }
+        self.fees += fees;
+    }
+
+    fn merge(&mut self, other: &FlowTotals) {
+        self.sent += other.sent;
+        self.received += other.received;
+        self.fees += other.fees;
+        self.num_sent += other.num_sent;
+        self.num_received += other.num_received;
+    }
+}
+
+/// Fees paid by where the payment went through, in sats
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    pub onchain: u64,
+    pub lightning: u64,
+    pub federation: u64,
+}
+
+impl FeeBreakdown {
+    fn merge(&mut self, other: &FeeBreakdown) {
+        self.onchain += other.onchain;
+        self.lightning += other.lightning;
+        self.federation += other.federation;
+    }
+}
+
+/// Spending in one calendar month (UTC)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MonthlySummary {
+    /// The month as `YYYY-MM`
+    pub month: String,
+    pub totals: FlowTotals,
+    pub net_flow: i64,
+    pub fees: FeeBreakdown,
+    /// Totals by `OnChain`, `Lightning` or `Federation`
+    pub by_type: HashMap<String, FlowTotals>,
+    /// Totals by label, labels that are contacts are in `by_contact`
+    pub by_label: HashMap<String, FlowTotals>,
+    /// Totals by contact id
+    pub by_contact: HashMap<String, FlowTotals>,
+}
+
+impl MonthlySummary {
+    fn add(&mut self, activity: &ActivityItem, contacts: &HashMap<String, Contact>) -> bool {
+        let Some((inbound, amount, fees)) = activity_flow(activity) else {
+            return false;
+        };
+
+        let kind = match activity {
+            ActivityItem::OnChain(_) => {
+                self.fees.onchain += fees;
+                "OnChain"
+            }
+            ActivityItem::Lightning(_) => {
+                self.fees.lightning += fees;
+                "Lightning"
+            }
+            ActivityItem::Federation(_) => {
+                self.fees.federation += fees;
+                "Federation"
+            }
+            ActivityItem::ChannelClosed(_) => return false,
+        };
+
+        self.totals.add(inbound, amount, fees);
+        self.net_flow = self.totals.net_flow();
+        self.by_type
+            .entry(kind.to_string())
+            .or_default()
+            .add(inbound, amount, fees);
+        for label in activity.labels() {
+            let totals = if contacts.contains_key(&label) {
+                &mut self.by_contact
+            } else {
+                &mut self.by_label
+            };
+            totals.entry(label).or_default().add(inbound, amount, fees);
+        }
+
+        true
+    }
+}
+
+/// Spending over a [SpendingPeriod], see [crate::MutinyWallet::get_spending_summary]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpendingSummary {
+    pub totals: FlowTotals,
+    pub net_flow: i64,
+    pub fees: FeeBreakdown,
+    /// Months with activity, oldest first
+    pub months: Vec<MonthlySummary>,
+}
+
+/// Monthly summaries kept up to date as activity settles,
+/// so we don't need to walk all the activity for every summary
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct SpendingStats {
+    pub months: BTreeMap<String, MonthlySummary>,
+    /// Unix timestamp of the newest counted activity
+    pub last_timestamp: u64,
+    /// Counted activity within the recount window, by index key
+    pub recent: HashMap<String, u64>,
+}
+
+impl SpendingStats {
+    /// Oldest settle time of activity that might not have been counted yet
+    pub fn recount_cutoff(&self) -> u64 {
+        self.last_timestamp
+            .saturating_sub(SPENDING_RECOUNT_WINDOW_SECS)
+    }
+
+    /// Counts the activity with the given index key if it hasn't been counted before
+    pub fn add(
+        &mut self,
+        key: String,
+        activity: &ActivityItem,
+        contacts: &HashMap<String, Contact>,
+    ) -> bool {
+        let Some(time) = activity.last_updated() else {
+            return false;
+        };
+        if time < self.recount_cutoff() || self.recent.contains_key(&key) {
+            return false;
+        }
+        let Some(month) = month_key(time) else {
+            return false;
+        };
+
+        let summary = self
+            .months
+            .entry(month.clone())
+            .or_insert_with(|| MonthlySummary {
+                month,
+                ..Default::default()
+            });
+        if !summary.add(activity, contacts) {
+            return false;
+        }
+
+        self.recent.insert(key, time);
+        self.last_timestamp = self.last_timestamp.max(time);
+        true
+    }
+
+    /// Forgets the counted activity that is older than the recount window
+    pub fn prune(&mut self) {
+        let cutoff = self.recount_cutoff();
+        self.recent.retain(|_, time| *time >= cutoff);
+    }
+
+    pub fn summary(&self, period: SpendingPeriod, now: u64) -> SpendingSummary {
+        let first_month = match period {
+            SpendingPeriod::LastMonths(n) => months_ago_key(now, n.saturating_sub(1)),
+            SpendingPeriod::AllTime => None,
+        };
+
+        let mut summary = SpendingSummary::default();
+        for (month, monthly) in self.months.iter() {
+            if first_month.as_ref().is_some_and(|first| month < first) {
+                continue;
+            }
+            summary.totals.merge(&monthly.totals);
+            summary.fees.merge(&monthly.fees);
+            summary.months.push(monthly.clone());
+        }
+        summary.net_flow = summary.totals.net_flow();
+
+        summary
+    }
+}
+
+pub(crate) fn get_spending_stats<S: MutinyStorage>(
+    storage: &S,
+) -> Result<SpendingStats, MutinyError> {
+    Ok(storage.get_data(SPENDING_STATS_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn set_spending_stats<S: MutinyStorage>(
+    storage: &S,
+    stats: &SpendingStats,
+) -> Result<(), MutinyError> {
+    storage.set_data(SPENDING_STATS_KEY.to_string(), stats, None)
+}
+
+/// Returns if the activity is inbound, its amount without fees, and the fees,
+/// if it completed and moved any funds
+fn activity_flow(activity: &ActivityItem) -> Option<(bool, u64, u64)> {
+    let (inbound, amount) = activity_amount(activity)?;
+    let (fees, onchain) = match activity {
+        ActivityItem::OnChain(t) => (t.fee, true),
+        ActivityItem::Lightning(i) if i.paid() => (i.fees_paid, false),
+        ActivityItem::Lightning(_) | ActivityItem::ChannelClosed(_) => return None,
+        ActivityItem::Federation(f) => match f {
+            FederationActivity::Lightning { invoice, .. } if !invoice.paid() => return None,
+            FederationActivity::PegIn { .. } | FederationActivity::PegOut { .. } => {
+                (f.fees(), true)
+            }
+            _ => (f.fees(), false),
+        },
+    };
+
+    // only count fees we paid
+    let fees = if inbound { 0 } else { fees.unwrap_or(0) };
+    // the amount of outgoing transactions includes the fee
+    let amount = if onchain && !inbound {
+        amount.saturating_sub(fees)
+    } else {
+        amount
+    };
+
+    Some((inbound, amount, fees))
+}
+
+/// The month of the unix timestamp as `YYYY-MM`
+fn month_key(timestamp: u64) -> Option<String> {
+    let time = DateTime::from_timestamp(timestamp as i64, 0)?;
+    Some(format!("{:04}-{:02}", time.year(), time.month()))
+}
+
+/// The month `months` before the month of the unix timestamp as `YYYY-MM`
+fn months_ago_key(timestamp: u64, months: u32) -> Option<String> {
+    let time = DateTime::from_timestamp(timestamp as i64, 0)?;
+    let index = time.year() as i64 * 12 + time.month0() as i64 - months as i64;
+    Some(format!(
+        "{:04}-{:02}",
+        index.div_euclid(12),
+        index.rem_euclid(12) + 1
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::HTLCStatus;
+    use crate::storage::MemoryStorage;
+    use crate::MutinyInvoice;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // 2024-03-15
+    const MARCH: u64 = 1_710_460_800;
+    // 2024-04-15
+    const APRIL: u64 = 1_713_139_200;
+
+    fn payment(inbound: bool, amount: u64, last_updated: u64, labels: &[&str]) -> ActivityItem {
+        ActivityItem::Lightning(Box::new(MutinyInvoice {
+            amount_sats: Some(amount),
+            fees_paid: if inbound { None } else { Some(10) },
+            status: HTLCStatus::Succeeded,
+            inbound,
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            last_updated,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_month_keys() {
+        assert_eq!(month_key(MARCH), Some("2024-03".to_string()));
+        assert_eq!(months_ago_key(MARCH, 0), Some("2024-03".to_string()));
+        assert_eq!(months_ago_key(MARCH, 3), Some("2023-12".to_string()));
+        assert_eq!(months_ago_key(MARCH, 15), Some("2022-12".to_string()));
+    }
+
+    #[test]
+    fn test_spending_stats() {
+        let storage = MemoryStorage::default();
+        let contact = Contact {
+            name: "Alice".to_string(),
+            npub: None,
+            ln_address: None,
+            lnurl: None,
+            image_url: None,
+            node_pubkey: None,
+            last_used: 0,
+        };
+        let contacts = HashMap::from([("alice".to_string(), contact)]);
+
+        let mut stats = get_spending_stats(&storage).unwrap();
+        assert!(stats.add(
+            "a".to_string(),
+            &payment(true, 1_000, MARCH, &[]),
+            &contacts
+        ));
+        assert!(stats.add(
+            "b".to_string(),
+            &payment(false, 400, APRIL, &["alice", "food"]),
+            &contacts
+        ));
+        // already counted
+        assert!(!stats.add("b".to_string(), &payment(false, 400, APRIL, &[]), &contacts));
+        // older than the recount window, so it was already counted
+        assert!(!stats.add("c".to_string(), &payment(true, 5, MARCH, &[]), &contacts));
+
+        stats.prune();
+        assert_eq!(stats.recent.len(), 1);
+        set_spending_stats(&storage, &stats).unwrap();
+        let stats = get_spending_stats(&storage).unwrap();
+
+        let summary = stats.summary(SpendingPeriod::AllTime, APRIL);
+        assert_eq!(summary.months.len(), 2);
+        assert_eq!(summary.totals.received, 1_000);
+        assert_eq!(summary.totals.sent, 400);
+        assert_eq!(summary.fees.lightning, 10);
+        assert_eq!(summary.net_flow, 590);
+
+        let summary = stats.summary(SpendingPeriod::LastMonths(1), APRIL);
+        assert_eq!(summary.months.len(), 1);
+        let april = &summary.months[0];
+        assert_eq!(april.month, "2024-04");
+        assert_eq!(april.net_flow, -410);
+        assert_eq!(april.by_contact.get("alice").unwrap().sent, 400);
+        assert_eq!(april.by_label.get("food").unwrap().sent, 400);
+        assert!(!april.by_label.contains_key("alice"));
+        assert_eq!(april.by_type.get("Lightning").unwrap().num_sent, 1);
+    }
+}
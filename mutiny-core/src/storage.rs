@@ -1,22 +1,36 @@
+use crate::backup::REMOTE_BACKUP_CONFIG_KEY;
+use crate::handoff::{
+    DeviceHandoffRequest, DEVICE_HANDOFF_KEY, DEVICE_HANDOFF_POLL_MS, DEVICE_HANDOFF_TIMEOUT_SECS,
+};
+use crate::migrations::run_migrations;
 use crate::nodemanager::{ChannelClosure, NodeStorage};
+use crate::search::{index_payment, transaction_fields, update_search_index};
 use crate::utils::{now, spawn};
 use crate::vss::{MutinyVssClient, VssKeyValueItem};
 use crate::{blindauth::TokenStorage, logging::MutinyLogger};
 use crate::{
-    encrypt::{decrypt_with_password, encrypt, encryption_key_from_pass, Cipher},
+    encrypt::{
+        decrypt_with_password, encrypt, encryption_key_from_pass,
+        encryption_key_from_pass_with_params, Cipher, KeyStretchingParams,
+    },
     federation::FederationStorage,
     DEVICE_LOCK_INTERVAL_SECS,
 };
 use crate::{
     error::{MutinyError, MutinyStorageError},
-    event::PaymentInfo,
+    event::{PaymentInfo, PaymentPath},
 };
 use crate::{event::HTLCStatus, MutinyInvoice};
 use crate::{labels::LabelStorage, TransactionDetails};
-use crate::{ldkstorage::CHANNEL_MANAGER_KEY, utils::sleep};
+use crate::{
+    ldkstorage::{CHANNEL_CLOSURE_PREFIX, CHANNEL_MANAGER_KEY},
+    utils::sleep,
+};
 use async_trait::async_trait;
 use bdk::chain::{Append, PersistBackend};
 use bip39::Mnemonic;
+use bitcoin::bip32::ExtendedPrivKey;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::{secp256k1::ThirtyTwoByteHash, Txid};
 use fedimint_ln_common::bitcoin::hashes::hex::ToHex;
 use futures_util::lock::Mutex;
@@ -27,6 +41,7 @@ use nostr::{Event, Kind, Metadata};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
@@ -40,18 +55,30 @@ pub const SERVICE_TOKENS: &str = "service_tokens";
 const FEE_ESTIMATES_KEY: &str = "fee_estimates";
 pub const BITCOIN_PRICE_CACHE_KEY: &str = "bitcoin_price_cache";
 const FIRST_SYNC_KEY: &str = "first_sync";
+const PEER_DISCOVERY_KEY: &str = "peer_discovery";
 pub const LAST_NWC_SYNC_TIME_KEY: &str = "last_nwc_sync_time";
 pub(crate) const DEVICE_ID_KEY: &str = "device_id";
 pub const DEVICE_LOCK_KEY: &str = "device_lock";
 pub(crate) const EXPECTED_NETWORK_KEY: &str = "network";
+pub(crate) const ROOT_FINGERPRINT_KEY: &str = "root_fingerprint";
+pub(crate) const ACCOUNT_INDEX_KEY: &str = "account_index";
+/// Prefix of the items in the activity index, stored by the key of the item
+pub(crate) const ACTIVITY_INDEX_PREFIX: &str = "activity_index/";
+/// Set once the activity index was stored for the activity from before it was kept
+pub(crate) const ACTIVITY_INDEX_BUILT_KEY: &str = "activity_index_built";
+/// The whole activity index used to be stored in one value under this key
+pub(crate) const LEGACY_ACTIVITY_INDEX_KEY: &str = "activity_index";
 pub const PAYMENT_INBOUND_PREFIX_KEY: &str = "payment_inbound/";
 pub const PAYMENT_OUTBOUND_PREFIX_KEY: &str = "payment_outbound/";
 pub const TRANSACTION_DETAILS_PREFIX_KEY: &str = "transaction_details/";
+pub(crate) const PAYMENT_PATHS_PREFIX_KEY: &str = "payment_paths/";
 pub(crate) const ONCHAIN_PREFIX: &str = "onchain_tx/";
 pub const LAST_DM_SYNC_TIME_KEY: &str = "last_dm_sync_time";
 pub const LAST_HERMES_SYNC_TIME_KEY: &str = "last_hermes_sync_time";
 pub const NOSTR_PROFILE_METADATA: &str = "nostr_profile_metadata";
 pub const NOSTR_CONTACT_LIST: &str = "nostr_contact_list";
+/// Prefix of the keys of the accounts other than the main wallet, see [NamespacedStorage]
+pub const ACCOUNT_PREFIX_KEY: &str = "accounts/";
 const DELAYED_WRITE_MS: i32 = 50;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -73,13 +100,86 @@ impl From<DelayedKeyValueItem> for VssKeyValueItem {
 }
 
 fn needs_encryption(key: &str) -> bool {
-    match key {
+    match strip_account_namespace(key) {
         MNEMONIC_KEY => true,
+        REMOTE_BACKUP_CONFIG_KEY => true,
         str if str.starts_with(CHANNEL_MANAGER_KEY) => true,
         _ => false,
     }
 }
 
+/// Re-encrypts all the values that need encryption with the new password,
+/// see [MutinyStorage::change_password_and_rewrite_storage]
+fn rewrite_encrypted_values<S: MutinyStorage>(
+    storage: &mut S,
+    old: Option<String>,
+    new: Option<String>,
+) -> Result<(), MutinyError> {
+    // check if old password is correct
+    if old != storage.password().map(|s| s.to_owned()) {
+        return Err(MutinyError::IncorrectPassword);
+    }
+
+    // get all of our keys
+    let mut keys: Vec<String> = storage.scan_keys("", None)?;
+    // the decoy wallet has its own password, it's the account whose seed we can't decrypt
+    let decoys: Vec<String> = keys
+        .iter()
+        .filter_map(|k| k.strip_suffix(MNEMONIC_KEY))
+        .filter(|prefix| prefix.starts_with(ACCOUNT_PREFIX_KEY) && prefix.ends_with('/'))
+        .filter(|prefix| {
+            storage
+                .get_data::<Value>(format!("{prefix}{MNEMONIC_KEY}"))
+                .is_err()
+        })
+        .map(|prefix| prefix.to_string())
+        .collect();
+    // get the ones that need encryption
+    keys.retain(|k| needs_encryption(k) && !decoys.iter().any(|p| k.starts_with(p)));
+
+    // decrypt all of the values
+    let mut values: HashMap<String, Value> = HashMap::new();
+    for key in keys {
+        let value = storage.get_data(&key)?;
+        if let Some(v) = value {
+            values.insert(key.to_owned(), v);
+        }
+    }
+
+    // change the password, every value gets re-encrypted so this also
+    // migrates storage off of the legacy key stretching params
+    let params = storage
+        .cipher()
+        .map(|c| c.params())
+        .filter(|p| *p != KeyStretchingParams::LEGACY)
+        .unwrap_or_default();
+    let new_cipher = new
+        .as_ref()
+        .filter(|p| !p.is_empty())
+        .map(|p| encryption_key_from_pass_with_params(p, params))
+        .transpose()?;
+    storage.change_password(new, new_cipher)?;
+
+    // encrypt all of the values
+    for (key, value) in values {
+        storage.set_data(key, value, None)?;
+    }
+
+    Ok(())
+}
+
+/// Keys that are the same for every account on the device, see [NamespacedStorage]
+fn is_shared_key(key: &str) -> bool {
+    matches!(key, DEVICE_ID_KEY | BITCOIN_PRICE_CACHE_KEY)
+}
+
+/// Removes the account prefix from the key of an account other than the main wallet
+fn strip_account_namespace(key: &str) -> &str {
+    key.strip_prefix(ACCOUNT_PREFIX_KEY)
+        .and_then(|k| k.split_once('/'))
+        .map_or(key, |(_, k)| k)
+}
+
 pub fn encrypt_value(
     key: impl AsRef<str>,
     value: Value,
@@ -188,6 +288,54 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
     /// This is used to for getting a sorted list of keys quickly
     fn activity_index(&self) -> Arc<RwLock<BTreeSet<IndexItem>>>;
 
+    /// Changes the activity index and saves the items that changed,
+    /// so it doesn't need to be rebuilt from all the stored activity on startup
+    fn update_activity_index<F>(&self, f: F) -> Result<(), MutinyError>
+    where
+        F: FnOnce(&mut BTreeSet<IndexItem>),
+    {
+        let index = self.activity_index();
+        let mut index = index.try_write()?;
+        let before = index.clone();
+        f(&mut index);
+
+        // on-chain activity isn't stored, it comes from the wallet
+        let added: Vec<&IndexItem> = index
+            .difference(&before)
+            .filter(|i| !i.key.starts_with(ONCHAIN_PREFIX))
+            .collect();
+        let removed: Vec<String> = before
+            .difference(&index)
+            .filter(|i| !i.key.starts_with(ONCHAIN_PREFIX))
+            .filter(|i| !added.iter().any(|a| a.key == i.key))
+            .map(|i| activity_index_key(&i.key))
+            .collect();
+
+        // written while holding the lock so the stored items change in the same order
+        if !removed.is_empty() {
+            self.delete(&removed)?;
+        }
+        for item in added {
+            self.set_data(activity_index_key(&item.key), item, None)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks all writes for the rest of the session,
+    /// see [crate::MutinyWalletBuilder::with_read_only]
+    fn set_read_only(&self);
+
+    /// If writes are blocked, they fail with [MutinyError::ReadOnly]
+    fn is_read_only(&self) -> bool;
+
+    /// Returns an error if writes are blocked
+    fn check_writable(&self) -> Result<(), MutinyError> {
+        if self.is_read_only() {
+            return Err(MutinyError::ReadOnly);
+        }
+        Ok(())
+    }
+
     /// Set a value in the storage, the value will already be encrypted if needed
     fn set(&self, items: Vec<(String, impl Serialize)>) -> Result<(), MutinyError>;
 
@@ -206,6 +354,7 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
     where
         T: Serialize,
     {
+        self.check_writable()?;
         let data = serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
             source: MutinyStorageError::SerdeError { source: e },
         })?;
@@ -238,6 +387,7 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
     where
         T: Serialize + Send,
     {
+        self.check_writable()?;
         let data = serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
             source: MutinyStorageError::SerdeError { source: e },
         })?;
@@ -284,6 +434,7 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
     where
         T: Serialize + Send,
     {
+        self.check_writable()?;
         let data = serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
             source: MutinyStorageError::SerdeError { source: e },
         })?;
@@ -356,6 +507,12 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
     /// Start the storage, this will be called before any other methods
     async fn start(&mut self) -> Result<(), MutinyError>;
 
+    /// Waits for the writes made so far to be persisted. Storages that write in the
+    /// background should implement this, the wallet calls it before stopping the storage.
+    async fn flush(&self) -> Result<(), MutinyError> {
+        Ok(())
+    }
+
     /// Stop the storage, this will be called when the application is shutting down
     fn stop(&self);
 
@@ -385,6 +542,21 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
         Ok(map)
     }
 
+    /// Gets the values with the prefix from where they are persisted, including the ones
+    /// that are dropped from memory once read like the channel monitors. Values are
+    /// returned as stored, see [decrypt_value], so one that can't be read doesn't
+    /// hide the others.
+    async fn scan_persisted(&self, prefix: &str) -> Result<HashMap<String, Value>, MutinyError> {
+        let mut map = HashMap::new();
+        for key in self.scan_keys(prefix, None)? {
+            if let Some(value) = self.get::<Value>(&key)? {
+                map.insert(key, value);
+            }
+        }
+
+        Ok(map)
+    }
+
     /// Insert a mnemonic into the storage
     fn insert_mnemonic(&self, mnemonic: Mnemonic) -> Result<Mnemonic, MutinyError> {
         self.set_data(MNEMONIC_KEY.to_string(), &mnemonic, None)?;
@@ -407,39 +579,7 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
         old: Option<String>,
         new: Option<String>,
     ) -> Result<(), MutinyError> {
-        // check if old password is correct
-        if old != self.password().map(|s| s.to_owned()) {
-            return Err(MutinyError::IncorrectPassword);
-        }
-
-        // get all of our keys
-        let mut keys: Vec<String> = self.scan_keys("", None)?;
-        // get the ones that need encryption
-        keys.retain(|k| needs_encryption(k));
-
-        // decrypt all of the values
-        let mut values: HashMap<String, Value> = HashMap::new();
-        for key in keys {
-            let value = self.get_data(&key)?;
-            if let Some(v) = value {
-                values.insert(key.to_owned(), v);
-            }
-        }
-
-        // change the password
-        let new_cipher = new
-            .as_ref()
-            .filter(|p| !p.is_empty())
-            .map(|p| encryption_key_from_pass(p))
-            .transpose()?;
-        self.change_password(new, new_cipher)?;
-
-        // encrypt all of the values
-        for (key, value) in values {
-            self.set_data(key, value, None)?;
-        }
-
-        Ok(())
+        rewrite_encrypted_values(self, old, new)
     }
 
     /// Override the storage with the new JSON object
@@ -450,16 +590,11 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
 
     /// Deletes all data from the storage and removes lock from VSS
     async fn delete_all(&self) -> Result<(), MutinyError> {
+        self.check_writable()?;
         Self::clear().await?;
         // remove lock from VSS if is is enabled
         if self.vss_client().is_some() {
-            let device = self.get_device_id()?;
-            // set time to 0 to unlock
-            let lock = DeviceLock { time: 0, device };
-            // still update the version so it is written to VSS
-            let time = now().as_secs() as u32;
-            self.set_data_async(DEVICE_LOCK_KEY.to_string(), lock, Some(time))
-                .await?;
+            self.release_device_lock().await?;
         }
 
         Ok(())
@@ -554,6 +689,16 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
         self.set_data(FIRST_SYNC_KEY.to_string(), true, None)
     }
 
+    /// If our nodes should connect to peers from the network graph, enabled by default
+    fn peer_discovery_enabled(&self) -> Result<bool, MutinyError> {
+        self.get_data::<bool>(PEER_DISCOVERY_KEY)
+            .map(|v| v.unwrap_or(true))
+    }
+
+    fn set_peer_discovery_enabled(&self, enabled: bool) -> Result<(), MutinyError> {
+        self.set_data(PEER_DISCOVERY_KEY.to_string(), enabled, None)
+    }
+
     fn get_dm_sync_time(&self, is_hermes: bool) -> Result<Option<u64>, MutinyError> {
         let key = if is_hermes {
             LAST_HERMES_SYNC_TIME_KEY
@@ -638,6 +783,77 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
             .await
     }
 
+    /// Gives up the device lock so another device can take it right away
+    async fn release_device_lock(&self) -> Result<(), MutinyError> {
+        let device = self.get_device_id()?;
+        // set time to 0 to unlock
+        let lock = DeviceLock { time: 0, device };
+        // still update the version so it is written to VSS
+        let time = now().as_secs() as u32;
+        self.set_data_async(DEVICE_LOCK_KEY.to_string(), lock, Some(time))
+            .await
+    }
+
+    /// Asks the device holding the lock to stop and hand it over, then waits until it
+    /// is released. Gives up with [MutinyError::AlreadyRunning] if the other device
+    /// doesn't answer in time.
+    ///
+    /// Devices only see each other through VSS, so it is required.
+    async fn request_device_handoff(&self) -> Result<(), MutinyError> {
+        if self.vss_client().is_none() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let device = self.get_device_id()?;
+        match self.fetch_device_lock().await? {
+            Some(lock) if lock.is_locked(&device) => {}
+            _ => return Ok(()),
+        }
+
+        let time = now().as_secs() as u32;
+        let request = DeviceHandoffRequest {
+            device: device.clone(),
+            time,
+        };
+        self.set_data_async(DEVICE_HANDOFF_KEY.to_string(), request, Some(time))
+            .await?;
+
+        let start = now().as_secs();
+        while now().as_secs().saturating_sub(start) < DEVICE_HANDOFF_TIMEOUT_SECS {
+            sleep(DEVICE_HANDOFF_POLL_MS).await;
+            match self.fetch_device_lock().await? {
+                Some(lock) if lock.is_locked(&device) => continue,
+                Some(lock) => {
+                    // our local copy is outdated, update it so we can take the lock
+                    self.set_data(DEVICE_LOCK_KEY.to_string(), lock, None)?;
+                    return Ok(());
+                }
+                None => return Ok(()),
+            }
+        }
+
+        Err(MutinyError::AlreadyRunning)
+    }
+
+    /// Gets the latest request from another device to take over the lock
+    async fn fetch_device_handoff_request(
+        &self,
+    ) -> Result<Option<DeviceHandoffRequest>, MutinyError> {
+        let Some(vss) = self.vss_client() else {
+            return Ok(None);
+        };
+
+        let keys = vss
+            .list_key_versions(Some(DEVICE_HANDOFF_KEY.to_string()))
+            .await?;
+        if keys.is_empty() {
+            return Ok(None);
+        }
+
+        let item = vss.get_object(DEVICE_HANDOFF_KEY).await?;
+        Ok(Some(serde_json::from_value(item.value)?))
+    }
+
     async fn fetch_device_lock(&self) -> Result<Option<DeviceLock>, MutinyError>;
 }
 
@@ -649,6 +865,7 @@ pub struct MemoryStorage {
     pub vss_client: Option<Arc<MutinyVssClient>>,
     delayed_keys: Arc<Mutex<HashMap<String, DelayedKeyValueItem>>>,
     pub activity_index: Arc<RwLock<BTreeSet<IndexItem>>>,
+    read_only: Arc<AtomicBool>,
 }
 
 impl MemoryStorage {
@@ -664,17 +881,25 @@ impl MemoryStorage {
             vss_client,
             delayed_keys: Arc::new(Mutex::new(HashMap::new())),
             activity_index: Arc::new(RwLock::new(BTreeSet::new())),
+            read_only: Arc::new(AtomicBool::new(false)),
         }
     }
 
     pub async fn load_from_vss(&self) -> Result<(), MutinyError> {
         if let Some(vss) = self.vss_client() {
-            let keys = vss.list_key_versions(None).await?;
-            let mut items = HashMap::new();
-            for key in keys {
-                let obj = vss.get_object(&key.key).await?;
-                items.insert(key.key, obj.value);
-            }
+            let keys: Vec<String> = vss
+                .list_key_versions(None)
+                .await?
+                .into_iter()
+                .map(|kv| kv.key)
+                .filter(|key| !vss.is_excluded(key))
+                .collect();
+            let items: HashMap<String, Value> = vss
+                .get_objects(&keys)
+                .await?
+                .into_iter()
+                .map(|obj| (obj.key, obj.value))
+                .collect();
             let mut map = self
                 .memory
                 .try_write()
@@ -711,7 +936,16 @@ impl MutinyStorage for MemoryStorage {
         self.activity_index.clone()
     }
 
+    fn set_read_only(&self) {
+        self.read_only.store(true, Ordering::Relaxed);
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
     fn set(&self, items: Vec<(String, impl Serialize)>) -> Result<(), MutinyError> {
+        self.check_writable()?;
         for (key, value) in items {
             let data = serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
                 source: MutinyStorageError::SerdeError { source: e },
@@ -745,6 +979,7 @@ impl MutinyStorage for MemoryStorage {
     }
 
     fn delete(&self, keys: &[impl AsRef<str>]) -> Result<(), MutinyError> {
+        self.check_writable()?;
         let mut map = self
             .memory
             .try_write()
@@ -758,6 +993,7 @@ impl MutinyStorage for MemoryStorage {
     }
 
     async fn start(&mut self) -> Result<(), MutinyError> {
+        run_migrations(self)?;
         Ok(())
     }
 
@@ -809,6 +1045,277 @@ impl MutinyStorage for MemoryStorage {
     }
 }
 
+/// Lets several independent wallets share one storage backend, see
+/// [crate::accounts::MutinyWalletManager].
+///
+/// The main wallet has no namespace and uses the keys as is, the keys of the other accounts
+/// are prefixed with `accounts/{namespace}/` and they get their own activity index.
+/// VSS is tied to the keys of the main wallet, so the other accounts are only stored locally.
+#[derive(Clone)]
+pub struct NamespacedStorage<S: MutinyStorage> {
+    inner: S,
+    namespace: Option<String>,
+    /// If this is the decoy wallet, which has its own password
+    decoy: bool,
+    delayed_keys: Arc<Mutex<HashMap<String, DelayedKeyValueItem>>>,
+    activity_index: Arc<RwLock<BTreeSet<IndexItem>>>,
+}
+
+impl<S: MutinyStorage> NamespacedStorage<S> {
+    pub fn new(inner: S, namespace: Option<String>) -> Self {
+        Self {
+            inner,
+            namespace,
+            decoy: false,
+            delayed_keys: Arc::new(Mutex::new(HashMap::new())),
+            activity_index: Arc::new(RwLock::new(BTreeSet::new())),
+        }
+    }
+
+    /// Storage of the decoy wallet that is opened with the duress password,
+    /// see [duress_namespace]
+    pub fn duress(inner: S, namespace: String) -> Self {
+        Self {
+            decoy: true,
+            ..Self::new(inner, Some(namespace))
+        }
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    fn prefix(&self) -> Option<String> {
+        self.namespace
+            .as_ref()
+            .map(|ns| format!("{ACCOUNT_PREFIX_KEY}{ns}/"))
+    }
+
+    fn namespaced_key(&self, key: &str) -> String {
+        match self.prefix() {
+            Some(prefix) if !is_shared_key(key) => format!("{prefix}{key}"),
+            _ => key.to_string(),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S: MutinyStorage> MutinyStorage for NamespacedStorage<S> {
+    fn password(&self) -> Option<&str> {
+        self.inner.password()
+    }
+
+    fn cipher(&self) -> Option<Cipher> {
+        self.inner.cipher()
+    }
+
+    fn vss_client(&self) -> Option<Arc<MutinyVssClient>> {
+        match self.namespace {
+            None => self.inner.vss_client(),
+            Some(_) => None,
+        }
+    }
+
+    fn activity_index(&self) -> Arc<RwLock<BTreeSet<IndexItem>>> {
+        match self.namespace {
+            None => self.inner.activity_index(),
+            Some(_) => self.activity_index.clone(),
+        }
+    }
+
+    fn set_read_only(&self) {
+        self.inner.set_read_only()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn set(&self, items: Vec<(String, impl Serialize)>) -> Result<(), MutinyError> {
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|(key, value)| (self.namespaced_key(&key), value))
+            .collect();
+        self.inner.set(items)
+    }
+
+    async fn set_async<T>(&self, key: String, value: T) -> Result<(), MutinyError>
+    where
+        T: Serialize + Send,
+    {
+        self.inner.set_async(self.namespaced_key(&key), value).await
+    }
+
+    fn get_delayed_objects(&self) -> Arc<Mutex<HashMap<String, DelayedKeyValueItem>>> {
+        match self.namespace {
+            None => self.inner.get_delayed_objects(),
+            Some(_) => self.delayed_keys.clone(),
+        }
+    }
+
+    fn get<T>(&self, key: impl AsRef<str>) -> Result<Option<T>, MutinyError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.inner.get(self.namespaced_key(key.as_ref()))
+    }
+
+    fn delete(&self, keys: &[impl AsRef<str>]) -> Result<(), MutinyError> {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| self.namespaced_key(key.as_ref()))
+            .collect();
+        self.inner.delete(&keys)
+    }
+
+    async fn start(&mut self) -> Result<(), MutinyError> {
+        self.inner.start().await?;
+        // the account has its own schema version
+        run_migrations(self)?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), MutinyError> {
+        self.inner.flush().await
+    }
+
+    fn stop(&self) {
+        self.inner.stop()
+    }
+
+    fn connected(&self) -> Result<bool, MutinyError> {
+        self.inner.connected()
+    }
+
+    fn scan_keys(&self, prefix: &str, suffix: Option<&str>) -> Result<Vec<String>, MutinyError> {
+        match self.prefix() {
+            Some(ns) => {
+                let keys = self.inner.scan_keys(&format!("{ns}{prefix}"), suffix)?;
+                Ok(keys
+                    .into_iter()
+                    .map(|k| k[ns.len()..].to_string())
+                    .collect())
+            }
+            None => {
+                let mut keys = self.inner.scan_keys(prefix, suffix)?;
+                keys.retain(|k| !k.starts_with(ACCOUNT_PREFIX_KEY));
+                Ok(keys)
+            }
+        }
+    }
+
+    fn change_password(
+        &mut self,
+        new: Option<String>,
+        new_cipher: Option<Cipher>,
+    ) -> Result<(), MutinyError> {
+        self.inner.change_password(new, new_cipher)
+    }
+
+    /// All the accounts share the same password, so this rewrites all of them.
+    /// The decoy wallet has its own password, so only its values are rewritten.
+    fn change_password_and_rewrite_storage(
+        &mut self,
+        old: Option<String>,
+        new: Option<String>,
+    ) -> Result<(), MutinyError> {
+        if self.decoy {
+            rewrite_encrypted_values(self, old, new)
+        } else {
+            self.inner.change_password_and_rewrite_storage(old, new)
+        }
+    }
+
+    async fn import(json: Value) -> Result<(), MutinyError> {
+        S::import(json).await
+    }
+
+    async fn clear() -> Result<(), MutinyError> {
+        S::clear().await
+    }
+
+    /// Deleting the main wallet deletes the whole storage, including the other accounts
+    async fn delete_all(&self) -> Result<(), MutinyError> {
+        match self.namespace {
+            None => self.inner.delete_all().await,
+            Some(_) => {
+                let keys = self.scan_keys("", None)?;
+                self.delete(&keys)
+            }
+        }
+    }
+
+    async fn fetch_device_lock(&self) -> Result<Option<DeviceLock>, MutinyError> {
+        match self.namespace {
+            None => self.inner.fetch_device_lock().await,
+            Some(_) => self.get_device_lock(),
+        }
+    }
+}
+
+/// Namespace of the decoy wallet of the wallet with this key.
+///
+/// It is derived from the key so it looks like the random id of any other account,
+/// and only the wallet itself can tell which account is its decoy. Someone with access
+/// to the device can still see there is an account that isn't in the list of accounts.
+pub fn duress_namespace(xprivkey: &ExtendedPrivKey) -> String {
+    let mut engine = sha256::Hash::engine();
+    engine.input(b"mutiny-duress-namespace");
+    engine.input(&xprivkey.private_key.secret_bytes());
+    let hash = sha256::Hash::from_engine(engine);
+
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    uuid::Builder::from_random_bytes(bytes)
+        .into_uuid()
+        .to_string()
+}
+
+/// Stores the seed of the decoy wallet in its namespace, encrypted with the duress
+/// password instead of the password of the storage
+pub fn set_duress_mnemonic<S: MutinyStorage>(
+    storage: &S,
+    namespace: &str,
+    password: &str,
+    mnemonic: &Mnemonic,
+) -> Result<(), MutinyError> {
+    storage.check_writable()?;
+    let cipher = encryption_key_from_pass(password)?;
+    let value = encrypt_value(MNEMONIC_KEY, serde_json::to_value(mnemonic)?, Some(cipher))?;
+    NamespacedStorage::duress(storage.clone(), namespace.to_string())
+        .set(vec![(MNEMONIC_KEY.to_string(), value)])
+}
+
+/// Finds the decoy wallet by the account whose seed decrypts with the password,
+/// returns its namespace and seed. Fails with [MutinyError::IncorrectPassword]
+/// if there is no decoy wallet or the password isn't the duress password.
+pub fn get_duress_mnemonic<S: MutinyStorage>(
+    storage: &S,
+    password: &str,
+) -> Result<(String, Mnemonic), MutinyError> {
+    for key in storage.scan_keys(ACCOUNT_PREFIX_KEY, Some(MNEMONIC_KEY))? {
+        let Some(namespace) = key
+            .strip_prefix(ACCOUNT_PREFIX_KEY)
+            .and_then(|k| k.strip_suffix(MNEMONIC_KEY))
+            .and_then(|k| k.strip_suffix('/'))
+        else {
+            continue;
+        };
+        let Some(value) = storage.get::<Value>(&key)? else {
+            continue;
+        };
+        let Ok(value) = decrypt_value(MNEMONIC_KEY, value, Some(password)) else {
+            continue;
+        };
+        if let Ok(mnemonic) = serde_json::from_value(value) {
+            return Ok((namespace.to_string(), mnemonic));
+        }
+    }
+
+    Err(MutinyError::IncorrectPassword)
+}
+
 // Dummy implementation for testing or if people want to ignore persistence
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
@@ -829,6 +1336,12 @@ impl MutinyStorage for () {
         Arc::new(RwLock::new(BTreeSet::new()))
     }
 
+    fn set_read_only(&self) {}
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
     fn set(&self, _: Vec<(String, impl Serialize)>) -> Result<(), MutinyError> {
         Ok(())
     }
@@ -897,33 +1410,21 @@ pub(crate) fn persist_transaction_details<S: MutinyStorage>(
 ) -> Result<(), MutinyError> {
     let key = transaction_details_key(transaction_details.internal_id);
     storage.set_data(key.clone(), transaction_details, None)?;
+    update_search_index(storage, |index| {
+        index.update(&key, &transaction_fields(transaction_details))
+    })?;
 
     // insert into activity index
-    match transaction_details.confirmation_time {
-        bdk_chain::ConfirmationTime::Confirmed { height: _, time } => {
-            let index = storage.activity_index();
-            let mut index = index.try_write()?;
-            // remove old version
-            index.remove(&IndexItem {
-                timestamp: None, // timestamp would be None for Unconfirmed
-                key: key.clone(),
-            });
-            index.insert(IndexItem {
-                timestamp: Some(time),
-                key,
-            });
-        }
-        bdk_chain::ConfirmationTime::Unconfirmed { .. } => {
-            let index = storage.activity_index();
-            let mut index = index.try_write()?;
-            index.insert(IndexItem {
-                timestamp: None,
-                key,
-            });
-        }
-    }
-
-    Ok(())
+    let timestamp = match transaction_details.confirmation_time {
+        bdk_chain::ConfirmationTime::Confirmed { height: _, time } => Some(time),
+        bdk_chain::ConfirmationTime::Unconfirmed { .. } => None,
+    };
+    storage.update_activity_index(|index| {
+        // remove old version, it has a different timestamp if it
+        // confirmed or was rolled back by a reorg
+        index.retain(|i| i.key != key);
+        index.insert(IndexItem { timestamp, key });
+    })
 }
 
 // Deletes the transaction detail and removes the pending index if it exists
@@ -933,18 +1434,102 @@ pub(crate) fn delete_transaction_details<S: MutinyStorage>(
 ) -> Result<(), MutinyError> {
     let key = transaction_details_key(txid);
     storage.delete(&[key.clone()])?;
+    update_search_index(storage, |index| index.remove(&key))?;
 
     // delete the pending index item, if it exists
-    let index = storage.activity_index();
-    let mut index = index.try_write()?;
-    index.remove(&IndexItem {
-        timestamp: None, // timestamp would be None for Unconfirmed
-        key: key.clone(),
-    });
+    storage.update_activity_index(|index| {
+        index.remove(&IndexItem {
+            timestamp: None, // timestamp would be None for Unconfirmed
+            key,
+        });
+    })
+}
+
+fn activity_index_key(key: &str) -> String {
+    format!("{ACTIVITY_INDEX_PREFIX}{key}")
+}
+
+/// Reads the stored activity index, without the on-chain activity
+pub(crate) fn get_activity_index<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<IndexItem>, MutinyError> {
+    Ok(storage
+        .scan::<IndexItem>(ACTIVITY_INDEX_PREFIX, None)?
+        .into_values()
+        .collect())
+}
 
+/// Stores the whole activity index again, for when the stored items
+/// don't match the ones in memory
+pub(crate) fn rewrite_activity_index<S: MutinyStorage>(storage: &S) -> Result<(), MutinyError> {
+    let index = storage.activity_index();
+    let index = index.try_read()?;
+    let items: HashMap<String, &IndexItem> = index
+        .iter()
+        .filter(|i| !i.key.starts_with(ONCHAIN_PREFIX))
+        .map(|i| (activity_index_key(&i.key), i))
+        .collect();
+
+    let stale: Vec<String> = storage
+        .scan_keys(ACTIVITY_INDEX_PREFIX, None)?
+        .into_iter()
+        .filter(|k| !items.contains_key(k))
+        .collect();
+    if !stale.is_empty() {
+        storage.delete(&stale)?;
+    }
+    for (key, item) in items {
+        if storage.get_data::<IndexItem>(&key)?.as_ref() != Some(item) {
+            storage.set_data(key, item, None)?;
+        }
+    }
     Ok(())
 }
 
+/// Builds the activity index from the stored payments, channel closures and transaction details.
+/// On-chain wallet transactions are not included, those come from the wallet.
+pub(crate) fn build_activity_index<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<IndexItem>, MutinyError> {
+    // add any transaction details stored from fedimint
+    let mut activity_index = storage
+        .scan::<TransactionDetails>(TRANSACTION_DETAILS_PREFIX_KEY, None)?
+        .into_iter()
+        .map(|(k, v)| {
+            let timestamp = match v.confirmation_time {
+                bdk_chain::ConfirmationTime::Confirmed { height: _, time } => Some(time),
+                bdk_chain::ConfirmationTime::Unconfirmed { .. } => None,
+            };
+            IndexItem { timestamp, key: k }
+        })
+        .collect::<Vec<_>>();
+
+    // add the channel closures to the activity index
+    let closures = storage
+        .scan::<ChannelClosure>(CHANNEL_CLOSURE_PREFIX, None)?
+        .into_iter()
+        .map(|(k, v)| IndexItem {
+            timestamp: Some(v.timestamp),
+            key: k,
+        });
+    activity_index.extend(closures);
+
+    // add inbound and outbound payments to the activity index
+    for prefix in [PAYMENT_INBOUND_PREFIX_KEY, PAYMENT_OUTBOUND_PREFIX_KEY] {
+        let payments = storage
+            .scan::<PaymentInfo>(prefix, None)?
+            .into_iter()
+            .filter(|(_, p)| matches!(p.status, HTLCStatus::Succeeded | HTLCStatus::InFlight))
+            .map(|(k, v)| IndexItem {
+                timestamp: Some(v.last_update),
+                key: k,
+            });
+        activity_index.extend(payments);
+    }
+
+    Ok(activity_index)
+}
+
 pub(crate) fn get_transaction_details<S: MutinyStorage>(
     storage: &S,
     internal_id: Txid,
@@ -974,20 +1559,17 @@ pub(crate) fn persist_payment_info<S: MutinyStorage>(
 ) -> Result<(), MutinyError> {
     let key = payment_key(inbound, payment_hash);
     storage.set_data(key.clone(), payment_info, None)?;
+    index_payment(storage, payment_hash, payment_info, inbound)?;
 
     // insert into activity index
     match payment_info.status {
-        HTLCStatus::InFlight => {
-            let index = storage.activity_index();
-            let mut index = index.try_write()?;
+        HTLCStatus::InFlight => storage.update_activity_index(|index| {
             index.insert(IndexItem {
                 timestamp: None,
                 key,
             });
-        }
-        HTLCStatus::Succeeded => {
-            let index = storage.activity_index();
-            let mut index = index.try_write()?;
+        }),
+        HTLCStatus::Succeeded => storage.update_activity_index(|index| {
             // remove old version
             index.remove(&IndexItem {
                 timestamp: None, // timestamp would be None for InFlight / Pending
@@ -997,19 +1579,15 @@ pub(crate) fn persist_payment_info<S: MutinyStorage>(
                 timestamp: Some(payment_info.last_update),
                 key,
             });
-        }
-        HTLCStatus::Failed => {
-            let index = storage.activity_index();
-            let mut index = index.try_write()?;
+        }),
+        HTLCStatus::Failed => storage.update_activity_index(|index| {
             index.remove(&IndexItem {
                 timestamp: None, // timestamp would be None for InFlight / Pending
                 key,
             });
-        }
-        HTLCStatus::Pending => {} // don't add to index until invoice is paid
+        }),
+        HTLCStatus::Pending => Ok(()), // don't add to index until invoice is paid
     }
-
-    Ok(())
 }
 
 pub(crate) fn get_invoice_by_hash<S: MutinyStorage>(
@@ -1089,6 +1667,34 @@ pub(crate) fn list_payment_info<S: MutinyStorage>(
         .collect())
 }
 
+fn payment_paths_key(payment_hash: &[u8; 32]) -> String {
+    format!("{PAYMENT_PATHS_PREFIX_KEY}{}", payment_hash.as_hex())
+}
+
+/// Records a resolved path of an outbound payment.
+/// Events can be replayed on restart, so the same attempt is only saved once.
+pub(crate) fn persist_payment_path<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    path: PaymentPath,
+) -> Result<(), MutinyError> {
+    let mut paths = get_payment_paths(storage, payment_hash)?;
+    if paths.iter().any(|p| p.same_attempt(&path)) {
+        return Ok(());
+    }
+    paths.push(path);
+
+    storage.set_data(payment_paths_key(payment_hash), paths, None)
+}
+
+pub(crate) fn get_payment_paths<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+) -> Result<Vec<PaymentPath>, MutinyError> {
+    let paths = storage.get_data(payment_paths_key(payment_hash))?;
+    Ok(paths.unwrap_or_default())
+}
+
 /// Update the contact list in storage, chooses the event that is newer
 /// If the event is older than the one in storage, it will be ignored
 ///
@@ -1157,10 +1763,20 @@ pub(crate) fn get_payment_hash_from_key<'a>(key: &'a str, prefix: &str) -> &'a s
 
 #[cfg(test)]
 mod tests {
+    use crate::encrypt::{encryption_key_from_pass_with_params, KeyStretchingParams};
+    use crate::error::MutinyError;
+    use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo, PaymentPath};
+    use crate::storage::{
+        build_activity_index, duress_namespace, get_activity_index, get_duress_mnemonic,
+        get_payment_paths, payment_key, persist_payment_info, persist_payment_path,
+        set_duress_mnemonic, IndexItem, NamespacedStorage, ACTIVITY_INDEX_PREFIX, MNEMONIC_KEY,
+    };
     use crate::test_utils::*;
     use crate::utils::sleep;
     use crate::{encrypt::encryption_key_from_pass, storage::MemoryStorage};
     use crate::{keymanager, storage::MutinyStorage};
+    use bitcoin::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -1179,6 +1795,15 @@ mod tests {
         assert_eq!(Some(mnemonic), stored_mnemonic);
     }
 
+    #[test]
+    fn peer_discovery_toggle() {
+        let storage = MemoryStorage::default();
+        assert!(storage.peer_discovery_enabled().unwrap());
+
+        storage.set_peer_discovery_enabled(false).unwrap();
+        assert!(!storage.peer_discovery_enabled().unwrap());
+    }
+
     #[test]
     fn insert_and_get_mnemonic_with_password() {
         let test_name = "insert_and_get_mnemonic_with_password";
@@ -1248,4 +1873,269 @@ mod tests {
             Err(crate::MutinyError::AlreadyRunning)
         );
     }
+
+    #[test]
+    fn test_persist_payment_paths() {
+        let storage = MemoryStorage::default();
+        let payment_hash = [1; 32];
+        let path = PaymentPath {
+            status: HTLCStatus::Failed,
+            amount_msat: 1_000,
+            fee_msat: 10,
+            hops: vec![],
+            failed_short_channel_id: Some(42),
+            last_update: 1,
+        };
+
+        persist_payment_path(&storage, &payment_hash, path.clone()).unwrap();
+        // replayed events are ignored
+        let replayed = PaymentPath {
+            last_update: 2,
+            ..path.clone()
+        };
+        persist_payment_path(&storage, &payment_hash, replayed).unwrap();
+
+        let settled = PaymentPath {
+            status: HTLCStatus::Succeeded,
+            failed_short_channel_id: None,
+            ..path.clone()
+        };
+        persist_payment_path(&storage, &payment_hash, settled.clone()).unwrap();
+
+        let paths = get_payment_paths(&storage, &payment_hash).unwrap();
+        assert_eq!(paths, vec![path, settled]);
+        assert!(get_payment_paths(&storage, &[2; 32]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_namespaced_storage() {
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let main = NamespacedStorage::new(storage.clone(), None);
+        let account = NamespacedStorage::new(storage.clone(), Some("account".to_string()));
+
+        main.set_data("key".to_string(), 1, None).unwrap();
+        account.set_data("key".to_string(), 2, None).unwrap();
+        assert_eq!(main.get_data::<u32>("key").unwrap(), Some(1));
+        assert_eq!(account.get_data::<u32>("key").unwrap(), Some(2));
+        assert_eq!(main.scan_keys("", None).unwrap(), vec!["key".to_string()]);
+        assert_eq!(
+            account.scan_keys("", None).unwrap(),
+            vec!["key".to_string()]
+        );
+
+        // the seed of other accounts is encrypted too
+        let seed = keymanager::generate_seed(12).unwrap();
+        account.insert_mnemonic(seed.clone()).unwrap();
+        let raw: Option<String> = storage.get("accounts/account/mnemonic").unwrap();
+        assert!(raw.is_some_and(|r| r != seed.to_string()));
+        assert_eq!(account.get_mnemonic().unwrap(), Some(seed));
+
+        // the device id is shared
+        assert_eq!(
+            main.get_device_id().unwrap(),
+            account.get_device_id().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_duress_mnemonic() {
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let mut storage = MemoryStorage::new(Some(pass.clone()), Some(cipher), None);
+        let seed = keymanager::generate_seed(12).unwrap();
+        storage.insert_mnemonic(seed.clone()).unwrap();
+
+        // the decoy wallet looks like any other account
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &seed.to_seed("")).unwrap();
+        let namespace = duress_namespace(&xpriv);
+        assert_eq!(namespace, duress_namespace(&xpriv));
+        assert_eq!(
+            uuid::Uuid::parse_str(&namespace).unwrap().get_version(),
+            Some(uuid::Version::Random)
+        );
+
+        let duress_pass = uuid::Uuid::new_v4().to_string();
+        let decoy_seed = keymanager::generate_seed(12).unwrap();
+        set_duress_mnemonic(&storage, &namespace, &duress_pass, &decoy_seed).unwrap();
+        assert_eq!(
+            get_duress_mnemonic(&storage, &duress_pass).unwrap(),
+            (namespace.clone(), decoy_seed.clone())
+        );
+        assert_eq!(
+            get_duress_mnemonic(&storage, &pass),
+            Err(MutinyError::IncorrectPassword)
+        );
+
+        // changing the wallet password doesn't touch the decoy wallet
+        let new_pass = uuid::Uuid::new_v4().to_string();
+        storage
+            .change_password_and_rewrite_storage(Some(pass), Some(new_pass.clone()))
+            .unwrap();
+        assert_eq!(storage.get_mnemonic().unwrap(), Some(seed));
+        assert_eq!(
+            get_duress_mnemonic(&storage, &duress_pass).unwrap(),
+            (namespace.clone(), decoy_seed.clone())
+        );
+
+        // the duress password only opens the decoy wallet
+        let mut duress_storage = storage.clone();
+        let duress_cipher = encryption_key_from_pass(&duress_pass).unwrap();
+        duress_storage
+            .change_password(Some(duress_pass), Some(duress_cipher))
+            .unwrap();
+        assert!(duress_storage.get_mnemonic().is_err());
+        let decoy = NamespacedStorage::duress(duress_storage, namespace);
+        assert_eq!(decoy.get_mnemonic().unwrap(), Some(decoy_seed));
+    }
+
+    #[test]
+    fn test_change_password_migrates_key_stretching() {
+        let pass = uuid::Uuid::new_v4().to_string();
+        let legacy =
+            encryption_key_from_pass_with_params(&pass, KeyStretchingParams::LEGACY).unwrap();
+        let mut storage = MemoryStorage::new(Some(pass.clone()), Some(legacy), None);
+        let seed = keymanager::generate_seed(12).unwrap();
+        storage.insert_mnemonic(seed.clone()).unwrap();
+
+        let new_pass = uuid::Uuid::new_v4().to_string();
+        storage
+            .change_password_and_rewrite_storage(Some(pass), Some(new_pass))
+            .unwrap();
+
+        let raw: String = storage.get(MNEMONIC_KEY).unwrap().unwrap();
+        let params = KeyStretchingParams::default();
+        assert!(raw.starts_with(&format!(
+            "argon2id:{},{},{}:",
+            params.m_cost, params.t_cost, params.p_cost
+        )));
+        assert_eq!(storage.get_mnemonic().unwrap(), Some(seed));
+    }
+
+    #[test]
+    async fn test_read_only_storage() {
+        let storage = MemoryStorage::default();
+        storage.set_data("key".to_string(), 1, None).unwrap();
+
+        let clone = storage.clone();
+        clone.set_read_only();
+        assert!(storage.is_read_only());
+
+        assert_eq!(
+            storage.set_data("key".to_string(), 2, None),
+            Err(crate::MutinyError::ReadOnly)
+        );
+        assert_eq!(
+            storage.set_data_async("key".to_string(), 2, None).await,
+            Err(crate::MutinyError::ReadOnly)
+        );
+        assert_eq!(storage.delete(&["key"]), Err(crate::MutinyError::ReadOnly));
+        assert_eq!(storage.get_data::<u32>("key").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_activity_index_is_stored() {
+        let storage = MemoryStorage::default();
+        let payment_hash = [1; 32];
+        let mut payment = PaymentInfo {
+            preimage: None,
+            secret: None,
+            status: HTLCStatus::InFlight,
+            amt_msat: MillisatAmount(Some(1_000)),
+            fee_paid_msat: None,
+            bolt11: None,
+            payee_pubkey: None,
+            privacy_level: Default::default(),
+            last_update: 100,
+            custom_tlvs: vec![],
+        };
+        persist_payment_info(&storage, &payment_hash, &payment, false).unwrap();
+        payment.status = HTLCStatus::Succeeded;
+        persist_payment_info(&storage, &payment_hash, &payment, false).unwrap();
+
+        let expected = vec![IndexItem {
+            timestamp: Some(100),
+            key: payment_key(false, &payment_hash),
+        }];
+        // the in flight item was replaced, not stored next to it
+        assert_eq!(get_activity_index(&storage).unwrap(), expected);
+        assert_eq!(
+            storage
+                .scan_keys(ACTIVITY_INDEX_PREFIX, None)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // a failed payment is taken out of the stored index
+        let failed_hash = [2; 32];
+        payment.status = HTLCStatus::InFlight;
+        persist_payment_info(&storage, &failed_hash, &payment, false).unwrap();
+        assert_eq!(get_activity_index(&storage).unwrap().len(), 2);
+        payment.status = HTLCStatus::Failed;
+        persist_payment_info(&storage, &failed_hash, &payment, false).unwrap();
+        assert_eq!(get_activity_index(&storage).unwrap(), expected);
+
+        // the migration finds the same activity
+        assert_eq!(build_activity_index(&storage).unwrap(), expected);
+    }
+
+    #[test]
+    async fn test_device_handoff_without_vss() {
+        let storage = MemoryStorage::default();
+
+        // the lock can only be handed over through VSS
+        assert_eq!(
+            storage.request_device_handoff().await,
+            Err(crate::MutinyError::InvalidArgumentsError)
+        );
+        assert_eq!(storage.fetch_device_handoff_request().await.unwrap(), None);
+    }
+
+    #[test]
+    async fn test_device_handoff() {
+        let test_name = "test_device_handoff";
+        log!("{}", test_name);
+
+        let vss = std::sync::Arc::new(create_vss_client().await);
+        let storage = MemoryStorage::new(None, None, Some(vss.clone()));
+        storage.load_from_vss().await.unwrap();
+        let id = storage.get_device_id().unwrap();
+
+        // nobody holds the lock yet, so there is nothing to hand off
+        storage.request_device_handoff().await.unwrap();
+        assert_eq!(storage.fetch_device_handoff_request().await.unwrap(), None);
+
+        storage.set_device_lock().await.unwrap();
+        // sleep 1 second to make sure it writes to VSS
+        sleep(1_000).await;
+
+        let new_storage = MemoryStorage::new(None, None, Some(vss));
+        new_storage.load_from_vss().await.unwrap();
+        let new_id = new_storage.get_device_id().unwrap();
+        assert_eq!(
+            new_storage.set_device_lock().await,
+            Err(crate::MutinyError::AlreadyRunning)
+        );
+
+        // the device holding the lock releases it once it sees the request
+        let release = async {
+            loop {
+                if let Some(request) = storage.fetch_device_handoff_request().await.unwrap() {
+                    assert_eq!(request.device, new_id);
+                    storage.release_device_lock().await.unwrap();
+                    break;
+                }
+                sleep(1_000).await;
+            }
+        };
+        let (handoff, _) = futures::join!(new_storage.request_device_handoff(), release);
+        handoff.unwrap();
+
+        let lock = new_storage.get_device_lock().unwrap().unwrap();
+        assert_eq!(lock.device, id);
+        assert!(!lock.is_locked(&new_id));
+        new_storage.set_device_lock().await.unwrap();
+    }
 }
@@ -0,0 +1,546 @@
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::storage::MutinyStorage;
+use crate::utils::fetch_with_timeout;
+use bitcoin::bip32::{ChildNumber, ExtendedPrivKey};
+use bitcoin::blockdata::opcodes::all::{
+    OP_CHECKSIG, OP_CLTV, OP_DROP, OP_ELSE, OP_ENDIF, OP_EQUAL, OP_HASH160, OP_IF,
+};
+use bitcoin::blockdata::script::{Builder, Instruction};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{ripemd160, sha256, Hash};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{
+    absolute, ecdsa, Address, Network, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
+use lightning_invoice::Bolt11Invoice;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub(crate) const SWAP_IN_PREFIX_KEY: &str = "swap_in/";
+const SWAP_REFUND_KEY_INDEX_KEY: &str = "swap_refund_key_index";
+/// How often we check on our pending swaps
+pub(crate) const SWAP_CHECK_INTERVAL_SECS: u64 = 60;
+pub(crate) const SWAP_IN_LABEL: &str = "Swap In";
+const PAIR_ID: &str = "BTC/BTC";
+/// Furthest past the tip we let a provider set the refund timeout, about three days of blocks
+const MAX_SWAP_TIMEOUT_BLOCKS: u32 = 432;
+
+/// Status of a swap from on-chain to lightning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SwapStatus {
+    /// The swap was created but we haven't sent the lockup transaction yet
+    Created,
+    /// We sent the lockup transaction and are waiting for the provider to pay our invoice
+    Funded,
+    /// The provider paid our invoice
+    Completed,
+    /// The provider failed to pay our invoice, the funds are refunded after the timeout
+    Failed,
+    /// We refunded the lockup transaction back to our wallet
+    Refunded,
+    /// The swap expired, or sending the lockup transaction failed, before anything was locked up
+    Expired,
+}
+
+impl SwapStatus {
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Created | Self::Funded | Self::Failed)
+    }
+
+    /// Maps the status of a swap at the provider to ours, if it is one we act on
+    fn from_provider(status: &str) -> Option<Self> {
+        match status {
+            "invoice.paid" | "transaction.claim.pending" | "transaction.claimed" => {
+                Some(Self::Completed)
+            }
+            "invoice.failedToPay" | "transaction.lockupFailed" | "swap.expired" => {
+                Some(Self::Failed)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A swap of on-chain funds into lightning, see [crate::MutinyWallet::swap_in]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapIn {
+    /// Id of the swap at the provider
+    pub id: String,
+    pub status: SwapStatus,
+    /// Invoice the provider pays us
+    pub invoice: Bolt11Invoice,
+    /// Amount we receive over lightning
+    pub amount_sats: u64,
+    /// Fee charged by the provider, not including the fee of the lockup transaction
+    pub fee_sats: u64,
+    /// Address we lock our funds into
+    pub address: String,
+    /// Amount we lock into the swap address
+    pub expected_amount: u64,
+    /// Hex encoded witness script of the swap address
+    pub redeem_script: String,
+    /// Block height after which we can refund the swap
+    pub timeout_block_height: u32,
+    /// Derivation index of the key we refund with
+    pub refund_key_index: u32,
+    pub lockup_txid: Option<Txid>,
+    pub refund_txid: Option<Txid>,
+    /// Unix timestamp of when the swap was created
+    pub created_at: u64,
+}
+
+pub(crate) fn persist_swap_in<S: MutinyStorage>(
+    storage: &S,
+    swap: &SwapIn,
+) -> Result<(), MutinyError> {
+    let key = format!("{SWAP_IN_PREFIX_KEY}{}", swap.id);
+    storage.set_data(key, swap, None)
+}
+
+pub(crate) fn get_swap_ins<S: MutinyStorage>(storage: &S) -> Result<Vec<SwapIn>, MutinyError> {
+    let map = storage.scan::<SwapIn>(SWAP_IN_PREFIX_KEY, None)?;
+    let mut swaps: Vec<SwapIn> = map.into_values().collect();
+    swaps.sort_by_key(|s| s.created_at);
+    Ok(swaps)
+}
+
+/// Takes the index of the next refund key. Every swap we ask the provider for gets
+/// a fresh key, even when we give up on it before it is saved.
+pub(crate) fn next_refund_key_index<S: MutinyStorage>(storage: &S) -> Result<u32, MutinyError> {
+    let index = match storage.get_data::<u32>(SWAP_REFUND_KEY_INDEX_KEY)? {
+        Some(index) => index,
+        // before the index was saved, the keys of the saved swaps were used
+        None => get_swap_ins(storage)?
+            .iter()
+            .map(|s| s.refund_key_index + 1)
+            .max()
+            .unwrap_or(0),
+    };
+    storage.set_data(SWAP_REFUND_KEY_INDEX_KEY.to_string(), index + 1, None)?;
+    Ok(index)
+}
+
+/// Derives the key we can refund a swap with after its timeout
+pub(crate) fn swap_refund_key(
+    xprivkey: ExtendedPrivKey,
+    index: u32,
+) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    let root = create_root_child_key(&context, xprivkey, ChildKey::Swap)?;
+    let child = root.derive_priv(&context, &[ChildNumber::from_hardened_idx(index)?])?;
+    Ok(child.private_key)
+}
+
+pub(crate) fn parse_script(hex: &str) -> Result<ScriptBuf, MutinyError> {
+    let bytes: Vec<u8> = FromHex::from_hex(hex).map_err(|_| MutinyError::SwapProviderError)?;
+    Ok(ScriptBuf::from(bytes))
+}
+
+/// Script of a submarine swap, the provider claims the funds with the preimage
+/// of our invoice or we refund them after the timeout.
+fn swap_script(
+    payment_hash: &sha256::Hash,
+    claim_pubkey: &PublicKey,
+    refund_pubkey: &PublicKey,
+    timeout_block_height: u32,
+) -> ScriptBuf {
+    let hash = ripemd160::Hash::hash(payment_hash.as_byte_array());
+    Builder::new()
+        .push_opcode(OP_HASH160)
+        .push_slice(hash.to_byte_array())
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_IF)
+        .push_key(&bitcoin::PublicKey::new(*claim_pubkey))
+        .push_opcode(OP_ELSE)
+        .push_int(timeout_block_height as i64)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_key(&bitcoin::PublicKey::new(*refund_pubkey))
+        .push_opcode(OP_ENDIF)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+/// Checks the swap the provider gave us locks the funds to our invoice and lets us refund
+/// them after the timeout, before we send anything to it. Returns the swap address.
+pub(crate) fn verify_swap_script(
+    script: &Script,
+    address: &str,
+    payment_hash: &sha256::Hash,
+    refund_pubkey: &PublicKey,
+    timeout_block_height: u32,
+    network: Network,
+) -> Result<Address, MutinyError> {
+    let claim_pubkey = match script.instructions().nth(4) {
+        Some(Ok(Instruction::PushBytes(bytes))) => {
+            PublicKey::from_slice(bytes.as_bytes()).map_err(|_| MutinyError::SwapProviderError)?
+        }
+        _ => return Err(MutinyError::SwapProviderError),
+    };
+
+    let expected = swap_script(
+        payment_hash,
+        &claim_pubkey,
+        refund_pubkey,
+        timeout_block_height,
+    );
+    let expected_address = Address::p2wsh(&expected, network);
+    if expected.as_script() != script || expected_address.to_string() != address {
+        return Err(MutinyError::SwapProviderError);
+    }
+
+    Ok(expected_address)
+}
+
+/// Checks the refund timeout the provider gave us is in the future but not so far out
+/// that our funds would be stuck for a long time if the provider doesn't pay us.
+pub(crate) fn verify_swap_timeout(
+    timeout_block_height: u32,
+    current_height: u32,
+) -> Result<(), MutinyError> {
+    if timeout_block_height <= current_height
+        || timeout_block_height - current_height > MAX_SWAP_TIMEOUT_BLOCKS
+    {
+        return Err(MutinyError::SwapProviderError);
+    }
+    Ok(())
+}
+
+/// Builds a transaction refunding the lockup output of a swap to the destination,
+/// valid once the swap has timed out.
+pub(crate) fn build_refund_tx(
+    script: &Script,
+    timeout_block_height: u32,
+    lockup: OutPoint,
+    lockup_value: u64,
+    refund_key: &SecretKey,
+    destination: ScriptBuf,
+    fee_rate: f32,
+) -> Result<Transaction, MutinyError> {
+    let lock_time = absolute::LockTime::from_height(timeout_block_height)
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let mut tx = Transaction {
+        version: 2,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: lockup,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: lockup_value,
+            script_pubkey: destination,
+        }],
+    };
+
+    // sign once to know the size of the transaction, then again with the fee taken out
+    sign_refund_tx(&mut tx, script, lockup_value, refund_key)?;
+    let fee = (tx.vsize() as f32 * fee_rate).ceil() as u64;
+    let dust = tx.output[0].script_pubkey.dust_value().to_sat();
+    tx.output[0].value = lockup_value
+        .checked_sub(fee)
+        .filter(|value| *value >= dust)
+        .ok_or(MutinyError::InsufficientBalance)?;
+    sign_refund_tx(&mut tx, script, lockup_value, refund_key)?;
+
+    Ok(tx)
+}
+
+fn sign_refund_tx(
+    tx: &mut Transaction,
+    script: &Script,
+    lockup_value: u64,
+    refund_key: &SecretKey,
+) -> Result<(), MutinyError> {
+    let sighash = SighashCache::new(&*tx)
+        .segwit_signature_hash(0, script, lockup_value, EcdsaSighashType::All)
+        .map_err(|_| MutinyError::WalletSigningFailed)?;
+    let msg = Message::from_slice(&sighash[..]).map_err(|_| MutinyError::WalletSigningFailed)?;
+    let sig = Secp256k1::signing_only().sign_ecdsa(&msg, refund_key);
+
+    // an empty preimage takes the refund branch of the script
+    let sig = ecdsa::Signature::sighash_all(sig).to_vec();
+    tx.input[0].witness = Witness::from_slice(&[sig, vec![], script.to_bytes()]);
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct PairsResponse {
+    pairs: HashMap<String, Pair>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Pair {
+    hash: String,
+    pub limits: PairLimits,
+    fees: PairFees,
+}
+
+impl Pair {
+    /// Fee the provider charges for paying an invoice of the given amount
+    pub fn swap_in_fee(&self, amount_sats: u64) -> u64 {
+        let percentage_fee = amount_sats as f64 * self.fees.percentage_swap_in / 100.0;
+        percentage_fee.ceil() as u64 + self.fees.miner_fees.base_asset.normal
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct PairLimits {
+    pub minimal: u64,
+    pub maximal: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PairFees {
+    percentage_swap_in: f64,
+    miner_fees: MinerFees,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MinerFees {
+    base_asset: AssetMinerFees,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AssetMinerFees {
+    normal: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreatedSwap {
+    pub id: String,
+    pub address: String,
+    pub redeem_script: String,
+    pub expected_amount: u64,
+    pub timeout_block_height: u32,
+}
+
+#[derive(Deserialize)]
+struct SwapStatusResponse {
+    status: String,
+}
+
+/// Client for a submarine swap provider speaking the Boltz API
+pub(crate) struct SwapClient {
+    url: String,
+    http_client: reqwest::Client,
+}
+
+impl SwapClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// Gets the current fees and limits for swapping on-chain bitcoin to lightning
+    pub async fn get_pair(&self) -> Result<Pair, MutinyError> {
+        let mut res: PairsResponse = self.request(Method::GET, "getpairs", None).await?;
+        res.pairs
+            .remove(PAIR_ID)
+            .ok_or(MutinyError::SwapProviderError)
+    }
+
+    /// Creates a swap paying our invoice once we lock funds into the returned address
+    pub async fn create_swap(
+        &self,
+        invoice: &Bolt11Invoice,
+        refund_pubkey: &PublicKey,
+        pair: &Pair,
+    ) -> Result<CreatedSwap, MutinyError> {
+        let body = json!({
+            "type": "submarine",
+            "pairId": PAIR_ID,
+            "orderSide": "sell",
+            "invoice": invoice.to_string(),
+            "refundPublicKey": refund_pubkey.to_string(),
+            "pairHash": pair.hash,
+        });
+        self.request(Method::POST, "createswap", Some(body)).await
+    }
+
+    pub async fn get_swap_status(&self, id: &str) -> Result<Option<SwapStatus>, MutinyError> {
+        let body = json!({ "id": id });
+        let res: SwapStatusResponse = self.request(Method::POST, "swapstatus", Some(body)).await?;
+        Ok(SwapStatus::from_provider(&res.status))
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<T, MutinyError> {
+        let mut request = self
+            .http_client
+            .request(method, format!("{}/{path}", self.url));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let request = request
+            .build()
+            .map_err(|_| MutinyError::SwapProviderError)?;
+
+        let response = fetch_with_timeout(&self.http_client, request).await?;
+        if !response.status().is_success() {
+            return Err(MutinyError::SwapProviderError);
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|_| MutinyError::SwapProviderError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::create_dummy_invoice;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn keys(index: u32) -> (SecretKey, PublicKey) {
+        let seed = [index as u8 + 1; 32];
+        let xpriv = ExtendedPrivKey::new_master(Network::Regtest, &seed).unwrap();
+        let secret = swap_refund_key(xpriv, index).unwrap();
+        (secret, secret.public_key(&Secp256k1::new()))
+    }
+
+    #[test]
+    fn test_verify_swap_script() {
+        let payment_hash = sha256::Hash::hash(&[1; 32]);
+        let (_, claim_pubkey) = keys(0);
+        let (_, refund_pubkey) = keys(1);
+
+        let script = swap_script(&payment_hash, &claim_pubkey, &refund_pubkey, 800_000);
+        let address = Address::p2wsh(&script, Network::Regtest).to_string();
+
+        let verified = verify_swap_script(
+            &script,
+            &address,
+            &payment_hash,
+            &refund_pubkey,
+            800_000,
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(verified.to_string(), address);
+
+        // a script we can't refund with our key
+        let (_, other_pubkey) = keys(2);
+        assert!(verify_swap_script(
+            &script,
+            &address,
+            &payment_hash,
+            &other_pubkey,
+            800_000,
+            Network::Regtest,
+        )
+        .is_err());
+
+        // a later timeout than agreed
+        let script = swap_script(&payment_hash, &claim_pubkey, &refund_pubkey, 900_000);
+        let address = Address::p2wsh(&script, Network::Regtest).to_string();
+        assert!(verify_swap_script(
+            &script,
+            &address,
+            &payment_hash,
+            &refund_pubkey,
+            800_000,
+            Network::Regtest,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_build_refund_tx() {
+        let payment_hash = sha256::Hash::hash(&[1; 32]);
+        let (_, claim_pubkey) = keys(0);
+        let (refund_key, refund_pubkey) = keys(1);
+        let script = swap_script(&payment_hash, &claim_pubkey, &refund_pubkey, 800_000);
+
+        let lockup = OutPoint::new(Txid::all_zeros(), 1);
+        let refund_pubkey = bitcoin::PublicKey::new(refund_pubkey);
+        let destination = Address::p2wpkh(&refund_pubkey, Network::Regtest)
+            .unwrap()
+            .script_pubkey();
+        let tx = build_refund_tx(
+            &script,
+            800_000,
+            lockup,
+            100_000,
+            &refund_key,
+            destination.clone(),
+            2.0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tx.lock_time,
+            absolute::LockTime::from_height(800_000).unwrap()
+        );
+        assert_eq!(tx.input[0].previous_output, lockup);
+        assert_eq!(tx.input[0].witness.len(), 3);
+        assert!(tx.input[0].witness.nth(1).unwrap().is_empty());
+
+        // the signature can be a byte shorter or longer after taking out the fee
+        let fee = 100_000 - tx.output[0].value;
+        assert!(fee.abs_diff(tx.vsize() as u64 * 2) <= 2);
+
+        // not enough to pay for the refund
+        let res = build_refund_tx(&script, 800_000, lockup, 300, &refund_key, destination, 2.0);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_verify_swap_timeout() {
+        assert!(verify_swap_timeout(800_144, 800_000).is_ok());
+        assert!(verify_swap_timeout(800_000 + MAX_SWAP_TIMEOUT_BLOCKS, 800_000).is_ok());
+        assert!(verify_swap_timeout(800_001 + MAX_SWAP_TIMEOUT_BLOCKS, 800_000).is_err());
+        assert!(verify_swap_timeout(800_000, 800_000).is_err());
+        assert!(verify_swap_timeout(799_000, 800_000).is_err());
+    }
+
+    #[test]
+    fn test_persist_swap_in() {
+        let storage = MemoryStorage::default();
+        let invoice = create_dummy_invoice(Some(1_000), Network::Regtest, None).0;
+        let swap = SwapIn {
+            id: "swap".to_string(),
+            status: SwapStatus::Funded,
+            invoice,
+            amount_sats: 1_000,
+            fee_sats: 10,
+            address: "address".to_string(),
+            expected_amount: 1_010,
+            redeem_script: "".to_string(),
+            timeout_block_height: 800_000,
+            refund_key_index: 0,
+            lockup_txid: Some(Txid::all_zeros()),
+            refund_txid: None,
+            created_at: 1_700_000_000,
+        };
+        persist_swap_in(&storage, &swap).unwrap();
+
+        assert_eq!(get_swap_ins(&storage).unwrap(), vec![swap]);
+
+        // the saved swap has the first key
+        assert_eq!(next_refund_key_index(&storage).unwrap(), 1);
+        assert_eq!(next_refund_key_index(&storage).unwrap(), 2);
+    }
+}
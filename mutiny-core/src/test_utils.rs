@@ -64,6 +64,20 @@ pub fn sign_nwc_request(nwc: &NostrWalletConnectURI, req: Request) -> Event {
         .unwrap()
 }
 
+pub fn sign_nwc_request_with_pow(nwc: &NostrWalletConnectURI, req: Request, pow: u8) -> Event {
+    let encrypted = encrypt(&nwc.secret, &nwc.public_key, req.as_json()).unwrap();
+    let p_tag = Tag::PublicKey {
+        public_key: nwc.public_key,
+        relay_url: None,
+        alias: None,
+        uppercase: false,
+    };
+
+    EventBuilder::new(Kind::WalletConnectRequest, encrypted, [p_tag])
+        .to_pow_event(&Keys::new(nwc.secret.clone()), pow)
+        .unwrap()
+}
+
 pub(crate) async fn create_mutiny_wallet<S: MutinyStorage>(storage: S) -> MutinyWallet<S> {
     let network = Network::Regtest;
     let xpriv = ExtendedPrivKey::new_master(network, &[0; 32]).unwrap();
@@ -115,6 +129,7 @@ pub(crate) async fn create_node<S: MutinyStorage>(storage: S) -> Node<S> {
             xprivkey,
             storage.clone(),
             network,
+            0,
             esplora.clone(),
             fee_estimator.clone(),
             stop.clone(),
@@ -191,6 +206,211 @@ pub fn create_dummy_invoice_with_payment_hash(
         .unwrap()
 }
 
+/// A transaction with a single output and no inputs, the value makes it unique
+pub fn create_dummy_tx(value: u64) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            value,
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+/// A chain that tests script by hand, see [crate::chain::ChainSource].
+///
+/// Broadcast transactions go in the mempool until a block is mined,
+/// [MockChainSource::reorg] replaces the last blocks and puts their transactions back.
+pub struct MockChainSource {
+    chain: Mutex<MockChain>,
+}
+
+impl Default for MockChainSource {
+    fn default() -> Self {
+        Self {
+            chain: Mutex::new(MockChain::default()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockChain {
+    /// Block hashes and the transactions confirmed in them, index 0 is height 1
+    blocks: Vec<(BlockHash, Vec<Txid>)>,
+    mempool: Vec<Txid>,
+    txs: HashMap<Txid, Transaction>,
+    reject_broadcasts: bool,
+    /// Makes the hashes of replacement blocks differ from the originals
+    nonce: u64,
+}
+
+impl MockChain {
+    fn tip_hash(&self) -> BlockHash {
+        self.blocks
+            .last()
+            .map(|(hash, _)| *hash)
+            .unwrap_or_else(|| genesis_block(Network::Regtest).block_hash())
+    }
+
+    fn mine_block(&mut self) -> BlockHash {
+        self.nonce += 1;
+        let mut bytes = self.tip_hash().to_byte_array().to_vec();
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        let hash = BlockHash::hash(&bytes);
+
+        let txids = std::mem::take(&mut self.mempool);
+        self.blocks.push((hash, txids));
+        hash
+    }
+
+    fn confirmed_height(&self, txid: &Txid) -> Option<u32> {
+        self.blocks
+            .iter()
+            .position(|(_, txids)| txids.contains(txid))
+            .map(|i| i as u32 + 1)
+    }
+}
+
+impl MockChainSource {
+    pub fn height(&self) -> u32 {
+        self.chain.lock().unwrap().blocks.len() as u32
+    }
+
+    /// Mines blocks, the first one confirms everything in the mempool
+    pub fn mine_blocks(&self, count: u32) -> BlockHash {
+        let mut chain = self.chain.lock().unwrap();
+        for _ in 0..count {
+            chain.mine_block();
+        }
+        chain.tip_hash()
+    }
+
+    /// Removes the last `depth` blocks and mines `new_blocks` empty ones in their place,
+    /// the transactions that were in the removed blocks go back in the mempool
+    pub fn reorg(&self, depth: u32, new_blocks: u32) {
+        let mut chain = self.chain.lock().unwrap();
+        let keep = chain.blocks.len().saturating_sub(depth as usize);
+        let removed = chain.blocks.split_off(keep);
+        let unconfirmed = removed.into_iter().flat_map(|(_, txids)| txids);
+        let mut mempool: Vec<Txid> = unconfirmed.collect();
+        mempool.append(&mut chain.mempool);
+
+        for _ in 0..new_blocks {
+            chain.mine_block();
+        }
+        chain.mempool = mempool;
+    }
+
+    /// Adds a transaction to the mempool without going through a broadcast
+    pub fn add_to_mempool(&self, tx: Transaction) {
+        let mut chain = self.chain.lock().unwrap();
+        let txid = tx.txid();
+        chain.txs.insert(txid, tx);
+        if !chain.mempool.contains(&txid) && chain.confirmed_height(&txid).is_none() {
+            chain.mempool.push(txid);
+        }
+    }
+
+    /// Removes an unconfirmed transaction, like the mempool evicting it
+    pub fn evict(&self, txid: &Txid) {
+        let mut chain = self.chain.lock().unwrap();
+        chain.mempool.retain(|t| t != txid);
+        chain.txs.remove(txid);
+    }
+
+    /// Makes every broadcast fail, like esplora rejecting the transactions
+    pub fn set_reject_broadcasts(&self, reject: bool) {
+        self.chain.lock().unwrap().reject_broadcasts = reject;
+    }
+
+    pub fn is_in_mempool(&self, txid: &Txid) -> bool {
+        self.chain.lock().unwrap().mempool.contains(txid)
+    }
+
+    /// Number of confirmations, 0 if it isn't confirmed
+    pub fn confirmations(&self, txid: &Txid) -> u32 {
+        let chain = self.chain.lock().unwrap();
+        chain
+            .confirmed_height(txid)
+            .map(|h| chain.blocks.len() as u32 - h + 1)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl ChainSource for MockChainSource {
+    async fn get_height(&self) -> Result<u32, esplora_client::Error> {
+        Ok(self.height())
+    }
+
+    async fn get_tip_hash(&self) -> Result<BlockHash, esplora_client::Error> {
+        Ok(self.chain.lock().unwrap().tip_hash())
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<BlockHash, esplora_client::Error> {
+        let chain = self.chain.lock().unwrap();
+        match height {
+            0 => Ok(genesis_block(Network::Regtest).block_hash()),
+            h => chain
+                .blocks
+                .get(h as usize - 1)
+                .map(|(hash, _)| *hash)
+                .ok_or(esplora_client::Error::HeaderHeightNotFound(h)),
+        }
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, esplora_client::Error> {
+        Ok(self.chain.lock().unwrap().txs.get(txid).cloned())
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus, esplora_client::Error> {
+        let chain = self.chain.lock().unwrap();
+        let status = match chain.confirmed_height(txid) {
+            Some(height) => TxStatus {
+                confirmed: true,
+                block_height: Some(height),
+                block_hash: Some(chain.blocks[height as usize - 1].0),
+                block_time: Some(now().as_secs()),
+            },
+            None => TxStatus {
+                confirmed: false,
+                block_height: None,
+                block_hash: None,
+                block_time: None,
+            },
+        };
+        Ok(status)
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), esplora_client::Error> {
+        if self.chain.lock().unwrap().reject_broadcasts {
+            return Err(esplora_client::Error::TransactionNotFound(tx.txid()));
+        }
+        self.add_to_mempool(tx.clone());
+        Ok(())
+    }
+
+    async fn get_spending_txid(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, esplora_client::Error> {
+        let chain = self.chain.lock().unwrap();
+        let known = chain
+            .mempool
+            .iter()
+            .chain(chain.blocks.iter().flat_map(|(_, txids)| txids.iter()));
+        let spending = known.filter_map(|txid| chain.txs.get(txid)).find(|tx| {
+            tx.input
+                .iter()
+                .any(|input| input.previous_output == *outpoint)
+        });
+        Ok(spending.map(|tx| tx.txid()))
+    }
+}
+
 #[allow(unused_macros)]
 macro_rules! log {
         ( $( $t:tt )* ) => {
@@ -200,9 +420,14 @@ macro_rules! log {
             println!( $( $t )* );
         }
     }
+use async_trait::async_trait;
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{Secp256k1, SecretKey};
 use bitcoin::{bip32::ExtendedPrivKey, Network};
+use bitcoin::{BlockHash, OutPoint, ScriptBuf, Transaction, TxOut, Txid};
+use esplora_client::TxStatus;
 use lightning::ln::PaymentSecret;
 use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
 use lightning_invoice::{Bolt11Invoice, InvoiceBuilder};
@@ -213,10 +438,12 @@ use nostr::nips::nip04::encrypt;
 use nostr::nips::nip47::*;
 use nostr::prelude::NostrWalletConnectURI;
 use nostr::{Event, EventBuilder, JsonUtil, Keys, Kind, Tag};
+use std::collections::HashMap;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::chain::ChainSource;
 use crate::node::{NetworkGraph, Node, RapidGossipSync};
 use crate::nodemanager::NodeIndex;
 use crate::onchain::{get_esplora_url, OnChainWallet};
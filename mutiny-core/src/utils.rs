@@ -1,7 +1,8 @@
 use crate::error::MutinyError;
 use crate::nostr::NostrKeySource;
 use bitcoin::key::XOnlyPublicKey;
-use bitcoin::Network;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Network, ScriptBuf};
 use core::cell::{RefCell, RefMut};
 use core::ops::{Deref, DerefMut};
 use core::time::Duration;
@@ -10,7 +11,9 @@ use futures::{
     pin_mut,
 };
 use hex_conservative::DisplayHex;
+use lightning::io::Cursor;
 use lightning::routing::scoring::{LockableScore, ScoreLookUp, ScoreUpdate};
+use lightning::util::ser::Readable;
 use lightning::util::ser::Writeable;
 use lightning::util::ser::Writer;
 use lightning_invoice::Bolt11Invoice;
@@ -20,6 +23,8 @@ use reqwest::Client;
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 pub const FETCH_TIMEOUT: i32 = 30_000;
 
@@ -211,6 +216,47 @@ where
     tokio::spawn(future);
 }
 
+/// Runs the task right away and then every `interval_secs` until `stop` is set.
+/// The stop flag is checked every second so stopping doesn't wait for the interval.
+async fn run_periodic<F, Fut>(stop: Arc<AtomicBool>, interval_secs: u64, mut task: F)
+where
+    F: FnMut() -> Fut,
+    Fut: future::Future<Output = ()>,
+{
+    let mut last_run: Option<u64> = None;
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let now = now().as_secs();
+        if last_run.map_or(true, |last| now.saturating_sub(last) >= interval_secs) {
+            task().await;
+            last_run = Some(now);
+        }
+
+        sleep(1_000).await;
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn_periodic<F, Fut>(stop: Arc<AtomicBool>, interval_secs: u64, task: F)
+where
+    F: FnMut() -> Fut + 'static,
+    Fut: future::Future<Output = ()> + 'static,
+{
+    spawn(run_periodic(stop, interval_secs, task));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn_periodic<F, Fut>(stop: Arc<AtomicBool>, interval_secs: u64, task: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: future::Future<Output = ()> + Send + 'static,
+{
+    spawn(run_periodic(stop, interval_secs, task));
+}
+
 pub(crate) fn parse_profile_metadata(data: Vec<Value>) -> HashMap<nostr::PublicKey, Metadata> {
     data.into_iter()
         .filter_map(|v| {
@@ -288,6 +334,23 @@ pub fn get_monitor_version(bytes: &[u8]) -> u64 {
     u64::from_be_bytes(bytes[2..10].try_into().unwrap())
 }
 
+/// Returns the id the channel keys are derived from, from a serialized version
+/// of a channel monitor.
+pub fn get_monitor_channel_keys_id(bytes: &[u8]) -> Option<[u8; 32]> {
+    // skip the version, the version number and the commitment number obscure factor
+    let mut reader = Cursor::new(bytes.get(2 + 8 + 6..)?);
+    let _destination_script: ScriptBuf = Readable::read(&mut reader).ok()?;
+    let has_revokable_script: u8 = Readable::read(&mut reader).ok()?;
+    if has_revokable_script == 0 {
+        let _key: PublicKey = Readable::read(&mut reader).ok()?;
+        let _to_self_delay: u16 = Readable::read(&mut reader).ok()?;
+        let _script: ScriptBuf = Readable::read(&mut reader).ok()?;
+    }
+    let _counterparty_payment_script: ScriptBuf = Readable::read(&mut reader).ok()?;
+    let _shutdown_script: ScriptBuf = Readable::read(&mut reader).ok()?;
+    Readable::read(&mut reader).ok()
+}
+
 /// Nodes that give hodl invoices, we want to warn users against this.
 pub const HODL_INVOICE_NODES: [&str; 5] = [
     "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798", // pubkey of ONE_KEY
@@ -319,3 +382,8 @@ pub fn convert_from_fedimint_invoice(
 ) -> Bolt11Invoice {
     Bolt11Invoice::from_str(&invoice.to_string()).expect("just converting types")
 }
+
+/// Compares secrets without returning early, so the time taken doesn't leak how much matched
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
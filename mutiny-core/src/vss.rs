@@ -1,25 +1,84 @@
 use crate::auth::MutinyAuthClient;
 use crate::encrypt::{decrypt_with_key, encrypt_with_key};
+use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+use crate::logging::LOGGING_KEY;
+use crate::storage::BITCOIN_PRICE_CACHE_KEY;
+use crate::utils::Mutex;
 use crate::{error::MutinyError, logging::MutinyLogger};
-use anyhow::anyhow;
+use async_lock::Mutex as AsyncMutex;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use futures::future::try_join_all;
 use hex_conservative::DisplayHex;
 use lightning::util::logger::*;
-use lightning::{log_error, log_info};
-use reqwest::{Method, Url};
+use lightning::{log_debug, log_error, log_info};
+use prost::Message;
+use reqwest::{Method, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
 
+mod types;
+
+/// Max number of objects sent or fetched at once
+const VSS_BATCH_SIZE: usize = 25;
+
+/// Keys that aren't synced to VSS by default, they are big and not worth the data
+pub const DEFAULT_VSS_EXCLUDED_KEYS: [&str; 2] = [LOGGING_KEY, BITCOIN_PRICE_CACHE_KEY];
+
+/// Object on an LDK VSS server with our version of every key, see [VssProtocol::Ldk]
+const LDK_VERSION_INDEX_KEY: &str = "mutiny_key_versions";
+
+/// Times a write to an LDK VSS server is retried after another device changed the index
+const LDK_CONFLICT_RETRIES: usize = 3;
+
+/// The API the VSS server speaks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VssProtocol {
+    /// Mutiny's storage server, it keeps the versions we give it
+    #[default]
+    Mutiny,
+    /// The open LDK VSS protocol, for self-hosted servers. The server has its own
+    /// versions, so ours are stored with each object and in an index object.
+    Ldk,
+}
+
 pub struct MutinyVssClient {
     auth_client: Option<Arc<MutinyAuthClient>>,
     client: Option<reqwest::Client>,
     url: String,
     store_id: Option<String>,
     encryption_key: SecretKey,
+    /// Static bearer token, for self-hosted servers
+    token: Option<String>,
+    protocol: VssProtocol,
+    /// Cache of the version index on an LDK VSS server, the lock is held while writing
+    ldk_index: AsyncMutex<Option<LdkIndex>>,
+    /// Keys and key prefixes that are never synced, see [MutinyVssClient::with_excluded_keys]
+    excluded_keys: Vec<String>,
+    /// Hash of the last value written or read for each key,
+    /// so values that didn't change aren't written again
+    digests: Mutex<HashMap<String, sha256::Hash>>,
     pub logger: Arc<MutinyLogger>,
 }
 
+/// Our version of every key on an LDK VSS server, and the server's version of the
+/// index object that every write is conditioned on
+#[derive(Debug, Clone, Default)]
+struct LdkIndex {
+    versions: HashMap<String, u32>,
+    server_version: i64,
+}
+
+/// What an LDK VSS server answered, errors other than these are returned as [MutinyError]
+enum LdkResponse {
+    Success(Vec<u8>),
+    NoSuchKey,
+    /// The version of an object we wrote didn't match the server's
+    Conflict,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct KeyVersion {
     pub key: String,
@@ -88,6 +147,11 @@ impl MutinyVssClient {
             url,
             store_id: None, // we get this from the auth client
             encryption_key,
+            token: None,
+            protocol: VssProtocol::Mutiny,
+            ldk_index: AsyncMutex::new(None),
+            excluded_keys: default_excluded_keys(),
+            digests: Mutex::new(HashMap::new()),
             logger,
         }
     }
@@ -98,20 +162,92 @@ impl MutinyVssClient {
         logger: Arc<MutinyLogger>,
     ) -> Self {
         log_info!(logger, "Creating unauthenticated vss client");
-        let pk = encryption_key
-            .public_key(&Secp256k1::new())
-            .serialize()
-            .to_lower_hex_string();
         Self {
             auth_client: None,
             client: Some(reqwest::Client::new()),
             url,
-            store_id: Some(pk),
+            store_id: Some(store_id_from_key(&encryption_key)),
             encryption_key,
+            token: None,
+            protocol: VssProtocol::Mutiny,
+            ldk_index: AsyncMutex::new(None),
+            excluded_keys: default_excluded_keys(),
+            digests: Mutex::new(HashMap::new()),
             logger,
         }
     }
 
+    /// Creates a client for a self-hosted server that takes a static bearer token
+    pub fn new_with_token(
+        url: String,
+        token: String,
+        encryption_key: SecretKey,
+        logger: Arc<MutinyLogger>,
+    ) -> Self {
+        log_info!(logger, "Creating vss client with a static token");
+        Self {
+            token: Some(token),
+            ..Self::new_unauthenticated(url, encryption_key, logger)
+        }
+    }
+
+    /// Sets the API the server speaks, defaults to [VssProtocol::Mutiny].
+    /// With LNURL-auth the JWT from the auth client is sent as a bearer token.
+    pub fn with_protocol(mut self, protocol: VssProtocol) -> Self {
+        if protocol == VssProtocol::Ldk {
+            // LDK servers need the store id and we make the requests ourselves
+            self.store_id = Some(store_id_from_key(&self.encryption_key));
+            self.client.get_or_insert_with(reqwest::Client::new);
+        }
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sets the keys that are never synced, a key is excluded if it starts with any of them.
+    /// Defaults to [DEFAULT_VSS_EXCLUDED_KEYS].
+    ///
+    /// The channel monitors and channel managers can't be excluded,
+    /// without them on VSS a restore would lose the channels.
+    pub fn with_excluded_keys(mut self, excluded_keys: Vec<String>) -> Result<Self, MutinyError> {
+        let protected = [MONITORS_PREFIX_KEY, CHANNEL_MANAGER_KEY];
+        if let Some(key) = excluded_keys.iter().find(|k| {
+            protected
+                .iter()
+                .any(|p| p.starts_with(k.as_str()) || k.starts_with(p))
+        }) {
+            log_error!(
+                self.logger,
+                "Can't exclude {key} from vss, it holds channel state"
+            );
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        self.excluded_keys = excluded_keys;
+        Ok(self)
+    }
+
+    pub fn is_excluded(&self, key: &str) -> bool {
+        self.excluded_keys
+            .iter()
+            .any(|k| key.starts_with(k.as_str()))
+    }
+
+    /// If the value is the last one we wrote or read for the key
+    fn is_unchanged(&self, item: &VssKeyValueItem) -> bool {
+        self.digests
+            .lock()
+            .map(|d| d.get(&item.key) == Some(&value_hash(&item.value)))
+            .unwrap_or(false)
+    }
+
+    fn record_digests<'a>(&self, items: impl Iterator<Item = &'a VssKeyValueItem>) {
+        if let Ok(mut digests) = self.digests.lock() {
+            for item in items {
+                digests.insert(item.key.clone(), value_hash(&item.value));
+            }
+        }
+    }
+
     async fn make_request(
         &self,
         method: Method,
@@ -122,38 +258,277 @@ impl MutinyVssClient {
             (Some(auth), _) => auth.request(method, url, body).await,
             (None, Some(client)) => {
                 let mut request = client.request(method, url);
+                if let Some(token) = self.token.as_ref() {
+                    request = request.bearer_auth(token);
+                }
                 if let Some(body) = body {
                     request = request.json(&body);
                 }
                 request.send().await.map_err(|e| {
                     log_error!(self.logger, "Error making request: {e}");
-                    MutinyError::Other(anyhow!("Error making request: {e}"))
+                    MutinyError::VssError
                 })
             }
             (None, None) => unreachable!("No auth client or http client"),
         }
     }
 
+    fn vss_url(&self, path: &str) -> Result<Url, MutinyError> {
+        Url::parse(&format!("{}/{path}", self.url)).map_err(|e| {
+            log_error!(self.logger, "Error parsing vss url {path}: {e}");
+            MutinyError::InvalidArgumentsError
+        })
+    }
+
+    /// Makes a protobuf request to an LDK VSS server
+    async fn make_ldk_request(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+    ) -> Result<LdkResponse, MutinyError> {
+        let url = self.vss_url(path)?;
+        let Some(client) = self.client.as_ref() else {
+            unreachable!("LDK protocol always has an http client")
+        };
+
+        let mut refresh = false;
+        loop {
+            let mut request = client
+                .post(url.clone())
+                .header("Content-Type", "application/octet-stream")
+                .body(body.clone());
+            if let Some(token) = self.token.as_ref() {
+                request = request.bearer_auth(token);
+            } else if let Some(auth) = self.auth_client.as_ref() {
+                request = request.bearer_auth(auth.get_jwt(refresh).await?);
+            }
+
+            let response = request.send().await.map_err(|e| {
+                log_error!(self.logger, "Error making vss request: {e}");
+                MutinyError::VssError
+            })?;
+
+            match response.status() {
+                // the JWT may have expired, get a new one and try again
+                StatusCode::UNAUTHORIZED if !refresh && self.auth_client.is_some() => {
+                    refresh = true;
+                }
+                status if status.is_success() => {
+                    let bytes = response.bytes().await.map_err(|e| {
+                        log_error!(self.logger, "Error reading vss response: {e}");
+                        MutinyError::VssError
+                    })?;
+                    return Ok(LdkResponse::Success(bytes.to_vec()));
+                }
+                status => {
+                    let error = match response.bytes().await {
+                        Ok(bytes) => types::ErrorResponse::decode(bytes.as_ref()).ok(),
+                        Err(_) => None,
+                    };
+                    let code = error
+                        .as_ref()
+                        .and_then(|e| types::ErrorCode::from_i32(e.error_code));
+                    match (status, code) {
+                        (_, Some(types::ErrorCode::NoSuchKeyException))
+                        | (StatusCode::NOT_FOUND, _) => return Ok(LdkResponse::NoSuchKey),
+                        (_, Some(types::ErrorCode::ConflictException))
+                        | (StatusCode::CONFLICT, _) => return Ok(LdkResponse::Conflict),
+                        _ => {
+                            let message = error.map(|e| e.message).unwrap_or_default();
+                            log_error!(
+                                self.logger,
+                                "VSS request to {path} failed: {status} {message}"
+                            );
+                            return Err(MutinyError::VssError);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn get_ldk_object(&self, key: &str) -> Result<Option<types::KeyValue>, MutinyError> {
+        let request = types::GetObjectRequest {
+            store_id: self.store_id.clone().unwrap_or_default(),
+            key: key.to_string(),
+        };
+        match self
+            .make_ldk_request("getObject", request.encode_to_vec())
+            .await?
+        {
+            LdkResponse::Success(bytes) => {
+                let response = types::GetObjectResponse::decode(bytes.as_slice()).map_err(|e| {
+                    log_error!(self.logger, "Error decoding vss response: {e}");
+                    MutinyError::VssError
+                })?;
+                Ok(response.value)
+            }
+            LdkResponse::NoSuchKey => Ok(None),
+            LdkResponse::Conflict => {
+                log_error!(self.logger, "Unexpected conflict getting vss object");
+                Err(MutinyError::VssError)
+            }
+        }
+    }
+
+    /// Our version of every key on an LDK VSS server, from the cache if we have it
+    async fn load_ldk_index(
+        &self,
+        index: &mut Option<LdkIndex>,
+        refresh: bool,
+    ) -> Result<LdkIndex, MutinyError> {
+        if let Some(index) = index.as_ref().filter(|_| !refresh) {
+            return Ok(index.clone());
+        }
+
+        let loaded = match self.get_ldk_object(LDK_VERSION_INDEX_KEY).await? {
+            Some(kv) => {
+                let decrypted = decrypt_with_key(&self.encryption_key, kv.value)?;
+                LdkIndex {
+                    versions: serde_json::from_slice(&decrypted)?,
+                    server_version: kv.version,
+                }
+            }
+            // a new object is written with version 0
+            None => LdkIndex::default(),
+        };
+        *index = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    /// Writes the items and the version index in one transaction, items
+    /// older than what the server has are skipped like the Mutiny server does.
+    ///
+    /// The index is written with the server's version of it, so the transaction fails
+    /// if another device wrote since we read it. The index is then read again and the
+    /// write retried, instead of overwriting the other device's versions.
+    async fn put_ldk_objects(&self, items: Vec<VssKeyValueItem>) -> Result<(), MutinyError> {
+        let mut cache = self.ldk_index.lock().await;
+        let store_id = self.store_id.clone().unwrap_or_default();
+
+        for chunk in items.chunks(VSS_BATCH_SIZE) {
+            let mut conflicts = 0;
+            loop {
+                let mut index = self.load_ldk_index(&mut cache, conflicts > 0).await?;
+                let chunk = chunk
+                    .iter()
+                    .filter(|item| {
+                        !index
+                            .versions
+                            .get(&item.key)
+                            .is_some_and(|v| *v > item.version)
+                    })
+                    .collect::<Vec<_>>();
+                if chunk.is_empty() {
+                    break;
+                }
+
+                let mut objects = Vec::with_capacity(chunk.len() + 1);
+                for item in chunk.iter() {
+                    index.versions.insert(item.key.clone(), item.version);
+                    // the server doesn't keep our version, so it goes in the value.
+                    // The objects themselves aren't versioned, the index guards them.
+                    let bytes = serde_json::to_vec(item)?;
+                    objects.push(types::KeyValue {
+                        key: item.key.clone(),
+                        version: -1,
+                        value: encrypt_with_key(&self.encryption_key, &bytes),
+                    });
+                }
+                let bytes = serde_json::to_vec(&index.versions)?;
+                objects.push(types::KeyValue {
+                    key: LDK_VERSION_INDEX_KEY.to_string(),
+                    version: index.server_version,
+                    value: encrypt_with_key(&self.encryption_key, &bytes),
+                });
+
+                let request = types::PutObjectRequest {
+                    store_id: store_id.clone(),
+                    global_version: None,
+                    transaction_items: objects,
+                    delete_items: vec![],
+                };
+                match self
+                    .make_ldk_request("putObjects", request.encode_to_vec())
+                    .await?
+                {
+                    LdkResponse::Success(_) => {
+                        index.server_version += 1;
+                        *cache = Some(index);
+                        self.record_digests(chunk.into_iter());
+                        break;
+                    }
+                    LdkResponse::Conflict if conflicts < LDK_CONFLICT_RETRIES => {
+                        log_debug!(
+                            self.logger,
+                            "VSS index changed on the server, retrying the write"
+                        );
+                        conflicts += 1;
+                    }
+                    _ => {
+                        *cache = None;
+                        log_error!(
+                            self.logger,
+                            "Could not write to vss, the index kept changing"
+                        );
+                        return Err(MutinyError::VssError);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the items in batches, skipping excluded keys and values that didn't change
     pub async fn put_objects(&self, items: Vec<VssKeyValueItem>) -> Result<(), MutinyError> {
+        let items = items
+            .into_iter()
+            .filter(|item| !self.is_excluded(&item.key) && !self.is_unchanged(item))
+            .collect::<Vec<_>>();
+        if items.is_empty() {
+            log_debug!(self.logger, "Nothing changed to put in VSS");
+            return Ok(());
+        }
+
+        if self.protocol == VssProtocol::Ldk {
+            return self.put_ldk_objects(items).await;
+        }
+
         let url = Url::parse(&format!("{}/putObjects", self.url)).map_err(|e| {
             log_error!(self.logger, "Error parsing put objects url: {e}");
             MutinyError::InvalidArgumentsError
         })?;
 
-        let items = items
-            .into_iter()
-            .map(|item| item.encrypt(&self.encryption_key))
-            .collect::<Vec<_>>();
+        for chunk in items.chunks(VSS_BATCH_SIZE) {
+            let encrypted = chunk
+                .iter()
+                .map(|item| item.clone().encrypt(&self.encryption_key))
+                .collect::<Vec<_>>();
 
-        // todo do we need global version here?
-        let body = json!({ "store_id": self.store_id, "transaction_items": items });
+            // todo do we need global version here?
+            let body = json!({ "store_id": self.store_id, "transaction_items": encrypted });
 
-        self.make_request(Method::PUT, url, Some(body)).await?;
+            self.make_request(Method::PUT, url.clone(), Some(body))
+                .await?;
+            self.record_digests(chunk.iter());
+        }
 
         Ok(())
     }
 
     pub async fn get_object(&self, key: &str) -> Result<VssKeyValueItem, MutinyError> {
+        if self.protocol == VssProtocol::Ldk {
+            let kv = self
+                .get_ldk_object(key)
+                .await?
+                .ok_or(MutinyError::NotFound)?;
+            let decrypted = decrypt_with_key(&self.encryption_key, kv.value)?;
+            let item: VssKeyValueItem = serde_json::from_slice(&decrypted)?;
+            self.record_digests(std::iter::once(&item));
+            return Ok(item);
+        }
+
         let url = Url::parse(&format!("{}/getObject", self.url)).map_err(|e| {
             log_error!(self.logger, "Error parsing get objects url: {e}");
             MutinyError::InvalidArgumentsError
@@ -168,16 +543,47 @@ impl MutinyVssClient {
             .await
             .map_err(|e| {
                 log_error!(self.logger, "Error parsing get objects response: {e}");
-                MutinyError::Other(anyhow!("Error parsing get objects response: {e}"))
+                MutinyError::VssError
             })?;
 
-        result.decrypt(&self.encryption_key)
+        let item = result.decrypt(&self.encryption_key)?;
+        self.record_digests(std::iter::once(&item));
+        Ok(item)
+    }
+
+    /// Gets the objects [VSS_BATCH_SIZE] at a time. Neither VSS API can read several
+    /// objects in one request, so each chunk is fetched with concurrent single gets,
+    /// a big sync doesn't make hundreds of requests at once on a slow connection.
+    pub async fn get_objects(&self, keys: &[String]) -> Result<Vec<VssKeyValueItem>, MutinyError> {
+        let mut items = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(VSS_BATCH_SIZE) {
+            let res = try_join_all(chunk.iter().map(|key| self.get_object(key))).await?;
+            items.extend(res);
+        }
+
+        Ok(items)
     }
 
     pub async fn list_key_versions(
         &self,
         key_prefix: Option<String>,
     ) -> Result<Vec<KeyVersion>, MutinyError> {
+        if self.protocol == VssProtocol::Ldk {
+            // always refresh, another device may have written since
+            let mut cache = self.ldk_index.lock().await;
+            let index = self.load_ldk_index(&mut cache, true).await?;
+            let versions = index
+                .versions
+                .into_iter()
+                .filter(|(key, _)| match key_prefix.as_ref() {
+                    Some(prefix) => key.starts_with(prefix.as_str()),
+                    None => true,
+                })
+                .map(|(key, version)| KeyVersion { key, version })
+                .collect();
+            return Ok(versions);
+        }
+
         let url = Url::parse(&format!("{}/listKeyVersions", self.url)).map_err(|e| {
             log_error!(self.logger, "Error parsing list key versions url: {e}");
             MutinyError::InvalidArgumentsError
@@ -192,19 +598,110 @@ impl MutinyVssClient {
             .await
             .map_err(|e| {
                 log_error!(self.logger, "Error parsing list key versions response: {e}");
-                MutinyError::Other(anyhow!("Error parsing list key versions response: {e}"))
+                MutinyError::VssError
             })?;
 
         Ok(result)
     }
 }
 
+/// Hash of the value exactly as it is serialized to be encrypted, any change to it
+/// is written, even one that only reorders a list
+fn value_hash(value: &Value) -> sha256::Hash {
+    sha256::Hash::hash(value.to_string().as_bytes())
+}
+
+/// Store id for servers that don't give us one, the pubkey of the encryption key
+fn store_id_from_key(encryption_key: &SecretKey) -> String {
+    encryption_key
+        .public_key(&Secp256k1::new())
+        .serialize()
+        .to_lower_hex_string()
+}
+
+fn default_excluded_keys() -> Vec<String> {
+    DEFAULT_VSS_EXCLUDED_KEYS
+        .iter()
+        .map(|k| k.to_string())
+        .collect()
+}
+
 #[cfg(test)]
 #[cfg(not(target_arch = "wasm32"))]
 mod tests {
     use super::*;
     use crate::test_utils::*;
 
+    #[test]
+    fn test_excluded_keys() {
+        let client = || {
+            MutinyVssClient::new_unauthenticated(
+                "http://localhost:8080".to_string(),
+                SecretKey::from_slice(&[1; 32]).unwrap(),
+                Arc::new(MutinyLogger::default()),
+            )
+        };
+
+        let vss = client()
+            .with_excluded_keys(vec!["logs".to_string()])
+            .unwrap();
+        assert!(vss.is_excluded("logs_1"));
+        assert!(!vss.is_excluded("monitors/abc_0_node"));
+
+        for key in ["monitors/", "monitors/abc", "manager", "m", ""] {
+            let res = client().with_excluded_keys(vec![key.to_string()]);
+            assert!(res.is_err(), "{key} should not be excludable");
+        }
+    }
+
+    #[test]
+    fn test_is_unchanged() {
+        let vss = MutinyVssClient::new_unauthenticated(
+            "http://localhost:8080".to_string(),
+            SecretKey::from_slice(&[1; 32]).unwrap(),
+            Arc::new(MutinyLogger::default()),
+        );
+        let item = VssKeyValueItem {
+            key: "key".to_string(),
+            value: json!([1, 2]),
+            version: 0,
+        };
+        assert!(!vss.is_unchanged(&item));
+        vss.record_digests(std::iter::once(&item));
+        assert!(vss.is_unchanged(&item));
+
+        // a reordered list is a different value
+        let reordered = VssKeyValueItem {
+            value: json!([2, 1]),
+            ..item
+        };
+        assert!(!vss.is_unchanged(&reordered));
+    }
+
+    #[test]
+    fn test_ldk_messages() {
+        let request = types::GetObjectRequest {
+            store_id: "store".to_string(),
+            key: "key".to_string(),
+        };
+        assert_eq!(
+            request.encode_to_vec(),
+            b"\x0a\x05store\x12\x03key".to_vec()
+        );
+
+        let kv = types::KeyValue {
+            key: "key".to_string(),
+            version: 2,
+            value: vec![1, 2, 3],
+        };
+        let response = types::GetObjectResponse {
+            value: Some(kv.clone()),
+        };
+        let decoded = types::GetObjectResponse::decode(response.encode_to_vec().as_slice());
+        assert_eq!(decoded.unwrap().value, Some(kv));
+        assert!(types::GetObjectResponse::decode([0x12, 0x05, 0x01].as_slice()).is_err());
+    }
+
     #[tokio::test]
     async fn test_vss() {
         let client = create_vss_client().await;
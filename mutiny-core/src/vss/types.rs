@@ -0,0 +1,86 @@
+// This file is @generated by prost-build.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetObjectRequest {
+    #[prost(string, tag = "1")]
+    pub store_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetObjectResponse {
+    #[prost(message, optional, tag = "2")]
+    pub value: ::core::option::Option<KeyValue>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PutObjectRequest {
+    #[prost(string, tag = "1")]
+    pub store_id: ::prost::alloc::string::String,
+    #[prost(int64, optional, tag = "2")]
+    pub global_version: ::core::option::Option<i64>,
+    #[prost(message, repeated, tag = "3")]
+    pub transaction_items: ::prost::alloc::vec::Vec<KeyValue>,
+    #[prost(message, repeated, tag = "4")]
+    pub delete_items: ::prost::alloc::vec::Vec<KeyValue>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PutObjectResponse {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ErrorResponse {
+    #[prost(enumeration = "ErrorCode", tag = "1")]
+    pub error_code: i32,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "2")]
+    pub version: i64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub value: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ErrorCode {
+    Unknown = 0,
+    ConflictException = 1,
+    InvalidRequestException = 2,
+    InternalServerException = 3,
+    NoSuchKeyException = 4,
+    AuthException = 5,
+}
+impl ErrorCode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ErrorCode::Unknown => "UNKNOWN",
+            ErrorCode::ConflictException => "CONFLICT_EXCEPTION",
+            ErrorCode::InvalidRequestException => "INVALID_REQUEST_EXCEPTION",
+            ErrorCode::InternalServerException => "INTERNAL_SERVER_EXCEPTION",
+            ErrorCode::NoSuchKeyException => "NO_SUCH_KEY_EXCEPTION",
+            ErrorCode::AuthException => "AUTH_EXCEPTION",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "UNKNOWN" => Some(Self::Unknown),
+            "CONFLICT_EXCEPTION" => Some(Self::ConflictException),
+            "INVALID_REQUEST_EXCEPTION" => Some(Self::InvalidRequestException),
+            "INTERNAL_SERVER_EXCEPTION" => Some(Self::InternalServerException),
+            "NO_SUCH_KEY_EXCEPTION" => Some(Self::NoSuchKeyException),
+            "AUTH_EXCEPTION" => Some(Self::AuthException),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use mutiny_core::error::MutinyError;
+use thiserror::Error;
+
+/// The errors returned to the mobile apps, mapped from [MutinyError].
+///
+/// Only the errors the apps handle get their own variant, everything else
+/// is returned as [MutinyFfiError::Other] with the error message.
+#[derive(Error, Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum MutinyFfiError {
+    /// Returned when trying to start Mutiny while it is already running.
+    #[error("Mutiny is already running.")]
+    AlreadyRunning,
+    /// Returned when trying to make changes in a read-only session.
+    #[error("Cannot make changes in a read-only session.")]
+    ReadOnly,
+    /// Returned on any resource that is not found.
+    #[error("Resource Not found.")]
+    NotFound,
+    /// Invalid Arguments were given
+    #[error("Invalid Arguments were given")]
+    InvalidArgumentsError,
+    /// Incorrect password entered.
+    #[error("Incorrect password entered.")]
+    IncorrectPassword,
+    /// The wallet does not have enough funds.
+    #[error("Insufficient balance.")]
+    InsufficientBalance,
+    /// The invoice or address is for a different network.
+    #[error("Incorrect network.")]
+    IncorrectNetwork,
+    /// Any other error, with its message.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<MutinyError> for MutinyFfiError {
+    fn from(e: MutinyError) -> Self {
+        match e {
+            MutinyError::AlreadyRunning => MutinyFfiError::AlreadyRunning,
+            MutinyError::ReadOnly => MutinyFfiError::ReadOnly,
+            MutinyError::NotFound => MutinyFfiError::NotFound,
+            MutinyError::InvalidArgumentsError => MutinyFfiError::InvalidArgumentsError,
+            MutinyError::IncorrectPassword => MutinyFfiError::IncorrectPassword,
+            MutinyError::InsufficientBalance => MutinyFfiError::InsufficientBalance,
+            MutinyError::IncorrectNetwork => MutinyFfiError::IncorrectNetwork,
+            e => MutinyFfiError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<bip39::Error> for MutinyFfiError {
+    fn from(_: bip39::Error) -> Self {
+        Self::InvalidArgumentsError
+    }
+}
+
+impl From<bitcoin::address::Error> for MutinyFfiError {
+    fn from(e: bitcoin::address::Error) -> Self {
+        MutinyError::from(e).into()
+    }
+}
+
+impl From<lightning_invoice::ParseOrSemanticError> for MutinyFfiError {
+    fn from(_: lightning_invoice::ParseOrSemanticError) -> Self {
+        Self::InvalidArgumentsError
+    }
+}
@@ -0,0 +1,279 @@
+//! uniffi bindings of mutiny-core for the iOS and Android apps,
+//! the native counterpart of mutiny-wasm.
+//!
+//! Generate the Swift and Kotlin bindings with `just bindings-swift` and `just bindings-kotlin`.
+//!
+//! The wallet state is saved to files in the `data_dir` the app gives, with a `storage_url`
+//! it is also backed up to VSS. The app keeps the mnemonic itself, in the keychain or
+//! keystore, and gives it back every time the wallet is opened.
+
+mod error;
+mod models;
+mod storage;
+
+use crate::error::MutinyFfiError;
+use crate::models::*;
+use crate::storage::FileStorage;
+use bip39::Mnemonic;
+use bitcoin::{Address, Network};
+use lightning_invoice::Bolt11Invoice;
+use mutiny_core::encrypt::encryption_key_from_pass;
+use mutiny_core::logging::MutinyLogger;
+use mutiny_core::storage::MutinyStorage;
+use mutiny_core::vss::{MutinyVssClient, VssProtocol};
+use mutiny_core::{generate_seed, xprivkey_from_mnemonic};
+use mutiny_core::{MutinyWalletBuilder, MutinyWalletConfigBuilder};
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+uniffi::setup_scaffolding!();
+
+/// The runtime the wallet and its background tasks run on,
+/// the futures of the foreign languages only wait for the results.
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("mutiny")
+        .build()
+        .expect("failed to build runtime")
+});
+
+/// Runs the future on our runtime, mutiny-core needs to spawn its tasks on tokio
+async fn run<F, T>(future: F) -> Result<T, MutinyFfiError>
+where
+    F: Future<Output = Result<T, MutinyFfiError>> + Send + 'static,
+    T: Send + 'static,
+{
+    RUNTIME
+        .spawn(future)
+        .await
+        .map_err(|e| MutinyFfiError::Other(e.to_string()))?
+}
+
+/// Settings to open the wallet with, the counterpart of the arguments of
+/// the mutiny-wasm `MutinyWallet` constructor.
+#[derive(uniffi::Record)]
+pub struct WalletConfig {
+    /// The mnemonic of the wallet, a new one is generated if none is given
+    pub mnemonic: Option<String>,
+    pub passphrase: Option<String>,
+    /// Password to encrypt the wallet state with
+    pub password: Option<String>,
+    /// Defaults to bitcoin
+    pub network: Option<String>,
+    pub esplora_url: Option<String>,
+    pub rgs_url: Option<String>,
+    pub lsp_url: Option<String>,
+    pub lsp_connection_string: Option<String>,
+    pub lsp_token: Option<String>,
+    /// Directory in the app's private storage to save the wallet in,
+    /// channels can't be used without it surviving restarts
+    pub data_dir: String,
+    /// VSS server to back up the wallet to
+    pub storage_url: Option<String>,
+    pub vss_token: Option<String>,
+    pub self_hosted_vss: bool,
+    pub scorer_url: Option<String>,
+    pub safe_mode: bool,
+    pub skip_device_lock: bool,
+    pub do_not_connect_peers: bool,
+    pub fast_start: bool,
+    pub disable_gossip: bool,
+}
+
+/// Builds the wallet and starts it, fails if it is already running on another device.
+#[uniffi::export]
+pub async fn build_wallet(config: WalletConfig) -> Result<Arc<MutinyWallet>, MutinyFfiError> {
+    run(async move {
+        let wallet = build_wallet_internal(config).await?;
+        Ok(Arc::new(wallet))
+    })
+    .await
+}
+
+async fn build_wallet_internal(config: WalletConfig) -> Result<MutinyWallet, MutinyFfiError> {
+    let logger = Arc::new(MutinyLogger::default());
+
+    let network: Network = match config.network {
+        Some(n) => n
+            .parse()
+            .map_err(|_| MutinyFfiError::InvalidArgumentsError)?,
+        None => Network::Bitcoin,
+    };
+    let mnemonic = match config.mnemonic {
+        Some(m) => Mnemonic::from_str(&m)?,
+        None => generate_seed(12)?,
+    };
+    let xprivkey = xprivkey_from_mnemonic(&mnemonic, config.passphrase.as_deref(), network)?;
+
+    let cipher = config
+        .password
+        .as_ref()
+        .filter(|p| !p.is_empty())
+        .map(|p| encryption_key_from_pass(p))
+        .transpose()?;
+
+    let vss_client = config.storage_url.map(|url| {
+        let key = xprivkey.private_key;
+        let vss = match config.vss_token {
+            Some(token) => MutinyVssClient::new_with_token(url, token, key, logger.clone()),
+            None => MutinyVssClient::new_unauthenticated(url, key, logger.clone()),
+        };
+        match config.self_hosted_vss {
+            true => Arc::new(vss.with_protocol(VssProtocol::Ldk)),
+            false => Arc::new(vss),
+        }
+    });
+
+    let storage = FileStorage::open(
+        config.data_dir,
+        config.password,
+        cipher,
+        vss_client,
+        &logger,
+    )
+    .await?;
+    storage.insert_mnemonic(mnemonic.clone())?;
+
+    let mut config_builder = MutinyWalletConfigBuilder::new(xprivkey).with_network(network);
+    if let Some(url) = config.esplora_url {
+        config_builder.with_user_esplora_url(url);
+    }
+    if let Some(url) = config.rgs_url {
+        config_builder.with_user_rgs_url(url);
+    }
+    if let Some(url) = config.lsp_url {
+        config_builder.with_lsp_url(url);
+    }
+    if let Some(url) = config.lsp_connection_string {
+        config_builder.with_lsp_connection_string(url);
+    }
+    if let Some(token) = config.lsp_token {
+        config_builder.with_lsp_token(token);
+    }
+    if let Some(url) = config.scorer_url {
+        config_builder.with_scorer_url(url);
+    }
+    if config.safe_mode {
+        config_builder.with_safe_mode();
+    }
+    if config.skip_device_lock {
+        config_builder.with_skip_device_lock();
+    }
+    if config.do_not_connect_peers {
+        config_builder.do_not_connect_peers();
+    }
+    if config.fast_start {
+        config_builder.with_fast_start();
+    }
+    if config.disable_gossip {
+        config_builder.with_gossip_disabled();
+    }
+    let wallet_config = config_builder.build();
+
+    let mut mw_builder = MutinyWalletBuilder::new(xprivkey, storage).with_config(wallet_config);
+    mw_builder.with_session_id(logger.session_id.clone());
+    let inner = mw_builder.build().await?;
+
+    Ok(MutinyWallet { mnemonic, inner })
+}
+
+#[derive(uniffi::Object)]
+pub struct MutinyWallet {
+    mnemonic: Mnemonic,
+    inner: mutiny_core::MutinyWallet<FileStorage>,
+}
+
+#[uniffi::export]
+impl MutinyWallet {
+    /// Returns the mnemonic seed phrase for the wallet.
+    pub fn get_mnemonic(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    pub fn get_network(&self) -> String {
+        self.inner.get_network().to_string()
+    }
+
+    /// Gets the current balance of the wallet.
+    /// This includes both on-chain, lightning funds, and federations.
+    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyFfiError> {
+        let inner = self.inner.clone();
+        run(async move { Ok(inner.get_balance().await?.into()) }).await
+    }
+
+    /// Returns a new on-chain address to receive to.
+    pub fn get_new_address(&self, labels: Vec<String>) -> Result<String, MutinyFfiError> {
+        let address = self.inner.node_manager.get_new_address(labels)?;
+        Ok(address.to_string())
+    }
+
+    /// Creates a BIP 21 with a new on-chain address and a lightning invoice
+    /// when an amount is given.
+    pub async fn create_bip21(
+        &self,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyFfiError> {
+        let inner = self.inner.clone();
+        run(async move { Ok(inner.create_bip21(amount_sats, labels).await?.into()) }).await
+    }
+
+    /// Pays a lightning invoice from a federation (preferred) or node.
+    /// An amount should only be provided if the invoice does not have an amount.
+    pub async fn pay_invoice(
+        &self,
+        invoice: String,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyFfiError> {
+        let invoice = Bolt11Invoice::from_str(&invoice)?;
+        let inner = self.inner.clone();
+        run(async move {
+            Ok(inner
+                .pay_invoice(&invoice, amount_sats, labels)
+                .await?
+                .into())
+        })
+        .await
+    }
+
+    /// Sends on-chain from a federation (preferred) or the node,
+    /// the fee rate is in sat/vbyte. Returns the txid.
+    pub async fn send_to_address(
+        &self,
+        address: String,
+        amount_sats: u64,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<String, MutinyFfiError> {
+        let send_to = Address::from_str(&address)?.require_network(self.inner.get_network())?;
+        let inner = self.inner.clone();
+        run(async move {
+            let txid = inner
+                .send_to_address(send_to, amount_sats, labels, fee_rate, None)
+                .await?;
+            Ok(txid.to_string())
+        })
+        .await
+    }
+
+    /// Lists the channels of all the nodes.
+    pub async fn list_channels(&self) -> Result<Vec<MutinyChannel>, MutinyFfiError> {
+        let inner = self.inner.clone();
+        run(async move {
+            let channels = inner.node_manager.list_channels().await?;
+            Ok(channels.into_iter().map(|c| c.into()).collect())
+        })
+        .await
+    }
+
+    /// Stops the wallet, it needs to be built again to be used.
+    pub async fn stop(&self) -> Result<(), MutinyFfiError> {
+        let inner = self.inner.clone();
+        run(async move { Ok(inner.stop().await?) }).await
+    }
+}
@@ -0,0 +1,114 @@
+use mutiny_core::event::HTLCStatus;
+use mutiny_core::nodemanager;
+
+#[derive(uniffi::Record)]
+pub struct MutinyBalance {
+    pub confirmed: u64,
+    pub unconfirmed: u64,
+    pub lightning: u64,
+    pub federation: u64,
+    pub force_close: u64,
+}
+
+impl From<mutiny_core::MutinyBalance> for MutinyBalance {
+    fn from(b: mutiny_core::MutinyBalance) -> Self {
+        MutinyBalance {
+            confirmed: b.confirmed,
+            unconfirmed: b.unconfirmed,
+            lightning: b.lightning,
+            federation: b.federation,
+            force_close: b.force_close,
+        }
+    }
+}
+
+#[derive(uniffi::Record)]
+pub struct MutinyBip21RawMaterials {
+    pub address: String,
+    pub invoice: Option<String>,
+    pub btc_amount: Option<String>,
+    pub labels: Vec<String>,
+}
+
+impl From<nodemanager::MutinyBip21RawMaterials> for MutinyBip21RawMaterials {
+    fn from(m: nodemanager::MutinyBip21RawMaterials) -> Self {
+        MutinyBip21RawMaterials {
+            address: m.address.to_string(),
+            invoice: m.invoice.map(|i| i.to_string()),
+            btc_amount: m.btc_amount,
+            labels: m.labels,
+        }
+    }
+}
+
+#[derive(uniffi::Record)]
+pub struct MutinyInvoice {
+    pub bolt11: Option<String>,
+    pub description: Option<String>,
+    pub payment_hash: String,
+    pub preimage: Option<String>,
+    pub payee_pubkey: Option<String>,
+    pub amount_sats: Option<u64>,
+    pub expire: u64,
+    pub status: String,
+    pub fees_paid: Option<u64>,
+    pub inbound: bool,
+    pub labels: Vec<String>,
+    pub last_updated: u64,
+    pub paid: bool,
+}
+
+impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
+    fn from(m: mutiny_core::MutinyInvoice) -> Self {
+        MutinyInvoice {
+            bolt11: m.bolt11.map(|b| b.to_string()),
+            description: m.description,
+            payment_hash: m.payment_hash.to_string(),
+            preimage: m.preimage,
+            payee_pubkey: m.payee_pubkey.map(|p| p.to_string()),
+            amount_sats: m.amount_sats,
+            expire: m.expire,
+            paid: m.status == HTLCStatus::Succeeded,
+            status: m.status.to_string(),
+            fees_paid: m.fees_paid,
+            inbound: m.inbound,
+            labels: m.labels,
+            last_updated: m.last_updated,
+        }
+    }
+}
+
+#[derive(uniffi::Record)]
+pub struct MutinyChannel {
+    pub user_chan_id: String,
+    pub balance: u64,
+    pub size: u64,
+    pub reserve: u64,
+    pub inbound: u64,
+    pub outpoint: Option<String>,
+    pub peer: String,
+    pub confirmations_required: Option<u32>,
+    pub confirmations: u32,
+    pub is_outbound: bool,
+    pub is_usable: bool,
+    pub is_anchor: bool,
+}
+
+impl From<nodemanager::MutinyChannel> for MutinyChannel {
+    fn from(m: nodemanager::MutinyChannel) -> Self {
+        MutinyChannel {
+            user_chan_id: m.user_chan_id,
+            balance: m.balance,
+            size: m.size,
+            reserve: m.reserve,
+            inbound: m.inbound,
+            outpoint: m.outpoint.map(|o| o.to_string()),
+            peer: m.peer.to_string(),
+            confirmations_required: m.confirmations_required,
+            confirmations: m.confirmations,
+            is_outbound: m.is_outbound,
+            is_usable: m.is_usable,
+            is_anchor: m.is_anchor,
+        }
+    }
+}
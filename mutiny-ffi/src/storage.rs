@@ -0,0 +1,309 @@
+//! On-device storage of the wallet, one file per key in the app's data directory.
+//!
+//! Values are kept in memory like [MemoryStorage] and every write is saved to its file
+//! before returning, so the channel monitors and the rest of the wallet survive a restart.
+
+use async_trait::async_trait;
+use mutiny_core::conflicts::{
+    handle_vss_object, needs_vss_key, SYNC_CONFLICTS_KEY, VSS_SYNC_STATE_KEY,
+};
+use mutiny_core::encrypt::Cipher;
+use mutiny_core::error::{MutinyError, MutinyStorageError};
+use mutiny_core::logging::MutinyLogger;
+use mutiny_core::migrations::run_migrations;
+use mutiny_core::storage::{
+    DelayedKeyValueItem, DeviceLock, IndexItem, MemoryStorage, MutinyStorage, DEVICE_LOCK_KEY,
+};
+use mutiny_core::vss::MutinyVssClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use futures_util::lock::Mutex;
+use lightning::log_info;
+use lightning::util::logger::Logger;
+
+const FILE_EXTENSION: &str = "json";
+const TEMP_FILE_EXTENSION: &str = "tmp";
+
+fn io_err(e: std::io::Error) -> MutinyError {
+    MutinyError::PersistenceFailed {
+        source: MutinyStorageError::Other(e.into()),
+    }
+}
+
+/// Keys can have any character, file names are the hex of the key
+fn file_name(key: &str) -> String {
+    let hex: String = key.bytes().map(|b| format!("{b:02x}")).collect();
+    format!("{hex}.{FILE_EXTENSION}")
+}
+
+fn key_from_path(path: &Path) -> Option<String> {
+    if path.extension()? != FILE_EXTENSION {
+        return None;
+    }
+    let hex = path.file_stem()?.to_str()?;
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[derive(Clone)]
+pub struct FileStorage {
+    inner: MemoryStorage,
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    /// Opens the storage in the directory, creating it if needed, and loads the wallet
+    /// saved in it. With VSS, the keys that changed there since are loaded on top.
+    pub async fn open(
+        dir: impl Into<PathBuf>,
+        password: Option<String>,
+        cipher: Option<Cipher>,
+        vss_client: Option<Arc<MutinyVssClient>>,
+        logger: &MutinyLogger,
+    ) -> Result<Self, MutinyError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(io_err)?;
+
+        let inner = MemoryStorage::new(password, cipher, vss_client);
+        let mut values = HashMap::new();
+        for entry in fs::read_dir(&dir).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+            // leftovers of a write that didn't finish are skipped
+            let Some(key) = key_from_path(&path) else {
+                continue;
+            };
+            let bytes = fs::read(&path).map_err(io_err)?;
+            let value: Value = serde_json::from_slice(&bytes)?;
+            values.insert(key, value);
+        }
+        inner
+            .memory
+            .try_write()
+            .map_err(|e| MutinyError::write_err(e.into()))?
+            .extend(values);
+
+        let storage = Self { inner, dir };
+        storage.load_from_vss(logger).await?;
+        Ok(storage)
+    }
+
+    /// Takes what changed on VSS since we last had it, the same way as the browser storage
+    async fn load_from_vss(&self, logger: &MutinyLogger) -> Result<(), MutinyError> {
+        let Some(vss) = self.inner.vss_client() else {
+            return Ok(());
+        };
+
+        let mut to_fetch = vec![];
+        for kv in vss.list_key_versions(None).await? {
+            if needs_vss_key(&kv, &vss, &self.inner, logger)? {
+                to_fetch.push(kv.key);
+            }
+        }
+
+        let mut items = vec![];
+        for obj in vss.get_objects(&to_fetch).await? {
+            if let Some((key, value)) = handle_vss_object(obj, &self.inner)? {
+                self.inner.set_data(key.clone(), value, None)?;
+                items.push(key);
+            }
+        }
+        // reconciling keys without a version updates these in memory
+        items.extend([
+            VSS_SYNC_STATE_KEY.to_string(),
+            SYNC_CONFLICTS_KEY.to_string(),
+        ]);
+        // the device lock is only saved to VSS
+        items.retain(|k| k != DEVICE_LOCK_KEY);
+
+        for key in items {
+            if let Some(value) = self.inner.get::<Value>(&key)? {
+                self.write_file(&key, &value)?;
+            }
+        }
+        log_info!(logger, "Loaded {} keys from vss", to_fetch.len());
+
+        Ok(())
+    }
+
+    /// Writes to a temporary file that replaces the value's file once it's synced,
+    /// so a crash never leaves half of a value behind
+    fn write_file(&self, key: &str, value: &Value) -> Result<(), MutinyError> {
+        let path = self.dir.join(file_name(key));
+        let temp = path.with_extension(TEMP_FILE_EXTENSION);
+
+        let mut file = fs::File::create(&temp).map_err(io_err)?;
+        file.write_all(&serde_json::to_vec(value)?)
+            .map_err(io_err)?;
+        file.sync_all().map_err(io_err)?;
+        fs::rename(&temp, &path).map_err(io_err)
+    }
+}
+
+#[async_trait]
+impl MutinyStorage for FileStorage {
+    fn password(&self) -> Option<&str> {
+        self.inner.password()
+    }
+
+    fn cipher(&self) -> Option<Cipher> {
+        self.inner.cipher()
+    }
+
+    fn vss_client(&self) -> Option<Arc<MutinyVssClient>> {
+        self.inner.vss_client()
+    }
+
+    fn activity_index(&self) -> Arc<RwLock<BTreeSet<IndexItem>>> {
+        self.inner.activity_index()
+    }
+
+    fn set_read_only(&self) {
+        self.inner.set_read_only()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn set(&self, items: Vec<(String, impl Serialize)>) -> Result<(), MutinyError> {
+        self.check_writable()?;
+        for (key, value) in items {
+            let value =
+                serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
+                    source: MutinyStorageError::SerdeError { source: e },
+                })?;
+            self.write_file(&key, &value)?;
+            self.inner.set(vec![(key, value)])?;
+        }
+
+        Ok(())
+    }
+
+    fn get<T>(&self, key: impl AsRef<str>) -> Result<Option<T>, MutinyError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.inner.get(key)
+    }
+
+    fn delete(&self, keys: &[impl AsRef<str>]) -> Result<(), MutinyError> {
+        self.check_writable()?;
+        for key in keys {
+            match fs::remove_file(self.dir.join(file_name(key.as_ref()))) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(io_err(e)),
+                _ => {}
+            }
+        }
+        self.inner.delete(keys)
+    }
+
+    async fn start(&mut self) -> Result<(), MutinyError> {
+        run_migrations(self)?;
+        Ok(())
+    }
+
+    fn stop(&self) {}
+
+    fn connected(&self) -> Result<bool, MutinyError> {
+        Ok(true)
+    }
+
+    fn scan_keys(&self, prefix: &str, suffix: Option<&str>) -> Result<Vec<String>, MutinyError> {
+        self.inner.scan_keys(prefix, suffix)
+    }
+
+    fn change_password(
+        &mut self,
+        new: Option<String>,
+        new_cipher: Option<Cipher>,
+    ) -> Result<(), MutinyError> {
+        self.inner.change_password(new, new_cipher)
+    }
+
+    /// Not supported, the storage doesn't know its directory without an instance
+    async fn import(_json: Value) -> Result<(), MutinyError> {
+        Err(MutinyError::InvalidArgumentsError)
+    }
+
+    /// Not supported, delete the data directory instead
+    async fn clear() -> Result<(), MutinyError> {
+        Err(MutinyError::InvalidArgumentsError)
+    }
+
+    async fn fetch_device_lock(&self) -> Result<Option<DeviceLock>, MutinyError> {
+        match self.vss_client() {
+            Some(vss) => match vss.get_object(DEVICE_LOCK_KEY).await {
+                Ok(lock) => Ok(Some(serde_json::from_value(lock.value)?)),
+                Err(MutinyError::NotFound) => Ok(None),
+                Err(e) => Err(e),
+            },
+            None => self.get_device_lock(),
+        }
+    }
+
+    fn get_delayed_objects(&self) -> Arc<Mutex<HashMap<String, DelayedKeyValueItem>>> {
+        self.inner.get_delayed_objects()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mutiny-ffi-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_file_name() {
+        let key = "monitors/abc_0_node";
+        assert_eq!(
+            key_from_path(&PathBuf::from(file_name(key))),
+            Some(key.to_string())
+        );
+        assert_eq!(key_from_path(&PathBuf::from("6d.tmp")), None);
+    }
+
+    #[tokio::test]
+    async fn test_values_survive_restart() {
+        let dir = temp_dir("restart");
+        let logger = MutinyLogger::default();
+
+        let storage = FileStorage::open(&dir, None, None, None, &logger)
+            .await
+            .unwrap();
+        storage
+            .set_data("monitors/a".to_string(), vec![1u8, 2, 3], Some(1))
+            .unwrap();
+        storage.set_data("b".to_string(), "b", None).unwrap();
+        storage.set_data("c".to_string(), "c", None).unwrap();
+        storage.delete(&["c"]).unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(&dir, None, None, None, &logger)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_data::<Vec<u8>>("monitors/a").unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            storage.get_data::<String>("b").unwrap(),
+            Some("b".to_string())
+        );
+        assert_eq!(storage.get_data::<String>("c").unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
+use gloo_utils::format::JsValueSerdeExt;
 use lightning_invoice::ParseOrSemanticError;
 use log::error;
 use mutiny_core::error::{MutinyError, MutinyStorageError};
+use serde::Serialize;
 use thiserror::Error;
 use wasm_bindgen::JsValue;
 
@@ -12,6 +14,9 @@ pub enum MutinyJsError {
     /// Returned when trying to stop Mutiny while it is not running.
     #[error("Mutiny is not running.")]
     NotRunning,
+    /// Returned when trying to make changes in a read-only session.
+    #[error("Cannot make changes in a read-only session.")]
+    ReadOnly,
     /// Returned when Mutiny tries to startup with a different network than the one it was
     /// previously running on.
     #[error("Incorrect expected network.")]
@@ -31,6 +36,12 @@ pub enum MutinyJsError {
     /// Payment of the given invoice has already been initiated.
     #[error("An invoice must not get payed twice.")]
     NonUniquePaymentHash,
+    /// A payment with the same idempotency key has not finished yet.
+    #[error("A payment with this idempotency key is already in progress.")]
+    PaymentInProgress,
+    /// The idempotency key was already used for a different payment.
+    #[error("This idempotency key was used for a different payment.")]
+    IdempotencyKeyReused,
     /// Payment Timed out
     #[error("Payment timed out.")]
     PaymentTimeout,
@@ -114,6 +125,21 @@ pub enum MutinyJsError {
     /// A chain access operation failed.
     #[error("Failed to conduct chain access operation.")]
     ChainAccessFailed,
+    /// A transaction did not get the confirmations we waited for in time.
+    #[error("Timed out waiting for the transaction to confirm.")]
+    ConfirmationTimeout,
+    /// The transaction could not be broadcast.
+    #[error("Failed to broadcast transaction.")]
+    BroadcastFailed,
+    /// The coinjoin peer did not respond or sent something we can't use.
+    #[error("Failed to complete the coinjoin.")]
+    CoinjoinFailed,
+    /// A request to the VSS server failed.
+    #[error("Failed to make a request to VSS.")]
+    VssError,
+    /// The storage was migrated by a newer version of the wallet.
+    #[error("The storage is from a newer version of the wallet.")]
+    StorageVersionTooNew,
     /// A failure to sync the on-chain wallet
     #[error("Failed to to sync on-chain wallet.")]
     WalletSyncError,
@@ -150,6 +176,9 @@ pub enum MutinyJsError {
     /// Incorrect password entered.
     #[error("Incorrect password entered.")]
     IncorrectPassword,
+    /// The BIP-39 passphrase doesn't match the wallet in storage.
+    #[error("Incorrect passphrase entered.")]
+    IncorrectPassphrase,
     /// Cannot change password to the same password
     #[error("Cannot change password to the same password.")]
     SamePassword,
@@ -180,9 +209,302 @@ pub enum MutinyJsError {
     /// Fedimint transaction too large
     #[error("Error constructing fedimint transaction, try lowering the amount.")]
     FederationTxTooLarge,
+    /// Paying an invoice without an amount requires confirmation.
+    #[error("Paying an invoice without an amount must be confirmed.")]
+    AmountlessInvoiceNotConfirmed,
+    /// The amount is over the maximum allowed for an invoice without an amount.
+    #[error("Amount is over the maximum allowed for an invoice without an amount.")]
+    AmountlessInvoiceTooLarge,
+    /// Invoices without an amount can only be paid to contacts.
+    #[error("Invoices without an amount can only be paid to contacts.")]
+    AmountlessInvoiceNotAllowed,
+    /// The LSP is paused for maintenance by the service operator.
+    #[error("The LSP is paused for maintenance, try again later.")]
+    LspMaintenance,
+    /// Failed to make a request to the swap provider.
+    #[error("Failed to make a request to the swap provider.")]
+    SwapProviderError,
+    /// The swap costs more than the maximum fee given.
+    #[error("The swap fee is higher than the maximum fee.")]
+    SwapFeeTooHigh,
+    /// The payment is over a limit of the spending policy.
+    #[error("The payment is over the spending limit.")]
+    SpendingLimitExceeded,
+    /// The spending policy only allows payments to whitelisted destinations.
+    #[error("The destination is not on the spending whitelist.")]
+    DestinationNotAllowed,
+    /// The spending policy requires the PIN for payments of this size.
+    #[error("The spending PIN is required for this payment.")]
+    SpendingPinRequired,
+    /// The spending PIN given is wrong.
+    #[error("Incorrect spending PIN.")]
+    IncorrectSpendingPin,
+    /// Too many wrong spending PINs were given, the next one can only be tried later.
+    #[error("Too many incorrect spending PINs, try again later.")]
+    SpendingPinLocked,
+    /// The invoice request is over a limit of the receive limits.
+    #[error("The invoice request is over the receive limit.")]
+    ReceiveLimitExceeded,
+    /// The receive limits don't allow the requester to create invoices.
+    #[error("The requester is not allowed to create invoices.")]
+    RequesterDenied,
+    /// The scoped handle does not have permission to call this function
+    #[error("Permission denied.")]
+    PermissionDenied,
     /// Unknown error.
     #[error("Unknown Error")]
     UnknownError,
+    /// Unknown error, with what went wrong as its context.
+    #[error("Unknown Error")]
+    Other(String),
+}
+
+/// The part of the wallet an error comes from
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorSubsystem {
+    Wallet,
+    Storage,
+    Lightning,
+    Lsp,
+    Onchain,
+    Lnurl,
+    Nostr,
+    Price,
+    Dlc,
+    Payjoin,
+    Ecash,
+    Swap,
+    SpendingPolicy,
+    Subscription,
+    Input,
+    Other,
+}
+
+/// What the frontend gets for a [MutinyJsError], as a JSON object
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ErrorPayload {
+    /// Stable snake_case name of the error, like `insufficient_balance`
+    pub code: String,
+    pub subsystem: ErrorSubsystem,
+    /// If trying the same thing again later could work
+    pub retryable: bool,
+    pub message: String,
+    /// More detail on what went wrong, when we have it
+    pub context: Option<String>,
+}
+
+impl MutinyJsError {
+    /// Machine readable name of the error, the variant name in snake_case.
+    /// These are matched on by the frontend, so they can't change.
+    pub fn code(&self) -> &'static str {
+        use MutinyJsError::*;
+        match self {
+            AlreadyRunning => "already_running",
+            NotRunning => "not_running",
+            ReadOnly => "read_only",
+            NetworkMismatch => "network_mismatch",
+            NotFound => "not_found",
+            FundingTxCreationFailed => "funding_tx_creation_failed",
+            ConnectionFailed => "connection_failed",
+            IncorrectNetwork => "incorrect_network",
+            NonUniquePaymentHash => "non_unique_payment_hash",
+            PaymentInProgress => "payment_in_progress",
+            IdempotencyKeyReused => "idempotency_key_reused",
+            PaymentTimeout => "payment_timeout",
+            InvoiceInvalid => "invoice_invalid",
+            InvoiceExpired => "invoice_expired",
+            InvoiceCreationFailed => "invoice_creation_failed",
+            ReserveAmountError => "reserve_amount_error",
+            InsufficientBalance => "insufficient_balance",
+            LnUrlFailure => "ln_url_failure",
+            LspGenericError => "lsp_generic_error",
+            LspFundingError => "lsp_funding_error",
+            LspAmountTooHighError => "lsp_amount_too_high_error",
+            LspConnectionError => "lsp_connection_error",
+            LspInvoiceRequired => "lsp_invoice_required",
+            SubscriptionClientNotConfigured => "subscription_client_not_configured",
+            InvalidParameter => "invalid_parameter",
+            IncorrectLnUrlFunction => "incorrect_ln_url_function",
+            RoutingFailed => "routing_failed",
+            PeerInfoParseFailed => "peer_info_parse_failed",
+            ChannelCreationFailed => "channel_creation_failed",
+            ChannelCreationFailedWithReason(_) => "channel_creation_failed_with_reason",
+            ChannelClosingFailed => "channel_closing_failed",
+            PersistenceFailed => "persistence_failed",
+            ReadError => "read_error",
+            LnDecodeError => "ln_decode_error",
+            SeedGenerationFailed => "seed_generation_failed",
+            InvalidMnemonic => "invalid_mnemonic",
+            WalletOperationFailed => "wallet_operation_failed",
+            WalletSigningFailed => "wallet_signing_failed",
+            ChainAccessFailed => "chain_access_failed",
+            ConfirmationTimeout => "confirmation_timeout",
+            BroadcastFailed => "broadcast_failed",
+            CoinjoinFailed => "coinjoin_failed",
+            VssError => "vss_error",
+            StorageVersionTooNew => "storage_version_too_new",
+            WalletSyncError => "wallet_sync_error",
+            RapidGossipSyncError => "rapid_gossip_sync_error",
+            JsonReadWriteError => "json_read_write_error",
+            PubkeyInvalid => "pubkey_invalid",
+            NostrError => "nostr_error",
+            Nip07Extension => "nip07_extension",
+            BitcoinPriceError => "bitcoin_price_error",
+            BadAmountError => "bad_amount_error",
+            DLCManagerError => "dlc_manager_error",
+            WasmBindgenError => "wasm_bindgen_error",
+            InvalidArgumentsError => "invalid_arguments_error",
+            IncorrectPassword => "incorrect_password",
+            IncorrectPassphrase => "incorrect_passphrase",
+            SamePassword => "same_password",
+            PayjoinCreateRequest => "payjoin_create_request",
+            PayjoinResponse(_) => "payjoin_response",
+            PayjoinConfigError => "payjoin_config_error",
+            CashuMintError => "cashu_mint_error",
+            EmptyMintURLError => "empty_mint_url_error",
+            TokenAlreadySpent => "token_already_spent",
+            FederationRequired => "federation_required",
+            FederationConnectionFailed => "federation_connection_failed",
+            FederationTxTooLarge => "federation_tx_too_large",
+            AmountlessInvoiceNotConfirmed => "amountless_invoice_not_confirmed",
+            AmountlessInvoiceTooLarge => "amountless_invoice_too_large",
+            AmountlessInvoiceNotAllowed => "amountless_invoice_not_allowed",
+            LspMaintenance => "lsp_maintenance",
+            SwapProviderError => "swap_provider_error",
+            SwapFeeTooHigh => "swap_fee_too_high",
+            SpendingLimitExceeded => "spending_limit_exceeded",
+            DestinationNotAllowed => "destination_not_allowed",
+            SpendingPinRequired => "spending_pin_required",
+            IncorrectSpendingPin => "incorrect_spending_pin",
+            SpendingPinLocked => "spending_pin_locked",
+            ReceiveLimitExceeded => "receive_limit_exceeded",
+            RequesterDenied => "requester_denied",
+            PermissionDenied => "permission_denied",
+            UnknownError => "unknown_error",
+            Other(_) => "other",
+        }
+    }
+
+    pub fn subsystem(&self) -> ErrorSubsystem {
+        use MutinyJsError::*;
+        match self {
+            AlreadyRunning | NotRunning | ReadOnly | NetworkMismatch | NotFound
+            | SeedGenerationFailed | InvalidMnemonic | IncorrectPassword | IncorrectPassphrase
+            | SamePassword | PermissionDenied => ErrorSubsystem::Wallet,
+            PersistenceFailed | ReadError | JsonReadWriteError | VssError
+            | StorageVersionTooNew => ErrorSubsystem::Storage,
+            FundingTxCreationFailed
+            | ConnectionFailed
+            | NonUniquePaymentHash
+            | PaymentInProgress
+            | IdempotencyKeyReused
+            | PaymentTimeout
+            | InvoiceInvalid
+            | InvoiceExpired
+            | InvoiceCreationFailed
+            | ReserveAmountError
+            | RoutingFailed
+            | PeerInfoParseFailed
+            | ChannelCreationFailed
+            | ChannelCreationFailedWithReason(_)
+            | ChannelClosingFailed
+            | LnDecodeError
+            | PubkeyInvalid
+            | RapidGossipSyncError
+            | AmountlessInvoiceNotConfirmed
+            | AmountlessInvoiceTooLarge
+            | AmountlessInvoiceNotAllowed
+            | ReceiveLimitExceeded
+            | RequesterDenied => ErrorSubsystem::Lightning,
+            LspGenericError
+            | LspFundingError
+            | LspAmountTooHighError
+            | LspConnectionError
+            | LspInvoiceRequired
+            | LspMaintenance => ErrorSubsystem::Lsp,
+            IncorrectNetwork
+            | InsufficientBalance
+            | WalletOperationFailed
+            | WalletSigningFailed
+            | ChainAccessFailed
+            | ConfirmationTimeout
+            | BroadcastFailed
+            | CoinjoinFailed
+            | WalletSyncError => ErrorSubsystem::Onchain,
+            LnUrlFailure | IncorrectLnUrlFunction => ErrorSubsystem::Lnurl,
+            NostrError | Nip07Extension => ErrorSubsystem::Nostr,
+            BitcoinPriceError => ErrorSubsystem::Price,
+            DLCManagerError => ErrorSubsystem::Dlc,
+            PayjoinCreateRequest | PayjoinResponse(_) | PayjoinConfigError => {
+                ErrorSubsystem::Payjoin
+            }
+            CashuMintError
+            | EmptyMintURLError
+            | TokenAlreadySpent
+            | FederationRequired
+            | FederationConnectionFailed
+            | FederationTxTooLarge => ErrorSubsystem::Ecash,
+            SwapProviderError | SwapFeeTooHigh => ErrorSubsystem::Swap,
+            SpendingLimitExceeded
+            | DestinationNotAllowed
+            | SpendingPinRequired
+            | IncorrectSpendingPin
+            | SpendingPinLocked => ErrorSubsystem::SpendingPolicy,
+            SubscriptionClientNotConfigured => ErrorSubsystem::Subscription,
+            InvalidParameter | InvalidArgumentsError | BadAmountError => ErrorSubsystem::Input,
+            WasmBindgenError | UnknownError | Other(_) => ErrorSubsystem::Other,
+        }
+    }
+
+    /// If trying the same thing again later could work, like after a network hiccup
+    pub fn is_retryable(&self) -> bool {
+        use MutinyJsError::*;
+        matches!(
+            self,
+            ConnectionFailed
+                | PaymentInProgress
+                | PaymentTimeout
+                | RoutingFailed
+                | PersistenceFailed
+                | ReadError
+                | LnUrlFailure
+                | LspGenericError
+                | LspConnectionError
+                | LspMaintenance
+                | ChainAccessFailed
+                | ConfirmationTimeout
+                | BroadcastFailed
+                | CoinjoinFailed
+                | VssError
+                | WalletSyncError
+                | RapidGossipSyncError
+                | NostrError
+                | BitcoinPriceError
+                | CashuMintError
+                | FederationConnectionFailed
+                | SwapProviderError
+        )
+    }
+
+    pub fn context(&self) -> Option<String> {
+        match self {
+            MutinyJsError::ChannelCreationFailedWithReason(reason) => Some(reason.clone()),
+            MutinyJsError::PayjoinResponse(reason) => Some(reason.clone()),
+            MutinyJsError::Other(reason) => Some(reason.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn payload(&self) -> ErrorPayload {
+        ErrorPayload {
+            code: self.code().to_string(),
+            subsystem: self.subsystem(),
+            retryable: self.is_retryable(),
+            message: self.to_string(),
+            context: self.context(),
+        }
+    }
 }
 
 impl From<MutinyError> for MutinyJsError {
@@ -190,11 +512,14 @@ impl From<MutinyError> for MutinyJsError {
         match e {
             MutinyError::AlreadyRunning => MutinyJsError::AlreadyRunning,
             MutinyError::NotRunning => MutinyJsError::NotRunning,
+            MutinyError::ReadOnly => MutinyJsError::ReadOnly,
             MutinyError::NotFound => MutinyJsError::NotFound,
             MutinyError::FundingTxCreationFailed => MutinyJsError::FundingTxCreationFailed,
             MutinyError::ConnectionFailed => MutinyJsError::ConnectionFailed,
             MutinyError::IncorrectNetwork => MutinyJsError::IncorrectNetwork,
             MutinyError::NonUniquePaymentHash => MutinyJsError::NonUniquePaymentHash,
+            MutinyError::PaymentInProgress => MutinyJsError::PaymentInProgress,
+            MutinyError::IdempotencyKeyReused => MutinyJsError::IdempotencyKeyReused,
             MutinyError::PaymentTimeout => MutinyJsError::PaymentTimeout,
             MutinyError::InvoiceInvalid => MutinyJsError::InvoiceInvalid,
             MutinyError::InvoiceExpired => MutinyJsError::InvoiceExpired,
@@ -221,6 +546,11 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::InvalidMnemonic => MutinyJsError::InvalidMnemonic,
             MutinyError::WalletSigningFailed => MutinyJsError::WalletSigningFailed,
             MutinyError::ChainAccessFailed => MutinyJsError::ChainAccessFailed,
+            MutinyError::ConfirmationTimeout => MutinyJsError::ConfirmationTimeout,
+            MutinyError::BroadcastFailed => MutinyJsError::BroadcastFailed,
+            MutinyError::CoinjoinFailed => MutinyJsError::CoinjoinFailed,
+            MutinyError::VssError => MutinyJsError::VssError,
+            MutinyError::StorageVersionTooNew => MutinyJsError::StorageVersionTooNew,
             MutinyError::WalletSyncError => MutinyJsError::WalletSyncError,
             MutinyError::RapidGossipSyncError => MutinyJsError::RapidGossipSyncError,
             MutinyError::DLCManagerError => MutinyJsError::DLCManagerError,
@@ -231,6 +561,7 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::Nip07Extension => MutinyJsError::Nip07Extension,
             MutinyError::BitcoinPriceError => MutinyJsError::BitcoinPriceError,
             MutinyError::IncorrectPassword => MutinyJsError::IncorrectPassword,
+            MutinyError::IncorrectPassphrase => MutinyJsError::IncorrectPassphrase,
             MutinyError::SamePassword => MutinyJsError::SamePassword,
             MutinyError::CashuMintError => MutinyJsError::CashuMintError,
             MutinyError::EmptyMintURLError => MutinyJsError::EmptyMintURLError,
@@ -238,7 +569,22 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::FederationRequired => MutinyJsError::FederationRequired,
             MutinyError::FederationConnectionFailed => MutinyJsError::FederationConnectionFailed,
             MutinyError::FederationTxTooLarge => MutinyJsError::FederationTxTooLarge,
-            MutinyError::Other(_) => MutinyJsError::UnknownError,
+            MutinyError::AmountlessInvoiceNotConfirmed => {
+                MutinyJsError::AmountlessInvoiceNotConfirmed
+            }
+            MutinyError::AmountlessInvoiceTooLarge => MutinyJsError::AmountlessInvoiceTooLarge,
+            MutinyError::AmountlessInvoiceNotAllowed => MutinyJsError::AmountlessInvoiceNotAllowed,
+            MutinyError::LspMaintenance => MutinyJsError::LspMaintenance,
+            MutinyError::SwapProviderError => MutinyJsError::SwapProviderError,
+            MutinyError::SwapFeeTooHigh => MutinyJsError::SwapFeeTooHigh,
+            MutinyError::SpendingLimitExceeded => MutinyJsError::SpendingLimitExceeded,
+            MutinyError::DestinationNotAllowed => MutinyJsError::DestinationNotAllowed,
+            MutinyError::SpendingPinRequired => MutinyJsError::SpendingPinRequired,
+            MutinyError::IncorrectSpendingPin => MutinyJsError::IncorrectSpendingPin,
+            MutinyError::SpendingPinLocked => MutinyJsError::SpendingPinLocked,
+            MutinyError::ReceiveLimitExceeded => MutinyJsError::ReceiveLimitExceeded,
+            MutinyError::RequesterDenied => MutinyJsError::RequesterDenied,
+            MutinyError::Other(e) => MutinyJsError::Other(format!("{e:#}")),
             MutinyError::SubscriptionClientNotConfigured => {
                 MutinyJsError::SubscriptionClientNotConfigured
             }
@@ -318,8 +664,101 @@ impl From<moksha_core::error::MokshaCoreError> for MutinyJsError {
     }
 }
 
+/// Errors are thrown as a JS `Error` with the [ErrorPayload] set on it, so `error.message`
+/// and `error.toString()` keep working and `error.code` tells the errors apart.
+///
+/// Errors used to be thrown as just the message string,
+/// code that compared the thrown value to a message needs to use `error.message` now.
 impl From<MutinyJsError> for JsValue {
     fn from(e: MutinyJsError) -> Self {
-        JsValue::from(e.to_string())
+        let payload = e.payload();
+        let error = js_sys::Error::new(&payload.message);
+        let properties = [
+            ("code", JsValue::from_str(&payload.code)),
+            (
+                "subsystem",
+                JsValue::from_serde(&payload.subsystem).unwrap_or(JsValue::NULL),
+            ),
+            ("retryable", JsValue::from_bool(payload.retryable)),
+            (
+                "context",
+                payload.context.map_or(JsValue::NULL, JsValue::from),
+            ),
+        ];
+        for (key, value) in properties {
+            // only fails if the object is frozen, a new error isn't
+            let _ = js_sys::Reflect::set(&error, &JsValue::from_str(key), &value);
+        }
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::JsCast;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_error_payload() {
+        let payload = MutinyJsError::from(MutinyError::InsufficientBalance).payload();
+        assert_eq!(payload.code, "insufficient_balance");
+        assert_eq!(payload.subsystem, ErrorSubsystem::Onchain);
+        assert!(!payload.retryable);
+        assert_eq!(payload.context, None);
+
+        let payload = MutinyJsError::from(MutinyError::ConnectionFailed).payload();
+        assert!(payload.retryable);
+
+        // unknown errors keep what went wrong
+        let err = MutinyError::Other(anyhow::anyhow!("coinjoin peer did not respond"));
+        let payload = MutinyJsError::from(err).payload();
+        assert_eq!(payload.code, "other");
+        assert_eq!(payload.message, "Unknown Error");
+        assert_eq!(
+            payload.context.as_deref(),
+            Some("coinjoin peer did not respond")
+        );
+
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["subsystem"], "other");
+    }
+
+    #[test]
+    fn test_thrown_error() {
+        let value = JsValue::from(MutinyJsError::from(MutinyError::IdempotencyKeyReused));
+        let error = value.dyn_ref::<js_sys::Error>().unwrap();
+        assert_eq!(
+            String::from(error.message()),
+            "This idempotency key was used for a different payment."
+        );
+        let get = |key: &str| js_sys::Reflect::get(error, &JsValue::from_str(key)).unwrap();
+        assert_eq!(get("code").as_string().unwrap(), "idempotency_key_reused");
+        assert_eq!(get("subsystem").as_string().unwrap(), "lightning");
+        assert_eq!(get("retryable").as_bool(), Some(false));
+        assert!(get("context").is_null());
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(MutinyJsError::DLCManagerError.code(), "dlc_manager_error");
+        assert_eq!(
+            MutinyJsError::EmptyMintURLError.code(),
+            "empty_mint_url_error"
+        );
+        assert_eq!(MutinyJsError::Nip07Extension.code(), "nip07_extension");
+        assert_eq!(MutinyJsError::LnUrlFailure.code(), "ln_url_failure");
+        assert_eq!(
+            MutinyJsError::PayjoinResponse("nope".to_string()).code(),
+            "payjoin_response"
+        );
+        assert_eq!(
+            MutinyJsError::IdempotencyKeyReused.code(),
+            "idempotency_key_reused"
+        );
+        assert_eq!(MutinyJsError::VssError.code(), "vss_error");
     }
 }
@@ -6,8 +6,12 @@ use gloo_utils::format::JsValueSerdeExt;
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error, log_info, log_trace};
 use log::error;
-use mutiny_core::blindauth::TokenStorage;
+use mutiny_core::conflicts::{
+    handle_vss_object, needs_vss_key, SYNC_CONFLICTS_KEY, VSS_SYNC_STATE_KEY,
+};
+use mutiny_core::logging::MutinyLogger;
 use mutiny_core::logging::LOGGING_KEY;
+use mutiny_core::migrations::run_migrations;
 use mutiny_core::storage::*;
 use mutiny_core::vss::*;
 use mutiny_core::*;
@@ -15,18 +19,21 @@ use mutiny_core::{
     encrypt::Cipher,
     error::{MutinyError, MutinyStorageError},
 };
-use mutiny_core::{federation::FederationStorage, logging::MutinyLogger};
-use mutiny_core::{federation::FEDIMINTS_PREFIX_KEY, nodemanager::NodeStorage};
-use rexie::{ObjectStore, Rexie, TransactionMode};
+use rexie::{ObjectStore, Rexie, Store, TransactionMode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::spawn_local;
 
 pub(crate) const WALLET_DATABASE_NAME: &str = "wallet";
 pub(crate) const WALLET_OBJECT_STORE_NAME: &str = "wallet_store";
+/// How long we wait before retrying writes that failed to save, doubled on every failure
+const WRITE_RETRY_INITIAL_MS: i32 = 500;
+/// Longest we wait between retries of writes that failed to save
+const WRITE_RETRY_MAX_MS: i32 = 30_000;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RexieContainer(Option<Rexie>);
@@ -35,6 +42,105 @@ pub struct RexieContainer(Option<Rexie>);
 unsafe impl Send for RexieContainer {}
 unsafe impl Sync for RexieContainer {}
 
+/// A write to indexed db that is waiting in the [WriteQueue]
+#[derive(Debug, Clone, PartialEq)]
+enum WriteOp {
+    Put(String, Value),
+    Delete(String),
+}
+
+/// Writes to indexed db in the order they were made.
+///
+/// Writes are queued and whoever holds the writer lock takes everything that is queued
+/// and saves it in a single transaction, so a burst of writes only needs one transaction
+/// and a later write to a key can never be overwritten by an earlier one.
+#[derive(Default)]
+pub(crate) struct WriteQueue {
+    pending: std::sync::Mutex<Vec<WriteOp>>,
+    writer: Mutex<()>,
+    /// If a task is already retrying writes that failed to save
+    retrying: AtomicBool,
+}
+
+impl WriteQueue {
+    fn push(&self, ops: impl IntoIterator<Item = WriteOp>) {
+        self.pending.lock().unwrap().extend(ops);
+    }
+
+    /// Removes a write that is still queued, returns if it was
+    fn remove(&self, op: &WriteOp) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.iter().position(|o| o == op) {
+            Some(index) => {
+                pending.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Saves everything that is queued, returns how many writes were saved.
+    /// If saving fails the writes stay queued to be tried again.
+    async fn write_pending(
+        &self,
+        indexed_db: &Arc<RwLock<RexieContainer>>,
+    ) -> Result<usize, MutinyError> {
+        let _guard = self.writer.lock().await;
+        let ops = std::mem::take(&mut *self.pending.lock().unwrap());
+        if ops.is_empty() {
+            return Ok(0);
+        }
+
+        if let Err(e) = IndexedDbStorage::write_to_indexed_db(indexed_db, &ops).await {
+            // put them back in front of the writes that were queued since
+            let mut pending = self.pending.lock().unwrap();
+            pending.splice(0..0, ops);
+            return Err(e);
+        }
+
+        Ok(ops.len())
+    }
+
+    /// Saves everything that is queued in the background,
+    /// retrying with backoff until it is saved or indexed db is closed
+    fn spawn_write(
+        self: Arc<Self>,
+        indexed_db: Arc<RwLock<RexieContainer>>,
+        logger: Arc<MutinyLogger>,
+    ) {
+        spawn_local(async move {
+            let Err(mut error) = self.write_pending(&indexed_db).await else {
+                return;
+            };
+            // one task retries at a time, it saves whatever was queued since too
+            if self.retrying.swap(true, Ordering::Relaxed) {
+                return;
+            }
+
+            let mut delay = WRITE_RETRY_INITIAL_MS;
+            loop {
+                // the lock is only held for writing while indexed db is being closed or reopened
+                if indexed_db.try_read().map_or(false, |db| db.0.is_none()) {
+                    log_error!(logger, "Indexed db closed with unsaved writes: {error}");
+                    break;
+                }
+                log_error!(
+                    logger,
+                    "Failed to save writes to indexed db, retrying in {delay}ms: {error}"
+                );
+                utils::sleep(delay).await;
+                delay = (delay * 2).min(WRITE_RETRY_MAX_MS);
+
+                match self.write_pending(&indexed_db).await {
+                    Ok(_) => break,
+                    Err(e) => error = e,
+                }
+            }
+            self.retrying.store(false, Ordering::Relaxed);
+        });
+    }
+}
+
 #[derive(Clone)]
 pub struct IndexedDbStorage {
     pub(crate) password: Option<String>,
@@ -48,6 +154,8 @@ pub struct IndexedDbStorage {
     logger: Arc<MutinyLogger>,
     delayed_keys: Arc<Mutex<HashMap<String, DelayedKeyValueItem>>>,
     activity_index: Arc<RwLock<BTreeSet<IndexItem>>>,
+    read_only: Arc<AtomicBool>,
+    write_queue: Arc<WriteQueue>,
 }
 
 impl IndexedDbStorage {
@@ -60,9 +168,11 @@ impl IndexedDbStorage {
         let idx = Self::build_indexed_db_database().await?;
         let indexed_db = Arc::new(RwLock::new(RexieContainer(Some(idx))));
         let password = password.filter(|p| !p.is_empty());
+        let write_queue = Arc::new(WriteQueue::default());
 
         let map = Self::read_all(
             &indexed_db,
+            &write_queue,
             password.clone(),
             cipher.clone(),
             vss.as_deref(),
@@ -80,6 +190,8 @@ impl IndexedDbStorage {
             logger,
             delayed_keys: Arc::new(Mutex::new(HashMap::new())),
             activity_index: Arc::new(RwLock::new(BTreeSet::new())),
+            read_only: Arc::new(AtomicBool::new(false)),
+            write_queue,
         })
     }
 
@@ -194,42 +306,48 @@ impl IndexedDbStorage {
         Ok(result)
     }
 
-    async fn save_to_indexed_db(
+    /// Saves the writes in a single transaction, in the order they were made
+    async fn write_to_indexed_db(
         indexed_db: &Arc<RwLock<RexieContainer>>,
-        items: &[(String, Value)],
+        ops: &[WriteOp],
     ) -> Result<(), MutinyError> {
-        // Device lock is only saved to VSS
-        if items.len() == 1 && items.iter().all(|(k, _)| k == DEVICE_LOCK_KEY) {
-            return Ok(());
-        }
-
         let tx = indexed_db
             .try_write()
-            .map_err(|e| MutinyError::read_err(e.into()))
+            .map_err(|e| {
+                error!("Failed to acquire indexed db lock: {e}");
+                MutinyError::read_err(e.into())
+            })
             .and_then(|indexed_db_lock| {
                 if let Some(indexed_db) = &indexed_db_lock.0 {
                     indexed_db
                         .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadWrite)
                         .map_err(|e| {
+                            error!("Failed to create indexed db transaction: {e}");
                             MutinyError::read_err(
                                 anyhow!("Failed to create indexed db transaction: {e}").into(),
                             )
                         })
                 } else {
+                    error!("No indexed db instance found");
                     Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
                 }
             })?;
 
         let store = tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
-            MutinyError::read_err(anyhow!("Failed to create indexed db store: {e}").into())
+            error!("Failed to create indexed db store: {e}");
+            MutinyError::read_err(anyhow!("Failed to create indexed db store {e}").into())
         })?;
 
-        // save to indexed db
-        for (key, data) in items {
-            store
-                .put(&JsValue::from_serde(&data)?, Some(&JsValue::from(key)))
-                .await
-                .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
+        for op in ops {
+            let res = match op {
+                WriteOp::Put(key, data) => {
+                    store
+                        .put(&JsValue::from_serde(&data)?, Some(&JsValue::from(key)))
+                        .await
+                }
+                WriteOp::Delete(key) => store.delete(&JsValue::from(key)).await,
+            };
+            res.map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
         }
 
         tx.done()
@@ -239,80 +357,45 @@ impl IndexedDbStorage {
         Ok(())
     }
 
-    async fn delete_from_indexed_db(
-        indexed_db: &Arc<RwLock<RexieContainer>>,
-        keys: &[String],
-    ) -> Result<(), MutinyError> {
+    /// Queues the writes and saves them in the background, see [WriteQueue]
+    fn queue_writes(&self, ops: Vec<WriteOp>) {
+        self.write_queue.push(ops);
+        self.write_queue
+            .clone()
+            .spawn_write(self.indexed_db.clone(), self.logger.clone());
+    }
+
+    fn read_only_store(indexed_db: &Arc<RwLock<RexieContainer>>) -> Result<Store, MutinyError> {
         let tx = indexed_db
-            .try_write()
-            .map_err(|e| {
-                error!("Failed to acquire indexed db lock: {e}");
-                MutinyError::read_err(e.into())
-            })
+            .try_read()
+            .map_err(|e| MutinyError::read_err(e.into()))
             .and_then(|indexed_db_lock| {
                 if let Some(indexed_db) = &indexed_db_lock.0 {
                     indexed_db
-                        .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadWrite)
+                        .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadOnly)
                         .map_err(|e| {
-                            error!("Failed to create indexed db transaction: {e}");
                             MutinyError::read_err(
                                 anyhow!("Failed to create indexed db transaction: {e}").into(),
                             )
                         })
                 } else {
-                    error!("No indexed db instance found");
                     Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
                 }
             })?;
-
-        let store = tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
-            error!("Failed to create indexed db store: {e}");
+        tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
             MutinyError::read_err(anyhow!("Failed to create indexed db store {e}").into())
-        })?;
-
-        // delete from indexed db
-        for key in keys {
-            store
-                .delete(&JsValue::from(key))
-                .await
-                .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
-        }
-
-        tx.done()
-            .await
-            .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
-
-        Ok(())
+        })
     }
 
     pub(crate) async fn read_all(
         indexed_db: &Arc<RwLock<RexieContainer>>,
+        write_queue: &WriteQueue,
         password: Option<String>,
         cipher: Option<Cipher>,
         vss: Option<&MutinyVssClient>,
         logger: &MutinyLogger,
     ) -> Result<HashMap<String, Value>, MutinyError> {
-        let store = {
-            let tx = indexed_db
-                .try_read()
-                .map_err(|e| MutinyError::read_err(e.into()))
-                .and_then(|indexed_db_lock| {
-                    if let Some(indexed_db) = &indexed_db_lock.0 {
-                        indexed_db
-                            .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadOnly)
-                            .map_err(|e| {
-                                MutinyError::read_err(
-                                    anyhow!("Failed to create indexed db transaction: {e}").into(),
-                                )
-                            })
-                    } else {
-                        Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
-                    }
-                })?;
-            tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
-                MutinyError::read_err(anyhow!("Failed to create indexed db store {e}").into())
-            })?
-        };
+        let store = Self::read_only_store(indexed_db)?;
 
         let start = instant::Instant::now();
         // use a memory storage to handle encryption and decryption
@@ -329,8 +412,8 @@ impl IndexedDbStorage {
                     "key from indexedDB is not a string"
                 ))))?;
 
-            // we no longer need to read this key,
-            // so we can remove it from memory
+            // we no longer need to read this key, the network graph
+            // is now stored per network, so we can remove it from memory
             if key == NETWORK_GRAPH_KEY {
                 continue;
             }
@@ -354,21 +437,44 @@ impl IndexedDbStorage {
                 log_info!(logger, "Reading from vss");
                 let start = instant::Instant::now();
                 let keys = vss.list_key_versions(None).await?;
-                let mut futs = Vec::with_capacity(keys.len());
+
+                // only fetch what changed since we last had it
+                let mut to_fetch = Vec::with_capacity(keys.len());
                 for kv in keys {
-                    futs.push(Self::handle_vss_key(kv, vss, &map, logger));
+                    log_debug!(
+                        logger,
+                        "Found vss key {} with version {}",
+                        kv.key,
+                        kv.version
+                    );
+                    if needs_vss_key(&kv, vss, &map, logger)? {
+                        to_fetch.push(kv.key);
+                    }
                 }
-                let results = futures::future::try_join_all(futs).await?;
-
-                let mut items_vector = Vec::with_capacity(results.len());
-                for (key, value) in results.into_iter().flatten() {
-                    // save to memory and batch the write to local storage
-                    map.set_data(key.clone(), value.clone(), None)?;
-                    items_vector.push((key, value));
+                log_debug!(logger, "Fetching {} keys from vss", to_fetch.len());
+                let objects = vss.get_objects(&to_fetch).await?;
+
+                let mut items_vector = Vec::with_capacity(objects.len());
+                for obj in objects {
+                    if let Some((key, value)) = handle_vss_object(obj, &map)? {
+                        // save to memory and batch the write to local storage
+                        map.set_data(key.clone(), value.clone(), None)?;
+                        items_vector.push((key, value));
+                    }
                 }
+                // reconciling keys without a version updates these in memory
+                for key in [VSS_SYNC_STATE_KEY, SYNC_CONFLICTS_KEY] {
+                    if let Some(value) = map.get::<Value>(key)? {
+                        items_vector.push((key.to_string(), value));
+                    }
+                }
+                // Device lock is only saved to VSS
+                items_vector.retain(|(k, _)| k != DEVICE_LOCK_KEY);
                 if !items_vector.is_empty() {
-                    // write them so we don't have to pull them down again
-                    Self::save_to_indexed_db(indexed_db, &items_vector).await?;
+                    // write them so we don't have to pull them down again,
+                    // through the queue so they are ordered with any other writes
+                    write_queue.push(items_vector.into_iter().map(|(k, v)| WriteOp::Put(k, v)));
+                    write_queue.write_pending(indexed_db).await?;
                 }
                 let final_map = map.memory.read().unwrap();
 
@@ -379,189 +485,6 @@ impl IndexedDbStorage {
         }
     }
 
-    async fn handle_vss_key(
-        kv: KeyVersion,
-        vss: &MutinyVssClient,
-        current: &MemoryStorage,
-        logger: &MutinyLogger,
-    ) -> Result<Option<(String, Value)>, MutinyError> {
-        log_debug!(
-            logger,
-            "Found vss key {} with version {}",
-            kv.key,
-            kv.version
-        );
-
-        match kv.key.as_str() {
-            NODES_KEY => {
-                // we can get version from node storage, so we should compare
-                match current.get_data::<NodeStorage>(&kv.key)? {
-                    Some(local) => {
-                        if local.version < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<NodeStorage>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            FEDERATIONS_KEY => {
-                // we can get version from federation storage, so we should compare
-                match current.get_data::<FederationStorage>(&kv.key)? {
-                    Some(local) => {
-                        if local.version < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<FederationStorage>(obj.value.clone())
-                                .is_ok()
-                            {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            DEVICE_LOCK_KEY => {
-                // we can get version from device lock, so we should compare
-                match current.get_data::<DeviceLock>(&kv.key)? {
-                    Some(lock) => {
-                        // we use time as version for device lock
-                        if lock.time < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<DeviceLock>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            SERVICE_TOKENS => {
-                // we can get version from TokenStorage, so we should compare
-                match current.get_data::<TokenStorage>(&kv.key)? {
-                    Some(token_storage) => {
-                        if token_storage.version < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<TokenStorage>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            key => {
-                if key.starts_with(MONITORS_PREFIX_KEY) {
-                    // we can get versions from monitors, so we should compare
-                    match current.get::<Vec<u8>>(&kv.key)? {
-                        Some(bytes) => {
-                            let current_version = utils::get_monitor_version(&bytes);
-
-                            // if the current version is less than the version from vss, then we want to use the vss version
-                            if current_version < kv.version as u64 {
-                                let obj = vss.get_object(&kv.key).await?;
-                                return Ok(Some((kv.key, obj.value)));
-                            } else {
-                                log_debug!(
-                                    logger,
-                                    "Skipping vss key {} with version {}, current version is {current_version}",
-                                    kv.key,
-                                    kv.version
-                                );
-                                return Ok(None);
-                            }
-                        }
-                        None => {
-                            let obj = vss.get_object(&kv.key).await?;
-                            return Ok(Some((kv.key, obj.value)));
-                        }
-                    }
-                } else if key.starts_with(CHANNEL_MANAGER_KEY) {
-                    // we can get versions from channel manager, so we should compare
-                    match current.get_data::<VersionedValue>(&kv.key)? {
-                        Some(local) => {
-                            if local.version < kv.version {
-                                let obj = vss.get_object(&kv.key).await?;
-                                if serde_json::from_value::<VersionedValue>(obj.value.clone())
-                                    .is_ok()
-                                {
-                                    return Ok(Some((kv.key, obj.value)));
-                                }
-                            } else {
-                                log_debug!(
-                                    logger,
-                                    "Skipping vss key {} with version {}, current version is {}",
-                                    kv.key,
-                                    kv.version,
-                                    local.version
-                                );
-                                return Ok(None);
-                            }
-                        }
-                        None => {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                } else if key.starts_with(FEDIMINTS_PREFIX_KEY) {
-                    // we can get versions from each fedimint, so we should compare
-                    match current.get_data::<VersionedValue>(&kv.key)? {
-                        Some(local) => {
-                            if local.version < kv.version {
-                                let obj = vss.get_object(&kv.key).await?;
-                                if serde_json::from_value::<VersionedValue>(obj.value.clone())
-                                    .is_ok()
-                                {
-                                    return Ok(Some((kv.key, obj.value)));
-                                }
-                            } else {
-                                log_debug!(
-                                    logger,
-                                    "Skipping vss key {} with version {}, current version is {}",
-                                    kv.key,
-                                    kv.version,
-                                    local.version
-                                );
-                                return Ok(None);
-                            }
-                        }
-                        None => {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        log_debug!(
-            logger,
-            "Skipping vss key {} with version {}",
-            kv.key,
-            kv.version
-        );
-
-        Ok(None)
-    }
-
     async fn build_indexed_db_database() -> Result<Rexie, MutinyError> {
         let rexie = Rexie::builder(WALLET_DATABASE_NAME)
             .version(1)
@@ -579,6 +502,7 @@ impl IndexedDbStorage {
     pub(crate) async fn reload_from_indexed_db(&self) -> Result<(), MutinyError> {
         let map = Self::read_all(
             &self.indexed_db,
+            &self.write_queue,
             self.password.clone(),
             self.cipher.clone(),
             self.vss.as_deref(),
@@ -603,6 +527,8 @@ fn used_once(key: &str) -> bool {
         NETWORK_GRAPH_KEY | PROB_SCORER_KEY | GOSSIP_SYNC_TIME_KEY | BITCOIN_PRICE_CACHE_KEY => {
             true
         }
+        str if str.starts_with(NETWORK_GRAPH_KEY) => true,
+        str if str.starts_with(GOSSIP_SYNC_TIME_KEY) => true,
         str if str.starts_with(MONITORS_PREFIX_KEY) => true,
         str if str.starts_with(CHANNEL_MANAGER_KEY) => true,
         _ => false,
@@ -627,7 +553,16 @@ impl MutinyStorage for IndexedDbStorage {
         self.activity_index.clone()
     }
 
+    fn set_read_only(&self) {
+        self.read_only.store(true, Ordering::Relaxed);
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
     fn set(&self, items: Vec<(String, impl Serialize)>) -> Result<(), MutinyError> {
+        self.check_writable()?;
         let items = items
             .into_iter()
             .map(|(k, v)| {
@@ -639,17 +574,14 @@ impl MutinyStorage for IndexedDbStorage {
             })
             .collect::<Result<Vec<(String, Value)>, MutinyError>>()?;
 
-        let indexed_db = self.indexed_db.clone();
-        let items_clone = items.clone();
-        let logger = self.logger.clone();
-        spawn_local(async move {
-            if let Err(e) = Self::save_to_indexed_db(&indexed_db, &items_clone).await {
-                log_error!(
-                    logger,
-                    "Failed to save ({items_clone:?}) to indexed db: {e}"
-                );
-            };
-        });
+        // Device lock is only saved to VSS
+        if !(items.len() == 1 && items[0].0 == DEVICE_LOCK_KEY) {
+            let ops = items
+                .iter()
+                .map(|(k, d)| WriteOp::Put(k.clone(), d.clone()))
+                .collect();
+            self.queue_writes(ops);
+        }
 
         // some values only are read once, so we don't need to write them to memory,
         // just need them in indexed db for next time
@@ -670,11 +602,26 @@ impl MutinyStorage for IndexedDbStorage {
     where
         T: Serialize,
     {
+        self.check_writable()?;
         let data = serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
             source: MutinyStorageError::SerdeError { source: e },
         })?;
 
-        Self::save_to_indexed_db(&self.indexed_db, &[(key.clone(), data.clone())]).await?;
+        // queued behind the other writes so it can't be overwritten by an older one
+        if key != DEVICE_LOCK_KEY {
+            let op = WriteOp::Put(key.clone(), data.clone());
+            self.write_queue.push([op.clone()]);
+            // once we have the writer, ours was either saved or put back in the queue,
+            // so this saves it without waiting for the writes queued after it
+            if let Err(e) = self.write_queue.write_pending(&self.indexed_db).await {
+                // the caller gets the error for ours, the others are retried
+                self.write_queue.remove(&op);
+                self.write_queue
+                    .clone()
+                    .spawn_write(self.indexed_db.clone(), self.logger.clone());
+                return Err(e);
+            }
+        }
 
         // some values only are read once, so we don't need to write them to memory,
         // just need them in indexed db for next time
@@ -721,19 +668,10 @@ impl MutinyStorage for IndexedDbStorage {
     }
 
     fn delete(&self, keys: &[impl AsRef<str>]) -> Result<(), MutinyError> {
+        self.check_writable()?;
         let keys: Vec<String> = keys.iter().map(|k| k.as_ref().to_string()).collect();
 
-        let indexed_db = self.indexed_db.clone();
-        let keys_clone = keys.clone();
-        let logger = self.logger.clone();
-        spawn_local(async move {
-            if let Err(e) = Self::delete_from_indexed_db(&indexed_db, &keys_clone).await {
-                log_error!(
-                    logger,
-                    "Failed to delete ({keys_clone:?}) from indexed db: {e}"
-                );
-            }
-        });
+        self.queue_writes(keys.iter().cloned().map(WriteOp::Delete).collect());
 
         let mut map = self
             .memory
@@ -758,6 +696,7 @@ impl MutinyStorage for IndexedDbStorage {
 
         let map = Self::read_all(
             &indexed_db,
+            &self.write_queue,
             self.password.clone(),
             self.cipher.clone(),
             self.vss.as_deref(),
@@ -767,6 +706,22 @@ impl MutinyStorage for IndexedDbStorage {
         let memory = Arc::new(RwLock::new(map));
         self.indexed_db = indexed_db;
         self.memory = memory;
+
+        let report = run_migrations(self)?;
+        if !report.migrations.is_empty() {
+            log_info!(
+                self.logger,
+                "Migrated storage from schema version {} to {}",
+                report.from_version,
+                report.to_version
+            );
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), MutinyError> {
+        // writes made while we were saving are saved in the next transaction
+        while self.write_queue.write_pending(&self.indexed_db).await? > 0 {}
         Ok(())
     }
 
@@ -782,6 +737,27 @@ impl MutinyStorage for IndexedDbStorage {
         Ok(self.indexed_db.try_read()?.0.is_some())
     }
 
+    /// Reads from indexed db, values that are used once aren't kept in memory
+    async fn scan_persisted(&self, prefix: &str) -> Result<HashMap<String, Value>, MutinyError> {
+        self.flush().await?;
+        let store = Self::read_only_store(&self.indexed_db)?;
+        let all_json = store.get_all(None, None, None, None).await.map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to get all from store: {e}").into())
+        })?;
+
+        let mut map = HashMap::new();
+        for (key, value) in all_json {
+            match key.as_string() {
+                Some(key) if key.starts_with(prefix) => {
+                    map.insert(key, value.into_serde()?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(map)
+    }
+
     fn scan_keys(&self, prefix: &str, suffix: Option<&str>) -> Result<Vec<String>, MutinyError> {
         let map = self
             .memory
@@ -955,6 +931,53 @@ mod tests {
         IndexedDbStorage::clear().await.unwrap();
     }
 
+    #[test]
+    async fn test_flush_keeps_write_order() {
+        let test_name = "test_flush_keeps_write_order";
+        log!("{test_name}");
+
+        let logger = Arc::new(MutinyLogger::default());
+        let storage = IndexedDbStorage::new(None, None, None, logger)
+            .await
+            .unwrap();
+
+        // a burst of writes to the same keys, the last one has to win
+        for i in 0..10 {
+            storage
+                .set(vec![
+                    ("test_key".to_string(), i),
+                    ("test_key2".to_string(), i),
+                ])
+                .unwrap();
+        }
+        storage.delete(&["test_key2"]).unwrap();
+        storage.set_async("test_key3".to_string(), 1).await.unwrap();
+        storage.flush().await.unwrap();
+
+        storage.reload_from_indexed_db().await.unwrap();
+        let result: Option<i32> = storage.get("test_key").unwrap();
+        assert_eq!(result, Some(9));
+        let result: Option<i32> = storage.get("test_key2").unwrap();
+        assert_eq!(result, None);
+        let result: Option<i32> = storage.get("test_key3").unwrap();
+        assert_eq!(result, Some(1));
+
+        // clear the storage to clean up
+        IndexedDbStorage::clear().await.unwrap();
+    }
+
+    #[test]
+    fn test_write_queue_remove() {
+        let queue = WriteQueue::default();
+        let first = WriteOp::Put("test_key".to_string(), json!(1));
+        let second = WriteOp::Put("test_key".to_string(), json!(2));
+        queue.push([first.clone(), second.clone()]);
+
+        assert!(queue.remove(&first));
+        assert!(!queue.remove(&first));
+        assert_eq!(*queue.pending.lock().unwrap(), vec![second]);
+    }
+
     #[test]
     async fn test_import() {
         let test_name = "test_import";
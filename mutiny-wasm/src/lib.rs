@@ -10,12 +10,16 @@ extern crate mutiny_core;
 pub mod error;
 mod indexed_db;
 mod models;
+mod nip07;
+mod scoped;
 mod utils;
 pub mod waila;
 
 use crate::error::MutinyJsError;
 use crate::indexed_db::IndexedDbStorage;
 use crate::models::*;
+use crate::nip07::Nip07Signer;
+use crate::scoped::ScopedWallet;
 use bip39::Mnemonic;
 use bitcoin::bip32::ExtendedPrivKey;
 use bitcoin::hashes::hex::FromHex;
@@ -26,30 +30,45 @@ use fedimint_core::{api::InviteCode, config::FederationId};
 use futures::lock::Mutex;
 use gloo_utils::format::JsValueSerdeExt;
 use hex_conservative::DisplayHex;
+use lightning::ln::ChannelId;
 use lightning::{log_error, log_info, log_warn, routing::gossip::NodeId, util::logger::Logger};
 use lightning_invoice::Bolt11Invoice;
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
 use moksha_core::token::TokenV3;
+use mutiny_core::audit::RepairOptions;
 use mutiny_core::auth::MutinyAuthClient;
+use mutiny_core::backup::{BackupCredentials, HttpBackupTarget, RemoteBackupConfig};
+use mutiny_core::compaction::CompactionPolicy;
+use mutiny_core::encrypt::Cipher;
+use mutiny_core::error::MutinyError;
+use mutiny_core::federation::FederationRoutingPolicy;
+use mutiny_core::labels::ContactGroup;
 use mutiny_core::lnurlauth::AuthManager;
 use mutiny_core::nostr::nip49::NIP49URI;
 use mutiny_core::nostr::nwc::{BudgetedSpendingConditions, NwcProfileTag, SpendingConditions};
-use mutiny_core::nostr::NostrKeySource;
-use mutiny_core::storage::{DeviceLock, MutinyStorage, DEVICE_LOCK_KEY};
+use mutiny_core::nostr::{connect_remote_signer, NostrKeySource};
+use mutiny_core::receivelimits::ReceiveLimits;
+use mutiny_core::split::SplitDirection;
+use mutiny_core::storage::{
+    get_duress_mnemonic, DeviceLock, MutinyStorage, NamespacedStorage, DEVICE_LOCK_KEY,
+};
 use mutiny_core::utils::{now, parse_npub, parse_npub_or_nip05, sleep, spawn};
-use mutiny_core::vss::MutinyVssClient;
+use mutiny_core::vss::{MutinyVssClient, VssProtocol};
 use mutiny_core::{
-    encrypt::encryption_key_from_pass, InvoiceHandler, MutinyWalletConfigBuilder, PrivacyLevel,
+    encrypt::encryption_key_from_pass, export::ExportFormat, policy::SpendingPolicy,
+    stats::SpendingPeriod, xprivkey_from_mnemonic, ActivityFilter, ActivityKind, InvoiceHandler,
+    InvoiceParams, MutinyWalletConfigBuilder, PayInvoiceOptions, PowerMode, PrivacyLevel,
+    RouteHintConfig,
 };
 use mutiny_core::{labels::Contact, MutinyWalletBuilder};
 use mutiny_core::{
     labels::LabelStorage,
-    nodemanager::{create_lsp_config, NodeManager},
+    nodemanager::{create_lsp_config, ChannelPolicy, NodeManager},
 };
 use mutiny_core::{logging::MutinyLogger, lsp::LspConfig, nostr::ProfileType};
 use nostr::prelude::Method;
-use nostr::{Keys, ToBech32};
+use nostr::{JsonUtil, Keys, Metadata, ToBech32};
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -67,7 +86,7 @@ async fn uninit() {
 #[wasm_bindgen]
 pub struct MutinyWallet {
     mnemonic: Mnemonic,
-    inner: mutiny_core::MutinyWallet<IndexedDbStorage>,
+    inner: mutiny_core::MutinyWallet<NamespacedStorage<IndexedDbStorage>>,
 }
 
 /// The [MutinyWallet] is the main entry point for interacting with the Mutiny Wallet.
@@ -82,6 +101,28 @@ impl MutinyWallet {
     /// Creates a new [MutinyWallet] with the given parameters.
     /// The mnemonic seed is read from storage, unless one is provided.
     /// If no mnemonic is provided, a new one is generated and stored.
+    ///
+    /// The optional BIP-39 passphrase isn't stored and must be given on every start.
+    /// The account index selects which BIP-32 account of the seed is used, the storage
+    /// can only hold one account.
+    ///
+    /// A read-only session can view the wallet while another tab or device holds the
+    /// device lock, but can't make any changes.
+    ///
+    /// If the password is the duress password, the decoy wallet is opened instead.
+    /// It is only stored locally and doesn't use the auth or storage servers.
+    ///
+    /// With `private_route_hints` the route hints of our invoices reveal as few of our
+    /// channels as possible, see [RouteHintConfig::private].
+    ///
+    /// Keys starting with any of the VSS excluded keys aren't synced to the storage server,
+    /// by default the logs and the price cache aren't.
+    ///
+    /// With `self_hosted_vss` the storage url is a standard LDK VSS server. It is authenticated
+    /// with the `vss_token` if given, otherwise with LNURL-auth if there is an auth url.
+    ///
+    /// The `gap_limit` is how many unused addresses in a row are checked when syncing
+    /// after a restore, defaults to 20.
     #[wasm_bindgen]
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
@@ -107,11 +148,34 @@ impl MutinyWallet {
         primal_url: Option<String>,
         blind_auth_url: Option<String>,
         hermes_url: Option<String>,
+        max_amountless_invoice_sats: Option<u64>,
+        allow_amountless_from_non_contacts: Option<bool>,
+        maintenance_npub: Option<String>,
+        maintenance_url: Option<String>,
+        announce_channels: Option<bool>,
+        swap_provider_url: Option<String>,
+        passphrase: Option<String>,
+        account_index: Option<u32>,
+        read_only: Option<bool>,
+        ln_address_proxy_url: Option<String>,
+        nostr_bunker_uri: Option<String>,
+        fast_start: Option<bool>,
+        disable_gossip: Option<bool>,
+        private_route_hints: Option<bool>,
+        vss_excluded_keys: Option<Vec<String>>,
+        self_hosted_vss: Option<bool>,
+        vss_token: Option<String>,
+        gap_limit: Option<u32>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         let start = instant::Instant::now();
-        // if both are set throw an error
+        // if more than one is set throw an error
         // todo default to nsec if both are for same key?
-        if nsec_override.is_some() && nip_07_key.is_some() {
+        let key_sources = [
+            nsec_override.is_some(),
+            nip_07_key.is_some(),
+            nostr_bunker_uri.is_some(),
+        ];
+        if key_sources.into_iter().filter(|set| *set).count() > 1 {
             return Err(MutinyJsError::InvalidArgumentsError);
         }
 
@@ -146,6 +210,24 @@ impl MutinyWallet {
             primal_url,
             blind_auth_url,
             hermes_url,
+            max_amountless_invoice_sats,
+            allow_amountless_from_non_contacts,
+            maintenance_npub,
+            maintenance_url,
+            announce_channels,
+            swap_provider_url,
+            passphrase,
+            account_index,
+            read_only,
+            ln_address_proxy_url,
+            nostr_bunker_uri,
+            fast_start,
+            disable_gossip,
+            private_route_hints,
+            vss_excluded_keys,
+            self_hosted_vss,
+            vss_token,
+            gap_limit,
         )
         .await
         {
@@ -189,6 +271,24 @@ impl MutinyWallet {
         primal_url: Option<String>,
         blind_auth_url: Option<String>,
         hermes_url: Option<String>,
+        max_amountless_invoice_sats: Option<u64>,
+        allow_amountless_from_non_contacts: Option<bool>,
+        maintenance_npub: Option<String>,
+        maintenance_url: Option<String>,
+        announce_channels: Option<bool>,
+        swap_provider_url: Option<String>,
+        passphrase: Option<String>,
+        account_index: Option<u32>,
+        read_only: Option<bool>,
+        ln_address_proxy_url: Option<String>,
+        nostr_bunker_uri: Option<String>,
+        fast_start: Option<bool>,
+        disable_gossip: Option<bool>,
+        private_route_hints: Option<bool>,
+        vss_excluded_keys: Option<Vec<String>>,
+        self_hosted_vss: Option<bool>,
+        vss_token: Option<String>,
+        gap_limit: Option<u32>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         let safe_mode = safe_mode.unwrap_or(false);
         let logger = Arc::new(MutinyLogger::default());
@@ -204,15 +304,27 @@ impl MutinyWallet {
             .unwrap_or(Network::Bitcoin);
 
         let override_mnemonic = mnemonic_str.map(|s| Mnemonic::from_str(&s)).transpose()?;
+        let has_override = override_mnemonic.is_some();
 
-        let mnemonic =
-            IndexedDbStorage::get_mnemonic(override_mnemonic, password.as_deref(), cipher.clone())
-                .await?;
+        // a password that doesn't open the wallet might be the duress password
+        let (mnemonic, duress_storage) = match IndexedDbStorage::get_mnemonic(
+            override_mnemonic,
+            password.as_deref(),
+            cipher.clone(),
+        )
+        .await
+        {
+            Err(MutinyError::IncorrectPassword) if !has_override => {
+                let (mnemonic, storage, namespace) =
+                    Self::open_duress_wallet(password.as_deref(), cipher.clone(), &logger).await?;
+                (mnemonic, Some((storage, namespace)))
+            }
+            res => (res?, None),
+        };
 
-        let seed = mnemonic.to_seed("");
-        let xprivkey = ExtendedPrivKey::new_master(network, &seed).unwrap();
+        let xprivkey = xprivkey_from_mnemonic(&mnemonic, passphrase.as_deref(), network)?;
 
-        let (auth_client, vss_client) = if safe_mode {
+        let (auth_client, vss_client) = if safe_mode || duress_storage.is_some() {
             (None, None)
         } else if let Some(auth_url) = auth_url.clone() {
             let auth_manager = AuthManager::new(xprivkey).unwrap();
@@ -244,28 +356,45 @@ impl MutinyWallet {
             });
 
             let vss = storage_url.map(|url| {
-                Arc::new(MutinyVssClient::new_authenticated(
-                    auth_client.clone(),
-                    url,
-                    xprivkey.private_key,
-                    logger.clone(),
-                ))
+                let vss = match vss_token {
+                    Some(token) => MutinyVssClient::new_with_token(
+                        url,
+                        token,
+                        xprivkey.private_key,
+                        logger.clone(),
+                    ),
+                    None => MutinyVssClient::new_authenticated(
+                        auth_client.clone(),
+                        url,
+                        xprivkey.private_key,
+                        logger.clone(),
+                    ),
+                };
+                Self::configure_vss_client(vss, self_hosted_vss, vss_excluded_keys)
             });
 
-            (Some(auth_client), vss)
+            (Some(auth_client), vss.transpose()?)
         } else {
             let vss = storage_url.map(|url| {
-                Arc::new(MutinyVssClient::new_unauthenticated(
-                    url,
-                    xprivkey.private_key,
-                    logger.clone(),
-                ))
+                let key = xprivkey.private_key;
+                let vss = match vss_token {
+                    Some(token) => MutinyVssClient::new_with_token(url, token, key, logger.clone()),
+                    None => MutinyVssClient::new_unauthenticated(url, key, logger.clone()),
+                };
+                Self::configure_vss_client(vss, self_hosted_vss, vss_excluded_keys)
             });
 
-            (None, vss)
+            (None, vss.transpose()?)
         };
 
-        let storage = IndexedDbStorage::new(password, cipher, vss_client, logger.clone()).await?;
+        let storage = match duress_storage {
+            Some((storage, namespace)) => NamespacedStorage::duress(storage, namespace),
+            None => {
+                let storage =
+                    IndexedDbStorage::new(password, cipher, vss_client, logger.clone()).await?;
+                NamespacedStorage::new(storage, None)
+            }
+        };
 
         let mut config_builder = MutinyWalletConfigBuilder::new(xprivkey).with_network(network);
         if let Some(w) = websocket_proxy_addr {
@@ -313,9 +442,48 @@ impl MutinyWallet {
         if let Some(true) = do_not_connect_peers {
             config_builder.do_not_connect_peers();
         }
+        if let Some(max_sats) = max_amountless_invoice_sats {
+            config_builder.with_max_amountless_invoice_sats(max_sats);
+        }
+        if let Some(true) = allow_amountless_from_non_contacts {
+            config_builder.allow_amountless_invoices_from_non_contacts();
+        }
+        if let Some(npub) = maintenance_npub {
+            config_builder.with_maintenance_pubkey(parse_npub(&npub)?);
+        }
+        if let Some(url) = maintenance_url {
+            config_builder.with_maintenance_url(url);
+        }
+        if let Some(true) = announce_channels {
+            config_builder.with_announced_channels();
+        }
+        if let Some(url) = swap_provider_url {
+            config_builder.with_swap_provider_url(url);
+        }
+        if let Some(url) = ln_address_proxy_url {
+            config_builder.with_ln_address_proxy_url(url);
+        }
+        if let Some(index) = account_index {
+            config_builder.with_account_index(index);
+        }
         if safe_mode {
             config_builder.with_safe_mode();
         }
+        if let Some(true) = read_only {
+            config_builder.with_read_only();
+        }
+        if let Some(true) = fast_start {
+            config_builder.with_fast_start();
+        }
+        if let Some(true) = disable_gossip {
+            config_builder.with_gossip_disabled();
+        }
+        if let Some(true) = private_route_hints {
+            config_builder.with_route_hint_config(RouteHintConfig::private());
+        }
+        if let Some(gap_limit) = gap_limit {
+            config_builder.with_gap_limit(gap_limit as usize);
+        }
         let config = config_builder.build();
 
         let mut mw_builder = MutinyWalletBuilder::new(xprivkey, storage).with_config(config);
@@ -328,6 +496,10 @@ impl MutinyWallet {
             let npub = parse_npub(&key)?;
             mw_builder.with_nostr_key_source(NostrKeySource::Extension(npub));
         }
+        if let Some(uri) = nostr_bunker_uri {
+            let source = connect_remote_signer(xprivkey, &uri).await?;
+            mw_builder.with_nostr_key_source(source);
+        }
         let inner = mw_builder.build().await?;
 
         Ok(MutinyWallet { mnemonic, inner })
@@ -337,6 +509,10 @@ impl MutinyWallet {
         self.inner.is_safe_mode()
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
     /// Returns if there is a saved wallet in storage.
     /// This is checked by seeing if a mnemonic seed exists in storage.
     #[wasm_bindgen]
@@ -350,7 +526,48 @@ impl MutinyWallet {
         password: Option<String>,
         auth_url: Option<String>,
         storage_url: Option<String>,
+        passphrase: Option<String>,
     ) -> Result<Option<u64>, MutinyJsError> {
+        let logger = Arc::new(MutinyLogger::default());
+        let cipher = password
+            .as_ref()
+            .filter(|p| !p.is_empty())
+            .map(|p| encryption_key_from_pass(p))
+            .transpose()?;
+        let mnemonic =
+            match IndexedDbStorage::get_mnemonic(None, password.as_deref(), cipher.clone()).await {
+                // the decoy wallet is only stored locally, so it has no device lock
+                Err(MutinyError::IncorrectPassword) => {
+                    Self::open_duress_wallet(password.as_deref(), cipher, &logger).await?;
+                    return Ok(None);
+                }
+                res => res?,
+            };
+
+        // Network doesn't matter here, only for encoding
+        let xprivkey = xprivkey_from_mnemonic(&mnemonic, passphrase.as_deref(), Network::Bitcoin)?;
+
+        let vss_client = Self::create_vss_client(xprivkey, auth_url, storage_url, &logger);
+        if let Some(vss) = vss_client {
+            let obj = vss.get_object(DEVICE_LOCK_KEY).await?;
+            let lock = serde_json::from_value::<DeviceLock>(obj.value)?;
+
+            return Ok(Some(lock.remaining_secs()));
+        };
+
+        Ok(None)
+    }
+
+    /// Asks the device running the wallet to stop and hand over the device lock, and waits
+    /// until it does. Afterwards the wallet can be started on this device.
+    /// Fails with `AlreadyRunning` if the other device doesn't answer within a minute.
+    #[wasm_bindgen]
+    pub async fn request_device_handoff(
+        password: Option<String>,
+        auth_url: Option<String>,
+        storage_url: Option<String>,
+        passphrase: Option<String>,
+    ) -> Result<(), MutinyJsError> {
         let logger = Arc::new(MutinyLogger::default());
         let cipher = password
             .as_ref()
@@ -360,11 +577,39 @@ impl MutinyWallet {
         let mnemonic =
             IndexedDbStorage::get_mnemonic(None, password.as_deref(), cipher.clone()).await?;
 
-        let seed = mnemonic.to_seed("");
         // Network doesn't matter here, only for encoding
-        let xprivkey = ExtendedPrivKey::new_master(Network::Bitcoin, &seed).unwrap();
+        let xprivkey = xprivkey_from_mnemonic(&mnemonic, passphrase.as_deref(), Network::Bitcoin)?;
+
+        let vss_client = Self::create_vss_client(xprivkey, auth_url, storage_url, &logger);
+        let storage = IndexedDbStorage::new(password, cipher, vss_client, logger).await?;
+        let res = storage.request_device_handoff().await;
+        storage.stop();
+
+        Ok(res?)
+    }
 
-        let vss_client = if let Some(auth_url) = auth_url {
+    /// Opens the storage of the decoy wallet and returns its seed and namespace,
+    /// fails with `IncorrectPassword` if the password isn't the duress password
+    async fn open_duress_wallet(
+        password: Option<&str>,
+        cipher: Option<Cipher>,
+        logger: &Arc<MutinyLogger>,
+    ) -> Result<(Mnemonic, IndexedDbStorage, String), MutinyError> {
+        let password = password.ok_or(MutinyError::IncorrectPassword)?;
+        let storage =
+            IndexedDbStorage::new(Some(password.to_string()), cipher, None, logger.clone()).await?;
+        let (namespace, mnemonic) = get_duress_mnemonic(&storage, password)?;
+        Ok((mnemonic, storage, namespace))
+    }
+
+    /// Creates a VSS client to reach the storage before the wallet is started
+    fn create_vss_client(
+        xprivkey: ExtendedPrivKey,
+        auth_url: Option<String>,
+        storage_url: Option<String>,
+        logger: &Arc<MutinyLogger>,
+    ) -> Option<Arc<MutinyVssClient>> {
+        if let Some(auth_url) = auth_url {
             let auth_manager = AuthManager::new(xprivkey).unwrap();
 
             let lnurl_client = Arc::new(
@@ -396,16 +641,47 @@ impl MutinyWallet {
                     logger.clone(),
                 ))
             })
+        }
+    }
+
+    fn configure_vss_client(
+        vss: MutinyVssClient,
+        self_hosted_vss: Option<bool>,
+        vss_excluded_keys: Option<Vec<String>>,
+    ) -> Result<Arc<MutinyVssClient>, MutinyError> {
+        let vss = match self_hosted_vss {
+            Some(true) => vss.with_protocol(VssProtocol::Ldk),
+            _ => vss,
         };
+        match vss_excluded_keys {
+            Some(keys) => Ok(Arc::new(vss.with_excluded_keys(keys)?)),
+            None => Ok(Arc::new(vss)),
+        }
+    }
 
-        if let Some(vss) = vss_client {
-            let obj = vss.get_object(DEVICE_LOCK_KEY).await?;
-            let lock = serde_json::from_value::<DeviceLock>(obj.value)?;
+    /// Returns what happened since the last call if another device took over the wallet.
+    /// The wallet stops by itself when that happens.
+    #[wasm_bindgen]
+    pub async fn take_device_handoff_events(
+        &self,
+    ) -> Result<JsValue /* Vec<DeviceHandoffEvent> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.take_device_handoff_events().await,
+        )?)
+    }
 
-            return Ok(Some(lock.remaining_secs()));
-        };
+    /// Waits until the nodes, federations and nostr are done starting when using fast start.
+    #[wasm_bindgen]
+    pub async fn ready(&self) {
+        self.inner.ready().await
+    }
 
-        Ok(None)
+    /// Returns the subsystems that finished starting since the last call.
+    #[wasm_bindgen]
+    pub fn take_readiness_events(
+        &self,
+    ) -> Result<JsValue /* Vec<ReadinessEvent> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.take_readiness_events())?)
     }
 
     /// Starts up all the nodes again.
@@ -422,6 +698,40 @@ impl MutinyWallet {
         Ok(self.inner.node_manager.stop().await?)
     }
 
+    /// Sets the power mode, either `background` or `foreground`.
+    /// This should be called when the app is backgrounded to sync less often
+    /// and reduce relay subscriptions, then again when it comes back to the foreground.
+    #[wasm_bindgen]
+    pub fn set_power_mode(&self, mode: String) -> Result<(), MutinyJsError> {
+        let mode = PowerMode::from_str(&mode)?;
+        self.inner.set_power_mode(mode);
+        Ok(())
+    }
+
+    /// Creates a restricted handle to the wallet that only exposes the given permissions.
+    /// This is useful for handing the wallet to third-party plugins or iframes.
+    ///
+    /// Valid permissions are `read_balance`, `read_activity`, `receive`, and `send`.
+    /// For example, `["receive"]` gives a receive-only handle and
+    /// `["read_balance", "read_activity"]` gives a read-only handle.
+    #[wasm_bindgen]
+    pub fn create_scoped_handle(
+        &self,
+        permissions: Vec<String>,
+    ) -> Result<ScopedWallet, MutinyJsError> {
+        ScopedWallet::new(self.inner.clone(), permissions)
+    }
+
+    /// Creates a NIP-07 signer with the wallet's nostr keys for the web app at `origin`,
+    /// so the wallet can act as the user's nostr signer.
+    ///
+    /// The `prompt` is called with `{ origin, method, params }` before every call
+    /// and should return, or resolve to, `true` to allow it.
+    #[wasm_bindgen]
+    pub fn get_nip07_signer(&self, origin: String, prompt: js_sys::Function) -> Nip07Signer {
+        Nip07Signer::new(self.inner.clone(), origin, prompt)
+    }
+
     /// Returns the mnemonic seed phrase for the wallet.
     #[wasm_bindgen]
     pub fn show_seed(&self) -> String {
@@ -473,6 +783,20 @@ impl MutinyWallet {
             .map(|pk| pk.to_bech32().expect("bech32"))?)
     }
 
+    /// Signs nostr events with the remote signer of the `bunker://` URI from now on,
+    /// keys for NWC stay local
+    #[wasm_bindgen]
+    pub async fn connect_nostr_remote_signer(
+        &self,
+        bunker_uri: String,
+    ) -> Result<String, MutinyJsError> {
+        Ok(self
+            .inner
+            .connect_nostr_remote_signer(bunker_uri)
+            .await
+            .map(|pk| pk.to_bech32().expect("bech32"))?)
+    }
+
     /// Returns the network of the wallet.
     #[wasm_bindgen]
     pub fn get_network(&self) -> String {
@@ -533,10 +857,41 @@ impl MutinyWallet {
         Ok(self.inner.create_bip21(amount, labels).await?.into())
     }
 
+    /// Creates a BIP 21 like [MutinyWallet::create_bip21] with settings for its invoice.
+    ///
+    /// The description hash is hex encoded and can't be given together with a description.
+    #[wasm_bindgen]
+    pub async fn create_bip21_with_params(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        expiry_secs: Option<u32>,
+        description: Option<String>,
+        description_hash: Option<String>,
+        min_final_cltv: Option<u16>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        let description_hash = description_hash
+            .map(|h| sha256::Hash::from_str(&h))
+            .transpose()?;
+        let params = InvoiceParams {
+            expiry_secs,
+            description,
+            description_hash,
+            min_final_cltv,
+            route_hints: None,
+        };
+        Ok(self
+            .inner
+            .create_bip21_with_params(amount, labels, params)
+            .await?
+            .into())
+    }
+
     /// Sends an on-chain transaction to the given address.
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     ///
     /// If a fee rate is not provided, one will be used from the fee estimator.
+    /// Retries with the same `idempotency_key` return the first txid instead of sending again.
     #[wasm_bindgen]
     pub async fn send_to_address(
         &self,
@@ -544,12 +899,47 @@ impl MutinyWallet {
         amount: u64,
         labels: Vec<String>,
         fee_rate: Option<f32>,
+        idempotency_key: Option<String>,
     ) -> Result<String, MutinyJsError> {
         let send_to =
             Address::from_str(&destination_address)?.require_network(self.inner.get_network())?;
         Ok(self
             .inner
-            .send_to_address(send_to, amount, labels, fee_rate)
+            .send_to_address(send_to, amount, labels, fee_rate, idempotency_key)
+            .await?
+            .to_string())
+    }
+
+    /// Pays multiple addresses in a single on-chain transaction,
+    /// `amounts` are in satoshis and must be in the same order as the addresses.
+    /// The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    #[wasm_bindgen]
+    pub async fn batch_send(
+        &self,
+        addresses: Vec<String>,
+        amounts: Vec<u64>,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<String, MutinyJsError> {
+        if addresses.len() != amounts.len() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let network = self.inner.get_network();
+        let recipients = addresses
+            .iter()
+            .zip(amounts)
+            .map(|(address, amount)| {
+                let address = Address::from_str(address)?.require_network(network)?;
+                Ok((address, amount))
+            })
+            .collect::<Result<Vec<_>, MutinyJsError>>()?;
+
+        Ok(self
+            .inner
+            .node_manager
+            .batch_send(recipients, fee_rate, labels)
             .await?
             .to_string())
     }
@@ -593,6 +983,28 @@ impl MutinyWallet {
             .to_string())
     }
 
+    /// Sweeps the funds of a foreign private key (WIF) or BIP-39 seed into the wallet,
+    /// one transaction for every address type that has funds.
+    /// The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// Returns an object for every address type that was swept or failed, with its
+    /// `address_type`, `amount_sats`, and the `txid` of the sweep or the `error`.
+    /// Address types that were already swept keep their txid when a later one fails.
+    #[wasm_bindgen]
+    pub async fn sweep_external(
+        &self,
+        wif_or_mnemonic: String,
+        fee_rate: Option<f32>,
+    ) -> Result<JsValue /* Vec<ExternalSweep> */, MutinyJsError> {
+        let sweeps = self
+            .inner
+            .sweep_external(&wif_or_mnemonic, fee_rate)
+            .await?;
+        Ok(JsValue::from_serde(&sweeps)?)
+    }
+
     /// Estimates the onchain fee for a transaction sending to the given address.
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     pub async fn estimate_tx_fee(
@@ -605,6 +1017,151 @@ impl MutinyWallet {
         Ok(self.inner.estimate_tx_fee(addr, amount, fee_rate).await?)
     }
 
+    /// Swaps on-chain funds into lightning through the swap provider, without opening a channel.
+    /// The amount is what we receive over lightning, the fees on top can't be more than `max_fee`.
+    #[wasm_bindgen]
+    pub async fn swap_in(
+        &self,
+        amount: u64,
+        max_fee: u64,
+    ) -> Result<JsValue /* SwapIn */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.swap_in(amount, max_fee).await?,
+        )?)
+    }
+
+    /// Lists our swaps from on-chain to lightning, oldest first.
+    #[wasm_bindgen]
+    pub fn list_swaps(&self) -> Result<JsValue /* Vec<SwapIn> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_swaps()?)?)
+    }
+
+    /// Restores the contacts, labels and NWC profiles we don't have yet from the
+    /// backup we keep on the nostr relays when not using VSS.
+    /// Returns how many were restored.
+    #[wasm_bindgen]
+    pub async fn restore_social_backup(&self) -> Result<usize, MutinyJsError> {
+        Ok(self.inner.restore_social_backup().await?)
+    }
+
+    /// Creates an encrypted static channel backup of our open channels.
+    /// Should be exported after opening or closing a channel.
+    #[wasm_bindgen]
+    pub async fn create_scb(&self) -> Result<String, MutinyJsError> {
+        Ok(self.inner.create_scb().await?)
+    }
+
+    /// Sets where to send encrypted backups of the whole wallet, or disables them
+    /// if no url is given. The credentials are a json `BackupCredentials`, defaulting to none.
+    ///
+    /// A backup is made right away to check the target works.
+    #[wasm_bindgen]
+    pub async fn set_remote_backup(
+        &self,
+        url: Option<String>,
+        credentials: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let config = match url {
+            Some(url) => {
+                let credentials = credentials
+                    .map(|c| serde_json::from_str(&c))
+                    .transpose()?
+                    .unwrap_or(BackupCredentials::None);
+                Some(RemoteBackupConfig { url, credentials })
+            }
+            None => None,
+        };
+        Ok(self.inner.set_remote_backup(config).await?)
+    }
+
+    /// Backs up the whole wallet to the remote target now.
+    #[wasm_bindgen]
+    pub async fn backup_to_remote(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.backup_to_remote().await?)
+    }
+
+    /// Gets where we back up to and when the last backup was made.
+    #[wasm_bindgen]
+    pub fn get_remote_backup_status(
+        &self,
+    ) -> Result<JsValue /* RemoteBackupStatus */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_remote_backup_status()?,
+        )?)
+    }
+
+    /// Deletes failed payments, expired invoices and the monitors of resolved channels,
+    /// as allowed by the compaction policy.
+    #[wasm_bindgen]
+    pub async fn compact_storage(&self) -> Result<JsValue /* CompactionStats */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.compact_storage().await?)?)
+    }
+
+    /// Gets the stats of the last storage compaction, if there was one.
+    #[wasm_bindgen]
+    pub fn get_last_compaction(
+        &self,
+    ) -> Result<JsValue /* Option<CompactionStats> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_last_compaction()?)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_compaction_policy(&self) -> Result<JsValue /* CompactionPolicy */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_compaction_policy()?)?)
+    }
+
+    /// Sets what storage compaction is allowed to delete, `None` keeps the data forever.
+    #[wasm_bindgen]
+    pub fn set_compaction_policy(
+        &self,
+        failed_payment_max_age_days: Option<u64>,
+        resolved_monitor_min_age_days: Option<u64>,
+        automatic: bool,
+    ) -> Result<(), MutinyJsError> {
+        let policy = CompactionPolicy {
+            failed_payment_max_age_days,
+            resolved_monitor_min_age_days,
+            automatic,
+        };
+        Ok(self.inner.set_compaction_policy(policy)?)
+    }
+
+    /// Keys that were changed on both this device and VSS and couldn't be merged.
+    #[wasm_bindgen]
+    pub fn get_sync_conflicts(&self) -> Result<JsValue /* Vec<SyncConflict> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_sync_conflicts()?)?)
+    }
+
+    /// Keeps either the VSS or the local value of a conflicting key.
+    #[wasm_bindgen]
+    pub fn resolve_sync_conflict(
+        &self,
+        key: String,
+        use_remote: bool,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self.inner.resolve_sync_conflict(&key, use_remote)?)
+    }
+
+    /// Checks the stored data can be read and looks for orphaned entries.
+    #[wasm_bindgen]
+    pub async fn audit_storage(&self) -> Result<JsValue /* StorageAudit */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.audit_storage().await?)?)
+    }
+
+    /// Fixes the repairable issues found by `audit_storage`.
+    #[wasm_bindgen]
+    pub fn repair_storage(
+        &self,
+        drop_orphaned_labels: bool,
+        rebuild_activity_index: bool,
+    ) -> Result<JsValue /* RepairReport */, MutinyJsError> {
+        let options = RepairOptions {
+            drop_orphaned_labels,
+            rebuild_activity_index,
+        };
+        Ok(JsValue::from_serde(&self.inner.repair_storage(options)?)?)
+    }
+
     /// Estimates the onchain fee for a transaction sweep our on-chain balance
     /// to the given address.
     ///
@@ -672,6 +1229,23 @@ impl MutinyWallet {
         Ok(result.to_string())
     }
 
+    /// Lists the signed on-chain transactions that couldn't be broadcast yet.
+    #[wasm_bindgen]
+    pub fn list_pending_broadcasts(
+        &self,
+    ) -> Result<JsValue /* Vec<PendingBroadcast> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_pending_broadcasts()?,
+        )?)
+    }
+
+    /// Stops retrying to broadcast the given transaction and frees up its inputs.
+    #[wasm_bindgen]
+    pub fn abandon_pending_broadcast(&self, txid: String) -> Result<(), MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        Ok(self.inner.node_manager.abandon_pending_broadcast(txid)?)
+    }
+
     /// Checks if the given address has any transactions.
     /// If it does, it returns the details of the first transaction.
     ///
@@ -697,28 +1271,106 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&self.inner.get_transaction(txid)?)?)
     }
 
-    /// Gets the current balance of the wallet.
-    /// This includes both on-chain and lightning funds.
-    ///
-    /// This will not include any funds in an unconfirmed lightning channel.
+    /// Gets the on-chain status of a transaction, including its number of confirmations.
     #[wasm_bindgen]
-    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyJsError> {
-        Ok(self.inner.get_balance().await?.into())
+    pub async fn get_tx_status(
+        &self,
+        txid: String,
+    ) -> Result<JsValue /* TransactionStatus */, MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        Ok(JsValue::from_serde(&self.inner.get_tx_status(txid).await?)?)
     }
 
-    /// Lists all the UTXOs in the wallet.
+    /// Gets the current block height of the chain.
     #[wasm_bindgen]
-    pub fn list_utxos(&self) -> Result<JsValue, MutinyJsError> {
-        Ok(JsValue::from_serde(&self.inner.node_manager.list_utxos()?)?)
+    pub async fn get_block_height(&self) -> Result<u32, MutinyJsError> {
+        Ok(self.inner.get_block_height().await?)
     }
 
-    /// Gets a fee estimate for an low priority transaction.
-    /// Value is in sat/vbyte.
+    /// Waits until the given transaction has at least the given number of confirmations,
+    /// giving up after `timeout_secs` if it is set.
     #[wasm_bindgen]
-    pub fn estimate_fee_low(&self) -> u32 {
-        self.inner.node_manager.estimate_fee_low()
-    }
-
+    pub async fn wait_for_confirmation(
+        &self,
+        txid: String,
+        confirmations: u32,
+        timeout_secs: Option<u64>,
+    ) -> Result<JsValue /* TransactionStatus */, MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        let status = self
+            .inner
+            .wait_for_confirmation(txid, confirmations, timeout_secs)
+            .await?;
+        Ok(JsValue::from_serde(&status)?)
+    }
+
+    /// Returns the service operator's active maintenance or incident notice, if any.
+    #[wasm_bindgen]
+    pub async fn get_maintenance_notice(
+        &self,
+    ) -> Result<JsValue /* Option<MaintenanceNotice> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_maintenance_notice().await,
+        )?)
+    }
+
+    /// Returns the changes to the operator's maintenance notice since the last call.
+    #[wasm_bindgen]
+    pub async fn take_maintenance_events(
+        &self,
+    ) -> Result<JsValue /* Vec<MaintenanceEvent> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.take_maintenance_events().await,
+        )?)
+    }
+
+    /// Returns payment timings aggregated by stage of the payment pipeline.
+    #[wasm_bindgen]
+    pub fn get_latency_stats(&self) -> Result<JsValue /* LatencyStats */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_latency_stats()?)?)
+    }
+
+    /// Returns how long startup took and the slowest recent operations.
+    #[wasm_bindgen]
+    pub fn get_performance_report(&self) -> Result<JsValue /* PerformanceReport */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_performance_report())?)
+    }
+
+    /// Returns true once the wallet is running and has completed its initial sync.
+    #[wasm_bindgen]
+    pub async fn is_ready(&self) -> bool {
+        self.inner.is_ready().await
+    }
+
+    /// Reports whether the wallet can currently receive, send, and sync,
+    /// so UI actions can be gated on the real state of the wallet.
+    #[wasm_bindgen]
+    pub async fn liveness(&self) -> Result<JsValue /* WalletLiveness */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.liveness().await?)?)
+    }
+
+    /// Gets the current balance of the wallet.
+    /// This includes both on-chain and lightning funds.
+    ///
+    /// This will not include any funds in an unconfirmed lightning channel.
+    #[wasm_bindgen]
+    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyJsError> {
+        Ok(self.inner.get_balance().await?.into())
+    }
+
+    /// Lists all the UTXOs in the wallet.
+    #[wasm_bindgen]
+    pub fn list_utxos(&self) -> Result<JsValue, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.node_manager.list_utxos()?)?)
+    }
+
+    /// Gets a fee estimate for an low priority transaction.
+    /// Value is in sat/vbyte.
+    #[wasm_bindgen]
+    pub fn estimate_fee_low(&self) -> u32 {
+        self.inner.node_manager.estimate_fee_low()
+    }
+
     /// Gets a fee estimate for an average priority transaction.
     /// Value is in sat/vbyte.
     #[wasm_bindgen]
@@ -747,6 +1399,41 @@ impl MutinyWallet {
         )?)
     }
 
+    /// Lists the pubkeys of the disabled lightning nodes in the manager.
+    #[wasm_bindgen]
+    pub async fn list_disabled_nodes(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_disabled_nodes().await?,
+        )?)
+    }
+
+    /// Lists the lightning nodes that failed to start and why.
+    /// The rest of the wallet keeps working without them.
+    #[wasm_bindgen]
+    pub async fn list_failed_nodes(&self) -> Result<JsValue /* Vec<FailedNode> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_failed_nodes().await,
+        )?)
+    }
+
+    /// Enables or disables a lightning node. Disabled nodes are not started
+    /// and do not sync, but their data is preserved.
+    ///
+    /// A node with active channels or claimable funds cannot be disabled.
+    #[wasm_bindgen]
+    pub async fn set_node_enabled(
+        &self,
+        pubkey: String,
+        enabled: bool,
+    ) -> Result<(), MutinyJsError> {
+        let pubkey = PublicKey::from_str(&pubkey)?;
+        Ok(self
+            .inner
+            .node_manager
+            .set_node_enabled(pubkey, enabled)
+            .await?)
+    }
+
     /// Changes all the node's LSPs to the given config. If any of the nodes have an active channel with the
     /// current LSP, it will fail to change the LSP.
     ///
@@ -811,6 +1498,22 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// If our nodes connect to well connected nodes from the network graph
+    /// when they have few peers.
+    #[wasm_bindgen]
+    pub fn peer_discovery_enabled(&self) -> Result<bool, MutinyJsError> {
+        Ok(self.inner.node_manager.peer_discovery_enabled()?)
+    }
+
+    /// Enables or disables connecting to peers discovered from the network graph.
+    #[wasm_bindgen]
+    pub fn set_peer_discovery_enabled(&self, enabled: bool) -> Result<(), MutinyJsError> {
+        Ok(self
+            .inner
+            .node_manager
+            .set_peer_discovery_enabled(enabled)?)
+    }
+
     /// Creates a lightning invoice. The amount should be in satoshis.
     /// If no amount is provided, the invoice will be created with no amount.
     /// If no description is provided, the invoice will be created with no description.
@@ -828,24 +1531,56 @@ impl MutinyWallet {
 
     /// Pays a lightning invoice from the selected node.
     /// An amount should only be provided if the invoice does not have an amount.
+    /// Paying an invoice without an amount requires `confirm_amountless` to be set.
     /// The amount should be in satoshis.
+    /// Retries with the same `idempotency_key` return the first payment instead of paying again.
+    /// Without a timeout we give up on payments over our node after 30 seconds.
     #[wasm_bindgen]
     pub async fn pay_invoice(
         &self,
         invoice_str: String,
         amt_sats: Option<u64>,
         labels: Vec<String>,
+        confirm_amountless: Option<bool>,
+        idempotency_key: Option<String>,
+        timeout_secs: Option<u64>,
     ) -> Result<MutinyInvoice, MutinyJsError> {
         let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        let options = PayInvoiceOptions {
+            confirm_amountless: confirm_amountless.unwrap_or(false),
+            idempotency_key,
+            timeout_secs,
+            ..Default::default()
+        };
         Ok(self
             .inner
-            .pay_invoice(&invoice, amt_sats, labels)
+            .pay_invoice_with_options(&invoice, amt_sats, labels, options)
             .await?
             .into())
     }
 
+    /// Estimates the fee of paying a lightning invoice from the selected node, without paying it.
+    /// Probes are sent along the route so the estimate is more accurate when paying.
+    /// An amount should only be provided if the invoice does not have an amount.
+    #[wasm_bindgen]
+    pub async fn estimate_ln_fee(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+    ) -> Result<JsValue /* LnFeeEstimate */, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        let estimate = self
+            .inner
+            .node_manager
+            .estimate_ln_fee(None, &invoice, amt_sats)
+            .await?;
+        Ok(JsValue::from_serde(&estimate)?)
+    }
+
     /// Sends a spontaneous payment to a node from the selected node.
     /// The amount should be in satoshis.
+    /// Retries with the same `idempotency_key` return the first payment instead of paying again.
+    /// Without a timeout we give up on the payment after 30 seconds.
     #[wasm_bindgen]
     pub async fn keysend(
         &self,
@@ -853,16 +1588,34 @@ impl MutinyWallet {
         amt_sats: u64,
         message: Option<String>,
         labels: Vec<String>,
+        idempotency_key: Option<String>,
+        timeout_secs: Option<u64>,
     ) -> Result<MutinyInvoice, MutinyJsError> {
         let to_node = PublicKey::from_str(&to_node)?;
         Ok(self
             .inner
             .node_manager
-            .keysend(None, to_node, amt_sats, message, labels)
+            .keysend(
+                None,
+                to_node,
+                amt_sats,
+                message,
+                labels,
+                timeout_secs,
+                idempotency_key,
+            )
             .await?
             .into())
     }
 
+    /// Stops retrying an outgoing lightning payment that is stuck in flight.
+    /// It can still complete if the receiver already has it.
+    #[wasm_bindgen]
+    pub async fn abandon_payment(&self, payment_hash: String) -> Result<(), MutinyJsError> {
+        let hash = sha256::Hash::from_str(&payment_hash)?;
+        Ok(self.inner.node_manager.abandon_payment(&hash).await?)
+    }
+
     /// Decodes a lightning invoice into useful information.
     /// Will return an error if the invoice is for a different network.
     #[wasm_bindgen]
@@ -937,6 +1690,37 @@ impl MutinyWallet {
         Ok(self.inner.lnurl_withdraw(&lnurl, amount_sats).await?)
     }
 
+    /// Withdraws from multiple LNURLs at once, `amounts_sats` has the amount of each.
+    /// The withdrawals are tracked until they are paid, see `list_pending_withdrawals`.
+    #[wasm_bindgen]
+    pub async fn lnurl_withdraw_batch(
+        &self,
+        lnurls: Vec<String>,
+        amounts_sats: Vec<u64>,
+    ) -> Result<JsValue /* Vec<PendingWithdrawal> */, MutinyJsError> {
+        if lnurls.len() != amounts_sats.len() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let withdrawals = lnurls
+            .iter()
+            .zip(amounts_sats)
+            .map(|(lnurl, amount)| Ok((LnUrl::from_str(lnurl)?, amount)))
+            .collect::<Result<Vec<_>, MutinyJsError>>()?;
+        let started = self.inner.lnurl_withdraw_batch(withdrawals).await?;
+        Ok(JsValue::from_serde(&started)?)
+    }
+
+    /// Lists the LNURL withdrawals that are tracked until they are paid,
+    /// including the recently finished ones
+    #[wasm_bindgen]
+    pub fn list_pending_withdrawals(
+        &self,
+    ) -> Result<JsValue /* Vec<PendingWithdrawal> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_pending_withdrawals()?,
+        )?)
+    }
+
     /// Calls upon a Cash mint and melts the token from it.
     #[wasm_bindgen]
     pub async fn melt_cashu_token(
@@ -949,13 +1733,37 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&invoices)?)
     }
 
-    /// Authenticates with a LNURL-auth for the given profile.
+    /// Authenticates with a LNURL-auth, with the identity chosen for the service.
     #[wasm_bindgen]
     pub async fn lnurl_auth(&self, lnurl: String) -> Result<(), MutinyJsError> {
         let lnurl = LnUrl::from_str(&lnurl)?;
         Ok(self.inner.lnurl_auth(lnurl).await?)
     }
 
+    /// Lists the services we logged in to with LNURL-auth or chose an identity for,
+    /// most recent login first.
+    #[wasm_bindgen]
+    pub fn list_lnurl_auth_identities(
+        &self,
+    ) -> Result<JsValue /* Vec<LnUrlAuthIdentity> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_lnurl_auth_identities()?,
+        )?)
+    }
+
+    /// Chooses the identity to log in to the domain with LNURL-auth,
+    /// the service sees a different linking key for every identity. 0 is the default identity.
+    #[wasm_bindgen]
+    pub fn set_lnurl_auth_identity(
+        &self,
+        domain: String,
+        index: u32,
+    ) -> Result<JsValue /* LnUrlAuthIdentity */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.set_lnurl_auth_identity(domain, index)?,
+        )?)
+    }
+
     /// Gets an invoice from the node manager.
     /// This includes sent and received invoices.
     #[wasm_bindgen]
@@ -972,6 +1780,27 @@ impl MutinyWallet {
         Ok(self.inner.get_invoice_by_hash(&hash).await?.into())
     }
 
+    /// Creates a new invoice to replace an expired, unpaid invoice we created,
+    /// keeping its amount, description, expiry and labels.
+    #[wasm_bindgen]
+    pub async fn reissue_invoice(&self, hash: String) -> Result<MutinyInvoice, MutinyJsError> {
+        let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
+        Ok(self.inner.reissue_invoice(&hash).await?.into())
+    }
+
+    /// Looks up a lightning payment by hash along with the paths it was sent over,
+    /// including the hops and fees of each part of a multi-part payment.
+    #[wasm_bindgen]
+    pub async fn get_payment_details(
+        &self,
+        hash: String,
+    ) -> Result<JsValue /* PaymentDetails */, MutinyJsError> {
+        let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
+        Ok(JsValue::from_serde(
+            &self.inner.get_payment_details(&hash).await?,
+        )?)
+    }
+
     /// Gets an invoice from the node manager.
     /// This includes sent and received invoices.
     #[wasm_bindgen]
@@ -994,6 +1823,104 @@ impl MutinyWallet {
             .into())
     }
 
+    /// Gets where a channel is in its life, from requesting it until it is closed.
+    ///
+    /// The `state` field is one of `negotiating`, `funding_broadcast`,
+    /// `waiting_confirmations`, `ready`, `closing` or `closed`.
+    #[wasm_bindgen]
+    pub async fn get_channel_lifecycle(
+        &self,
+        user_channel_id: String,
+    ) -> Result<JsValue /* ChannelLifecycle */, MutinyJsError> {
+        let user_channel_id: [u8; 16] = FromHex::from_hex(&user_channel_id)?;
+        let lifecycle = self
+            .inner
+            .node_manager
+            .get_channel_lifecycle(u128::from_be_bytes(user_channel_id))
+            .await?;
+        Ok(JsValue::from_serde(&lifecycle)?)
+    }
+
+    /// Lists our outbound channels that have been pending for too long, because their
+    /// funding transaction isn't confirming or the peer went away.
+    #[wasm_bindgen]
+    pub async fn list_stuck_channels(
+        &self,
+    ) -> Result<JsValue /* Vec<StuckChannel> */, MutinyJsError> {
+        let channels = self.inner.node_manager.list_stuck_channels().await?;
+        Ok(JsValue::from_serde(&channels)?)
+    }
+
+    /// Bumps the fee of a force closed channel, by its hex channel id, to the given fee
+    /// rate in sats per vbyte. Only anchor channels can be bumped.
+    #[wasm_bindgen]
+    pub async fn bump_channel_close_fee(
+        &self,
+        channel_id: String,
+        fee_rate: f32,
+    ) -> Result<(), MutinyJsError> {
+        let channel_id: [u8; 32] = FromHex::from_hex(&channel_id)?;
+        Ok(self
+            .inner
+            .node_manager
+            .bump_channel_close_fee(ChannelId(channel_id), fee_rate)
+            .await?)
+    }
+
+    /// Lists the funds of closed channels that are still being claimed on-chain,
+    /// with how many blocks until each claim can be swept to the wallet.
+    #[wasm_bindgen]
+    pub async fn get_force_close_details(
+        &self,
+    ) -> Result<JsValue /* Vec<ForceCloseClaim> */, MutinyJsError> {
+        let claims = self.inner.node_manager.get_force_close_details().await?;
+        Ok(JsValue::from_serde(&claims)?)
+    }
+
+    /// Lists the outputs of closed channels that we failed to sweep to our wallet.
+    /// They are retried in the background with a higher fee every time.
+    #[wasm_bindgen]
+    pub async fn list_unswept_outputs(
+        &self,
+    ) -> Result<JsValue /* Vec<UnsweptOutput> */, MutinyJsError> {
+        let outputs = self.inner.node_manager.list_unswept_outputs().await?;
+        Ok(JsValue::from_serde(&outputs)?)
+    }
+
+    /// Sweeps the unswept outputs now with the given fee rate in sats per vbyte.
+    /// Returns the txids of the sweep transactions.
+    #[wasm_bindgen]
+    pub async fn force_sweep_outputs(
+        &self,
+        fee_rate: f32,
+    ) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        let txids = self
+            .inner
+            .node_manager
+            .force_sweep_outputs(fee_rate)
+            .await?;
+        let txids: Vec<String> = txids.into_iter().map(|t| t.to_string()).collect();
+        Ok(JsValue::from_serde(&txids)?)
+    }
+
+    /// Abandons a pending outbound channel and records why it closed.
+    ///
+    /// Its funding transaction is double-spent back to the wallet first, the txid of the
+    /// double-spend is returned. The channel stays open if the double-spend fails.
+    #[wasm_bindgen]
+    pub async fn abandon_pending_channel(
+        &self,
+        user_channel_id: String,
+    ) -> Result<Option<String>, MutinyJsError> {
+        let user_channel_id: [u8; 16] = FromHex::from_hex(&user_channel_id)?;
+        let txid = self
+            .inner
+            .node_manager
+            .abandon_pending_channel(u128::from_be_bytes(user_channel_id))
+            .await?;
+        Ok(txid.map(|t| t.to_string()))
+    }
+
     /// Gets all channel closures from the node manager.
     ///
     /// The channel closures are sorted by the time they were closed.
@@ -1009,6 +1936,8 @@ impl MutinyWallet {
     /// Opens a channel from our selected node to the given pubkey.
     /// The amount is in satoshis.
     ///
+    /// The channel is announced if `announce` is set, otherwise the wallet's default is used.
+    ///
     /// The node must be online and have a connection to the peer.
     /// The wallet much have enough funds to open the channel.
     #[wasm_bindgen]
@@ -1017,6 +1946,7 @@ impl MutinyWallet {
         to_pubkey: Option<String>,
         amount: u64,
         fee_rate: Option<f32>,
+        announce: Option<bool>,
     ) -> Result<MutinyChannel, MutinyJsError> {
         let to_pubkey = match to_pubkey {
             Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
@@ -1028,7 +1958,7 @@ impl MutinyWallet {
         Ok(self
             .inner
             .node_manager
-            .open_channel(None, to_pubkey, amount, fee_rate, None)
+            .open_channel(None, to_pubkey, amount, fee_rate, None, announce)
             .await?
             .into())
     }
@@ -1036,10 +1966,13 @@ impl MutinyWallet {
     /// Opens a channel from our selected node to the given pubkey.
     /// It will spend the all the on-chain utxo in full to fund the channel.
     ///
+    /// The channel is announced if `announce` is set, otherwise the wallet's default is used.
+    ///
     /// The node must be online and have a connection to the peer.
     pub async fn sweep_all_to_channel(
         &self,
         to_pubkey: Option<String>,
+        announce: Option<bool>,
     ) -> Result<MutinyChannel, MutinyJsError> {
         let to_pubkey = match to_pubkey {
             Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
@@ -1051,7 +1984,7 @@ impl MutinyWallet {
         Ok(self
             .inner
             .node_manager
-            .sweep_all_to_channel(to_pubkey)
+            .sweep_all_to_channel(to_pubkey, announce)
             .await?
             .into())
     }
@@ -1131,6 +2064,34 @@ impl MutinyWallet {
             .await?)
     }
 
+    /// Sets the forwarding fees, CLTV delta and HTLC limits of the channel with the given
+    /// outpoint. Values that aren't set use our defaults.
+    #[wasm_bindgen]
+    pub async fn update_channel_config(
+        &self,
+        outpoint: String,
+        forwarding_fee_base_msat: Option<u32>,
+        forwarding_fee_proportional_millionths: Option<u32>,
+        cltv_expiry_delta: Option<u16>,
+        htlc_minimum_msat: Option<u64>,
+        htlc_maximum_msat: Option<u64>,
+    ) -> Result<(), MutinyJsError> {
+        let outpoint: OutPoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let policy = ChannelPolicy {
+            forwarding_fee_base_msat,
+            forwarding_fee_proportional_millionths,
+            cltv_expiry_delta,
+            htlc_minimum_msat,
+            htlc_maximum_msat,
+        };
+        Ok(self
+            .inner
+            .node_manager
+            .update_channel_config(&outpoint, policy)
+            .await?)
+    }
+
     /// Lists all the channels for all the nodes in the node manager.
     #[wasm_bindgen]
     pub async fn list_channels(&self) -> Result<JsValue /* Vec<MutinyChannel> */, MutinyJsError> {
@@ -1139,6 +2100,15 @@ impl MutinyWallet {
         )?)
     }
 
+    /// Gets the inbound and outbound liquidity of all the channels,
+    /// and how much can actually be sent and received right now.
+    #[wasm_bindgen]
+    pub async fn get_liquidity(&self) -> Result<JsValue /* Liquidity */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_liquidity().await?,
+        )?)
+    }
+
     /// Lists all the peers for all the nodes in the node manager.
     #[wasm_bindgen]
     pub async fn list_peers(&self) -> Result<JsValue /* Vec<MutinyPeer> */, MutinyJsError> {
@@ -1147,6 +2117,13 @@ impl MutinyWallet {
         )?)
     }
 
+    /// Looks up the aliases, colors and addresses of peers that we don't have yet
+    /// from the mempool.space API, they are then returned by `list_peers`.
+    #[wasm_bindgen]
+    pub async fn lookup_peer_metadata(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.node_manager.lookup_peer_metadata().await?)
+    }
+
     /// Returns all the on-chain and lightning activity from the wallet.
     #[wasm_bindgen]
     pub async fn get_activity(
@@ -1157,8 +2134,44 @@ impl MutinyWallet {
         // get activity from the node manager
         let activity = self.inner.get_activity(limit, offset)?;
         let mut activity: Vec<ActivityItem> = activity.into_iter().map(|a| a.into()).collect();
+        self.add_activity_contacts(&mut activity)?;
+
+        Ok(JsValue::from_serde(&activity)?)
+    }
 
-        // add contacts to the activity
+    /// Returns a page of activity that settled before `until_ts`, newest first.
+    /// Leave `until_ts` empty for the first page, it has all the pending activity.
+    /// Use the `next_until_ts` of the page to get the next one, it is empty on the last page.
+    ///
+    /// The activity can be filtered by kind (`onchain`, `lightning`, `channel_closed`,
+    /// `federation`), by label, and by contact id.
+    #[wasm_bindgen]
+    pub async fn get_activity_page(
+        &self,
+        limit: usize,
+        until_ts: Option<u64>,
+        kind: Option<String>,
+        label: Option<String>,
+        contact_id: Option<String>,
+    ) -> Result<JsValue /* ActivityPage */, MutinyJsError> {
+        let filter = ActivityFilter {
+            kind: kind.map(|k| ActivityKind::from_str(&k)).transpose()?,
+            label,
+            contact_id,
+        };
+        let page = self.inner.get_activity_page(limit, until_ts, filter)?;
+        let mut items: Vec<ActivityItem> = page.items.into_iter().map(|a| a.into()).collect();
+        self.add_activity_contacts(&mut items)?;
+
+        let page = ActivityPage {
+            items,
+            next_until_ts: page.next_until_ts,
+        };
+        Ok(JsValue::from_serde(&page)?)
+    }
+
+    /// Adds the contacts to the activity items that are tagged with one
+    fn add_activity_contacts(&self, activity: &mut [ActivityItem]) -> Result<(), MutinyJsError> {
         let contacts = self.inner.node_manager.get_contacts()?;
         let follows = self.inner.nostr.get_follow_list()?;
         for a in activity.iter_mut() {
@@ -1178,9 +2191,152 @@ impl MutinyWallet {
             a.labels.retain(|l| !contacts.contains_key(l));
         }
 
+        Ok(())
+    }
+
+    /// Returns the activity with its value in the given currency at the time it settled.
+    /// Prices are recorded while the wallet is running, older activity has no fiat value.
+    #[wasm_bindgen]
+    pub async fn get_activity_with_fiat(
+        &self,
+        currency: String,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<JsValue /* Vec<FiatActivityItem> */, MutinyJsError> {
+        let activity = self.inner.get_activity_with_fiat(currency, limit, offset)?;
+        let activity: Vec<FiatActivityItem> = activity.into_iter().map(|a| a.into()).collect();
         Ok(JsValue::from_serde(&activity)?)
     }
 
+    /// Exports the settled activity for accounting, either as `csv` or `json`.
+    /// The timestamps are optional unix timestamps to limit the export to a period,
+    /// fiat values are in the given currency, `usd` by default.
+    #[wasm_bindgen]
+    pub fn export_activity(
+        &self,
+        format: String,
+        from_ts: Option<u64>,
+        to_ts: Option<u64>,
+        currency: Option<String>,
+    ) -> Result<String, MutinyJsError> {
+        let format = ExportFormat::from_str(&format)?;
+        Ok(self
+            .inner
+            .export_activity(format, from_ts, to_ts, currency)?)
+    }
+
+    /// Returns the spending by month for the given number of months, including the current one,
+    /// or for all time if none is given.
+    #[wasm_bindgen]
+    pub fn get_spending_summary(
+        &self,
+        months: Option<u32>,
+    ) -> Result<JsValue /* SpendingSummary */, MutinyJsError> {
+        let period = match months {
+            Some(months) => SpendingPeriod::LastMonths(months),
+            None => SpendingPeriod::AllTime,
+        };
+        Ok(JsValue::from_serde(
+            &self.inner.get_spending_summary(period)?,
+        )?)
+    }
+
+    /// Returns the spending policy enforced on all payments
+    #[wasm_bindgen]
+    pub fn get_spending_policy(&self) -> Result<JsValue /* SpendingPolicy */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_spending_policy()?)?)
+    }
+
+    /// Replaces the spending policy, all limits are in sats and optional.
+    /// The PIN is needed if one is set.
+    #[wasm_bindgen]
+    pub fn set_spending_policy(
+        &self,
+        daily_limit_sats: Option<u64>,
+        weekly_limit_sats: Option<u64>,
+        max_payment_sats: Option<u64>,
+        whitelist: Option<Vec<String>>,
+        pin_threshold_sats: Option<u64>,
+        pin: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let policy = SpendingPolicy {
+            daily_limit_sats,
+            weekly_limit_sats,
+            max_payment_sats,
+            whitelist,
+            pin_threshold_sats,
+        };
+        Ok(self.inner.set_spending_policy(policy, pin)?)
+    }
+
+    /// Sets the spending PIN, or removes it if no new PIN is given.
+    /// The current PIN is needed if one is set.
+    #[wasm_bindgen]
+    pub fn set_spending_pin(
+        &self,
+        current_pin: Option<String>,
+        new_pin: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self.inner.set_spending_pin(current_pin, new_pin)?)
+    }
+
+    /// Allows payments over the PIN threshold for a few minutes
+    #[wasm_bindgen]
+    pub fn unlock_spending(&self, pin: String) -> Result<(), MutinyJsError> {
+        Ok(self.inner.unlock_spending(pin)?)
+    }
+
+    /// Requires the PIN again for payments over the threshold
+    #[wasm_bindgen]
+    pub fn lock_spending(&self) {
+        self.inner.lock_spending()
+    }
+
+    /// Returns how much was spent in the last day and week
+    #[wasm_bindgen]
+    pub fn get_spending_policy_usage(
+        &self,
+    ) -> Result<JsValue /* SpendingPolicyUsage */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_spending_policy_usage()?,
+        )?)
+    }
+
+    /// Returns the limits on invoices requested through NWC and our lightning address
+    #[wasm_bindgen]
+    pub fn get_receive_limits(&self) -> Result<JsValue /* ReceiveLimits */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_receive_limits()?)?)
+    }
+
+    /// Replaces the limits on invoices requested through NWC and our lightning address,
+    /// every limit is optional. Requests from the denied npubs are always rejected.
+    #[wasm_bindgen]
+    pub fn set_receive_limits(
+        &self,
+        max_invoice_sats: Option<u64>,
+        max_invoices_per_hour: Option<u32>,
+        denied_npubs: Vec<String>,
+    ) -> Result<(), MutinyJsError> {
+        let denied_npubs = denied_npubs
+            .iter()
+            .map(|n| parse_npub(n))
+            .collect::<Result<Vec<_>, _>>()?;
+        let limits = ReceiveLimits {
+            max_invoice_sats,
+            max_invoices_per_hour,
+            denied_npubs,
+        };
+        Ok(self.inner.set_receive_limits(limits)?)
+    }
+
+    /// Returns how many invoice requests were allowed and rejected by the receive limits
+    #[wasm_bindgen]
+    pub fn get_receive_limit_stats(
+        &self,
+    ) -> Result<JsValue /* ReceiveLimitStats */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_receive_limit_stats()?)?)
+    }
+
     /// Returns all the on-chain and lightning activity for a given label
     #[wasm_bindgen]
     pub async fn get_label_activity(
@@ -1212,7 +2368,98 @@ impl MutinyWallet {
             a.labels.retain(|l| l != &label);
         }
 
-        Ok(JsValue::from_serde(&activity)?)
+        Ok(JsValue::from_serde(&activity)?)
+    }
+
+    /// Returns all the on-chain, lightning, and federation activity for a given contact
+    #[wasm_bindgen]
+    pub async fn get_contact_activity(
+        &self,
+        contact_id: String,
+    ) -> Result<JsValue /* Vec<ActivityItem> */, MutinyJsError> {
+        let activity = self.inner.get_contact_activity(&contact_id)?;
+        let mut activity: Vec<ActivityItem> = activity.into_iter().map(|a| a.into()).collect();
+
+        if let Some(contact) = self.inner.node_manager.get_contact(&contact_id)? {
+            let follows = self.inner.nostr.get_follow_list()?;
+            let is_followed = contact
+                .npub
+                .as_ref()
+                .map(|n| follows.contains(n))
+                .unwrap_or(false);
+
+            // This is the same as we do in get_activity
+            for a in activity.iter_mut() {
+                a.contacts.push(TagItem::from(
+                    contact_id.clone(),
+                    contact.clone(),
+                    is_followed,
+                ));
+                a.labels.retain(|l| l != &contact_id);
+            }
+        }
+
+        Ok(JsValue::from_serde(&activity)?)
+    }
+
+    /// Returns aggregated payment stats for a given contact.
+    /// If `since` is given, only payments completed after that unix timestamp are counted.
+    #[wasm_bindgen]
+    pub fn get_contact_stats(
+        &self,
+        contact_id: String,
+        since: Option<u64>,
+    ) -> Result<JsValue /* ContactStats */, MutinyJsError> {
+        let stats = self.inner.get_contact_stats(&contact_id, since)?;
+        Ok(JsValue::from_serde(&stats)?)
+    }
+
+    /// Searches activity, contacts, and labeled addresses for the given query.
+    #[wasm_bindgen]
+    pub async fn search(
+        &self,
+        query: String,
+    ) -> Result<JsValue /* SearchResults */, MutinyJsError> {
+        let results = self.inner.search(&query)?;
+
+        let contacts = self.inner.node_manager.get_contacts()?;
+        let follows = self.inner.nostr.get_follow_list()?;
+        let is_followed = |c: &Contact| c.npub.as_ref().is_some_and(|n| follows.contains(n));
+
+        // This is the same as we do in get_activity
+        let mut activity: Vec<ActivityItem> =
+            results.activity.into_iter().map(|a| a.into()).collect();
+        for a in activity.iter_mut() {
+            for label in a.labels.iter() {
+                if let Some(contact) = contacts.get(label) {
+                    a.contacts.push(TagItem::from(
+                        label.clone(),
+                        contact.clone(),
+                        is_followed(contact),
+                    ));
+                }
+            }
+            a.labels.retain(|l| !contacts.contains_key(l));
+        }
+
+        let results = SearchResults {
+            activity,
+            contacts: results
+                .contacts
+                .into_iter()
+                .map(|(id, c)| {
+                    let followed = is_followed(&c);
+                    TagItem::from(id, c, followed)
+                })
+                .collect(),
+            addresses: results
+                .addresses
+                .into_iter()
+                .map(|(address, labels)| AddressLabels { address, labels })
+                .collect(),
+        };
+
+        Ok(JsValue::from_serde(&results)?)
     }
 
     /// Adds a new federation based on its federation code
@@ -1242,6 +2489,16 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&self.inner.list_federations().await?)?)
     }
 
+    /// How starting each federation client went, failed federations are skipped until restart.
+    #[wasm_bindgen]
+    pub async fn get_federation_init_statuses(
+        &self,
+    ) -> Result<JsValue /* Vec<FederationInitStatus> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_federation_init_statuses().await,
+        )?)
+    }
+
     /// Removes a federation by setting its archived status to true, based on the FederationId.
     #[wasm_bindgen]
     pub async fn remove_federation(&self, federation_id: String) -> Result<(), MutinyJsError> {
@@ -1260,6 +2517,84 @@ impl MutinyWallet {
         Ok(self.inner.get_federation_balances().await?.into())
     }
 
+    /// Gets the preference for which federation to use for payments
+    #[wasm_bindgen]
+    pub fn get_federation_preference(
+        &self,
+    ) -> Result<JsValue /* FederationPreference */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_federation_preference()?,
+        )?)
+    }
+
+    /// Sets how we pick which federation to use when paying and receiving.
+    /// One of `lowest_fee`, `largest_balance`, or `pinned_default`.
+    #[wasm_bindgen]
+    pub fn set_federation_routing_policy(&self, policy: String) -> Result<(), MutinyJsError> {
+        let policy = match policy.as_str() {
+            "lowest_fee" => FederationRoutingPolicy::LowestFee,
+            "largest_balance" => FederationRoutingPolicy::LargestBalance,
+            "pinned_default" => FederationRoutingPolicy::PinnedDefault,
+            _ => return Err(MutinyJsError::InvalidArgumentsError),
+        };
+        Ok(self.inner.set_federation_routing_policy(policy)?)
+    }
+
+    /// Sets the federation to use first when paying and receiving.
+    /// Passing nothing clears the default.
+    #[wasm_bindgen]
+    pub async fn set_default_federation(
+        &self,
+        federation_id: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let federation_id = federation_id
+            .map(|f| FederationId::from_str(&f).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .transpose()?;
+        Ok(self.inner.set_default_federation(federation_id).await?)
+    }
+
+    /// Gets an on-chain address to deposit directly into the given federation.
+    #[wasm_bindgen]
+    pub async fn get_federation_deposit_address(
+        &self,
+        federation_id: String,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        let federation_id = FederationId::from_str(&federation_id)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let address = self
+            .inner
+            .get_federation_deposit_address(federation_id, labels.clone())
+            .await?;
+        Ok(MutinyBip21RawMaterials {
+            address: address.to_string(),
+            invoice: None,
+            btc_amount: None,
+            labels,
+        })
+    }
+
+    /// Withdraws from the given federation to an on-chain address.
+    /// The amount is in satoshis.
+    #[wasm_bindgen]
+    pub async fn withdraw_from_federation(
+        &self,
+        federation_id: String,
+        destination_address: String,
+        amount: u64,
+        labels: Vec<String>,
+    ) -> Result<String, MutinyJsError> {
+        let federation_id = FederationId::from_str(&federation_id)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let send_to =
+            Address::from_str(&destination_address)?.require_network(self.inner.get_network())?;
+        Ok(self
+            .inner
+            .withdraw_from_federation(federation_id, send_to, amount, labels)
+            .await?
+            .to_string())
+    }
+
     /// Creates a recommendation event for a federation
     pub async fn recommend_federation(
         &self,
@@ -1456,6 +2791,7 @@ impl MutinyWallet {
         ln_address: Option<String>,
         lnurl: Option<String>,
         image_url: Option<String>,
+        node_pubkey: Option<String>,
     ) -> Result<String, MutinyJsError> {
         let contact = Contact {
             name,
@@ -1465,6 +2801,7 @@ impl MutinyWallet {
                 .transpose()?,
             lnurl: lnurl.map(|l| LnUrl::from_str(&l)).transpose()?,
             image_url,
+            node_pubkey: node_pubkey.map(|p| PublicKey::from_str(&p)).transpose()?,
             last_used: now().as_secs(),
         };
 
@@ -1481,6 +2818,7 @@ impl MutinyWallet {
         ln_address: Option<String>,
         lnurl: Option<String>,
         image_url: Option<String>,
+        node_pubkey: Option<String>,
     ) -> Result<String, MutinyJsError> {
         let contact = Contact {
             name,
@@ -1490,6 +2828,7 @@ impl MutinyWallet {
                 .transpose()?,
             lnurl: lnurl.map(|l| LnUrl::from_str(&l)).transpose()?,
             image_url,
+            node_pubkey: node_pubkey.map(|p| PublicKey::from_str(&p)).transpose()?,
             last_used: now().as_secs(),
         };
         Ok(self.inner.node_manager.create_new_contact(contact)?)
@@ -1507,6 +2846,7 @@ impl MutinyWallet {
         ln_address: Option<String>,
         lnurl: Option<String>,
         image_url: Option<String>,
+        node_pubkey: Option<String>,
     ) -> Result<(), MutinyJsError> {
         let contact = Contact {
             name,
@@ -1516,12 +2856,45 @@ impl MutinyWallet {
                 .transpose()?,
             lnurl: lnurl.map(|l| LnUrl::from_str(&l)).transpose()?,
             image_url,
+            node_pubkey: node_pubkey.map(|p| PublicKey::from_str(&p)).transpose()?,
             last_used: now().as_secs(),
         };
 
         Ok(self.inner.node_manager.edit_contact(id, contact)?)
     }
 
+    /// Gets all the contact groups by their id
+    pub fn get_contact_groups(
+        &self,
+    ) -> Result<JsValue /* HashMap<String, ContactGroup> */, MutinyJsError> {
+        let groups = self.inner.node_manager.get_contact_groups()?;
+        Ok(JsValue::from_serde(&groups)?)
+    }
+
+    /// Creates a group of existing contacts and returns its id
+    pub fn create_contact_group(
+        &self,
+        name: String,
+        members: Vec<String>,
+    ) -> Result<String, MutinyJsError> {
+        let group = ContactGroup { name, members };
+        Ok(self.inner.node_manager.create_contact_group(group)?)
+    }
+
+    pub fn edit_contact_group(
+        &self,
+        id: String,
+        name: String,
+        members: Vec<String>,
+    ) -> Result<(), MutinyJsError> {
+        let group = ContactGroup { name, members };
+        Ok(self.inner.node_manager.edit_contact_group(id, group)?)
+    }
+
+    pub fn delete_contact_group(&self, id: String) -> Result<(), MutinyJsError> {
+        Ok(self.inner.node_manager.delete_contact_group(id)?)
+    }
+
     pub async fn get_contact_for_npub(
         &self,
         npub: String,
@@ -1570,6 +2943,20 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&logs)?)
     }
 
+    /// Exports the logs with pubkeys, invoices, addresses and amounts redacted.
+    /// If a support npub is given, the logs are encrypted to it.
+    #[wasm_bindgen]
+    pub async fn export_logs(
+        support_npub: Option<String>,
+    ) -> Result<Option<String>, MutinyJsError> {
+        let support_npub = support_npub.map(|n| parse_npub(&n)).transpose()?;
+        let export = IndexedDbStorage::get_logs()
+            .await?
+            .map(|logs| mutiny_core::logging::export_logs(logs, support_npub))
+            .transpose()?;
+        Ok(export)
+    }
+
     /// Get nostr wallet connect profiles
     #[wasm_bindgen]
     pub fn get_nwc_profiles(&self) -> Result<Vec<NwcProfile>, MutinyJsError> {
@@ -1751,12 +3138,150 @@ impl MutinyWallet {
         Ok(self.inner.nostr.edit_nwc_profile(profile)?.into())
     }
 
+    /// Requires the app connecting to a NWC profile to present a NIP-13 proof of work
+    /// of the given difficulty on its first request before the profile activates.
+    /// Passing no difficulty removes the requirement.
+    #[wasm_bindgen]
+    pub async fn set_nwc_profile_required_pow(
+        &self,
+        profile_index: u32,
+        difficulty: Option<u8>,
+    ) -> Result<models::NwcProfile, MutinyJsError> {
+        Ok(self
+            .inner
+            .nostr
+            .set_nwc_profile_required_pow(profile_index, difficulty)?
+            .into())
+    }
+
     /// Finds a nostr wallet connect profile by index
     #[wasm_bindgen]
     pub async fn get_nwc_profile(&self, index: u32) -> Result<models::NwcProfile, MutinyJsError> {
         Ok(self.inner.nostr.get_nwc_profile(index)?.into())
     }
 
+    /// Creates a child wallet, a constrained wallet for a kid managed from this one.
+    /// The child spends with its own NWC connection, up to the allowance.
+    #[wasm_bindgen]
+    pub async fn create_child_wallet(
+        &self,
+        name: String,
+        allowance: u64,
+        period: BudgetPeriod,
+        single_max: Option<u64>,
+    ) -> Result<JsValue /* ChildWallet */, MutinyJsError> {
+        let child = self
+            .inner
+            .create_child_wallet(name, allowance, period.into(), single_max)
+            .await?;
+        Ok(JsValue::from_serde(&child)?)
+    }
+
+    /// Lists the child wallets managed from this wallet
+    #[wasm_bindgen]
+    pub fn list_child_wallets(&self) -> Result<JsValue /* Vec<ChildWallet> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_child_wallets()?)?)
+    }
+
+    /// The balance of a child wallet, what is left of its allowance this period
+    #[wasm_bindgen]
+    pub fn get_child_wallet_balance(&self, id: String) -> Result<u64, MutinyJsError> {
+        Ok(self.inner.get_child_wallet_balance(&id)?)
+    }
+
+    /// Changes the allowance of a child wallet
+    #[wasm_bindgen]
+    pub fn set_child_wallet_allowance(
+        &self,
+        id: String,
+        allowance: u64,
+        period: BudgetPeriod,
+        single_max: Option<u64>,
+    ) -> Result<models::NwcProfile, MutinyJsError> {
+        Ok(self
+            .inner
+            .set_child_wallet_allowance(&id, allowance, period.into(), single_max)?
+            .into())
+    }
+
+    /// Gets the seed of a child wallet, to set it up on the kid's own device
+    #[wasm_bindgen]
+    pub fn get_child_wallet_seed(&self, id: String) -> Result<String, MutinyJsError> {
+        Ok(self.inner.get_child_wallet_mnemonic(&id)?.to_string())
+    }
+
+    /// Removes a child wallet and its NWC connection
+    #[wasm_bindgen]
+    pub async fn remove_child_wallet(&self, id: String) -> Result<(), MutinyJsError> {
+        Ok(self.inner.remove_child_wallet(&id).await?)
+    }
+
+    /// Registers a recurring payment to a service. With `nwc` the service gets its own
+    /// NWC connection to pull the payments, otherwise it sends an invoice every period.
+    #[wasm_bindgen]
+    pub async fn register_subscription(
+        &self,
+        name: String,
+        amount_sats: u64,
+        period: BudgetPeriod,
+        nwc: bool,
+    ) -> Result<JsValue /* Subscription */, MutinyJsError> {
+        let subscription = self
+            .inner
+            .register_subscription(name, amount_sats, period.into(), nwc)
+            .await?;
+        Ok(JsValue::from_serde(&subscription)?)
+    }
+
+    /// Lists the subscriptions, including the paused and canceled ones
+    #[wasm_bindgen]
+    pub fn list_subscriptions(&self) -> Result<JsValue /* Vec<Subscription> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_subscriptions()?)?)
+    }
+
+    /// The NWC connection string to give to the service of a subscription
+    #[wasm_bindgen]
+    pub fn get_subscription_nwc_uri(&self, id: String) -> Result<Option<String>, MutinyJsError> {
+        Ok(self.inner.get_subscription_nwc_uri(&id)?)
+    }
+
+    /// Pauses a subscription until it is resumed
+    #[wasm_bindgen]
+    pub fn pause_subscription(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Subscription */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.pause_subscription(&id)?)?)
+    }
+
+    /// Resumes a paused subscription
+    #[wasm_bindgen]
+    pub fn resume_subscription(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Subscription */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.resume_subscription(&id)?)?)
+    }
+
+    /// Cancels a subscription and removes its NWC connection
+    #[wasm_bindgen]
+    pub fn cancel_subscription(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Subscription */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.cancel_subscription(&id)?)?)
+    }
+
+    /// Returns the subscription events since the last call, like renewal reminders
+    #[wasm_bindgen]
+    pub async fn take_subscription_events(
+        &self,
+    ) -> Result<JsValue /* Vec<SubscriptionEvent> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.take_subscription_events().await,
+        )?)
+    }
+
     /// Create a single use nostr wallet connect profile
     #[wasm_bindgen]
     pub async fn create_single_use_nwc(
@@ -1957,6 +3482,16 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&profile)?)
     }
 
+    /// Publishes the given NIP-01 metadata json as the user's nostr profile,
+    /// replacing the current one, along with our relay list
+    #[wasm_bindgen]
+    pub async fn set_nostr_profile(&self, metadata: String) -> Result<JsValue, MutinyJsError> {
+        let metadata =
+            Metadata::from_json(metadata).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let profile = self.inner.nostr.set_nostr_profile(metadata).await?;
+        Ok(JsValue::from_serde(&profile)?)
+    }
+
     /// Sets the user's nostr profile data to a "deleted" state
     #[wasm_bindgen]
     pub async fn delete_profile(&self) -> Result<JsValue, MutinyJsError> {
@@ -1977,6 +3512,13 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Gets how many events we published to our relays, retried and failed to publish
+    pub fn get_relay_pool_metrics(&self) -> Result<JsValue /* RelayPoolMetrics */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.nostr.get_relay_pool_metrics(),
+        )?)
+    }
+
     /// Gets the list of npubs we're following
     pub async fn get_follow_list(
         &self,
@@ -2029,6 +3571,110 @@ impl MutinyWallet {
         Ok(event_id.to_hex())
     }
 
+    /// Creates an invoice and sends it as a payment request DM to the given npub
+    pub async fn request_payment_from_contact(
+        &self,
+        npub: String,
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let npub = parse_npub(&npub)?;
+        Ok(self
+            .inner
+            .request_payment_from_contact(npub, amount, memo)
+            .await?
+            .into())
+    }
+
+    /// Splits a payment in equal shares between the members of a contact group.
+    /// The direction is either `request`, to send every member an invoice for their share,
+    /// or `pay`, to pay every member their share.
+    pub async fn split_payment(
+        &self,
+        group_id: String,
+        total_sats: u64,
+        memo: Option<String>,
+        direction: String,
+    ) -> Result<JsValue /* PaymentSplit */, MutinyJsError> {
+        let direction = match direction.as_str() {
+            "request" => SplitDirection::Request,
+            "pay" => SplitDirection::Pay,
+            _ => return Err(MutinyJsError::InvalidArgumentsError),
+        };
+        let split = self
+            .inner
+            .split_payment(group_id, total_sats, memo, direction)
+            .await?;
+        Ok(JsValue::from_serde(&split)?)
+    }
+
+    /// Lists the payment splits we made, newest first
+    pub fn list_payment_splits(&self) -> Result<JsValue /* Vec<PaymentSplit> */, MutinyJsError> {
+        let splits = self.inner.list_payment_splits()?;
+        Ok(JsValue::from_serde(&splits)?)
+    }
+
+    /// Lists all pending payment requests we have received over DMs
+    pub fn get_pending_dm_requests(&self) -> Result<Vec<PendingNwcInvoice>, MutinyJsError> {
+        let pending = self.inner.nostr.get_pending_dm_requests()?;
+        Ok(pending.into_iter().map(|inv| (inv, None).into()).collect())
+    }
+
+    /// Accepts a payment request we received over DM and pays it
+    pub async fn accept_dm_payment_request(&self, hash: String) -> Result<(), MutinyJsError> {
+        let hash: sha256::Hash = hash
+            .parse()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        self.inner.nostr.approve_invoice(hash, &self.inner).await?;
+        Ok(())
+    }
+
+    /// Declines a payment request we received over DM
+    pub async fn decline_dm_payment_request(&self, hash: String) -> Result<(), MutinyJsError> {
+        let hash: sha256::Hash = hash
+            .parse()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        self.inner.nostr.deny_invoice(hash).await?;
+        Ok(())
+    }
+
+    /// Lists the payments waiting for approval: NWC requests that need approval,
+    /// invoices we got over DM and subscription renewals.
+    #[wasm_bindgen]
+    pub fn list_pending_requests(
+        &self,
+    ) -> Result<JsValue /* Vec<PaymentRequest> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_pending_requests()?)?)
+    }
+
+    /// Pays a pending payment request, the id is its payment hash
+    #[wasm_bindgen]
+    pub async fn approve_payment_request(&self, id: String) -> Result<(), MutinyJsError> {
+        let id: sha256::Hash = id
+            .parse()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.inner.approve_payment_request(id).await?)
+    }
+
+    /// Rejects a pending payment request without paying it
+    #[wasm_bindgen]
+    pub async fn reject_payment_request(&self, id: String) -> Result<(), MutinyJsError> {
+        let id: sha256::Hash = id
+            .parse()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.inner.reject_payment_request(id).await?)
+    }
+
+    /// Returns the payment requests that arrived since the last call
+    #[wasm_bindgen]
+    pub async fn take_payment_request_events(
+        &self,
+    ) -> Result<JsValue /* Vec<PaymentRequestEvent> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.take_payment_request_events().await,
+        )?)
+    }
+
     /// Uploads a profile pic to nostr.build and returns the uploaded file's URL
     pub async fn upload_profile_pic(&self, img_base64: String) -> Result<String, MutinyJsError> {
         let bytes = base64::decode(&img_base64)?;
@@ -2050,6 +3696,51 @@ impl MutinyWallet {
         Ok(self.inner.check_lnurl_name().await?)
     }
 
+    /// Registers a lightning address with the LNURL proxy the wallet was configured with.
+    /// Payments to it are received by this wallet while it is running.
+    #[wasm_bindgen]
+    pub async fn register_ln_address(
+        &self,
+        name: String,
+    ) -> Result<JsValue /* LnAddress */, MutinyJsError> {
+        let address = self.inner.register_ln_address(name).await?;
+        Ok(JsValue::from_serde(&address)?)
+    }
+
+    /// Stops the LNURL proxy from serving our lightning address
+    #[wasm_bindgen]
+    pub async fn unregister_ln_address(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.unregister_ln_address().await?)
+    }
+
+    /// The lightning address this wallet receives payments for, if one is registered
+    #[wasm_bindgen]
+    pub fn get_ln_address(&self) -> Result<JsValue /* Option<LnAddress> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_ln_address()?)?)
+    }
+
+    /// Payments requested through our lightning addresses, newest first
+    #[wasm_bindgen]
+    pub fn list_ln_address_payments(
+        &self,
+    ) -> Result<JsValue /* Vec<LnAddressPayment> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_ln_address_payments()?,
+        )?)
+    }
+
+    /// Exports our pathfinding scores as a base64 encoded snapshot.
+    #[wasm_bindgen]
+    pub fn export_scorer(&self) -> Result<String, MutinyJsError> {
+        Ok(self.inner.node_manager.export_scorer()?)
+    }
+
+    /// Imports a pathfinding scorer snapshot, replacing our own scores.
+    #[wasm_bindgen]
+    pub fn import_scorer(&self, snapshot: String) -> Result<(), MutinyJsError> {
+        Ok(self.inner.node_manager.import_scorer(&snapshot)?)
+    }
+
     /// Resets the scorer and network graph. This can be useful if you get stuck in a bad state.
     #[wasm_bindgen]
     pub async fn reset_router(&self) -> Result<(), MutinyJsError> {
@@ -2059,6 +3750,13 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Downloads a full gossip snapshot instead of only the changes since the last sync.
+    /// This can be useful if the network graph is missing channels.
+    #[wasm_bindgen]
+    pub async fn force_full_gossip_sync(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.node_manager.force_full_gossip_sync().await?)
+    }
+
     /// Resets BDK's keychain tracker. This will require a re-sync of the blockchain.
     ///
     /// This can be useful if you get stuck in a bad state.
@@ -2067,6 +3765,17 @@ impl MutinyWallet {
         Ok(self.inner.reset_onchain_tracker().await?)
     }
 
+    /// Looks for on-chain funds beyond the usual scan window, checking `gap_limit`
+    /// unused addresses in a row. Transactions confirmed before `from_height` are
+    /// ignored, use 0 to scan the whole chain.
+    #[wasm_bindgen]
+    pub async fn deep_rescan(&self, gap_limit: u32, from_height: u32) -> Result<(), MutinyJsError> {
+        Ok(self
+            .inner
+            .deep_rescan(gap_limit as usize, from_height)
+            .await?)
+    }
+
     /// Exports the current state of the node manager to a json object.
     #[wasm_bindgen]
     pub async fn export_json(password: Option<String>) -> Result<String, MutinyJsError> {
@@ -2120,6 +3829,22 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&res)?)
     }
 
+    /// Recovers the ecash of all our federations from our seed.
+    /// Should be called after restoring from a mnemonic, only works in safe mode.
+    ///
+    /// Returns the federation ids being recovered, progress can be checked with
+    /// `get_federation_resync_progress`.
+    pub async fn recover_federations(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        let ids: Vec<String> = self
+            .inner
+            .recover_federations()
+            .await?
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect();
+        Ok(JsValue::from_serde(&ids)?)
+    }
+
     /// Restore's the mnemonic after deleting the previous state.
     ///
     /// Backup the state beforehand. Does not restore lightning data.
@@ -2144,6 +3869,75 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Restores the mnemonic and the channels of a static channel backup after deleting
+    /// the previous state. Our peers will force close the channels once the wallet starts.
+    ///
+    /// Should refresh or restart afterwards. Wallet should be stopped.
+    #[wasm_bindgen]
+    pub async fn recover_from_scb(
+        m: String,
+        scb: String,
+        network_str: Option<String>,
+        password: Option<String>,
+        passphrase: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let network = network_str
+            .map(|n| Network::from_str(&n).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .transpose()?
+            .unwrap_or(Network::Bitcoin);
+        let mnemonic = Mnemonic::from_str(&m).map_err(|_| MutinyJsError::InvalidMnemonic)?;
+
+        let logger = Arc::new(MutinyLogger::default());
+        let cipher = password
+            .as_ref()
+            .filter(|p| !p.is_empty())
+            .map(|p| encryption_key_from_pass(p))
+            .transpose()?;
+        let storage = IndexedDbStorage::new(password, cipher, None, logger.clone()).await?;
+        mutiny_core::MutinyWallet::<IndexedDbStorage>::recover_from_scb(
+            storage,
+            mnemonic,
+            passphrase.as_deref(),
+            network,
+            &scb,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Restores the whole wallet from a remote backup after deleting the previous state.
+    /// The credentials are a json `BackupCredentials`, defaulting to none.
+    ///
+    /// Should refresh or restart afterwards. Wallet should be stopped.
+    #[wasm_bindgen]
+    pub async fn restore_from_backup(
+        m: String,
+        url: String,
+        credentials: Option<String>,
+        network_str: Option<String>,
+        passphrase: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let network = network_str
+            .map(|n| Network::from_str(&n).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .transpose()?
+            .unwrap_or(Network::Bitcoin);
+        let mnemonic = Mnemonic::from_str(&m).map_err(|_| MutinyJsError::InvalidMnemonic)?;
+        let credentials = credentials
+            .map(|c| serde_json::from_str(&c))
+            .transpose()?
+            .unwrap_or(BackupCredentials::None);
+        let target = HttpBackupTarget::new(RemoteBackupConfig { url, credentials })?;
+
+        mutiny_core::MutinyWallet::<IndexedDbStorage>::restore_from_backup(
+            mnemonic,
+            passphrase.as_deref(),
+            network,
+            &target,
+        )
+        .await?;
+        Ok(())
+    }
+
     #[wasm_bindgen]
     pub async fn change_password(
         &mut self,
@@ -2156,6 +3950,24 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Sets a second password that opens an empty decoy wallet instead of this one.
+    /// Setting a new one replaces the decoy wallet.
+    #[wasm_bindgen]
+    pub async fn set_duress_password(&self, password: String) -> Result<(), MutinyJsError> {
+        Ok(self.inner.set_duress_password(password).await?)
+    }
+
+    /// Removes the duress password and deletes the decoy wallet
+    #[wasm_bindgen]
+    pub async fn remove_duress_password(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.remove_duress_password().await?)
+    }
+
+    #[wasm_bindgen]
+    pub fn has_duress_password(&self) -> Result<bool, MutinyJsError> {
+        Ok(self.inner.has_duress_password()?)
+    }
+
     /// Converts a bitcoin amount in BTC to satoshis.
     #[wasm_bindgen]
     pub fn convert_btc_to_sats(btc: f64) -> Result<u64, MutinyJsError> {
@@ -2251,6 +4063,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2288,6 +4118,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2319,6 +4167,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -2363,6 +4229,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2393,6 +4277,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -2444,6 +4346,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -2493,6 +4413,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -2529,6 +4467,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -2569,6 +4525,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2639,6 +4613,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2698,6 +4690,24 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
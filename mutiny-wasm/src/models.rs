@@ -6,7 +6,8 @@ use hex_conservative::DisplayHex;
 use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
-use mutiny_core::event::HTLCStatus;
+use mutiny_core::event::{CustomTlv, HTLCStatus};
+use mutiny_core::federation::FederationActivity;
 use mutiny_core::labels::Contact as MutinyContact;
 use mutiny_core::nostr::nwc::SpendingConditions;
 use mutiny_core::*;
@@ -22,6 +23,7 @@ pub enum ActivityType {
     Lightning,
     ChannelOpen,
     ChannelClose,
+    Ecash,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -35,6 +37,9 @@ pub struct ActivityItem {
     pub(crate) contacts: Vec<TagItem>,
     pub last_updated: Option<u64>,
     privacy_level: String,
+    federation_id: Option<String>,
+    federation_kind: Option<String>,
+    pub fees_sats: Option<u64>,
 }
 
 #[wasm_bindgen]
@@ -58,6 +63,18 @@ impl ActivityItem {
     pub fn contacts(&self) -> Vec<TagItem> {
         self.contacts.clone()
     }
+
+    /// The federation that handled this item, if it was not handled by our node or wallet
+    #[wasm_bindgen(getter)]
+    pub fn federation_id(&self) -> Option<String> {
+        self.federation_id.clone()
+    }
+
+    /// One of `Lightning`, `EcashReissue`, `PegIn`, or `PegOut` for federation activity
+    #[wasm_bindgen(getter)]
+    pub fn federation_kind(&self) -> Option<String> {
+        self.federation_kind.clone()
+    }
 }
 
 impl From<mutiny_core::ActivityItem> for ActivityItem {
@@ -72,6 +89,13 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             }
             mutiny_core::ActivityItem::Lightning(_) => ActivityType::Lightning,
             mutiny_core::ActivityItem::ChannelClosed(_) => ActivityType::ChannelClose,
+            mutiny_core::ActivityItem::Federation(ref f) => match f {
+                FederationActivity::Lightning { .. } => ActivityType::Lightning,
+                FederationActivity::EcashReissue { .. } => ActivityType::Ecash,
+                FederationActivity::PegIn { .. } | FederationActivity::PegOut { .. } => {
+                    ActivityType::OnChain
+                }
+            },
         };
 
         let id = match a {
@@ -83,33 +107,50 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
                 .user_channel_id
                 .map(|c| c.to_lower_hex_string())
                 .unwrap_or_default(),
+            mutiny_core::ActivityItem::Federation(ref f) => match f {
+                FederationActivity::Lightning { invoice, .. } => {
+                    invoice.payment_hash.into_32().to_lower_hex_string()
+                }
+                FederationActivity::EcashReissue { .. } => String::new(),
+                FederationActivity::PegIn { transaction, .. }
+                | FederationActivity::PegOut { transaction, .. } => {
+                    transaction.internal_id.to_string()
+                }
+            },
         };
 
         let (inbound, amount_sats) = match a {
-            mutiny_core::ActivityItem::OnChain(ref t) => {
-                let inbound = t.received > t.sent;
-                let amount_sats = if inbound {
-                    Some(t.received - t.sent)
-                } else {
-                    Some(t.sent - t.received)
-                };
-                (inbound, amount_sats)
-            }
+            mutiny_core::ActivityItem::OnChain(ref t) => onchain_amount(t),
             mutiny_core::ActivityItem::Lightning(ref ln) => (ln.inbound, ln.amount_sats),
             mutiny_core::ActivityItem::ChannelClosed(_) => (false, None),
+            mutiny_core::ActivityItem::Federation(ref f) => match f {
+                FederationActivity::Lightning { invoice, .. } => {
+                    (invoice.inbound, invoice.amount_sats)
+                }
+                FederationActivity::EcashReissue { amount_sats, .. } => (true, Some(*amount_sats)),
+                FederationActivity::PegIn { transaction, .. }
+                | FederationActivity::PegOut { transaction, .. } => onchain_amount(transaction),
+            },
         };
 
-        let privacy_level = match kind {
-            ActivityType::OnChain => PrivacyLevel::NotAvailable,
-            ActivityType::Lightning => {
-                if let mutiny_core::ActivityItem::Lightning(ref ln) = a {
-                    ln.privacy_level
-                } else {
-                    PrivacyLevel::NotAvailable
-                }
-            }
-            ActivityType::ChannelOpen => PrivacyLevel::NotAvailable,
-            ActivityType::ChannelClose => PrivacyLevel::NotAvailable,
+        let privacy_level = match a {
+            mutiny_core::ActivityItem::Lightning(ref ln) => ln.privacy_level,
+            mutiny_core::ActivityItem::Federation(FederationActivity::Lightning {
+                ref invoice,
+                ..
+            }) => invoice.privacy_level,
+            _ => PrivacyLevel::NotAvailable,
+        };
+
+        let (federation_id, federation_kind, fees_sats) = match a {
+            mutiny_core::ActivityItem::Federation(ref f) => (
+                Some(f.federation_id().to_string()),
+                Some(f.kind().to_string()),
+                f.fees(),
+            ),
+            mutiny_core::ActivityItem::OnChain(ref t) => (None, None, t.fee),
+            mutiny_core::ActivityItem::Lightning(ref ln) => (None, None, ln.fees_paid),
+            mutiny_core::ActivityItem::ChannelClosed(_) => (None, None, None),
         };
 
         ActivityItem {
@@ -121,10 +162,64 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             contacts: vec![],
             last_updated: a.last_updated(),
             privacy_level: privacy_level.to_string(),
+            federation_id,
+            federation_kind,
+            fees_sats,
+        }
+    }
+}
+
+fn onchain_amount(t: &mutiny_core::TransactionDetails) -> (bool, Option<u64>) {
+    let inbound = t.received > t.sent;
+    let amount_sats = if inbound {
+        Some(t.received - t.sent)
+    } else {
+        Some(t.sent - t.received)
+    };
+    (inbound, amount_sats)
+}
+
+/// A page of activity, see [crate::MutinyWallet::get_activity_page]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActivityPage {
+    pub items: Vec<ActivityItem>,
+    pub next_until_ts: Option<u64>,
+}
+
+/// An activity item with its value in fiat at the time it settled
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FiatActivityItem {
+    pub activity: ActivityItem,
+    pub currency: String,
+    pub price: Option<f32>,
+    pub fiat_value: Option<f64>,
+}
+
+impl From<mutiny_core::price::FiatActivityItem> for FiatActivityItem {
+    fn from(a: mutiny_core::price::FiatActivityItem) -> Self {
+        FiatActivityItem {
+            activity: a.activity.into(),
+            currency: a.currency,
+            price: a.price,
+            fiat_value: a.fiat_value,
         }
     }
 }
 
+/// Results of a search across activity, contacts, and labeled addresses
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchResults {
+    pub activity: Vec<ActivityItem>,
+    pub contacts: Vec<TagItem>,
+    pub addresses: Vec<AddressLabels>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AddressLabels {
+    pub address: String,
+    pub labels: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[wasm_bindgen]
 pub struct MutinyInvoice {
@@ -143,6 +238,7 @@ pub struct MutinyInvoice {
     pub last_updated: u64,
     pub potential_hodl_invoice: bool,
     labels: Vec<String>,
+    custom_tlvs: Vec<CustomTlv>,
 }
 
 #[wasm_bindgen]
@@ -196,6 +292,12 @@ impl MutinyInvoice {
     pub fn labels(&self) -> Vec<String> {
         self.labels.clone()
     }
+
+    /// Custom TLV records the sender included with a keysend payment
+    #[wasm_bindgen(getter)]
+    pub fn custom_tlvs(&self) -> JsValue /* Vec<CustomTlv> */ {
+        JsValue::from_serde(&self.custom_tlvs).unwrap()
+    }
 }
 
 impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
@@ -221,6 +323,7 @@ impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
             last_updated: m.last_updated,
             potential_hodl_invoice,
             labels: m.labels,
+            custom_tlvs: m.custom_tlvs,
         }
     }
 }
@@ -232,6 +335,7 @@ pub struct MutinyPeer {
     connection_string: Option<String>,
     alias: Option<String>,
     color: Option<String>,
+    addresses: Vec<String>,
     label: Option<String>,
     pub is_connected: bool,
 }
@@ -263,6 +367,11 @@ impl MutinyPeer {
         self.color.clone()
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn addresses(&self) -> Vec<String> {
+        self.addresses.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn label(&self) -> Option<String> {
         self.label.clone()
@@ -276,6 +385,7 @@ impl From<nodemanager::MutinyPeer> for MutinyPeer {
             connection_string: m.connection_string,
             alias: m.alias,
             color: m.color,
+            addresses: m.addresses,
             label: m.label,
             is_connected: m.is_connected,
         }
@@ -726,6 +836,8 @@ pub struct TagItem {
     image_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     primal_image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node_pubkey: Option<PublicKey>,
     /// Epoch time in seconds when this tag was last used
     pub last_used_time: u64,
     /// If we follow this npub on nostr
@@ -791,6 +903,11 @@ impl TagItem {
     pub fn primal_image_url(&self) -> Option<String> {
         self.primal_image_url.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn node_pubkey(&self) -> Option<String> {
+        self.node_pubkey.map(|p| p.to_string())
+    }
 }
 
 impl TagItem {
@@ -809,6 +926,7 @@ impl TagItem {
                 )
             }),
             image_url: contact.image_url,
+            node_pubkey: contact.node_pubkey,
             last_used_time: contact.last_used,
             is_followed,
         }
@@ -827,6 +945,7 @@ impl From<labels::TagItem> for TagItem {
                 lnurl: None,
                 image_url: None,
                 primal_image_url: None,
+                node_pubkey: None,
                 last_used_time: item.last_used_time,
                 is_followed: false,
             },
@@ -850,6 +969,10 @@ pub struct NwcProfile {
     tag: String,
     label: Option<String>,
     enabled: bool,
+    /// Proof of work difficulty the connecting app must present before the profile activates
+    pub required_pow: Option<u8>,
+    /// If the connecting app has met the proof of work requirement
+    pub pow_verified: bool,
 }
 
 impl Serialize for NwcProfile {
@@ -874,6 +997,8 @@ impl Serialize for NwcProfile {
             "spending_conditions_type": self.spending_conditions_type(),
             "url_suffix": self.url_suffix(),
             "enabled": self.enabled(),
+            "required_pow": self.required_pow,
+            "pow_verified": self.pow_verified,
         });
 
         json.serialize(serializer)
@@ -1020,6 +1145,8 @@ impl From<nostr::nwc::NwcProfile> for NwcProfile {
             tag: value.tag.to_string(),
             label: value.label,
             enabled: value.enabled.unwrap_or(true),
+            required_pow: value.required_pow,
+            pow_verified: value.pow_verified,
         }
     }
 }
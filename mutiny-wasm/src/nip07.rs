@@ -0,0 +1,145 @@
+use crate::error::MutinyJsError;
+use crate::indexed_db::IndexedDbStorage;
+use gloo_utils::format::JsValueSerdeExt;
+use js_sys::{Function, Promise};
+use mutiny_core::storage::NamespacedStorage;
+use mutiny_core::utils::parse_npub;
+use nostr::{EventBuilder, Kind, Tag, Timestamp};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// An event to sign as given to `window.nostr.signEvent`,
+/// the pubkey, id and signature are filled in by us
+#[derive(Deserialize)]
+struct Nip07Event {
+    created_at: Option<u64>,
+    kind: u64,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    content: String,
+}
+
+/// What is passed to the permission prompt
+#[derive(Serialize)]
+struct Nip07Request<'a> {
+    origin: &'a str,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+/// A [NIP-07](https://github.com/nostr-protocol/nips/blob/master/07.md) shaped signer
+/// backed by the wallet's nostr keys, so it can act as the user's signer in web apps.
+///
+/// Every call first asks the permission prompt it was created with, which is called
+/// with `{ origin, method, params }` and returns, or resolves to, whether to allow it.
+/// Calls that are not allowed return [MutinyJsError::PermissionDenied].
+#[wasm_bindgen]
+pub struct Nip07Signer {
+    inner: mutiny_core::MutinyWallet<NamespacedStorage<IndexedDbStorage>>,
+    origin: String,
+    prompt: Function,
+}
+
+impl Nip07Signer {
+    pub(crate) fn new(
+        inner: mutiny_core::MutinyWallet<NamespacedStorage<IndexedDbStorage>>,
+        origin: String,
+        prompt: Function,
+    ) -> Self {
+        Nip07Signer {
+            inner,
+            origin,
+            prompt,
+        }
+    }
+
+    async fn require(&self, method: &str, params: serde_json::Value) -> Result<(), MutinyJsError> {
+        let request = Nip07Request {
+            origin: &self.origin,
+            method,
+            params,
+        };
+        let request = JsValue::from_serde(&request)?;
+
+        // the prompt can answer right away or with a promise,
+        // if it throws we treat it as denied
+        let answer = self
+            .prompt
+            .call1(&JsValue::NULL, &request)
+            .map_err(|_| MutinyJsError::PermissionDenied)?;
+        let answer = JsFuture::from(Promise::resolve(&answer))
+            .await
+            .map_err(|_| MutinyJsError::PermissionDenied)?;
+
+        match answer.as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(MutinyJsError::PermissionDenied),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Nip07Signer {
+    /// The origin of the web app this signer was created for
+    #[wasm_bindgen(getter)]
+    pub fn origin(&self) -> String {
+        self.origin.clone()
+    }
+
+    /// Returns our nostr public key as hex, `window.nostr.getPublicKey()`
+    #[wasm_bindgen(js_name = getPublicKey)]
+    pub async fn get_public_key(&self) -> Result<String, MutinyJsError> {
+        self.require("getPublicKey", serde_json::Value::Null)
+            .await?;
+        Ok(self.inner.nostr.get_npub().await.to_hex())
+    }
+
+    /// Signs the event with our nostr key, `window.nostr.signEvent(event)`.
+    /// The event only needs its `kind`, `tags`, `content` and optionally `created_at`.
+    #[wasm_bindgen(js_name = signEvent)]
+    pub async fn sign_event(&self, event: JsValue) -> Result<JsValue /* Event */, MutinyJsError> {
+        let params: serde_json::Value = event.into_serde()?;
+        let event: Nip07Event = serde_json::from_value(params.clone())?;
+        self.require("signEvent", params).await?;
+
+        let npub = self.inner.nostr.get_npub().await;
+        let mut builder = EventBuilder::new(Kind::from(event.kind), event.content, event.tags);
+        if let Some(created_at) = event.created_at {
+            builder = builder.custom_created_at(Timestamp::from(created_at));
+        }
+        let signed = self
+            .inner
+            .nostr
+            .sign_event(builder.to_unsigned_event(npub))
+            .await?;
+
+        Ok(JsValue::from_serde(&signed)?)
+    }
+
+    /// Encrypts a message for the pubkey, `window.nostr.nip04.encrypt(pubkey, plaintext)`
+    #[wasm_bindgen(js_name = nip04Encrypt)]
+    pub async fn nip04_encrypt(
+        &self,
+        pubkey: String,
+        plaintext: String,
+    ) -> Result<String, MutinyJsError> {
+        let npub = parse_npub(&pubkey)?;
+        self.require("nip04.encrypt", serde_json::json!({ "pubkey": pubkey }))
+            .await?;
+        Ok(self.inner.nostr.encrypt_dm(npub, &plaintext).await?)
+    }
+
+    /// Decrypts a message from the pubkey, `window.nostr.nip04.decrypt(pubkey, ciphertext)`
+    #[wasm_bindgen(js_name = nip04Decrypt)]
+    pub async fn nip04_decrypt(
+        &self,
+        pubkey: String,
+        ciphertext: String,
+    ) -> Result<String, MutinyJsError> {
+        let npub = parse_npub(&pubkey)?;
+        self.require("nip04.decrypt", serde_json::json!({ "pubkey": pubkey }))
+            .await?;
+        Ok(self.inner.nostr.decrypt_dm(npub, &ciphertext).await?)
+    }
+}
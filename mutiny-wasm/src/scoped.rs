@@ -0,0 +1,218 @@
+use crate::error::MutinyJsError;
+use crate::indexed_db::IndexedDbStorage;
+use crate::models::*;
+use bitcoin::hashes::sha256;
+use gloo_utils::format::JsValueSerdeExt;
+use lightning_invoice::Bolt11Invoice;
+use mutiny_core::storage::NamespacedStorage;
+use mutiny_core::PayInvoiceOptions;
+use std::collections::HashSet;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+/// A capability that can be granted to a [ScopedWallet]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Permission {
+    /// Read the wallet balance
+    ReadBalance,
+    /// Read activity and look up invoices
+    ReadActivity,
+    /// Create invoices and addresses
+    Receive,
+    /// Pay lightning invoices
+    Send,
+}
+
+impl FromStr for Permission {
+    type Err = MutinyJsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read_balance" => Ok(Permission::ReadBalance),
+            "read_activity" => Ok(Permission::ReadActivity),
+            "receive" => Ok(Permission::Receive),
+            "send" => Ok(Permission::Send),
+            _ => Err(MutinyJsError::InvalidArgumentsError),
+        }
+    }
+}
+
+impl core::fmt::Display for Permission {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Permission::ReadBalance => write!(f, "read_balance"),
+            Permission::ReadActivity => write!(f, "read_activity"),
+            Permission::Receive => write!(f, "receive"),
+            Permission::Send => write!(f, "send"),
+        }
+    }
+}
+
+/// A restricted handle to the wallet that can be given to third-party plugins or iframes.
+///
+/// Only the methods allowed by the permissions it was created with can be called,
+/// everything else returns [MutinyJsError::PermissionDenied].
+#[wasm_bindgen]
+pub struct ScopedWallet {
+    inner: mutiny_core::MutinyWallet<NamespacedStorage<IndexedDbStorage>>,
+    permissions: HashSet<Permission>,
+}
+
+impl ScopedWallet {
+    pub(crate) fn new(
+        inner: mutiny_core::MutinyWallet<NamespacedStorage<IndexedDbStorage>>,
+        permissions: Vec<String>,
+    ) -> Result<ScopedWallet, MutinyJsError> {
+        let permissions = permissions
+            .iter()
+            .map(|p| Permission::from_str(p))
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        Ok(ScopedWallet { inner, permissions })
+    }
+
+    fn require(&self, permission: Permission) -> Result<(), MutinyJsError> {
+        if self.permissions.contains(&permission) {
+            Ok(())
+        } else {
+            Err(MutinyJsError::PermissionDenied)
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ScopedWallet {
+    /// Returns the permissions this handle was created with
+    #[wasm_bindgen(getter)]
+    pub fn permissions(&self) -> Vec<String> {
+        self.permissions.iter().map(|p| p.to_string()).collect()
+    }
+
+    /// Gets the current balance of the wallet.
+    ///
+    /// Requires the `read_balance` permission.
+    #[wasm_bindgen]
+    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyJsError> {
+        self.require(Permission::ReadBalance)?;
+        Ok(self.inner.get_balance().await?.into())
+    }
+
+    /// Returns all the on-chain and lightning activity from the wallet.
+    /// Contacts are not included.
+    ///
+    /// Requires the `read_activity` permission.
+    #[wasm_bindgen]
+    pub async fn get_activity(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<JsValue /* Vec<ActivityItem> */, MutinyJsError> {
+        self.require(Permission::ReadActivity)?;
+        let activity = self.inner.get_activity(limit, offset)?;
+        let activity: Vec<ActivityItem> = activity.into_iter().map(|a| a.into()).collect();
+        Ok(JsValue::from_serde(&activity)?)
+    }
+
+    /// Gets an invoice by its payment hash.
+    ///
+    /// Requires the `read_activity` permission.
+    #[wasm_bindgen]
+    pub async fn get_invoice_by_hash(&self, hash: String) -> Result<MutinyInvoice, MutinyJsError> {
+        self.require(Permission::ReadActivity)?;
+        let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
+        Ok(self.inner.get_invoice_by_hash(&hash).await?.into())
+    }
+
+    /// Creates a lightning invoice. The amount should be in satoshis.
+    ///
+    /// Requires the `receive` permission.
+    #[wasm_bindgen]
+    pub async fn create_invoice(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.require(Permission::Receive)?;
+        Ok(self.inner.create_invoice(amount, labels).await?.into())
+    }
+
+    /// Gets a new bitcoin address from the wallet.
+    ///
+    /// Requires the `receive` permission.
+    #[wasm_bindgen]
+    pub async fn get_new_address(
+        &self,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        self.require(Permission::Receive)?;
+        let address = self.inner.create_address(labels.clone()).await?;
+        Ok(MutinyBip21RawMaterials {
+            address: address.to_string(),
+            invoice: None,
+            btc_amount: None,
+            labels,
+        })
+    }
+
+    /// Creates a BIP 21 invoice with a new address and a lightning invoice.
+    ///
+    /// Requires the `receive` permission.
+    #[wasm_bindgen]
+    pub async fn create_bip21(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        self.require(Permission::Receive)?;
+        Ok(self.inner.create_bip21(amount, labels).await?.into())
+    }
+
+    /// Pays a lightning invoice.
+    /// An amount should only be provided if the invoice does not have an amount.
+    /// Paying an invoice without an amount requires `confirm_amountless` to be set.
+    ///
+    /// Requires the `send` permission.
+    #[wasm_bindgen]
+    pub async fn pay_invoice(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        confirm_amountless: Option<bool>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.require(Permission::Send)?;
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        let options = PayInvoiceOptions {
+            confirm_amountless: confirm_amountless.unwrap_or(false),
+            ..Default::default()
+        };
+        Ok(self
+            .inner
+            .pay_invoice_with_options(&invoice, amt_sats, labels, options)
+            .await?
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_parse_permissions() {
+        for p in [
+            Permission::ReadBalance,
+            Permission::ReadActivity,
+            Permission::Receive,
+            Permission::Send,
+        ] {
+            assert_eq!(Permission::from_str(&p.to_string()).unwrap(), p);
+        }
+
+        assert!(Permission::from_str("admin").is_err());
+    }
+}